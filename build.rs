@@ -11,6 +11,10 @@ fn build_ver() {
     let version = git_ver().unwrap_or(format!("{} (unknown commit)", cargo_ver));
 
     println!("cargo:rustc-env=NEOLINK_VERSION={}", version);
+    println!(
+        "cargo:rustc-env=NEOLINK_GIT_HASH={}",
+        git_hash().unwrap_or_else(|| "unknown".to_string())
+    );
     println!(
         "cargo:rustc-env=NEOLINK_PROFILE={}",
         env::var("PROFILE").unwrap()
@@ -21,6 +25,24 @@ fn git_ver() -> Option<String> {
     github_ver().or_else(git_cmd_ver)
 }
 
+fn git_hash() -> Option<String> {
+    if let Ok(sha1) = env::var("GITHUB_SHA") {
+        return Some(sha1.chars().take(12).collect());
+    }
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        String::from_utf8(output.stdout)
+            .ok()
+            .map(|hash| hash.trim().to_string())
+    } else {
+        None
+    }
+}
+
 fn git_cmd_ver() -> Option<String> {
     let mut git_cmd = Command::new("git");
     git_cmd.args(["describe", "--tags"]);
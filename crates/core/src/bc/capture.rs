@@ -0,0 +1,242 @@
+//! Record the messages received from a camera so that they can be replayed
+//!
+//! A capture holds the messages one after the other as they are on the wire
+//! but unencrypted, so that [`Replay`] can read them back through the
+//! deserializer without the login of the camera. The login messages are
+//! written without their body so that neither the nonce nor the device info
+//! end up in the file.
+//!
+//! These files are what users can submit for cameras that are not supported
+//! yet
+//!
+use super::codex::BcCodex;
+use super::model::*;
+use crate::{Credentials, Error, Result};
+use bytes::BytesMut;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::codec::Decoder;
+
+/// Writes the received messages of one connection to a capture file
+pub struct Capture {
+    file: BufWriter<File>,
+}
+
+impl Capture {
+    /// Start a new capture file for the camera `name` in `dir`
+    ///
+    /// The file is named after the camera and the time of the connection
+    pub fn create(dir: &Path, name: &str) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        let name = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let path = dir.join(format!("{name}-{time}.bc"));
+        log::info!("Capturing the messages of {name} to {path:?}");
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Append a received message to the capture
+    pub fn record(&mut self, bc: &Bc) -> Result<()> {
+        let buf = match bc {
+            Bc {
+                meta:
+                    meta @ BcMeta {
+                        msg_id: MSG_ID_LOGIN,
+                        ..
+                    },
+                ..
+            } => {
+                let scrubbed = Bc::new(
+                    BcMeta {
+                        msg_id: meta.msg_id,
+                        channel_id: meta.channel_id,
+                        stream_type: meta.stream_type,
+                        // The capture is unencrypted so the login must say so too
+                        response_code: match meta.response_code >> 8 {
+                            0xdd => 0xdd00,
+                            _ => meta.response_code,
+                        },
+                        msg_num: meta.msg_num,
+                        class: meta.class,
+                    },
+                    None,
+                    None,
+                );
+                scrubbed.serialize(vec![], &EncryptionProtocol::Unencrypted)?
+            }
+            bc => bc.serialize(vec![], &EncryptionProtocol::Unencrypted)?,
+        };
+        self.file.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// Reads the messages of a capture back through the deserializer
+pub struct Replay {
+    codex: BcCodex,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl Replay {
+    /// Replay the capture held in `data`
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            codex: BcCodex::new(Credentials::default()),
+            buf: BytesMut::from(data),
+            done: false,
+        }
+    }
+
+    /// Replay the capture file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self::new(&std::fs::read(path)?))
+    }
+}
+
+impl Iterator for Replay {
+    type Item = Result<Bc>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.codex.decode(&mut self.buf) {
+            Ok(Some(bc)) => Some(Ok(bc)),
+            Ok(None) if self.buf.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(None) => {
+                self.done = true;
+                Some(Err(Error::OtherString(format!(
+                    "The capture ends with {} bytes of an incomplete message",
+                    self.buf.len()
+                ))))
+            }
+            Err(e) => {
+                // The position in the capture is lost after an error
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bc::xml::*;
+
+    fn sample() -> Vec<Bc> {
+        vec![
+            Bc::new(
+                BcMeta {
+                    msg_id: MSG_ID_LOGIN,
+                    channel_id: 0,
+                    stream_type: 0,
+                    response_code: 0xdd12,
+                    msg_num: 0,
+                    class: 0x0000,
+                },
+                None,
+                Some(BcPayloads::BcXml(BcXml {
+                    encryption: Some(Encryption {
+                        version: "1.1".to_string(),
+                        type_: "md5".to_string(),
+                        nonce: "9E6D1FCB9E69846D".to_string(),
+                    }),
+                    ..Default::default()
+                })),
+            ),
+            Bc::new(
+                BcMeta {
+                    msg_id: MSG_ID_VERSION,
+                    channel_id: 0,
+                    stream_type: 0,
+                    response_code: 200,
+                    msg_num: 1,
+                    class: 0x0000,
+                },
+                None,
+                Some(BcPayloads::BcXml(BcXml {
+                    version_info: Some(VersionInfo {
+                        name: "Camera".to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })),
+            ),
+        ]
+    }
+
+    fn capture(messages: &[Bc], test: &str) -> Vec<u8> {
+        let dir =
+            std::env::temp_dir().join(format!("neolink-capture-{test}-{}", std::process::id()));
+        let mut capture = Capture::create(&dir, "test").unwrap();
+        for bc in messages.iter() {
+            capture.record(bc).unwrap();
+        }
+        drop(capture);
+        let path = std::fs::read_dir(&dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_capture_replay() {
+        let data = capture(&sample(), "replay");
+        let replayed = Replay::new(&data).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(replayed.len(), 2);
+
+        // The login is recorded without its nonce
+        assert_eq!(replayed[0].meta.response_code, 0xdd00);
+        assert_eq!(
+            replayed[0].body,
+            BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None
+            })
+        );
+        assert_eq!(replayed[1], sample().remove(1));
+    }
+
+    #[test]
+    fn test_replay_truncated() {
+        let data = capture(&sample(), "truncated");
+        let mut replay = Replay::new(&data[..data.len() - 1]);
+        assert!(matches!(replay.next(), Some(Ok(_))));
+        assert!(matches!(replay.next(), Some(Err(_))));
+        assert!(replay.next().is_none());
+    }
+
+    /// Every capture in `samples/captures` must replay without error
+    #[test]
+    fn test_replay_samples() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/bc/samples/captures");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|ext| ext != "bc").unwrap_or(true) {
+                continue;
+            }
+            for bc in Replay::open(&path).unwrap() {
+                if let Err(e) = bc {
+                    panic!("Failed to replay {:?}: {:?}", path, e);
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use super::model::*;
 use super::xml::{BcPayloads, BcXml};
 use super::xml_crypto;
-use crate::Error;
+use crate::{trace, Error};
 use bytes::{Buf, BytesMut};
 use log::*;
 use nom::{
@@ -115,10 +115,7 @@ fn bc_modern_msg<'a>(
     // Now we'll take the buffer that Nom gave a ref to and parse it.
     let extension = if ext_len > 0 {
         if context.debug {
-            println!(
-                "Extension Txt: {:?}",
-                String::from_utf8(processed_ext_buf.to_vec()).unwrap_or("Not Text".to_string())
-            );
+            trace::debug_text("Extension Txt", &String::from_utf8_lossy(processed_ext_buf));
         }
         // Apply the XML parse function, but throw away the reference to decrypted in the Ok and
         // Err case. This error-error-error thing is the same idiom Nom uses internally.
@@ -193,19 +190,19 @@ fn bc_modern_msg<'a>(
             };
         } else {
             if context.debug {
-                println!(
-                    "Payload Txt: {:?}",
-                    String::from_utf8(processed_payload_buf.to_vec())
-                        .unwrap_or("Not Text".to_string())
+                trace::debug_text(
+                    "Payload Txt",
+                    &String::from_utf8_lossy(&processed_payload_buf),
                 );
             }
             let xml = BcXml::try_parse(processed_payload_buf.as_slice()).map_err(|_| {
-                error!("header.msg_id: {}", header.msg_id);
                 error!(
-                    "processed_payload_buf: {:X?}::{:?}",
-                    processed_payload_buf,
-                    std::str::from_utf8(&processed_payload_buf)
+                    "Unable to parse the payload xml of msg_id {}",
+                    header.msg_id
                 );
+                if context.debug {
+                    trace::debug_bytes("Payload", &processed_payload_buf);
+                }
                 Err::Error(make_error(
                     buf,
                     "Unable to parse Payload XML",
@@ -28,6 +28,8 @@
 //! which are binary data in the bc media packet format
 //!
 
+/// Records the received messages to disk and replays them
+pub mod capture;
 /// Contains the structure of the messages such as headers and payloads
 pub mod model;
 
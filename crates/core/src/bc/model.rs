@@ -17,6 +17,40 @@ pub const MSG_ID_LOGOUT: u32 = 2;
 pub const MSG_ID_VIDEO: u32 = 3;
 /// ID used to stop the video stream
 pub const MSG_ID_VIDEO_STOP: u32 = 4;
+/// Start playback of a recorded file, the media is sent with this ID
+pub const MSG_ID_REPLAY_START: u32 = 5;
+/// Stop playback of a recorded file
+pub const MSG_ID_REPLAY_STOP: u32 = 7;
+/// Opens a search for recorded files and returns a handle
+pub const MSG_ID_FILE_INFO_LIST_OPEN: u32 = 14;
+/// Gets the next batch of recorded files of an open search
+pub const MSG_ID_FILE_INFO_LIST_GET: u32 = 15;
+/// Closes a recorded file search
+pub const MSG_ID_FILE_INFO_LIST_CLOSE: u32 = 16;
+/// Set the ports and state of the network services
+pub const MSG_ID_SET_SERVICE_PORTS: u32 = 36;
+/// Get the ports and state of the network services
+pub const MSG_ID_GET_SERVICE_PORTS: u32 = 37;
+/// Set the picture and image signal processor settings
+pub const MSG_ID_SET_VIDEO_INPUT: u32 = 25;
+/// Get the picture and image signal processor settings
+pub const MSG_ID_GET_VIDEO_INPUT: u32 = 26;
+/// Get the camera name and time overlays
+pub const MSG_ID_GET_OSD: u32 = 44;
+/// Set the camera name and time overlays
+pub const MSG_ID_SET_OSD: u32 = 45;
+/// Get the motion detection settings
+pub const MSG_ID_GET_MD_ALARM: u32 = 46;
+/// Set the motion detection settings
+pub const MSG_ID_SET_MD_ALARM: u32 = 47;
+/// Get the privacy mask regions
+pub const MSG_ID_GET_SHELTER: u32 = 52;
+/// Set the privacy mask regions
+pub const MSG_ID_SET_SHELTER: u32 = 53;
+/// Get the encoder settings of the streams
+pub const MSG_ID_GET_COMPRESSION: u32 = 56;
+/// Set the encoder settings of the streams
+pub const MSG_ID_SET_COMPRESSION: u32 = 57;
 /// TalkAbility messages have this ID
 pub const MSG_ID_TALKABILITY: u32 = 10;
 /// TalkReset messages have this ID
@@ -35,6 +69,10 @@ pub const MSG_ID_MOTION: u32 = 33;
 pub const MSG_ID_VERSION: u32 = 80;
 /// Ping messages have this ID
 pub const MSG_ID_PING: u32 = 93;
+/// Get the capacity and state of the SD cards and hard drives
+pub const MSG_ID_GET_HDD_INFO: u32 = 102;
+/// Format an SD card or hard drive
+pub const MSG_ID_HDD_INIT: u32 = 103;
 /// General system info messages have this ID
 pub const MSG_ID_GET_GENERAL: u32 = 104;
 /// Setting general system info (clock mostly) messages have this ID
@@ -65,6 +103,12 @@ pub const MSG_ID_SET_LED_STATUS: u32 = 209;
 pub const MSG_ID_GET_PIR_ALARM: u32 = 212;
 /// Setting PIR status messages have this ID
 pub const MSG_ID_START_PIR_ALARM: u32 = 213;
+/// Ask the camera to send a test push notification
+pub const MSG_ID_TEST_PUSH: u32 = 220;
+/// Ask the camera to send a test email with its email settings
+pub const MSG_ID_TEST_EMAIL: u32 = 221;
+/// Ask the camera to upload a test file with its FTP settings
+pub const MSG_ID_TEST_FTP: u32 = 222;
 /// UDP Keep alive
 pub const MSG_ID_UDP_KEEP_ALIVE: u32 = 234;
 /// Battery message initiaed by the camera
@@ -73,6 +117,10 @@ pub const MSG_ID_BATTERY_INFO_LIST: u32 = 252;
 pub const MSG_ID_BATTERY_INFO: u32 = 253;
 /// Used for to play sounds like the siren
 pub const MSG_ID_PLAY_AUDIO: u32 = 263;
+/// Set the speaker volume and alarm sound settings
+pub const MSG_ID_SET_AUDIO_CFG: u32 = 264;
+/// Get the speaker volume and alarm sound settings
+pub const MSG_ID_GET_AUDIO_CFG: u32 = 265;
 /// Manual Floodlight Control
 pub const MSG_ID_FLOODLIGHT_MANUAL: u32 = 288;
 /// Set Floodlight tasks xml
@@ -83,6 +131,10 @@ pub const MSG_ID_FLOODLIGHT_STATUS_LIST: u32 = 291;
 pub const MSG_ID_GET_ZOOM_FOCUS: u32 = 294;
 /// Used for camera Zoom write
 pub const MSG_ID_SET_ZOOM_FOCUS: u32 = 295;
+/// Get the ai detection settings of one kind of object
+pub const MSG_ID_GET_AI_ALARM: u32 = 342;
+/// Set the ai detection settings of one kind of object
+pub const MSG_ID_SET_AI_ALARM: u32 = 343;
 /// Get the floodlight task xml
 pub const MSG_ID_FLOODLIGHT_TASKS_READ: u32 = 438;
 
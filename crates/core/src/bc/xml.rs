@@ -124,6 +124,45 @@ pub struct BcXml {
     /// Play a sound
     #[yaserde(rename = "audioPlayInfo")]
     pub audio_play_info: Option<AudioPlayInfo>,
+    /// The speaker volume and alarm sound settings
+    #[yaserde(rename = "audioCfg")]
+    pub audio_cfg: Option<AudioCfg>,
+    /// Used to search for and replay recorded files
+    #[yaserde(rename = "FileInfoList")]
+    pub file_info_list: Option<FileInfoList>,
+    /// The encoder settings of the streams
+    #[yaserde(rename = "Compression")]
+    pub compression: Option<Compression>,
+    /// The ports and state of the network services
+    #[yaserde(rename = "ServerPort")]
+    pub server_port: Option<ServerPort>,
+    /// The motion detection sensitivity and zone
+    #[yaserde(rename = "MdAlarm")]
+    pub md_alarm: Option<MdAlarm>,
+    /// The ai detection sensitivity and zone of one kind of object
+    #[yaserde(rename = "AiAlarm")]
+    pub ai_alarm: Option<AiAlarm>,
+    /// The privacy mask regions
+    #[yaserde(rename = "Shelter")]
+    pub shelter: Option<Shelter>,
+    /// The camera name overlay
+    #[yaserde(rename = "OsdChannelName")]
+    pub osd_channel_name: Option<OsdChannelName>,
+    /// The date and time overlay
+    #[yaserde(rename = "OsdDatetime")]
+    pub osd_datetime: Option<OsdDatetime>,
+    /// The brightness, contrast and colour of the picture
+    #[yaserde(rename = "VideoInput")]
+    pub video_input: Option<VideoInput>,
+    /// The day night mode, exposure and orientation of the picture
+    #[yaserde(rename = "InputAdvanceCfg")]
+    pub input_advance_cfg: Option<InputAdvanceCfg>,
+    /// The SD cards and hard drives of the camera
+    #[yaserde(rename = "HddInfoList")]
+    pub hdd_info_list: Option<HddInfoList>,
+    /// Sent to format an SD card or hard drive
+    #[yaserde(rename = "HddInitList")]
+    pub hdd_init_list: Option<HddInitList>,
 }
 
 impl BcXml {
@@ -599,6 +638,10 @@ pub struct AlarmEvent {
     /// The timestamp associated with the recording. `0` if not recording
     #[yaserde(rename = "timeStamp")]
     pub timeStamp: i32,
+    /// How strongly the motion was detected as a percentage. Only sent by some firmwares
+    pub sensitivity: Option<u8>,
+    /// Confidence of the AI detection as a percentage. Only sent by some firmwares
+    pub confidence: Option<u8>,
 }
 
 /// The Ptz messages used to move the camera
@@ -996,6 +1039,564 @@ pub struct PtzZoomFocus {
     pub focus: HelperPosition,
 }
 
+/// FileInfoList xml
+///
+/// Used to search for the recordings on the camera's SD card and to start their playback
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FileInfoList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// The files or the search request
+    #[yaserde(rename = "FileInfo")]
+    pub file_info: Vec<FileInfo>,
+}
+
+/// FileInfo xml
+///
+/// Depending on the request different fields are present
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct FileInfo {
+    /// Unknown always 0
+    pub uid: Option<u32>,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Handle of an open search. Returned by the camera when the search is opened
+    pub handle: Option<u32>,
+    /// Stream of the recording: Known values `"mainStream"` and `"subStream"`
+    #[yaserde(rename = "streamType")]
+    pub stream_type: Option<String>,
+    /// Triggers of the recording: Observed values `"manual, sched, md, pir, io"`
+    #[yaserde(rename = "recordType")]
+    pub record_type: Option<String>,
+    /// Name of the file on the camera, used to start the playback
+    pub name: Option<String>,
+    /// Size of the file in bytes
+    pub size: Option<u64>,
+    /// Replay speed used when starting playback: Known value 1
+    #[yaserde(rename = "playSpeed")]
+    pub play_speed: Option<u32>,
+    /// Start time of the search or of the file
+    #[yaserde(rename = "startTime")]
+    pub start_time: Option<ReplayDateTime>,
+    /// End time of the search or of the file
+    #[yaserde(rename = "endTime")]
+    pub end_time: Option<ReplayDateTime>,
+}
+
+/// Date time used in the replay xmls, this is in the camera's local time
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
+pub struct ReplayDateTime {
+    /// Year
+    pub year: i32,
+    /// Month 1-12
+    pub month: u8,
+    /// Day 1-31
+    pub day: u8,
+    /// Hour 0-23
+    pub hour: u8,
+    /// Minute 0-59
+    pub minute: u8,
+    /// Second 0-59
+    pub second: u8,
+}
+
+/// Compression xml
+///
+/// The encoder settings of each stream. This is read with [`MSG_ID_GET_COMPRESSION`]
+/// and written back with [`MSG_ID_SET_COMPRESSION`]
+///
+/// [`MSG_ID_GET_COMPRESSION`]: crate::bc::model::MSG_ID_GET_COMPRESSION
+/// [`MSG_ID_SET_COMPRESSION`]: crate::bc::model::MSG_ID_SET_COMPRESSION
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Compression {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Settings of the main stream
+    #[yaserde(rename = "mainStream")]
+    pub main_stream: Option<StreamCompression>,
+    /// Settings of the sub stream
+    #[yaserde(rename = "subStream")]
+    pub sub_stream: Option<StreamCompression>,
+    /// Settings of the extern stream
+    #[yaserde(rename = "thirdStream")]
+    pub third_stream: Option<StreamCompression>,
+}
+
+/// Encoder settings of a single stream
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct StreamCompression {
+    /// If audio is included in the stream: Known values 0, 1
+    pub audio: Option<u8>,
+    /// Resolution in the form `"width*height"` e.g. `"2560*1440"`
+    #[yaserde(rename = "resolutionName")]
+    pub resolution_name: Option<String>,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// The codec: Known values `"h264"`, `"h265"`
+    #[yaserde(rename = "encoderType")]
+    pub encoder_type: Option<String>,
+    /// Frame rate, this should be a value from [`EncodeTable::framerate_table`]
+    pub frame: u32,
+    /// Bit rate in kbps, this should be a value from [`EncodeTable::bitrate_table`]
+    #[yaserde(rename = "bitRate")]
+    pub bit_rate: u32,
+    /// H264 profile: Known values `"base"`, `"main"`, `"high"`
+    #[yaserde(rename = "encoderProfile")]
+    pub encoder_profile: Option<String>,
+    /// Interval between I frames in multiples of the frame rate
+    pub gop: Option<u32>,
+}
+
+/// ServerPort xml
+///
+/// The ports of the network services of the camera and whether they are
+/// enabled. This is read with [`MSG_ID_GET_SERVICE_PORTS`] and written back
+/// with [`MSG_ID_SET_SERVICE_PORTS`]
+///
+/// [`MSG_ID_GET_SERVICE_PORTS`]: crate::bc::model::MSG_ID_GET_SERVICE_PORTS
+/// [`MSG_ID_SET_SERVICE_PORTS`]: crate::bc::model::MSG_ID_SET_SERVICE_PORTS
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct ServerPort {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Port of the Baichuan protocol (default 9000)
+    #[yaserde(rename = "serverPort")]
+    pub server_port: Option<u16>,
+    /// Port of the http server
+    #[yaserde(rename = "httpPort")]
+    pub http_port: Option<u16>,
+    /// 1 if the http server is enabled
+    #[yaserde(rename = "httpEnable")]
+    pub http_enable: Option<u8>,
+    /// Port of the https server
+    #[yaserde(rename = "httpsPort")]
+    pub https_port: Option<u16>,
+    /// 1 if the https server is enabled
+    #[yaserde(rename = "httpsEnable")]
+    pub https_enable: Option<u8>,
+    /// Port of the rtsp server
+    #[yaserde(rename = "rtspPort")]
+    pub rtsp_port: Option<u16>,
+    /// 1 if the rtsp server is enabled
+    #[yaserde(rename = "rtspEnable")]
+    pub rtsp_enable: Option<u8>,
+    /// Port of the rtmp server
+    #[yaserde(rename = "rtmpPort")]
+    pub rtmp_port: Option<u16>,
+    /// 1 if the rtmp server is enabled
+    #[yaserde(rename = "rtmpEnable")]
+    pub rtmp_enable: Option<u8>,
+    /// Port of the onvif server
+    #[yaserde(rename = "onvifPort")]
+    pub onvif_port: Option<u16>,
+    /// 1 if the onvif server is enabled
+    #[yaserde(rename = "onvifEnable")]
+    pub onvif_enable: Option<u8>,
+}
+
+/// MdAlarm xml
+///
+/// The motion detection settings of the camera. This is read with
+/// [`MSG_ID_GET_MD_ALARM`] and written back with [`MSG_ID_SET_MD_ALARM`]
+///
+/// [`MSG_ID_GET_MD_ALARM`]: crate::bc::model::MSG_ID_GET_MD_ALARM
+/// [`MSG_ID_SET_MD_ALARM`]: crate::bc::model::MSG_ID_SET_MD_ALARM
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct MdAlarm {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 if motion detection is enabled
+    pub enable: Option<u8>,
+    /// Columns of the detection zone grid
+    pub width: Option<u32>,
+    /// Rows of the detection zone grid
+    pub height: Option<u32>,
+    /// The detection zone, one `1` or `0` for each cell of the grid row by row
+    pub area: Option<String>,
+    /// The sensitivity during each part of the day
+    #[yaserde(rename = "sensInfoList")]
+    pub sens_info_list: Option<SensInfoList>,
+}
+
+/// The sensitivities of the motion detection
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct SensInfoList {
+    /// One entry for each part of the day
+    #[yaserde(rename = "sensInfo")]
+    pub sens_info: Vec<SensInfo>,
+}
+
+/// The sensitivity of the motion detection during part of the day
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct SensInfo {
+    /// Index of the entry
+    pub id: u8,
+    /// 1 if this entry is used
+    pub enable: Option<u8>,
+    /// Hour this entry starts
+    #[yaserde(rename = "beginHour")]
+    pub begin_hour: u8,
+    /// Minute this entry starts
+    #[yaserde(rename = "beginMinute")]
+    pub begin_minute: u8,
+    /// Hour this entry ends
+    #[yaserde(rename = "endHour")]
+    pub end_hour: u8,
+    /// Minute this entry ends
+    #[yaserde(rename = "endMinute")]
+    pub end_minute: u8,
+    /// Sensitivity from 1 to 50, lower values trigger more easily
+    pub sensitivity: u8,
+}
+
+/// AiAlarm xml
+///
+/// The ai detection settings of one kind of object such as `people`. This is
+/// read with [`MSG_ID_GET_AI_ALARM`] and written back with
+/// [`MSG_ID_SET_AI_ALARM`]
+///
+/// [`MSG_ID_GET_AI_ALARM`]: crate::bc::model::MSG_ID_GET_AI_ALARM
+/// [`MSG_ID_SET_AI_ALARM`]: crate::bc::model::MSG_ID_SET_AI_ALARM
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct AiAlarm {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The kind of object: Known values `"people"`, `"vehicle"`, `"dog_cat"`
+    #[yaserde(rename = "aiType")]
+    pub ai_type: String,
+    /// Sensitivity from 0 to 100, higher values trigger more easily
+    pub sensitivity: Option<u8>,
+    /// Seconds that the object must stay before it triggers
+    #[yaserde(rename = "stayTime")]
+    pub stay_time: Option<u32>,
+    /// Columns of the detection zone grid
+    pub width: Option<u32>,
+    /// Rows of the detection zone grid
+    pub height: Option<u32>,
+    /// The detection zone, one `1` or `0` for each cell of the grid row by row
+    pub area: Option<String>,
+}
+
+/// Shelter xml
+///
+/// The privacy mask of the camera, the regions are blacked out of the video
+/// and recordings. This is read with [`MSG_ID_GET_SHELTER`] and written back
+/// with [`MSG_ID_SET_SHELTER`]
+///
+/// [`MSG_ID_GET_SHELTER`]: crate::bc::model::MSG_ID_GET_SHELTER
+/// [`MSG_ID_SET_SHELTER`]: crate::bc::model::MSG_ID_SET_SHELTER
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Shelter {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 if the privacy mask is enabled
+    pub enable: Option<u8>,
+    /// Width of the area that the region coordinates are in
+    pub width: Option<u32>,
+    /// Height of the area that the region coordinates are in
+    pub height: Option<u32>,
+    /// Most regions the camera supports
+    #[yaserde(rename = "maxNum")]
+    pub max_num: Option<u32>,
+    /// The regions of the mask
+    #[yaserde(rename = "shelterList")]
+    pub shelter_list: Option<ShelterList>,
+}
+
+/// The regions of the privacy mask
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct ShelterList {
+    /// One entry for each region
+    #[yaserde(rename = "shelter")]
+    pub shelters: Vec<ShelterRegion>,
+}
+
+/// A rectangle of the privacy mask
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct ShelterRegion {
+    /// Index of the region
+    pub id: u8,
+    /// 1 if this region is masked
+    pub enable: u8,
+    /// Left edge
+    pub x: u32,
+    /// Top edge
+    pub y: u32,
+    /// Width of the region
+    pub width: u32,
+    /// Height of the region
+    pub height: u32,
+}
+
+/// OsdChannelName xml
+///
+/// The camera name that is drawn on the video. This is read with
+/// [`MSG_ID_GET_OSD`] and written back with [`MSG_ID_SET_OSD`] together with
+/// the [OsdDatetime]
+///
+/// [`MSG_ID_GET_OSD`]: crate::bc::model::MSG_ID_GET_OSD
+/// [`MSG_ID_SET_OSD`]: crate::bc::model::MSG_ID_SET_OSD
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct OsdChannelName {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The name of the camera
+    pub name: String,
+    /// 1 if the name is drawn on the video
+    pub enable: u8,
+    /// Left edge of the name
+    #[yaserde(rename = "topLeftX")]
+    pub top_left_x: Option<u32>,
+    /// Top edge of the name
+    #[yaserde(rename = "topLeftY")]
+    pub top_left_y: Option<u32>,
+    /// 1 if the Reolink watermark is drawn
+    #[yaserde(rename = "enWatermark")]
+    pub en_watermark: Option<u8>,
+    /// 1 if the text has a background
+    #[yaserde(rename = "enBgcolor")]
+    pub en_bgcolor: Option<u8>,
+}
+
+/// OsdDatetime xml
+///
+/// The date and time that is drawn on the video, see [OsdChannelName]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct OsdDatetime {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 if the time is drawn on the video
+    pub enable: u8,
+    /// Left edge of the time
+    #[yaserde(rename = "topLeftX")]
+    pub top_left_x: Option<u32>,
+    /// Top edge of the time
+    #[yaserde(rename = "topLeftY")]
+    pub top_left_y: Option<u32>,
+    /// Width of the time
+    pub width: Option<u32>,
+    /// Height of the time
+    pub height: Option<u32>,
+    /// Language of the day names: Known values `"English"`
+    pub language: Option<String>,
+}
+
+/// VideoInput xml
+///
+/// The picture settings, each value is from 0 to 255 with 128 as the default.
+/// It is sent with [`MSG_ID_GET_VIDEO_INPUT`] and [`MSG_ID_SET_VIDEO_INPUT`]
+/// together with the [InputAdvanceCfg]
+///
+/// [`MSG_ID_GET_VIDEO_INPUT`]: crate::bc::model::MSG_ID_GET_VIDEO_INPUT
+/// [`MSG_ID_SET_VIDEO_INPUT`]: crate::bc::model::MSG_ID_SET_VIDEO_INPUT
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct VideoInput {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Brightness
+    pub bright: Option<u8>,
+    /// Contrast
+    pub contrast: Option<u8>,
+    /// Saturation
+    pub saturation: Option<u8>,
+    /// Hue
+    pub hue: Option<u8>,
+    /// Sharpness
+    pub sharpen: Option<u8>,
+}
+
+/// InputAdvanceCfg xml
+///
+/// The image signal processor settings, see [VideoInput]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct InputAdvanceCfg {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Unknown: Observed values 1
+    #[yaserde(rename = "digitalChannel")]
+    pub digital_channel: Option<u8>,
+    /// Anti flicker for the mains frequency
+    #[yaserde(rename = "PowerLineFrequency")]
+    pub power_line_frequency: Option<PowerLineFrequency>,
+    /// Exposure settings
+    #[yaserde(rename = "Exposure")]
+    pub exposure: Option<Exposure>,
+    /// White balance settings
+    #[yaserde(rename = "Scene")]
+    pub scene: Option<Scene>,
+    /// Day night mode
+    #[yaserde(rename = "DayNight")]
+    pub day_night: Option<DayNight>,
+    /// Back light compensation
+    #[yaserde(rename = "BLC")]
+    pub blc: Option<Blc>,
+    /// 1 if the picture is mirrored left to right
+    pub mirror: Option<u8>,
+    /// 1 if the picture is flipped upside down
+    pub flip: Option<u8>,
+    /// Iris settings
+    #[yaserde(rename = "Iris")]
+    pub iris: Option<Iris>,
+    /// Noise reduction
+    pub nr3d: Option<Nr3d>,
+}
+
+/// Anti flicker part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct PowerLineFrequency {
+    /// Known values `"50hz"` and `"60hz"`
+    pub mode: String,
+    /// 1 if the anti flicker is on
+    pub enable: u8,
+}
+
+/// Exposure part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Exposure {
+    /// Known values `"auto"`
+    pub mode: String,
+    /// Gain limits
+    #[yaserde(rename = "Gainctl")]
+    pub gainctl: Option<ExposureLimits>,
+    /// Shutter limits
+    #[yaserde(rename = "Shutterctl")]
+    pub shutterctl: Option<ExposureLimits>,
+    /// Shutter speed: Observed values `"1/30"`
+    #[yaserde(rename = "shutterLevel")]
+    pub shutter_level: Option<String>,
+    /// Gain level
+    #[yaserde(rename = "gainLevel")]
+    pub gain_level: Option<u32>,
+}
+
+/// The default and current limits of a part of the [Exposure]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct ExposureLimits {
+    /// Default minimum
+    #[yaserde(rename = "defMin")]
+    pub def_min: u32,
+    /// Default maximum
+    #[yaserde(rename = "defMax")]
+    pub def_max: u32,
+    /// Current minimum
+    #[yaserde(rename = "curMin")]
+    pub cur_min: u32,
+    /// Current maximum
+    #[yaserde(rename = "curMax")]
+    pub cur_max: u32,
+}
+
+/// White balance part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Scene {
+    /// Known values `"auto"` and `"manual"`
+    pub mode: String,
+    /// Supported modes: Observed values `"auto, manual"`
+    #[yaserde(rename = "modeList")]
+    pub mode_list: Option<String>,
+    /// Red gain used in manual mode
+    #[yaserde(rename = "Redgain")]
+    pub redgain: Option<IspRange>,
+    /// Blue gain used in manual mode
+    #[yaserde(rename = "Bluegain")]
+    pub bluegain: Option<IspRange>,
+}
+
+/// A value of the [InputAdvanceCfg] and its limits
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct IspRange {
+    /// Minimum
+    pub min: u32,
+    /// Maximum
+    pub max: u32,
+    /// Current value
+    pub cur: u32,
+}
+
+/// Day night part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct DayNight {
+    /// Observed values `"auto"`, `"color"` and `"blackAndWhite"` force the mode
+    pub mode: String,
+    /// Known values `"ir"`
+    #[yaserde(rename = "IrcutMode")]
+    pub ircut_mode: Option<String>,
+    /// How dark before switching to night: Observed values `"medium"`
+    #[yaserde(rename = "Threshold")]
+    pub threshold: Option<String>,
+}
+
+/// Back light compensation part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Blc {
+    /// 1 if the compensation is on
+    pub enable: u8,
+    /// Observed values `"backLight"`
+    pub mode: Option<String>,
+    /// Strength in the dynamic range mode
+    pub dynamicrange: Option<IspRange>,
+    /// Strength in the back light mode
+    pub backlight: Option<IspRange>,
+}
+
+/// Iris part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Iris {
+    /// 1 if the auto iris is on
+    pub enable: u8,
+    /// Known values `"success"`
+    pub state: Option<String>,
+    /// Unknown: Observed values 0
+    #[yaserde(rename = "focusAutoiris")]
+    pub focus_autoiris: Option<u8>,
+}
+
+/// Noise reduction part of the [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct Nr3d {
+    /// Known values `"high"`
+    pub value: Option<String>,
+    /// 1 if the noise reduction is on
+    pub enable: u8,
+}
+
 /// StartZoomFocus xml
 #[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize)]
 pub struct StartZoomFocus {
@@ -1005,7 +1606,7 @@ pub struct StartZoomFocus {
     /// Channel ID
     #[yaserde(rename = "channelId")]
     pub channel_id: u8,
-    /// Command: Observed values: zoomPos. (Write Only)
+    /// Command: Observed values: zoomPos, focusPos. (Write Only)
     pub command: String,
     /// Target Position: Observed Values: 2994, 2508, 2888, 3089, 3194, 3163. (Write Only)
     #[yaserde(rename = "movePos")]
@@ -1321,6 +1922,116 @@ pub struct AudioPlayInfo {
     pub on_off: u32,
 }
 
+/// audioCfg xml
+///
+/// The speaker volume and the settings of the alarm sound. It is read with
+/// [`MSG_ID_GET_AUDIO_CFG`] and written back with [`MSG_ID_SET_AUDIO_CFG`]
+///
+/// [`MSG_ID_GET_AUDIO_CFG`]: crate::bc::model::MSG_ID_GET_AUDIO_CFG
+/// [`MSG_ID_SET_AUDIO_CFG`]: crate::bc::model::MSG_ID_SET_AUDIO_CFG
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct AudioCfg {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// Channel ID
+    #[yaserde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Unknown: Observed values 0
+    pub timeout: Option<u32>,
+    /// The alarm sound: Observed values 0
+    #[yaserde(rename = "audioSelect")]
+    pub audio_select: Option<u32>,
+    /// Speaker volume from 0 to 100
+    pub volume: u8,
+    /// Unknown: Observed values 0
+    #[yaserde(rename = "preAlarm")]
+    pub pre_alarm: Option<u32>,
+    /// 1 if the alarm sound is paused
+    #[yaserde(rename = "pauseAlarm")]
+    pub pause_alarm: Option<u32>,
+    /// Unknown: Observed values 0
+    #[yaserde(rename = "pauseType")]
+    pub pause_type: Option<u32>,
+    /// Start of the pause
+    #[yaserde(rename = "pauseStartTime")]
+    pub pause_start_time: Option<u32>,
+    /// Length of the pause
+    #[yaserde(rename = "pauseTime")]
+    pub pause_time: Option<u32>,
+    /// Unknown: Observed values 0
+    #[yaserde(rename = "audioListId")]
+    pub audio_list_id: Option<u32>,
+    /// Unknown: Observed values 1
+    #[yaserde(rename = "linkageCtrlEnable")]
+    pub linkage_ctrl_enable: Option<u32>,
+}
+
+/// HddInfoList xml
+///
+/// The SD cards or hard drives of the camera, read with
+/// [`MSG_ID_GET_HDD_INFO`]. It is empty when no card is inserted
+///
+/// [`MSG_ID_GET_HDD_INFO`]: crate::bc::model::MSG_ID_GET_HDD_INFO
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct HddInfoList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// One for each card or drive
+    #[yaserde(rename = "HddInfo")]
+    pub hdd_info: Vec<HddInfo>,
+}
+
+/// The capacity and state of a single SD card or hard drive
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct HddInfo {
+    /// The id of the card, used to format it
+    pub number: u8,
+    /// Total size in MB
+    pub capacity: u64,
+    /// Free space in MB
+    #[yaserde(rename = "remainSize")]
+    pub remain_size: Option<u64>,
+    /// 1 if the card is mounted
+    pub mount: Option<u8>,
+    /// 1 if the card is formatted
+    pub format: Option<u8>,
+    /// Kind of storage: Observed values `sd`
+    #[yaserde(rename = "storageType")]
+    pub storage_type: Option<String>,
+}
+
+impl HddInfo {
+    /// The card is formatted and mounted so it can be recorded to
+    pub fn healthy(&self) -> bool {
+        self.mount != Some(0) && self.format != Some(0)
+    }
+}
+
+/// HddInitList xml
+///
+/// Sent with [`MSG_ID_HDD_INIT`] to format the cards with these ids
+///
+/// [`MSG_ID_HDD_INIT`]: crate::bc::model::MSG_ID_HDD_INIT
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct HddInitList {
+    /// XML Version
+    #[yaserde(attribute)]
+    pub version: String,
+    /// One for each card to format
+    #[yaserde(rename = "HddInit")]
+    pub hdd_init: Vec<HddInit>,
+}
+
+/// The card to format
+#[derive(PartialEq, Eq, Default, Debug, YaDeserialize, YaSerialize, Clone)]
+pub struct HddInit {
+    /// The id of the card from [`HddInfo::number`]
+    #[yaserde(rename = "initId")]
+    pub init_id: u8,
+}
+
 /// Convience function to return the xml version used throughout the library
 pub fn xml_ver() -> String {
     "1.1".to_string()
@@ -1491,3 +2202,522 @@ fn test_binary_deser() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn test_file_info_list() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FileInfoList version="1.1">
+        <FileInfo>
+        <channelId>0</channelId>
+        <streamType>mainStream</streamType>
+        <recordType>manual, sched, md, pir, io</recordType>
+        <name>Mp4Record/2023-10-10/RecM01_20231010_101500_101600_6D28808_32A4E1.mp4</name>
+        <size>3319009</size>
+        <startTime>
+        <year>2023</year>
+        <month>10</month>
+        <day>10</day>
+        <hour>10</hour>
+        <minute>15</minute>
+        <second>0</second>
+        </startTime>
+        <endTime>
+        <year>2023</year>
+        <month>10</month>
+        <day>10</day>
+        <hour>10</hour>
+        <minute>16</minute>
+        <second>0</second>
+        </endTime>
+        </FileInfo>
+        </FileInfoList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match &b {
+        BcXml {
+            file_info_list: Some(FileInfoList { file_info, .. }),
+            ..
+        } => {
+            assert_eq!(file_info.len(), 1);
+            assert_eq!(file_info[0].size, Some(3319009));
+            assert_eq!(
+                file_info[0].end_time,
+                Some(ReplayDateTime {
+                    year: 2023,
+                    month: 10,
+                    day: 10,
+                    hour: 10,
+                    minute: 16,
+                    second: 0,
+                })
+            );
+        }
+        _ => panic!(),
+    }
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_compression() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Compression version="1.1">
+        <channelId>0</channelId>
+        <mainStream>
+        <audio>1</audio>
+        <resolutionName>2560*1440</resolutionName>
+        <width>2560</width>
+        <height>1440</height>
+        <encoderType>h264</encoderType>
+        <frame>25</frame>
+        <bitRate>6144</bitRate>
+        <encoderProfile>high</encoderProfile>
+        <gop>2</gop>
+        </mainStream>
+        <subStream>
+        <audio>1</audio>
+        <resolutionName>640*360</resolutionName>
+        <width>640</width>
+        <height>360</height>
+        <frame>10</frame>
+        <bitRate>256</bitRate>
+        <encoderProfile>main</encoderProfile>
+        </subStream>
+        </Compression>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match &b {
+        BcXml {
+            compression:
+                Some(Compression {
+                    main_stream: Some(main),
+                    sub_stream: Some(sub),
+                    third_stream: None,
+                    ..
+                }),
+            ..
+        } => {
+            assert_eq!(main.width, 2560);
+            assert_eq!(main.bit_rate, 6144);
+            assert_eq!(main.encoder_type.as_deref(), Some("h264"));
+            assert_eq!(sub.frame, 10);
+            assert_eq!(sub.encoder_type, None);
+        }
+        _ => panic!(),
+    }
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_alarm_event_confidence() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <AlarmEventList version="1.1">
+        <AlarmEvent version="1.1">
+        <channelId>0</channelId>
+        <status>MD</status>
+        <AItype>people</AItype>
+        <recording>0</recording>
+        <timeStamp>0</timeStamp>
+        <sensitivity>64</sensitivity>
+        <confidence>87</confidence>
+        </AlarmEvent>
+        <AlarmEvent version="1.1">
+        <channelId>1</channelId>
+        <status>none</status>
+        <recording>0</recording>
+        <timeStamp>0</timeStamp>
+        </AlarmEvent>
+        </AlarmEventList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match &b {
+        BcXml {
+            alarm_event_list: Some(AlarmEventList { alarm_events, .. }),
+            ..
+        } => {
+            assert_eq!(alarm_events.len(), 2);
+            assert_eq!(alarm_events[0].ai_type.as_deref(), Some("people"));
+            assert_eq!(alarm_events[0].sensitivity, Some(64));
+            assert_eq!(alarm_events[0].confidence, Some(87));
+            assert_eq!(alarm_events[1].sensitivity, None);
+            assert_eq!(alarm_events[1].confidence, None);
+        }
+        _ => panic!(),
+    }
+
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_server_port() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <ServerPort version="1.1">
+        <serverPort>9000</serverPort>
+        <httpPort>80</httpPort>
+        <httpEnable>1</httpEnable>
+        <httpsPort>443</httpsPort>
+        <httpsEnable>0</httpsEnable>
+        <rtspPort>554</rtspPort>
+        <rtspEnable>1</rtspEnable>
+        <rtmpPort>1935</rtmpPort>
+        <rtmpEnable>0</rtmpEnable>
+        <onvifPort>8000</onvifPort>
+        <onvifEnable>1</onvifEnable>
+        </ServerPort>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let server_port = b.server_port.as_ref().unwrap();
+    assert_eq!(server_port.server_port, Some(9000));
+    assert_eq!(server_port.rtsp_port, Some(554));
+    assert_eq!(server_port.rtsp_enable, Some(1));
+    assert_eq!(server_port.rtmp_enable, Some(0));
+    assert_eq!(server_port.onvif_port, Some(8000));
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_md_alarm() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <MdAlarm version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <width>4</width>
+        <height>2</height>
+        <area>11110011</area>
+        <sensInfoList>
+        <sensInfo>
+        <id>0</id>
+        <enable>1</enable>
+        <beginHour>0</beginHour>
+        <beginMinute>0</beginMinute>
+        <endHour>23</endHour>
+        <endMinute>59</endMinute>
+        <sensitivity>10</sensitivity>
+        </sensInfo>
+        </sensInfoList>
+        </MdAlarm>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let md_alarm = b.md_alarm.as_ref().unwrap();
+    assert_eq!(md_alarm.width, Some(4));
+    assert_eq!(md_alarm.area.as_deref(), Some("11110011"));
+    let sens_info = &md_alarm.sens_info_list.as_ref().unwrap().sens_info;
+    assert_eq!(sens_info.len(), 1);
+    assert_eq!(sens_info[0].end_hour, 23);
+    assert_eq!(sens_info[0].sensitivity, 10);
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_shelter() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Shelter version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <width>640</width>
+        <height>360</height>
+        <maxNum>4</maxNum>
+        <shelterList>
+        <shelter>
+        <id>0</id>
+        <enable>1</enable>
+        <x>10</x>
+        <y>20</y>
+        <width>100</width>
+        <height>50</height>
+        </shelter>
+        <shelter>
+        <id>1</id>
+        <enable>0</enable>
+        <x>0</x>
+        <y>0</y>
+        <width>0</width>
+        <height>0</height>
+        </shelter>
+        </shelterList>
+        </Shelter>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let shelter = b.shelter.as_ref().unwrap();
+    assert_eq!(shelter.enable, Some(1));
+    assert_eq!(shelter.width, Some(640));
+    assert_eq!(shelter.max_num, Some(4));
+    let shelters = &shelter.shelter_list.as_ref().unwrap().shelters;
+    assert_eq!(shelters.len(), 2);
+    assert_eq!(shelters[0].x, 10);
+    assert_eq!(shelters[0].height, 50);
+    assert_eq!(shelters[1].enable, 0);
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_osd() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <OsdChannelName version="1.1">
+        <channelId>0</channelId>
+        <name>Front Door</name>
+        <enable>1</enable>
+        <topLeftX>0</topLeftX>
+        <topLeftY>0</topLeftY>
+        <enWatermark>1</enWatermark>
+        <enBgcolor>0</enBgcolor>
+        </OsdChannelName>
+        <OsdDatetime version="1.1">
+        <channelId>0</channelId>
+        <enable>0</enable>
+        <topLeftX>1</topLeftX>
+        <topLeftY>1</topLeftY>
+        <width>0</width>
+        <height>0</height>
+        <language>English</language>
+        </OsdDatetime>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let channel_name = b.osd_channel_name.as_ref().unwrap();
+    assert_eq!(channel_name.name, "Front Door");
+    assert_eq!(channel_name.enable, 1);
+    assert_eq!(channel_name.en_watermark, Some(1));
+    let datetime = b.osd_datetime.as_ref().unwrap();
+    assert_eq!(datetime.enable, 0);
+    assert_eq!(datetime.language.as_deref(), Some("English"));
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_video_input() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <VideoInput version="1.1">
+        <channelId>0</channelId>
+        <bright>128</bright>
+        <contrast>128</contrast>
+        <saturation>128</saturation>
+        <hue>128</hue>
+        <sharpen>128</sharpen>
+        </VideoInput>
+        <InputAdvanceCfg version="1.1">
+        <channelId>0</channelId>
+        <digitalChannel>1</digitalChannel>
+        <PowerLineFrequency>
+        <mode>50hz</mode>
+        <enable>0</enable>
+        </PowerLineFrequency>
+        <Exposure>
+        <mode>auto</mode>
+        <Gainctl>
+        <defMin>1</defMin>
+        <defMax>100</defMax>
+        <curMin>1</curMin>
+        <curMax>62</curMax>
+        </Gainctl>
+        <Shutterctl>
+        <defMin>0</defMin>
+        <defMax>125</defMax>
+        <curMin>0</curMin>
+        <curMax>125</curMax>
+        </Shutterctl>
+        <shutterLevel>1/30</shutterLevel>
+        <gainLevel>50</gainLevel>
+        </Exposure>
+        <Scene>
+        <mode>auto</mode>
+        <modeList>auto, manual</modeList>
+        <Redgain>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </Redgain>
+        <Bluegain>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </Bluegain>
+        </Scene>
+        <DayNight>
+        <mode>auto</mode>
+        <IrcutMode>ir</IrcutMode>
+        <Threshold>medium</Threshold>
+        </DayNight>
+        <BLC>
+        <enable>0</enable>
+        <mode>backLight</mode>
+        <backlight>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </backlight>
+        <dynamicrange>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </dynamicrange>
+        </BLC>
+        <mirror>0</mirror>
+        <flip>1</flip>
+        <Iris>
+        <enable>0</enable>
+        <state>success</state>
+        <focusAutoiris>0</focusAutoiris>
+        </Iris>
+        <nr3d>
+        <value>high</value>
+        <enable>1</enable>
+        </nr3d>
+        </InputAdvanceCfg>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let video_input = b.video_input.as_ref().unwrap();
+    assert_eq!(video_input.bright, Some(128));
+    assert_eq!(video_input.sharpen, Some(128));
+    let advance = b.input_advance_cfg.as_ref().unwrap();
+    assert_eq!(advance.day_night.as_ref().unwrap().mode, "auto");
+    assert_eq!(advance.mirror, Some(0));
+    assert_eq!(advance.flip, Some(1));
+    assert_eq!(
+        advance.exposure.as_ref().unwrap().shutter_level.as_deref(),
+        Some("1/30")
+    );
+    assert_eq!(
+        advance
+            .blc
+            .as_ref()
+            .unwrap()
+            .backlight
+            .as_ref()
+            .unwrap()
+            .cur,
+        128
+    );
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_audio_cfg() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <audioCfg version="1.1">
+        <channelId>0</channelId>
+        <timeout>0</timeout>
+        <audioSelect>0</audioSelect>
+        <volume>85</volume>
+        <preAlarm>0</preAlarm>
+        <pauseAlarm>0</pauseAlarm>
+        <pauseType>0</pauseType>
+        <pauseStartTime>0</pauseStartTime>
+        <pauseTime>0</pauseTime>
+        <audioListId>0</audioListId>
+        <linkageCtrlEnable>1</linkageCtrlEnable>
+        </audioCfg>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let audio_cfg = b.audio_cfg.as_ref().unwrap();
+    assert_eq!(audio_cfg.volume, 85);
+    assert_eq!(audio_cfg.linkage_ctrl_enable, Some(1));
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+}
+
+#[test]
+fn test_hdd_info() {
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1">
+        <HddInfo>
+        <number>0</number>
+        <capacity>30432</capacity>
+        <remainSize>12011</remainSize>
+        <mount>1</mount>
+        <format>1</format>
+        <storageType>sd</storageType>
+        </HddInfo>
+        </HddInfoList>
+        </body>"#
+    );
+
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let hdd = &b.hdd_info_list.as_ref().unwrap().hdd_info[0];
+    assert_eq!(hdd.capacity, 30432);
+    assert_eq!(hdd.remain_size, Some(12011));
+    assert!(hdd.healthy());
+
+    // Round trip it
+    let b2 = BcXml::try_parse(b.serialize(vec![]).unwrap().as_slice()).unwrap();
+    assert_eq!(b, b2);
+
+    // Without a card it is empty
+    let sample = indoc!(
+        r#"
+        <?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1" />
+        </body>"#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    assert!(b.hdd_info_list.unwrap().hdd_info.is_empty());
+}
@@ -1,38 +1,53 @@
 use crate::bc;
+use crate::bc::capture::Capture;
 use futures::stream::StreamExt;
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::{
     collections::HashMap,
     sync::atomic::{AtomicBool, AtomicU16, Ordering},
 };
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 use Md5Trunc::*;
 
 mod abilityinfo;
+mod audio;
 mod battery;
+mod compression;
 mod connection;
 mod credentials;
 mod errors;
 mod floodlight;
+mod isp;
 mod keepalive;
 mod ledstate;
 mod link;
 mod login;
 mod logout;
+mod mask;
+mod mdconfig;
+mod mediaqueue;
 mod motion;
+mod notifytest;
+mod osd;
 mod ping;
 mod pirstate;
+mod playback;
 mod ptz;
 mod pushinfo;
+mod raw;
 mod reboot;
+mod reply;
 mod resolution;
+mod services;
 mod siren;
 mod snap;
 mod stream;
+mod storage;
 mod stream_info;
 mod support;
 mod talk;
@@ -41,20 +56,35 @@ mod uid;
 mod version;
 
 pub(crate) use connection::*;
+pub use compression::EncodeChange;
+pub use connection::{scan_local, LocalCamera};
 pub use credentials::*;
 pub use errors::Error;
+pub use isp::{DayNightMode, ImageSettings, IspChange};
 pub use ledstate::LightState;
 pub use login::MaxEncryption;
-pub use motion::{MotionData, MotionStatus};
-pub use pirstate::PirState;
+pub use mask::MaskRegion;
+pub use mdconfig::DetectionZone;
+pub use motion::{AiDetection, MotionData, MotionDetail, MotionStatus};
+pub use notifytest::NotifyTest;
+pub use osd::Osd;
+pub use pirstate::{PirSchedule, PirState};
 pub use ptz::Direction;
 pub use pushinfo::PhoneType;
+pub use raw::RawReply;
+pub use reply::Confirmation;
 pub use resolution::*;
+pub use services::Service;
 use std::sync::Arc;
-pub use stream::{StreamData, StreamKind};
+pub use stream::{StreamData, StreamHandles, StreamKind, DEFAULT_STREAM_BUFFER};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
+/// How long to wait for the camera to accept a command
+pub const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long to wait for the camera to accept a command over the reolink relay
+pub const RELAY_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Clone, Copy)]
 enum ReadKind {
     ReadOnly,
@@ -68,11 +98,16 @@ enum ReadKind {
 pub struct BcCamera {
     channel_id: u8,
     connection: Arc<BcConnection>,
-    logged_in: AtomicBool,
-    message_num: AtomicU16,
+    // Login and message numbers belong to the connection so are shared by
+    // every channel of an NVR
+    logged_in: Arc<AtomicBool>,
+    message_num: Arc<AtomicU16>,
     // Certain commands such as logout require the username/pass in plain text.... why....???
     credentials: Credentials,
-    abilities: RwLock<HashMap<String, ReadKind>>,
+    abilities: Arc<RwLock<HashMap<String, ReadKind>>>,
+    // How long set commands wait for the camera to accept them
+    reply_timeout: Duration,
+    stream_handles: StreamHandles,
     #[allow(dead_code)]
     cancel: CancellationToken,
 }
@@ -101,6 +136,13 @@ pub struct BcCameraOpt {
     pub credentials: Credentials,
     /// Toggle debug print of underlying data
     pub debug: bool,
+    /// How long to wait for the camera to accept a command. When not given
+    /// [`DEFAULT_REPLY_TIMEOUT`] is used or [`RELAY_REPLY_TIMEOUT`] when relayed
+    pub reply_timeout: Option<Duration>,
+    /// The preview handles used to start the streams
+    pub stream_handles: StreamHandles,
+    /// Record the received messages into a capture file in this directory
+    pub capture_dir: Option<PathBuf>,
 }
 
 /// Used to choose the print format of various status messages like battery levels
@@ -132,6 +174,8 @@ pub enum ConnectionProtocol {
 enum CameraLocation {
     Tcp(SocketAddr),
     Udp(DiscoveryResult),
+    /// Udp via the reolink relay servers
+    Relay(DiscoveryResult),
 }
 
 impl BcCamera {
@@ -293,7 +337,7 @@ impl BcCamera {
                                         uid_relay,
                                         disc.get_addr()
                                     );
-                                    Ok(CameraLocation::Udp(disc))
+                                    Ok(CameraLocation::Relay(disc))
                                 },
                                 Err(e) => Err(e),
                             }
@@ -327,15 +371,20 @@ impl BcCamera {
         let username: String = options.credentials.username.clone();
         let passwd: Option<String> = options.credentials.password.clone();
 
+        let location = BcCamera::find_camera(options).await?;
+        let reply_timeout = options.reply_timeout.unwrap_or(match location {
+            CameraLocation::Relay(_) => RELAY_REPLY_TIMEOUT,
+            _ => DEFAULT_REPLY_TIMEOUT,
+        });
         let (sink, source): (BcConnSink, BcConnSource) = {
-            match BcCamera::find_camera(options).await? {
+            match location {
                 CameraLocation::Tcp(addr) => {
                     let (x, r) = TcpSource::new(addr, &username, passwd.as_ref(), options.debug)
                         .await?
                         .split();
                     (Box::new(x), Box::new(r))
                 }
-                CameraLocation::Udp(discovery) => {
+                CameraLocation::Udp(discovery) | CameraLocation::Relay(discovery) => {
                     let (x, r) = UdpSource::new_from_discovery(
                         discovery,
                         &username,
@@ -349,22 +398,55 @@ impl BcCamera {
             }
         };
 
-        let conn = BcConnection::new(sink, source).await?;
+        let capture = options
+            .capture_dir
+            .as_deref()
+            .map(|dir| Capture::create(dir, &options.name))
+            .transpose()?;
+        let conn = BcConnection::new(sink, source, &options.name, capture).await?;
 
         trace!("Success");
         let me = Self {
             connection: Arc::new(conn),
-            message_num: AtomicU16::new(0),
+            message_num: Arc::new(AtomicU16::new(0)),
             channel_id: options.channel_id,
-            logged_in: AtomicBool::new(false),
+            logged_in: Arc::new(AtomicBool::new(false)),
             credentials: Credentials::new(username, passwd),
             abilities: Default::default(),
+            reply_timeout,
+            stream_handles: options.stream_handles,
             cancel: CancellationToken::new(),
         };
         me.keepalive().await?;
         Ok(me)
     }
 
+    ///
+    /// Create an interface to another channel of the same device
+    ///
+    /// The connection, login and abilities are shared with this camera
+    /// so the channels of an NVR only need one connection. Use
+    /// [`BcCamera::get_channels`] to find the channels of the device
+    ///
+    pub fn channel(&self, channel_id: u8) -> Self {
+        Self {
+            channel_id,
+            connection: self.connection.clone(),
+            logged_in: self.logged_in.clone(),
+            message_num: self.message_num.clone(),
+            credentials: self.credentials.clone(),
+            abilities: self.abilities.clone(),
+            reply_timeout: self.reply_timeout,
+            stream_handles: self.stream_handles,
+            cancel: self.cancel.child_token(),
+        }
+    }
+
+    /// Get the channel this interface sends its commands to
+    pub fn channel_id(&self) -> u8 {
+        self.channel_id
+    }
+
     /// This method will get a new message number and increment the message count atomically
     pub fn new_message_num(&self) -> u16 {
         self.message_num.fetch_add(1, Ordering::Relaxed)
@@ -0,0 +1,164 @@
+use super::{BcCamera, Confirmation, Error, Result, StreamKind};
+use crate::bc::{model::*, xml::*};
+
+const STREAMS: [StreamKind; 3] = [StreamKind::Main, StreamKind::Sub, StreamKind::Extern];
+
+impl Compression {
+    /// Whether the streams record the microphone
+    ///
+    /// This is `None` if the camera does not report it for any stream
+    pub fn audio_enabled(&self) -> Option<bool> {
+        STREAMS
+            .iter()
+            .filter_map(|kind| self.stream(*kind).and_then(|stream| stream.audio))
+            .map(|audio| audio != 0)
+            .reduce(|a, b| a || b)
+    }
+
+    /// Turn the microphone on or off in every stream
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        for kind in STREAMS.iter() {
+            if let Some(stream) = self.stream_mut(*kind) {
+                stream.audio = Some(enabled as u8);
+            }
+        }
+    }
+}
+
+impl BcCamera {
+    /// Get the [AudioCfg] xml which contains the speaker volume
+    pub async fn get_audio_cfg(&self) -> Result<AudioCfg> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_AUDIO_CFG, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_AUDIO_CFG,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    audio_cfg: Some(audio_cfg),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(audio_cfg)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected audioCfg xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the speaker settings using the [AudioCfg] xml
+    pub async fn set_audio_cfg(&self, audio_cfg: AudioCfg) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_AUDIO_CFG, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_AUDIO_CFG,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    audio_cfg: Some(audio_cfg),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the audioCfg xml")
+            .await
+    }
+
+    /// This is a convience function to set the speaker volume from 0 to 100
+    pub async fn set_volume(&self, volume: u8) -> Result<Confirmation> {
+        if volume > 100 {
+            return Err(Error::OtherString(format!(
+                "The volume {} should be from 0 to 100",
+                volume
+            )));
+        }
+        let mut audio_cfg = self.get_audio_cfg().await?;
+        audio_cfg.volume = volume;
+        self.set_audio_cfg(audio_cfg).await
+    }
+
+    /// Whether the streams record the microphone, see [`Compression::audio_enabled`]
+    pub async fn get_audio_recording(&self) -> Result<Option<bool>> {
+        Ok(self.get_compression().await?.audio_enabled())
+    }
+
+    /// Turn the microphone on or off in every stream
+    pub async fn set_audio_recording(&self, enabled: bool) -> Result<()> {
+        let mut compression = self.get_compression().await?;
+        if compression.audio_enabled().is_none() {
+            return Err(Error::Other(
+                "The camera does not report whether the streams have audio",
+            ));
+        }
+        compression.set_audio_enabled(enabled);
+        self.set_compression(compression).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_enabled() {
+        let mut compression = Compression {
+            main_stream: Some(StreamCompression {
+                audio: Some(1),
+                ..Default::default()
+            }),
+            sub_stream: Some(StreamCompression {
+                audio: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(compression.audio_enabled(), Some(true));
+
+        compression.set_audio_enabled(false);
+        assert_eq!(compression.audio_enabled(), Some(false));
+        assert_eq!(compression.sub_stream.as_ref().unwrap().audio, Some(0));
+        assert!(compression.third_stream.is_none());
+
+        assert_eq!(Compression::default().audio_enabled(), None);
+    }
+}
@@ -0,0 +1,311 @@
+use super::{BcCamera, Error, Result, StreamKind};
+use crate::bc::{model::*, xml::*};
+
+/// The encoder changes made by [`BcCamera::set_stream_info`], each setting that is `None` is left as it is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncodeChange {
+    /// Width and height, this must be the resolution of one of the [EncodeTable]s of the stream
+    pub resolution: Option<(u32, u32)>,
+    /// Bit rate in kbps, this must be a value from [`EncodeTable::bitrate_table`]
+    pub bit_rate: Option<u32>,
+    /// Frame rate, this must be a value from [`EncodeTable::framerate_table`]
+    pub frame_rate: Option<u32>,
+}
+
+impl BcCamera {
+    /// Get the [Compression] xml which contains the encoder settings of the streams
+    pub async fn get_compression(&self) -> Result<Compression> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection
+            .subscribe(MSG_ID_GET_COMPRESSION, msg_num)
+            .await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_COMPRESSION,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    compression: Some(compression),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(compression)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected Compression xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the encoder settings using the [Compression] xml
+    ///
+    /// The frame and bit rates should be values from the [EncodeTable] of
+    /// [`BcCamera::get_stream_info`]
+    pub async fn set_compression(&self, compression: Compression) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection
+            .subscribe(MSG_ID_SET_COMPRESSION, msg_num)
+            .await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_COMPRESSION,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    compression: Some(compression),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if let BcMeta {
+            response_code: 200, ..
+        } = msg.meta
+        {
+            Ok(())
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "The camera did not accept the Compression xml",
+            })
+        }
+    }
+}
+
+impl Compression {
+    /// The settings of the given stream
+    pub fn stream(&self, stream: StreamKind) -> Option<&StreamCompression> {
+        match stream {
+            StreamKind::Main => self.main_stream.as_ref(),
+            StreamKind::Sub => self.sub_stream.as_ref(),
+            StreamKind::Extern => self.third_stream.as_ref(),
+        }
+    }
+
+    /// The settings of the given stream for modification
+    pub fn stream_mut(&mut self, stream: StreamKind) -> Option<&mut StreamCompression> {
+        match stream {
+            StreamKind::Main => self.main_stream.as_mut(),
+            StreamKind::Sub => self.sub_stream.as_mut(),
+            StreamKind::Extern => self.third_stream.as_mut(),
+        }
+    }
+
+    /// Apply the changes to one stream
+    ///
+    /// The changes are checked against the [EncodeTable]s of the stream. Values
+    /// that are not changed but are invalid at a new resolution are moved to
+    /// the nearest valid one
+    pub fn apply_encode(
+        &mut self,
+        stream: StreamKind,
+        info: &StreamInfoList,
+        change: &EncodeChange,
+    ) -> Result<()> {
+        let settings = self.stream_mut(stream).ok_or_else(|| {
+            Error::OtherString(format!(
+                "The camera has no encoder settings for the {}",
+                stream
+            ))
+        })?;
+        let tables = info
+            .stream_infos
+            .iter()
+            .flat_map(|info| info.encode_tables.iter())
+            .filter(|table| table.name == stream.to_string())
+            .collect::<Vec<_>>();
+
+        let (width, height) = change
+            .resolution
+            .unwrap_or((settings.width, settings.height));
+        let table = tables
+            .iter()
+            .find(|table| table.resolution.width == width && table.resolution.height == height)
+            .ok_or_else(|| {
+                Error::OtherString(format!(
+                    "The {} does not support {}x{}, supported resolutions are {}",
+                    stream,
+                    width,
+                    height,
+                    tables
+                        .iter()
+                        .map(|table| format!(
+                            "{}x{}",
+                            table.resolution.width, table.resolution.height
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+
+        let frame = pick_rate(
+            &table.framerate_table,
+            change.frame_rate,
+            settings.frame,
+            "frame rate",
+        )?;
+        let bit_rate = pick_rate(
+            &table.bitrate_table,
+            change.bit_rate,
+            settings.bit_rate,
+            "bit rate",
+        )?;
+
+        if (settings.width, settings.height) != (width, height) {
+            settings.width = width;
+            settings.height = height;
+            if settings.resolution_name.is_some() {
+                settings.resolution_name = Some(format!("{}*{}", width, height));
+            }
+        }
+        settings.frame = frame;
+        settings.bit_rate = bit_rate;
+        Ok(())
+    }
+}
+
+/// The requested value if it is in the table otherwise the nearest one to the current value
+fn pick_rate(table: &[u32], requested: Option<u32>, current: u32, what: &str) -> Result<u32> {
+    match requested {
+        Some(value) if table.is_empty() || table.contains(&value) => Ok(value),
+        Some(value) => Err(Error::OtherString(format!(
+            "The {} {} is not supported, supported values are {}",
+            what,
+            value,
+            table
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+        None => Ok(table
+            .iter()
+            .copied()
+            .min_by_key(|value| value.abs_diff(current))
+            .unwrap_or(current)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_table(name: &str, width: u32, height: u32, bitrates: &[u32]) -> EncodeTable {
+        EncodeTable {
+            name: name.to_string(),
+            resolution: StreamResolution { width, height },
+            default_framerate: 0,
+            default_bitrate: 0,
+            framerate_table: vec![25, 20, 15, 10],
+            bitrate_table: bitrates.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_apply_encode() {
+        let info = StreamInfoList {
+            stream_infos: vec![StreamInfo {
+                channel_bits: 1,
+                encode_tables: vec![
+                    encode_table("mainStream", 2560, 1440, &[1024, 2048, 4096]),
+                    encode_table("mainStream", 1920, 1080, &[1024, 2048]),
+                    encode_table("subStream", 640, 360, &[256, 512]),
+                ],
+            }],
+        };
+        let mut compression = Compression {
+            main_stream: Some(StreamCompression {
+                resolution_name: Some("2560*1440".to_string()),
+                width: 2560,
+                height: 1440,
+                frame: 25,
+                bit_rate: 4096,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        compression
+            .apply_encode(
+                StreamKind::Main,
+                &info,
+                &EncodeChange {
+                    frame_rate: Some(15),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let main = compression.stream(StreamKind::Main).unwrap();
+        assert_eq!((main.frame, main.bit_rate), (15, 4096));
+
+        // The bit rate is moved into the table of the new resolution
+        compression
+            .apply_encode(
+                StreamKind::Main,
+                &info,
+                &EncodeChange {
+                    resolution: Some((1920, 1080)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let main = compression.stream(StreamKind::Main).unwrap();
+        assert_eq!((main.width, main.height, main.bit_rate), (1920, 1080, 2048));
+        assert_eq!(main.resolution_name.as_deref(), Some("1920*1080"));
+
+        for change in [
+            EncodeChange {
+                resolution: Some((640, 360)),
+                ..Default::default()
+            },
+            EncodeChange {
+                bit_rate: Some(3000),
+                ..Default::default()
+            },
+        ] {
+            assert!(compression
+                .apply_encode(StreamKind::Main, &info, &change)
+                .is_err());
+        }
+        assert!(compression
+            .apply_encode(StreamKind::Sub, &info, &EncodeChange::default())
+            .is_err());
+    }
+}
@@ -1,13 +1,20 @@
 use super::BcSubscription;
-use crate::{bc::model::*, Error, Result};
+use crate::{
+    bc::{capture::Capture, model::*},
+    Error, Result,
+};
 use futures::future::BoxFuture;
 use futures::sink::{Sink, SinkExt};
 use futures::stream::{Stream, StreamExt};
 use log::*;
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::mpsc::{
+    channel,
+    error::{SendError, TrySendError},
+    OwnedPermit, Sender,
+};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
@@ -29,32 +36,52 @@ struct Subscriber {
 pub(crate) type BcConnSink = Box<dyn Sink<Bc, Error = Error> + Send + Sync + Unpin>;
 pub(crate) type BcConnSource = Box<dyn Stream<Item = Result<Bc>> + Send + Sync + Unpin>;
 
+/// Max number of messages held for subscribers that are not keeping up
+///
+/// Once this is reached we stop reading from the camera until they catch up
+const MAX_STALLED: usize = 200;
+
 /// A shareable connection to a camera.  Handles serialization of messages.  To send/receive, call
 /// .[subscribe()] with a message number.  You can use the BcSubscription to send or receive only
 /// messages with that number; each incoming message is routed to its appropriate subscriber.
 ///
 /// There can be only one subscriber per kind of message at a time.
+///
+/// All commands share this one connection even while streaming. This matters over the
+/// relay where some firmwares will refuse a second connection. To keep commands
+/// responsive they are sent before any queued binary data (like talk audio) and their
+/// replies are not held up by stream subscribers that are slow to read.
 pub struct BcConnection {
     sink: Sender<Result<Bc>>,
+    bulk_sink: Sender<Result<Bc>>,
     poll_commander: Sender<PollCommand>,
     rx_thread: RwLock<JoinSet<Result<()>>>,
     cancel: CancellationToken,
+    name: String,
 }
 
 impl BcConnection {
-    pub async fn new(mut sink: BcConnSink, mut source: BcConnSource) -> Result<BcConnection> {
+    pub async fn new(
+        mut sink: BcConnSink,
+        mut source: BcConnSource,
+        name: &str,
+        mut capture: Option<Capture>,
+    ) -> Result<BcConnection> {
         let (sinker, sinker_rx) = channel::<Result<Bc>>(100);
+        let (bulk_sinker, bulk_sinker_rx) = channel::<Result<Bc>>(100);
         let cancel = CancellationToken::new();
 
         let (poll_commander, poll_commanded) = channel(200);
+        let (incoming_tx, incoming_rx) = channel(200);
         let mut poller = Poller {
             subscribers: Default::default(),
             sink: sinker.clone(),
             reciever: ReceiverStream::new(poll_commanded),
+            incoming: ReceiverStream::new(incoming_rx),
+            stalled: Default::default(),
         };
 
         let mut rx_thread = JoinSet::<Result<()>>::new();
-        let thread_poll_commander = poll_commander.clone();
         let thread_cancel = cancel.clone();
         rx_thread.spawn(async move {
             tokio::select! {
@@ -62,9 +89,16 @@ impl BcConnection {
                     Result::Ok(())
                 },
                 v = async {
-                    let sender = thread_poll_commander;
+                    let sender = incoming_tx;
                     while let Some(bc) = source.next().await {
-                        sender.send(PollCommand::Bc(Box::new(bc))).await?;
+                        if let (Some(recorder), Ok(bc)) = (capture.as_mut(), bc.as_ref()) {
+                            if let Err(e) = recorder.record(bc) {
+                                // Keep the connection, only the capture stops
+                                warn!("Stopped capturing the messages: {e:?}");
+                                capture = None;
+                            }
+                        }
+                        sender.send(bc).await?;
                     }
                     Result::Ok(())
                 } => v
@@ -77,7 +111,15 @@ impl BcConnection {
                 _ = thread_cancel.cancelled() => Result::Ok(()),
                 v = async {
                     let mut stream = ReceiverStream::new(sinker_rx);
-                    while let Some(packet) = stream.next().await {
+                    let mut bulk_stream = ReceiverStream::new(bulk_sinker_rx);
+                    loop {
+                        // Commands always go before bulk data
+                        let packet = tokio::select! {
+                            biased;
+                            Some(packet) = stream.next() => packet,
+                            Some(packet) = bulk_stream.next() => packet,
+                            else => break,
+                        };
                         sink.send(packet?).await?;
                     }
                     Ok(())
@@ -102,14 +144,25 @@ impl BcConnection {
 
         Ok(BcConnection {
             sink: sinker,
+            bulk_sink: bulk_sinker,
             poll_commander,
             rx_thread: RwLock::new(rx_thread),
             cancel,
+            name: name.to_string(),
         })
     }
 
+    /// Name of the camera, used to label the message spans
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
     pub(super) async fn send(&self, bc: Bc) -> crate::Result<()> {
-        self.sink.send(Ok(bc)).await?;
+        if is_bulk(&bc) {
+            self.bulk_sink.send(Ok(bc)).await?;
+        } else {
+            self.sink.send(Ok(bc)).await?;
+        }
         Ok(())
     }
 
@@ -118,7 +171,7 @@ impl BcConnection {
         self.poll_commander
             .send(PollCommand::AddSubscriber(msg_id, Some(msg_num), tx))
             .await?;
-        Ok(BcSubscription::new(rx, Some(msg_num as u32), self))
+        Ok(BcSubscription::new(rx, msg_id, Some(msg_num as u32), self))
     }
 
     /// Some messages are initiated by the camera. This creates a handler for them
@@ -155,7 +208,7 @@ impl BcConnection {
         self.poll_commander
             .send(PollCommand::AddSubscriber(msg_id, None, tx))
             .await?;
-        Ok(BcSubscription::new(rx, None, self))
+        Ok(BcSubscription::new(rx, msg_id, None, self))
     }
 
     pub(crate) async fn join(&self) -> Result<()> {
@@ -204,8 +257,19 @@ impl Drop for BcConnection {
     }
 }
 
+/// Messages with binary payloads such as talk audio can be large and
+/// numerous so they are queued separately from the commands
+fn is_bulk(bc: &Bc) -> bool {
+    matches!(
+        bc.body,
+        BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::Binary(_)),
+            ..
+        })
+    )
+}
+
 enum PollCommand {
-    Bc(Box<Result<Bc>>),
     AddHandler(u32, Arc<MsgHandler>),
     RemoveHandler(u32),
     AddSubscriber(u32, Option<u16>, Sender<Result<Bc>>),
@@ -215,7 +279,6 @@ enum PollCommand {
 impl std::fmt::Debug for PollCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            PollCommand::Bc(_) => f.write_str("PollCommand::Bc"),
             PollCommand::AddHandler(_, _) => f.write_str("PollCommand::AddHandler"),
             PollCommand::RemoveHandler(_) => f.write_str("PollCommand::RemoveHandler"),
             PollCommand::AddSubscriber(_, _, _) => f.write_str("PollCommand::AddSubscriber"),
@@ -224,17 +287,44 @@ impl std::fmt::Debug for PollCommand {
     }
 }
 
+type StalledMsg = (Sender<Result<Bc>>, Result<Bc>);
+
 struct Poller {
     subscribers: Subscriber,
     sink: Sender<Result<Bc>>,
     reciever: ReceiverStream<PollCommand>,
+    incoming: ReceiverStream<Result<Bc>>,
+    /// Messages for subscribers whose channels were full when they arrived
+    stalled: VecDeque<StalledMsg>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum PollEvent {
+    Command(PollCommand),
+    Bc(Result<Bc>),
+    Unstalled(std::result::Result<OwnedPermit<Result<Bc>>, SendError<()>>),
 }
 
 impl Poller {
     async fn run(&mut self) -> Result<()> {
         let cancel = CancellationToken::new();
         let _dropguard = cancel.clone().drop_guard();
-        while let Some(command) = self.reciever.next().await {
+        loop {
+            // Commands are handled first so that a subscription is always in place
+            // before its reply can arrive
+            let event = tokio::select! {
+                biased;
+                command = self.reciever.next() => match command {
+                    Some(command) => PollEvent::Command(command),
+                    None => break,
+                },
+                permit = Self::front_permit(&self.stalled), if !self.stalled.is_empty() => {
+                    PollEvent::Unstalled(permit)
+                }
+                Some(bc) = self.incoming.next(), if self.stalled.len() < MAX_STALLED => {
+                    PollEvent::Bc(bc)
+                }
+            };
             // Clean Up subscribers
             self.subscribers
                 .num
@@ -243,123 +333,122 @@ impl Poller {
             self.subscribers
                 .num
                 .retain(|_, channels| !channels.is_empty());
-            // Handle the command
-            match command {
-                PollCommand::Bc(boxed_response) => {
-                    match *boxed_response {
-                        Ok(response) => {
-                            let msg_id = response.meta.msg_id;
-                            let msg_num = response.meta.msg_num;
-                            log::trace!(
-                                "Looking for ID: {} with num: {}, in {:?} and {:?}",
-                                msg_id,
-                                msg_num,
-                                self.subscribers.id.keys().to_owned(),
-                                self.subscribers
-                                    .num
-                                    .iter()
-                                    .map(|(k, v)| (k, v.keys()))
-                                    .collect::<Vec<_>>(),
-                            );
-                            match (
-                                self.subscribers.id.get(&msg_id),
-                                self.subscribers.num.get_mut(&msg_id), // Both filter first on ID
-                            ) {
-                                (Some(occ), _) => {
-                                    log::trace!("Calling ID callback");
-                                    let occ = occ.clone();
-                                    let sink = self.sink.clone();
-                                    // Move this on another thread coz I have NO idea
-                                    // how long the callback will run for
-                                    // and we must NOT hang
-                                    let cancel = cancel.clone();
-                                    tokio::task::spawn(async move {
-                                        tokio::select! {
-                                            _ = cancel.cancelled() => Result::Ok(()),
-                                            v = occ(&response) => {
-                                                if let Some(reply) = v {
-                                                    assert!(reply.meta.msg_num == response.meta.msg_num);
-                                                    sink.send(Ok(reply)).await?;
-                                                }
-                                                Result::Ok(())
-                                            }
-                                        }
-                                    });
-                                    log::trace!("Called ID callback");
-                                }
-                                (None, Some(occ)) => {
-                                    let sender = if let Some(sender) =
-                                        occ.get(&Some(msg_num)).filter(|a| !a.is_closed()).cloned()
-                                    {
-                                        // Connection with id exists and is not closed
-                                        Some(sender)
-                                    } else if let Some(sender) = occ.get(&None).cloned() {
-                                        // Upgrade a None to a known MsgID
-                                        occ.remove(&None);
-                                        occ.insert(Some(msg_num), sender.clone());
-                                        Some(sender)
-                                    } else if occ
-                                        .get(&Some(msg_num))
-                                        .map(|a| a.is_closed())
-                                        .unwrap_or(false)
-                                    {
-                                        // Connection is closed and there is no None to replace it
-                                        // Remove it for cleanup and report no sender
-                                        occ.remove(&Some(msg_num));
-                                        None
-                                    } else {
-                                        None
-                                    };
-                                    if let Some(sender) = sender {
-                                        if sender.capacity() == 0 {
-                                            warn!("Reaching limit of channel");
-                                            warn!(
-                                                "Remaining: {} of {} message space for {} (ID: {})",
-                                                sender.capacity(),
-                                                sender.max_capacity(),
-                                                &msg_num,
-                                                &msg_id
-                                            );
-                                        } else {
-                                            trace!(
-                                                "Remaining: {} of {} message space for {} (ID: {})",
-                                                sender.capacity(),
-                                                sender.max_capacity(),
-                                                &msg_num,
-                                                &msg_id
-                                            );
+            // Handle the event
+            match event {
+                PollEvent::Bc(Ok(response)) => {
+                    let msg_id = response.meta.msg_id;
+                    let msg_num = response.meta.msg_num;
+                    log::trace!(
+                        "Looking for ID: {} with num: {}, in {:?} and {:?}",
+                        msg_id,
+                        msg_num,
+                        self.subscribers.id.keys().to_owned(),
+                        self.subscribers
+                            .num
+                            .iter()
+                            .map(|(k, v)| (k, v.keys()))
+                            .collect::<Vec<_>>(),
+                    );
+                    match (
+                        self.subscribers.id.get(&msg_id),
+                        self.subscribers.num.get_mut(&msg_id), // Both filter first on ID
+                    ) {
+                        (Some(occ), _) => {
+                            log::trace!("Calling ID callback");
+                            let occ = occ.clone();
+                            let sink = self.sink.clone();
+                            // Move this on another thread coz I have NO idea
+                            // how long the callback will run for
+                            // and we must NOT hang
+                            let cancel = cancel.clone();
+                            tokio::task::spawn(async move {
+                                tokio::select! {
+                                    _ = cancel.cancelled() => Result::Ok(()),
+                                    v = occ(&response) => {
+                                        if let Some(reply) = v {
+                                            assert!(reply.meta.msg_num == response.meta.msg_num);
+                                            sink.send(Ok(reply)).await?;
                                         }
-                                        let _ = sender.send(Ok(response)).await;
-                                    } else {
-                                        debug!(
-                                            "Ignoring uninteresting message id {} (number: {})",
-                                            msg_id, msg_num
-                                        );
-                                        trace!("Contents: {:?}", response);
+                                        Result::Ok(())
                                     }
                                 }
-                                (None, None) => {
-                                    debug!(
-                                        "Ignoring uninteresting message id {} (number: {})",
-                                        msg_id, msg_num
-                                    );
-                                    trace!("Contents: {:?}", response);
-                                }
+                            });
+                            log::trace!("Called ID callback");
+                        }
+                        (None, Some(occ)) => {
+                            let sender = if let Some(sender) =
+                                occ.get(&Some(msg_num)).filter(|a| !a.is_closed()).cloned()
+                            {
+                                // Connection with id exists and is not closed
+                                Some(sender)
+                            } else if let Some(sender) = occ.get(&None).cloned() {
+                                // Upgrade a None to a known MsgID
+                                occ.remove(&None);
+                                occ.insert(Some(msg_num), sender.clone());
+                                Some(sender)
+                            } else if occ
+                                .get(&Some(msg_num))
+                                .map(|a| a.is_closed())
+                                .unwrap_or(false)
+                            {
+                                // Connection is closed and there is no None to replace it
+                                // Remove it for cleanup and report no sender
+                                occ.remove(&Some(msg_num));
+                                None
+                            } else {
+                                None
+                            };
+                            if let Some(sender) = sender {
+                                trace!(
+                                    "Remaining: {} of {} message space for {} (ID: {})",
+                                    sender.capacity(),
+                                    sender.max_capacity(),
+                                    &msg_num,
+                                    &msg_id
+                                );
+                                self.deliver(sender, response);
+                            } else {
+                                debug!(
+                                    "Ignoring uninteresting message id {} (number: {})",
+                                    msg_id, msg_num
+                                );
+                                trace!("Contents: {:?}", response);
                             }
                         }
-                        Err(e) => {
-                            for sub in self.subscribers.num.values() {
-                                for sender in sub.values() {
-                                    let _ = sender.send(Err(e.clone())).await;
-                                }
+                        (None, None) => {
+                            debug!(
+                                "Ignoring uninteresting message id {} (number: {})",
+                                msg_id, msg_num
+                            );
+                            trace!("Contents: {:?}", response);
+                        }
+                    }
+                }
+                PollEvent::Bc(Err(e)) => {
+                    self.stalled.clear();
+                    for sub in self.subscribers.num.values() {
+                        for sender in sub.values() {
+                            let _ = sender.send(Err(e.clone())).await;
+                        }
+                    }
+                    self.subscribers.num.clear();
+                    self.subscribers.id.clear();
+                    return Err(e);
+                }
+                PollEvent::Unstalled(permit) => {
+                    if let Some((_, msg)) = self.stalled.pop_front() {
+                        match permit {
+                            Ok(permit) => {
+                                permit.send(msg);
+                            }
+                            Err(_) => {
+                                // Subscriber has gone, drop everything held for it
+                                self.stalled.retain(|(sender, _)| !sender.is_closed());
                             }
-                            self.subscribers.num.clear();
-                            self.subscribers.id.clear();
-                            return Err(e);
                         }
                     }
                 }
-                PollCommand::AddHandler(msg_id, handler) => {
+                PollEvent::Command(PollCommand::AddHandler(msg_id, handler)) => {
                     match self.subscribers.id.entry(msg_id) {
                         Entry::Vacant(vac_entry) => {
                             vac_entry.insert(handler);
@@ -369,10 +458,10 @@ impl Poller {
                         }
                     };
                 }
-                PollCommand::RemoveHandler(msg_id) => {
+                PollEvent::Command(PollCommand::RemoveHandler(msg_id)) => {
                     self.subscribers.id.remove(&msg_id);
                 }
-                PollCommand::AddSubscriber(msg_id, msg_num, tx) => {
+                PollEvent::Command(PollCommand::AddSubscriber(msg_id, msg_num, tx)) => {
                     match self
                         .subscribers
                         .num
@@ -395,11 +484,193 @@ impl Poller {
                         }
                     };
                 }
-                PollCommand::Disconnect => {
+                PollEvent::Command(PollCommand::Disconnect) => {
                     return Err(Error::DroppedConnection);
                 }
             }
         }
         Ok(())
     }
+
+    /// Waits until the subscriber of the oldest stalled message has room for it
+    async fn front_permit(
+        stalled: &VecDeque<StalledMsg>,
+    ) -> std::result::Result<OwnedPermit<Result<Bc>>, SendError<()>> {
+        match stalled.front() {
+            Some((sender, _)) => sender.clone().reserve_owned().await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Pass a message to a subscriber without waiting on it
+    ///
+    /// If the subscriber is full the message is stalled instead so that
+    /// messages for everyone else (like command replies) keep flowing
+    fn deliver(&mut self, sender: Sender<Result<Bc>>, response: Bc) {
+        if self
+            .stalled
+            .iter()
+            .any(|(stalled, _)| stalled.same_channel(&sender))
+        {
+            // Keep the order for this subscriber
+            self.stalled.push_back((sender, Ok(response)));
+            return;
+        }
+        match sender.try_send(Ok(response)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(msg)) => {
+                warn!("Reaching limit of channel");
+                warn!(
+                    "Stalling message for a slow subscriber ({} of {} stalled)",
+                    self.stalled.len() + 1,
+                    MAX_STALLED
+                );
+                self.stalled.push_back((sender, msg));
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{TcpSource, UdpSource};
+    use super::*;
+    use crate::bc::codex::BcCodex;
+    use crate::Credentials;
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, UdpSocket};
+    use tokio::time::{timeout, Duration};
+    use tokio_util::codec::Framed;
+
+    const STREAM_NUM: u16 = 1;
+    const PTZ_NUM: u16 = 2;
+    /// More frames than a subscriber channel holds but fewer than we will stall
+    const FRAMES: usize = 250;
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn header(msg_id: u32, msg_num: u16, response_code: u16) -> Bc {
+        Bc::new_from_meta(BcMeta {
+            msg_id,
+            channel_id: 0,
+            msg_num,
+            response_code,
+            stream_type: 0,
+            class: 0x6414,
+        })
+    }
+
+    /// Replies to ptz and floods the stream as soon as it is requested
+    async fn fake_camera<T>(mut camera: T) -> Result<()>
+    where
+        T: Stream<Item = Result<Bc>> + Sink<Bc, Error = Error> + Unpin,
+    {
+        while let Some(bc) = camera.next().await {
+            let bc = bc?;
+            match bc.meta.msg_id {
+                MSG_ID_VIDEO => {
+                    for _ in 0..FRAMES {
+                        camera
+                            .feed(header(MSG_ID_VIDEO, bc.meta.msg_num, 200))
+                            .await?;
+                    }
+                    camera.flush().await?;
+                }
+                MSG_ID_PTZ_CONTROL => {
+                    camera
+                        .send(header(MSG_ID_PTZ_CONTROL, bc.meta.msg_num, 200))
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a stream that is not read from and check that ptz still gets
+    /// its reply over the same connection
+    async fn stream_and_ptz(sink: BcConnSink, source: BcConnSource) -> Result<()> {
+        let conn = BcConnection::new(sink, source, "test", None).await?;
+
+        let mut stream = conn.subscribe(MSG_ID_VIDEO, STREAM_NUM).await?;
+        stream.send(header(MSG_ID_VIDEO, STREAM_NUM, 0)).await?;
+
+        let mut ptz = conn.subscribe(MSG_ID_PTZ_CONTROL, PTZ_NUM).await?;
+        ptz.send(header(MSG_ID_PTZ_CONTROL, PTZ_NUM, 0)).await?;
+        let reply = timeout(WAIT, ptz.recv())
+            .await
+            .expect("Ptz reply was held up by the stream")?;
+        assert_eq!(reply.meta.response_code, 200);
+
+        for _ in 0..FRAMES {
+            let frame = timeout(WAIT, stream.recv())
+                .await
+                .expect("Stream frame was lost")?;
+            assert_eq!(frame.meta.msg_id, MSG_ID_VIDEO);
+        }
+
+        conn.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn test_stream_and_ptz_tcp() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let camera = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await?;
+            fake_camera(Framed::new(
+                socket,
+                BcCodex::new(Credentials::new("admin", None::<String>)),
+            ))
+            .await
+        });
+
+        let (sink, source) = TcpSource::new(addr, "admin", None::<String>, false)
+            .await?
+            .split();
+        stream_and_ptz(Box::new(sink), Box::new(source)).await?;
+
+        camera.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_and_ptz_udp() -> Result<()> {
+        const CLIENT_ID: i32 = 1;
+        const CAMERA_ID: i32 = 2;
+        let client_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let camera_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let client_addr: SocketAddr = client_socket.local_addr()?;
+        let camera_addr: SocketAddr = camera_socket.local_addr()?;
+
+        // The udp payload is symmetric so the camera is just a source
+        // with the ids swapped
+        let camera = UdpSource::new_from_socket(
+            camera_socket,
+            client_addr,
+            CAMERA_ID,
+            CLIENT_ID,
+            "admin",
+            None::<String>,
+            false,
+        )
+        .await?;
+        let camera = tokio::spawn(fake_camera(camera));
+
+        let (sink, source) = UdpSource::new_from_socket(
+            client_socket,
+            camera_addr,
+            CLIENT_ID,
+            CAMERA_ID,
+            "admin",
+            None::<String>,
+            false,
+        )
+        .await?
+        .split();
+        stream_and_ptz(Box::new(sink), Box::new(source)).await?;
+
+        camera.abort();
+        Ok(())
+    }
 }
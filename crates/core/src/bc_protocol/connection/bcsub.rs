@@ -1,5 +1,6 @@
 use super::BcConnection;
 use crate::bcmedia::codex::BcMediaCodex;
+use crate::trace::{bc_event, bc_span, BcSpan};
 use crate::{bc::model::*, bcmedia::model::*, Error, Result};
 use futures::stream::{Stream, TryStreamExt};
 use std::io::{Error as IoError, ErrorKind, Result as IoResult};
@@ -14,6 +15,7 @@ pub struct BcSubscription<'a> {
     rx: ReceiverStream<Result<Bc>>,
     msg_num: Option<u32>,
     conn: &'a BcConnection,
+    span: BcSpan,
 }
 
 pub struct BcStream<'a> {
@@ -38,6 +40,7 @@ impl<'a> Stream for BcStream<'a> {
 impl<'a> BcSubscription<'a> {
     pub fn new(
         rx: Receiver<Result<Bc>>,
+        msg_id: u32,
         msg_num: Option<u32>,
         conn: &'a BcConnection,
     ) -> BcSubscription<'a> {
@@ -45,6 +48,7 @@ impl<'a> BcSubscription<'a> {
             rx: ReceiverStream::new(rx),
             msg_num,
             conn,
+            span: bc_span(conn.name(), msg_id, msg_num.map(|n| n as u16)),
         }
     }
 
@@ -54,6 +58,7 @@ impl<'a> BcSubscription<'a> {
         } else {
             log::debug!("Sending message before msg_num has been aquired");
         }
+        bc_event(&self.span, "send", &bc.meta);
         self.conn.send(bc).await?;
         Ok(())
     }
@@ -61,6 +66,7 @@ impl<'a> BcSubscription<'a> {
     pub async fn recv(&mut self) -> Result<Bc> {
         let bc = self.rx.next().await.ok_or(Error::DroppedSubscriber)?;
         if let Ok(bc) = &bc {
+            bc_event(&self.span, "recv", &bc.meta);
             if let Some(msg_num) = self.msg_num {
                 assert!(bc.meta.msg_num as u32 == msg_num);
             } else {
@@ -1279,6 +1279,75 @@ impl Discovery {
     }
 }
 
+/// A camera that replied to a local broadcast scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalCamera {
+    /// The ip address the reply came from
+    pub addr: std::net::IpAddr,
+    /// The UID if one could be found in the reply
+    pub uid: Option<String>,
+}
+
+/// Broadcast a C2D_S on every local network and collect the cameras that reply
+///
+/// The reply is binary rather than xml and its layout is not known, so the UID
+/// is taken from the first run of 16 uppercase letters and digits in it.
+pub async fn scan_local(wait: Duration) -> Result<Vec<LocalCamera>> {
+    let socket = connect().await?;
+    let port = socket.local_addr()?.port();
+    let msg = BcUdp::Discovery(UdpDiscovery {
+        tid: generate_tid(),
+        payload: UdpXml {
+            c2d_s: Some(C2dS {
+                to: PortList { port: port as u32 },
+            }),
+            ..Default::default()
+        },
+    })
+    .serialize(vec![])?;
+
+    let mut found: BTreeMap<std::net::IpAddr, Option<String>> = Default::default();
+    let mut buf = vec![0u8; 4096];
+    let mut inter = interval(*RESEND_WAIT);
+    inter.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let dests = get_broadcasts(&[2015])?;
+    let scan = async {
+        loop {
+            tokio::select! {
+                _ = inter.tick() => {
+                    for dest in dests.iter() {
+                        let _ = socket.send_to(&msg, dest).await;
+                    }
+                }
+                v = socket.recv_from(&mut buf) => {
+                    let (len, addr) = v?;
+                    trace!("Scan reply from {:?}: {:?}", addr, &buf[..len]);
+                    let uid = find_uid(&buf[..len]);
+                    let entry = found.entry(addr.ip()).or_default();
+                    if entry.is_none() {
+                        *entry = uid;
+                    }
+                }
+            }
+        }
+    };
+    // The scan only ends on an error or when the wait is over
+    let scanned: std::result::Result<Result<()>, _> = timeout(wait, scan).await;
+    if let Ok(Err(e)) = scanned {
+        return Err(e);
+    }
+    Ok(found
+        .into_iter()
+        .map(|(addr, uid)| LocalCamera { addr, uid })
+        .collect())
+}
+
+fn find_uid(data: &[u8]) -> Option<String> {
+    data.split(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit()))
+        .find(|run| run.len() == 16)
+        .map(|run| String::from_utf8_lossy(run).into_owned())
+}
+
 fn get_local_ip() -> Result<std::net::IpAddr> {
     get_if_addrs::get_if_addrs()?
         .iter()
@@ -1610,3 +1679,17 @@ async fn connect() -> Result<UdpSocket> {
     ```
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_uid() {
+        assert_eq!(
+            find_uid(b"\x01\x00IPC\x0095270000ABCDEFGH\x00192.168.1.10\x00"),
+            Some("95270000ABCDEFGH".to_string())
+        );
+        assert_eq!(find_uid(b"\x01\x00IPC\x00192.168.1.10\x00"), None);
+    }
+}
@@ -18,6 +18,7 @@ pub(crate) use self::{
     bcconn::BcConnection, bcconn::*, bcsub::BcSubscription, discovery::Discovery,
     tcpsource::TcpSource, udpsource::UdpSource,
 };
+pub use self::discovery::{scan_local, LocalCamera};
 
 pub(crate) struct DiscoveryResult {
     socket: Arc<UdpSocket>,
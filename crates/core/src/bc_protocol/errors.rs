@@ -113,6 +113,19 @@ pub enum Error {
     #[error(display = "Unknown encryption: {:x?}", _0)]
     UnknownEncryption(usize),
 
+    /// Raised when the camera wants a stronger encryption than we allow
+    #[error(
+        display = "Camera requires {} encryption but at most {} is allowed",
+        required,
+        allowed
+    )]
+    EncryptionNotAllowed {
+        /// The maximum that was requested
+        allowed: super::MaxEncryption,
+        /// The encryption the camera chose
+        required: super::MaxEncryption,
+    },
+
     /// Raised when the camera cannot be found
     #[error(display = "Camera Not Findable")]
     ConnectionUnavaliable,
@@ -163,6 +176,13 @@ pub enum Error {
     #[error(display = "Nom Parsing error: {}", _0)]
     NomError(String),
 
+    /// Raised when the media stream keeps failing to parse
+    ///
+    /// Some firmwares encrypt their media in a way that cannot be read
+    /// with `Aes`, logging in with `HeaderAes` can fix it
+    #[error(display = "Media stream could not be parsed after {} packets", _0)]
+    MediaUndecodable(usize),
+
     /// Raised when a camera/user lacks an ability
     #[error(
         display = "Missing ability: {} with {} permission has only {}",
@@ -1,6 +1,6 @@
 use tokio::sync::mpsc::{channel, Receiver};
 
-use super::{BcCamera, Error, Result};
+use super::{BcCamera, Confirmation, Error, Result};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
@@ -41,7 +41,7 @@ impl BcCamera {
     }
 
     /// Set the floodlight status using the [FloodlightManual] xml
-    pub async fn set_floodlight_manual(&self, state: bool, duration: u16) -> Result<()> {
+    pub async fn set_floodlight_manual(&self, state: bool, duration: u16) -> Result<Confirmation> {
         let connection = self.get_connection();
 
         let msg_num = self.new_message_num();
@@ -80,25 +80,11 @@ impl BcCamera {
 
         sub_set.send(get).await?;
 
-        if let Ok(reply) =
-            tokio::time::timeout(tokio::time::Duration::from_micros(500), sub_set.recv()).await
-        {
-            let msg = reply?;
-            if let BcMeta {
-                response_code: 200, ..
-            } = msg.meta
-            {
-                Ok(())
-            } else {
-                Err(Error::UnintelligibleReply {
-                    reply: std::sync::Arc::new(Box::new(msg)),
-                    why: "The camera did not accept the Floodlight manual state",
-                })
-            }
-        } else {
-            // Some cameras seem to just not send a reply on success, so after 500ms we return Ok
-            Ok(())
-        }
+        self.wait_for_confirmation(
+            &mut sub_set,
+            "The camera did not accept the Floodlight manual state",
+        )
+        .await
     }
 
     /// Get the Flood Light tasks XML
@@ -0,0 +1,265 @@
+use super::{BcCamera, Confirmation, Error, Result};
+use crate::bc::{model::*, xml::*};
+use std::{fmt, str::FromStr};
+
+/// The picture and image signal processor settings, they are read and written together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSettings {
+    /// Brightness, contrast and colour
+    pub video_input: VideoInput,
+    /// Day night mode, exposure and orientation
+    pub advance: InputAdvanceCfg,
+}
+
+/// The day night mode of the [DayNight] xml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayNightMode {
+    /// Switch with the light level
+    Auto,
+    /// Always in colour
+    Color,
+    /// Always in black and white with the IR lights
+    BlackAndWhite,
+}
+
+impl DayNightMode {
+    fn xml_name(&self) -> &'static str {
+        match self {
+            DayNightMode::Auto => "auto",
+            DayNightMode::Color => "color",
+            DayNightMode::BlackAndWhite => "blackAndWhite",
+        }
+    }
+}
+
+impl fmt::Display for DayNightMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.xml_name())
+    }
+}
+
+impl FromStr for DayNightMode {
+    type Err = Error;
+
+    /// Parse the xml name or one of `day` and `night`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(DayNightMode::Auto),
+            "color" | "colour" | "day" => Ok(DayNightMode::Color),
+            "blackandwhite" | "night" => Ok(DayNightMode::BlackAndWhite),
+            _ => Err(Error::OtherString(format!(
+                "Invalid day night mode {}, expected auto, day or night",
+                s
+            ))),
+        }
+    }
+}
+
+/// The changes made by [BcCamera::isp_set], each setting that is `None` is left as it is
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IspChange {
+    /// Brightness from 0 to 255
+    pub bright: Option<u8>,
+    /// Contrast from 0 to 255
+    pub contrast: Option<u8>,
+    /// Saturation from 0 to 255
+    pub saturation: Option<u8>,
+    /// Day night mode
+    pub day_night: Option<DayNightMode>,
+    /// Flip the picture upside down
+    pub flip: Option<bool>,
+    /// Mirror the picture left to right
+    pub mirror: Option<bool>,
+}
+
+impl ImageSettings {
+    /// The current day night mode if the camera reports a known one
+    pub fn day_night(&self) -> Option<DayNightMode> {
+        self.advance
+            .day_night
+            .as_ref()
+            .and_then(|day_night| day_night.mode.parse().ok())
+    }
+
+    /// Apply the changes to the xml
+    pub fn apply(&mut self, change: &IspChange) -> Result<()> {
+        if let Some(bright) = change.bright {
+            self.video_input.bright = Some(bright);
+        }
+        if let Some(contrast) = change.contrast {
+            self.video_input.contrast = Some(contrast);
+        }
+        if let Some(saturation) = change.saturation {
+            self.video_input.saturation = Some(saturation);
+        }
+        if let Some(mode) = change.day_night {
+            let day_night = self.advance.day_night.as_mut().ok_or_else(|| {
+                Error::Other("The camera does not support changing the day night mode")
+            })?;
+            day_night.mode = mode.xml_name().to_string();
+        }
+        if let Some(flip) = change.flip {
+            self.advance.flip = Some(flip as u8);
+        }
+        if let Some(mirror) = change.mirror {
+            self.advance.mirror = Some(mirror as u8);
+        }
+        Ok(())
+    }
+}
+
+impl BcCamera {
+    /// Get the [VideoInput] and [InputAdvanceCfg] xml of the picture
+    pub async fn get_isp(&self) -> Result<ImageSettings> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection
+            .subscribe(MSG_ID_GET_VIDEO_INPUT, msg_num)
+            .await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_VIDEO_INPUT,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    video_input: Some(video_input),
+                    input_advance_cfg: Some(advance),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(ImageSettings {
+                video_input,
+                advance,
+            })
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected VideoInput and InputAdvanceCfg xml but they were not recieved",
+            })
+        }
+    }
+
+    /// Set the picture using the [VideoInput] and [InputAdvanceCfg] xml
+    pub async fn set_isp(&self, settings: ImageSettings) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection
+            .subscribe(MSG_ID_SET_VIDEO_INPUT, msg_num)
+            .await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_VIDEO_INPUT,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    video_input: Some(settings.video_input),
+                    input_advance_cfg: Some(settings.advance),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the VideoInput xml")
+            .await
+    }
+
+    /// This is a convience function to change some of the picture settings
+    pub async fn isp_set(&self, change: &IspChange) -> Result<Confirmation> {
+        let mut settings = self.get_isp().await?;
+        settings.apply(change)?;
+        self.set_isp(settings).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_night_parse() {
+        assert_eq!("auto".parse::<DayNightMode>().unwrap(), DayNightMode::Auto);
+        assert_eq!("day".parse::<DayNightMode>().unwrap(), DayNightMode::Color);
+        assert_eq!(
+            "Night".parse::<DayNightMode>().unwrap(),
+            DayNightMode::BlackAndWhite
+        );
+        assert_eq!(
+            "blackAndWhite".parse::<DayNightMode>().unwrap(),
+            DayNightMode::BlackAndWhite
+        );
+        assert!("dusk".parse::<DayNightMode>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut settings = ImageSettings {
+            video_input: VideoInput {
+                bright: Some(128),
+                contrast: Some(128),
+                ..Default::default()
+            },
+            advance: InputAdvanceCfg {
+                day_night: Some(DayNight {
+                    mode: "auto".to_string(),
+                    ..Default::default()
+                }),
+                flip: Some(0),
+                ..Default::default()
+            },
+        };
+        settings
+            .apply(&IspChange {
+                bright: Some(200),
+                day_night: Some(DayNightMode::BlackAndWhite),
+                flip: Some(true),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(settings.video_input.bright, Some(200));
+        assert_eq!(settings.video_input.contrast, Some(128));
+        assert_eq!(settings.day_night(), Some(DayNightMode::BlackAndWhite));
+        assert_eq!(settings.advance.flip, Some(1));
+        assert_eq!(settings.advance.mirror, None);
+
+        settings.advance.day_night = None;
+        assert!(settings
+            .apply(&IspChange {
+                day_night: Some(DayNightMode::Auto),
+                ..Default::default()
+            })
+            .is_err());
+    }
+}
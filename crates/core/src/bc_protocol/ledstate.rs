@@ -1,4 +1,4 @@
-use super::{BcCamera, Error, Result};
+use super::{BcCamera, Confirmation, Error, Result};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
@@ -51,7 +51,7 @@ impl BcCamera {
     }
 
     /// Set the led lights using the [LedState] xml
-    pub async fn set_ledstate(&self, mut led_state: LedState) -> Result<()> {
+    pub async fn set_ledstate(&self, mut led_state: LedState) -> Result<Confirmation> {
         self.has_ability_rw("ledState").await?;
         let connection = self.get_connection();
 
@@ -83,55 +83,35 @@ impl BcCamera {
         };
 
         sub_set.send(get).await?;
-        if let Ok(reply) =
-            tokio::time::timeout(tokio::time::Duration::from_micros(500), sub_set.recv()).await
-        {
-            let msg = reply?;
-
-            if let BcMeta {
-                response_code: 200, ..
-            } = msg.meta
-            {
-                Ok(())
-            } else {
-                Err(Error::UnintelligibleReply {
-                    reply: std::sync::Arc::new(Box::new(msg)),
-                    why: "The camera did not except the LEDState xml",
-                })
-            }
-        } else {
-            // Some cameras seem to just not send a reply on success, so after 500ms we return Ok
-            Ok(())
-        }
+        self.wait_for_confirmation(&mut sub_set, "The camera did not except the LEDState xml")
+            .await
     }
 
     /// This is a convience function to control the IR LED lights
     ///
     /// This is for the RED IR lights that can come on automaitcally
     /// during low light.
-    pub async fn irled_light_set(&self, state: LightState) -> Result<()> {
+    pub async fn irled_light_set(&self, state: LightState) -> Result<Confirmation> {
         let mut led_state = self.get_ledstate().await?;
         led_state.state = match state {
             LightState::On => "open".to_string(),
             LightState::Off => "close".to_string(),
             LightState::Auto => "auto".to_string(),
         };
-        self.set_ledstate(led_state).await?;
-        Ok(())
+        self.set_ledstate(led_state).await
     }
 
     /// This is a convience function to control the LED light
     /// True is on and false is off
     ///
     /// This is for the little blue on light of some camera
-    pub async fn led_light_set(&self, state: bool) -> Result<()> {
+    pub async fn led_light_set(&self, state: bool) -> Result<Confirmation> {
         let mut led_state = self.get_ledstate().await?;
         led_state.light_state = match state {
             true => "open".to_string(),
             false => "close".to_string(),
         };
-        self.set_ledstate(led_state).await?;
-        Ok(())
+        self.set_ledstate(led_state).await
     }
 }
 
@@ -1,25 +1,83 @@
 use super::{md5_string, BcCamera, Error, Result, Truncate};
 use crate::bc::{model::*, xml::*};
-use std::sync::atomic::Ordering;
+use std::{fmt, str::FromStr, sync::atomic::Ordering};
 
 /// The requested encryption level to request
 /// to the camera
 ///
 /// The camera may use a lower one depending on support
 ///
-/// Note the reolink camera only encrypt the control messages
-/// the camera feed is always accessible
-#[derive(Debug, Clone, Copy)]
+/// Levels are ordered from weakest to strongest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MaxEncryption {
     /// No encryption
     None,
     /// BCEncrypt is a simple XOR algortirhm with a fixed key
     /// used in many older models
     BcEncrypt,
+    /// AES on the control messages only, the camera feed is sent as is
+    HeaderAes,
     /// AES is used in newer model
+    ///
+    /// The control messages and, on the cameras that support it, the camera
+    /// feed are encrypted
     Aes,
 }
 
+impl MaxEncryption {
+    /// The low byte of the login response code for this level
+    fn byte(&self) -> u16 {
+        match self {
+            MaxEncryption::None => 0x00,
+            MaxEncryption::BcEncrypt => 0x01,
+            MaxEncryption::HeaderAes => 0x02,
+            MaxEncryption::Aes => 0x12,
+        }
+    }
+
+    fn from_byte(byte: u16) -> Option<Self> {
+        match byte {
+            0x00 => Some(MaxEncryption::None),
+            0x01 => Some(MaxEncryption::BcEncrypt),
+            0x02 => Some(MaxEncryption::HeaderAes),
+            0x12 => Some(MaxEncryption::Aes),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MaxEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MaxEncryption::None => "None",
+            MaxEncryption::BcEncrypt => "BcEncrypt",
+            MaxEncryption::HeaderAes => "HeaderAes",
+            MaxEncryption::Aes => "Aes",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MaxEncryption {
+    type Err = Error;
+
+    /// Parse the name of a level ignoring case
+    ///
+    /// `FullAes` is still read as `Aes`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(MaxEncryption::None),
+            "bcencrypt" => Ok(MaxEncryption::BcEncrypt),
+            "headeraes" => Ok(MaxEncryption::HeaderAes),
+            "aes" | "fullaes" => Ok(MaxEncryption::Aes),
+            _ => Err(Error::OtherString(format!(
+                "Unknown encryption level: {}",
+                s
+            ))),
+        }
+    }
+}
+
 impl BcCamera {
     /// Login to the camera.
     ///
@@ -30,6 +88,9 @@ impl BcCamera {
     /// Login to the camera.
     ///
     /// This should be called before most other commands
+    ///
+    /// Fails with [`Error::EncryptionNotAllowed`] if the camera insists on a
+    /// stronger encryption than `max_encryption`
     pub async fn login_with_maxenc(&self, max_encryption: MaxEncryption) -> Result<DeviceInfo> {
         let device_info;
         // This { is here due to the connection and set_credentials both requiring a mutable borrow
@@ -55,11 +116,7 @@ impl BcCamera {
             //     .map(|p| md5_string(p, ZeroLast))
             //     .unwrap_or_else(|| EMPTY_LEGACY_PASSWORD.to_owned());
 
-            let enc_byte = match max_encryption {
-                MaxEncryption::None => 0xdc00,
-                MaxEncryption::BcEncrypt => 0xdc01,
-                MaxEncryption::Aes => 0xdc12,
-            };
+            let enc_byte = 0xdc00 | max_encryption.byte();
             let legacy_login = Bc {
                 meta: BcMeta {
                     msg_id: MSG_ID_LOGIN,
@@ -76,6 +133,21 @@ impl BcCamera {
 
             let legacy_reply = sub_login.recv().await?;
 
+            // The reply code is 0xdd followed by the encryption the camera chose
+            if legacy_reply.meta.response_code >> 8 == 0xdd {
+                let byte = legacy_reply.meta.response_code & 0xff;
+                match MaxEncryption::from_byte(byte) {
+                    Some(required) if required > max_encryption => {
+                        return Err(Error::EncryptionNotAllowed {
+                            allowed: max_encryption,
+                            required,
+                        });
+                    }
+                    Some(_) => {}
+                    None => return Err(Error::UnknownEncryption(byte as usize)),
+                }
+            }
+
             let nonce;
             match &legacy_reply.body {
                 BcBody::ModernMsg(ModernMsg {
@@ -0,0 +1,271 @@
+use super::{BcCamera, Confirmation, Error, Result};
+use crate::bc::{model::*, xml::*};
+use std::{fmt, str::FromStr};
+
+/// A rectangle of the privacy mask
+///
+/// Written as `x,y,width,height` in the coordinates of the [Shelter] xml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskRegion {
+    /// Left edge
+    pub x: u32,
+    /// Top edge
+    pub y: u32,
+    /// Width of the region
+    pub width: u32,
+    /// Height of the region
+    pub height: u32,
+}
+
+impl fmt::Display for MaskRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for MaskRegion {
+    type Err = Error;
+
+    /// Parse `x,y,width,height` such as `10,20,100,50`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            Error::OtherString(format!(
+                "Invalid mask region {}, expected x,y,width,height such as 10,20,100,50",
+                s
+            ))
+        };
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>>>()?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(MaskRegion {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<&ShelterRegion> for MaskRegion {
+    fn from(region: &ShelterRegion) -> Self {
+        MaskRegion {
+            x: region.x,
+            y: region.y,
+            width: region.width,
+            height: region.height,
+        }
+    }
+}
+
+impl Shelter {
+    /// The regions that are masked
+    pub fn regions(&self) -> Vec<MaskRegion> {
+        self.shelter_list
+            .iter()
+            .flat_map(|list| list.shelters.iter())
+            .filter(|region| region.enable != 0)
+            .map(MaskRegion::from)
+            .collect()
+    }
+
+    /// Replace the masked regions
+    ///
+    /// The unused entries are kept but disabled since some cameras expect
+    /// every entry to be sent back
+    pub fn set_regions(&mut self, regions: &[MaskRegion]) -> Result<()> {
+        if let Some(max_num) = self.max_num {
+            if regions.len() > max_num as usize {
+                return Err(Error::OtherString(format!(
+                    "The camera supports at most {} mask regions",
+                    max_num
+                )));
+            }
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            if let Some(region) = regions
+                .iter()
+                .find(|region| region.x + region.width > width || region.y + region.height > height)
+            {
+                return Err(Error::OtherString(format!(
+                    "Mask region {} is outside of the {}x{} picture",
+                    region, width, height
+                )));
+            }
+        }
+
+        let list = self.shelter_list.get_or_insert_with(Default::default);
+        let entries = list.shelters.len().max(regions.len());
+        list.shelters = (0..entries)
+            .map(|id| match regions.get(id) {
+                Some(region) => ShelterRegion {
+                    id: id as u8,
+                    enable: 1,
+                    x: region.x,
+                    y: region.y,
+                    width: region.width,
+                    height: region.height,
+                },
+                None => ShelterRegion {
+                    id: id as u8,
+                    ..Default::default()
+                },
+            })
+            .collect();
+        self.enable = Some((!regions.is_empty()) as u8);
+        Ok(())
+    }
+}
+
+impl BcCamera {
+    /// Get the [Shelter] xml which contains the privacy mask regions
+    pub async fn get_privacy_mask(&self) -> Result<Shelter> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_SHELTER, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_SHELTER,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    shelter: Some(shelter),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(shelter)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected Shelter xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the privacy mask using the [Shelter] xml
+    pub async fn set_privacy_mask(&self, shelter: Shelter) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_SHELTER, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_SHELTER,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    shelter: Some(shelter),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the Shelter xml")
+            .await
+    }
+
+    /// Mask exactly these regions, an empty list turns the mask off
+    pub async fn set_privacy_mask_regions(&self, regions: &[MaskRegion]) -> Result<Confirmation> {
+        let mut shelter = self.get_privacy_mask().await?;
+        shelter.set_regions(regions)?;
+        self.set_privacy_mask(shelter).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_region_parse() {
+        let region = "10,20,100,50".parse::<MaskRegion>().unwrap();
+        assert_eq!(
+            region,
+            MaskRegion {
+                x: 10,
+                y: 20,
+                width: 100,
+                height: 50
+            }
+        );
+        assert_eq!(region.to_string(), "10,20,100,50");
+        assert!("10,20,100".parse::<MaskRegion>().is_err());
+        assert!("10,20,0,50".parse::<MaskRegion>().is_err());
+        assert!("a,b,c,d".parse::<MaskRegion>().is_err());
+    }
+
+    #[test]
+    fn test_set_regions() {
+        let mut shelter = Shelter {
+            width: Some(640),
+            height: Some(360),
+            max_num: Some(2),
+            shelter_list: Some(ShelterList {
+                shelters: vec![
+                    ShelterRegion {
+                        id: 0,
+                        enable: 1,
+                        x: 1,
+                        y: 1,
+                        width: 1,
+                        height: 1,
+                    },
+                    ShelterRegion {
+                        id: 1,
+                        ..Default::default()
+                    },
+                ],
+            }),
+            ..Default::default()
+        };
+        let region = "10,20,100,50".parse::<MaskRegion>().unwrap();
+        shelter.set_regions(&[region]).unwrap();
+        assert_eq!(shelter.regions(), vec![region]);
+        assert_eq!(shelter.shelter_list.as_ref().unwrap().shelters.len(), 2);
+        assert_eq!(shelter.enable, Some(1));
+
+        assert!(shelter.set_regions(&[region; 3]).is_err());
+        assert!(shelter
+            .set_regions(&["600,0,100,10".parse().unwrap()])
+            .is_err());
+
+        shelter.set_regions(&[]).unwrap();
+        assert!(shelter.regions().is_empty());
+        assert_eq!(shelter.enable, Some(0));
+    }
+}
@@ -0,0 +1,374 @@
+use super::{BcCamera, Confirmation, Error, Result};
+use crate::bc::{model::*, xml::*};
+use std::{fmt, str::FromStr};
+
+/// The grid of cells that motion or ai detection watches
+///
+/// As text each row of the grid is a line with `#` for a watched cell and
+/// `.` for an ignored one. The camera's own `1` and `0` are also accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectionZone {
+    /// Columns of the grid
+    pub width: usize,
+    /// Rows of the grid
+    pub height: usize,
+    /// One entry for each cell row by row, true if it is watched
+    pub cells: Vec<bool>,
+}
+
+impl DetectionZone {
+    /// Read the zone of the `width`, `height` and `area` fields of the xml
+    pub fn from_area(width: u32, height: u32, area: &str) -> Result<Self> {
+        let (width, height) = (width as usize, height as usize);
+        let cells = area
+            .trim()
+            .chars()
+            .map(|cell| match cell {
+                '1' => Ok(true),
+                '0' => Ok(false),
+                _ => Err(Error::OtherString(format!(
+                    "Invalid cell {:?} in the detection zone",
+                    cell
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if cells.len() != width * height {
+            return Err(Error::OtherString(format!(
+                "Detection zone has {} cells but the grid is {}x{}",
+                cells.len(),
+                width,
+                height
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    /// The zone as the `area` of the xml
+    pub fn to_area(&self) -> String {
+        self.cells
+            .iter()
+            .map(|&cell| if cell { '1' } else { '0' })
+            .collect()
+    }
+
+    /// A zone of the same size that watches every cell
+    pub fn all(&self) -> Self {
+        Self {
+            cells: vec![true; self.cells.len()],
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for DetectionZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.chunks(self.width.max(1)) {
+            let line: String = row
+                .iter()
+                .map(|&cell| if cell { '#' } else { '.' })
+                .collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DetectionZone {
+    type Err = Error;
+
+    /// Parse the lines of `#` and `.` written by [`DetectionZone`]'s Display
+    fn from_str(s: &str) -> Result<Self> {
+        let rows = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.chars()
+                    .map(|cell| match cell {
+                        '#' | '1' => Ok(true),
+                        '.' | '0' => Ok(false),
+                        _ => Err(Error::OtherString(format!(
+                            "Invalid cell {:?} in the detection zone, expected # or .",
+                            cell
+                        ))),
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let width = rows.first().map(|row| row.len()).unwrap_or(0);
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err(Error::Other(
+                "Every row of the detection zone must be the same length",
+            ));
+        }
+        Ok(Self {
+            width,
+            height: rows.len(),
+            cells: rows.concat(),
+        })
+    }
+}
+
+/// Get and set the zone of either [MdAlarm] or [AiAlarm]
+macro_rules! zone_accessors {
+    ($xml:ty) => {
+        impl $xml {
+            /// The detection zone if the camera sent one
+            pub fn zone(&self) -> Option<Result<DetectionZone>> {
+                match (self.width, self.height, self.area.as_ref()) {
+                    (Some(width), Some(height), Some(area)) => {
+                        Some(DetectionZone::from_area(width, height, area))
+                    }
+                    _ => None,
+                }
+            }
+
+            /// Replace the detection zone, it must be the size of the camera's grid
+            pub fn set_zone(&mut self, zone: &DetectionZone) -> Result<()> {
+                let size = (self.width, self.height);
+                if size != (Some(zone.width as u32), Some(zone.height as u32)) {
+                    return Err(Error::OtherString(format!(
+                        "The detection zone must be {}x{} to match the camera",
+                        size.0.unwrap_or(0),
+                        size.1.unwrap_or(0)
+                    )));
+                }
+                self.area = Some(zone.to_area());
+                Ok(())
+            }
+        }
+    };
+}
+
+zone_accessors!(MdAlarm);
+zone_accessors!(AiAlarm);
+
+impl BcCamera {
+    /// Get the [MdAlarm] xml which contains the motion detection sensitivity and zone
+    pub async fn get_md_alarm(&self) -> Result<MdAlarm> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_MD_ALARM, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_MD_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    md_alarm: Some(md_alarm),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(md_alarm)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected MdAlarm xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the motion detection using the [MdAlarm] xml
+    pub async fn set_md_alarm(&self, md_alarm: MdAlarm) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_MD_ALARM, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_MD_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    md_alarm: Some(md_alarm),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the MdAlarm xml")
+            .await
+    }
+
+    /// Set the motion detection sensitivity of every part of the day from 1
+    /// to 50, lower values trigger more easily
+    pub async fn md_set_sensitivity(&self, sensitivity: u8) -> Result<Confirmation> {
+        if !(1..=50).contains(&sensitivity) {
+            return Err(Error::OtherString(format!(
+                "Motion sensitivity {} is out of range, expected 1 to 50",
+                sensitivity
+            )));
+        }
+        let mut md_alarm = self.get_md_alarm().await?;
+        let sens_info_list = md_alarm
+            .sens_info_list
+            .as_mut()
+            .ok_or(Error::Other("The camera has no motion sensitivity"))?;
+        for sens_info in sens_info_list.sens_info.iter_mut() {
+            sens_info.sensitivity = sensitivity;
+        }
+        self.set_md_alarm(md_alarm).await
+    }
+
+    /// Get the [AiAlarm] xml of one kind of object such as `people`
+    pub async fn get_ai_alarm(&self, ai_type: &str) -> Result<AiAlarm> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_AI_ALARM, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_AI_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    ai_alarm: Some(AiAlarm {
+                        version: xml_ver(),
+                        channel_id: self.channel_id,
+                        ai_type: ai_type.to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    ai_alarm: Some(ai_alarm),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(ai_alarm)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected AiAlarm xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the ai detection of one kind of object using the [AiAlarm] xml
+    pub async fn set_ai_alarm(&self, ai_alarm: AiAlarm) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_AI_ALARM, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_AI_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    ai_alarm: Some(ai_alarm),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the AiAlarm xml")
+            .await
+    }
+
+    /// Set the ai detection sensitivity of one kind of object from 0 to 100,
+    /// higher values trigger more easily
+    pub async fn ai_set_sensitivity(&self, ai_type: &str, sensitivity: u8) -> Result<Confirmation> {
+        if sensitivity > 100 {
+            return Err(Error::OtherString(format!(
+                "AI sensitivity {} is out of range, expected 0 to 100",
+                sensitivity
+            )));
+        }
+        let mut ai_alarm = self.get_ai_alarm(ai_type).await?;
+        ai_alarm.sensitivity = Some(sensitivity);
+        self.set_ai_alarm(ai_alarm).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detection_zone() {
+        let zone = DetectionZone::from_area(4, 2, "11110011").unwrap();
+        assert_eq!(zone.to_string(), "####\n..##\n");
+        assert_eq!("####\n..##\n".parse::<DetectionZone>().unwrap(), zone);
+        assert_eq!(zone.to_area(), "11110011");
+        assert_eq!(zone.all().to_area(), "11111111");
+
+        assert!(DetectionZone::from_area(4, 2, "1111").is_err());
+        assert!("###\n#\n".parse::<DetectionZone>().is_err());
+        assert!("#x#\n".parse::<DetectionZone>().is_err());
+    }
+
+    #[test]
+    fn test_set_zone() {
+        let mut md_alarm = MdAlarm {
+            width: Some(2),
+            height: Some(2),
+            area: Some("1111".to_string()),
+            ..Default::default()
+        };
+        let zone: DetectionZone = "#.\n.#".parse().unwrap();
+        md_alarm.set_zone(&zone).unwrap();
+        assert_eq!(md_alarm.area.as_deref(), Some("1001"));
+        assert!(md_alarm.set_zone(&"###".parse().unwrap()).is_err());
+    }
+}
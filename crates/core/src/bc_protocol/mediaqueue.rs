@@ -0,0 +1,188 @@
+//! The queue of media between the camera connection and [`super::StreamData`]
+//!
+//! A live stream must never stop the connection from reading. If it did the
+//! replies to every other command would wait behind it and the camera would
+//! time out. So when the reader falls behind the oldest frames are dropped to
+//! make room, and counted so that it can be reported. Playback instead waits
+//! for room as a recording is expected to be complete.
+use std::collections::VecDeque;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::Notify;
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    capacity: usize,
+    dropped: AtomicU64,
+    /// Wakes the receiver on a new item or when the sender is gone
+    recv_notify: Notify,
+    /// Wakes the sender when there is room or when the receiver is gone
+    send_notify: Notify,
+}
+
+struct Inner<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+/// Create a queue that holds at most `capacity` items
+pub(crate) fn media_queue<T>(capacity: usize) -> (MediaSender<T>, MediaReceiver<T>) {
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            items: VecDeque::with_capacity(capacity),
+            closed: false,
+        }),
+        capacity,
+        dropped: AtomicU64::new(0),
+        recv_notify: Notify::new(),
+        send_notify: Notify::new(),
+    });
+    (
+        MediaSender {
+            shared: shared.clone(),
+        },
+        MediaReceiver { shared },
+    )
+}
+
+pub(crate) struct MediaSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MediaSender<T> {
+    /// Add an item without waiting, the oldest item is dropped when full
+    ///
+    /// The item is given back if the receiver is gone
+    pub(crate) fn push(&self, item: T) -> Result<(), T> {
+        let mut inner = self.shared.inner.lock().unwrap();
+        if inner.closed {
+            return Err(item);
+        }
+        if inner.items.len() >= self.shared.capacity {
+            inner.items.pop_front();
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        inner.items.push_back(item);
+        drop(inner);
+        self.shared.recv_notify.notify_one();
+        Ok(())
+    }
+
+    /// Add an item waiting for room when full
+    ///
+    /// The item is given back if the receiver is gone
+    pub(crate) async fn send(&self, item: T) -> Result<(), T> {
+        loop {
+            {
+                let mut inner = self.shared.inner.lock().unwrap();
+                if inner.closed {
+                    return Err(item);
+                }
+                if inner.items.len() < self.shared.capacity {
+                    inner.items.push_back(item);
+                    drop(inner);
+                    self.shared.recv_notify.notify_one();
+                    return Ok(());
+                }
+            }
+            self.shared.send_notify.notified().await;
+        }
+    }
+}
+
+impl<T> Drop for MediaSender<T> {
+    fn drop(&mut self) {
+        self.shared.inner.lock().unwrap().closed = true;
+        self.shared.recv_notify.notify_one();
+    }
+}
+
+pub(crate) struct MediaReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MediaReceiver<T> {
+    /// The next item, `None` once the sender is gone and the queue is empty
+    ///
+    /// This is cancel safe, an item is only taken when it is returned
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut inner = self.shared.inner.lock().unwrap();
+                if let Some(item) = inner.items.pop_front() {
+                    drop(inner);
+                    self.shared.send_notify.notify_one();
+                    return Some(item);
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.shared.recv_notify.notified().await;
+        }
+    }
+
+    /// The number of items dropped so far to make room
+    pub(crate) fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for MediaReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.inner.lock().unwrap().closed = true;
+        self.shared.send_notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_push_drops_oldest() {
+        let (tx, mut rx) = media_queue(3);
+        for i in 0..5 {
+            tx.push(i).unwrap();
+        }
+        assert_eq!(rx.dropped(), 2);
+        drop(tx);
+        let mut got = vec![];
+        while let Some(i) = rx.recv().await {
+            got.push(i);
+        }
+        assert_eq!(got, vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_send_waits_for_room() {
+        let (tx, mut rx) = media_queue(1);
+        tx.send(0).await.unwrap();
+        assert!(timeout(Duration::from_millis(50), tx.send(1))
+            .await
+            .is_err());
+
+        let sender = tokio::spawn(async move {
+            tx.send(1).await.unwrap();
+        });
+        assert_eq!(timeout(WAIT, rx.recv()).await.unwrap(), Some(0));
+        assert_eq!(timeout(WAIT, rx.recv()).await.unwrap(), Some(1));
+        sender.await.unwrap();
+        assert_eq!(timeout(WAIT, rx.recv()).await.unwrap(), None);
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_closed_receiver() {
+        let (tx, rx) = media_queue(1);
+        drop(rx);
+        assert_eq!(tx.push(0), Err(0));
+        assert_eq!(tx.send(1).await, Err(1));
+    }
+}
@@ -14,6 +14,114 @@ pub enum MotionStatus {
     Stop(Instant),
     /// Sent when an Alarm about something other than motion was received
     NoChange(Instant),
+    /// Sent when the button of a doorbell was pressed
+    Visitor(Instant),
+}
+
+/// Split the status of an alarm into the visitor and motion parts
+///
+/// Doorbells add `visitor` to the status such as `"MD,visitor"` when their
+/// button is pressed
+fn alarm_status(status: &str) -> (bool, bool) {
+    let mut visitor = false;
+    let mut motion = false;
+    for part in status.split(',').map(str::trim) {
+        match part {
+            "visitor" => visitor = true,
+            "none" | "" => {}
+            _ => motion = true,
+        }
+    }
+    (visitor, motion)
+}
+
+/// The numeric values that some cameras send with an alarm
+///
+/// These can be used to ignore detections that the camera is not sure about
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MotionDetail {
+    /// The AI detection type such as `"people"`, `None` if nothing was detected by the AI
+    pub ai_type: Option<String>,
+    /// How strongly the motion was detected as a percentage
+    pub motion_confidence: Option<u8>,
+    /// Confidence of the AI detection as a percentage
+    pub ai_confidence: Option<u8>,
+    /// The PIR sensor triggered the alarm, only some firmwares name it in the
+    /// status such as `"MD,pir"`
+    pub pir: bool,
+}
+
+impl MotionDetail {
+    /// The kinds of object that the AI detected
+    ///
+    /// Some cameras list several such as `"people,vehicle"`
+    pub fn ai_detections(&self) -> Vec<AiDetection> {
+        self.ai_type
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && *name != "none")
+            .map(AiDetection::from)
+            .collect()
+    }
+}
+
+/// A kind of object that the AI of a camera can detect
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AiDetection {
+    /// `people` on the camera
+    Person,
+    /// `vehicle` on the camera
+    Vehicle,
+    /// `dog_cat` on the camera
+    Animal,
+    /// `face` on the camera
+    Face,
+    /// Anything else the camera sends, as it was sent
+    Other(String),
+}
+
+impl From<&str> for AiDetection {
+    fn from(name: &str) -> Self {
+        match name {
+            "people" | "person" => AiDetection::Person,
+            "vehicle" => AiDetection::Vehicle,
+            "dog_cat" | "animal" => AiDetection::Animal,
+            "face" => AiDetection::Face,
+            other => AiDetection::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for AiDetection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiDetection::Person => write!(f, "person"),
+            AiDetection::Vehicle => write!(f, "vehicle"),
+            AiDetection::Animal => write!(f, "animal"),
+            AiDetection::Face => write!(f, "face"),
+            AiDetection::Other(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl From<&AlarmEvent> for MotionDetail {
+    fn from(alarm_event: &AlarmEvent) -> Self {
+        let ai_type = alarm_event
+            .ai_type
+            .clone()
+            .filter(|ai_type| ai_type != "none");
+        Self {
+            ai_confidence: alarm_event.confidence.filter(|_| ai_type.is_some()),
+            ai_type,
+            motion_confidence: alarm_event.sensitivity,
+            pir: alarm_event
+                .status
+                .split(',')
+                .any(|part| part.trim().eq_ignore_ascii_case("pir")),
+        }
+    }
 }
 
 /// A handle on current motion related events comming from the camera
@@ -22,8 +130,10 @@ pub enum MotionStatus {
 pub struct MotionData {
     handle: JoinSet<Result<()>>,
     cancel: CancellationToken,
-    rx: Receiver<Result<MotionStatus>>,
+    rx: Receiver<Result<(MotionStatus, MotionDetail)>>,
     last_update: MotionStatus,
+    last_detail: MotionDetail,
+    last_visitor: Option<Instant>,
 }
 
 impl MotionData {
@@ -36,7 +146,7 @@ impl MotionData {
         Ok(match &self.last_update {
             MotionStatus::Start(_) => Some(true),
             MotionStatus::Stop(_) => Some(false),
-            MotionStatus::NoChange(_) => None,
+            MotionStatus::NoChange(_) | MotionStatus::Visitor(_) => None,
         })
     }
 
@@ -49,10 +159,24 @@ impl MotionData {
         Ok(match &self.last_update {
             MotionStatus::Start(_) => Some(true),
             MotionStatus::Stop(time) => Some((Instant::now() - *time) < duration),
-            MotionStatus::NoChange(_) => None,
+            MotionStatus::NoChange(_) | MotionStatus::Visitor(_) => None,
         })
     }
 
+    /// The detail sent with the last motion event that was consumed
+    ///
+    /// The values are `None` if the camera does not report them
+    pub fn motion_detail(&self) -> &MotionDetail {
+        &self.last_detail
+    }
+
+    /// When the doorbell button was last pressed of the events consumed so far
+    ///
+    /// `None` if it has not been pressed or the camera is not a doorbell
+    pub fn last_visitor(&self) -> Option<Instant> {
+        self.last_visitor
+    }
+
     /// Consume the motion events diretly
     ///
     /// An error is raised if the motion connection to the camera is dropped
@@ -60,12 +184,25 @@ impl MotionData {
         let mut results: Vec<MotionStatus> = vec![];
         loop {
             match self.rx.try_recv() {
-                Ok(motion) => results.push(motion?),
+                Ok(motion) => {
+                    let (status, detail) = motion?;
+                    if let MotionStatus::Visitor(time) = status {
+                        self.last_visitor = Some(time);
+                    } else {
+                        self.last_detail = detail;
+                    }
+                    results.push(status);
+                }
                 Err(TryRecvError::Empty) => break,
                 Err(e) => return Err(Error::from(e)),
             }
         }
-        if let Some(last) = results.last() {
+        // A press of the doorbell does not change the motion state
+        if let Some(last) = results
+            .iter()
+            .rev()
+            .find(|status| !matches!(status, MotionStatus::Visitor(_)))
+        {
             self.last_update = *last;
         }
         Ok(results)
@@ -79,8 +216,13 @@ impl MotionData {
         if let Some(last) = motions.last() {
             Ok(*last)
         } else if let Some(moition) = self.rx.recv().await {
-            let moition = moition?;
-            self.last_update = moition;
+            let (moition, detail) = moition?;
+            if let MotionStatus::Visitor(time) = moition {
+                self.last_visitor = Some(time);
+            } else {
+                self.last_update = moition;
+                self.last_detail = detail;
+            }
             Ok(moition)
         } else {
             Err(Error::Other("Motion dropped"))
@@ -236,27 +378,30 @@ impl BcCamera {
                                     ..
                                 }) = motion_msg.body
                                 {
-                                    let mut result = MotionStatus::NoChange(Instant::now());
+                                    let mut result = (MotionStatus::NoChange(Instant::now()), Default::default());
                                     for alarm_event in &alarm_event_list.alarm_events {
                                         if alarm_event.channel_id == channel_id {
-                                            if alarm_event.status != "none"
-                                                || alarm_event
-                                                    .ai_type
-                                                    .as_ref()
-                                                    .map(|ai_type| ai_type != "none")
-                                                    .unwrap_or(false)
-                                            {
-                                                result = MotionStatus::Start(Instant::now());
+                                            let detail = MotionDetail::from(alarm_event);
+                                            let (visitor, motion) = alarm_status(&alarm_event.status);
+                                            if visitor {
+                                                let press = (MotionStatus::Visitor(Instant::now()), Default::default());
+                                                if tx.send(Ok(press)).await.is_err() {
+                                                    // Motion reciever has been dropped
+                                                    return Ok(());
+                                                }
+                                            }
+                                            if motion || detail.ai_type.is_some() {
+                                                result = (MotionStatus::Start(Instant::now()), detail);
                                                 break;
                                             } else {
-                                                result = MotionStatus::Stop(Instant::now());
+                                                result = (MotionStatus::Stop(Instant::now()), detail);
                                                 break;
                                             }
                                         }
                                     }
                                     Ok(result)
                                 } else {
-                                    Ok(MotionStatus::NoChange(Instant::now()))
+                                    Ok((MotionStatus::NoChange(Instant::now()), Default::default()))
                                 }
                             }
                             // On connection drop we stop
@@ -278,6 +423,8 @@ impl BcCamera {
             cancel,
             rx,
             last_update: MotionStatus::NoChange(Instant::now()),
+            last_detail: Default::default(),
+            last_visitor: None,
         })
     }
 }
@@ -294,3 +441,52 @@ impl Drop for MotionData {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alarm_status() {
+        assert_eq!(alarm_status("none"), (false, false));
+        assert_eq!(alarm_status("MD"), (false, true));
+        assert_eq!(alarm_status("visitor"), (true, false));
+        assert_eq!(alarm_status("MD,visitor"), (true, true));
+        assert_eq!(alarm_status("none, visitor"), (true, false));
+    }
+
+    #[test]
+    fn test_pir() {
+        let detail = |status: &str| {
+            MotionDetail::from(&AlarmEvent {
+                status: status.to_string(),
+                ..Default::default()
+            })
+        };
+        assert!(!detail("MD").pir);
+        assert!(detail("MD, PIR").pir);
+        assert!(detail("pir").pir);
+    }
+
+    #[test]
+    fn test_ai_detections() {
+        let detail = |ai_type: Option<&str>| MotionDetail {
+            ai_type: ai_type.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(detail(None).ai_detections().is_empty());
+        assert!(detail(Some("none")).ai_detections().is_empty());
+        assert_eq!(
+            detail(Some("people,dog_cat")).ai_detections(),
+            vec![AiDetection::Person, AiDetection::Animal]
+        );
+        assert_eq!(
+            detail(Some("vehicle, package"))
+                .ai_detections()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["vehicle", "package"]
+        );
+    }
+}
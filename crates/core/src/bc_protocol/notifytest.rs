@@ -0,0 +1,72 @@
+use super::{BcCamera, Confirmation, Result};
+use crate::bc::model::*;
+use std::fmt;
+
+/// A way that the camera delivers its alarms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyTest {
+    /// Push notifications to the phone apps
+    Push,
+    /// Email with the email settings of the camera
+    Email,
+    /// Upload with the FTP settings of the camera
+    Ftp,
+}
+
+impl NotifyTest {
+    fn msg_id(&self) -> u32 {
+        match self {
+            NotifyTest::Push => MSG_ID_TEST_PUSH,
+            NotifyTest::Email => MSG_ID_TEST_EMAIL,
+            NotifyTest::Ftp => MSG_ID_TEST_FTP,
+        }
+    }
+}
+
+impl fmt::Display for NotifyTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyTest::Push => write!(f, "push"),
+            NotifyTest::Email => write!(f, "email"),
+            NotifyTest::Ftp => write!(f, "ftp"),
+        }
+    }
+}
+
+impl BcCamera {
+    /// Ask the camera to deliver a test notification
+    ///
+    /// The camera replies once it has attempted the delivery. An error
+    /// reply means that it could not deliver it, usually because of its
+    /// settings, and [`Confirmation::AssumedOk`] that it did not reply in
+    /// time
+    pub async fn test_notify(&self, test: NotifyTest) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(test.msg_id(), msg_num).await?;
+        let msg = Bc {
+            meta: BcMeta {
+                msg_id: test.msg_id(),
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub.send(msg).await?;
+        self.wait_for_confirmation(
+            &mut sub,
+            "The camera could not deliver the test notification",
+        )
+        .await
+    }
+}
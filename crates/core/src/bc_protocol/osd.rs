@@ -0,0 +1,118 @@
+use super::{BcCamera, Confirmation, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The overlays drawn on the video, they are read and written together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Osd {
+    /// The camera name overlay
+    pub channel_name: OsdChannelName,
+    /// The date and time overlay
+    pub datetime: OsdDatetime,
+}
+
+impl BcCamera {
+    /// Get the [OsdChannelName] and [OsdDatetime] xml of the overlays
+    pub async fn get_osd(&self) -> Result<Osd> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_OSD, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(channel_name),
+                    osd_datetime: Some(datetime),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(Osd {
+                channel_name,
+                datetime,
+            })
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected OsdChannelName and OsdDatetime xml but they were not recieved",
+            })
+        }
+    }
+
+    /// Set the overlays using the [OsdChannelName] and [OsdDatetime] xml
+    pub async fn set_osd(&self, osd: Osd) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_OSD, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(osd.channel_name),
+                    osd_datetime: Some(osd.datetime),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the Osd xml")
+            .await
+    }
+
+    /// This is a convience function to change the overlays
+    ///
+    /// Each setting that is `None` is left as it is
+    pub async fn osd_set(
+        &self,
+        show_name: Option<bool>,
+        show_time: Option<bool>,
+        name: Option<&str>,
+    ) -> Result<Confirmation> {
+        let mut osd = self.get_osd().await?;
+        if let Some(show_name) = show_name {
+            osd.channel_name.enable = show_name as u8;
+        }
+        if let Some(show_time) = show_time {
+            osd.datetime.enable = show_time as u8;
+        }
+        if let Some(name) = name {
+            osd.channel_name.name = name.to_string();
+        }
+        self.set_osd(osd).await
+    }
+}
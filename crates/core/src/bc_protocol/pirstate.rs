@@ -1,5 +1,6 @@
-use super::{BcCamera, Error, Result};
+use super::{BcCamera, Confirmation, Error, Result};
 use crate::bc::{model::*, xml::*};
+use std::{fmt, str::FromStr};
 use tokio::time::{interval, Duration};
 
 impl BcCamera {
@@ -66,7 +67,7 @@ impl BcCamera {
     }
 
     /// Set the PIR sensor using the [RfAlarmCfg] xml
-    pub async fn set_pirstate(&self, rf_alarm_cfg: RfAlarmCfg) -> Result<()> {
+    pub async fn set_pirstate(&self, rf_alarm_cfg: RfAlarmCfg) -> Result<Confirmation> {
         self.has_ability_rw("rfAlarm").await?;
         let connection = self.get_connection();
         let msg_num = self.new_message_num();
@@ -96,42 +97,45 @@ impl BcCamera {
         };
 
         sub_set.send(get).await?;
-        if let Ok(reply) =
-            tokio::time::timeout(tokio::time::Duration::from_micros(500), sub_set.recv()).await
-        {
-            let msg = reply?;
-            if msg.meta.response_code != 200 {
-                return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
-            }
-
-            if let BcMeta {
-                response_code: 200, ..
-            } = msg.meta
-            {
-                Ok(())
-            } else {
-                Err(Error::UnintelligibleReply {
-                    reply: std::sync::Arc::new(Box::new(msg)),
-                    why: "The camera did not except the RfAlarmCfg xml",
-                })
-            }
-        } else {
-            // Some cameras seem to just not send a reply on success, so after 500ms we return Ok
-            Ok(())
-        }
+        self.wait_for_confirmation(&mut sub_set, "The camera did not except the RfAlarmCfg xml")
+            .await
     }
 
     /// This is a convience function to control the PIR status
     /// True is on and false is off
-    pub async fn pir_set(&self, state: bool) -> Result<()> {
+    pub async fn pir_set(&self, state: bool) -> Result<Confirmation> {
         let mut pir_state = self.get_pirstate().await?;
         // println!("{:?}", pir_state);
         pir_state.enable = match state {
             true => 1,
             false => 0,
         };
-        self.set_pirstate(pir_state).await?;
-        Ok(())
+        self.set_pirstate(pir_state).await
+    }
+
+    /// Set the sensitivity of the PIR sensor from 0 to 100, higher values
+    /// trigger more easily
+    pub async fn pir_set_sensitivity(&self, sensitivity: u8) -> Result<Confirmation> {
+        if sensitivity > 100 {
+            return Err(Error::OtherString(format!(
+                "PIR sensitivity must be from 0 to 100 not {}",
+                sensitivity
+            )));
+        }
+        let mut pir_state = self.get_pirstate().await?;
+        pir_state.sensitivity = sensitivity;
+        self.set_pirstate(pir_state).await
+    }
+
+    /// Arm the PIR during the hours of the [PirSchedule] on every day
+    ///
+    /// This only changes when the PIR is armed, it must also be turned on
+    /// with [`BcCamera::pir_set`]
+    pub async fn pir_set_schedule(&self, schedule: PirSchedule) -> Result<Confirmation> {
+        let mut pir_state = self.get_pirstate().await?;
+        pir_state.time_block_list.time_block =
+            schedule.time_blocks(&pir_state.time_block_list.time_block);
+        self.set_pirstate(pir_state).await
     }
 }
 
@@ -142,3 +146,203 @@ pub enum PirState {
     /// Turn the PIR off
     Off,
 }
+
+/// The names of the days used when the camera did not send any
+const WEEK_DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// The hours of every day that the PIR is armed
+///
+/// Written as `begin-end` in hours such as `20-6` where `end` is not
+/// included and may be before `begin` to wrap past midnight. `always` is the
+/// same as `0-24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PirSchedule {
+    /// First armed hour from 0 to 23
+    pub begin: u8,
+    /// Hour that the PIR is no longer armed from 1 to 24
+    pub end: u8,
+}
+
+impl PirSchedule {
+    /// Armed for the whole day
+    pub const ALWAYS: PirSchedule = PirSchedule { begin: 0, end: 24 };
+
+    /// The time blocks of this schedule for each day in `current`
+    ///
+    /// A schedule that wraps past midnight needs two blocks per day
+    pub fn time_blocks(&self, current: &[TimeBlock]) -> Vec<TimeBlock> {
+        let mut days: Vec<String> = vec![];
+        for block in current {
+            if !days.contains(&block.weekDay) {
+                days.push(block.weekDay.clone());
+            }
+        }
+        if days.is_empty() {
+            days = WEEK_DAYS.iter().map(|day| day.to_string()).collect();
+        }
+
+        let hours = if self.begin < self.end {
+            vec![(self.begin, self.end - 1)]
+        } else {
+            let mut hours = vec![];
+            if self.end > 0 {
+                hours.push((0, self.end - 1));
+            }
+            hours.push((self.begin, 23));
+            hours
+        };
+        days.iter()
+            .flat_map(|day| {
+                hours.iter().map(move |(begin, end)| TimeBlock {
+                    enable: 1,
+                    weekDay: day.clone(),
+                    begin_hour: *begin,
+                    end_hour: *end,
+                })
+            })
+            .collect()
+    }
+
+    /// Read the schedule back from the time blocks
+    ///
+    /// This is `None` if the days differ or the blocks were set up in a way
+    /// that a [PirSchedule] cannot describe, such as with the camera app
+    pub fn from_time_blocks(blocks: &[TimeBlock]) -> Option<Self> {
+        let day = &blocks.first()?.weekDay;
+        let mut hours = blocks
+            .iter()
+            .filter(|block| &block.weekDay == day && block.enable != 0)
+            .map(|block| (block.begin_hour, block.end_hour))
+            .collect::<Vec<_>>();
+        hours.sort_unstable();
+        let schedule = match hours[..] {
+            [(begin, end)] if begin <= end => PirSchedule {
+                begin,
+                end: end + 1,
+            },
+            [(0, end), (begin, 23)] if end + 1 < begin => PirSchedule {
+                begin,
+                end: end + 1,
+            },
+            _ => return None,
+        };
+        // Every day must match the first
+        let expected = schedule.time_blocks(blocks);
+        let mut actual = blocks
+            .iter()
+            .filter(|block| block.enable != 0)
+            .map(|block| (&block.weekDay, block.begin_hour, block.end_hour))
+            .collect::<Vec<_>>();
+        let mut expected = expected
+            .iter()
+            .map(|block| (&block.weekDay, block.begin_hour, block.end_hour))
+            .collect::<Vec<_>>();
+        actual.sort_unstable();
+        expected.sort_unstable();
+        (actual == expected).then_some(schedule)
+    }
+}
+
+impl fmt::Display for PirSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == PirSchedule::ALWAYS {
+            write!(f, "always")
+        } else {
+            write!(f, "{}-{}", self.begin, self.end)
+        }
+    }
+}
+
+impl FromStr for PirSchedule {
+    type Err = Error;
+
+    /// Parse `always` or `begin-end` in hours such as `20-6`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            Error::OtherString(format!(
+                "Invalid PIR schedule {}, expected always or hours such as 20-6",
+                s
+            ))
+        };
+        if s.eq_ignore_ascii_case("always") {
+            return Ok(PirSchedule::ALWAYS);
+        }
+        let (begin, end) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let begin = begin.trim().parse::<u8>().map_err(|_| invalid())?;
+        let end = end.trim().parse::<u8>().map_err(|_| invalid())?;
+        if begin > 23 || end > 24 || begin == end {
+            return Err(invalid());
+        }
+        // Midnight is the end of the day
+        let end = if end == 0 { 24 } else { end };
+        Ok(PirSchedule { begin, end })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pir_schedule_parse() {
+        assert_eq!(
+            "20-6".parse::<PirSchedule>().unwrap(),
+            PirSchedule { begin: 20, end: 6 }
+        );
+        assert_eq!(
+            "always".parse::<PirSchedule>().unwrap(),
+            PirSchedule::ALWAYS
+        );
+        assert_eq!(
+            "22-0".parse::<PirSchedule>().unwrap(),
+            PirSchedule { begin: 22, end: 24 }
+        );
+        assert!("6-6".parse::<PirSchedule>().is_err());
+        assert!("24-6".parse::<PirSchedule>().is_err());
+        assert!("night".parse::<PirSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_pir_schedule_blocks() {
+        let current = vec![
+            TimeBlock {
+                enable: 1,
+                weekDay: "Sunday".to_string(),
+                begin_hour: 0,
+                end_hour: 23,
+            },
+            TimeBlock {
+                enable: 1,
+                weekDay: "Monday".to_string(),
+                begin_hour: 0,
+                end_hour: 23,
+            },
+        ];
+        assert_eq!(
+            PirSchedule::from_time_blocks(&current),
+            Some(PirSchedule::ALWAYS)
+        );
+
+        for schedule in [
+            PirSchedule { begin: 20, end: 6 },
+            PirSchedule { begin: 8, end: 18 },
+            PirSchedule { begin: 22, end: 24 },
+            PirSchedule::ALWAYS,
+        ] {
+            let blocks = schedule.time_blocks(&current);
+            assert!(blocks
+                .iter()
+                .all(|block| block.weekDay == "Sunday" || block.weekDay == "Monday"));
+            assert_eq!(PirSchedule::from_time_blocks(&blocks), Some(schedule));
+        }
+        assert_eq!(PirSchedule { begin: 20, end: 6 }.time_blocks(&[]).len(), 14);
+    }
+}
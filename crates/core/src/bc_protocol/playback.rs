@@ -0,0 +1,283 @@
+use super::{mediaqueue::media_queue, BcCamera, Error, Result, StreamData, StreamKind};
+use crate::bc::{model::*, xml::*};
+use futures::stream::StreamExt;
+use std::convert::TryFrom;
+use time::{Date, Month, PrimitiveDateTime, Time};
+use tokio::task;
+use tokio_util::sync::CancellationToken;
+
+/// The record types we search for, this is all the known types
+const ALL_RECORD_TYPES: &str = "manual, sched, md, pir, io";
+
+impl From<PrimitiveDateTime> for ReplayDateTime {
+    fn from(dt: PrimitiveDateTime) -> Self {
+        ReplayDateTime {
+            year: dt.year(),
+            month: dt.month().into(),
+            day: dt.day(),
+            hour: dt.hour(),
+            minute: dt.minute(),
+            second: dt.second(),
+        }
+    }
+}
+
+impl TryFrom<&ReplayDateTime> for PrimitiveDateTime {
+    type Error = Error;
+
+    fn try_from(dt: &ReplayDateTime) -> Result<Self> {
+        let date = Month::try_from(dt.month)
+            .ok()
+            .and_then(|month| Date::from_calendar_date(dt.year, month, dt.day).ok())
+            .ok_or(Error::Other("Invalid date in replay xml"))?;
+        let time = Time::from_hms(dt.hour, dt.minute, dt.second)
+            .map_err(|_| Error::Other("Invalid time in replay xml"))?;
+        Ok(PrimitiveDateTime::new(date, time))
+    }
+}
+
+impl BcCamera {
+    /// Get the list of recordings on the camera's SD card that overlap the given time range
+    ///
+    /// The times are in the camera's local time. The name of each returned [FileInfo]
+    /// can be used with [`BcCamera::start_playback`]
+    pub async fn get_recordings(
+        &self,
+        stream: StreamKind,
+        start: PrimitiveDateTime,
+        end: PrimitiveDateTime,
+    ) -> Result<Vec<FileInfo>> {
+        self.has_ability_ro("replay").await?;
+
+        let reply = self
+            .file_info_request(
+                MSG_ID_FILE_INFO_LIST_OPEN,
+                FileInfo {
+                    uid: Some(0),
+                    channel_id: self.channel_id,
+                    stream_type: Some(stream.to_string()),
+                    record_type: Some(ALL_RECORD_TYPES.to_string()),
+                    start_time: Some(start.into()),
+                    end_time: Some(end.into()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let handle = reply
+            .into_iter()
+            .find_map(|info| info.handle)
+            .ok_or(Error::Other(
+                "Camera did not return a handle for the search",
+            ))?;
+
+        let mut recordings = vec![];
+        let result: Result<()> = async {
+            loop {
+                let files = self
+                    .file_info_request(
+                        MSG_ID_FILE_INFO_LIST_GET,
+                        FileInfo {
+                            channel_id: self.channel_id,
+                            handle: Some(handle),
+                            ..Default::default()
+                        },
+                    )
+                    .await?
+                    .into_iter()
+                    .filter(|info| info.name.is_some())
+                    .collect::<Vec<_>>();
+                if files.is_empty() {
+                    break;
+                }
+                recordings.extend(files);
+            }
+            Ok(())
+        }
+        .await;
+
+        // Always close the search even if getting the files failed
+        if let Err(e) = self
+            .file_info_request(
+                MSG_ID_FILE_INFO_LIST_CLOSE,
+                FileInfo {
+                    channel_id: self.channel_id,
+                    handle: Some(handle),
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            log::debug!("Failed to close the recording search: {:?}", e);
+        }
+        result?;
+
+        Ok(recordings)
+    }
+
+    async fn file_info_request(&self, msg_id: u32, file_info: FileInfo) -> Result<Vec<FileInfo>> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(msg_id, msg_num).await?;
+
+        let send = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    file_info_list: Some(FileInfoList {
+                        version: xml_ver(),
+                        file_info: vec![file_info],
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub.send(send).await?;
+        let msg = sub.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    file_info_list: Some(FileInfoList { file_info, .. }),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(file_info)
+        } else {
+            // An accepted request without a list has no files for us
+            Ok(vec![])
+        }
+    }
+
+    ///
+    /// Starts the playback of a recorded file
+    ///
+    /// The `name` is the name of the file as returned by [`BcCamera::get_recordings`]
+    ///
+    /// The returned object works the same way as the one from [`BcCamera::start_video`]
+    /// when it is dropped the playback is stopped
+    ///
+    /// Unlike a live stream nothing of the recording is dropped when the buffer is full, the
+    /// playback waits for it to be read instead
+    pub async fn start_playback(
+        &self,
+        name: &str,
+        stream: StreamKind,
+        mut buffer_size: usize,
+        strict: bool,
+    ) -> Result<StreamData> {
+        self.has_ability_ro("replay").await?;
+
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+
+        let abort_handle = CancellationToken::new();
+        let abort_handle_thread = abort_handle.clone();
+
+        if buffer_size == 0 {
+            buffer_size = 100;
+        }
+        let (tx, rx) = media_queue(buffer_size);
+        let channel_id = self.channel_id;
+        let replay_xml = move || BcXml {
+            file_info_list: Some(FileInfoList {
+                version: xml_ver(),
+                file_info: vec![FileInfo {
+                    uid: Some(0),
+                    channel_id,
+                    name: Some(name.to_string()),
+                    stream_type: Some(stream.to_string()),
+                    play_speed: Some(1),
+                    ..Default::default()
+                }],
+            }),
+            ..Default::default()
+        };
+        let start_replay = replay_xml();
+        let stop_replay = replay_xml();
+
+        let handle = task::spawn(async move {
+            let mut sub_replay = connection.subscribe(MSG_ID_REPLAY_START, msg_num).await?;
+
+            let start = Bc::new_from_xml(
+                BcMeta {
+                    msg_id: MSG_ID_REPLAY_START,
+                    channel_id,
+                    msg_num,
+                    stream_type: 0,
+                    response_code: 0,
+                    class: 0x6414,
+                },
+                start_replay,
+            );
+            sub_replay.send(start).await?;
+
+            let msg = sub_replay.recv().await?;
+            if msg.meta.response_code != 200 {
+                return Err(Error::UnintelligibleReply {
+                    reply: std::sync::Arc::new(Box::new(msg)),
+                    why: "The camera did not accept the replay start command.",
+                });
+            }
+
+            {
+                let mut media_sub = sub_replay.bcmedia_stream(strict);
+
+                tokio::select! {
+                    _ = abort_handle_thread.cancelled() => {},
+                    _ = async {
+                        while let Some(bc_media) = media_sub.next().await {
+                            if tx.send(bc_media).await.is_err() {
+                                break; // Connection dropped
+                            }
+                        }
+                    } => {}
+                }
+            }
+
+            let stop = Bc::new_from_xml(
+                BcMeta {
+                    msg_id: MSG_ID_REPLAY_STOP,
+                    channel_id,
+                    msg_num,
+                    stream_type: 0,
+                    response_code: 0,
+                    class: 0x6414,
+                },
+                stop_replay,
+            );
+            let mut sub_stop = connection.subscribe(MSG_ID_REPLAY_STOP, msg_num).await?;
+            sub_stop.send(stop).await?;
+
+            tokio::select! {
+                v = async {
+                    let msg = sub_stop.recv().await?;
+                    if msg.meta.response_code == 200 {
+                        Ok(())
+                    } else {
+                        Err(Error::CameraServiceUnavaliable(msg.meta.response_code))
+                    }
+                } => v,
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(2)) => {Ok(())},
+            }
+        });
+
+        Ok(StreamData::new(handle, rx, abort_handle))
+    }
+}
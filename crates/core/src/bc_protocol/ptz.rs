@@ -250,6 +250,28 @@ impl BcCamera {
         let zoom_pos = zoom_pos.clamp(current.zoom.min_pos, current.zoom.max_pos);
         log::debug!("Clamped to {}", zoom_pos);
 
+        self.start_zoom_focus("zoomPos", zoom_pos).await
+    }
+
+    /// The camera will move the focus to the given position.
+    ///
+    /// The position is clamped to the focus range reported in [PtzZoomFocus]
+    pub async fn focus_to(&self, focus_pos: u32) -> Result<()> {
+        log::debug!("Setting focus to {}", focus_pos);
+        let current = self.get_zoom().await?;
+        log::debug!(
+            "   curr: {}, min: {}, max: {}",
+            current.focus.cur_pos,
+            current.focus.min_pos,
+            current.focus.max_pos
+        );
+        let focus_pos = focus_pos.clamp(current.focus.min_pos, current.focus.max_pos);
+        log::debug!("Clamped to {}", focus_pos);
+
+        self.start_zoom_focus("focusPos", focus_pos).await
+    }
+
+    async fn start_zoom_focus(&self, command: &str, move_pos: u32) -> Result<()> {
         self.has_ability_rw("control").await?;
         let connection = self.get_connection();
         let msg_num = self.new_message_num();
@@ -273,8 +295,8 @@ impl BcCamera {
                     start_zoom_focus: Some(StartZoomFocus {
                         version: xml_ver(),
                         channel_id: self.channel_id,
-                        command: "zoomPos".to_string(),
-                        move_pos: zoom_pos,
+                        command: command.to_string(),
+                        move_pos,
                     }),
                     ..Default::default()
                 })),
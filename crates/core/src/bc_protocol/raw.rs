@@ -0,0 +1,76 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The reply to a message sent with [`BcCamera::send_raw`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawReply {
+    /// The response code of the reply, `200` when the camera accepted the message
+    pub response_code: u16,
+    /// The extension xml of the reply
+    pub extension: Option<String>,
+    /// The payload of the reply when it is xml
+    pub xml: Option<String>,
+    /// The payload of the reply when it is binary
+    pub binary: Option<Vec<u8>>,
+}
+
+impl BcCamera {
+    /// Send a message with any id and return the reply of the camera
+    ///
+    /// This is meant to explore the protocol and to work around commands
+    /// that are not supported yet. The `xml` is the `<body>` of the message
+    /// and is parsed into [`BcXml`] before it is sent so only the elements
+    /// that this library knows are kept. The same is true for the reply
+    pub async fn send_raw(&self, msg_id: u32, xml: Option<&str>) -> Result<RawReply> {
+        let payload = match xml {
+            Some(xml) => Some(BcPayloads::BcXml(
+                BcXml::try_parse(xml.as_bytes()).map_err(Error::OtherString)?,
+            )),
+            None => None,
+        };
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(msg_id, msg_num).await?;
+
+        let msg = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                stream_type: 0,
+                response_code: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload,
+            }),
+        };
+
+        sub.send(msg).await?;
+        let msg = sub.recv().await?;
+
+        let mut reply = RawReply {
+            response_code: msg.meta.response_code,
+            extension: None,
+            xml: None,
+            binary: None,
+        };
+        if let BcBody::ModernMsg(ModernMsg { extension, payload }) = msg.body {
+            reply.extension = extension
+                .map(|extension| extension.serialize(vec![]))
+                .transpose()
+                .map_err(Error::OtherString)?
+                .map(|xml| String::from_utf8_lossy(&xml).into_owned());
+            match payload {
+                Some(BcPayloads::BcXml(xml)) => {
+                    let xml = xml.serialize(vec![]).map_err(Error::OtherString)?;
+                    reply.xml = Some(String::from_utf8_lossy(&xml).into_owned());
+                }
+                Some(BcPayloads::Binary(binary)) => reply.binary = Some(binary),
+                None => {}
+            }
+        }
+        Ok(reply)
+    }
+}
@@ -0,0 +1,45 @@
+use super::{BcCamera, BcSubscription, Error, Result};
+use crate::bc::model::*;
+
+/// The outcome of a command that the camera may not reply to
+///
+/// Some cameras never reply to a successful set command while on a slow
+/// link the reply can simply be late. Both look the same to us so commands
+/// that time out are reported as [`Confirmation::AssumedOk`] and it is up
+/// to the caller to verify them if it matters
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+    /// The camera accepted the command
+    Confirmed,
+    /// The camera did not reply within the reply timeout, the command was
+    /// sent but may not have been applied
+    AssumedOk,
+}
+
+impl BcCamera {
+    /// Wait up to the reply timeout for the camera to accept a set command
+    pub(crate) async fn wait_for_confirmation(
+        &self,
+        sub: &mut BcSubscription<'_>,
+        why: &'static str,
+    ) -> Result<Confirmation> {
+        match tokio::time::timeout(self.reply_timeout, sub.recv()).await {
+            Ok(reply) => {
+                let msg = reply?;
+                if let BcMeta {
+                    response_code: 200, ..
+                } = msg.meta
+                {
+                    Ok(Confirmation::Confirmed)
+                } else {
+                    Err(Error::UnintelligibleReply {
+                        reply: std::sync::Arc::new(Box::new(msg)),
+                        why,
+                    })
+                }
+            }
+            Err(_) => Ok(Confirmation::AssumedOk),
+        }
+    }
+}
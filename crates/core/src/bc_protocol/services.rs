@@ -0,0 +1,128 @@
+use super::{BcCamera, Confirmation, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The network services of the camera that can be turned on and off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// The http server
+    Http,
+    /// The https server
+    Https,
+    /// The rtsp server
+    Rtsp,
+    /// The rtmp server
+    Rtmp,
+    /// The onvif server
+    Onvif,
+}
+
+impl Service {
+    /// Every service in the order they appear in the [ServerPort] xml
+    pub const ALL: [Service; 5] = [
+        Service::Http,
+        Service::Https,
+        Service::Rtsp,
+        Service::Rtmp,
+        Service::Onvif,
+    ];
+
+    fn enable_mut(self, server_port: &mut ServerPort) -> &mut Option<u8> {
+        match self {
+            Service::Http => &mut server_port.http_enable,
+            Service::Https => &mut server_port.https_enable,
+            Service::Rtsp => &mut server_port.rtsp_enable,
+            Service::Rtmp => &mut server_port.rtmp_enable,
+            Service::Onvif => &mut server_port.onvif_enable,
+        }
+    }
+}
+
+impl BcCamera {
+    /// Get the [ServerPort] xml which contains the ports and state of the network services
+    pub async fn get_services(&self) -> Result<ServerPort> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection
+            .subscribe(MSG_ID_GET_SERVICE_PORTS, msg_num)
+            .await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_SERVICE_PORTS,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavaliable(msg.meta.response_code));
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    server_port: Some(server_port),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(server_port)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected ServerPort xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the ports and state of the network services using the [ServerPort] xml
+    pub async fn set_services(&self, server_port: ServerPort) -> Result<Confirmation> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection
+            .subscribe(MSG_ID_SET_SERVICE_PORTS, msg_num)
+            .await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_SERVICE_PORTS,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    server_port: Some(server_port),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        self.wait_for_confirmation(&mut sub_set, "The camera did not accept the ServerPort xml")
+            .await
+    }
+
+    /// Turn several services on or off in a single message
+    ///
+    /// The current [ServerPort] xml is read once and written back with all of
+    /// the changes so the camera never sees a partly applied set
+    pub async fn set_services_bulk(&self, services: &[(Service, bool)]) -> Result<Confirmation> {
+        let mut server_port = self.get_services().await?;
+        for (service, enabled) in services.iter().copied() {
+            *service.enable_mut(&mut server_port) = Some(enabled as u8);
+        }
+        self.set_services(server_port).await
+    }
+}
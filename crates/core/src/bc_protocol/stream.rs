@@ -1,10 +1,13 @@
-use super::{BcCamera, Error, Result};
+use super::{
+    mediaqueue::{media_queue, MediaReceiver},
+    BcCamera, Error, Result,
+};
 use crate::{
     bc::{model::*, xml::*},
     bcmedia::model::*,
+    trace::{in_span, stream_span},
 };
 use futures::stream::StreamExt;
-use tokio::sync::mpsc::{channel, Receiver};
 use tokio::task::{self, JoinHandle};
 use tokio_util::sync::CancellationToken;
 
@@ -22,6 +25,56 @@ pub enum StreamKind {
     Extern,
 }
 
+/// Number of messages buffered by [`BcCamera::start_video`] when it is given
+/// a `buffer_size` of 0
+pub const DEFAULT_STREAM_BUFFER: usize = 100;
+
+/// The preview handles used to start each stream
+///
+/// The defaults are the numbers used with the offical client.
+///
+/// On an E1 and swann cameras:
+///  - mainStream always has a value of 0
+///  - subStream always has a value of 1
+///  - There is no externStram
+///
+/// On a B800:
+///  - mainStream is 0
+///  - subStream is 256
+///  - externStram is 1024
+///
+/// Some models will only start the externStream with a different handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHandles {
+    /// Handle of the mainStream
+    pub main: u32,
+    /// Handle of the subStream
+    pub sub: u32,
+    /// Handle of the externStream
+    pub extern_stream: u32,
+}
+
+impl Default for StreamHandles {
+    fn default() -> Self {
+        Self {
+            main: 0,
+            sub: 256,
+            extern_stream: 1024,
+        }
+    }
+}
+
+impl StreamHandles {
+    /// The handle to start `stream` with
+    pub fn get(&self, stream: StreamKind) -> u32 {
+        match stream {
+            StreamKind::Main => self.main,
+            StreamKind::Sub => self.sub,
+            StreamKind::Extern => self.extern_stream,
+        }
+    }
+}
+
 impl std::fmt::Display for StreamKind {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -39,11 +92,23 @@ impl std::fmt::Display for StreamKind {
 /// When this object is dropped the streaming is stopped
 pub struct StreamData {
     handle: Option<JoinHandle<Result<()>>>,
-    rx: Receiver<Result<BcMedia>>,
+    rx: MediaReceiver<Result<BcMedia>>,
     abort_handle: CancellationToken,
 }
 
 impl StreamData {
+    pub(super) fn new(
+        handle: JoinHandle<Result<()>>,
+        rx: MediaReceiver<Result<BcMedia>>,
+        abort_handle: CancellationToken,
+    ) -> Self {
+        StreamData {
+            handle: Some(handle),
+            rx,
+            abort_handle,
+        }
+    }
+
     /// Pull data from the camera's buffer
     /// This returns raw BcMedia packets
     pub async fn get_data(&mut self) -> Result<Result<BcMedia>> {
@@ -74,6 +139,15 @@ impl StreamData {
         }
     }
 
+    /// The number of packets dropped so far because they were not pulled
+    /// with `get_data` before the buffer filled
+    ///
+    /// The oldest packets are dropped first, a video decoder should wait for
+    /// the next IFrame after any were dropped
+    pub fn dropped(&self) -> u64 {
+        self.rx.dropped()
+    }
+
     /// Attempts to gracefully shutdown this will cancel the background task and send
     /// the Stop command to the camera
     pub async fn shutdown(&mut self) -> Result<()> {
@@ -110,7 +184,14 @@ impl BcCamera {
     /// To pull frames from the camera's buffer use `recv_data` on the returned object
     ///
     /// The buffer_size represents number of compete messages so 1 would be one complete message
-    /// which may be a single audio frame or a whole video key frame. If 0 a default of 100 is used
+    /// which may be a single audio frame or a whole video key frame. If 0 [`DEFAULT_STREAM_BUFFER`]
+    /// is used
+    ///
+    /// When the buffer is full the oldest message is dropped rather than waiting so that a slow
+    /// reader cannot stall the connection, see [`StreamData::dropped`]
+    ///
+    /// The stream is started with the handle from the [`StreamHandles`] of the
+    /// [`super::BcCameraOpt`]
     ///
     /// A value of scrict=true will mean that the stream will error if the underlying stream is not
     /// as expected
@@ -133,12 +214,14 @@ impl BcCamera {
         let abort_handle_thread = abort_handle.clone();
 
         if buffer_size == 0 {
-            buffer_size = 100;
+            buffer_size = DEFAULT_STREAM_BUFFER;
         }
-        let (tx, rx) = channel(buffer_size);
+        let (tx, rx) = media_queue(buffer_size);
         let channel_id = self.channel_id;
+        let preview_handle = self.stream_handles.get(stream);
 
-        let handle = task::spawn(async move {
+        let span = stream_span(connection.name(), stream, msg_num);
+        let handle = task::spawn(in_span(span, async move {
             let mut sub_video = connection.subscribe(MSG_ID_VIDEO, msg_num).await?;
 
             // On an E1 and swann cameras:
@@ -155,21 +238,6 @@ impl BcCamera {
                 StreamKind::Extern => 0,
             };
 
-            // Theses are the numbers used with the offical client
-            // On an E1 and swann cameras:
-            //  - mainStream always has a value of 0
-            //  - subStream always has a value of 1
-            //  - There is no externStram
-            // On a B800:
-            //  - mainStream is 0
-            //  - subStream is 256
-            //  - externStram is 1024
-            let handle = match stream {
-                StreamKind::Main => 0,
-                StreamKind::Sub => 256,
-                StreamKind::Extern => 1024,
-            };
-
             let stream_name = match stream {
                 StreamKind::Main => "mainStream",
                 StreamKind::Sub => "subStream",
@@ -190,7 +258,7 @@ impl BcCamera {
                     preview: Some(Preview {
                         version: xml_ver(),
                         channel_id,
-                        handle,
+                        handle: preview_handle,
                         stream_type: Some(stream_name),
                     }),
                     ..Default::default()
@@ -221,7 +289,8 @@ impl BcCamera {
                             // debug!("Stream: Got");
                             // We now have a complete interesting packet. Send it to on the callback
                             // debug!("Stream: Send");
-                            if tx.send(bc_media).await.is_err() {
+                            // Never wait on the reader so that the connection keeps reading
+                            if tx.push(bc_media).is_err() {
                                 // debug!("Stream: Dropped");
                                 break; // Connection dropped
                             }
@@ -245,7 +314,7 @@ impl BcCamera {
                     preview: Some(Preview {
                         version: xml_ver(),
                         channel_id,
-                        handle,
+                        handle: preview_handle,
                         stream_type: None,
                     }),
                     ..Default::default()
@@ -279,13 +348,9 @@ impl BcCamera {
             }?;
 
             Ok(())
-        });
+        }));
 
-        Ok(StreamData {
-            handle: Some(handle),
-            rx,
-            abort_handle,
-        })
+        Ok(StreamData::new(handle, rx, abort_handle))
     }
 
     /// Stop a camera from sending more stream data.
@@ -313,20 +378,7 @@ impl BcCamera {
             StreamKind::Extern => 0,
         };
 
-        // Theses are the numbers used with the offical client
-        // On an E1 and swann cameras:
-        //  - mainStream always has a value of 0
-        //  - subStream always has a value of 1
-        //  - There is no externStram
-        // On a B800:
-        //  - mainStream is 0
-        //  - subStream is 256
-        //  - externStram is 1024
-        let handle = match stream {
-            StreamKind::Main => 0,
-            StreamKind::Sub => 256,
-            StreamKind::Extern => 1024,
-        };
+        let handle = self.stream_handles.get(stream);
 
         let stop_video = Bc::new_from_xml(
             BcMeta {
@@ -1,4 +1,4 @@
-use super::{BcCamera, Error, Result};
+use super::{BcCamera, EncodeChange, Error, Result, StreamKind};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
@@ -47,4 +47,15 @@ impl BcCamera {
             })
         }
     }
+
+    /// Change the resolution, bit rate and frame rate of a stream
+    ///
+    /// The changes are checked against the [EncodeTable]s of the stream before
+    /// they are sent to the camera
+    pub async fn set_stream_info(&self, stream: StreamKind, change: &EncodeChange) -> Result<()> {
+        let info = self.get_stream_info().await?;
+        let mut compression = self.get_compression().await?;
+        compression.apply_encode(stream, &info, change)?;
+        self.set_compression(compression).await
+    }
 }
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
 
@@ -45,4 +47,23 @@ impl BcCamera {
             })
         }
     }
+
+    /// Get the video channels of the device
+    ///
+    /// This is only `0` for a camera but an NVR lists each of its channels
+    pub async fn get_channels(&self) -> Result<Vec<u8>> {
+        let support = self.get_support().await?;
+        let mut channels = support
+            .items
+            .iter()
+            .filter_map(|item| u8::try_from(item.chn_id).ok())
+            .collect::<Vec<_>>();
+        if channels.is_empty() {
+            let num = support.channel_num.unwrap_or(1).clamp(1, u8::MAX as u32) as u8;
+            channels.extend(0..num);
+        }
+        channels.sort_unstable();
+        channels.dedup();
+        Ok(channels)
+    }
 }
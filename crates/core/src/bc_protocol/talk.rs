@@ -1,5 +1,6 @@
 use super::{BcCamera, Error, Result};
 use crate::{bc::model::*, bc::xml::*, bcmedia::model::*};
+use bytes::Bytes;
 use crossbeam_channel::Receiver;
 use std::io::{BufRead, Error as IoError, ErrorKind, Read};
 
@@ -192,7 +193,7 @@ impl BcCamera {
             let mut payload = vec![];
             for bytes in payload_bytes.chunks(full_block_size as usize) {
                 let bcmedia_adpcm = BcMedia::Adpcm(BcMediaAdpcm {
-                    data: bytes.to_vec(),
+                    data: Bytes::copy_from_slice(bytes),
                 });
                 payload = bcmedia_adpcm.serialize(payload)?;
             }
@@ -353,7 +354,7 @@ impl BcCamera {
             let mut payload = vec![];
             for block_bytes in payload_bytes.chunks(full_block_size as usize) {
                 let bytes: Vec<u8> = block_bytes.to_vec();
-                let bcmedia_adpcm = BcMedia::Adpcm(BcMediaAdpcm { data: bytes });
+                let bcmedia_adpcm = BcMedia::Adpcm(BcMediaAdpcm { data: bytes.into() });
                 payload = bcmedia_adpcm.serialize(payload)?;
             }
 
@@ -8,11 +8,18 @@ use bytes::BytesMut;
 use log::*;
 use tokio_util::codec::{Decoder, Encoder};
 
+/// How many packets in a row can fail to parse before the stream is given up
+///
+/// This happens when the camera encrypts the media in a way we cannot read
+const MAX_FAILED_PACKETS: usize = 100;
+
 pub struct BcMediaCodex {
     /// If true we will not search for the start of the next packet
     /// in the event that the stream appears to be corrupted
     strict: bool,
     amount_skipped: usize,
+    /// Packets that failed to parse since the last good one
+    failed_packets: usize,
 }
 
 impl BcMediaCodex {
@@ -20,6 +27,7 @@ impl BcMediaCodex {
         Self {
             strict,
             amount_skipped: 0,
+            failed_packets: 0,
         }
     }
 }
@@ -56,6 +64,7 @@ impl Decoder for BcMediaCodex {
                         debug!("Amount skipped to restore stream: {}", self.amount_skipped);
                         self.amount_skipped = 0;
                     }
+                    self.failed_packets = 0;
                     return Ok(Some(bc));
                 }
                 Err(Error::NomIncomplete(_)) => {
@@ -71,6 +80,10 @@ impl Decoder for BcMediaCodex {
                     } else if src.is_empty() {
                         return Ok(None);
                     } else {
+                        self.failed_packets += 1;
+                        if self.failed_packets > MAX_FAILED_PACKETS {
+                            return Err(Error::MediaUndecodable(self.failed_packets));
+                        }
                         if self.amount_skipped == 0 {
                             debug!("Error in stream attempting to restore");
                             trace!("   Stream Error: {:?}", e);
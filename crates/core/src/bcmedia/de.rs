@@ -1,7 +1,7 @@
 use super::model::BcMediaIframe;
 use super::model::*;
 use crate::Error;
-use bytes::{Buf, BytesMut};
+use bytes::{Bytes, BytesMut};
 use nom::{bytes::streaming::take, combinator::*, error::context, number::streaming::*};
 
 type IResult<I, O, E = nom::error::VerboseError<I>> = Result<(I, O), nom::Err<E>>;
@@ -11,16 +11,35 @@ const PAD_SIZE: u32 = 8;
 
 impl BcMedia {
     pub(crate) fn deserialize(buf: &mut BytesMut) -> Result<BcMedia, Error> {
-        let (result, len) = match consumed(bcmedia)(buf) {
-            Ok((_, (parsed_buff, result))) => Ok((result, parsed_buff.len())),
+        let (mut result, len, payload) = match consumed(bcmedia)(buf) {
+            Ok((_, (parsed_buff, (result, payload)))) => {
+                let start = payload.as_ptr() as usize - parsed_buff.as_ptr() as usize;
+                Ok((result, parsed_buff.len(), start..start + payload.len()))
+            }
             Err(e) => Err(e),
         }?;
-        buf.advance(len);
+        // The payload is a view into the packet rather than a copy of it
+        let packet = buf.split_to(len).freeze();
+        result.set_payload(packet.slice(payload));
         Ok(result)
     }
+
+    fn set_payload(&mut self, payload: Bytes) {
+        match self {
+            BcMedia::Iframe(BcMediaIframe { data, .. })
+            | BcMedia::Pframe(BcMediaPframe { data, .. })
+            | BcMedia::Aac(BcMediaAac { data })
+            | BcMedia::Adpcm(BcMediaAdpcm { data }) => *data = payload,
+            BcMedia::InfoV1(_) | BcMedia::InfoV2(_) => {}
+        }
+    }
 }
 
-fn bcmedia(buf: &[u8]) -> IResult<&[u8], BcMedia> {
+/// Parses the headers of a packet
+///
+/// The payload is returned as a slice of `buf` and left empty in the
+/// [`BcMedia`] so that it can be taken out of the buffer without a copy
+fn bcmedia(buf: &[u8]) -> IResult<&[u8], (BcMedia, &[u8])> {
     let (buf, magic) = context(
         "Failed to match any known bcmedia",
         verify(le_u32, |x| {
@@ -39,27 +58,27 @@ fn bcmedia(buf: &[u8]) -> IResult<&[u8], BcMedia> {
     match magic {
         MAGIC_HEADER_BCMEDIA_INFO_V1 => {
             let (buf, payload) = bcmedia_info_v1(buf)?;
-            Ok((buf, BcMedia::InfoV1(payload)))
+            Ok((buf, (BcMedia::InfoV1(payload), &buf[..0])))
         }
         MAGIC_HEADER_BCMEDIA_INFO_V2 => {
             let (buf, payload) = bcmedia_info_v2(buf)?;
-            Ok((buf, BcMedia::InfoV2(payload)))
+            Ok((buf, (BcMedia::InfoV2(payload), &buf[..0])))
         }
         MAGIC_HEADER_BCMEDIA_IFRAME..=MAGIC_HEADER_BCMEDIA_IFRAME_LAST => {
-            let (buf, payload) = bcmedia_iframe(buf)?;
-            Ok((buf, BcMedia::Iframe(payload)))
+            let (buf, (payload, data)) = bcmedia_iframe(buf)?;
+            Ok((buf, (BcMedia::Iframe(payload), data)))
         }
         MAGIC_HEADER_BCMEDIA_PFRAME..=MAGIC_HEADER_BCMEDIA_PFRAME_LAST => {
-            let (buf, payload) = bcmedia_pframe(buf)?;
-            Ok((buf, BcMedia::Pframe(payload)))
+            let (buf, (payload, data)) = bcmedia_pframe(buf)?;
+            Ok((buf, (BcMedia::Pframe(payload), data)))
         }
         MAGIC_HEADER_BCMEDIA_AAC => {
-            let (buf, payload) = bcmedia_aac(buf)?;
-            Ok((buf, BcMedia::Aac(payload)))
+            let (buf, (payload, data)) = bcmedia_aac(buf)?;
+            Ok((buf, (BcMedia::Aac(payload), data)))
         }
         MAGIC_HEADER_BCMEDIA_ADPCM => {
-            let (buf, payload) = bcmedia_adpcm(buf)?;
-            Ok((buf, BcMedia::Adpcm(payload)))
+            let (buf, (payload, data)) = bcmedia_adpcm(buf)?;
+            Ok((buf, (BcMedia::Adpcm(payload), data)))
         }
         _ => unreachable!(),
     }
@@ -163,7 +182,7 @@ fn take4(buf: &[u8]) -> IResult<&[u8], &str> {
     })(buf)
 }
 
-fn bcmedia_iframe(buf: &[u8]) -> IResult<&[u8], BcMediaIframe> {
+fn bcmedia_iframe(buf: &[u8]) -> IResult<&[u8], (BcMediaIframe, &[u8])> {
     let (buf, video_type_str) = context(
         "Video Type is unrecognised in IFrame",
         verify(take4, |x| matches!(x, "H264" | "H265")),
@@ -202,17 +221,20 @@ fn bcmedia_iframe(buf: &[u8]) -> IResult<&[u8], BcMediaIframe> {
 
     Ok((
         buf,
-        BcMediaIframe {
-            video_type,
-            // payload_size,
-            microseconds,
-            time,
-            data: data_slice.to_vec(),
-        },
+        (
+            BcMediaIframe {
+                video_type,
+                // payload_size,
+                microseconds,
+                time,
+                data: Bytes::new(),
+            },
+            data_slice,
+        ),
     ))
 }
 
-fn bcmedia_pframe(buf: &[u8]) -> IResult<&[u8], BcMediaPframe> {
+fn bcmedia_pframe(buf: &[u8]) -> IResult<&[u8], (BcMediaPframe, &[u8])> {
     let (buf, video_type_str) = context(
         "Video Type is unrecognised in PFrame",
         verify(take4, |x| matches!(x, "H264" | "H265")),
@@ -238,16 +260,19 @@ fn bcmedia_pframe(buf: &[u8]) -> IResult<&[u8], BcMediaPframe> {
 
     Ok((
         buf,
-        BcMediaPframe {
-            video_type,
-            // payload_size,
-            microseconds,
-            data: data_slice.to_vec(),
-        },
+        (
+            BcMediaPframe {
+                video_type,
+                // payload_size,
+                microseconds,
+                data: Bytes::new(),
+            },
+            data_slice,
+        ),
     ))
 }
 
-fn bcmedia_aac(buf: &[u8]) -> IResult<&[u8], BcMediaAac> {
+fn bcmedia_aac(buf: &[u8]) -> IResult<&[u8], (BcMediaAac, &[u8])> {
     let (buf, payload_size) = le_u16(buf)?;
     let (buf, _payload_size_b) = le_u16(buf)?;
     let (buf, data_slice) = take(payload_size)(buf)?;
@@ -259,14 +284,17 @@ fn bcmedia_aac(buf: &[u8]) -> IResult<&[u8], BcMediaAac> {
 
     Ok((
         buf,
-        BcMediaAac {
-            // payload_size,
-            data: data_slice.to_vec(),
-        },
+        (
+            BcMediaAac {
+                // payload_size,
+                data: Bytes::new(),
+            },
+            data_slice,
+        ),
     ))
 }
 
-fn bcmedia_adpcm(buf: &[u8]) -> IResult<&[u8], BcMediaAdpcm> {
+fn bcmedia_adpcm(buf: &[u8]) -> IResult<&[u8], (BcMediaAdpcm, &[u8])> {
     const SUB_HEADER_SIZE: u16 = 4;
 
     let (buf, payload_size) = le_u16(buf)?;
@@ -288,11 +316,14 @@ fn bcmedia_adpcm(buf: &[u8]) -> IResult<&[u8], BcMediaAdpcm> {
 
     Ok((
         buf,
-        BcMediaAdpcm {
-            // payload_size,
-            // block_size,
-            data: data_slice.to_vec(),
-        },
+        (
+            BcMediaAdpcm {
+                // payload_size,
+                // block_size,
+                data: Bytes::new(),
+            },
+            data_slice,
+        ),
     ))
 }
 
@@ -485,6 +516,31 @@ mod tests {
         }
     }
 
+    /// The payload must be a view into the buffer rather than a copy
+    #[test]
+    fn test_payload_not_copied() {
+        init();
+
+        let sample = [
+            include_bytes!("samples/iframe_0.raw").as_ref(),
+            include_bytes!("samples/iframe_1.raw").as_ref(),
+            include_bytes!("samples/iframe_2.raw").as_ref(),
+            include_bytes!("samples/iframe_3.raw").as_ref(),
+            include_bytes!("samples/iframe_4.raw").as_ref(),
+        ]
+        .concat();
+
+        let mut buf = BytesMut::from(&sample[..]);
+        let start = buf.as_ptr() as usize;
+        let end = start + buf.len();
+        if let Ok(BcMedia::Iframe(BcMediaIframe { data, .. })) = BcMedia::deserialize(&mut buf) {
+            let data_start = data.as_ptr() as usize;
+            assert!(data_start >= start && data_start + data.len() <= end);
+        } else {
+            panic!();
+        }
+    }
+
     #[test]
     fn test_pframe() {
         init();
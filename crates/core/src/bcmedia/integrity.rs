@@ -0,0 +1,165 @@
+//! Cheap checks that a video frame is not corrupt
+//!
+//! When UDP packets are lost the reassembled stream can hold frames that are
+//! cut short or that run into the next frame. Decoders downstream may crash
+//! on such data so [`check_frame`] looks at the NAL syntax of a frame before
+//! it is forwarded. It does not decode the slices, so a frame that passes can
+//! still be damaged; it only catches the damage that is cheap to spot.
+use super::model::{
+    VideoType, MAGIC_HEADER_BCMEDIA_IFRAME, MAGIC_HEADER_BCMEDIA_IFRAME_LAST,
+    MAGIC_HEADER_BCMEDIA_PFRAME, MAGIC_HEADER_BCMEDIA_PFRAME_LAST,
+};
+use super::paramsets::split_nals;
+
+/// Why a frame was found to be corrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDefect {
+    /// The frame has no data
+    Empty,
+    /// The data does not begin with an annex b start code
+    NoStartCode,
+    /// A NAL unit is shorter than its header
+    TruncatedNal,
+    /// A NAL header has the forbidden bit set or an invalid type
+    BadNalHeader,
+    /// There is no slice data in the frame
+    NoSlice,
+    /// The header of the next frame is inside the data, part of the frame
+    /// was lost
+    LengthMismatch,
+}
+
+impl std::fmt::Display for FrameDefect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            FrameDefect::Empty => "empty frame",
+            FrameDefect::NoStartCode => "missing start code",
+            FrameDefect::TruncatedNal => "truncated NAL unit",
+            FrameDefect::BadNalHeader => "invalid NAL header",
+            FrameDefect::NoSlice => "no slice data",
+            FrameDefect::LengthMismatch => "frame runs into the next frame",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+fn starts_with_start_code(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1])
+}
+
+/// Check a NAL header returning if it holds slice data
+fn check_nal(video_type: VideoType, nal: &[u8]) -> Result<bool, FrameDefect> {
+    let header_len = match video_type {
+        VideoType::H264 => 1,
+        VideoType::H265 => 2,
+    };
+    if nal.len() < header_len {
+        return Err(FrameDefect::TruncatedNal);
+    }
+    if nal[0] & 0x80 != 0 {
+        return Err(FrameDefect::BadNalHeader);
+    }
+    let slice = match video_type {
+        VideoType::H264 => {
+            let nal_type = nal[0] & 0x1f;
+            if nal_type == 0 {
+                return Err(FrameDefect::BadNalHeader);
+            }
+            (1..=5).contains(&nal_type)
+        }
+        VideoType::H265 => {
+            // nuh_temporal_id_plus1 may never be zero
+            if nal[1] & 0x07 == 0 {
+                return Err(FrameDefect::BadNalHeader);
+            }
+            (nal[0] >> 1) & 0x3f < 32
+        }
+    };
+    if slice && nal.len() <= header_len {
+        return Err(FrameDefect::TruncatedNal);
+    }
+    Ok(slice)
+}
+
+/// Find the magic and codec of a bcmedia video header in the data
+fn has_frame_header(data: &[u8]) -> bool {
+    data.windows(8).any(|window| {
+        let magic = u32::from_le_bytes([window[0], window[1], window[2], window[3]]);
+        matches!(
+            magic,
+            MAGIC_HEADER_BCMEDIA_IFRAME..=MAGIC_HEADER_BCMEDIA_IFRAME_LAST
+                | MAGIC_HEADER_BCMEDIA_PFRAME..=MAGIC_HEADER_BCMEDIA_PFRAME_LAST
+        ) && matches!(&window[4..8], b"H264" | b"H265")
+    })
+}
+
+/// Check the annex b data of a video frame for signs of corruption
+pub fn check_frame(video_type: VideoType, data: &[u8]) -> Result<(), FrameDefect> {
+    if data.is_empty() {
+        return Err(FrameDefect::Empty);
+    }
+    if !starts_with_start_code(data) {
+        return Err(FrameDefect::NoStartCode);
+    }
+    let mut has_slice = false;
+    for nal in split_nals(data) {
+        has_slice |= check_nal(video_type, nal)?;
+    }
+    if !has_slice {
+        return Err(FrameDefect::NoSlice);
+    }
+    if has_frame_header(data) {
+        return Err(FrameDefect::LengthMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: &[u8] = &[0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f];
+    const IDR: &[u8] = &[0, 0, 0, 1, 0x65, 0x88, 0x84, 0x00];
+
+    #[test]
+    fn test_valid_frames() {
+        assert_eq!(check_frame(VideoType::H264, &[SPS, IDR].concat()), Ok(()));
+        let h265 = [0, 0, 1, 0x26, 0x01, 0xaf, 0x10];
+        assert_eq!(check_frame(VideoType::H265, &h265), Ok(()));
+    }
+
+    #[test]
+    fn test_corrupt_frames() {
+        assert_eq!(check_frame(VideoType::H264, &[]), Err(FrameDefect::Empty));
+        assert_eq!(
+            check_frame(VideoType::H264, &IDR[4..]),
+            Err(FrameDefect::NoStartCode)
+        );
+        assert_eq!(
+            check_frame(VideoType::H264, &[0, 0, 0, 1, 0x65]),
+            Err(FrameDefect::TruncatedNal)
+        );
+        assert_eq!(
+            check_frame(VideoType::H264, &[0, 0, 1, 0xe5, 0x88]),
+            Err(FrameDefect::BadNalHeader)
+        );
+        assert_eq!(check_frame(VideoType::H264, SPS), Err(FrameDefect::NoSlice));
+        // H265 with a zero temporal id
+        assert_eq!(
+            check_frame(VideoType::H265, &[0, 0, 1, 0x26, 0x00, 0xaf]),
+            Err(FrameDefect::BadNalHeader)
+        );
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let mut data = IDR.to_vec();
+        data.extend_from_slice(&MAGIC_HEADER_BCMEDIA_PFRAME.to_le_bytes());
+        data.extend_from_slice(b"H264");
+        data.extend_from_slice(&[0x10, 0, 0, 0]);
+        assert_eq!(
+            check_frame(VideoType::H264, &data),
+            Err(FrameDefect::LengthMismatch)
+        );
+    }
+}
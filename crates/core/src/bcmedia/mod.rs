@@ -1,7 +1,11 @@
 pub(crate) mod codex;
 /// Deserlizer for BCMedia
 pub mod de;
+/// Integrity checks of the video frames
+pub mod integrity;
 /// Structure model for BCMedia
 pub mod model;
+/// Parameter set caching for the video
+pub mod paramsets;
 /// Serlizer for BCMedia
 pub mod ser;
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 /// Video streams encapsulate a stream of BcMedia
 #[derive(Debug, Clone)]
 pub enum BcMedia {
@@ -108,7 +110,7 @@ pub(super) const MAGIC_HEADER_BCMEDIA_IFRAME: u32 = 0x63643030;
 pub(super) const MAGIC_HEADER_BCMEDIA_IFRAME_LAST: u32 = 0x63643039;
 
 /// Video Types for I/PFrame
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VideoType {
     /// H264 video data
     H264,
@@ -131,7 +133,7 @@ pub struct BcMediaIframe {
     pub time: Option<u32>,
     //unknown: u32, // Known values 1-00/06/29 2-00/01 3-00/C3 4-00
     /// Raw IFrame data
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 impl std::fmt::Debug for BcMediaIframe {
@@ -170,7 +172,7 @@ pub struct BcMediaPframe {
     pub microseconds: u32,
     // unknown: u32, // Known values 1-00/23/5A 2-00 3-00 4-00
     /// Raw PFrame data
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 impl std::fmt::Debug for BcMediaPframe {
@@ -202,7 +204,7 @@ pub struct BcMediaAac {
     // Size of payload after header in bytes exactly the same as before
     // pub payload_size_b: u16,
     /// Raw AAC data
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 pub(super) const MAGIC_HEADER_BCMEDIA_ADPCM: u32 = 0x62773130;
@@ -229,5 +231,5 @@ pub struct BcMediaAdpcm {
     /// of adpcm samples
     ///
     /// To calculate the block-align size simply remove 4 from the `len()`
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
@@ -0,0 +1,225 @@
+//! Keeps the parameter sets in front of every keyframe
+//!
+//! Some cameras only send the SPS/PPS (and VPS for H265) with some of their
+//! IFrames. A decoder that joins on an IFrame without them shows a green or
+//! grey picture until the next one arrives. [`ParamSetCache`] remembers the
+//! last parameter sets of a stream and puts them back into the IFrames that
+//! lack them.
+use super::model::{BcMedia, BcMediaIframe, BcMediaPframe, VideoType};
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Split annex b data into its NAL units without the start codes
+pub fn split_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                // A four byte start code leaves a trailing zero on the previous NAL
+                let mut end = i;
+                while end > start && data[end - 1] == 0 {
+                    end -= 1;
+                }
+                nals.push(&data[start..end]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        if start < data.len() {
+            nals.push(&data[start..]);
+        }
+    }
+    nals
+}
+
+/// The kind of a NAL unit that matters for the parameter sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NalKind {
+    Vps,
+    Sps,
+    Pps,
+    Delimiter,
+    Other,
+}
+
+fn nal_kind(video_type: VideoType, nal: &[u8]) -> NalKind {
+    let Some(&header) = nal.first() else {
+        return NalKind::Other;
+    };
+    match video_type {
+        VideoType::H264 => match header & 0x1f {
+            7 => NalKind::Sps,
+            8 => NalKind::Pps,
+            9 => NalKind::Delimiter,
+            _ => NalKind::Other,
+        },
+        VideoType::H265 => match (header >> 1) & 0x3f {
+            32 => NalKind::Vps,
+            33 => NalKind::Sps,
+            34 => NalKind::Pps,
+            35 => NalKind::Delimiter,
+            _ => NalKind::Other,
+        },
+    }
+}
+
+/// The last parameter sets seen on a stream
+#[derive(Debug, Clone, Default)]
+pub struct ParamSetCache {
+    video_type: Option<VideoType>,
+    vps: Option<Vec<u8>>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+}
+
+impl ParamSetCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cache the parameter sets of a video frame and add them to IFrames
+    /// that lack them
+    ///
+    /// Other media is left alone
+    pub fn inspect(&mut self, media: &mut BcMedia) {
+        match media {
+            BcMedia::Iframe(BcMediaIframe {
+                video_type, data, ..
+            }) => {
+                if let Some(fixed) = self.frame(*video_type, true, data) {
+                    *data = fixed.into();
+                }
+            }
+            BcMedia::Pframe(BcMediaPframe {
+                video_type, data, ..
+            }) => {
+                self.frame(*video_type, false, data);
+            }
+            _ => {}
+        }
+    }
+
+    /// Cache the parameter sets of a frame
+    ///
+    /// Returns the new frame data if parameter sets had to be added to
+    /// the keyframe
+    pub fn frame(&mut self, video_type: VideoType, keyframe: bool, data: &[u8]) -> Option<Vec<u8>> {
+        if self.video_type != Some(video_type) {
+            // The old parameter sets are no use for the new codec
+            *self = Self {
+                video_type: Some(video_type),
+                ..Default::default()
+            };
+        }
+
+        let nals = split_nals(data);
+        let (mut has_vps, mut has_sps, mut has_pps) = (false, false, false);
+        for nal in nals.iter() {
+            match nal_kind(video_type, nal) {
+                NalKind::Vps => {
+                    has_vps = true;
+                    self.vps = Some(nal.to_vec());
+                }
+                NalKind::Sps => {
+                    has_sps = true;
+                    self.sps = Some(nal.to_vec());
+                }
+                NalKind::Pps => {
+                    has_pps = true;
+                    self.pps = Some(nal.to_vec());
+                }
+                NalKind::Delimiter | NalKind::Other => {}
+            }
+        }
+        if !keyframe {
+            return None;
+        }
+
+        let missing = [
+            (self.vps.as_ref(), has_vps || video_type == VideoType::H264),
+            (self.sps.as_ref(), has_sps),
+            (self.pps.as_ref(), has_pps),
+        ]
+        .iter()
+        .filter(|&&(_, present)| !present)
+        .filter_map(|&(cached, _)| cached)
+        .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return None;
+        }
+
+        let mut out = Vec::with_capacity(
+            data.len() + missing.iter().map(|nal| nal.len() + 4).sum::<usize>() + 4,
+        );
+        let mut inserted = false;
+        for nal in nals {
+            // The parameter sets go after the access unit delimiter
+            if !inserted && nal_kind(video_type, nal) != NalKind::Delimiter {
+                for param in missing.iter() {
+                    out.extend_from_slice(&START_CODE);
+                    out.extend_from_slice(param);
+                }
+                inserted = true;
+            }
+            out.extend_from_slice(&START_CODE);
+            out.extend_from_slice(nal);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| START_CODE.iter().chain(nal.iter()).copied())
+            .collect()
+    }
+
+    const SPS: &[u8] = &[0x67, 0x64, 0x00, 0x1f];
+    const PPS: &[u8] = &[0x68, 0xee, 0x3c, 0x80];
+    const IDR: &[u8] = &[0x65, 0x88, 0x84, 0x00];
+    const SLICE: &[u8] = &[0x41, 0x9a, 0x02];
+    const AUD: &[u8] = &[0x09, 0xf0];
+
+    #[test]
+    fn test_split_nals() {
+        let mut data = vec![0, 0, 1];
+        data.extend_from_slice(SPS);
+        data.extend_from_slice(&START_CODE);
+        data.extend_from_slice(PPS);
+        assert_eq!(split_nals(&data), vec![SPS, PPS]);
+    }
+
+    #[test]
+    fn test_iframe_without_params() {
+        let mut cache = ParamSetCache::new();
+        let first = annexb(&[SPS, PPS, IDR]);
+        assert_eq!(cache.frame(VideoType::H264, true, &first), None);
+        assert_eq!(cache.frame(VideoType::H264, false, &annexb(&[SLICE])), None);
+
+        let bare = annexb(&[AUD, IDR]);
+        assert_eq!(
+            cache.frame(VideoType::H264, true, &bare),
+            Some(annexb(&[AUD, SPS, PPS, IDR]))
+        );
+    }
+
+    #[test]
+    fn test_codec_change() {
+        let mut cache = ParamSetCache::new();
+        cache.frame(VideoType::H264, true, &annexb(&[SPS, PPS, IDR]));
+        // H265 IDR, the H264 parameter sets must not be used
+        let idr = annexb(&[&[0x26, 0x01, 0xaf]]);
+        assert_eq!(cache.frame(VideoType::H265, true, &idr), None);
+    }
+}
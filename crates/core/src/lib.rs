@@ -33,6 +33,7 @@ pub mod bc_protocol;
 pub mod bcmedia;
 ///  Contains low level structures and formats for the udpstream
 pub mod bcudp;
+mod trace;
 
 /// This is the top level error structure of the library
 ///
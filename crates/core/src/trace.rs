@@ -0,0 +1,95 @@
+//! Tracing of the bc messages
+//!
+//! With the `tracing` feature every subscription gets a span with the camera,
+//! msg_id and msg_num so that each request and its replies can be followed in
+//! tokio-console or any other tracing subscriber. The video of a stream runs
+//! in a span with the stream kind so its subscriptions are found under it.
+//! Without it these do nothing and the debug text goes to the log.
+//!
+use crate::bc::model::BcMeta;
+use std::future::Future;
+
+/// The span of one subscription
+#[cfg(feature = "tracing")]
+pub(crate) type BcSpan = tracing::Span;
+
+/// The span of one subscription
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BcSpan;
+
+/// Start the span of a subscription, `msg_num` is `None` until it is learnt
+pub(crate) fn bc_span(camera: &str, msg_id: u32, msg_num: Option<u16>) -> BcSpan {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::debug_span!("bc", camera, msg_id, msg_num)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (camera, msg_id, msg_num);
+        BcSpan
+    }
+}
+
+/// Start the span of the video of a stream, the subscriptions made while it
+/// runs are its children
+pub(crate) fn stream_span(camera: &str, stream: impl std::fmt::Display, msg_num: u16) -> BcSpan {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info_span!("stream", camera, stream = %stream, msg_num)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (camera, stream, msg_num);
+        BcSpan
+    }
+}
+
+/// Run `fut` inside `span`
+pub(crate) async fn in_span<F: Future>(span: BcSpan, fut: F) -> F::Output {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Instrument::instrument(fut, span).await
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = span;
+        fut.await
+    }
+}
+
+/// Record a message that was sent or received in a span
+pub(crate) fn bc_event(span: &BcSpan, direction: &'static str, meta: &BcMeta) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        parent: span,
+        msg_id = meta.msg_id,
+        msg_num = meta.msg_num,
+        response_code = meta.response_code,
+        class = meta.class,
+        "{}",
+        direction
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (span, direction, meta);
+}
+
+/// Log target of the raw messages so that they can be turned on without the
+/// rest of the crate, e.g. `RUST_LOG=neolink_core::bc::wire=debug`
+pub(crate) const WIRE_TARGET: &str = "neolink_core::bc::wire";
+
+/// Text of a message that is only shown when the camera has `debug` on
+pub(crate) fn debug_text(what: &'static str, text: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: WIRE_TARGET, what, text);
+    #[cfg(not(feature = "tracing"))]
+    log::debug!(target: WIRE_TARGET, "{}: {}", what, text);
+}
+
+/// Hexdump of a message that is only shown when the camera has `debug` on
+pub(crate) fn debug_bytes(what: &'static str, bytes: &[u8]) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: WIRE_TARGET, what, bytes = ?bytes);
+    #[cfg(not(feature = "tracing"))]
+    log::debug!(target: WIRE_TARGET, "{}: {:02X?}", what, bytes);
+}
@@ -12,7 +12,7 @@ lazy_static! {
     static ref RE_TLS_CLIENT_AUTH: Regex = Regex::new(r"^(none|request|require)$").unwrap();
     static ref RE_PAUSE_MODE: Regex = Regex::new(r"^(black|still|test|none)$").unwrap();
     static ref RE_MAXENC_SRC: Regex =
-        Regex::new(r"^([nN]one|[Aa][Ee][Ss]|[Bb][Cc][Ee][Nn][Cc][Rr][Yy][Pp][Tt])$").unwrap();
+        Regex::new(r"^(?i)(none|bcencrypt|headeraes|aes|fullaes)$").unwrap();
 }
 
 #[derive(Debug, Deserialize, Validate, Clone)]
@@ -92,6 +92,9 @@ impl AddressOrUid {
                 password: camera_config.password.clone(),
             },
             debug: false,
+            reply_timeout: None,
+            stream_handles: Default::default(),
+            capture_dir: None,
         };
 
         trace!("Camera Info: {:?}", options);
@@ -127,12 +130,10 @@ pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<Bc
             )
         })?;
 
-    let max_encryption = match camera_config.max_encryption.to_lowercase().as_str() {
-        "none" => MaxEncryption::None,
-        "bcencrypt" => MaxEncryption::BcEncrypt,
-        "aes" => MaxEncryption::Aes,
-        _ => MaxEncryption::Aes,
-    };
+    let max_encryption: MaxEncryption = camera_config
+        .max_encryption
+        .parse()
+        .with_context(|| format!("Invalid max_encryption for {}", camera_config.name))?;
     info!("{}: Logging in", camera_config.name);
     camera
         .login_with_maxenc(max_encryption)
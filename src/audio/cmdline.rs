@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The audio command will control the microphone recording and speaker volume of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Whether the streams include the microphone
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub record: Option<bool>,
+    /// The speaker volume from 0 to 100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: Option<u8>,
+}
@@ -0,0 +1,84 @@
+///
+/// # Neolink Audio
+///
+/// This module handles the audio settings of the camera, whether the
+/// microphone is included in the streams and the volume of the speaker
+///
+/// Without any changes it prints the current settings
+///
+/// # Usage
+///
+/// ```bash
+/// # To stop the camera recording sound
+/// neolink audio --config=config.toml CameraName --record off
+/// # To lower the speaker volume
+/// neolink audio --config=config.toml CameraName --volume 40
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the audio subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if let Some(record) = opt.record {
+        camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.set_audio_recording(record)
+                        .await
+                        .context(tr!("audio.set_failed"))
+                })
+            })
+            .await?;
+    }
+    if let Some(volume) = opt.volume {
+        let confirmation = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.set_volume(volume)
+                        .await
+                        .context(tr!("audio.set_failed"))
+                })
+            })
+            .await?;
+        if confirmation == Confirmation::AssumedOk {
+            log::warn!("{}", tr!("audio.set_unconfirmed"));
+        }
+    }
+
+    if opt.record.is_none() && opt.volume.is_none() {
+        let (record, volume) = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    let record = cam.get_audio_recording().await?;
+                    // Cameras without a speaker do not have the audioCfg
+                    let volume = cam.get_audio_cfg().await.ok().map(|cfg| cfg.volume);
+                    Ok((record, volume))
+                })
+            })
+            .await
+            .context(tr!("audio.get_failed"))?;
+        let onoff = |value: Option<bool>| match value {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => "unknown",
+        };
+        println!("record: {}", onoff(record));
+        match volume {
+            Some(volume) => println!("volume: {}", volume),
+            None => println!("volume: unknown"),
+        }
+    }
+
+    Ok(())
+}
@@ -5,4 +5,10 @@ use clap::Parser;
 pub struct Opt {
     /// The name of the camera. Must be a name in the config
     pub camera: String,
+    /// Keep the camera connected and print the battery status on every poll
+    #[arg(long)]
+    pub follow: bool,
+    /// Seconds between each poll when following
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
 }
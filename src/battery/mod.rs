@@ -8,13 +8,17 @@
 ///
 /// ```bash
 /// neolink battery --config=config.toml CameraName
+/// # Or print it every 5 minutes until interrupted
+/// neolink battery --config=config.toml CameraName --follow --interval 300
 /// ```
 ///
 use anyhow::{Context, Result};
+use tokio::time::{interval, Duration};
 
 mod cmdline;
 
 use crate::common::NeoReactor;
+use crate::i18n::tr;
 
 pub(crate) use cmdline::Opt;
 
@@ -25,22 +29,39 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
     log::debug!("Battery: Instance aquired");
 
-    let state = camera
-        .run_task(|cam| {
-            Box::pin(async move {
-                cam.battery_info()
-                    .await
-                    .context("Unable to get camera Battery state")
+    // Holding a permit stops an idle disconnect between the polls
+    let _permit = if opt.follow {
+        Some(camera.permit().await?)
+    } else {
+        None
+    };
+    let mut poll = interval(Duration::from_secs(opt.interval));
+    loop {
+        poll.tick().await;
+        let state = match camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.battery_info().await.context(tr!("battery.get_failed")) })
             })
-        })
-        .await?;
+            .await
+        {
+            Ok(state) => state,
+            // A missed poll should not end the monitor
+            Err(e) if opt.follow => {
+                log::warn!("{e:?}");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
-    let ser = String::from_utf8(
-        yaserde::ser::serialize_with_writer(&state, vec![], &Default::default())
-            .expect("Should Ser the struct"),
-    )
-    .expect("Should be UTF8");
-    println!("{}", ser);
+        let ser = String::from_utf8(
+            yaserde::ser::serialize_with_writer(&state, vec![], &Default::default())
+                .expect("Should Ser the struct"),
+        )
+        .expect("Should be UTF8");
+        println!("{}", ser);
 
-    Ok(())
+        if !opt.follow {
+            return Ok(());
+        }
+    }
 }
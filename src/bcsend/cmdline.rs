@@ -0,0 +1,16 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The bc-send command sends any Bc message and prints the reply
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The id of the message such as 199 for the support matrix
+    #[arg(long)]
+    pub msg_id: u32,
+    /// A file with the xml `<body>` of the message, sent without a payload when not given
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub xml: Option<PathBuf>,
+}
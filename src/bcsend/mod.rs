@@ -0,0 +1,67 @@
+///
+/// # Neolink Bc Send
+///
+/// This module handles the bc-send subcommand
+///
+/// It sends a Bc message with any id to the camera and prints the xml of the
+/// reply. This is for exploring the protocol and for quick workarounds of
+/// commands that neolink does not support yet.
+///
+/// The xml is parsed before it is sent so only the elements that neolink
+/// knows are kept, the same goes for the reply.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink bc-send --config=config.toml --msg-id 199 CameraName
+/// neolink bc-send --config=config.toml --msg-id 26 --xml body.xml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the bc-send subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let xml = match opt.xml.as_ref() {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| tr!("bcsend.read_failed", path = format!("{:?}", path)))?,
+        ),
+        None => None,
+    };
+    let msg_id = opt.msg_id;
+
+    let camera = reactor.get(&opt.camera).await?;
+    let reply = camera
+        .run_task(|cam| {
+            let xml = xml.clone();
+            Box::pin(async move {
+                cam.send_raw(msg_id, xml.as_deref())
+                    .await
+                    .context(tr!("bcsend.failed", msg_id = msg_id))
+            })
+        })
+        .await?;
+
+    if reply.response_code != 200 {
+        log::warn!("{}", tr!("bcsend.not_accepted", code = reply.response_code));
+    }
+    if let Some(extension) = reply.extension.as_ref() {
+        println!("{extension}");
+    }
+    if let Some(xml) = reply.xml.as_ref() {
+        println!("{xml}");
+    }
+    if let Some(binary) = reply.binary.as_ref() {
+        log::info!("{}", tr!("bcsend.binary", len = binary.len()));
+    }
+
+    Ok(())
+}
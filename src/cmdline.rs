@@ -1,4 +1,4 @@
-use clap::{crate_authors, crate_version, Parser};
+use clap::{crate_authors, crate_version, Parser, ValueEnum};
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -11,20 +11,60 @@ use std::str::FromStr;
 pub struct Opt {
     #[arg(short, long, global = true, value_parser = PathBuf::from_str)]
     pub config: Option<PathBuf>,
+    /// How to write a failure to stderr, json is a single line for scripts
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+    /// Record the messages received from the cameras into this directory for
+    /// debugging, the captures can be replayed by the tests of neolink_core
+    #[arg(long, global = true, value_parser = PathBuf::from_str)]
+    pub pcap_dir: Option<PathBuf>,
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
 
+/// The format of the error written when neolink fails
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 pub enum Command {
+    #[cfg(feature = "gstreamer")]
     Rtsp(super::rtsp::Opt),
     StatusLight(super::statusled::Opt),
+    Floodlight(super::floodlight::Opt),
     Reboot(super::reboot::Opt),
     Pir(super::pir::Opt),
+    Motion(super::motion::Opt),
+    MdConfig(super::mdconfig::Opt),
+    Mask(super::mask::Opt),
+    Osd(super::osd::Opt),
+    Isp(super::isp::Opt),
+    Encode(super::encode::Opt),
+    Audio(super::audio::Opt),
+    TestNotify(super::testnotify::Opt),
+    Storage(super::storage::Opt),
     Ptz(super::ptz::Opt),
+    Services(super::services::Opt),
+    Pushnoti(super::pushnoti::Opt),
     Talk(super::talk::Opt),
     Mqtt(super::mqtt::Opt),
+    #[cfg(feature = "gstreamer")]
     MqttRtsp(super::mqtt::Opt),
     Image(super::image::Opt),
     Battery(super::battery::Opt),
+    BcSend(super::bcsend::Opt),
+    Info(super::info::Opt),
+    OnvifEvents(super::onvif::Opt),
+    #[cfg(feature = "gstreamer")]
+    Webrtc(super::webrtc::Opt),
+    Hls(super::hls::Opt),
+    Record(super::record::Opt),
+    SnapshotDaemon(super::snapshot::Opt),
+    Timelapse(super::timelapse::Opt),
+    Webhook(super::webhook::Opt),
+    Config(super::configtool::Opt),
+    Discover(super::discover::Opt),
 }
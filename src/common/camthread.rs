@@ -1,11 +1,22 @@
+use anyhow::anyhow;
+use futures::TryFutureExt;
 use std::sync::{Arc, Weak};
 use tokio::{
-    sync::watch::{Receiver as WatchReceiver, Sender as WatchSender},
-    time::{interval, sleep, timeout, Duration, Instant},
+    sync::{
+        watch::{Receiver as WatchReceiver, Sender as WatchSender},
+        Mutex,
+    },
+    time::{interval, interval_at, sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{config::CameraConfig, utils::connect_and_login, AnyResult};
+use super::CameraMetrics;
+use crate::{
+    config::{BatteryPolicyConfig, CameraConfig, KeepAlive},
+    crash, quirks,
+    utils::connect_and_login,
+    AnyResult,
+};
 use neolink_core::bc_protocol::BcCamera;
 
 #[derive(Eq, PartialEq, Copy, Clone)]
@@ -14,11 +25,89 @@ pub(crate) enum NeoCamThreadState {
     Disconnected,
 }
 
+/// What the battery policy turned down while the battery is low
+///
+/// The default is a camera with a good battery or without a policy
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub(crate) struct PowerPolicy {
+    pub(crate) low_battery: bool,
+    /// Serve the subStream to new clients of the mainStream
+    pub(crate) sub_stream: bool,
+    /// Disconnect after this long without a user
+    pub(crate) idle_disconnect: Option<Duration>,
+    pub(crate) pause_prebuffer: bool,
+}
+
+impl PowerPolicy {
+    /// The policy of a battery at `percent`, `was_low` gives the hysteresis
+    pub(crate) fn from_battery(config: &BatteryPolicyConfig, percent: u32, was_low: bool) -> Self {
+        let low_battery = match was_low {
+            true => percent < config.recover,
+            false => percent <= config.low,
+        };
+        if !low_battery {
+            return Self::default();
+        }
+        Self {
+            low_battery,
+            sub_stream: config.sub_stream,
+            idle_disconnect: config
+                .idle_disconnect
+                .then(|| Duration::from_secs(config.idle_timeout)),
+            pause_prebuffer: config.pause_prebuffer,
+        }
+    }
+}
+
+/// The connection of an NVR that is shared by the cameras of its channels
+///
+/// It only lives while one of the channels holds it
+#[derive(Default)]
+pub(crate) struct NvrLink {
+    camera: Mutex<Weak<BcCamera>>,
+}
+
+impl NvrLink {
+    /// The connection to the NVR, made by the first channel that needs it
+    async fn connect(&self, config: &CameraConfig) -> AnyResult<Arc<BcCamera>> {
+        let mut camera = self.camera.lock().await;
+        if let Some(nvr) = camera.upgrade() {
+            return Ok(nvr);
+        }
+        let nvr = Arc::new(connect_and_login(config).await?);
+        *camera = Arc::downgrade(&nvr);
+        Ok(nvr)
+    }
+
+    /// Forget a connection that was lost so the next channel makes a new one
+    async fn lost(&self, nvr: &Arc<BcCamera>) {
+        let mut camera = self.camera.lock().await;
+        if camera.as_ptr() == Arc::as_ptr(nvr) {
+            *camera = Weak::new();
+        }
+    }
+
+    /// Ask the NVR for its channels
+    pub(crate) async fn channels(&self, config: &CameraConfig) -> AnyResult<Vec<u8>> {
+        let nvr = self.connect(config).await?;
+        let channels = nvr.get_channels().await;
+        if channels.is_err() {
+            self.lost(&nvr).await;
+        }
+        Ok(channels?)
+    }
+}
+
 pub(crate) struct NeoCamThread {
     state: WatchReceiver<NeoCamThreadState>,
     config: WatchReceiver<CameraConfig>,
     cancel: CancellationToken,
     camera_watch: WatchSender<Weak<BcCamera>>,
+    metrics: Arc<CameraMetrics>,
+    /// The connection shared with the other channels of the NVR
+    nvr: Option<Arc<NvrLink>>,
+    /// Set once the first connection is made so later ones count as reconnects
+    connected_before: bool,
 }
 
 impl NeoCamThread {
@@ -27,42 +116,86 @@ impl NeoCamThread {
         watch_config_rx: WatchReceiver<CameraConfig>,
         camera_watch_tx: WatchSender<Weak<BcCamera>>,
         cancel: CancellationToken,
+        metrics: Arc<CameraMetrics>,
+        nvr: Option<Arc<NvrLink>>,
     ) -> Self {
         Self {
             state: watch_state_rx,
             config: watch_config_rx,
             cancel,
             camera_watch: camera_watch_tx,
+            metrics,
+            nvr,
+            connected_before: false,
         }
     }
     async fn run_camera(&mut self, config: &CameraConfig) -> AnyResult<()> {
         let name = config.name.clone();
-        let camera = Arc::new(connect_and_login(config).await?);
+        let nvr = match self.nvr.as_ref() {
+            Some(link) => Some(link.connect(config).await?),
+            None => None,
+        };
+        let camera = Arc::new(match nvr.as_ref() {
+            Some(nvr) => nvr.channel(config.channel_id),
+            None => connect_and_login(config).await?,
+        });
+        if self.connected_before {
+            self.metrics.reconnected();
+        }
+        self.connected_before = true;
 
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
         update_camera_time(&camera, &name, config.update_time).await?;
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
 
+        let quirks = match camera.version().await {
+            Ok(version) => {
+                crash::set_camera_model(&name, &version);
+                quirks::set_camera_version(&name, &version)
+            }
+            Err(e) => {
+                log::debug!("{name}: Could not get the camera model: {e:?}");
+                quirks::get(&name)
+            }
+        };
+
         self.camera_watch.send_replace(Arc::downgrade(&camera));
+        self.metrics.set_connected(true);
 
         let cancel_check = self.cancel.clone();
         // Now we wait for a disconnect
-        tokio::select! {
+        let result = tokio::select! {
             _ = cancel_check.cancelled() => {
                 log::debug!("{name}: Camera Cancelled");
                 AnyResult::Ok(())
             }
             v = camera.join() => {
                 log::debug!("{name}: Camera Join: {:?}", v);
-                v?;
-                Ok(())
+                match v {
+                    // Only one channel gets the error, the others see the
+                    // connection close
+                    Ok(()) if nvr.is_some() => Err(anyhow!("The connection to the NVR was closed")),
+                    v => v.map_err(anyhow::Error::from),
+                }
             },
             v = async {
-                let mut interval = interval(Duration::from_secs(5));
+                let keep_alive = match config.keep_alive {
+                    _ if quirks.no_ping => KeepAlive::None,
+                    KeepAlive::Auto if is_battery(&camera, config.channel_id).await => KeepAlive::None,
+                    KeepAlive::Auto => KeepAlive::Linktype,
+                    keep_alive => keep_alive,
+                };
+                log::debug!("{name}: Keep alive with {keep_alive:?}");
+                let mut interval = interval(Duration::from_secs(config.keep_alive_interval));
                 let mut missed_pings = 0;
                 loop {
                     interval.tick().await;
-                    match timeout(Duration::from_secs(5), camera.get_linktype()).await {
+                    let reply = match keep_alive {
+                        KeepAlive::Linktype => timeout(Duration::from_secs(5), camera.get_linktype().map_ok(|_| ())).await,
+                        KeepAlive::Ping => timeout(Duration::from_secs(5), camera.ping()).await,
+                        KeepAlive::Auto | KeepAlive::None => return futures::future::pending().await,
+                    };
+                    match reply {
                         Ok(Ok(_)) => {
                             missed_pings = 0;
                             continue
@@ -87,10 +220,38 @@ impl NeoCamThread {
                     }
                 }
             } => v,
-        }?;
+            v = async {
+                let Some(resync) = config.time_resync.as_ref() else {
+                    return futures::future::pending::<AnyResult<()>>().await;
+                };
+                let period = Duration::from_secs(resync.interval);
+                let mut interval = interval_at(Instant::now() + period, period);
+                loop {
+                    interval.tick().await;
+                    // A camera that cannot tell the time is no reason to drop the connection
+                    if let Err(e) = resync_camera_time(&camera, &name, resync.max_drift).await {
+                        log::warn!("{name}: Could not resync the camera time: {e:?}");
+                    }
+                }
+            } => v,
+        };
+        if let (Err(_), Some(link), Some(nvr)) = (&result, self.nvr.as_ref(), nvr.as_ref()) {
+            link.lost(nvr).await;
+        }
+        result?;
 
-        let _ = camera.logout().await;
-        let _ = camera.shutdown().await;
+        match nvr {
+            // The last channel closes the connection of the NVR
+            Some(nvr) if Arc::strong_count(&nvr) > 1 => {}
+            Some(nvr) => {
+                let _ = nvr.logout().await;
+                let _ = nvr.shutdown().await;
+            }
+            None => {
+                let _ = camera.logout().await;
+                let _ = camera.shutdown().await;
+            }
+        }
 
         Ok(())
     }
@@ -102,6 +263,8 @@ impl NeoCamThread {
     pub(crate) async fn run(&mut self) -> AnyResult<()> {
         const MAX_BACKOFF: Duration = Duration::from_secs(5);
         const MIN_BACKOFF: Duration = Duration::from_millis(50);
+        // A camera that refused the encryption is unlikely to change its mind soon
+        const REFUSED_BACKOFF: Duration = Duration::from_secs(60);
 
         let mut backoff = MIN_BACKOFF;
 
@@ -130,6 +293,7 @@ impl NeoCamThread {
                 }
             };
             self.camera_watch.send_replace(Weak::new());
+            self.metrics.set_connected(false);
 
             if res.is_none() {
                 // If None go back and reload NOW
@@ -168,6 +332,18 @@ impl NeoCamThread {
                             self.cancel.cancel();
                             return Err(e);
                         }
+                        Some(neolink_core::Error::EncryptionNotAllowed { allowed, required }) => {
+                            // Keep trying in case the camera settings change,
+                            // a change to the config tries again at once
+                            log::error!(
+                                "{name}: The camera requires {required} encryption but max_encryption is {allowed}, raise max_encryption in the config to connect"
+                            );
+                            log::info!("{name}: Attempt reconnect in {:?}", REFUSED_BACKOFF);
+                            tokio::select! {
+                                _ = sleep(REFUSED_BACKOFF) => {},
+                                Ok(_) = config_rec.changed() => {},
+                            }
+                        }
                         _ => {
                             // Non fatal
                             log::warn!("{name}: Connection Lost: {:?}", e);
@@ -189,6 +365,25 @@ impl Drop for NeoCamThread {
     }
 }
 
+/// Battery cameras have the battery flag in the support item of their channel
+async fn is_battery(camera: &BcCamera, channel_id: u8) -> bool {
+    match camera.get_support().await {
+        Ok(support) => {
+            support
+                .items
+                .iter()
+                .find(|item| item.chn_id == channel_id as u32)
+                .and_then(|item| item.battery)
+                .unwrap_or(0)
+                > 0
+        }
+        Err(e) => {
+            log::debug!("Could not get the support matrix, assuming no battery: {e:?}");
+            false
+        }
+    }
+}
+
 async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) -> AnyResult<()> {
     let cam_time = camera.get_time().await?;
     let mut update = false;
@@ -224,3 +419,23 @@ async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) ->
     }
     Ok(())
 }
+
+/// Set the camera clock again if it has drifted more than `max_drift` seconds
+///
+/// The camera keeps its own time zone
+async fn resync_camera_time(camera: &BcCamera, name: &str, max_drift: u64) -> AnyResult<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(cam_time) = camera.get_time().await? else {
+        log::warn!("{}: Camera has lost its time, Updating", name);
+        camera.set_time(now).await?;
+        return Ok(());
+    };
+    let drift = (cam_time - now).abs();
+    if drift.whole_seconds() as u64 <= max_drift {
+        log::debug!("{}: Camera time is {} off", name, drift);
+        return Ok(());
+    }
+    log::info!("{}: Camera time is {} off, Updating", name, drift);
+    camera.set_time(now.to_offset(cam_time.offset())).await?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Reloads the config file when it changes
+//!
+//! The file is polled rather than watched with inotify so that it also works
+//! for network shares and docker bind mounts. A config that fails to parse or
+//! validate is logged and the running config is kept.
+use anyhow::Result;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+use super::NeoReactor;
+use crate::{config::Config, i18n::tr};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Editors can write a file in several steps so wait for them to finish
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Watches the config file until dropped
+pub(crate) struct ConfigWatcher {
+    _cancel: DropGuard,
+}
+
+impl ConfigWatcher {
+    pub(crate) fn new(path: PathBuf, reactor: NeoReactor) -> Self {
+        let cancel = CancellationToken::new();
+        let thread_cancel = cancel.clone();
+        tokio::task::spawn(async move {
+            let r = tokio::select! {
+                _ = thread_cancel.cancelled() => Ok(()),
+                v = watch(&path, &reactor) => v,
+            };
+            log::debug!("Config watcher ended: {r:?}");
+        });
+        Self {
+            _cancel: cancel.drop_guard(),
+        }
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+async fn watch(path: &Path, reactor: &NeoReactor) -> Result<()> {
+    let mut last_modified = modified(path).await;
+    let mut wait = interval(POLL_INTERVAL);
+    wait.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        wait.tick().await;
+        if modified(path).await == last_modified {
+            continue;
+        }
+        sleep(SETTLE_TIME).await;
+        last_modified = modified(path).await;
+
+        let config = match Config::from_file(path) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!(
+                    "{}: {e:?}",
+                    tr!("config.reload_failed", path = format!("{:?}", path))
+                );
+                continue;
+            }
+        };
+        if *reactor.config().await?.borrow() == config {
+            continue;
+        }
+        reactor.update_config(config).await?;
+        log::info!("{}", tr!("config.reloaded", path = format!("{:?}", path)));
+    }
+}
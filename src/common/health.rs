@@ -0,0 +1,97 @@
+//! Json health of the cameras
+//!
+//! Served on `/health` using the same [`Metrics`] that the cameras register
+//! for prometheus. It is opt in with the `[health]` config section.
+//!
+//! The same server also has the [`VersionInfo`] on `/version`
+use anyhow::Result;
+use hyper::{Method, StatusCode};
+use serde::Serialize;
+use std::{collections::BTreeMap, time::SystemTime};
+use tokio::sync::watch::Receiver as WatchReceiver;
+
+use super::{http_server, response, Metrics, VersionInfo};
+use crate::config::Config;
+
+#[derive(Serialize)]
+struct HealthV1 {
+    schema: &'static str,
+    cameras: BTreeMap<String, CameraHealth>,
+}
+
+#[derive(Serialize)]
+struct CameraHealth {
+    connected: bool,
+    /// When the last video or audio frame arrived in rfc3339
+    last_frame: Option<String>,
+    /// When the last motion event started in rfc3339
+    last_motion: Option<String>,
+    /// Frame rate of each stream that has been started
+    fps: BTreeMap<String, u32>,
+}
+
+fn rfc3339(time: Option<SystemTime>) -> Option<String> {
+    time.map(|time| humantime::format_rfc3339_seconds(time).to_string())
+}
+
+impl HealthV1 {
+    fn new(metrics: &Metrics) -> Self {
+        let cameras = metrics
+            .cameras()
+            .into_iter()
+            .map(|(name, camera)| {
+                let health = CameraHealth {
+                    connected: camera.connected(),
+                    last_frame: rfc3339(camera.last_frame()),
+                    last_motion: rfc3339(camera.last_motion()),
+                    fps: camera.fps(),
+                };
+                (name, health)
+            })
+            .collect();
+        Self {
+            schema: "neolink.health.v1",
+            cameras,
+        }
+    }
+}
+
+/// Serve the health while the `[health]` section is in the config
+pub(crate) async fn health_server(config: WatchReceiver<Config>, metrics: Metrics) -> Result<()> {
+    http_server(
+        config,
+        "Health",
+        |config| {
+            config
+                .health
+                .as_ref()
+                .map(|health| format!("{}:{}", health.bind_addr, health.bind_port))
+        },
+        move |req| match (req.method(), req.uri().path()) {
+            (&Method::GET, "/health") => match serde_json::to_string(&HealthV1::new(&metrics)) {
+                Ok(body) => response(StatusCode::OK, "application/json", body),
+                Err(e) => {
+                    log::warn!("Failed to serialise the health: {e:?}");
+                    response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "text/plain",
+                        String::new(),
+                    )
+                }
+            },
+            (&Method::GET, "/version") => match serde_json::to_string(&VersionInfo::current()) {
+                Ok(body) => response(StatusCode::OK, "application/json", body),
+                Err(e) => {
+                    log::warn!("Failed to serialise the version: {e:?}");
+                    response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "text/plain",
+                        String::new(),
+                    )
+                }
+            },
+            _ => response(StatusCode::NOT_FOUND, "text/plain", String::new()),
+        },
+    )
+    .await
+}
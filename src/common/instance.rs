@@ -12,13 +12,16 @@ use tokio::{
         mpsc::Sender as MpscSender, oneshot::channel as oneshot, watch::channel as watch,
         watch::Receiver as WatchReceiver,
     },
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{MdState, NeoCamCommand, NeoCamThreadState, Permit, PushNoti, StreamInstance};
+use super::{
+    CameraMetrics, MdState, NeoCamCommand, NeoCamThreadState, Permit, PowerPolicy, PushNoti,
+    StreamInstance,
+};
 use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::{BcCamera, StreamKind};
+use neolink_core::bc_protocol::{BcCamera, Direction, StreamKind};
 
 /// This instance is the primary interface used throughout the app
 ///
@@ -28,6 +31,7 @@ pub(crate) struct NeoInstance {
     camera_watch: WatchReceiver<Weak<BcCamera>>,
     camera_control: MpscSender<NeoCamCommand>,
     cancel: CancellationToken,
+    metrics: Arc<CameraMetrics>,
 }
 
 impl NeoInstance {
@@ -35,11 +39,13 @@ impl NeoInstance {
         camera_watch: WatchReceiver<Weak<BcCamera>>,
         camera_control: MpscSender<NeoCamCommand>,
         cancel: CancellationToken,
+        metrics: Arc<CameraMetrics>,
     ) -> Result<Self> {
         Ok(Self {
             camera_watch,
             camera_control,
             cancel,
+            metrics,
         })
     }
 
@@ -305,6 +311,48 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// When the doorbell button was last pressed
+    ///
+    /// Stays `None` for cameras that are not doorbells
+    pub(crate) async fn visitor(&self) -> Result<WatchReceiver<Option<Instant>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Visitor(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Until when an external trigger is active
+    ///
+    /// This is `None` until the first trigger fires
+    pub(crate) async fn trigger(&self) -> Result<WatchReceiver<Option<Instant>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Trigger(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Mark an external trigger as active for `duration`
+    pub(crate) async fn fire_trigger(&self, duration: Duration) -> Result<()> {
+        self.camera_control
+            .send(NeoCamCommand::Fire(Instant::now() + duration))
+            .await?;
+        Ok(())
+    }
+
+    /// Move the camera in a direction or stop it
+    ///
+    /// All callers share the camera's ptz thread which merges rapid movements
+    /// and only sends them at the configured `ptz_rate`
+    pub(crate) async fn ptz(&self, direction: Direction, speed: f32) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Ptz(direction, speed, instance_tx))
+            .await?;
+        instance_rx.await?
+    }
+
     pub(crate) async fn config(&self) -> Result<WatchReceiver<CameraConfig>> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -317,6 +365,11 @@ impl NeoInstance {
         self.camera_watch.clone()
     }
 
+    /// The counters of this camera that are served on `/metrics`
+    pub(crate) fn metrics(&self) -> Arc<CameraMetrics> {
+        self.metrics.clone()
+    }
+
     pub(crate) async fn connect(&self) -> Result<()> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -342,6 +395,15 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// What the battery policy has turned down on the camera
+    pub(crate) async fn power(&self) -> Result<WatchReceiver<PowerPolicy>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Power(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn permit(&self) -> Result<Permit> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
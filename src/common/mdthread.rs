@@ -15,16 +15,22 @@ use tokio_util::sync::CancellationToken;
 
 use super::NeoInstance;
 use crate::{AnyResult, Result};
-use neolink_core::bc_protocol::MotionStatus;
+use neolink_core::bc_protocol::{MotionDetail, MotionStatus};
 
 pub(crate) enum MdState {
-    Start(Instant),
+    /// Motion is being detected, this is updated on each alarm from the
+    /// camera so the detail can change while it is ongoing
+    Start(Instant, MotionDetail),
     Stop(Instant),
     Unknown,
 }
 
 pub(crate) struct NeoCamMdThread {
     md_watcher: Arc<WatchSender<MdState>>,
+    /// When the doorbell button was last pressed
+    visitor_watcher: Arc<WatchSender<Option<Instant>>>,
+    /// Until when an external trigger is active
+    trigger_watcher: WatchSender<Option<Instant>>,
     md_request_rx: MpscReceiver<MdRequest>,
     cancel: CancellationToken,
     instance: NeoInstance,
@@ -37,8 +43,13 @@ impl NeoCamMdThread {
     ) -> Result<Self> {
         let (md_watcher, _) = watch(MdState::Unknown);
         let md_watcher = Arc::new(md_watcher);
+        let (visitor_watcher, _) = watch(None);
+        let visitor_watcher = Arc::new(visitor_watcher);
+        let (trigger_watcher, _) = watch(None);
         Ok(Self {
             md_watcher,
+            visitor_watcher,
+            trigger_watcher,
             md_request_rx,
             cancel: CancellationToken::new(),
             instance,
@@ -48,6 +59,7 @@ impl NeoCamMdThread {
     pub(crate) async fn run(&mut self) -> Result<()> {
         let thread_cancel = self.cancel.clone();
         let watcher = self.md_watcher.clone();
+        let visitor_watcher = self.visitor_watcher.clone();
         let md_instance = self.instance.clone();
         tokio::select! {
             _ = thread_cancel.cancelled() => {
@@ -61,6 +73,28 @@ impl NeoCamMdThread {
                         } => {
                           let _ = sender.send(self.md_watcher.subscribe());
                         },
+                        MdRequest::Visitor {
+                            sender
+                        } => {
+                          let _ = sender.send(self.visitor_watcher.subscribe());
+                        },
+                        MdRequest::Trigger {
+                            sender
+                        } => {
+                          let _ = sender.send(self.trigger_watcher.subscribe());
+                        },
+                        MdRequest::Fire {
+                            until
+                        } => {
+                            // Overlapping triggers keep the latest end
+                            self.trigger_watcher.send_if_modified(|current| {
+                                let changed = current.map(|current| current < until).unwrap_or(true);
+                                if changed {
+                                    *current = Some(until);
+                                }
+                                changed
+                            });
+                        },
                     }
                 }
                 Ok(())
@@ -69,15 +103,26 @@ impl NeoCamMdThread {
                 loop {
                     let r: AnyResult<()> = md_instance.run_passive_task(|cam| {
                         let watcher = watcher.clone();
+                        let visitor_watcher = visitor_watcher.clone();
                         Box::pin(
                         async move {
                             let mut md = cam.listen_on_motion().await.with_context(|| "Error in getting MD listen_on_motion")?;
                             loop {
                                 let event = md.next_motion().await.with_context(|| "Error in getting MD next_motion")?;
+                                // Presses can come together with a motion change so check every time
+                                if let Some(at) = md.last_visitor() {
+                                    let at = Some(Instant::from(at));
+                                    visitor_watcher.send_if_modified(|last| {
+                                        let changed = *last != at;
+                                        *last = at;
+                                        changed
+                                    });
+                                }
                                 match event {
                                     MotionStatus::Start(at) => {
+                                        let detail = md.motion_detail().clone();
                                         watcher.send_replace(
-                                            MdState::Start(at.into())
+                                            MdState::Start(at.into(), detail)
                                         );
                                     }
                                     MotionStatus::Stop(at) => {
@@ -85,7 +130,7 @@ impl NeoCamMdThread {
                                             MdState::Stop(at.into())
                                         );
                                     }
-                                    MotionStatus::NoChange(_) => {},
+                                    MotionStatus::NoChange(_) | MotionStatus::Visitor(_) => {},
                                 }
                             }
                         }
@@ -111,4 +156,13 @@ pub(crate) enum MdRequest {
     Get {
         sender: OneshotSender<WatchReceiver<MdState>>,
     },
+    Visitor {
+        sender: OneshotSender<WatchReceiver<Option<Instant>>>,
+    },
+    Trigger {
+        sender: OneshotSender<WatchReceiver<Option<Instant>>>,
+    },
+    Fire {
+        until: Instant,
+    },
 }
@@ -0,0 +1,392 @@
+//! Prometheus metrics of the cameras
+//!
+//! Every [`super::NeoCam`] registers a [`CameraMetrics`] with the reactor's
+//! [`Metrics`] which are served in the prometheus text format on `/metrics`.
+//! The server is opt in with the `[metrics]` config section. With a namespace
+//! every metric also carries it as a label so that several instances can be
+//! scraped into one prometheus.
+//!
+//! The same metrics also back the json health, see [`super::health_server`]
+use anyhow::{Context, Result};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch::Receiver as WatchReceiver;
+
+use crate::config::Config;
+use neolink_core::bc_protocol::StreamKind;
+
+/// The counters and gauges of one camera
+///
+/// These are kept when the camera is reloaded so that the counters only ever
+/// increase for the lifetime of neolink
+#[derive(Debug)]
+pub(crate) struct CameraMetrics {
+    frames: AtomicU64,
+    bytes: AtomicU64,
+    /// Video frames dropped because they were corrupt
+    dropped_frames: AtomicU64,
+    /// Frames dropped because they were not read before the stream buffer filled
+    overflowed_frames: AtomicU64,
+    reconnects: AtomicU64,
+    motion_events: AtomicU64,
+    /// `-1` until the battery has been read
+    battery_percent: AtomicI64,
+    rtsp_clients: AtomicI64,
+    /// Video bytes pushed to the rtsp clients
+    rtsp_bytes: AtomicU64,
+    connected: AtomicBool,
+    /// Milliseconds since the unix epoch, `0` if never
+    last_frame: AtomicU64,
+    /// Milliseconds since the unix epoch, `0` if never
+    last_motion: AtomicU64,
+    fps: Mutex<HashMap<StreamKind, u32>>,
+}
+
+impl Default for CameraMetrics {
+    fn default() -> Self {
+        Self {
+            frames: Default::default(),
+            bytes: Default::default(),
+            dropped_frames: Default::default(),
+            overflowed_frames: Default::default(),
+            reconnects: Default::default(),
+            motion_events: Default::default(),
+            battery_percent: AtomicI64::new(-1),
+            rtsp_clients: Default::default(),
+            rtsp_bytes: Default::default(),
+            connected: Default::default(),
+            last_frame: Default::default(),
+            last_motion: Default::default(),
+            fps: Default::default(),
+        }
+    }
+}
+
+impl CameraMetrics {
+    /// A video or audio frame was received from the camera
+    pub(crate) fn frame(&self, bytes: usize) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.last_frame.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// A corrupt video frame was dropped instead of forwarded
+    pub(crate) fn frame_dropped(&self) {
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Frames were dropped because the stream buffer was full
+    pub(crate) fn frames_overflowed(&self, count: u64) {
+        self.overflowed_frames.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn overflowed_frames(&self) -> u64 {
+        self.overflowed_frames.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// The camera connected again after a previous connection was lost
+    pub(crate) fn reconnected(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A motion event started
+    pub(crate) fn motion_event(&self) {
+        self.motion_events.fetch_add(1, Ordering::Relaxed);
+        self.last_motion.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// The frame rate that the camera reported for a stream
+    pub(crate) fn set_fps(&self, stream: StreamKind, fps: u32) {
+        self.fps.lock().unwrap().insert(stream, fps);
+    }
+
+    pub(crate) fn connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn last_frame(&self) -> Option<SystemTime> {
+        from_millis(self.last_frame.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn last_motion(&self) -> Option<SystemTime> {
+        from_millis(self.last_motion.load(Ordering::Relaxed))
+    }
+
+    /// The frame rate of each stream by its name such as `mainStream`
+    pub(crate) fn fps(&self) -> BTreeMap<String, u32> {
+        self.fps
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(stream, fps)| (stream.to_string(), *fps))
+            .collect()
+    }
+
+    pub(crate) fn set_battery(&self, percent: u32) {
+        self.battery_percent
+            .store(percent as i64, Ordering::Relaxed);
+    }
+
+    /// Video was sent to the rtsp clients
+    pub(crate) fn rtsp_sent(&self, bytes: usize) {
+        self.rtsp_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rtsp_bytes(&self) -> u64 {
+        self.rtsp_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Count an rtsp client until the returned guard is dropped
+    pub(crate) fn rtsp_client(self: &Arc<Self>) -> RtspClientGuard {
+        self.rtsp_clients.fetch_add(1, Ordering::Relaxed);
+        RtspClientGuard(self.clone())
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn from_millis(millis: u64) -> Option<SystemTime> {
+    (millis != 0).then(|| UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Counts an rtsp client for as long as it is held
+pub(crate) struct RtspClientGuard(Arc<CameraMetrics>);
+
+impl Drop for RtspClientGuard {
+    fn drop(&mut self) {
+        self.0.rtsp_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Reads one metric of a camera, negative while it is unknown
+pub(crate) type Getter = fn(&CameraMetrics) -> i64;
+
+/// The name, prometheus type, help and getter of every metric
+pub(crate) const FAMILIES: [(&str, &str, &str, Getter); 9] = [
+    (
+        "neolink_frames_received_total",
+        "counter",
+        "Video and audio frames received from the camera",
+        |m| m.frames.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_bytes_received_total",
+        "counter",
+        "Bytes of video and audio received from the camera",
+        |m| m.bytes.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_frames_dropped_total",
+        "counter",
+        "Corrupt video frames dropped instead of being forwarded",
+        |m| m.dropped_frames.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_frames_overflowed_total",
+        "counter",
+        "Frames dropped because they were not read before the stream buffer filled",
+        |m| m.overflowed_frames.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_reconnects_total",
+        "counter",
+        "Times the camera connected again after losing the connection",
+        |m| m.reconnects.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_motion_events_total",
+        "counter",
+        "Motion events started",
+        |m| m.motion_events.load(Ordering::Relaxed) as i64,
+    ),
+    (
+        "neolink_battery_percent",
+        "gauge",
+        "Battery level of the camera",
+        |m| m.battery_percent.load(Ordering::Relaxed),
+    ),
+    (
+        "neolink_rtsp_clients",
+        "gauge",
+        "Connected rtsp clients",
+        |m| m.rtsp_clients.load(Ordering::Relaxed),
+    ),
+    (
+        "neolink_rtsp_bytes_sent_total",
+        "counter",
+        "Bytes of video sent to the rtsp clients",
+        |m| m.rtsp_bytes.load(Ordering::Relaxed) as i64,
+    ),
+];
+
+/// The metrics of all cameras by name
+#[derive(Clone, Default)]
+pub(crate) struct Metrics {
+    cameras: Arc<Mutex<BTreeMap<String, Arc<CameraMetrics>>>>,
+}
+
+impl Metrics {
+    /// Get the metrics of a camera creating them on first use
+    pub(crate) fn register(&self, name: &str) -> Arc<CameraMetrics> {
+        self.cameras
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// The metrics of every registered camera by name
+    pub(crate) fn cameras(&self) -> BTreeMap<String, Arc<CameraMetrics>> {
+        self.cameras.lock().unwrap().clone()
+    }
+
+    /// Render all metrics in the prometheus text format
+    pub(crate) fn render(&self, namespace: Option<&str>) -> String {
+        let cameras = self.cameras.lock().unwrap();
+        let namespace = namespace
+            .map(|namespace| format!("namespace=\"{}\",", label_value(namespace)))
+            .unwrap_or_default();
+        let mut out = String::new();
+        for (name, kind, help, get) in FAMILIES {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (camera, metrics) in cameras.iter() {
+                let value = get(metrics);
+                if value < 0 {
+                    // Unknown, such as the battery of a wired camera
+                    continue;
+                }
+                let camera = label_value(camera);
+                let _ = writeln!(out, "{name}{{{namespace}camera=\"{camera}\"}} {value}");
+            }
+        }
+        out
+    }
+}
+
+/// Escape a prometheus label value
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Serve the metrics while the `[metrics]` section is in the config
+///
+/// They are on `/metrics` and, with a namespace, also on `/{namespace}/metrics`
+pub(crate) async fn metrics_server(config: WatchReceiver<Config>, metrics: Metrics) -> Result<()> {
+    let handler_config = config.clone();
+    http_server(
+        config,
+        "Metrics",
+        |config| {
+            config
+                .metrics
+                .as_ref()
+                .map(|metrics| format!("{}:{}", metrics.bind_addr, metrics.bind_port))
+        },
+        move |req| {
+            let config = handler_config.borrow();
+            let path = req.uri().path();
+            let path = path
+                .strip_prefix(config.path_prefix().as_str())
+                .unwrap_or(path);
+            match (req.method(), path) {
+                (&Method::GET, "/metrics") => response(
+                    StatusCode::OK,
+                    "text/plain; version=0.0.4",
+                    metrics.render(config.namespace()),
+                ),
+                _ => response(StatusCode::NOT_FOUND, "text/plain", String::new()),
+            }
+        },
+    )
+    .await
+}
+
+/// Run a http server on the address from `bind` for as long as it is set
+///
+/// The server is restarted whenever the address changes
+pub(crate) async fn http_server<B, H>(
+    mut config: WatchReceiver<Config>,
+    what: &'static str,
+    bind: B,
+    handler: H,
+) -> Result<()>
+where
+    B: Fn(&Config) -> Option<String>,
+    H: Fn(&Request<Body>) -> Response<Body> + Clone + Send + Sync + 'static,
+{
+    loop {
+        let addr = bind(&config.borrow_and_update());
+        tokio::select! {
+            v = config.wait_for(|config| bind(config) != addr) => {
+                v?;
+            }
+            v = serve(addr.clone(), what, handler.clone()) => {
+                // Keep trying with the same address until the config is fixed
+                log::warn!("{what} server stopped: {v:?}");
+                config.wait_for(|config| bind(config) != addr).await?;
+            }
+        }
+    }
+}
+
+async fn serve<H>(addr: Option<String>, what: &'static str, handler: H) -> Result<()>
+where
+    H: Fn(&Request<Body>) -> Response<Body> + Clone + Send + Sync + 'static,
+{
+    let Some(addr) = addr else {
+        return futures::future::pending().await;
+    };
+    let addr: SocketAddr = addr
+        .parse()
+        .with_context(|| format!("Invalid {what} bind address"))?;
+
+    let make_svc = make_service_fn(move |_conn| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handler(&req);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    log::info!("{what} server listening on http://{addr}");
+    Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind {what} server to {addr}"))?
+        .serve(make_svc)
+        .await?;
+    Ok(())
+}
+
+pub(crate) fn response(status: StatusCode, content_type: &str, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .expect("Response should be valid")
+}
@@ -1,17 +1,39 @@
 mod camthread;
+mod configwatch;
+mod health;
 mod instance;
 mod mdthread;
+mod metrics;
 mod neocam;
+#[cfg(feature = "otel")]
+mod otel;
+mod prebuffer;
+mod ptzthread;
 mod pushnoti;
 mod reactor;
 mod streamthread;
+mod trace;
+mod triggers;
+mod update;
 mod usecounter;
+mod version;
 
 pub(crate) use camthread::*;
+pub(crate) use configwatch::*;
+pub(crate) use health::*;
 pub(crate) use instance::*;
 pub(crate) use mdthread::*;
+pub(crate) use metrics::*;
 pub(crate) use neocam::*;
+#[cfg(feature = "otel")]
+pub(crate) use otel::*;
+pub(crate) use prebuffer::*;
+pub(crate) use ptzthread::*;
 pub(crate) use pushnoti::*;
 pub(crate) use reactor::*;
 pub(crate) use streamthread::*;
+pub(crate) use trace::*;
+pub(crate) use triggers::*;
+pub(crate) use update::*;
 pub(crate) use usecounter::*;
+pub(crate) use version::*;
@@ -6,7 +6,7 @@
 //!    Clonable interface to share amongst threadsanyhow::anyhow;
 use anyhow::Context;
 use futures::{stream::StreamExt, TryFutureExt};
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 use tokio::{
     sync::{
         mpsc::{channel as mpsc, Sender as MpscSender},
@@ -14,17 +14,18 @@ use tokio::{
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
     task::JoinSet,
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
 use super::{
-    MdRequest, MdState, NeoCamMdThread, NeoCamStreamThread, NeoCamThread, NeoCamThreadState,
-    NeoInstance, Permit, PnRequest, PushNoti, StreamInstance, StreamRequest, UseCounter,
+    camera_span, in_span, CameraMetrics, MdRequest, MdState, NeoCamMdThread, NeoCamPtzThread,
+    NeoCamStreamThread, NeoCamThread, NeoCamThreadState, NeoInstance, NvrLink, Permit, PnRequest,
+    PowerPolicy, PtzRequest, PushNoti, StreamInstance, StreamRequest, UseCounter,
 };
 use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::{BcCamera, StreamKind};
+use neolink_core::bc_protocol::{BcCamera, Direction, StreamKind};
 
 #[allow(dead_code)]
 pub(crate) enum NeoCamCommand {
@@ -35,12 +36,17 @@ pub(crate) enum NeoCamCommand {
     LowStream(OneshotSender<Option<StreamInstance>>),
     Streams(OneshotSender<Vec<StreamInstance>>),
     Motion(OneshotSender<WatchReceiver<MdState>>),
+    Visitor(OneshotSender<WatchReceiver<Option<Instant>>>),
+    Trigger(OneshotSender<WatchReceiver<Option<Instant>>>),
+    Fire(Instant),
     Config(OneshotSender<WatchReceiver<CameraConfig>>),
     Disconnect(OneshotSender<()>),
     Connect(OneshotSender<()>),
     State(OneshotSender<NeoCamThreadState>),
+    Power(OneshotSender<WatchReceiver<PowerPolicy>>),
     GetPermit(OneshotSender<Permit>),
     PushNoti(OneshotSender<WatchReceiver<Option<PushNoti>>>),
+    Ptz(Direction, f32, OneshotSender<Result<()>>),
 }
 /// The underlying camera binding
 pub(crate) struct NeoCam {
@@ -48,6 +54,7 @@ pub(crate) struct NeoCam {
     config_watch: WatchSender<CameraConfig>,
     commander: MpscSender<NeoCamCommand>,
     camera_watch: WatchReceiver<Weak<BcCamera>>,
+    metrics: Arc<CameraMetrics>,
     set: JoinSet<AnyResult<()>>,
 }
 
@@ -55,13 +62,19 @@ impl NeoCam {
     pub(crate) async fn new(
         config: CameraConfig,
         pn_request_tx: MpscSender<PnRequest>,
+        metrics: Arc<CameraMetrics>,
+        nvr: Option<Arc<NvrLink>>,
     ) -> Result<NeoCam> {
         let (commander_tx, commander_rx) = mpsc(100);
         let (watch_config_tx, watch_config_rx) = watch(config.clone());
         let (camera_watch_tx, camera_watch_rx) = watch(Weak::new());
         let (stream_request_tx, stream_request_rx) = mpsc(100);
         let (md_request_tx, md_request_rx) = mpsc(100);
+        let (ptz_request_tx, ptz_request_rx) = mpsc(100);
         let (state_tx, state_rx) = watch(NeoCamThreadState::Connected);
+        let (power_tx, _) = watch(PowerPolicy::default());
+        let power_tx = Arc::new(power_tx);
+        let (battery_tx, battery_rx) = watch(None);
 
         let set = JoinSet::new();
         let users = UseCounter::new().await;
@@ -71,6 +84,7 @@ impl NeoCam {
             config_watch: watch_config_tx,
             commander: commander_tx.clone(),
             camera_watch: camera_watch_rx.clone(),
+            metrics: metrics.clone(),
             set,
         };
 
@@ -79,13 +93,16 @@ impl NeoCam {
         //
         // This thread must be started first so that we can begin creating instances for the
         // other threads
+        let span = camera_span(&config.name);
         let sender_cancel = me.cancel.clone();
         let mut commander_rx = ReceiverStream::new(commander_rx);
         let strict = config.strict;
         let thread_commander_tx = commander_tx.clone();
         let thread_watch_config_rx = watch_config_rx.clone();
         let thread_pn_request_tx = pn_request_tx.clone();
-        me.set.spawn(async move {
+        let thread_metrics = metrics.clone();
+        let thread_power_tx = power_tx.clone();
+        me.set.spawn(in_span(span.clone(), async move {
             let thread_cancel = sender_cancel.clone();
             let res = tokio::select! {
                 _ = sender_cancel.cancelled() => {
@@ -105,6 +122,7 @@ impl NeoCam {
                                     camera_watch_rx.clone(),
                                     thread_commander_tx.clone(),
                                     thread_cancel.clone(),
+                                    thread_metrics.clone(),
                                 );
                                 let _ = result.send(instance);
                             }
@@ -145,6 +163,27 @@ impl NeoCam {
                                     }
                                 ).await?;
                             },
+                            NeoCamCommand::Visitor(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Visitor {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Trigger(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Trigger {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Fire(until) => {
+                                md_request_tx.send(
+                                    MdRequest::Fire {
+                                        until,
+                                    }
+                                ).await?;
+                            },
                             NeoCamCommand::Config(sender) => {
                                 let _ = sender.send(thread_watch_config_rx.clone());
                             },
@@ -165,12 +204,26 @@ impl NeoCam {
                             NeoCamCommand::State(sender) => {
                                 let _ = sender.send(*state_tx.borrow());
                             }
+                            NeoCamCommand::Power(sender) => {
+                                let _ = sender.send(thread_power_tx.subscribe());
+                            }
                             NeoCamCommand::GetPermit(sender) => {
                                 let _ = sender.send(users.create_activated().await?);
                             }
                             NeoCamCommand::PushNoti(sender) => {
+                                let account = thread_watch_config_rx.borrow().push_account.clone();
                                 thread_pn_request_tx.send(
                                     PnRequest::Get {
+                                        account,
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Ptz(direction, speed, sender) => {
+                                ptz_request_tx.send(
+                                    PtzRequest::Move {
+                                        direction,
+                                        speed,
                                         sender,
                                     }
                                 ).await?;
@@ -186,7 +239,7 @@ impl NeoCam {
             };
             log::debug!("Control thread terminated");
             res
-        });
+        }));
 
         // This gets the first instance which we use for making the other threads
         let (instance_tx, instance_rx) = oneshot();
@@ -204,19 +257,21 @@ impl NeoCam {
             thread_watch_config_rx,
             camera_watch_tx,
             me.cancel.clone(),
+            metrics.clone(),
+            nvr,
         )
         .await;
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             let v = cam_thread.run().await;
             log::debug!("Camera MAIN thread ended; {:?}", v);
             v
-        });
+        }));
 
         // This thread maintains the streams
         let stream_instance = instance.subscribe().await?;
         let stream_cancel = me.cancel.clone();
         let mut stream_thread = NeoCamStreamThread::new(stream_request_rx, stream_instance).await?;
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select! {
                 _ = stream_cancel.cancelled() => AnyResult::Ok(()),
                 v = stream_thread.run() => {
@@ -224,13 +279,13 @@ impl NeoCam {
                     v
                 },
             }
-        });
+        }));
 
         // This thread monitors the motion
         let md_instance = instance.subscribe().await?;
         let md_cancel = me.cancel.clone();
         let mut md_thread = NeoCamMdThread::new(md_request_rx, md_instance).await?;
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select! {
                 _ = md_cancel.cancelled() => AnyResult::Ok(()),
                 v = md_thread.run() => {
@@ -238,13 +293,27 @@ impl NeoCam {
                     v
                 },
             }
-        });
+        }));
+
+        // This thread sends the ptz movements
+        let ptz_instance = instance.subscribe().await?;
+        let ptz_cancel = me.cancel.clone();
+        let mut ptz_thread = NeoCamPtzThread::new(ptz_request_rx, ptz_instance).await?;
+        me.set.spawn(in_span(span.clone(), async move {
+            tokio::select! {
+                _ = ptz_cancel.cancelled() => AnyResult::Ok(()),
+                v = ptz_thread.run() => {
+                    log::debug!("PTZ thread ended; {:?}", v);
+                    v
+                },
+            }
+        }));
 
         // This thread just does a one time report on camera info
         let report_instance = instance.subscribe().await?;
         let report_cancel = me.cancel.clone();
         let report_name = config.name.clone();
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select! {
                 _ = report_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -273,13 +342,13 @@ impl NeoCam {
                     Ok(())
                 } => v
             }
-        });
+        }));
 
         // Handles push notifications
         let pn_root_instance = instance.subscribe().await?;
         let pn_cancel = me.cancel.clone();
         let thread_pn_request_tx = pn_request_tx.clone();
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select!{
                 _ = pn_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -292,8 +361,9 @@ impl NeoCam {
 
                         // Activate it
                         let pn_instance = pn_root_instance.subscribe().await?;
+                        let account = config_rx.borrow().push_account.clone();
                         let (tx, rx) = oneshot();
-                        thread_pn_request_tx.send(PnRequest::Activate{sender: tx, instance: pn_instance}).await?;
+                        thread_pn_request_tx.send(PnRequest::Activate{account, sender: tx, instance: pn_instance}).await?;
                         rx.await??;
 
                         let pn_permit_instance = pn_root_instance.subscribe().await?;
@@ -325,12 +395,80 @@ impl NeoCam {
                     v
                 },
             }
-        });
+        }));
+
+        // Wakes the camera on a push notification or doorbell press
+        //
+        // The trigger is fired so that the rtsp streams and recordings carry on
+        // for the window without a client
+        let wake_instance = instance.subscribe().await?;
+        let wake_cancel = me.cancel.clone();
+        let wake_name = config.name.clone();
+        me.set.spawn(in_span(span.clone(), async move {
+            tokio::select!{
+                _ = wake_cancel.cancelled() => {
+                    AnyResult::Ok(())
+                },
+                v = async {
+                    let config_rx = wake_instance.config().await?;
+                    let wake_on_push = |config: &CameraConfig| config.pause.on_motion && config.pause.wake_on_push;
+                    loop {
+                        // Wait for the green light
+                        config_rx.clone().wait_for(wake_on_push).await?;
+
+                        let mut red_config_rx = config_rx.clone();
+                        let r = tokio::select! {
+                            v = async {
+                                let mut pn = wake_instance.push_notifications().await?;
+                                let mut visitor = wake_instance.visitor().await?;
+                                let _ = visitor.borrow_and_update();
+                                let mut prev_noti = None;
+                                loop {
+                                    // Some for a push and None for a doorbell press
+                                    let noti = tokio::select! {
+                                        v = pn.wait_for(|noti| noti != &prev_noti && noti.is_some()) => Some(v?.clone()),
+                                        v = visitor.changed() => {
+                                            v?;
+                                            None
+                                        }
+                                    };
+                                    match noti {
+                                        Some(noti) => prev_noti = noti,
+                                        None if visitor.borrow_and_update().is_none() => continue,
+                                        None => {}
+                                    }
+                                    let window = Duration::from_secs(config_rx.borrow().pause.wake_window);
+                                    log::info!("{wake_name}: Woken for {window:?}");
+                                    wake_instance.fire_trigger(window).await?;
+                                    let permit = wake_instance.permit().await?;
+                                    tokio::task::spawn(async move {
+                                        sleep(window).await;
+                                        drop(permit);
+                                    });
+                                }
+                            } => v,
+                            // Continue loop on Red light
+                            v = red_config_rx.wait_for(|config| !wake_on_push(config)).map_ok(|_| ()) => {
+                                v?;
+                                AnyResult::Ok(())
+                            },
+                        };
+                        if r.is_err() {
+                            break r;
+                        }
+                    }?;
+                    AnyResult::Ok(())
+                } => {
+                    log::debug!("Wake on push thread ended; {:?}", v);
+                    v
+                },
+            }
+        }));
 
         // MD permits
         let md_permit_instance = instance.subscribe().await?;
         let md_permit_cancel = me.cancel.clone();
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select! {
                 _ = md_permit_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -338,7 +476,7 @@ impl NeoCam {
                 v = async {
                     let mut md = md_permit_instance.motion().await.with_context(|| "Unable to acquire motion watcher")?;
                     loop{
-                        md.wait_for(|md| matches!(md, MdState::Start(_))).await.with_context(|| "MD Watcher lost")?;
+                        md.wait_for(|md| matches!(md, MdState::Start(..))).await.with_context(|| "MD Watcher lost")?;
                         let _permit = md_permit_instance.permit().await.with_context(|| "Unuable to acquire motion permit")?;
                         md.wait_for(|md| matches!(md, MdState::Stop(_))).await.with_context(|| "MD Watcher lost")?;
                         // Try waiting for 30s
@@ -346,7 +484,7 @@ impl NeoCam {
                         // loop early to reaquire the permit
                         tokio::select!{
                             _ = sleep(Duration::from_secs(30)) => {},
-                            v = md.wait_for(|md| matches!(md, MdState::Start(_))) => {v.with_context(|| "MD Watcher lost")?;},
+                            v = md.wait_for(|md| matches!(md, MdState::Start(..))) => {v.with_context(|| "MD Watcher lost")?;},
                         }
                     }
                 } => {
@@ -354,7 +492,99 @@ impl NeoCam {
                     v
                 },
             }
-        });
+        }));
+
+        // Metrics of the motion events and battery
+        let metrics_instance = instance.subscribe().await?;
+        let metrics_cancel = me.cancel.clone();
+        me.set.spawn(in_span(span.clone(), async move {
+            tokio::select! {
+                _ = metrics_cancel.cancelled() => {
+                    AnyResult::Ok(())
+                },
+                v = async {
+                    let mut md = metrics_instance.motion().await.with_context(|| "Unable to acquire motion watcher")?;
+                    loop{
+                        md.wait_for(|md| matches!(md, MdState::Start(..))).await.with_context(|| "MD Watcher lost")?;
+                        metrics.motion_event();
+                        md.wait_for(|md| matches!(md, MdState::Stop(_))).await.with_context(|| "MD Watcher lost")?;
+                    }
+                } => v,
+                v = async {
+                    let config_rx = metrics_instance.config().await?;
+                    // Passive so that reading the battery never keeps the camera awake
+                    loop {
+                        let info = metrics_instance.run_passive_task(|cam| Box::pin(async move {
+                            Ok(cam.battery_info().await?)
+                        })).await;
+                        match info {
+                            Ok(info) => {
+                                metrics.set_battery(info.battery_percent);
+                                battery_tx.send_replace(Some(info.battery_percent));
+                            }
+                            Err(e) => match e.downcast::<neolink_core::Error>() {
+                                Ok(neolink_core::Error::CameraServiceUnavaliable(_))
+                                | Ok(neolink_core::Error::MissingAbility { .. })
+                                | Ok(neolink_core::Error::UnintelligibleReply { .. }) => {
+                                    // No battery
+                                    futures::future::pending::<()>().await;
+                                }
+                                Ok(e) => break AnyResult::<()>::Err(e.into()),
+                                Err(e) => break Err(e),
+                            },
+                        }
+                        let period = config_rx.borrow().battery_policy.as_ref().map(|policy| policy.interval).unwrap_or(60);
+                        sleep(Duration::from_secs(period)).await;
+                    }
+                } => {
+                    log::debug!("Battery metrics thread ended; {:?}", v);
+                    v
+                },
+            }
+        }));
+
+        // This thread picks what to turn down while the battery is low
+        let power_instance = instance.subscribe().await?;
+        let power_cancel = me.cancel.clone();
+        let power_name = config.name.clone();
+        let mut battery_rx = battery_rx;
+        me.set.spawn(in_span(span.clone(), async move {
+            tokio::select! {
+                _ = power_cancel.cancelled() => {
+                    AnyResult::Ok(())
+                },
+                v = async {
+                    let mut config_rx = power_instance.config().await?;
+                    loop {
+                        let policy = config_rx.borrow_and_update().battery_policy.clone();
+                        let percent = *battery_rx.borrow_and_update();
+                        let was_low = power_tx.borrow().low_battery;
+                        let power = match (policy, percent) {
+                            (Some(policy), Some(percent)) => PowerPolicy::from_battery(&policy, percent, was_low),
+                            _ => PowerPolicy::default(),
+                        };
+                        if power.low_battery != was_low {
+                            match power.low_battery {
+                                true => log::info!("{power_name}: Battery is low, saving power"),
+                                false => log::info!("{power_name}: Battery recovered"),
+                            }
+                        }
+                        power_tx.send_if_modified(|current| {
+                            let changed = *current != power;
+                            *current = power;
+                            changed
+                        });
+                        tokio::select! {
+                            v = config_rx.changed() => v?,
+                            v = battery_rx.changed() => v?,
+                        }
+                    }
+                } => {
+                    log::debug!("Battery policy thread ended; {:?}", v);
+                    v
+                },
+            }
+        }));
 
         // This thread will apply battery saving by disconnecting the camera when there are no
         // active permits.
@@ -364,20 +594,21 @@ impl NeoCam {
         let connect_instance = instance.subscribe().await?;
         let connect_cancel = me.cancel.clone();
         let connect_name = config.name.clone();
-        me.set.spawn(async move {
+        me.set.spawn(in_span(span.clone(), async move {
             tokio::select!{
                 _ = connect_cancel.cancelled() => {
                     AnyResult::Ok(())
                 },
                 v = async {
-                    let mut config_rx = connect_instance.config().await?;
+                    let config_rx = connect_instance.config().await?;
+                    let power_rx = connect_instance.power().await?;
                     loop {
                         // Wait for the green light
-                        config_rx.wait_for(|config| config.idle_disconnect).await?;
+                        wait_idle_disconnect(config_rx.clone(), power_rx.clone(), true).await?;
 
                         let r = tokio::select!{
                             // Wait for red light
-                            v = config_rx.wait_for(|config| !config.idle_disconnect).map_ok(|_| ()) => {
+                            v = wait_idle_disconnect(config_rx.clone(), power_rx.clone(), false) => {
                                 v?;
                                 connect_instance.connect().await?; // Ensure we are online now that we are not idle_disconnect
                                 AnyResult::Ok(())
@@ -392,9 +623,10 @@ impl NeoCam {
                                     connect_instance.connect().await?;
                                     permit.dropped_users().await?;
                                     log::debug!("{connect_name}: Idle Wait");
-                                    // Wait 30s or if we hit another use then go back and wait again
+                                    // Wait 30s, or less on a low battery, or if we hit another use then go back and wait again
+                                    let idle = power_rx.borrow().idle_disconnect.unwrap_or(Duration::from_secs(30));
                                     tokio::select! {
-                                        _ = sleep(Duration::from_secs(30)) => {},
+                                        _ = sleep(idle) => {},
                                         _ = permit.aquired_users() => continue,
                                     };
                                     log::debug!("{connect_name}: Idle");
@@ -412,7 +644,7 @@ impl NeoCam {
                     v
                 },
             }
-        });
+        }));
 
         Ok(me)
     }
@@ -422,6 +654,7 @@ impl NeoCam {
             self.camera_watch.clone(),
             self.commander.clone(),
             self.cancel.clone(),
+            self.metrics.clone(),
         )
     }
 
@@ -431,6 +664,39 @@ impl NeoCam {
     }
 }
 
+/// Wait until idle disconnect is `on`, from the config or the battery policy
+async fn wait_idle_disconnect(
+    mut config_rx: WatchReceiver<CameraConfig>,
+    mut power_rx: WatchReceiver<PowerPolicy>,
+    on: bool,
+) -> AnyResult<()> {
+    loop {
+        let idle_disconnect = config_rx.borrow_and_update().idle_disconnect
+            || power_rx.borrow_and_update().idle_disconnect.is_some();
+        if idle_disconnect == on {
+            return Ok(());
+        }
+        tokio::select! {
+            v = config_rx.changed() => v?,
+            v = power_rx.changed() => v?,
+        }
+    }
+}
+
+impl NeoCam {
+    /// Hang up and wait for the camera threads to end
+    ///
+    /// The camera thread logs out as it ends so once this returns the
+    /// session on the camera has been released
+    pub(crate) async fn shutdown(&mut self) {
+        log::trace!("Shutdown NeoCam");
+        let _ = self.commander.send(NeoCamCommand::HangUp).await;
+        self.cancel.cancel();
+        while self.set.join_next().await.is_some() {}
+        log::trace!("Shutdown NeoCam complete");
+    }
+}
+
 impl Drop for NeoCam {
     fn drop(&mut self) {
         log::trace!("Drop NeoCam");
@@ -0,0 +1,121 @@
+//! OpenTelemetry export of the metrics and spans
+//!
+//! With the `otel` feature and an `[otel]` config section the camera
+//! [`super::FAMILIES`], the spans of the camera threads and the core `tracing`
+//! spans of every bc subscription are sent to an OTLP collector over grpc.
+//! Each data point carries the `camera` attribute, each span the `camera`
+//! field. The namespace is sent as the `service.namespace` of the resource.
+//!
+//! The section is only read at start, changing it needs a restart
+use anyhow::{Context, Result};
+use opentelemetry::{metrics::MeterProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, Resource};
+use std::time::Duration;
+use tokio::sync::watch::Receiver as WatchReceiver;
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::{Metrics, FAMILIES};
+use crate::config::Config;
+
+/// Export to the collector of the `[otel]` section until cancelled
+pub(crate) async fn otel_exporter(config: WatchReceiver<Config>, metrics: Metrics) -> Result<()> {
+    let Some(otel) = config.borrow().otel.clone() else {
+        return Ok(());
+    };
+    let mut attributes = vec![KeyValue::new("service.name", otel.service_name.clone())];
+    if let Some(namespace) = config.borrow().namespace() {
+        attributes.push(KeyValue::new("service.namespace", namespace.to_string()));
+    }
+    let resource = Resource::new(attributes);
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&otel.endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource.clone()))
+        .install_batch(runtime::Tokio)
+        .context("Failed to start the OpenTelemetry tracer")?;
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    if tracing::subscriber::set_global_default(subscriber).is_err() {
+        // Most likely the tokio console
+        log::warn!(
+            "OpenTelemetry: Another tracing subscriber is installed, spans are not exported"
+        );
+    }
+
+    let provider = opentelemetry_otlp::new_pipeline()
+        .metrics(runtime::Tokio)
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&otel.endpoint),
+        )
+        .with_resource(resource)
+        .with_period(Duration::from_secs(otel.interval.max(1)))
+        .build()
+        .context("Failed to start the OpenTelemetry metrics")?;
+    let meter = provider.meter("neolink");
+
+    // The instruments stop reporting once dropped
+    let mut counters = vec![];
+    let mut gauges = vec![];
+    for (name, kind, help, get) in FAMILIES {
+        let metrics = metrics.clone();
+        let observe = move || {
+            metrics
+                .cameras()
+                .into_iter()
+                .map(|(camera, camera_metrics)| (get(&camera_metrics), camera))
+                // Negative values are unknown, such as the battery of a wired camera
+                .filter(|(value, _)| *value >= 0)
+                .collect::<Vec<_>>()
+        };
+        if kind == "counter" {
+            // OTLP counters have no `_total`, the prometheus exporters add it back
+            counters.push(
+                meter
+                    .u64_observable_counter(name.trim_end_matches("_total"))
+                    .with_description(help)
+                    .with_callback(move |observer| {
+                        for (value, camera) in observe() {
+                            observer.observe(value as u64, &[KeyValue::new("camera", camera)]);
+                        }
+                    })
+                    .init(),
+            );
+        } else {
+            gauges.push(
+                meter
+                    .i64_observable_gauge(name)
+                    .with_description(help)
+                    .with_callback(move |observer| {
+                        for (value, camera) in observe() {
+                            observer.observe(value, &[KeyValue::new("camera", camera)]);
+                        }
+                    })
+                    .init(),
+            );
+        }
+    }
+
+    log::info!("OpenTelemetry: Exporting to {}", otel.endpoint);
+    let _guard = OtelGuard(provider);
+    futures::future::pending().await
+}
+
+/// Flushes the last metrics and spans when the exporter stops
+struct OtelGuard(opentelemetry_sdk::metrics::MeterProvider);
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            log::debug!("OpenTelemetry: Failed to flush the metrics: {e:?}");
+        }
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
@@ -0,0 +1,281 @@
+//! Keeps the last seconds of a stream's video so that a clip can start
+//! before the motion or push notification that triggered it
+//!
+//! The frames are grouped by keyframe so that what is returned can always be
+//! decoded. The groups are either kept in memory or written to a directory,
+//! which can be a tmpfs, so that long buffers of high bitrate streams do not
+//! take up the process memory. The files are written from a blocking thread
+//! so a slow disk never holds up the stream
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    fs::{self, File},
+    future::Future,
+    io::Write,
+    path::PathBuf,
+};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        oneshot::{channel as oneshot, Receiver as OneshotReceiver, Sender as OneshotSender},
+    },
+    time::Duration,
+};
+
+use super::StampedData;
+use crate::config::PrebufferConfig;
+
+pub(crate) struct Prebuffer {
+    duration: Duration,
+    writer: Option<DiskWriter>,
+    groups: VecDeque<Group>,
+}
+
+/// A keyframe and the frames after it
+struct Group {
+    start: Duration,
+    frames: Frames,
+}
+
+enum Frames {
+    Memory(Vec<StampedData>),
+    Disk(DiskFile),
+}
+
+impl Prebuffer {
+    pub(crate) fn new(config: &PrebufferConfig, camera: &str, kind: StreamKind) -> Result<Self> {
+        let writer = match config.dir.as_ref() {
+            Some(dir) => {
+                let dir = dir.join(camera).join(kind.to_string());
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                // Left over from a previous run
+                for entry in fs::read_dir(&dir)?.flatten() {
+                    let _ = fs::remove_file(entry.path());
+                }
+                Some(DiskWriter::new(dir))
+            }
+            None => None,
+        };
+        Ok(Self {
+            duration: Duration::from_secs(config.seconds),
+            writer,
+            groups: Default::default(),
+        })
+    }
+
+    /// Add a video frame, the frames before the first keyframe are skipped
+    ///
+    /// This never waits on the disk, the frames are written in the background
+    pub(crate) fn push(&mut self, frame: &StampedData) -> Result<()> {
+        if self
+            .groups
+            .back()
+            .is_some_and(|group| frame.ts < group.start)
+        {
+            // The stream restarted
+            self.clear();
+        }
+        if frame.keyframe {
+            let frames = match self.writer.as_ref() {
+                Some(writer) => Frames::Disk(writer.create(frame.ts)?),
+                None => Frames::Memory(vec![]),
+            };
+            self.groups.push_back(Group {
+                start: frame.ts,
+                frames,
+            });
+        }
+        let Some(group) = self.groups.back_mut() else {
+            return Ok(());
+        };
+        match &mut group.frames {
+            Frames::Memory(frames) => frames.push(frame.clone()),
+            Frames::Disk(file) => file.append(frame)?,
+        }
+
+        // Keep the newest keyframe that still covers the duration
+        let drop_time = frame.ts.saturating_sub(self.duration);
+        while self.groups.len() > 1 && self.groups[1].start <= drop_time {
+            self.groups.pop_front();
+        }
+        Ok(())
+    }
+
+    /// The buffered frames, oldest first and starting on a keyframe
+    ///
+    /// The frames are taken when this is called so the prebuffer does not
+    /// need to stay locked while the returned future reads them from disk
+    pub(crate) fn frames(&self) -> impl Future<Output = Result<Vec<StampedData>>> + Send {
+        let mut all = vec![];
+        let mut paths = vec![];
+        for group in self.groups.iter() {
+            match &group.frames {
+                Frames::Memory(frames) => all.extend(frames.iter().cloned()),
+                Frames::Disk(file) => paths.push(file.path.clone()),
+            }
+        }
+        let read = match self.writer.as_ref() {
+            Some(writer) if !paths.is_empty() => Some(writer.read(paths)),
+            _ => None,
+        };
+        async move {
+            if let Some(read) = read {
+                let files = read?
+                    .await
+                    .map_err(|_| anyhow!("The prebuffer writer stopped"))??;
+                for data in files {
+                    decode(&data, &mut all)?;
+                }
+            }
+            Ok(all)
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.groups.clear();
+    }
+}
+
+enum DiskOp {
+    Create(PathBuf),
+    Append(PathBuf, StampedData),
+    Remove(PathBuf),
+    Read(Vec<PathBuf>, OneshotSender<Result<Vec<Vec<u8>>>>),
+}
+
+/// Does the file io of a prebuffer on a blocking thread
+///
+/// The operations are done in order so a read sees all the frames that were
+/// pushed before it. The thread ends once the prebuffer and its files are
+/// dropped
+#[derive(Clone)]
+struct DiskWriter {
+    dir: PathBuf,
+    tx: UnboundedSender<DiskOp>,
+}
+
+impl DiskWriter {
+    fn new(dir: PathBuf) -> Self {
+        let (tx, mut rx) = unbounded_channel();
+        let thread_dir = dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut files: HashMap<PathBuf, File> = Default::default();
+            while let Some(op) = rx.blocking_recv() {
+                match op {
+                    DiskOp::Create(path) => match File::create(&path) {
+                        Ok(file) => {
+                            files.insert(path, file);
+                        }
+                        Err(e) => log::warn!("Failed to create {}: {e:?}", path.display()),
+                    },
+                    DiskOp::Append(path, frame) => {
+                        // A file that failed to create was already reported
+                        if let Some(file) = files.get_mut(&path) {
+                            if let Err(e) = file.write_all(&encode(&frame)) {
+                                log::warn!("Failed to prebuffer to {}: {e:?}", path.display());
+                                files.remove(&path);
+                            }
+                        }
+                    }
+                    DiskOp::Remove(path) => {
+                        files.remove(&path);
+                        let _ = fs::remove_file(&path);
+                    }
+                    DiskOp::Read(paths, reply) => {
+                        let _ = reply.send(
+                            paths
+                                .iter()
+                                .map(|path| {
+                                    fs::read(path).with_context(|| {
+                                        format!("Failed to read {}", path.display())
+                                    })
+                                })
+                                .collect(),
+                        );
+                    }
+                }
+            }
+            log::trace!("Prebuffer writer of {} ended", thread_dir.display());
+        });
+        Self { dir, tx }
+    }
+
+    fn send(&self, op: DiskOp) -> Result<()> {
+        self.tx
+            .send(op)
+            .map_err(|_| anyhow!("The prebuffer writer stopped"))
+    }
+
+    /// Start the file of the group of the keyframe at `ts`
+    fn create(&self, ts: Duration) -> Result<DiskFile> {
+        let path = self.dir.join(format!("{:020}.frames", ts.as_micros()));
+        self.send(DiskOp::Create(path.clone()))?;
+        Ok(DiskFile {
+            path,
+            writer: self.clone(),
+        })
+    }
+
+    fn read(&self, paths: Vec<PathBuf>) -> Result<OneshotReceiver<Result<Vec<Vec<u8>>>>> {
+        let (reply_tx, reply_rx) = oneshot();
+        self.send(DiskOp::Read(paths, reply_tx))?;
+        Ok(reply_rx)
+    }
+}
+
+/// The file of a group, it is removed when dropped
+struct DiskFile {
+    path: PathBuf,
+    writer: DiskWriter,
+}
+
+impl DiskFile {
+    fn append(&self, frame: &StampedData) -> Result<()> {
+        self.writer
+            .send(DiskOp::Append(self.path.clone(), frame.clone()))
+    }
+}
+
+impl Drop for DiskFile {
+    fn drop(&mut self) {
+        let _ = self.writer.send(DiskOp::Remove(self.path.clone()));
+    }
+}
+
+/// `keyframe: u8, ts: u64 micros, time: u32 (0 for none), len: u32, data`
+fn encode(frame: &StampedData) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(17 + frame.data.len());
+    buf.push(frame.keyframe as u8);
+    buf.extend((frame.ts.as_micros() as u64).to_le_bytes());
+    buf.extend(frame.time.unwrap_or(0).to_le_bytes());
+    buf.extend((frame.data.len() as u32).to_le_bytes());
+    buf.extend(frame.data.iter());
+    buf
+}
+
+fn decode(mut data: &[u8], frames: &mut Vec<StampedData>) -> Result<()> {
+    while !data.is_empty() {
+        if data.len() < 17 {
+            return Err(anyhow!("Truncated prebuffer frame"));
+        }
+        let keyframe = data[0] != 0;
+        let ts = u64::from_le_bytes(data[1..9].try_into()?);
+        let time = u32::from_le_bytes(data[9..13].try_into()?);
+        let len = u32::from_le_bytes(data[13..17].try_into()?) as usize;
+        let body = data
+            .get(17..17 + len)
+            .ok_or_else(|| anyhow!("Truncated prebuffer frame"))?;
+        frames.push(StampedData {
+            keyframe,
+            data: Bytes::copy_from_slice(body),
+            ts: Duration::from_micros(ts),
+            time: (time != 0).then_some(time),
+        });
+        data = &data[17 + len..];
+    }
+    Ok(())
+}
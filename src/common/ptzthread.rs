@@ -0,0 +1,148 @@
+//! This thread sends the PTZ movements to the camera
+//!
+//! Requests from the cli and mqtt are merged so that only the most
+//! recent direction is sent and at most once every `ptz_rate` ms.
+//! Stop is retried so that the camera does not keep on moving.
+
+use anyhow::anyhow;
+use tokio::{
+    sync::{mpsc::Receiver as MpscReceiver, oneshot::Sender as OneshotSender},
+    time::{sleep_until, Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::NeoInstance;
+use crate::{AnyResult, Result};
+use neolink_core::bc_protocol::Direction;
+
+/// Times a stop is sent before giving up on it
+const STOP_ATTEMPTS: usize = 3;
+
+pub(crate) enum PtzRequest {
+    Move {
+        direction: Direction,
+        speed: f32,
+        sender: OneshotSender<Result<()>>,
+    },
+}
+
+/// The movement waiting for its turn and everyone that asked for it or for
+/// one of the movements that it replaced
+struct PendingMove {
+    direction: Direction,
+    speed: f32,
+    senders: Vec<OneshotSender<Result<()>>>,
+}
+
+pub(crate) struct NeoCamPtzThread {
+    ptz_request_rx: MpscReceiver<PtzRequest>,
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamPtzThread {
+    pub(crate) async fn new(
+        ptz_request_rx: MpscReceiver<PtzRequest>,
+        instance: NeoInstance,
+    ) -> Result<Self> {
+        Ok(Self {
+            ptz_request_rx,
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let config = self.instance.config().await?;
+        let mut pending: Option<PendingMove> = None;
+        let mut next_send = Instant::now();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                loop {
+                    tokio::select! {
+                        request = self.ptz_request_rx.recv() => {
+                            match request {
+                                Some(PtzRequest::Move {
+                                    direction,
+                                    speed,
+                                    sender,
+                                }) => {
+                                    // Most recent wins, the replaced ones get the
+                                    // result of this one
+                                    let mut senders = pending
+                                        .take()
+                                        .map(|pending| pending.senders)
+                                        .unwrap_or_default();
+                                    senders.push(sender);
+                                    pending = Some(PendingMove {
+                                        direction,
+                                        speed,
+                                        senders,
+                                    });
+                                }
+                                None => break,
+                            }
+                        },
+                        _ = sleep_until(next_send), if pending.is_some() => {
+                            if let Some(PendingMove { direction, speed, senders }) = pending.take() {
+                                let res = self.send(direction, speed).await;
+                                let rate = config.borrow().ptz_rate;
+                                next_send = Instant::now() + Duration::from_millis(rate);
+                                for sender in senders {
+                                    let _ = sender.send(
+                                        res.as_ref()
+                                            .map(|_| ())
+                                            .map_err(|e| anyhow!("{:#}", e)),
+                                    );
+                                }
+                            }
+                        },
+                    }
+                }
+                // Senders are gone but a stop may still be waiting
+                if let Some(PendingMove { direction, speed, .. }) = pending.take() {
+                    self.send(direction, speed).await?;
+                }
+                Ok(())
+            } => v,
+        }
+    }
+
+    async fn send(&self, direction: Direction, speed: f32) -> AnyResult<()> {
+        let attempts = if direction == Direction::Stop {
+            STOP_ATTEMPTS
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            let res = self
+                .instance
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.send_ptz(direction, speed).await?;
+                        Ok(())
+                    })
+                })
+                .await;
+            match res {
+                Err(e) if attempt < attempts => {
+                    log::debug!("Retrying PTZ stop after: {:?}", e);
+                    attempt += 1;
+                }
+                res => break res,
+            }
+        }
+    }
+}
+
+impl Drop for NeoCamPtzThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamPtzThread");
+        self.cancel.cancel();
+    }
+}
@@ -2,26 +2,60 @@
 //! the last notification is pushed into a watcher
 //! as is, which comes fromt the json structure
 //!
+//! Each push account is a separate FCM registration with its own listener.
+//! The registrations and the ids of the notifications that were already
+//! received are kept in the push store so that the cameras keep sending to
+//! the same token after a restart and old notifications are not replayed
+//!
 
 use anyhow::Context;
 use fcm_push_listener::*;
-use std::{fs, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 use tokio::{
     sync::{
-        mpsc::{Receiver as MpscReceiver, Sender as MpscSender},
+        mpsc::Receiver as MpscReceiver,
         oneshot::Sender as OneshotSender,
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
+    task::JoinSet,
     time::{sleep, timeout, Duration},
 };
 
 use super::NeoInstance;
-use crate::AnyResult;
+use crate::{config::Config, AnyResult};
+
+/// The account of cameras that do not set `push_account`
+pub(crate) const DEFAULT_ACCOUNT: &str = "default";
+
+/// How many of the received notification ids are kept per account
+const MAX_RECEIVED_IDS: usize = 100;
+
+const SENDER_ID: &str = "743639030586"; // andriod
+                                        // const SENDER_ID: &str = "696841269229"; // ios
 
 pub(crate) struct PushNotiThread {
+    store: Arc<PushStore>,
+    accounts: HashMap<String, Account>,
+    listeners: JoinSet<()>,
+}
+
+struct Account {
     pn_watcher: Arc<WatchSender<Option<PushNoti>>>,
-    registed_cameras: Vec<NeoInstance>,
-    received_ids: Vec<String>,
+    token: WatchReceiver<Option<PushToken>>,
+    registed_cameras: Arc<Mutex<Vec<NeoInstance>>>,
+}
+
+/// The token that the cameras send their notifications to
+#[derive(Clone, Debug)]
+struct PushToken {
+    fcm_token: String,
+    uid: String,
 }
 
 // The push notification
@@ -33,203 +67,352 @@ pub(crate) struct PushNoti {
 
 pub(crate) enum PnRequest {
     Get {
+        account: String,
         sender: OneshotSender<WatchReceiver<Option<PushNoti>>>,
     },
     Activate {
+        account: String,
         instance: NeoInstance,
         sender: OneshotSender<AnyResult<()>>,
     },
-    AddPushID {
-        id: String,
-    },
 }
 
 impl PushNotiThread {
-    pub(crate) async fn new() -> AnyResult<Self> {
-        let (pn_watcher, _) = watch(None);
+    pub(crate) async fn new(config: &Config) -> AnyResult<Self> {
+        let store = PushStore::load(PushStore::path(config));
 
         Ok(PushNotiThread {
-            pn_watcher: Arc::new(pn_watcher),
-            registed_cameras: vec![],
-            received_ids: vec![],
+            store: Arc::new(store),
+            accounts: Default::default(),
+            listeners: JoinSet::new(),
         })
     }
 
     pub(crate) async fn run(
         &mut self,
-        sender: &MpscSender<PnRequest>,
         pn_request_rx: &mut MpscReceiver<PnRequest>,
     ) -> AnyResult<()> {
-        loop {
-            // Short wait on start/retry
-            sleep(Duration::from_secs(3)).await;
+        while let Some(msg) = pn_request_rx.recv().await {
+            match msg {
+                PnRequest::Get { account, sender } => {
+                    let account = self.account(&account);
+                    let _ = sender.send(account.pn_watcher.subscribe());
+                }
+                PnRequest::Activate {
+                    account,
+                    instance,
+                    sender,
+                } => {
+                    let account = self.account(&account);
+                    account
+                        .registed_cameras
+                        .lock()
+                        .unwrap()
+                        .push(instance.clone());
+                    let mut token = account.token.clone();
+                    tokio::task::spawn(async move {
+                        let r = async {
+                            let token = token
+                                .wait_for(|token| token.is_some())
+                                .await?
+                                .clone()
+                                .expect("Waited for the token");
+                            send_pushinfo(&instance, &token).await
+                        }
+                        .await;
+                        let _ = sender.send(r);
+                    });
+                }
+            }
+        }
+        log::debug!("Push Notification requests ended");
+        Ok(())
+    }
 
-            let sender_id = "743639030586"; // andriod
-                                            // let sender_id = "696841269229"; // ios
+    /// Get an account, its listener is started on first use
+    fn account(&mut self, name: &str) -> &Account {
+        if !self.accounts.contains_key(name) {
+            let (pn_watcher, _) = watch(None);
+            let (token_tx, token_rx) = watch(None);
+            let account = Account {
+                pn_watcher: Arc::new(pn_watcher),
+                token: token_rx,
+                registed_cameras: Default::default(),
+            };
+            self.listeners.spawn(listen(
+                name.to_string(),
+                self.store.clone(),
+                account.pn_watcher.clone(),
+                token_tx,
+                account.registed_cameras.clone(),
+            ));
+            self.accounts.insert(name.to_string(), account);
+        }
+        self.accounts.get(name).expect("Was just inserted")
+    }
+}
 
-            let token_path = dirs::config_dir().map(|mut d| {
-                d.push("./neolink_token.toml");
-                d
+/// The uid that the cameras are told along with the token
+pub(crate) fn push_uid(fcm_token: &str) -> String {
+    let md5ed = md5::compute(format!("WHY_REOLINK_{:?}", fcm_token));
+    format!("{:X}", md5ed)
+}
+
+async fn send_pushinfo(instance: &NeoInstance, token: &PushToken) -> AnyResult<()> {
+    instance
+        .run_task(|camera| {
+            let fcm_token = token.fcm_token.clone();
+            let uid = token.uid.clone();
+            Box::pin(async move {
+                let r = camera.send_pushinfo_android(&fcm_token, &uid).await;
+                log::debug!(
+                    "Registered {} for push notifications: {:?}",
+                    camera.uid().await?,
+                    r
+                );
+                r?;
+                AnyResult::Ok(())
+            })
+        })
+        .await
+}
+
+/// Listen for the push notifications of one account
+async fn listen(
+    account: String,
+    store: Arc<PushStore>,
+    pn_watcher: Arc<WatchSender<Option<PushNoti>>>,
+    token_tx: WatchSender<Option<PushToken>>,
+    registed_cameras: Arc<Mutex<Vec<NeoInstance>>>,
+) {
+    loop {
+        // Short wait on start/retry
+        sleep(Duration::from_secs(3)).await;
+
+        let (registration, received_ids) = if let Some(saved) = store.account(&account) {
+            log::debug!("Loaded push notification token of {}", account);
+            (saved.registration, saved.received_ids)
+        } else {
+            log::debug!("Registering new push notification token for {}", account);
+            match fcm_push_listener::register(SENDER_ID).await {
+                Ok(registration) => {
+                    if let Err(e) = store.set_registration(&account, registration.clone()) {
+                        log::warn!(
+                            "Unable to save the push notification details of {}: {:?}",
+                            account,
+                            e
+                        );
+                    }
+                    (registration, vec![])
+                }
+                Err(e) => {
+                    log::warn!("Issue connecting to push notifications server: {:?}", e);
+                    continue;
+                }
+            }
+        };
+
+        // Send registration.fcm_token to the server to allow it to send push messages to you.
+        log::debug!("registration.fcm_token: {}", registration.fcm_token);
+        let token = PushToken {
+            uid: push_uid(&registration.fcm_token),
+            fcm_token: registration.fcm_token.clone(),
+        };
+        log::debug!("push notification UID: {}", token.uid);
+        token_tx.send_replace(Some(token.clone()));
+
+        let cameras = registed_cameras.lock().unwrap().clone();
+        for instance in cameras {
+            let token = token.clone();
+            tokio::task::spawn(async move {
+                let _ = send_pushinfo(&instance, &token).await;
             });
-            log::debug!("Push notification details are saved to {:?}", token_path);
-
-            let registration = if let Some(Ok(Ok(registration))) =
-                token_path.as_ref().map(|token_path| {
-                    fs::read_to_string(token_path).map(|v| toml::from_str::<Registration>(&v))
-                }) {
-                log::debug!("Loaded push notification token");
-                registration
-            } else {
-                log::debug!("Registering new push notification token");
-                match fcm_push_listener::register(sender_id).await {
-                    Ok(registration) => {
-                        let new_token = toml::to_string(&registration)
-                            .with_context(|| "Unable to serialise fcm token")?;
-                        if let Some(Err(e)) = token_path
-                            .as_ref()
-                            .map(|token_path| fs::write(token_path, &new_token))
-                        {
-                            log::warn!(
-                                "Unable to save push notification details ({}) to {:#?} because of the error {:#?}",
-                                new_token,
-                                token_path,
+        }
+
+        log::debug!("Push notification Listening on {}", account);
+        let thread_store = store.clone();
+        let thread_account = account.clone();
+        let thread_pn_watcher = pn_watcher.clone();
+        let mut listener = FcmPushListener::create(
+            registration,
+            move |message: FcmMessage| {
+                log::debug!("Got FCM Message: {:?}", message.payload_json);
+                if let Some(id) = message.persistent_id.clone() {
+                    // If it is not saved it is just received again after a restart
+                    if let Err(e) = thread_store.add_received_id(&thread_account, id) {
+                        log::debug!("Unable to save the push notification id: {:?}", e);
+                    }
+                }
+                thread_pn_watcher.send_replace(Some(PushNoti {
+                    message: message.payload_json,
+                    id: message.persistent_id,
+                }));
+            },
+            received_ids,
+        );
+
+        loop {
+            let r = timeout(Duration::from_secs(60 * 5), listener.connect()).await;
+            match &r {
+                Ok(Ok(_)) => {
+                    log::debug!("Push notification listener reported normal shutdown");
+                }
+                Ok(Err(e)) => {
+                    use fcm_push_listener::Error::*;
+                    match &e {
+                        MissingMessagePayload
+                        | MissingCryptoMetadata
+                        | ProtobufDecode(_)
+                        | Base64Decode(_) => {
+                            // Wipe data so next call is a new token
+                            let _ = store.remove(&account);
+                            token_tx.send_replace(None);
+                            log::debug!(
+                                "Error on push notification listener: {:?}. Clearing token",
                                 e
                             );
                         }
-                        registration
-                    }
-                    Err(e) => {
-                        log::warn!("Issue connecting to push notifications server: {:?}", e);
-                        continue;
+                        Http(e) if e.is_request() || e.is_connect() || e.is_timeout() => {
+                            log::debug!("Error on push notification listener: {:?}", e);
+                        }
+                        _ => {
+                            log::debug!("Error on push notification listener: {:?}", e);
+                            // This sort of error can be a network error
+                            // Wait for a little longer
+                            sleep(Duration::from_secs(30)).await;
+                        }
                     }
                 }
+                Err(_) => {
+                    // timeout
+                    continue;
+                }
             };
+            break;
+        }
+    }
+}
 
-            // Send registration.fcm_token to the server to allow it to send push messages to you.
-            log::debug!("registration.fcm_token: {}", registration.fcm_token);
-            let md5ed = md5::compute(format!("WHY_REOLINK_{:?}", registration.fcm_token));
-            let uid = format!("{:X}", md5ed);
-            let fcm_token = registration.fcm_token.clone();
-            log::debug!("push notification UID: {}", uid);
-
-            log::debug!("Push notification Listening");
-            let thread_pn_watcher = self.pn_watcher.clone();
-            let mut listener = FcmPushListener::create(
-                registration,
-                |message: FcmMessage| {
-                    log::debug!("Got FCM Message: {:?}", message.payload_json);
-                    if let Some(id) = message.persistent_id.clone() {
-                        // Don't worry if queue is full we will just not register as received yet
-                        let _ = sender.try_send(PnRequest::AddPushID { id });
-                    }
-                    thread_pn_watcher.send_replace(Some(PushNoti {
-                        message: message.payload_json,
-                        id: message.persistent_id,
-                    }));
+/// The push notification registrations that are kept between runs
+pub(crate) struct PushStore {
+    path: Option<PathBuf>,
+    data: Mutex<PushStoreData>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PushStoreData {
+    #[serde(default)]
+    pub(crate) accounts: BTreeMap<String, SavedAccount>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SavedAccount {
+    pub(crate) registration: Registration,
+    /// The newest ids of the notifications that were received, the server
+    /// sends the others again
+    #[serde(default)]
+    pub(crate) received_ids: Vec<String>,
+}
+
+impl PushStore {
+    /// The `push_store` of the config or `neolink_token.toml` in the config dir
+    pub(crate) fn path(config: &Config) -> Option<PathBuf> {
+        config.push_store.clone().or_else(|| {
+            dirs::config_dir().map(|mut d| {
+                d.push("neolink_token.toml");
+                d
+            })
+        })
+    }
+
+    /// Load the store, a missing or unreadable file is an empty store
+    pub(crate) fn load(path: Option<PathBuf>) -> Self {
+        log::debug!("Push notification details are saved to {:?}", path);
+        let data = match path.as_deref().map(Self::read) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                log::warn!("Ignoring the saved push notification details: {:?}", e);
+                Default::default()
+            }
+            None => Default::default(),
+        };
+        Self {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    fn read(path: &Path) -> AnyResult<PushStoreData> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        if text.trim().is_empty() {
+            return Ok(Default::default());
+        }
+        // Older versions saved a single registration
+        if let Ok(registration) = toml::from_str::<Registration>(&text) {
+            let mut data = PushStoreData::default();
+            data.accounts.insert(
+                DEFAULT_ACCOUNT.to_string(),
+                SavedAccount {
+                    registration,
+                    received_ids: vec![],
                 },
-                self.received_ids.clone(),
             );
+            return Ok(data);
+        }
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
 
-            for instance in self.registed_cameras.iter() {
-                let uid = uid.clone();
-                let fcm_token = fcm_token.clone();
-                let instance = instance.clone();
-                tokio::task::spawn(async move {
-                    let _ = instance
-                        .run_task(|camera| {
-                            let fcm_token = fcm_token.clone();
-                            let uid = uid.clone();
-                            Box::pin(async move {
-                                let r = camera.send_pushinfo_android(&fcm_token, &uid).await;
-                                log::debug!(
-                                    "Registered {} for push notifications: {:?}",
-                                    camera.uid().await?,
-                                    r
-                                );
-                                r?;
-                                AnyResult::Ok(())
-                            })
-                        })
-                        .await;
-                });
-            }
+    pub(crate) fn data(&self) -> PushStoreData {
+        self.data.lock().unwrap().clone()
+    }
 
-            tokio::select! {
-                v = async {
-                    loop {
-                        let r = timeout(Duration::from_secs(60*5), listener.connect()).await;
-                        match &r {
-                            Ok(Ok(_)) => {
-                                log::debug!("Push notification listener reported normal shutdown");
-                            }
-                            Ok(Err(e)) => {
-                                use fcm_push_listener::Error::*;
-                                match &e {
-                                    MissingMessagePayload | MissingCryptoMetadata | ProtobufDecode(_) | Base64Decode(_) => {
-                                        // Wipe data so next call is a new token
-                                        token_path.map(|token_path|
-                                            fs::write(token_path, "")
-                                        );
-                                        log::debug!("Error on push notification listener: {:?}. Clearing token", e);
-                                    },
-                                    Http(e) if e.is_request() || e.is_connect() || e.is_timeout() => {
-                                        log::debug!("Error on push notification listener: {:?}", e);
-                                    }
-                                    _ => {
-                                        log::debug!("Error on push notification listener: {:?}", e);
-                                        // This sort of error can be a network error
-                                        // Wait for a little longer
-                                        sleep(Duration::from_secs(30)).await;
-                                    }
-                                }
-                            },
-                            Err(_) => {
-                                // timeout
-                                continue;
-                            }
-                        };
-                        break;
-                    }
-                } => v,
-                v = async {
-                    while let Some(msg) = pn_request_rx.recv().await {
-                        match msg {
-                            PnRequest::Get{sender} => {
-                                let _ = sender.send(self.pn_watcher.subscribe());
-                            }
-                            PnRequest::Activate{instance, sender} => {
-                                let uid = uid.clone();
-                                let fcm_token = fcm_token.clone();
-                                self.registed_cameras.push(instance.clone());
-                                tokio::task::spawn(async move {
-                                    let r = instance.run_task(|camera| {
-                                        let fcm_token = fcm_token.clone();
-                                        let uid = uid.clone();
-                                        Box::pin(async move {
-                                            let r = camera.send_pushinfo_android(&fcm_token, &uid).await;
-                                            log::debug!(
-                                                "Registered {} for push notifications: {:?}",
-                                                camera.uid().await?,
-                                                r
-                                            );
-                                            r?;
-                                            AnyResult::Ok(())
-                                        })
-                                    }).await;
-                                    let _ = sender.send(r);
-                                });
-                            }
-                            PnRequest::AddPushID{id} => {
-                                self.received_ids.push(id);
-                            }
-                        }
-                    }
-                } => {
-                    // These are critical errors
-                    log::debug!("Push Notification thread ended {:?}", v);
-                    break AnyResult::Ok(());
+    fn account(&self, account: &str) -> Option<SavedAccount> {
+        self.data.lock().unwrap().accounts.get(account).cloned()
+    }
+
+    fn set_registration(&self, account: &str, registration: Registration) -> AnyResult<()> {
+        self.update(|data| {
+            data.accounts.insert(
+                account.to_string(),
+                SavedAccount {
+                    registration,
+                    received_ids: vec![],
                 },
-            };
+            );
+        })
+    }
+
+    fn add_received_id(&self, account: &str, id: String) -> AnyResult<()> {
+        self.update(|data| {
+            if let Some(saved) = data.accounts.get_mut(account) {
+                saved.received_ids.push(id);
+                let extra = saved.received_ids.len().saturating_sub(MAX_RECEIVED_IDS);
+                saved.received_ids.drain(..extra);
+            }
+        })
+    }
+
+    fn remove(&self, account: &str) -> AnyResult<()> {
+        self.update(|data| {
+            data.accounts.remove(account);
+        })
+    }
+
+    fn update<F: FnOnce(&mut PushStoreData)>(&self, f: F) -> AnyResult<()> {
+        let mut data = self.data.lock().unwrap();
+        f(&mut data);
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let text = toml::to_string(&*data).with_context(|| "Unable to serialise fcm token")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
         }
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
     }
 }
@@ -1,8 +1,9 @@
 //! This is the highest level to a camera
 //! it represents a collection of managed cameras
 use anyhow::anyhow;
+use futures::FutureExt;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::Arc,
 };
 use tokio::{
@@ -12,19 +13,33 @@ use tokio::{
         watch::{channel as watch, Receiver as WatchReceiver},
     },
     task::JoinSet,
-    time::{sleep, Duration},
+    time::{sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{NeoCam, NeoInstance};
-use crate::{common::PushNotiThread, config::Config, AnyResult, Result};
+use super::{
+    health_server, metrics_server, trigger_runner, update_checker, Metrics, UpdateAvailable,
+};
+use super::{NeoCam, NeoInstance, NvrLink};
+use crate::{
+    common::PushNotiThread, config::Config, crash, exitcode::ErrorClass, i18n, i18n::tr, logging,
+    quirks, AnyResult, Result,
+};
+
+/// How long each camera gets to log out during [`NeoReactor::shutdown`]
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest wait between two attempts to get the channels of an NVR
+const MAX_NVR_BACKOFF: Duration = Duration::from_secs(60);
 
 #[allow(clippy::large_enum_variant)]
 enum NeoReactorCommand {
     HangUp,
+    Shutdown(OneshotSender<()>),
     Config(OneshotSender<WatchReceiver<Config>>),
+    Update(OneshotSender<WatchReceiver<Option<UpdateAvailable>>>),
     UpdateConfig(Config, OneshotSender<Result<()>>),
     Get(String, OneshotSender<Result<Option<NeoInstance>>>),
+    NvrChannels(String, Vec<u8>),
 }
 
 /// Reactor handles the collection of cameras
@@ -39,16 +54,33 @@ impl NeoReactor {
     pub(crate) async fn new(config: Config) -> Self {
         let (commad_tx, mut command_rx) = mpsc(100);
         let (push_noti, mut pn_rx) = mpsc(10);
-        let pn_tx = push_noti.clone();
+        let pn_config = config.clone();
         let cancel = CancellationToken::new();
-        let (config_tx, _) = watch(config);
+        // The cameras of the NVRs are added once their channels are found
+        let mut raw_config = config.clone();
+        let (config_tx, _) = watch(config.expand_nvrs(&HashMap::new()));
+        let (update_tx, _) = watch(None);
         let mut set = JoinSet::new();
+        let metrics = Metrics::default();
 
         let cancel1 = cancel.clone();
         let cancel2 = cancel.clone();
         let config_tx = Arc::new(config_tx);
+        let update_tx = Arc::new(update_tx);
+        let thread_config_tx = config_tx.clone();
+        let thread_update_tx = update_tx.clone();
+        let metrics_config_tx = config_tx.clone();
+        let health_config_tx = config_tx.clone();
+        let trigger_config_tx = config_tx.clone();
+        let otel_config_tx = config_tx.clone();
+        let thread_metrics = metrics.clone();
+        let nvr_commander = commad_tx.clone();
         set.spawn(async move {
             let mut instances: HashMap<String, NeoCam> = Default::default();
+            let mut nvr_links: HashMap<String, Arc<NvrLink>> = Default::default();
+            let mut nvr_channels: HashMap<String, Vec<u8>> = Default::default();
+            let mut nvr_searches = NvrSearches::new(nvr_commander);
+            nvr_searches.update(&raw_config, &nvr_channels, &mut nvr_links);
 
             let r = tokio::select! {
                 _ = cancel1.cancelled() => {
@@ -64,17 +96,35 @@ impl NeoReactor {
                                 cancel2.cancel();
                                 return Result::<(), anyhow::Error>::Ok(());
                             }
+                            NeoReactorCommand::Shutdown(reply) =>  {
+                                log::debug!("Cancel:: NeoReactorCommand::Shutdown");
+                                // All cameras log out at the same time so the whole
+                                // shutdown is bounded by a single SHUTDOWN_TIMEOUT
+                                futures::future::join_all(instances.iter_mut().map(|(name, instance)| async move {
+                                    if timeout(SHUTDOWN_TIMEOUT, instance.shutdown()).await.is_err() {
+                                        log::warn!("{name}: Did not log out within {SHUTDOWN_TIMEOUT:?}");
+                                    }
+                                })).await;
+                                instances.clear();
+                                cancel2.cancel();
+                                let _ = reply.send(());
+                                return Ok(());
+                            }
                             NeoReactorCommand::Config(reply) =>  {
                                 let _ = reply.send(config_tx.subscribe());
                             }
+                            NeoReactorCommand::Update(reply) =>  {
+                                let _ = reply.send(update_tx.subscribe());
+                            }
                             NeoReactorCommand::Get(name, sender) => {
                                 let new = match instances.entry(name.clone()) {
                                     Entry::Occupied(occ) => Result::Ok(Some(occ.get().subscribe().await?)),
                                     Entry::Vacant(vac) => {
                                         log::debug!("Inserting new insance");
                                         let current_config: Config = (*config_tx.borrow()).clone();
-                                        if let Some(config) = current_config.cameras.iter().find(|cam| cam.name == name).cloned() {
-                                            let cam = NeoCam::new(config, push_noti.clone()).await?;
+                                        if let Some(config) = current_config.cameras.iter().find(|cam| cam.name == name).map(|cam| current_config.camera_config(cam)) {
+                                            let nvr = config.nvr_name.as_ref().map(|nvr_name| nvr_links.entry(nvr_name.clone()).or_default().clone());
+                                            let cam = NeoCam::new(config, push_noti.clone(), thread_metrics.register(&name), nvr).await?;
                                             log::debug!("New instance created");
                                             Result::Ok(Some(
                                                 vac.insert(
@@ -91,9 +141,20 @@ impl NeoReactor {
                                 log::debug!("Got instance from reactor");
                                 let _ = sender.send(new);
                             },
+                            NeoReactorCommand::NvrChannels(nvr_name, channels) => {
+                                nvr_searches.found(&nvr_name);
+                                nvr_channels.insert(nvr_name, channels);
+                                let _ = config_tx.send_replace(raw_config.clone().expand_nvrs(&nvr_channels));
+                            }
                             NeoReactorCommand::UpdateConfig(new_conf, reply) => {
+                                // Channels are asked again from an NVR that changed
+                                nvr_channels.retain(|nvr_name, _| raw_config.cameras.iter().find(|cam| &cam.name == nvr_name) == new_conf.cameras.iter().find(|cam| &cam.name == nvr_name));
+                                nvr_searches.update(&new_conf, &nvr_channels, &mut nvr_links);
+                                raw_config = new_conf.clone();
+                                let new_conf = new_conf.expand_nvrs(&nvr_channels);
+
                                 // Shutdown or Notify instances of a change
-                                let mut names = new_conf.cameras.iter().filter(|cam_conf| cam_conf.enabled).map(|cam_conf| (cam_conf.name.clone(), cam_conf.clone())).collect::<HashMap<_,_>>();
+                                let mut names = new_conf.cameras.iter().filter(|cam_conf| cam_conf.enabled).map(|cam_conf| (cam_conf.name.clone(), new_conf.camera_config(cam_conf))).collect::<HashMap<_,_>>();
                                 // Remove those no longer in the config
                                 instances.retain(|name, _| names.contains_key(name));
                                 for (name, instance) in instances.iter() {
@@ -102,6 +163,13 @@ impl NeoReactor {
                                     }
                                 }
 
+                                crash::set_config(&new_conf);
+                                i18n::set_language(new_conf.language.as_deref());
+                                quirks::set_config(&new_conf);
+                                if let Err(e) = logging::set_camera_logs(&new_conf) {
+                                    log::warn!("Failed to open the camera log files: {e:?}");
+                                }
+
                                 // Set the new conf
                                 let _ = config_tx.send_replace(new_conf);
                                 // Reply that we are done
@@ -121,10 +189,10 @@ impl NeoReactor {
             let r = tokio::select! {
                 _ = cancel1.cancelled() => AnyResult::Ok(()),
                 v = async {
-                    let mut pn = PushNotiThread::new().await?;
+                    let mut pn = PushNotiThread::new(&pn_config).await?;
 
                     loop {
-                        let r = pn.run(&pn_tx, &mut pn_rx).await;
+                        let r = pn.run(&mut pn_rx).await;
                         if r.is_err() {
                             log::debug!("Issue with push notifier: {r:?}");
                             sleep(Duration::from_secs(5)).await;
@@ -138,6 +206,80 @@ impl NeoReactor {
             r
         });
 
+        // Update checker
+        let cancel1 = cancel.clone();
+        set.spawn(async move {
+            let r = tokio::select! {
+                _ = cancel1.cancelled() => AnyResult::Ok(()),
+                v = update_checker(thread_config_tx.subscribe(), thread_update_tx) => v,
+            };
+            log::debug!("Update checker ended: {r:?}");
+            r
+        });
+
+        // Metrics server
+        let cancel1 = cancel.clone();
+        let health_metrics = metrics.clone();
+        #[cfg(feature = "otel")]
+        let otel_metrics = metrics.clone();
+        set.spawn(async move {
+            let r = tokio::select! {
+                _ = cancel1.cancelled() => AnyResult::Ok(()),
+                v = metrics_server(metrics_config_tx.subscribe(), metrics) => v,
+            };
+            log::debug!("Metrics server ended: {r:?}");
+            r
+        });
+
+        // Health server
+        let cancel1 = cancel.clone();
+        set.spawn(async move {
+            let r = tokio::select! {
+                _ = cancel1.cancelled() => AnyResult::Ok(()),
+                v = health_server(health_config_tx.subscribe(), health_metrics) => v,
+            };
+            log::debug!("Health server ended: {r:?}");
+            r
+        });
+
+        // External triggers
+        //
+        // They get their own handle to the reactor that does not own the
+        // tasks so that dropping it does not stop the reactor
+        let cancel1 = cancel.clone();
+        let trigger_reactor = Self {
+            cancel: cancel.clone(),
+            commander: commad_tx.clone(),
+            set: None,
+        };
+        set.spawn(async move {
+            let r = tokio::select! {
+                _ = cancel1.cancelled() => AnyResult::Ok(()),
+                v = trigger_runner(trigger_config_tx.subscribe(), trigger_reactor) => v,
+            };
+            log::debug!("Triggers ended: {r:?}");
+            r
+        });
+
+        // OpenTelemetry exporter
+        #[cfg(feature = "otel")]
+        {
+            let cancel1 = cancel.clone();
+            let otel_config = otel_config_tx.subscribe();
+            set.spawn(async move {
+                let r = tokio::select! {
+                    _ = cancel1.cancelled() => AnyResult::Ok(()),
+                    v = super::otel_exporter(otel_config, otel_metrics) => v,
+                };
+                log::debug!("OpenTelemetry exporter ended: {r:?}");
+                r
+            });
+        }
+        #[cfg(not(feature = "otel"))]
+        if otel_config_tx.borrow().otel.is_some() {
+            log::warn!("The [otel] section needs neolink built with the otel feature");
+        }
+
         Self {
             cancel,
             commander: commad_tx,
@@ -145,6 +287,26 @@ impl NeoReactor {
         }
     }
 
+    /// Log out of all cameras then stop the reactor
+    ///
+    /// Unlike dropping the reactor this waits for the cameras so that
+    /// the process does not exit with the sessions still open. Each camera
+    /// gets [`SHUTDOWN_TIMEOUT`] to log out
+    pub(crate) async fn shutdown(&self) {
+        let (reply_tx, reply_rx) = oneshot();
+        if self
+            .commander
+            .send(NeoReactorCommand::Shutdown(reply_tx))
+            .await
+            .is_ok()
+        {
+            // Extra time for a camera that is still being created
+            let _ = timeout(SHUTDOWN_TIMEOUT * 2, reply_rx).await;
+        }
+        self.cancel.cancel();
+        log::debug!("NeoReactor shutdown complete");
+    }
+
     /// Get camera by name but do not create
     pub(crate) async fn get(&self, name: &str) -> Result<NeoInstance> {
         let (sender_tx, sender_rx) = oneshot();
@@ -152,9 +314,76 @@ impl NeoReactor {
             .send(NeoReactorCommand::Get(name.to_string(), sender_tx))
             .await?;
 
-        sender_rx
-            .await??
-            .ok_or(anyhow!("Camera `{name}` not found in config"))
+        sender_rx.await??.ok_or_else(|| {
+            anyhow!("Camera `{name}` not found in config").context(ErrorClass::Config)
+        })
+    }
+
+    /// Start all enabled cameras at once and wait for them to connect
+    ///
+    /// The wait is bounded by the `startup_timeout` of the config, the
+    /// cameras that are not ready by then keep connecting in the background.
+    /// Cameras with `idle_disconnect` only connect once used so they are not
+    /// waited for
+    pub(crate) async fn connect_all(&self) -> Result<()> {
+        let config = self.config().await?.borrow().clone();
+        let wait = Duration::from_millis(config.startup_timeout);
+        let names = config
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled)
+            .map(|cam| (cam.name.clone(), !cam.idle_disconnect))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let ready = futures::future::join_all(names.iter().map(|(name, wanted)| async move {
+            let instance = match self.get(name).await {
+                Ok(instance) => instance,
+                Err(e) => {
+                    log::warn!("{name}: Could not start the camera: {e:?}");
+                    return false;
+                }
+            };
+            if !*wanted || wait.is_zero() {
+                return false;
+            }
+            let mut camera = instance.camera();
+            match timeout(wait, camera.wait_for(|cam| cam.upgrade().is_some())).await {
+                Ok(Ok(_)) => {
+                    log::info!(
+                        "{}",
+                        tr!(
+                            "startup.camera_ready",
+                            camera = name,
+                            elapsed = format!("{:.1?}", start.elapsed())
+                        )
+                    );
+                    true
+                }
+                Ok(Err(_)) => false,
+                Err(_) => {
+                    log::warn!("{}", tr!("startup.camera_waiting", camera = name));
+                    false
+                }
+            }
+        }))
+        .await;
+
+        if !wait.is_zero() {
+            log::info!(
+                "{}",
+                tr!(
+                    "startup.summary",
+                    ready = ready.iter().filter(|ready| **ready).count(),
+                    total = names.iter().filter(|(_, wanted)| *wanted).count(),
+                    elapsed = format!("{:.1?}", start.elapsed())
+                )
+            );
+        }
+        Ok(())
     }
 
     pub(crate) async fn config(&self) -> Result<WatchReceiver<Config>> {
@@ -166,6 +395,16 @@ impl NeoReactor {
         Ok(sender_rx.await?)
     }
 
+    /// Watch for newer releases found by the update checker
+    pub(crate) async fn update_available(&self) -> Result<WatchReceiver<Option<UpdateAvailable>>> {
+        let (sender_tx, sender_rx) = oneshot();
+        self.commander
+            .send(NeoReactorCommand::Update(sender_tx))
+            .await?;
+
+        Ok(sender_rx.await?)
+    }
+
     pub(crate) async fn update_config(&self, new_config: Config) -> Result<()> {
         let (sender_tx, sender_rx) = oneshot();
         self.commander
@@ -176,6 +415,100 @@ impl NeoReactor {
     }
 }
 
+/// Asks the NVRs that do not list their channels for them
+///
+/// The channels are sent back to the reactor with
+/// [`NeoReactorCommand::NvrChannels`]
+struct NvrSearches {
+    commander: MpscSender<NeoReactorCommand>,
+    searching: HashMap<String, CancellationToken>,
+    set: JoinSet<()>,
+}
+
+impl NvrSearches {
+    fn new(commander: MpscSender<NeoReactorCommand>) -> Self {
+        Self {
+            commander,
+            searching: Default::default(),
+            set: Default::default(),
+        }
+    }
+
+    /// Start a search for each NVR of the config whose channels are not known
+    fn update(
+        &mut self,
+        config: &Config,
+        found: &HashMap<String, Vec<u8>>,
+        links: &mut HashMap<String, Arc<NvrLink>>,
+    ) {
+        let wanted = config
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled)
+            .filter(|cam| {
+                cam.nvr
+                    .as_ref()
+                    .map(|nvr| nvr.channels.is_none())
+                    .unwrap_or(false)
+            })
+            .filter(|cam| !found.contains_key(&cam.name))
+            .collect::<Vec<_>>();
+        let wanted_names = wanted
+            .iter()
+            .map(|cam| cam.name.clone())
+            .collect::<HashSet<_>>();
+        // Stop the searches of the NVRs that are gone or changed
+        self.searching.retain(|name, cancel| {
+            let keep = wanted_names.contains(name);
+            if !keep {
+                cancel.cancel();
+            }
+            keep
+        });
+
+        for cam in wanted {
+            if self.searching.contains_key(&cam.name) {
+                continue;
+            }
+            let name = cam.name.clone();
+            let camera = config.camera_config(cam);
+            let link = links.entry(name.clone()).or_default().clone();
+            let commander = self.commander.clone();
+            let cancel = CancellationToken::new();
+            self.searching.insert(name.clone(), cancel.clone());
+            self.set.spawn(async move {
+                tokio::select! {
+                    _ = cancel.cancelled() => {},
+                    _ = async {
+                        let mut backoff = Duration::from_secs(1);
+                        loop {
+                            match link.channels(&camera).await {
+                                Ok(channels) => {
+                                    log::info!("{name}: Found the NVR channels {channels:?}");
+                                    let _ = commander.send(NeoReactorCommand::NvrChannels(name, channels)).await;
+                                    break;
+                                }
+                                Err(e) => {
+                                    log::warn!("{name}: Could not get the channels of the NVR: {e:?}");
+                                    sleep(backoff).await;
+                                    backoff = (backoff * 2).min(MAX_NVR_BACKOFF);
+                                }
+                            }
+                        }
+                    } => {},
+                }
+            });
+        }
+    }
+
+    /// The search of the NVR has finished
+    fn found(&mut self, name: &str) {
+        self.searching.remove(name);
+        // Clear the results of the finished searches
+        while let Some(Some(_)) = self.set.join_next().now_or_never() {}
+    }
+}
+
 impl Drop for NeoReactor {
     fn drop(&mut self) {
         if let Some(set) = self.set.take() {
@@ -3,10 +3,11 @@
 //! If there are no listeners to the broadcast
 //! then it will hangup
 
+use bytes::Bytes;
 use futures::stream::{FuturesUnordered, StreamExt};
 use std::{
     collections::{hash_map::Entry, HashMap, VecDeque},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 use tokio::{
     sync::{
@@ -18,13 +19,16 @@ use tokio::{
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
     task::JoinHandle,
-    time::{sleep, timeout, Duration},
+    time::{sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{NeoInstance, Permit, UseCounter};
-use crate::{AnyResult, Result};
-use neolink_core::{bc_protocol::StreamKind, bcmedia::model::*};
+use super::{in_span, stream_span, NeoInstance, Permit, Prebuffer, UseCounter};
+use crate::{quirks, AnyResult, Result};
+use neolink_core::{
+    bc_protocol::{StreamKind, DEFAULT_STREAM_BUFFER},
+    bcmedia::{integrity::check_frame, model::*, paramsets::ParamSetCache},
+};
 
 pub(crate) struct NeoCamStreamThread {
     streams: HashMap<StreamKind, StreamData>,
@@ -99,7 +103,7 @@ impl NeoCamStreamThread {
                                 StreamKind::Sub,
                             ];
                             let config = self.instance.config().await?.borrow().clone();
-                            let config_streams = config.stream.as_stream_kinds();
+                            let config_streams = quirks::stream_kinds(&config.name, &config.stream);
                             for name in streams.drain(..) {
                                 if config_streams.contains(&name) {
                                     // Fill it in
@@ -130,7 +134,7 @@ impl NeoCamStreamThread {
                                 StreamKind::Main,
                             ];
                             let config = self.instance.config().await?.borrow().clone();
-                            let config_streams = config.stream.as_stream_kinds();
+                            let config_streams = quirks::stream_kinds(&config.name, &config.stream);
                             for name in streams.drain(..) {
                                 if config_streams.contains(&name) {
                                     // Fill it in
@@ -156,7 +160,7 @@ impl NeoCamStreamThread {
                             sender
                         } => {
                             let config = self.instance.config().await?.borrow_and_update().clone();
-                            let streams = config.stream.as_stream_kinds();
+                            let streams = quirks::stream_kinds(&config.name, &config.stream);
                             for stream in streams.iter().copied() {
                                 if let Entry::Vacant(vac) = self.streams.entry(stream) {
                                     vac.insert(
@@ -229,6 +233,7 @@ pub(crate) struct StreamData {
     aud: BroadcastSender<StampedData>,
     vid_history: Arc<WatchSender<VecDeque<StampedData>>>,
     aud_history: Arc<WatchSender<VecDeque<StampedData>>>,
+    prebuffer: Option<Arc<Mutex<Prebuffer>>>,
     config: Arc<WatchSender<StreamConfig>>,
     name: StreamKind,
     instance: NeoInstance,
@@ -276,17 +281,90 @@ impl StreamConfig {
 #[derive(Debug, Clone)]
 pub(crate) struct StampedData {
     pub(crate) keyframe: bool,
-    pub(crate) data: Arc<Vec<u8>>,
+    /// A view into the packet from the camera, cloning it does not copy the frame
+    pub(crate) data: Bytes,
     pub(crate) ts: Duration,
+    /// POSIX time of the frame from the camera's clock, only sent with keyframes
+    pub(crate) time: Option<u32>,
+}
+
+/// Turns the camera's frame microseconds into timestamps that only go forward
+///
+/// The microseconds are a u32 that wraps about every 71 minutes and restarts
+/// with each stream. Steps of up to [`MediaClock::MAX_STEP`] are taken as they
+/// are, across a wrap too, anything else is replaced by the time that passed
+/// since the last frame. The timestamps are also pulled slowly towards the
+/// local clock so a camera clock that runs fast or slow does not drift away
+/// from the clients.
+pub(crate) struct MediaClock {
+    last_micros: Option<u32>,
+    last_frame: Instant,
+    /// The local time of a timestamp of zero
+    origin: Instant,
+    ts: Duration,
+}
+
+impl MediaClock {
+    /// The largest step between two frames that is trusted
+    const MAX_STEP: Duration = Duration::from_secs(5);
+    /// Drift beyond this moves the origin rather than being pulled in
+    const MAX_DRIFT: Duration = Duration::from_secs(2);
+    /// How much of the drift is corrected on each frame
+    const DRIFT_DIVISOR: u32 = 100;
+
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            last_micros: None,
+            last_frame: now,
+            origin: now,
+            ts: Duration::ZERO,
+        }
+    }
+
+    /// The camera starts its microseconds again with each new stream
+    pub(crate) fn restart(&mut self) {
+        self.last_micros = None;
+    }
+
+    /// The timestamp of a frame with the camera's microseconds
+    pub(crate) fn stamp(&mut self, micros: u32) -> Duration {
+        let now = Instant::now();
+        let step = self
+            .last_micros
+            .map(|last| Duration::from_micros(micros.wrapping_sub(last) as u64))
+            .filter(|step| *step <= Self::MAX_STEP)
+            .unwrap_or_else(|| now - self.last_frame);
+        self.last_micros = Some(micros);
+        self.last_frame = now;
+
+        let prev = self.ts;
+        self.ts += step;
+        let local = now - self.origin;
+        if local > self.ts + Self::MAX_DRIFT || self.ts > local + Self::MAX_DRIFT {
+            log::debug!(
+                "Camera clock is {:?} from the local clock",
+                local.abs_diff(self.ts)
+            );
+            self.origin = now - self.ts;
+        } else if local > self.ts {
+            self.ts += (local - self.ts) / Self::DRIFT_DIVISOR;
+        } else {
+            let slowed = self.ts - (self.ts - local) / Self::DRIFT_DIVISOR;
+            self.ts = slowed.max(prev);
+        }
+        self.ts
+    }
 }
 
 pub(crate) struct StreamInstance {
-    #[allow(dead_code)]
     pub(crate) name: StreamKind,
     pub(crate) vid: BroadcastReceiver<StampedData>,
     pub(crate) vid_history: WatchReceiver<VecDeque<StampedData>>,
     pub(crate) aud: BroadcastReceiver<StampedData>,
     pub(crate) aud_history: WatchReceiver<VecDeque<StampedData>>,
+    /// The last seconds of video when the camera has a `[cameras.prebuffer]`
+    pub(crate) prebuffer: Option<Arc<Mutex<Prebuffer>>>,
     pub(crate) config: WatchReceiver<StreamConfig>,
     in_use: Permit,
 }
@@ -299,6 +377,7 @@ impl StreamInstance {
             vid_history: data.vid_history.subscribe(),
             aud: data.aud.subscribe(),
             aud_history: data.aud_history.subscribe(),
+            prebuffer: data.prebuffer.clone(),
             config: data.config.subscribe(),
             in_use: data.users.create_activated().await?,
         })
@@ -326,6 +405,17 @@ impl StreamData {
         let vid_history = Arc::new(vid_history);
         let (aud_history, _) = watch::<VecDeque<StampedData>>(VecDeque::new());
         let aud_history = Arc::new(aud_history);
+        let prebuffer = {
+            let camera_config = instance.config().await?.borrow().clone();
+            match camera_config.prebuffer.as_ref() {
+                Some(prebuffer_config) => Some(Arc::new(Mutex::new(Prebuffer::new(
+                    prebuffer_config,
+                    &camera_config.name,
+                    name,
+                )?))),
+                None => None,
+            }
+        };
         let (resolution, bitrate, fps, fps_table) = instance
             .run_passive_task(|cam| {
                 Box::pin(async move {
@@ -375,6 +465,7 @@ impl StreamData {
             vid_history,
             aud,
             aud_history,
+            prebuffer,
             instance,
             handle: None,
             strict,
@@ -386,15 +477,22 @@ impl StreamData {
         let aud = me.aud.clone();
         let instance = me.instance.subscribe().await?;
         let name = me.name;
-        let cam_name = instance.config().await?.borrow().name.clone();
+        let camera_config = instance.config().await?;
+        let cam_name = camera_config.borrow().name.clone();
         let print_name = format!("{cam_name}::{name}");
         let strict = me.strict;
         let config = me.config.clone();
         let thread_inuse = me.users.create_deactivated().await?;
         let vid_history = me.vid_history.clone();
         let aud_history = me.aud_history.clone();
+        let prebuffer = me.prebuffer.clone();
+        let power = instance.power().await?;
         let mut permit = instance.permit().await?;
-        me.handle = Some(tokio::task::spawn(async move {
+        let metrics = instance.metrics();
+        // Kept over reconnects so the timestamps keep going forward
+        let media_clock = Arc::new(Mutex::new(MediaClock::new()));
+        let span = stream_span(&cam_name, name);
+        me.handle = Some(tokio::task::spawn(in_span(span, async move {
             let r = tokio::select! {
                 _ = cancel.cancelled() => {
                     Result::<(), anyhow::Error>::Ok(())
@@ -453,9 +551,14 @@ impl StreamData {
                                     let stream_config = config.clone();
                                     let vid_history = vid_history.clone();
                                     let aud_history = aud_history.clone();
+                                    let prebuffer = prebuffer.clone();
+                                    let power = power.clone();
                                     let watchdog_tx = watchdog_tx.clone();
                                     let fps_table = fps_table.clone();
                                     let print_name = print_name.clone();
+                                    let metrics = metrics.clone();
+                                    let camera_config = camera_config.clone();
+                                    let media_clock = media_clock.clone();
 
                                     log::debug!("{print_name}: Running Stream Instance Task");
                                     Box::pin(async move {
@@ -466,19 +569,45 @@ impl StreamData {
 
                                         let res = async {
                                             let mut prev_ts = Duration::ZERO;
-                                            let mut stream_data = camera.start_video(name, 0, strict).await?;
+                                            media_clock.lock().unwrap().restart();
+                                            let mut stream_data = camera.start_video(name, DEFAULT_STREAM_BUFFER, strict).await?;
+                                            // New consumers start on an IFrame so make sure each has its parameter sets
+                                            let mut param_sets = ParamSetCache::new();
+                                            let mut overflowed = 0;
                                             loop {
                                                 log::debug!("{print_name}:   Waiting for frame");
-                                                let data = stream_data.get_data().await??;
+                                                let mut data = stream_data.get_data().await??;
+                                                // The buffer drops the oldest frames when we fall behind
+                                                let dropped = stream_data.dropped();
+                                                if dropped > overflowed {
+                                                    log::debug!("{print_name}: Fell behind the camera, {} frames dropped", dropped - overflowed);
+                                                    metrics.frames_overflowed(dropped - overflowed);
+                                                    overflowed = dropped;
+                                                    // The frames after the gap may refer to the dropped ones
+                                                    recieved_iframe = false;
+                                                }
                                                 log::debug!("{print_name}:   Waiting for Watchdog");
                                                 watchdog_tx.send(()).await?;  // Feed the watchdog
                                                 log::debug!("{print_name}:   Got frame");
 
+                                                // Corrupt frames can crash the decoders of the clients
+                                                if let BcMedia::Iframe(BcMediaIframe{video_type, data, ..}) | BcMedia::Pframe(BcMediaPframe{video_type, data, ..}) = &data {
+                                                    if let Err(defect) = check_frame(*video_type, data) {
+                                                        log::debug!("{print_name}: Dropping corrupt frame: {defect}");
+                                                        metrics.frame_dropped();
+                                                        // The frames after it refer to it so wait for the next IFrame
+                                                        recieved_iframe = false;
+                                                        continue;
+                                                    }
+                                                }
+                                                param_sets.inspect(&mut data);
+
                                                 // Update the stream config with any information
                                                 match &data {
                                                     BcMedia::InfoV1(info) => {
                                                         stream_config.send_if_modified(|state| {
                                                             let new_fps = fps_table.get(info.fps as usize).copied().unwrap_or(info.fps as u32);
+                                                            metrics.set_fps(name, new_fps);
                                                             if state.resolution[0] != info.video_width || state.resolution[1] != info.video_height || new_fps != state.fps  {
                                                                 state.resolution[0] = info.video_width;
                                                                 state.resolution[1] = info.video_height;
@@ -492,6 +621,7 @@ impl StreamData {
                                                     BcMedia::InfoV2(info) => {
                                                         stream_config.send_if_modified(|state| {
                                                             let new_fps = fps_table.get(info.fps as usize).copied().unwrap_or(info.fps as u32);
+                                                            metrics.set_fps(name, new_fps);
                                                             if state.resolution[0] != info.video_width || state.resolution[1] != info.video_height || new_fps != state.fps  {
                                                                 state.resolution[0] = info.video_width;
                                                                 state.resolution[1] = info.video_height;
@@ -556,14 +686,24 @@ impl StreamData {
                                                 }
 
                                                 match data {
-                                                    BcMedia::Iframe(BcMediaIframe{data, microseconds, ..}) => {
-                                                        prev_ts = Duration::from_micros(microseconds as u64);
+                                                    BcMedia::Iframe(BcMediaIframe{data, microseconds, time, ..}) => {
+                                                        prev_ts = media_clock.lock().unwrap().stamp(microseconds);
                                                         // log::debug!("IFrame: {prev_ts:?}");
+                                                        metrics.frame(data.len());
                                                         let d = StampedData{
                                                                 keyframe: true,
-                                                                data: Arc::new(data),
-                                                                ts: prev_ts
+                                                                data,
+                                                                ts: prev_ts,
+                                                                time,
                                                         };
+                                                        if let Some(prebuffer) = prebuffer.as_ref() {
+                                                            let mut prebuffer = prebuffer.lock().unwrap();
+                                                            if power.borrow().pause_prebuffer {
+                                                                prebuffer.clear();
+                                                            } else if let Err(e) = prebuffer.push(&d) {
+                                                                log::warn!("{print_name}: Failed to prebuffer: {e:?}");
+                                                            }
+                                                        }
                                                         let _ = vid_tx.send(d.clone());
                                                         vid_history.send_modify(|history| {
                                                            let drop_time = d.ts.saturating_sub(BUFFER_DURATION);
@@ -577,14 +717,24 @@ impl StreamData {
                                                         log::trace!("Sent Vid Key Frame");
                                                     },
                                                     BcMedia::Pframe(BcMediaPframe{data, microseconds,..}) if recieved_iframe => {
-                                                        prev_ts = Duration::from_micros(microseconds as u64);
+                                                        prev_ts = media_clock.lock().unwrap().stamp(microseconds);
                                                         // log::debug!("PFrame: {prev_ts:?}");
                                                         // log::debug!("data: {data:02X?}");
+                                                        metrics.frame(data.len());
                                                         let d = StampedData{
                                                             keyframe: false,
-                                                            data: Arc::new(data),
-                                                            ts: prev_ts
+                                                            data,
+                                                            ts: prev_ts,
+                                                            time: None,
                                                         };
+                                                        if let Some(prebuffer) = prebuffer.as_ref() {
+                                                            let mut prebuffer = prebuffer.lock().unwrap();
+                                                            if power.borrow().pause_prebuffer {
+                                                                prebuffer.clear();
+                                                            } else if let Err(e) = prebuffer.push(&d) {
+                                                                log::warn!("{print_name}: Failed to prebuffer: {e:?}");
+                                                            }
+                                                        }
                                                         let _ = vid_tx.send(d.clone());
                                                         vid_history.send_modify(|history| {
                                                            let drop_time = d.ts.saturating_sub(BUFFER_DURATION);
@@ -597,10 +747,12 @@ impl StreamData {
                                                     }
                                                     BcMedia::Aac(BcMediaAac{data, ..}) | BcMedia::Adpcm(BcMediaAdpcm{data,..}) if recieved_iframe => {
                                                         // log::debug!("Audio: {prev_ts:?}");
+                                                        metrics.frame(data.len());
                                                         let d = StampedData{
                                                             keyframe: aud_keyframe,
-                                                            data: Arc::new(data),
+                                                            data,
                                                             ts: prev_ts,
+                                                            time: None,
                                                         };
                                                         aud_keyframe = false;
                                                         let _ = aud_tx.send(d.clone())?;
@@ -617,6 +769,15 @@ impl StreamData {
                                                 }
                                             }
                                         }.await;
+                                        if let Err(e) = &res {
+                                            let undecodable = matches!(e.downcast_ref::<neolink_core::Error>(), Some(neolink_core::Error::MediaUndecodable(_)));
+                                            let camera_config = camera_config.borrow().clone();
+                                            if undecodable && camera_config.encryption_fallback && quirks::fall_back_encryption(&camera_config) {
+                                                log::warn!("{print_name}: The stream could not be parsed with Aes, logging in again with HeaderAes");
+                                                // Dropping the connection makes the camera thread login again
+                                                let _ = camera.shutdown().await;
+                                            }
+                                        }
                                         Ok(res)
                                     })
                                 }) => {
@@ -641,7 +802,7 @@ impl StreamData {
             };
             log::debug!("{print_name}: Stream Thead Stopped: {:?}", r);
             r
-        }));
+        })));
 
         Ok(me)
     }
@@ -0,0 +1,70 @@
+//! Spans of the camera threads
+//!
+//! With the `tracing` feature every thread of a camera runs in a span with the
+//! camera name, and the stream threads also carry the stream kind. The core
+//! bc spans with the `msg_num` of each message are made inside of these, and
+//! the log messages are forwarded as events of the current span, so with
+//! several cameras an error can be traced back to its camera in tokio-console
+//! or OpenTelemetry. Without the feature these do nothing.
+//!
+use neolink_core::bc_protocol::StreamKind;
+use std::future::Future;
+
+/// The span of a camera thread
+#[cfg(feature = "tracing")]
+pub(crate) type CameraSpan = tracing::Span;
+
+/// The span of a camera thread
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CameraSpan;
+
+/// Start the span of the threads of a camera
+pub(crate) fn camera_span(camera: &str) -> CameraSpan {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info_span!(parent: None, "camera", camera)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = camera;
+        CameraSpan
+    }
+}
+
+/// Start the span of the thread of one stream of a camera
+pub(crate) fn stream_span(camera: &str, stream: StreamKind) -> CameraSpan {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info_span!(parent: None, "camera", camera, stream = %stream)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (camera, stream);
+        CameraSpan
+    }
+}
+
+/// Run `fut` inside `span`
+pub(crate) async fn in_span<F: Future>(span: CameraSpan, fut: F) -> F::Output {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Instrument::instrument(fut, span).await
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = span;
+        fut.await
+    }
+}
+
+/// Forward a log record to tracing as an event of the current span
+pub(crate) fn log_event(record: &log::Record) {
+    #[cfg(feature = "tracing")]
+    {
+        // Nowhere to report a failure to log so it is dropped
+        let _ = tracing_log::format_trace(record);
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = record;
+}
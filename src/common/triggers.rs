@@ -0,0 +1,260 @@
+//! External inputs that act on the cameras
+//!
+//! Each `[[triggers]]` of the config is fired by a message on its
+//! `mqtt_topic`, by a line printed by its `command` or by a
+//! `POST /trigger/{name}` to the `[trigger_server]`.
+//!
+//! When it fires its cameras are kept awake for `duration` seconds, the
+//! floodlight is turned on if asked for and the camera's trigger is set so
+//! that `neolink record --on-motion` records a clip just like for motion.
+use anyhow::{anyhow, Context, Result};
+use hyper::{Method, StatusCode};
+use std::{process::Stdio, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::{
+        mpsc::{channel as mpsc, Sender as MpscSender},
+        watch::Receiver as WatchReceiver,
+    },
+    task::JoinSet,
+    time::{sleep, Duration},
+};
+
+use super::{http_server, response, NeoReactor};
+use crate::{
+    config::{Config, TriggerConfig},
+    mqtt::{Mqtt, MqttInstance},
+    AnyResult,
+};
+
+/// Wait before starting a command or the triggers again after they stop
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Run the triggers of the config, they are restarted whenever they change
+pub(crate) async fn trigger_runner(
+    mut config: WatchReceiver<Config>,
+    reactor: NeoReactor,
+) -> Result<()> {
+    loop {
+        let triggers = config.borrow_and_update().triggers.clone();
+        let run_config = config.clone();
+        tokio::select! {
+            v = config.wait_for(|config| config.triggers != triggers) => {
+                v?;
+            }
+            v = run(&triggers, run_config, &reactor) => {
+                log::warn!("Triggers stopped: {v:?}");
+                sleep(RESTART_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn run(
+    triggers: &[TriggerConfig],
+    config: WatchReceiver<Config>,
+    reactor: &NeoReactor,
+) -> Result<()> {
+    if triggers.is_empty() {
+        return futures::future::pending().await;
+    }
+    let (fired_tx, mut fired_rx) = mpsc(100);
+    let mut sources = JoinSet::new();
+
+    for (index, trigger) in triggers.iter().enumerate() {
+        if let Some(command) = trigger.command.clone() {
+            let name = trigger.name.clone();
+            let fired = fired_tx.clone();
+            sources.spawn(async move { command_source(&name, &command, index, fired).await });
+        }
+    }
+
+    let topics = triggers
+        .iter()
+        .filter_map(|trigger| trigger.mqtt_topic.clone())
+        .collect::<Vec<_>>();
+    if !topics.is_empty() {
+        if config.borrow().mqtt.is_none() {
+            log::warn!("The triggers with an mqtt_topic need the [mqtt] section");
+        }
+        let mqtt = Mqtt::listener(config.clone(), "Neolink-triggers", topics).await;
+        let mqtt_triggers = triggers.to_vec();
+        let fired = fired_tx.clone();
+        // Subscribing waits for the broker so the client lives in the task
+        sources.spawn(async move {
+            let instance = mqtt.subscribe("").await?;
+            mqtt_source(instance, &mqtt_triggers, fired).await
+        });
+    }
+
+    let names: Arc<Vec<String>> = Arc::new(
+        triggers
+            .iter()
+            .map(|trigger| trigger.name.clone())
+            .collect(),
+    );
+    let server_fired = fired_tx.clone();
+    sources.spawn(http_server(
+        config,
+        "Trigger",
+        |config| {
+            config
+                .trigger_server
+                .as_ref()
+                .map(|server| format!("{}:{}", server.bind_addr, server.bind_port))
+        },
+        move |req| {
+            let index = req
+                .uri()
+                .path()
+                .strip_prefix("/trigger/")
+                .and_then(|name| names.iter().position(|known| known == name));
+            match (req.method(), index) {
+                (&Method::POST, Some(index)) => match server_fired.try_send(index) {
+                    Ok(()) => response(StatusCode::OK, "text/plain", "OK".to_string()),
+                    Err(_) => {
+                        response(StatusCode::SERVICE_UNAVAILABLE, "text/plain", String::new())
+                    }
+                },
+                (_, Some(_)) => {
+                    response(StatusCode::METHOD_NOT_ALLOWED, "text/plain", String::new())
+                }
+                _ => response(StatusCode::NOT_FOUND, "text/plain", String::new()),
+            }
+        },
+    ));
+
+    let mut firing = JoinSet::new();
+    loop {
+        tokio::select! {
+            Some(index) = fired_rx.recv() => {
+                let trigger = &triggers[index];
+                log::info!("Trigger {} fired", trigger.name);
+                for camera in trigger.cameras.iter() {
+                    let reactor = reactor.clone();
+                    let camera = camera.clone();
+                    let duration = Duration::from_secs(trigger.duration);
+                    let floodlight = trigger.floodlight;
+                    firing.spawn(async move {
+                        let res = fire(&reactor, &camera, duration, floodlight).await;
+                        if let Err(e) = &res {
+                            log::warn!("{camera}: Failed to act on the trigger: {e:?}");
+                        }
+                    });
+                }
+            }
+            Some(res) = sources.join_next() => {
+                return res.context("A trigger source panicked")?;
+            }
+            Some(_) = firing.join_next() => {}
+        }
+    }
+}
+
+/// Act on one camera of a trigger and keep it awake for `duration`
+async fn fire(
+    reactor: &NeoReactor,
+    camera: &str,
+    duration: Duration,
+    floodlight: bool,
+) -> Result<()> {
+    let instance = reactor.get(camera).await?;
+    instance.fire_trigger(duration).await?;
+
+    let _permit = instance.permit().await?;
+    // The duration is at most an hour as checked by the config
+    let secs = duration.as_secs() as u16;
+    instance
+        .run_task(|cam| {
+            Box::pin(async move {
+                if floodlight {
+                    cam.set_floodlight_manual(true, secs)
+                        .await
+                        .context("Failed to turn on the floodlight")?;
+                }
+                AnyResult::Ok(())
+            })
+        })
+        .await?;
+    sleep(duration).await;
+    Ok(())
+}
+
+/// Fire on each line that the command prints, starting it again when it exits
+async fn command_source(
+    name: &str,
+    command: &str,
+    index: usize,
+    fired: MpscSender<usize>,
+) -> Result<()> {
+    loop {
+        match run_command(command, index, &fired).await {
+            Ok(()) => log::warn!("Trigger {name}: The command exited"),
+            Err(e) => log::warn!("Trigger {name}: The command failed: {e:?}"),
+        }
+        if fired.is_closed() {
+            return Ok(());
+        }
+        sleep(RESTART_DELAY).await;
+    }
+}
+
+async fn run_command(command: &str, index: usize, fired: &MpscSender<usize>) -> Result<()> {
+    let mut child = shell(command)
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start the command")?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("The command has no stdout"))?;
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        log::debug!("Trigger command printed: {line}");
+        fired.send(index).await?;
+    }
+    child.wait().await?;
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Fire the triggers whose topic matches each message
+async fn mqtt_source(
+    mut mqtt: MqttInstance,
+    triggers: &[TriggerConfig],
+    fired: MpscSender<usize>,
+) -> Result<()> {
+    loop {
+        let msg = mqtt.recv().await?;
+        if matches!(
+            msg.message.trim().to_lowercase().as_str(),
+            "off" | "false" | "0"
+        ) {
+            continue;
+        }
+        for (index, trigger) in triggers.iter().enumerate() {
+            let matched = trigger
+                .mqtt_topic
+                .as_deref()
+                .is_some_and(|filter| rumqttc::matches(&msg.topic, filter));
+            if matched {
+                fired.send(index).await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Checks GitHub for newer releases of neolink
+//!
+//! This only ever reports that an update is available, it never installs anything.
+//! It is opt in with the `[update_check]` config section.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, sync::Arc};
+use tokio::{
+    sync::watch::{Receiver as WatchReceiver, Sender as WatchSender},
+    time::{sleep, Duration},
+};
+
+use crate::config::{Config, ReleaseChannel, UpdateCheckConfig};
+
+/// A release that is newer than the running version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct UpdateAvailable {
+    schema: &'static str,
+    /// The running version
+    pub(crate) current: String,
+    /// The tag of the newer release
+    pub(crate) latest: String,
+    /// Link to the release notes
+    pub(crate) url: String,
+    pub(crate) prerelease: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    html_url: String,
+    draft: bool,
+    prerelease: bool,
+}
+
+/// A `major.minor.patch[-pre]` version, enough to order neolink's release tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Version {
+    numbers: [u64; 3],
+    pre: Option<String>,
+}
+
+impl Version {
+    /// Parse a tag such as `v0.6.3-rc.1` or a `git describe` output
+    /// such as `v0.6.2-12-gabcdef0`
+    fn parse(version: &str) -> Option<Version> {
+        let version = version.split_whitespace().next()?;
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let (numbers, pre) = match version.split_once('-') {
+            Some((numbers, pre)) => (numbers, Some(pre)),
+            None => (version, None),
+        };
+        let mut parts = numbers.split('.').map(|n| n.parse::<u64>().ok());
+        let numbers = [parts.next()??, parts.next()??, parts.next()??];
+        if parts.next().is_some() {
+            return None;
+        }
+        // Drop the `-{commits}-g{hash}` that git describe adds after the tag
+        let is_describe =
+            |commits: &str, hash: &str| hash.starts_with('g') && commits.parse::<u64>().is_ok();
+        let pre = pre.and_then(|pre| match pre.rsplitn(3, '-').collect::<Vec<_>>()[..] {
+            [hash, commits, tag_pre] if is_describe(commits, hash) => Some(tag_pre),
+            [hash, commits] if is_describe(commits, hash) => None,
+            _ => Some(pre),
+        });
+        Some(Version {
+            numbers,
+            pre: pre.map(|pre| pre.to_string()),
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers
+            .cmp(&other.numbers)
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                // A pre-release comes before its full release
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => cmp_pre(a, b),
+            })
+    }
+}
+
+/// Compare two pre-releases such as `rc.2` and `rc.10` the semver way
+///
+/// Each dot separated identifier is compared in turn, numbers by their
+/// value and before any text. When one runs out first it is the older
+fn cmp_pre(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn current_version() -> Option<Version> {
+    Version::parse(env!("NEOLINK_VERSION")).or_else(|| Version::parse(env!("CARGO_PKG_VERSION")))
+}
+
+/// Query GitHub for the newest release on the channel that is newer than this build
+async fn check(config: &UpdateCheckConfig) -> Result<Option<UpdateAvailable>> {
+    let current = current_version().context("Unable to parse the running version")?;
+    let releases: Vec<GithubRelease> = reqwest::Client::new()
+        .get(format!(
+            "https://api.github.com/repos/{}/releases?per_page=30",
+            config.repository
+        ))
+        .header(reqwest::header::USER_AGENT, "neolink")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Unexpected reply from GitHub releases")?;
+
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .filter(|release| config.channel == ReleaseChannel::Beta || !release.prerelease)
+        .filter_map(|release| Some((Version::parse(&release.tag_name)?, release)))
+        .filter(|(version, _)| version > &current)
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, release)| UpdateAvailable {
+            schema: "neolink.update.v1",
+            current: env!("NEOLINK_VERSION").trim().to_string(),
+            latest: release.tag_name,
+            url: release.html_url,
+            prerelease: release.prerelease,
+        }))
+}
+
+/// Runs the update checks for as long as the config has an `[update_check]` section
+///
+/// Newer releases are logged and sent on `update_tx`
+pub(crate) async fn update_checker(
+    mut config: WatchReceiver<Config>,
+    update_tx: Arc<WatchSender<Option<UpdateAvailable>>>,
+) -> Result<()> {
+    loop {
+        let update_config = config.borrow_and_update().update_check.clone();
+        let wait = match update_config.as_ref() {
+            Some(update_config) => {
+                match check(update_config).await {
+                    Ok(Some(update)) => {
+                        if update_tx.borrow().as_ref() != Some(&update) {
+                            log::info!(
+                                "Neolink {} is available (running {}): {}",
+                                update.latest,
+                                update.current,
+                                update.url
+                            );
+                        }
+                        update_tx.send_replace(Some(update));
+                    }
+                    Ok(None) => {
+                        log::debug!("Neolink is up to date");
+                        update_tx.send_replace(None);
+                    }
+                    Err(e) => log::debug!("Failed to check for updates: {e:?}"),
+                }
+                Some(Duration::from_secs(update_config.interval * 60 * 60))
+            }
+            None => None,
+        };
+
+        // Check again after the interval or when the update config changes
+        tokio::select! {
+            v = config.wait_for(|new_conf| new_conf.update_check != update_config) => {
+                v?;
+            },
+            _ = async {
+                match wait {
+                    Some(wait) => sleep(wait).await,
+                    None => futures::future::pending().await,
+                }
+            } => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn test_pre_numeric() {
+        assert!(version("v0.6.3-rc.2") < version("v0.6.3-rc.10"));
+        assert!(version("v0.6.3-rc.10") > version("v0.6.3-rc.9"));
+    }
+
+    #[test]
+    fn test_pre_before_release() {
+        assert!(version("v0.6.3-rc.1") < version("v0.6.3"));
+        assert!(version("v0.6.3") > version("v0.6.3-rc.10"));
+    }
+
+    #[test]
+    fn test_pre_identifiers() {
+        // Numbers sort before text and a shorter set of identifiers is older
+        assert!(version("v1.0.0-1") < version("v1.0.0-alpha"));
+        assert!(version("v1.0.0-alpha") < version("v1.0.0-alpha.1"));
+        assert!(version("v1.0.0-alpha.beta") < version("v1.0.0-beta"));
+        assert!(version("v1.0.0-rc.1") == version("v1.0.0-rc.1-3-gabcdef0"));
+    }
+}
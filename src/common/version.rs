@@ -0,0 +1,42 @@
+//! What build of neolink is running
+//!
+//! Served on `/version` by the health server and published to
+//! `neolink/version` by the mqtt bridge so that a fleet running mixed
+//! versions can be told apart remotely.
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct VersionInfo {
+    schema: &'static str,
+    /// The version from the git tag, or the crate version with no git
+    version: &'static str,
+    crate_version: &'static str,
+    git_hash: &'static str,
+    profile: &'static str,
+    /// The optional parts that were compiled in
+    features: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    pub(crate) fn current() -> Self {
+        let features = [
+            ("gstreamer", cfg!(feature = "gstreamer")),
+            // Push notifications are always built in
+            ("pushnoti", true),
+            ("tracing", cfg!(feature = "tracing")),
+            ("otel", cfg!(feature = "otel")),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+        Self {
+            schema: "neolink.version.v1",
+            version: env!("NEOLINK_VERSION").trim(),
+            crate_version: env!("CARGO_PKG_VERSION"),
+            git_hash: env!("NEOLINK_GIT_HASH"),
+            profile: env!("NEOLINK_PROFILE"),
+            features,
+        }
+    }
+}
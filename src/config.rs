@@ -1,10 +1,12 @@
-use crate::mqtt::Discoveries;
+use crate::{i18n::tr, mqtt::Discoveries};
+use anyhow::Context;
 use lazy_static::lazy_static;
-use neolink_core::bc_protocol::{DiscoveryMethods, PrintFormat, StreamKind};
+use neolink_core::bc_protocol::{DiscoveryMethods, PrintFormat, StreamHandles, StreamKind};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use validator::{Validate, ValidationError};
 use validator_derive::Validate;
 
@@ -12,7 +14,8 @@ lazy_static! {
     static ref RE_TLS_CLIENT_AUTH: Regex = Regex::new(r"^(none|request|require)$").unwrap();
     static ref RE_PAUSE_MODE: Regex = Regex::new(r"^(black|still|test|none)$").unwrap();
     static ref RE_MAXENC_SRC: Regex =
-        Regex::new(r"^([nN]one|[Aa][Ee][Ss]|[Bb][Cc][Ee][Nn][Cc][Rr][Yy][Pp][Tt])$").unwrap();
+        Regex::new(r"^(?i)(none|bcencrypt|headeraes|aes|fullaes)$").unwrap();
+    static ref RE_NAMESPACE: Regex = Regex::new(r"^/?[\w.-]+(/[\w.-]+)*/?$").unwrap();
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
@@ -33,6 +36,7 @@ pub(crate) struct Config {
     #[serde(default = "default_certificate")]
     pub(crate) certificate: Option<String>,
 
+    #[validate]
     #[serde(default = "Default::default")]
     pub(crate) mqtt: Option<MqttServerConfig>,
 
@@ -47,6 +51,458 @@ pub(crate) struct Config {
     #[validate]
     #[serde(default)]
     pub(crate) users: Vec<UserConfig>,
+    /// Language of the CLI messages e.g. `"de"`, defaults to the system language
+    #[serde(default)]
+    pub(crate) language: Option<String>,
+
+    /// Write the logs of each camera into their own file
+    #[validate]
+    #[serde(default)]
+    pub(crate) log_files: Option<LogFilesConfig>,
+
+    /// Periodically check GitHub for a newer release
+    #[validate]
+    #[serde(default)]
+    pub(crate) update_check: Option<UpdateCheckConfig>,
+
+    /// Where to write crash reports and who to notify
+    #[serde(default = "default_crash_report")]
+    pub(crate) crash_report: CrashReportConfig,
+
+    /// Prefix for the mqtt topics, rtsp mounts and http paths such as `site1`
+    ///
+    /// Used to keep several neolink instances apart on one broker or NVR
+    #[validate(regex(
+        path = "RE_NAMESPACE",
+        message = "Namespace may only contain letters, numbers, `_`, `.`, `-` and `/`",
+        code = "namespace"
+    ))]
+    #[serde(default)]
+    pub(crate) namespace: Option<String>,
+
+    #[serde(default = "default_onvif")]
+    pub(crate) onvif: OnvifConfig,
+
+    #[serde(default = "default_webrtc")]
+    pub(crate) webrtc: WebRtcConfig,
+
+    #[validate]
+    #[serde(default = "default_hls")]
+    pub(crate) hls: HlsConfig,
+
+    #[validate]
+    #[serde(default = "default_record")]
+    pub(crate) record: RecordConfig,
+
+    #[serde(default = "default_snapshot")]
+    pub(crate) snapshot: SnapshotConfig,
+
+    #[validate]
+    #[serde(default = "default_timelapse")]
+    pub(crate) timelapse: TimelapseConfig,
+
+    /// Where to POST the json events of the webhook subcommand
+    #[validate]
+    #[serde(default)]
+    pub(crate) webhook: Option<WebhookConfig>,
+
+    /// Serve prometheus metrics of all cameras on `/metrics`
+    #[serde(default)]
+    pub(crate) metrics: Option<MetricsConfig>,
+
+    /// Serve the json health of all cameras on `/health`
+    #[serde(default)]
+    pub(crate) health: Option<HealthConfig>,
+
+    /// Export the metrics and bc spans over OTLP, needs the `otel` feature
+    #[serde(default)]
+    pub(crate) otel: Option<OtelConfig>,
+
+    /// Time in ms to wait for a camera to accept a command. Defaults to 1s or
+    /// 5s when the camera is reached over the reolink relay
+    #[validate(range(min = 100, message = "Invalid reply timeout", code = "reply_timeout"))]
+    #[serde(default)]
+    pub(crate) reply_timeout: Option<u64>,
+
+    /// Extra camera quirks, these are applied after the built in ones
+    #[validate]
+    #[serde(default)]
+    pub(crate) quirks: Vec<QuirkConfig>,
+
+    /// External inputs that wake cameras, start recordings and turn on the
+    /// floodlight
+    #[validate]
+    #[serde(default)]
+    pub(crate) triggers: Vec<TriggerConfig>,
+
+    /// Serve `POST /trigger/{name}` to fire the triggers
+    #[serde(default)]
+    pub(crate) trigger_server: Option<TriggerServerConfig>,
+
+    /// Serve the rtsp sessions over http so they can be listed and
+    /// disconnected
+    #[serde(default)]
+    pub(crate) rtsp_admin: Option<RtspAdminConfig>,
+
+    /// Time in ms to wait at startup for all enabled cameras to connect
+    /// before rtsp and mqtt start serving them. Those that are not ready by
+    /// then keep connecting in the background, `0` does not wait
+    #[serde(default = "default_startup_timeout")]
+    pub(crate) startup_timeout: u64,
+
+    /// Reload the config file when it changes
+    #[serde(default = "default_true")]
+    pub(crate) watch_config: bool,
+
+    /// Where the push notification registrations are kept between runs.
+    /// Defaults to `neolink_token.toml` in the user's config dir
+    #[serde(default)]
+    pub(crate) push_store: Option<std::path::PathBuf>,
+}
+
+impl Config {
+    /// Read, parse and validate a config file
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| tr!("config.read_failed", path = format!("{:?}", path)))?,
+        )
+        .with_context(|| tr!("config.parse_failed", path = format!("{:?}", path)))?;
+
+        config
+            .validate()
+            .with_context(|| tr!("config.validate_failed", path = format!("{:?}", path)))?;
+        Ok(config)
+    }
+
+    /// The namespace without any surrounding `/`
+    pub(crate) fn namespace(&self) -> Option<&str> {
+        self.namespace
+            .as_deref()
+            .map(|namespace| namespace.trim_matches('/'))
+            .filter(|namespace| !namespace.is_empty())
+    }
+
+    /// The root of all mqtt topics: `neolink` or `{namespace}/neolink`
+    pub(crate) fn mqtt_root(&self) -> String {
+        match self.namespace() {
+            Some(namespace) => format!("{namespace}/neolink"),
+            None => "neolink".to_string(),
+        }
+    }
+
+    /// The config of a camera with the global defaults filled in
+    pub(crate) fn camera_config(&self, camera: &CameraConfig) -> CameraConfig {
+        let mut camera = camera.clone();
+        camera.reply_timeout = camera.reply_timeout.or(self.reply_timeout);
+        camera
+    }
+
+    /// The config with each NVR replaced by a camera for each of its channels
+    ///
+    /// `found` holds the channels that were asked from the NVRs that do not
+    /// list them, an NVR without either has no cameras yet
+    pub(crate) fn expand_nvrs(mut self, found: &HashMap<String, Vec<u8>>) -> Self {
+        self.cameras = self
+            .cameras
+            .drain(..)
+            .flat_map(|camera| match camera.nvr.as_ref() {
+                None => vec![camera],
+                Some(nvr) => nvr
+                    .channels
+                    .as_ref()
+                    .or_else(|| found.get(&camera.name))
+                    .map(|channels| {
+                        channels
+                            .iter()
+                            .map(|channel| camera.nvr_channel(*channel))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+        self
+    }
+
+    /// Prefix for rtsp mounts and http paths: empty or `/{namespace}`
+    pub(crate) fn path_prefix(&self) -> String {
+        self.namespace()
+            .map(|namespace| format!("/{namespace}"))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct OnvifConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_onvif_port")]
+    pub(crate) bind_port: u16,
+
+    /// Answer WS-Discovery probes so that NVRs find the cameras
+    #[serde(default = "default_true")]
+    pub(crate) discovery: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct WebRtcConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_webrtc_port")]
+    pub(crate) bind_port: u16,
+
+    /// STUN server used to find the public address e.g. `stun://stun.l.google.com:19302`
+    ///
+    /// Not needed when the browser is on the same network
+    #[serde(default)]
+    pub(crate) stun_server: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct HlsConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_hls_port")]
+    pub(crate) bind_port: u16,
+
+    /// Target length of a segment in seconds, segments are only cut on
+    /// keyframes so they can be longer than this
+    #[validate(range(
+        min = 0.5,
+        message = "Invalid segment duration",
+        code = "segment_duration"
+    ))]
+    #[serde(default = "default_hls_segment_duration")]
+    pub(crate) segment_duration: f64,
+
+    /// Target length of a low latency part in seconds
+    #[validate(range(min = 0.1, message = "Invalid part duration", code = "part_duration"))]
+    #[serde(default = "default_hls_part_duration")]
+    pub(crate) part_duration: f64,
+
+    /// Number of segments kept in the playlist
+    #[validate(range(min = 3, message = "Need at least 3 segments", code = "segments"))]
+    #[serde(default = "default_hls_segments")]
+    pub(crate) segments: usize,
+
+    /// Serve LL-HLS parts and allow blocking playlist reloads
+    #[serde(default = "default_true")]
+    pub(crate) low_latency: bool,
+
+    /// When set every request needs a url signed with this secret, see
+    /// `neolink hls sign`
+    #[serde(default)]
+    pub(crate) secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct RecordConfig {
+    /// Target length of a recording in seconds, files are only cut on
+    /// keyframes so they can be longer than this
+    #[validate(range(
+        min = 10,
+        message = "Invalid segment duration",
+        code = "segment_duration"
+    ))]
+    #[serde(default = "default_record_segment_duration")]
+    pub(crate) segment_duration: u64,
+
+    /// Recordings older than this many days are deleted, they are kept
+    /// forever if unset
+    #[validate(range(min = 1, message = "Invalid retention days", code = "retention_days"))]
+    #[serde(default)]
+    pub(crate) retention_days: Option<u64>,
+
+    /// Only record clips while there is motion
+    #[serde(default)]
+    pub(crate) on_motion: bool,
+
+    /// Seconds of video kept from before the motion started
+    #[serde(default = "default_record_pre_roll")]
+    pub(crate) pre_roll: u64,
+
+    /// Seconds to keep recording after the motion stopped
+    #[serde(default = "default_record_post_roll")]
+    pub(crate) post_roll: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct SnapshotConfig {
+    /// Directory the motion snapshots are written to
+    #[serde(default)]
+    pub(crate) dir: Option<std::path::PathBuf>,
+
+    /// Url that each motion snapshot is POSTed to as a jpeg
+    #[serde(default)]
+    pub(crate) webhook: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct TimelapseConfig {
+    /// Directory the frames and videos are written to
+    #[serde(default)]
+    pub(crate) dir: Option<std::path::PathBuf>,
+
+    /// Seconds between two frames
+    #[validate(range(min = 1, message = "Invalid timelapse interval", code = "interval"))]
+    #[serde(default = "default_timelapse_interval")]
+    pub(crate) interval: u64,
+
+    /// Turn the frames of each day into an mp4 once the day is over
+    #[serde(default)]
+    pub(crate) assemble: bool,
+
+    /// Frames per second of the assembled mp4
+    #[validate(range(min = 1, max = 120, message = "Invalid timelapse fps", code = "fps"))]
+    #[serde(default = "default_timelapse_fps")]
+    pub(crate) fps: u32,
+
+    /// Delete the frames of a day once its mp4 has been written
+    #[serde(default)]
+    pub(crate) delete_frames: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct WebhookConfig {
+    /// Url that the json events are POSTed to
+    pub(crate) url: String,
+
+    /// Attempts at delivering an event before it is dropped
+    #[validate(range(min = 1, message = "Need at least one attempt", code = "retries"))]
+    #[serde(default = "default_webhook_retries")]
+    pub(crate) retries: u32,
+
+    /// Seconds between checks of the battery and PIR state
+    #[validate(range(min = 10, message = "Invalid poll interval", code = "poll_interval"))]
+    #[serde(default = "default_webhook_poll_interval")]
+    pub(crate) poll_interval: u64,
+}
+
+impl WebhookConfig {
+    /// A webhook to `url` with the default settings
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            url,
+            retries: default_webhook_retries(),
+            poll_interval: default_webhook_poll_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct MetricsConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_metrics_port")]
+    pub(crate) bind_port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct HealthConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_health_port")]
+    pub(crate) bind_port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct TriggerServerConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_trigger_port")]
+    pub(crate) bind_port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct RtspAdminConfig {
+    /// Only on this machine by default as it can disconnect clients
+    #[serde(rename = "bind", default = "default_local_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[serde(rename = "port", default = "default_rtsp_admin_port")]
+    pub(crate) bind_port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct OtelConfig {
+    /// The grpc endpoint of the OTLP collector
+    #[serde(default = "default_otel_endpoint")]
+    pub(crate) endpoint: String,
+
+    /// Name reported as the `service.name` resource
+    #[serde(default = "default_otel_service_name")]
+    pub(crate) service_name: String,
+
+    /// Seconds between metric exports
+    #[serde(default = "default_otel_interval")]
+    pub(crate) interval: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct CrashReportConfig {
+    /// Directory the crash reports are written to
+    #[serde(default = "default_crash_dir")]
+    pub(crate) dir: std::path::PathBuf,
+
+    /// Url that a json summary of the crash is POSTed to
+    #[serde(default)]
+    pub(crate) webhook: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct UpdateCheckConfig {
+    /// Which releases to consider
+    #[serde(default = "default_release_channel")]
+    pub(crate) channel: ReleaseChannel,
+
+    /// Hours between checks
+    #[validate(range(
+        min = 1,
+        message = "Update checks must be at least an hour apart",
+        code = "interval"
+    ))]
+    #[serde(default = "default_update_interval")]
+    pub(crate) interval: u64,
+
+    /// The GitHub `owner/repo` to check the releases of
+    #[serde(default = "default_update_repository")]
+    pub(crate) repository: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum ReleaseChannel {
+    /// Only full releases
+    #[serde(alias = "stable")]
+    Stable,
+    /// Full releases and pre-releases
+    #[serde(alias = "beta")]
+    Beta,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct LogFilesConfig {
+    /// Directory of the `{camera}.log` files
+    #[serde(default = "default_log_dir")]
+    pub(crate) dir: std::path::PathBuf,
+
+    /// Size in bytes at which a log file is rotated
+    #[validate(range(
+        min = 1024,
+        message = "Log files must be at least 1024 bytes",
+        code = "max_size"
+    ))]
+    #[serde(default = "default_log_max_size")]
+    pub(crate) max_size: u64,
+
+    /// Number of rotated files to keep as `{camera}.log.1` ... `{camera}.log.N`
+    #[serde(default = "default_log_keep")]
+    pub(crate) keep: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
@@ -65,6 +521,49 @@ pub(crate) struct MqttServerConfig {
 
     #[serde(default)]
     pub(crate) client_auth: Option<(std::path::PathBuf, std::path::PathBuf)>,
+
+    /// Use TLS with the system's root certificates when there is no `ca`
+    #[serde(default)]
+    pub(crate) tls: bool,
+
+    #[serde(default = "default_mqtt_protocol")]
+    pub(crate) protocol: MqttProtocol,
+
+    /// MQTT v5 only: Seconds that the broker keeps our session after a disconnect
+    #[serde(default)]
+    pub(crate) session_expiry: Option<u32>,
+
+    /// MQTT v5 only: How many topics are sent as a short alias after their first use
+    #[serde(default)]
+    pub(crate) topic_aliases: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum MqttProtocol {
+    /// MQTT v3.1.1
+    #[serde(alias = "v3", alias = "v4", alias = "3.1.1", alias = "V4")]
+    V3,
+    /// MQTT v5
+    #[serde(alias = "v5", alias = "5")]
+    V5,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) enum KeepAlive {
+    /// Nothing for battery cameras and `Linktype` for the others
+    #[default]
+    #[serde(alias = "auto")]
+    Auto,
+    /// Ask for the link type, this is answered by all cameras
+    #[serde(alias = "linktype", alias = "link_type")]
+    Linktype,
+    /// Send the bc ping message
+    #[serde(alias = "ping")]
+    Ping,
+    /// Send nothing, only the keep alives that the camera sends over udp are
+    /// answered
+    #[serde(alias = "none", alias = "udp")]
+    None,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
@@ -146,6 +645,17 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_channel_id", alias = "channel")]
     pub(crate) channel_id: u8,
 
+    /// Serve each channel of this NVR as a camera named `{name}/ch{channel}`,
+    /// a single camera without the section
+    #[validate]
+    #[serde(default)]
+    pub(crate) nvr: Option<NvrConfig>,
+
+    /// Cameras with the same `nvr_name` share one connection, set on the
+    /// channels made from an `nvr` section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) nvr_name: Option<String>,
+
     #[validate]
     #[serde(default = "default_mqtt")]
     pub(crate) mqtt: MqttConfig,
@@ -154,6 +664,47 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_pause")]
     pub(crate) pause: PauseConfig,
 
+    /// Cap in kbit/s on the video served over rtsp across all streams and
+    /// clients of this camera
+    #[validate(range(min = 64, message = "Invalid max bitrate", code = "max_bitrate"))]
+    #[serde(default)]
+    pub(crate) max_bitrate: Option<u32>,
+
+    /// How many rtsp clients get the mainStream before the next ones are
+    /// served the subStream on the mainStream path
+    #[validate(range(
+        min = 1,
+        message = "Invalid max main clients",
+        code = "max_main_clients"
+    ))]
+    #[serde(default)]
+    pub(crate) max_main_clients: Option<u32>,
+
+    /// Codec of the audio served over rtsp and ONVIF
+    #[serde(default = "default_audio_codec")]
+    pub(crate) audio_codec: AudioCodec,
+
+    /// How much the rtsp streams are buffered, trading delay for smoothness
+    #[serde(default = "default_latency")]
+    pub(crate) latency: Latency,
+
+    /// Mux the video and audio of the rtsp streams into one MPEG-TS
+    #[serde(default)]
+    pub(crate) use_mpegts: bool,
+
+    /// Play the ONVIF backchannel audio of the rtsp clients on the camera's speaker
+    #[serde(default)]
+    pub(crate) backchannel: bool,
+
+    #[validate]
+    #[serde(default)]
+    pub(crate) rtp: RtpConfig,
+
+    /// Multicast groups of the rtsp streams, unicast only without one
+    #[validate]
+    #[serde(default)]
+    pub(crate) multicast: MulticastConfig,
+
     #[serde(default = "default_discovery")]
     pub(crate) discovery: DiscoveryMethods,
 
@@ -165,16 +716,35 @@ pub(crate) struct CameraConfig {
     ))]
     pub(crate) max_encryption: String,
 
+    /// Login again with HeaderAes when the media cannot be parsed under Aes
+    #[serde(default = "default_encryption_fallback")]
+    pub(crate) encryption_fallback: bool,
+
     #[serde(default = "default_strict")]
     /// If strict then the media stream will error in the event that the media packets are not as expected
     pub(crate) strict: bool,
 
+    /// Override the preview handles used to start the streams
+    #[serde(default)]
+    pub(crate) stream_handles: StreamHandlesConfig,
+
     #[serde(default = "default_print", alias = "print")]
     pub(crate) print_format: PrintFormat,
 
     #[serde(default = "default_update_time", alias = "time")]
     pub(crate) update_time: bool,
 
+    /// Keep setting the camera clock while connected, off without the section
+    #[validate]
+    #[serde(default)]
+    pub(crate) time_resync: Option<TimeResyncConfig>,
+
+    /// Keep the last seconds of the video of each stream for clips that start
+    /// before their trigger, off without the section
+    #[validate]
+    #[serde(default)]
+    pub(crate) prebuffer: Option<PrebufferConfig>,
+
     #[validate(range(
         min = 0,
         max = 500,
@@ -206,8 +776,159 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_true", alias = "push", alias = "push_noti")]
     pub(crate) push_notifications: bool,
 
+    /// The push notification registration to use, cameras with the same
+    /// account share one listener
+    #[validate(length(min = 1, message = "Invalid push account", code = "push_account"))]
+    #[serde(default = "default_push_account")]
+    pub(crate) push_account: String,
+
     #[serde(default = "default_false", alias = "idle", alias = "idle_disc")]
     pub(crate) idle_disconnect: bool,
+
+    /// How the connection is kept alive, `auto` sends nothing to battery
+    /// cameras so that they can sleep
+    #[serde(default)]
+    pub(crate) keep_alive: KeepAlive,
+
+    /// Seconds between two keep alive messages
+    #[validate(range(
+        min = 1,
+        max = 300,
+        message = "Invalid keep alive interval",
+        code = "keep_alive_interval"
+    ))]
+    #[serde(default = "default_keep_alive_interval")]
+    pub(crate) keep_alive_interval: u64,
+
+    /// Save the battery of the camera while it is low, off without the section
+    #[validate]
+    #[serde(default)]
+    pub(crate) battery_policy: Option<BatteryPolicyConfig>,
+
+    /// Time in ms to wait for the camera to accept a command, overrides the
+    /// global `reply_timeout`
+    #[validate(range(min = 100, message = "Invalid reply timeout", code = "reply_timeout"))]
+    #[serde(default)]
+    pub(crate) reply_timeout: Option<u64>,
+
+    /// Least time in ms between two PTZ movements, movements that arrive
+    /// quicker are merged and only the most recent is sent
+    #[validate(range(max = 5000, message = "Invalid ptz rate", code = "ptz_rate"))]
+    #[serde(default = "default_ptz_rate")]
+    pub(crate) ptz_rate: u64,
+}
+
+impl CameraConfig {
+    /// The camera of one channel of this NVR
+    fn nvr_channel(&self, channel: u8) -> Self {
+        let mut camera = self.clone();
+        camera.name = format!("{}/ch{channel}", self.name);
+        camera.channel_id = channel;
+        camera.nvr = None;
+        camera.nvr_name = Some(self.name.clone());
+        camera
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+#[validate(schema(function = "validate_nvr"))]
+pub(crate) struct NvrConfig {
+    /// The channels to serve, asked from the NVR when not given
+    #[serde(default)]
+    pub(crate) channels: Option<Vec<u8>>,
+}
+
+/// Changes in behaviour for a camera model
+///
+/// Only the quirks that are given are changed so an entry can also turn off a
+/// built in quirk by setting it to false
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct QuirkConfig {
+    /// The model as reported by the camera such as `E1`, not case sensitive
+    pub(crate) model: String,
+
+    /// Only apply to firmware versions that start with this such as `v3.0.0.1`
+    #[serde(default)]
+    pub(crate) firmware: Option<String>,
+
+    /// The camera does not reply to pings so do not send them
+    #[serde(default)]
+    pub(crate) no_ping: Option<bool>,
+
+    /// The camera cannot start the externStream so never use it
+    #[serde(default)]
+    pub(crate) broken_extern_stream: Option<bool>,
+
+    /// Login with at most this encryption, lowers the camera's `max_encryption`
+    #[validate(regex(
+        path = "RE_MAXENC_SRC",
+        message = "Invalid maximum encryption method",
+        code = "max_encryption"
+    ))]
+    #[serde(default)]
+    pub(crate) max_encryption: Option<String>,
+}
+
+/// An external input such as a door sensor that acts on cameras
+///
+/// It can be fired by any of its sources and by the trigger server
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct TriggerConfig {
+    /// Used in the logs and the path of the trigger server
+    pub(crate) name: String,
+
+    /// The cameras that the trigger acts on
+    pub(crate) cameras: Vec<String>,
+
+    /// Seconds that the cameras stay awake and recording after it fires
+    #[validate(range(
+        min = 1,
+        max = 3600,
+        message = "Invalid trigger duration",
+        code = "duration"
+    ))]
+    #[serde(default = "default_trigger_duration")]
+    pub(crate) duration: u64,
+
+    /// Turn on the floodlight for the duration
+    #[serde(default)]
+    pub(crate) floodlight: bool,
+
+    /// Fire on each message to this topic unless it is `off`, `false` or `0`
+    ///
+    /// This is the full topic such as `zigbee2mqtt/gate`, wildcards are allowed
+    #[serde(default)]
+    pub(crate) mqtt_topic: Option<String>,
+
+    /// Fire on each line printed by this shell command such as `gpiomon`
+    ///
+    /// The command is started again if it exits
+    #[serde(default)]
+    pub(crate) command: Option<String>,
+}
+
+/// Preview handles of the streams, the defaults are used for any not given
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamHandlesConfig {
+    #[serde(default, alias = "mainStream")]
+    pub(crate) main: Option<u32>,
+
+    #[serde(default, alias = "subStream")]
+    pub(crate) sub: Option<u32>,
+
+    #[serde(default, rename = "extern", alias = "externStream")]
+    pub(crate) extern_stream: Option<u32>,
+}
+
+impl StreamHandlesConfig {
+    pub(crate) fn handles(&self) -> StreamHandles {
+        let default = StreamHandles::default();
+        StreamHandles {
+            main: self.main.unwrap_or(default.main),
+            sub: self.sub.unwrap_or(default.sub),
+            extern_stream: self.extern_stream.unwrap_or(default.extern_stream),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq, Hash)]
@@ -218,6 +939,39 @@ pub(crate) struct UserConfig {
 
     #[serde(alias = "password")]
     pub(crate) pass: String,
+
+    /// A client certificate (PEM) that logs in as this user over rtsps
+    /// without the password
+    #[serde(default)]
+    pub(crate) certificate: Option<String>,
+
+    /// The cameras this user may watch, either `"Camera"` for all of its
+    /// streams or `"Camera/sub"` for only one. All cameras when not set
+    #[validate(custom = "validate_user_cameras")]
+    #[serde(default)]
+    pub(crate) cameras: Option<Vec<String>>,
+}
+
+impl UserConfig {
+    /// If this user may watch the stream of the camera
+    pub(crate) fn can_watch(&self, camera: &str, stream: StreamKind) -> bool {
+        let Some(cameras) = self.cameras.as_ref() else {
+            return true;
+        };
+        cameras.iter().any(|entry| match entry.split_once('/') {
+            Some((name, kind)) => name == camera && parse_stream_kind(kind) == Some(stream),
+            None => entry == camera,
+        })
+    }
+}
+
+fn parse_stream_kind(kind: &str) -> Option<StreamKind> {
+    match kind.to_lowercase().trim_end_matches("stream") {
+        "main" => Some(StreamKind::Main),
+        "sub" => Some(StreamKind::Sub),
+        "extern" => Some(StreamKind::Extern),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
@@ -263,19 +1017,267 @@ pub(crate) struct MqttConfig {
 
     #[serde(default)]
     pub(crate) discovery: Option<MqttDiscoveryConfig>,
+
+    /// Format of the status payloads that used to be raw xml
+    #[serde(default = "default_payload_format", alias = "format")]
+    pub(crate) payload_format: PayloadFormat,
+
+    /// Publish a jpeg to `status/snap` every this many ms, off when not set
+    #[validate(range(
+        min = 500,
+        message = "Update ms should be > 500",
+        code = "snap_interval"
+    ))]
+    #[serde(default)]
+    pub(crate) snap_interval: Option<u64>,
+
+    /// Publish a jpeg to `status/snap` each time motion starts
+    #[serde(default)]
+    pub(crate) snap_on_motion: bool,
+
+    /// Encoding of the `status/snap` jpegs
+    #[serde(default = "default_snap_format")]
+    pub(crate) snap_format: SnapFormat,
+
+    /// Time in ms that the motion must have stopped before `off` is published
+    #[serde(default)]
+    pub(crate) motion_debounce: u64,
+
+    /// Time in ms after an `off` before the next `on` is published
+    #[serde(default)]
+    pub(crate) motion_cooldown: u64,
+
+    /// Publish each motion as one `status/motion/event` with its duration
+    /// instead of the `on`/`off` of `status/motion`
+    #[serde(default)]
+    pub(crate) motion_events: bool,
+}
+
+impl MqttConfig {
+    pub(crate) fn publishes_snaps(&self) -> bool {
+        self.snap_interval.is_some() || self.snap_on_motion
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum PayloadFormat {
+    /// Versioned json with a `schema` field
+    #[serde(alias = "json")]
+    Json,
+    /// The camera's xml as it was published before json was added
+    #[serde(alias = "xml")]
+    Xml,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum SnapFormat {
+    /// Base64 text like `status/preview`
+    #[serde(alias = "base64")]
+    Base64,
+    /// The jpeg bytes as they are
+    #[serde(alias = "raw")]
+    Raw,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum AudioCodec {
+    /// Uncompressed 16 bit pcm at the camera's sample rate
+    #[serde(alias = "l16")]
+    L16,
+    /// G.711 mu-law at 8kHz
+    #[serde(alias = "pcmu", alias = "PCMU")]
+    Pcmu,
+    /// G.711 a-law at 8kHz
+    #[serde(alias = "pcma", alias = "PCMA")]
+    Pcma,
+    /// AAC encoded again after the camera's audio is decoded
+    #[serde(alias = "aac", alias = "AAC")]
+    Aac,
+    /// Opus at the rate the encoder picks
+    #[serde(alias = "opus")]
+    Opus,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Latency {
+    /// A small buffer that drops old data to stay close to live
+    #[serde(alias = "low")]
+    Low,
+    /// The default buffer of about 15s of video
+    #[serde(alias = "normal")]
+    Normal,
+    /// A large buffer that is not live so gstreamer can smooth out bursts
+    #[serde(alias = "smooth")]
+    Smooth,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq, Default)]
+pub(crate) struct RtpConfig {
+    /// Payload type of the video on every mount of the camera
+    #[validate(range(max = 127, message = "Invalid payload type", code = "video_pt"))]
+    #[serde(default)]
+    pub(crate) video_pt: Option<u32>,
+
+    /// Payload type of the audio on every mount of the camera
+    #[validate(range(max = 127, message = "Invalid payload type", code = "audio_pt"))]
+    #[serde(default)]
+    pub(crate) audio_pt: Option<u32>,
+
+    /// Seconds between the H264/H265 SPS/PPS sent in band, -1 sends them
+    /// with every keyframe and 0 never
+    #[validate(range(
+        min = -1,
+        max = 3600,
+        message = "Invalid config interval",
+        code = "config_interval"
+    ))]
+    #[serde(default)]
+    pub(crate) config_interval: Option<i32>,
+}
+
+/// Multicast groups of the streams, each stream has its own group
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct MulticastConfig {
+    #[validate]
+    #[serde(default, alias = "mainStream")]
+    pub(crate) main: Option<MulticastGroup>,
+
+    #[validate]
+    #[serde(default, alias = "subStream")]
+    pub(crate) sub: Option<MulticastGroup>,
+
+    #[validate]
+    #[serde(default, rename = "extern", alias = "externStream")]
+    pub(crate) extern_stream: Option<MulticastGroup>,
+}
+
+impl MulticastConfig {
+    pub(crate) fn group(&self, stream: StreamKind) -> Option<&MulticastGroup> {
+        match stream {
+            StreamKind::Main => self.main.as_ref(),
+            StreamKind::Sub => self.sub.as_ref(),
+            StreamKind::Extern => self.extern_stream.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct MulticastGroup {
+    /// The multicast address that the stream is sent to
+    #[validate(custom = "validate_multicast_address")]
+    pub(crate) address: String,
+
+    /// The first of the four ports of the video and audio RTP and RTCP
+    #[validate(range(min = 1024, max = 65532, message = "Invalid port", code = "port"))]
+    pub(crate) port: u16,
+
+    /// How many routers the packets may cross
+    #[validate(range(min = 1, message = "Invalid ttl", code = "ttl"))]
+    #[serde(default = "default_multicast_ttl")]
+    pub(crate) ttl: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct PrebufferConfig {
+    /// Seconds of video that are kept
+    #[validate(range(min = 1, max = 600, message = "Invalid prebuffer", code = "seconds"))]
+    #[serde(default = "default_prebuffer_seconds")]
+    pub(crate) seconds: u64,
+
+    /// Directory for the frames, such as a tmpfs, they are kept in memory when not set
+    #[serde(default)]
+    pub(crate) dir: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+#[validate(schema(function = "validate_battery_policy", skip_on_field_errors = true))]
+pub(crate) struct BatteryPolicyConfig {
+    /// Battery percent at or below which the camera is low
+    #[validate(range(max = 100, message = "Invalid low battery", code = "low"))]
+    #[serde(default = "default_battery_low")]
+    pub(crate) low: u32,
+
+    /// Battery percent at or above which the camera is no longer low
+    #[validate(range(max = 100, message = "Invalid recover battery", code = "recover"))]
+    #[serde(default = "default_battery_recover")]
+    pub(crate) recover: u32,
+
+    /// Seconds between the reads of the battery, it is only read while connected
+    #[validate(range(min = 10, message = "Invalid battery interval", code = "interval"))]
+    #[serde(default = "default_battery_interval")]
+    pub(crate) interval: u64,
+
+    /// Serve the subStream to the clients of the mainStream while low
+    #[serde(default = "default_true")]
+    pub(crate) sub_stream: bool,
+
+    /// Disconnect while idle when low even without `idle_disconnect`
+    #[serde(default = "default_true")]
+    pub(crate) idle_disconnect: bool,
+
+    /// Seconds without a user before disconnecting while low
+    #[serde(default = "default_battery_idle_timeout")]
+    pub(crate) idle_timeout: u64,
+
+    /// Stop filling the prebuffer while low
+    #[serde(default = "default_true")]
+    pub(crate) pause_prebuffer: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct TimeResyncConfig {
+    /// Seconds between the checks of the camera clock
+    #[validate(range(min = 60, message = "Invalid resync interval", code = "interval"))]
+    #[serde(default = "default_resync_interval")]
+    pub(crate) interval: u64,
+
+    /// Seconds the camera clock may be off before it is set again
+    #[validate(range(min = 1, message = "Invalid max drift", code = "max_drift"))]
+    #[serde(default = "default_max_drift")]
+    pub(crate) max_drift: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
 pub(crate) struct MqttDiscoveryConfig {
     pub(crate) topic: String,
 
+    /// Picked from the camera's support matrix when empty
+    #[serde(default)]
     pub(crate) features: HashSet<Discoveries>,
 }
 
 fn validate_mqtt_server(config: &MqttServerConfig) -> Result<(), ValidationError> {
-    if config.ca.is_some() && config.client_auth.is_some() {
+    if config.ca.is_none() && config.client_auth.is_some() {
+        Err(ValidationError::new(
+            "client_auth needs the ca of the broker to be set",
+        ))
+    } else if config.protocol != MqttProtocol::V5
+        && (config.session_expiry.is_some() || config.topic_aliases > 0)
+    {
+        Err(ValidationError::new(
+            "session_expiry and topic_aliases need protocol = \"v5\"",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_nvr(config: &NvrConfig) -> Result<(), ValidationError> {
+    match config.channels.as_ref() {
+        Some(channels) if channels.is_empty() => Err(ValidationError::new(
+            "An NVR needs at least one channel, remove channels to ask the NVR",
+        )),
+        Some(channels) if channels.iter().any(|channel| *channel > 31) => {
+            Err(ValidationError::new("Invalid channel"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_battery_policy(config: &BatteryPolicyConfig) -> Result<(), ValidationError> {
+    if config.recover < config.low {
         Err(ValidationError::new(
-            "Cannot have both ca and client_auth set",
+            "recover must be at least the low battery percent",
         ))
     } else {
         Ok(())
@@ -286,6 +1288,10 @@ const fn default_true() -> bool {
     true
 }
 
+fn default_push_account() -> String {
+    "default".to_string()
+}
+
 const fn default_false() -> bool {
     false
 }
@@ -301,9 +1307,36 @@ fn default_mqtt() -> MqttConfig {
         enable_floodlight: true,
         floodlight_update: 2000,
         discovery: Default::default(),
+        payload_format: default_payload_format(),
+        snap_interval: None,
+        snap_on_motion: false,
+        snap_format: default_snap_format(),
+        motion_debounce: 0,
+        motion_cooldown: 0,
+        motion_events: false,
     }
 }
 
+fn default_snap_format() -> SnapFormat {
+    SnapFormat::Base64
+}
+
+fn default_mqtt_protocol() -> MqttProtocol {
+    MqttProtocol::V3
+}
+
+fn default_payload_format() -> PayloadFormat {
+    PayloadFormat::Json
+}
+
+fn default_audio_codec() -> AudioCodec {
+    AudioCodec::L16
+}
+
+fn default_latency() -> Latency {
+    Latency::Normal
+}
+
 fn default_print() -> PrintFormat {
     PrintFormat::None
 }
@@ -327,6 +1360,21 @@ pub(crate) struct PauseConfig {
     #[serde(default = "default_motion_timeout", alias = "timeout")]
     pub(crate) motion_timeout: f64,
 
+    /// With `on_motion` a push notification or doorbell press wakes the
+    /// camera and streams it even without a client
+    #[serde(default = "default_false", alias = "wake")]
+    pub(crate) wake_on_push: bool,
+
+    /// Seconds the camera is streamed and recorded after `wake_on_push`
+    #[validate(range(
+        min = 1,
+        max = 3600,
+        message = "Invalid wake window",
+        code = "wake_window"
+    ))]
+    #[serde(default = "default_wake_window")]
+    pub(crate) wake_window: u64,
+
     #[serde(default = "default_pause_mode")]
     #[validate(regex(
         path = "RE_PAUSE_MODE",
@@ -427,10 +1475,188 @@ impl std::fmt::Display for SplashPattern {
     }
 }
 
+fn default_onvif() -> OnvifConfig {
+    OnvifConfig {
+        bind_addr: default_bind_addr(),
+        bind_port: default_onvif_port(),
+        discovery: true,
+    }
+}
+
+fn default_onvif_port() -> u16 {
+    8000
+}
+
+fn default_webrtc() -> WebRtcConfig {
+    WebRtcConfig {
+        bind_addr: default_bind_addr(),
+        bind_port: default_webrtc_port(),
+        stun_server: None,
+    }
+}
+
+fn default_webrtc_port() -> u16 {
+    8889
+}
+
+fn default_hls() -> HlsConfig {
+    HlsConfig {
+        bind_addr: default_bind_addr(),
+        bind_port: default_hls_port(),
+        segment_duration: default_hls_segment_duration(),
+        part_duration: default_hls_part_duration(),
+        segments: default_hls_segments(),
+        low_latency: true,
+        secret: None,
+    }
+}
+
+fn default_hls_port() -> u16 {
+    8888
+}
+
+fn default_hls_segment_duration() -> f64 {
+    2.0
+}
+
+fn default_hls_part_duration() -> f64 {
+    0.5
+}
+
+fn default_hls_segments() -> usize {
+    6
+}
+
+fn default_record() -> RecordConfig {
+    RecordConfig {
+        segment_duration: default_record_segment_duration(),
+        retention_days: None,
+        on_motion: false,
+        pre_roll: default_record_pre_roll(),
+        post_roll: default_record_post_roll(),
+    }
+}
+
+fn default_record_pre_roll() -> u64 {
+    5
+}
+
+fn default_record_post_roll() -> u64 {
+    10
+}
+
+fn default_record_segment_duration() -> u64 {
+    600
+}
+
+fn default_snapshot() -> SnapshotConfig {
+    SnapshotConfig {
+        dir: None,
+        webhook: None,
+    }
+}
+
+fn default_timelapse() -> TimelapseConfig {
+    TimelapseConfig {
+        dir: None,
+        interval: default_timelapse_interval(),
+        assemble: false,
+        fps: default_timelapse_fps(),
+        delete_frames: false,
+    }
+}
+
+fn default_timelapse_interval() -> u64 {
+    60
+}
+
+fn default_timelapse_fps() -> u32 {
+    25
+}
+
+fn default_webhook_retries() -> u32 {
+    5
+}
+
+fn default_webhook_poll_interval() -> u64 {
+    60
+}
+
+fn default_crash_report() -> CrashReportConfig {
+    CrashReportConfig {
+        dir: default_crash_dir(),
+        webhook: None,
+    }
+}
+
+fn default_crash_dir() -> std::path::PathBuf {
+    "crash_reports".into()
+}
+
+fn default_release_channel() -> ReleaseChannel {
+    ReleaseChannel::Stable
+}
+
+fn default_update_interval() -> u64 {
+    24
+}
+
+fn default_update_repository() -> String {
+    "QuantumEntangledAndy/neolink".to_string()
+}
+
+fn default_log_dir() -> std::path::PathBuf {
+    "logs".into()
+}
+
+fn default_log_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_log_keep() -> usize {
+    5
+}
+
+fn default_metrics_port() -> u16 {
+    9090
+}
+
+fn default_health_port() -> u16 {
+    9091
+}
+
+fn default_trigger_port() -> u16 {
+    9092
+}
+
+fn default_rtsp_admin_port() -> u16 {
+    9093
+}
+
+fn default_trigger_duration() -> u64 {
+    30
+}
+
+fn default_otel_endpoint() -> String {
+    "http://localhost:4317".to_string()
+}
+
+fn default_otel_service_name() -> String {
+    "neolink".to_string()
+}
+
+fn default_otel_interval() -> u64 {
+    60
+}
+
 fn default_bind_addr() -> String {
     "0.0.0.0".to_string()
 }
 
+fn default_local_bind_addr() -> String {
+    "127.0.0.1".to_string()
+}
+
 fn default_bind_port() -> u16 {
     8554
 }
@@ -451,6 +1677,10 @@ fn default_tokio_console() -> bool {
     false
 }
 
+fn default_startup_timeout() -> u64 {
+    30000
+}
+
 fn default_channel_id() -> u8 {
     0
 }
@@ -459,10 +1689,51 @@ fn default_update_time() -> bool {
     false
 }
 
+fn default_prebuffer_seconds() -> u64 {
+    10
+}
+
+fn default_multicast_ttl() -> u8 {
+    16
+}
+
+fn default_keep_alive_interval() -> u64 {
+    5
+}
+
+fn default_battery_low() -> u32 {
+    20
+}
+
+fn default_battery_recover() -> u32 {
+    30
+}
+
+fn default_battery_interval() -> u64 {
+    60
+}
+
+fn default_battery_idle_timeout() -> u64 {
+    10
+}
+
+fn default_resync_interval() -> u64 {
+    // Daily
+    24 * 60 * 60
+}
+
+fn default_max_drift() -> u64 {
+    5
+}
+
 fn default_motion_timeout() -> f64 {
     1.
 }
 
+fn default_wake_window() -> u64 {
+    30
+}
+
 fn default_on_disconnect() -> bool {
     false
 }
@@ -479,11 +1750,17 @@ fn default_strict() -> bool {
     false
 }
 
+fn default_encryption_fallback() -> bool {
+    true
+}
+
 fn default_pause() -> PauseConfig {
     PauseConfig {
         on_motion: default_on_motion(),
         on_disconnect: default_on_disconnect(),
         motion_timeout: default_motion_timeout(),
+        wake_on_push: false,
+        wake_window: default_wake_window(),
         mode: default_pause_mode(),
     }
 }
@@ -492,6 +1769,10 @@ fn default_buffer_size() -> usize {
     25
 }
 
+fn default_ptz_rate() -> u64 {
+    200
+}
+
 fn default_max_discovery_retries() -> usize {
     10
 }
@@ -515,6 +1796,26 @@ fn validate_username(name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_multicast_address(address: &str) -> Result<(), ValidationError> {
+    match address.parse::<std::net::IpAddr>() {
+        Ok(ip) if ip.is_multicast() => Ok(()),
+        _ => Err(ValidationError::new("Not a multicast address")),
+    }
+}
+
+fn validate_user_cameras(cameras: &[String]) -> Result<(), ValidationError> {
+    for entry in cameras {
+        if let Some((_, kind)) = entry.split_once('/') {
+            if parse_stream_kind(kind).is_none() {
+                return Err(ValidationError::new(
+                    "The stream of a user's camera must be main, sub or extern",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), ValidationError> {
     match (&camera_config.camera_addr, &camera_config.camera_uid) {
         (None, None) => Err(ValidationError::new(
@@ -0,0 +1,23 @@
+use clap::{Parser, Subcommand};
+
+/// The config command checks a config file or writes a starter one
+#[derive(Parser, Debug)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub cmd: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Check the file given by --config and print each problem with it
+    Validate,
+    /// Print a starter config to stdout
+    Init {
+        /// Scan the local network for cameras and add each one that replies
+        #[arg(long)]
+        discover: bool,
+        /// How many seconds to wait for replies when scanning
+        #[arg(long, default_value_t = 5)]
+        wait: u64,
+    },
+}
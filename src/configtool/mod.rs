@@ -0,0 +1,148 @@
+///
+/// # Neolink Config
+///
+/// This module checks config files and writes new ones
+///
+/// `validate` reports every field that failed validation with its path in the
+/// file. `init` prints a starter config, with `--discover` the local networks
+/// are scanned with a udp broadcast and every camera that replies is added
+/// with its address and, when the reply contains one, its UID.
+///
+/// This command does not need a working config so it runs before the cameras
+/// are started.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink config --config=config.toml validate
+/// neolink config init --discover > config.toml
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc_protocol::{scan_local, LocalCamera};
+use std::{fmt::Write, path::Path, time::Duration};
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+mod cmdline;
+
+use crate::config::Config;
+use crate::i18n::tr;
+
+pub(crate) use cmdline::{ConfigCommand, Opt};
+
+/// Entry point for the config subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config: Option<&Path>) -> Result<()> {
+    match opt.cmd {
+        ConfigCommand::Validate => validate(config.context(tr!("config.missing"))?),
+        ConfigCommand::Init { discover, wait } => {
+            let cameras = if discover {
+                let cameras = scan_local(Duration::from_secs(wait)).await?;
+                log::info!("{}", tr!("configtool.found", count = cameras.len()));
+                cameras
+            } else {
+                vec![]
+            };
+            print!("{}", starter_config(&cameras));
+            Ok(())
+        }
+    }
+}
+
+fn validate(path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| tr!("config.read_failed", path = format!("{:?}", path)))?;
+    // Toml errors already point at the line and column
+    let config: Config = toml::from_str(&text)
+        .with_context(|| tr!("config.parse_failed", path = format!("{:?}", path)))?;
+    match config.validate() {
+        Ok(()) => {
+            println!("{}", tr!("configtool.valid", path = format!("{:?}", path)));
+            Ok(())
+        }
+        Err(errors) => {
+            let mut problems = vec![];
+            collect_errors("", &errors, &mut problems);
+            for problem in problems.iter() {
+                println!("{problem}");
+            }
+            Err(anyhow!(tr!(
+                "configtool.invalid",
+                path = format!("{:?}", path),
+                count = problems.len()
+            )))
+        }
+    }
+}
+
+/// Flatten the errors into lines like `cameras[0].max_encryption: message`
+fn collect_errors(prefix: &str, errors: &ValidationErrors, out: &mut Vec<String>) {
+    let mut fields: Vec<_> = errors.errors().iter().collect();
+    fields.sort_by_key(|(field, _)| *field);
+    for (field, kind) in fields {
+        // Schema level errors are stored under `__all__`
+        let path = match (*field, prefix) {
+            ("__all__", "") => "<root>".to_string(),
+            ("__all__", prefix) => prefix.to_string(),
+            (field, "") => field.to_string(),
+            (field, prefix) => format!("{prefix}.{field}"),
+        };
+        match kind {
+            ValidationErrorsKind::Struct(inner) => collect_errors(&path, inner, out),
+            ValidationErrorsKind::List(items) => {
+                for (i, inner) in items.iter() {
+                    collect_errors(&format!("{path}[{i}]"), inner, out);
+                }
+            }
+            ValidationErrorsKind::Field(field_errors) => {
+                for error in field_errors.iter() {
+                    let mut line = format!("{path}: ");
+                    match error.message.as_ref() {
+                        Some(message) => line.push_str(message),
+                        None => line.push_str(&error.code),
+                    }
+                    if let Some(value) = error.params.get("value") {
+                        let _ = write!(line, " (got {value})");
+                    }
+                    out.push(line);
+                }
+            }
+        }
+    }
+}
+
+fn starter_config(cameras: &[LocalCamera]) -> String {
+    let mut out = String::from("bind = \"0.0.0.0\"\n");
+    if cameras.is_empty() {
+        out.push_str(
+            "\n[[cameras]]\nname = \"Camera01\"\nusername = \"admin\"\npassword = \"password\"\naddress = \"192.168.1.10:9000\"\n",
+        );
+    }
+    for (i, camera) in cameras.iter().enumerate() {
+        out.push_str(&camera_toml(
+            &format!("Camera{:02}", i + 1),
+            camera,
+            "admin",
+            "password",
+        ));
+    }
+    out
+}
+
+/// The `[[cameras]]` block of a camera found on the local network
+pub(crate) fn camera_toml(
+    name: &str,
+    camera: &LocalCamera,
+    username: &str,
+    password: &str,
+) -> String {
+    let mut out = format!(
+        "\n[[cameras]]\nname = \"{name}\"\nusername = \"{username}\"\npassword = \"{password}\"\naddress = \"{}:9000\"\n",
+        camera.addr
+    );
+    if let Some(uid) = camera.uid.as_ref() {
+        let _ = writeln!(out, "uid = \"{uid}\"");
+    }
+    out
+}
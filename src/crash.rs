@@ -0,0 +1,242 @@
+//!
+//! # Crash Reports
+//!
+//! A panic hook that writes a crash report to `{dir}/crash-{time}.txt` before
+//! the normal panic output. The report has the version, platform, panic
+//! message, backtrace and the models of the connected cameras so that it can
+//! be attached to a bug report as is.
+//!
+//! Passwords, mqtt credentials and camera UIDs from the config are replaced
+//! with `<redacted>` everywhere in the report.
+//!
+//! When `webhook` is set a json summary of the crash is also POSTed to it.
+//!
+//! ```toml
+//! [crash_report]
+//!   dir = "crash_reports"
+//!   webhook = "https://example.com/neolink-crash"
+//! ```
+//!
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use neolink_core::bc::xml::VersionInfo;
+use serde::Serialize;
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    panic::Location,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::config::{Config, CrashReportConfig};
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Default)]
+struct CrashState {
+    config: Option<CrashReportConfig>,
+    /// Strings that must not appear in a report
+    secrets: Vec<String>,
+    /// Camera name to a description of its model
+    cameras: BTreeMap<String, String>,
+}
+
+lazy_static! {
+    static ref STATE: RwLock<CrashState> = Default::default();
+}
+
+#[derive(Serialize)]
+struct CrashWebhook<'a> {
+    schema: &'static str,
+    version: &'static str,
+    message: &'a str,
+    location: Option<String>,
+    report: Option<String>,
+}
+
+/// Install the panic hook, this keeps the default hook's output
+pub(crate) fn install() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        report(info.payload(), info.location());
+        default_hook(info);
+    }));
+}
+
+/// Set the report location and the secrets to redact from the config
+pub(crate) fn set_config(config: &Config) {
+    let mut secrets = config
+        .cameras
+        .iter()
+        .flat_map(|camera| [camera.password.clone(), camera.camera_uid.clone()])
+        .flatten()
+        .chain(config.users.iter().map(|user| user.pass.clone()))
+        .chain(
+            config
+                .mqtt
+                .iter()
+                .filter_map(|mqtt| mqtt.credentials.clone())
+                .flat_map(|(user, pass)| [user, pass]),
+        )
+        .filter(|secret| !secret.is_empty())
+        .collect::<Vec<_>>();
+    // Longest first so that a secret containing another is fully replaced
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.dedup();
+
+    if let Ok(mut state) = STATE.write() {
+        state.config = Some(config.crash_report.clone());
+        state.secrets = secrets;
+    }
+}
+
+/// Record the model of a camera for the crash reports
+pub(crate) fn set_camera_model(name: &str, version: &VersionInfo) {
+    let model = format!(
+        "{} hw:{} fw:{}",
+        version.model.as_deref().unwrap_or("unknown"),
+        version.hardwareVersion,
+        version.firmwareVersion
+    );
+    if let Ok(mut state) = STATE.write() {
+        state.cameras.insert(name.to_string(), model);
+    }
+}
+
+fn report(payload: &(dyn Any + Send), location: Option<&Location>) {
+    // A panic while the state is locked must not deadlock the hook
+    let state = match STATE.try_read() {
+        Ok(state) => state,
+        Err(_) => return,
+    };
+    let config = match state.config.as_ref() {
+        Some(config) => config,
+        None => return,
+    };
+    let redact = |text: &str| {
+        state.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    };
+
+    let message = redact(
+        payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+            .unwrap_or("Box<dyn Any>"),
+    );
+    let location = location.map(|location| location.to_string());
+    let report = redact(&format_report(
+        &message,
+        location.as_deref(),
+        &state.cameras,
+    ));
+
+    let path = match write_report(&config.dir, &report) {
+        Ok(path) => {
+            eprintln!("Crash report written to {}", path.display());
+            Some(path.display().to_string())
+        }
+        Err(e) => {
+            eprintln!("Failed to write crash report: {e:?}");
+            None
+        }
+    };
+
+    if let Some(url) = config.webhook.clone() {
+        let body = CrashWebhook {
+            schema: "neolink.crash.v1",
+            version: env!("NEOLINK_VERSION"),
+            message: &message,
+            location,
+            report: path,
+        };
+        if let Err(e) = send_webhook(url, &body) {
+            eprintln!("Failed to send crash webhook: {e:?}");
+        }
+    }
+}
+
+fn format_report(
+    message: &str,
+    location: Option<&str>,
+    cameras: &BTreeMap<String, String>,
+) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "Neolink crash report");
+    let _ = writeln!(
+        report,
+        "Time: {}",
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    let _ = writeln!(
+        report,
+        "Version: {} {}",
+        env!("NEOLINK_VERSION").trim(),
+        env!("NEOLINK_PROFILE")
+    );
+    let _ = writeln!(
+        report,
+        "Platform: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        report,
+        "Thread: {}",
+        std::thread::current().name().unwrap_or("<unnamed>")
+    );
+    let _ = writeln!(report, "Location: {}", location.unwrap_or("<unknown>"));
+    let _ = writeln!(report, "Message: {message}");
+    let _ = writeln!(report);
+    let _ = writeln!(report, "Cameras:");
+    for (name, model) in cameras.iter() {
+        let _ = writeln!(report, "  {name}: {model}");
+    }
+    let _ = writeln!(report);
+    let _ = writeln!(report, "Backtrace:");
+    let _ = writeln!(report, "{}", Backtrace::force_capture());
+    report
+}
+
+fn write_report(dir: &Path, report: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create the crash report directory {dir:?}"))?;
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = dir.join(format!("crash-{time}.txt"));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn send_webhook(url: String, body: &CrashWebhook) -> Result<()> {
+    let body = serde_json::to_string(body)?;
+    // The panicking thread may be a runtime worker so use a fresh runtime
+    // on a thread of its own
+    std::thread::spawn(move || -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            reqwest::Client::new()
+                .post(url)
+                .timeout(Duration::from_secs(10))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    })
+    .join()
+    .map_err(|_| anyhow::anyhow!("Webhook thread panicked"))?
+}
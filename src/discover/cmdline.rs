@@ -0,0 +1,18 @@
+use clap::Parser;
+
+/// The discover command lists the cameras on the local network
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// How many seconds to wait for replies
+    #[arg(long, default_value_t = 5)]
+    pub wait: u64,
+    /// Login with this username to read the model and firmware
+    #[arg(long)]
+    pub username: Option<String>,
+    /// The password to login with
+    #[arg(long)]
+    pub password: Option<String>,
+    /// Print a `[[cameras]]` config block for each camera instead of a table
+    #[arg(long)]
+    pub toml: bool,
+}
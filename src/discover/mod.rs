@@ -0,0 +1,137 @@
+///
+/// # Neolink Discover
+///
+/// This module lists the cameras on the local network
+///
+/// A udp broadcast is sent on every local network and each camera that
+/// replies is listed with its ip address and, when the reply contains one,
+/// its UID. The model and firmware can only be read after a login so they
+/// are only listed when a `--username` is given.
+///
+/// This command does not need a config.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink discover
+/// neolink discover --username=admin --password=password --toml >> config.toml
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::{
+    bc::xml::VersionInfo,
+    bc_protocol::{
+        scan_local, BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods,
+        LocalCamera,
+    },
+};
+use std::time::Duration;
+
+mod cmdline;
+
+use crate::configtool::camera_toml;
+use crate::i18n::tr;
+use crate::utils::timeout;
+
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the discover subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt) -> Result<()> {
+    let cameras = scan_local(Duration::from_secs(opt.wait)).await?;
+    log::info!("{}", tr!("configtool.found", count = cameras.len()));
+
+    let mut found = vec![];
+    for camera in cameras.iter() {
+        let version = match opt.username.as_ref() {
+            Some(username) => match version(camera, username, opt.password.as_deref()).await {
+                Ok(version) => Some(version),
+                Err(e) => {
+                    log::warn!("{}: {e:?}", camera.addr);
+                    None
+                }
+            },
+            None => None,
+        };
+        found.push((camera, version));
+    }
+
+    if opt.toml {
+        for (i, (camera, version)) in found.iter().enumerate() {
+            if let Some(version) = version.as_ref() {
+                print!(
+                    "\n# {} {}",
+                    version.model.as_deref().unwrap_or("Unknown"),
+                    version.firmwareVersion
+                );
+            }
+            print!(
+                "{}",
+                camera_toml(
+                    &format!("Camera{:02}", i + 1),
+                    camera,
+                    opt.username.as_deref().unwrap_or("admin"),
+                    opt.password.as_deref().unwrap_or("password"),
+                )
+            );
+        }
+    } else {
+        println!("{:<16} {:<20} {:<16} FIRMWARE", "IP", "UID", "MODEL");
+        for (camera, version) in found.iter() {
+            println!(
+                "{:<16} {:<20} {:<16} {}",
+                camera.addr.to_string(),
+                camera.uid.as_deref().unwrap_or("-"),
+                version
+                    .as_ref()
+                    .and_then(|version| version.model.as_deref())
+                    .unwrap_or("-"),
+                version
+                    .as_ref()
+                    .map(|version| version.firmwareVersion.as_str())
+                    .unwrap_or("-"),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Login to a discovered camera and read its version
+async fn version(
+    camera: &LocalCamera,
+    username: &str,
+    password: Option<&str>,
+) -> Result<VersionInfo> {
+    let options = BcCameraOpt {
+        name: camera.addr.to_string(),
+        channel_id: 0,
+        addrs: vec![camera.addr],
+        port: None,
+        uid: camera.uid.clone(),
+        protocol: ConnectionProtocol::TcpUdp,
+        discovery: DiscoveryMethods::Local,
+        credentials: Credentials {
+            username: username.to_string(),
+            password: password.map(str::to_string),
+        },
+        debug: false,
+        max_discovery_retries: 1,
+        reply_timeout: None,
+        stream_handles: Default::default(),
+        capture_dir: None,
+    };
+    let bc_camera = timeout(BcCamera::new(&options))
+        .await
+        .context("Timed out connecting")??;
+    timeout(bc_camera.login())
+        .await
+        .context("Timed out logging in")??;
+    let version = timeout(bc_camera.version())
+        .await
+        .context("Timed out reading the version")??;
+    let _ = bc_camera.logout().await;
+    let _ = bc_camera.shutdown().await;
+    Ok(version)
+}
@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::StreamKind;
+
+fn stream_kind(name: &str) -> Result<StreamKind, String> {
+    match name {
+        "main" | "mainStream" => Ok(StreamKind::Main),
+        "sub" | "subStream" => Ok(StreamKind::Sub),
+        "extern" | "externStream" => Ok(StreamKind::Extern),
+        _ => Err(format!("Unknown stream {name}, use main, sub or extern")),
+    }
+}
+
+fn resolution_parse(src: &str) -> Result<(u32, u32)> {
+    let (width, height) = src
+        .split_once(|c: char| c == 'x' || c == '*')
+        .ok_or_else(|| {
+            anyhow!("Could not understand {src}, should be WIDTHxHEIGHT such as 1920x1080")
+        })?;
+    Ok((width.trim().parse()?, height.trim().parse()?))
+}
+
+/// The encode command will change the resolution, bit rate and frame rate of a stream
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The stream to change: main, sub or extern
+    #[arg(short, long, default_value = "main", value_parser = stream_kind)]
+    pub stream: StreamKind,
+    /// The resolution such as 1920x1080
+    #[arg(long, value_parser = resolution_parse, value_name = "WIDTHxHEIGHT")]
+    pub resolution: Option<(u32, u32)>,
+    /// The bit rate in kbps
+    #[arg(long)]
+    pub bitrate: Option<u32>,
+    /// The frame rate
+    #[arg(long)]
+    pub fps: Option<u32>,
+}
@@ -0,0 +1,85 @@
+///
+/// # Neolink Encode
+///
+/// This module changes the resolution, bit rate and frame rate of the camera
+/// streams, for example to lower the bit rate over a slow link
+///
+/// Without any changes it prints the current settings of the stream and the
+/// values that the camera supports
+///
+/// # Usage
+///
+/// ```bash
+/// # To list the supported values of the sub stream
+/// neolink encode --config=config.toml CameraName --stream sub
+/// # To lower the bit rate and frame rate of the main stream
+/// neolink encode --config=config.toml CameraName --bitrate 1024 --fps 15
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::EncodeChange;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the encode subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let stream = opt.stream;
+
+    let change = EncodeChange {
+        resolution: opt.resolution,
+        bit_rate: opt.bitrate,
+        frame_rate: opt.fps,
+    };
+    if change == EncodeChange::default() {
+        let (info, compression) = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    let info = cam.get_stream_info().await?;
+                    let compression = cam.get_compression().await?;
+                    Ok((info, compression))
+                })
+            })
+            .await
+            .context(tr!("encode.get_failed"))?;
+        if let Some(current) = compression.stream(stream) {
+            println!(
+                "{}: {}x{} {}fps {}kbps",
+                stream, current.width, current.height, current.frame, current.bit_rate
+            );
+        }
+        for table in info
+            .stream_infos
+            .iter()
+            .flat_map(|info| info.encode_tables.iter())
+            .filter(|table| table.name == stream.to_string())
+        {
+            println!(
+                "  {}x{}: fps {:?} kbps {:?}",
+                table.resolution.width,
+                table.resolution.height,
+                table.framerate_table,
+                table.bitrate_table
+            );
+        }
+        return Ok(());
+    }
+
+    camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.set_stream_info(stream, &change)
+                    .await
+                    .context(tr!("encode.set_failed"))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Exit codes and the error output of the CLI
+//!
+//! Each class of failure has its own exit code so that scripts can tell them
+//! apart without reading the logs:
+//!
+//! | Code | Class          | Meaning                                            |
+//! |------|----------------|----------------------------------------------------|
+//! | 0    |                | Success                                            |
+//! | 1    | `other`        | Any failure not listed below                       |
+//! | 2    |                | Invalid command line, reported by clap             |
+//! | 3    | `config`       | The config is missing, cannot be read or is invalid|
+//! | 4    | `unreachable`  | The camera could not be reached                    |
+//! | 5    | `auth_failed`  | The camera refused the username or password        |
+//! | 6    | `unsupported`  | The camera or this build does not support it       |
+//!
+//! With `--error-format json` the error is written to stderr as a single line
+//! of json instead of text.
+use serde::Serialize;
+use std::{fmt, process::ExitCode};
+
+use crate::cmdline::ErrorFormat;
+use neolink_core::Error as CoreError;
+
+/// The class of a failure, each has its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorClass {
+    Other,
+    Config,
+    Unreachable,
+    AuthFailed,
+    Unsupported,
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Shown when the class is added as the context of an error
+        f.write_str(match self {
+            ErrorClass::Other => "Failed",
+            ErrorClass::Config => "Invalid config",
+            ErrorClass::Unreachable => "Camera unreachable",
+            ErrorClass::AuthFailed => "Camera login failed",
+            ErrorClass::Unsupported => "Not supported",
+        })
+    }
+}
+
+impl ErrorClass {
+    pub(crate) fn exit_code(self) -> u8 {
+        match self {
+            ErrorClass::Other => 1,
+            ErrorClass::Config => 3,
+            ErrorClass::Unreachable => 4,
+            ErrorClass::AuthFailed => 5,
+            ErrorClass::Unsupported => 6,
+        }
+    }
+
+    /// Find the class from a class added as context or the camera's error
+    pub(crate) fn of(error: &anyhow::Error) -> Self {
+        if let Some(class) = error.downcast_ref::<ErrorClass>() {
+            return *class;
+        }
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<CoreError>())
+            .map(|error| match error {
+                CoreError::AuthFailed | CoreError::CameraLoginFail => ErrorClass::AuthFailed,
+                CoreError::CannotInitCamera
+                | CoreError::ConnectionUnavaliable
+                | CoreError::DroppedConnection
+                | CoreError::TimeoutDisconnected
+                | CoreError::Timeout(_)
+                | CoreError::AddrResolutionError
+                | CoreError::DiscoveryTimeout => ErrorClass::Unreachable,
+                CoreError::CameraServiceUnavaliable(_) | CoreError::MissingAbility { .. } => {
+                    ErrorClass::Unsupported
+                }
+                _ => ErrorClass::Other,
+            })
+            .unwrap_or(ErrorClass::Other)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorV1 {
+    schema: &'static str,
+    class: ErrorClass,
+    exit_code: u8,
+    message: String,
+    causes: Vec<String>,
+}
+
+/// Write the error to stderr and return its exit code
+pub(crate) fn report(error: &anyhow::Error, format: ErrorFormat) -> ExitCode {
+    let class = ErrorClass::of(error);
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {error:?}"),
+        ErrorFormat::Json => {
+            let payload = ErrorV1 {
+                schema: "neolink.error.v1",
+                class,
+                exit_code: class.exit_code(),
+                message: error.to_string(),
+                causes: error
+                    .chain()
+                    .skip(1)
+                    .map(|cause| cause.to_string())
+                    .collect(),
+            };
+            match serde_json::to_string(&payload) {
+                Ok(json) => eprintln!("{json}"),
+                Err(_) => eprintln!("Error: {error:?}"),
+            }
+        }
+    }
+    ExitCode::from(class.exit_code())
+}
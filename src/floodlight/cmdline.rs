@@ -0,0 +1,28 @@
+use clap::{Parser, ValueEnum};
+
+/// The floodlight command will control the floodlight of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// What to do with the floodlight
+    #[arg(value_enum)]
+    pub action: FloodlightAction,
+    /// How many seconds the light stays on for with `on`
+    #[arg(long, default_value_t = 180)]
+    pub duration: u16,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FloodlightAction {
+    /// Turn the light on for `--duration` seconds
+    On,
+    /// Turn the light off
+    Off,
+    /// Let the camera turn the light on by itself such as on motion at night
+    Auto,
+    /// Stop the camera turning the light on by itself
+    Manual,
+    /// Print the FloodlightTask xml that holds the automatic settings
+    Task,
+}
@@ -0,0 +1,88 @@
+///
+/// # Neolink Floodlight
+///
+/// This module handles the controls of the floodlight
+///
+/// The light can be turned on for a number of seconds or off. The camera can
+/// also turn it on by itself, such as on motion at night, which are its
+/// floodlight tasks. `auto` and `manual` turn these tasks on and off.
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the light on for five minutes
+/// neolink floodlight --config=config.toml CameraName on --duration 300
+/// # Or off
+/// neolink floodlight --config=config.toml CameraName off
+/// # Let the camera turn it on by itself
+/// neolink floodlight --config=config.toml CameraName auto
+/// # Print the current tasks xml
+/// neolink floodlight --config=config.toml CameraName task
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+use cmdline::FloodlightAction;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the floodlight subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    match opt.action {
+        FloodlightAction::On | FloodlightAction::Off => {
+            let on = matches!(opt.action, FloodlightAction::On);
+            let duration = opt.duration;
+            let confirmation = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_floodlight_manual(on, duration)
+                            .await
+                            .context(tr!("floodlight.set_failed"))
+                    })
+                })
+                .await?;
+            if confirmation == Confirmation::AssumedOk {
+                log::warn!("{}", tr!("floodlight.set_unconfirmed"));
+            }
+        }
+        FloodlightAction::Auto | FloodlightAction::Manual => {
+            let auto = matches!(opt.action, FloodlightAction::Auto);
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.flightlight_tasks_enable(auto)
+                            .await
+                            .context(tr!("floodlight.tasks_set_failed"))
+                    })
+                })
+                .await?;
+        }
+        FloodlightAction::Task => {
+            let tasks = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.get_flightlight_tasks()
+                            .await
+                            .context(tr!("floodlight.tasks_get_failed"))
+                    })
+                })
+                .await?;
+            let tasks_ser = String::from_utf8(
+                yaserde::ser::serialize_with_writer(&tasks, vec![], &Default::default())
+                    .expect("Should Ser the struct"),
+            )
+            .expect("Should be UTF8");
+            println!("{}", tasks_ser);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use clap::{Parser, Subcommand};
+
+/// Serve the camera streams over HLS
+#[derive(Parser, Debug)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub cmd: Option<HlsCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HlsCommand {
+    /// Print a url signed with the `[hls] secret` that works until it expires
+    Sign {
+        /// The name of the camera. Must be a name in the config
+        camera: String,
+        /// The stream of the playlist: main, sub or extern
+        #[arg(long, default_value = "main")]
+        stream: String,
+        /// Sign the url of a jpeg snapshot instead of the playlist
+        #[arg(long)]
+        snapshot: bool,
+        /// How many seconds the url works for
+        #[arg(long, default_value_t = 300)]
+        ttl: u64,
+        /// Start of the url as the dashboard reaches neolink such as
+        /// `https://cams.example.com`. Defaults to the bind address
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+}
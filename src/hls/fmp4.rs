@@ -0,0 +1,363 @@
+//! A minimal fragmented mp4 writer
+//!
+//! Just enough of ISO/IEC 14496-12 to carry a single H264 or H265 track
+//! as CMAF style fragments. The camera sends annex b NAL units which are
+//! rewritten with length prefixes and the parameter sets are moved into
+//! the sample entry of the init segment.
+use anyhow::{anyhow, Result};
+use neolink_core::bcmedia::paramsets::split_nals;
+
+use crate::common::VidFormat;
+
+/// Timescale of the video track, the usual 90kHz
+pub(crate) const TIMESCALE: u32 = 90_000;
+
+/// A video frame ready to go into a fragment
+pub(crate) struct Sample {
+    /// The length prefixed NAL units of the frame
+    pub(crate) data: Vec<u8>,
+    /// Duration in [`TIMESCALE`] units
+    pub(crate) duration: u32,
+    pub(crate) keyframe: bool,
+}
+
+/// The parameter sets needed for the init segment
+#[derive(Default, Clone, PartialEq, Eq)]
+pub(crate) struct ParameterSets {
+    vps: Option<Vec<u8>>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+}
+
+impl ParameterSets {
+    /// True once all the parameter sets of the format have been seen
+    pub(crate) fn is_complete(&self, format: &VidFormat) -> bool {
+        match format {
+            VidFormat::H264 => self.sps.is_some() && self.pps.is_some(),
+            VidFormat::H265 => self.vps.is_some() && self.sps.is_some() && self.pps.is_some(),
+            VidFormat::None => false,
+        }
+    }
+}
+
+/// Convert an annex b frame to length prefixed NAL units
+///
+/// Parameter sets are taken out of the frame and stored in `params`,
+/// access unit delimiters are dropped
+pub(crate) fn frame_to_sample(
+    format: &VidFormat,
+    data: &[u8],
+    params: &mut ParameterSets,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    for nal in split_nals(data) {
+        let Some(&header) = nal.first() else {
+            continue;
+        };
+        let slot = match format {
+            VidFormat::H264 => match header & 0x1f {
+                7 => Some(&mut params.sps),
+                8 => Some(&mut params.pps),
+                9 => continue,
+                _ => None,
+            },
+            VidFormat::H265 => match (header >> 1) & 0x3f {
+                32 => Some(&mut params.vps),
+                33 => Some(&mut params.sps),
+                34 => Some(&mut params.pps),
+                35 => continue,
+                _ => None,
+            },
+            VidFormat::None => None,
+        };
+        match slot {
+            Some(slot) => *slot = Some(nal.to_vec()),
+            None => {
+                out.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                out.extend_from_slice(nal);
+            }
+        }
+    }
+    out
+}
+
+/// Remove the emulation prevention bytes of a NAL
+fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], content: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(kind);
+    content(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn write_full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    content: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(out, kind, |out| {
+        out.push(version);
+        out.extend_from_slice(&flags.to_be_bytes()[1..]);
+        content(out);
+    })
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_matrix(out: &mut Vec<u8>) {
+    for value in [0x00010000, 0, 0, 0, 0x00010000, 0, 0, 0, 0x40000000] {
+        put_u32(out, value);
+    }
+}
+
+fn write_avcc(out: &mut Vec<u8>, sps: &[u8], pps: &[u8]) -> Result<()> {
+    if sps.len() < 4 {
+        return Err(anyhow!("SPS is too short"));
+    }
+    write_box(out, b"avcC", |out| {
+        out.push(1); // configurationVersion
+        out.extend_from_slice(&sps[1..4]); // profile, compatibility, level
+        out.push(0xff); // 4 byte lengths
+        out.push(0xe1); // One SPS
+        put_u16(out, sps.len() as u16);
+        out.extend_from_slice(sps);
+        out.push(1); // One PPS
+        put_u16(out, pps.len() as u16);
+        out.extend_from_slice(pps);
+    });
+    Ok(())
+}
+
+fn write_hvcc(out: &mut Vec<u8>, vps: &[u8], sps: &[u8], pps: &[u8]) -> Result<()> {
+    // Two bytes of NAL header, one byte of ids then the general profile_tier_level
+    let rbsp = unescape(sps);
+    let ptl = rbsp.get(3..15).ok_or_else(|| anyhow!("SPS is too short"))?;
+    write_box(out, b"hvcC", |out| {
+        out.push(1); // configurationVersion
+        out.extend_from_slice(ptl); // profile, compatibility, constraints, level
+        put_u16(out, 0xf000); // min_spatial_segmentation_idc
+        out.push(0xfc); // parallelismType
+        out.push(0xfd); // chroma_format_idc 4:2:0
+        out.push(0xf8); // bit_depth_luma_minus8
+        out.push(0xf8); // bit_depth_chroma_minus8
+        put_u16(out, 0); // avgFrameRate
+        out.push(0x0f); // One temporal layer, nested, 4 byte lengths
+        out.push(3); // numOfArrays
+        for (nal_type, nal) in [(32u8, vps), (33, sps), (34, pps)] {
+            out.push(0x80 | nal_type);
+            put_u16(out, 1);
+            put_u16(out, nal.len() as u16);
+            out.extend_from_slice(nal);
+        }
+    });
+    Ok(())
+}
+
+/// Seconds from the mp4 epoch (1904) to the POSIX epoch (1970)
+const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// The `ftyp` and `moov` boxes that start the stream
+///
+/// `created` is the POSIX time of the first frame if it is known
+pub(crate) fn init_segment(
+    format: &VidFormat,
+    params: &ParameterSets,
+    width: u32,
+    height: u32,
+    created: Option<u32>,
+) -> Result<Vec<u8>> {
+    let created = created
+        .map(|time| (time as u64 + MP4_EPOCH_OFFSET) as u32)
+        .unwrap_or(0);
+    let missing = || anyhow!("Missing parameter sets");
+    let mut sample_entry = vec![];
+    let (entry, config) = match format {
+        VidFormat::H264 => {
+            write_avcc(
+                &mut sample_entry,
+                params.sps.as_ref().ok_or_else(missing)?,
+                params.pps.as_ref().ok_or_else(missing)?,
+            )?;
+            (b"avc1", sample_entry)
+        }
+        VidFormat::H265 => {
+            write_hvcc(
+                &mut sample_entry,
+                params.vps.as_ref().ok_or_else(missing)?,
+                params.sps.as_ref().ok_or_else(missing)?,
+                params.pps.as_ref().ok_or_else(missing)?,
+            )?;
+            (b"hvc1", sample_entry)
+        }
+        VidFormat::None => return Err(anyhow!("Stream has no video")),
+    };
+
+    let mut out = vec![];
+    write_box(&mut out, b"ftyp", |out| {
+        out.extend_from_slice(b"iso5");
+        put_u32(out, 512);
+        for brand in [b"iso5", b"iso6", b"mp41", b"cmfc"] {
+            out.extend_from_slice(brand);
+        }
+    });
+    write_box(&mut out, b"moov", |out| {
+        write_full_box(out, b"mvhd", 0, 0, |out| {
+            put_u32(out, created); // creation_time
+            put_u32(out, created); // modification_time
+            put_u32(out, 1000); // timescale
+            put_u32(out, 0); // duration
+            put_u32(out, 0x00010000); // rate
+            put_u16(out, 0x0100); // volume
+            out.extend_from_slice(&[0; 10]);
+            put_matrix(out);
+            out.extend_from_slice(&[0; 24]); // pre_defined
+            put_u32(out, 2); // next_track_ID
+        });
+        write_box(out, b"trak", |out| {
+            write_full_box(out, b"tkhd", 0, 3, |out| {
+                put_u32(out, created); // creation_time
+                put_u32(out, created); // modification_time
+                put_u32(out, 1); // track_ID
+                put_u32(out, 0);
+                put_u32(out, 0); // duration
+                out.extend_from_slice(&[0; 8]);
+                put_u16(out, 0); // layer
+                put_u16(out, 0); // alternate_group
+                put_u16(out, 0); // volume
+                put_u16(out, 0);
+                put_matrix(out);
+                put_u32(out, width << 16);
+                put_u32(out, height << 16);
+            });
+            write_box(out, b"mdia", |out| {
+                write_full_box(out, b"mdhd", 0, 0, |out| {
+                    put_u32(out, created); // creation_time
+                    put_u32(out, created); // modification_time
+                    put_u32(out, TIMESCALE);
+                    put_u32(out, 0); // duration
+                    put_u16(out, 0x55c4); // und
+                    put_u16(out, 0);
+                });
+                write_full_box(out, b"hdlr", 0, 0, |out| {
+                    put_u32(out, 0);
+                    out.extend_from_slice(b"vide");
+                    out.extend_from_slice(&[0; 12]);
+                    out.extend_from_slice(b"VideoHandler\0");
+                });
+                write_box(out, b"minf", |out| {
+                    write_full_box(out, b"vmhd", 0, 1, |out| {
+                        out.extend_from_slice(&[0; 8]);
+                    });
+                    write_box(out, b"dinf", |out| {
+                        write_full_box(out, b"dref", 0, 0, |out| {
+                            put_u32(out, 1);
+                            write_full_box(out, b"url ", 0, 1, |_| {});
+                        });
+                    });
+                    write_box(out, b"stbl", |out| {
+                        write_full_box(out, b"stsd", 0, 0, |out| {
+                            put_u32(out, 1);
+                            write_box(out, entry, |out| {
+                                out.extend_from_slice(&[0; 6]);
+                                put_u16(out, 1); // data_reference_index
+                                out.extend_from_slice(&[0; 16]);
+                                put_u16(out, width as u16);
+                                put_u16(out, height as u16);
+                                put_u32(out, 0x00480000); // 72 dpi
+                                put_u32(out, 0x00480000);
+                                put_u32(out, 0);
+                                put_u16(out, 1); // frame_count
+                                out.extend_from_slice(&[0; 32]); // compressorname
+                                put_u16(out, 0x0018); // depth
+                                put_u16(out, 0xffff); // pre_defined
+                                out.extend_from_slice(&config);
+                            });
+                        });
+                        for kind in [b"stts", b"stsc", b"stco"] {
+                            write_full_box(out, kind, 0, 0, |out| put_u32(out, 0));
+                        }
+                        write_full_box(out, b"stsz", 0, 0, |out| {
+                            put_u32(out, 0);
+                            put_u32(out, 0);
+                        });
+                    });
+                });
+            });
+        });
+        write_box(out, b"mvex", |out| {
+            write_full_box(out, b"trex", 0, 0, |out| {
+                put_u32(out, 1); // track_ID
+                put_u32(out, 1); // default_sample_description_index
+                put_u32(out, 0);
+                put_u32(out, 0);
+                put_u32(out, 0);
+            });
+        });
+    });
+    Ok(out)
+}
+
+/// A `moof` and `mdat` pair holding the samples
+pub(crate) fn fragment(sequence: u32, decode_time: u64, samples: &[Sample]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut data_offset_at = 0;
+    write_box(&mut out, b"moof", |out| {
+        write_full_box(out, b"mfhd", 0, 0, |out| put_u32(out, sequence));
+        write_box(out, b"traf", |out| {
+            // default-base-is-moof
+            write_full_box(out, b"tfhd", 0, 0x020000, |out| put_u32(out, 1));
+            write_full_box(out, b"tfdt", 1, 0, |out| {
+                out.extend_from_slice(&decode_time.to_be_bytes())
+            });
+            // data-offset, sample-duration, sample-size and sample-flags present
+            write_full_box(out, b"trun", 0, 0x000701, |out| {
+                put_u32(out, samples.len() as u32);
+                data_offset_at = out.len();
+                put_u32(out, 0);
+                for sample in samples {
+                    put_u32(out, sample.duration);
+                    put_u32(out, sample.data.len() as u32);
+                    put_u32(
+                        out,
+                        if sample.keyframe {
+                            0x02000000
+                        } else {
+                            0x01010000
+                        },
+                    );
+                }
+            });
+        });
+    });
+    let data_offset = (out.len() + 8) as u32;
+    out[data_offset_at..data_offset_at + 4].copy_from_slice(&data_offset.to_be_bytes());
+    write_box(&mut out, b"mdat", |out| {
+        for sample in samples {
+            out.extend_from_slice(&sample.data);
+        }
+    });
+    out
+}
@@ -0,0 +1,319 @@
+///
+/// # Neolink HLS
+///
+/// This module serves the camera streams over HLS so that they can be
+/// watched in a browser or embedded in a dashboard without an RTSP client
+///
+/// The playlist of each stream is at
+/// `http://{bind}:{port}/{CameraName}/main/index.m3u8` (or `sub`/`extern`).
+/// Safari plays it natively, other browsers can use hls.js
+///
+/// The video is sent as is in fmp4 segments, there is no transcoding and no
+/// audio. Low latency HLS is served by default which gets the delay down to
+/// a couple of seconds with players that support it
+///
+/// A jpeg snapshot of each camera is at
+/// `http://{bind}:{port}/{CameraName}/snapshot.jpg`
+///
+/// With a `secret` every request needs a short lived url from `neolink hls
+/// sign` so that dashboards can embed the streams without a permanent
+/// password, see [`token`]
+///
+/// # Usage
+///
+/// ```bash
+/// neolink hls --config=config.toml
+/// neolink hls --config=config.toml sign --ttl 3600 CameraName
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [hls]
+///   bind = "0.0.0.0"
+///   port = 8888
+///   segment_duration = 2.0
+///   part_duration = 0.5
+///   segments = 6
+///   low_latency = true
+///   secret = "a long random string"
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use hyper::{
+    header::{ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+mod cmdline;
+pub(crate) mod fmp4;
+mod segmenter;
+mod token;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::HlsConfig,
+};
+use cmdline::HlsCommand;
+use segmenter::HlsStream;
+
+pub(crate) use cmdline::Opt;
+
+const PLAYLIST_TYPE: &str = "application/vnd.apple.mpegurl";
+
+struct HlsState {
+    reactor: NeoReactor,
+    config: HlsConfig,
+    /// Namespace prefix of all paths, see [`crate::config::Config::path_prefix`]
+    prefix: String,
+    streams: Mutex<HashMap<(String, StreamKind), Arc<HlsStream>>>,
+}
+
+/// Entry point for the hls subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (config, prefix) = {
+        let config = reactor.config().await?;
+        let config = config.borrow();
+        (config.hls.clone(), config.path_prefix())
+    };
+    let addr: SocketAddr = format!("{}:{}", config.bind_addr, config.bind_port)
+        .parse()
+        .context("Invalid HLS bind address")?;
+
+    if let Some(HlsCommand::Sign {
+        camera,
+        stream,
+        snapshot,
+        ttl,
+        base_url,
+    }) = opt.cmd
+    {
+        let secret = config
+            .secret
+            .as_deref()
+            .ok_or_else(|| anyhow!("Set a secret in [hls] to sign urls"))?;
+        let path = match (snapshot, stream_kind(&stream)) {
+            (true, _) => "snapshot.jpg".to_string(),
+            (false, Some(_)) => format!("{stream}/index.m3u8"),
+            (false, None) => return Err(anyhow!("Unknown stream {stream}")),
+        };
+        let base_url = base_url.unwrap_or_else(|| format!("http://{addr}"));
+        let query = token::sign(secret, &camera, Duration::from_secs(ttl));
+        println!(
+            "{}{prefix}/{camera}/{path}?{query}",
+            base_url.trim_end_matches('/')
+        );
+        return Ok(());
+    }
+
+    log::info!("HLS playlists avaliable at http://{addr}{prefix}/{{CameraName}}/main/index.m3u8");
+    let state = Arc::new(HlsState {
+        reactor,
+        config,
+        prefix,
+        streams: Default::default(),
+    });
+
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(req, state).await) }
+            }))
+        }
+    });
+
+    Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind HLS server to {addr}"))?
+        .serve(make_svc)
+        .await?;
+    Ok(())
+}
+
+fn response(status: StatusCode, content_type: &str, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(body.into())
+        .expect("Response should be valid")
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    response(status, "text/plain", message.to_string())
+}
+
+fn stream_kind(name: &str) -> Option<StreamKind> {
+    match name {
+        "main" | "mainStream" => Some(StreamKind::Main),
+        "sub" | "subStream" => Some(StreamKind::Sub),
+        "extern" | "externStream" => Some(StreamKind::Extern),
+        _ => None,
+    }
+}
+
+/// Get the value of a query parameter
+fn query<'a>(req: &'a Request<Body>, key: &str) -> Option<&'a str> {
+    req.uri().query()?.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == key).then_some(value)
+    })
+}
+
+async fn handle(req: Request<Body>, state: Arc<HlsState>) -> Response<Body> {
+    if req.method() == Method::OPTIONS {
+        return response(StatusCode::NO_CONTENT, "text/plain", String::new());
+    }
+    if req.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed");
+    }
+
+    let path = match req.uri().path().strip_prefix(&state.prefix) {
+        Some(path) => path.trim_matches('/'),
+        None => return error(StatusCode::NOT_FOUND, "Not found"),
+    };
+    let path = path.split('/').collect::<Vec<_>>();
+    let camera = path[0];
+
+    // The signature is carried over to the uris in the playlist
+    let signed = match state.config.secret.as_deref() {
+        Some(secret) => match (query(&req, "expires"), query(&req, "sig")) {
+            (Some(expires), Some(sig)) if token::verify(secret, camera, expires, sig) => {
+                Some(format!("expires={expires}&sig={sig}"))
+            }
+            _ => return error(StatusCode::FORBIDDEN, "Invalid or expired signature"),
+        },
+        None => None,
+    };
+
+    let (kind, file) = match path[..] {
+        [_, "snapshot.jpg"] => return snapshot(&state, camera).await,
+        [_, stream, file] => match stream_kind(stream) {
+            Some(kind) => (kind, file),
+            None => return error(StatusCode::NOT_FOUND, "Unknown stream"),
+        },
+        _ => return error(StatusCode::NOT_FOUND, "Not found"),
+    };
+
+    let stream = match get_stream(&state, camera, kind).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::debug!("{camera}: Failed to start HLS stream: {e:?}");
+            return error(StatusCode::NOT_FOUND, "Unknown camera");
+        }
+    };
+    stream.touch();
+
+    let not_ready = || error(StatusCode::NOT_FOUND, "Not avaliable");
+    if file == "index.m3u8" {
+        let msn = query(&req, "_HLS_msn").and_then(|v| v.parse().ok());
+        let part = query(&req, "_HLS_part").and_then(|v| v.parse().ok());
+        match stream.playlist(msn, part).await {
+            Some(playlist) => match signed {
+                Some(signed) => response(
+                    StatusCode::OK,
+                    PLAYLIST_TYPE,
+                    token::sign_playlist(&playlist, &signed),
+                ),
+                None => response(StatusCode::OK, PLAYLIST_TYPE, playlist),
+            },
+            None => error(StatusCode::SERVICE_UNAVAILABLE, "Stream is not ready"),
+        }
+    } else if let Some(version) = file
+        .strip_prefix("init")
+        .and_then(|file| file.strip_suffix(".mp4"))
+        .and_then(|version| version.parse().ok())
+    {
+        match stream.init(version).await {
+            Some(data) => response(StatusCode::OK, "video/mp4", data.as_ref().clone()),
+            None => not_ready(),
+        }
+    } else if let Some(msn) = file
+        .strip_prefix("segment")
+        .and_then(|file| file.strip_suffix(".m4s"))
+        .and_then(|msn| msn.parse().ok())
+    {
+        match stream.segment(msn) {
+            Some(data) => response(StatusCode::OK, "video/iso.segment", data),
+            None => not_ready(),
+        }
+    } else if let Some((msn, index)) = file
+        .strip_prefix("part")
+        .and_then(|file| file.strip_suffix(".m4s"))
+        .and_then(|file| file.split_once('.'))
+        .and_then(|(msn, index)| Some((msn.parse().ok()?, index.parse().ok()?)))
+    {
+        match stream.part(msn, index).await {
+            Some(data) => response(StatusCode::OK, "video/iso.segment", data.as_ref().clone()),
+            None => not_ready(),
+        }
+    } else {
+        error(StatusCode::NOT_FOUND, "Not found")
+    }
+}
+
+async fn snapshot(state: &HlsState, camera: &str) -> Response<Body> {
+    let jpeg = async {
+        get_camera(state, camera)
+            .await?
+            .run_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+            .await
+    };
+    match jpeg.await {
+        Ok(jpeg) => response(StatusCode::OK, "image/jpeg", jpeg),
+        Err(e) => {
+            log::debug!("{camera}: Failed to take a snapshot: {e:?}");
+            error(StatusCode::NOT_FOUND, "Snapshot not avaliable")
+        }
+    }
+}
+
+/// Get an enabled camera from the config without creating unknown ones
+async fn get_camera(state: &HlsState, camera: &str) -> Result<NeoInstance> {
+    let known = state
+        .reactor
+        .config()
+        .await?
+        .borrow()
+        .cameras
+        .iter()
+        .any(|cam| cam.enabled && cam.name == camera);
+    anyhow::ensure!(known, "Unknown camera");
+    state.reactor.get(camera).await
+}
+
+/// Get the segmenter of a stream, starting it if no one is watching yet
+async fn get_stream(state: &HlsState, camera: &str, kind: StreamKind) -> Result<Arc<HlsStream>> {
+    let key = (camera.to_string(), kind);
+    if let Some(stream) = state.streams.lock().unwrap().get(&key) {
+        if !stream.is_finished() {
+            return Ok(stream.clone());
+        }
+    }
+
+    let instance = get_camera(state, camera).await?;
+
+    let mut streams = state.streams.lock().unwrap();
+    streams.retain(|_, stream| !stream.is_finished());
+    // Someone else may have started it while we were waiting on the reactor
+    if let Some(stream) = streams.get(&key) {
+        return Ok(stream.clone());
+    }
+    log::info!("{camera}: Starting HLS {kind}");
+    let stream = HlsStream::start(instance, kind, state.config.clone());
+    streams.insert(key, stream.clone());
+    Ok(stream)
+}
@@ -0,0 +1,527 @@
+//! Cuts the video of a camera stream into HLS segments
+//!
+//! Segments always start on a keyframe and are made up of one or more
+//! parts, each part being a single fmp4 fragment. Regular HLS clients
+//! only see the completed segments while LL-HLS clients are also given
+//! the parts of the newest segments.
+use anyhow::{anyhow, Result};
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::watch::{channel as watch, Sender as WatchSender},
+    time::{interval, timeout_at, Duration, Instant},
+};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use super::fmp4::{self, ParameterSets, Sample, TIMESCALE};
+use crate::{
+    common::{NeoInstance, StampedData, VidFormat},
+    config::HlsConfig,
+    AnyResult,
+};
+
+/// Stop segmenting a stream once no one has asked for it in this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Number of segments at the end of the playlist that have their parts listed
+const PART_SEGMENTS: u64 = 3;
+
+struct Part {
+    data: Arc<Vec<u8>>,
+    /// Duration in [`TIMESCALE`] units
+    duration: u64,
+    independent: bool,
+}
+
+struct Segment {
+    msn: u64,
+    /// The init segment that the parts need
+    init: u64,
+    parts: Vec<Part>,
+    complete: bool,
+}
+
+impl Segment {
+    fn duration(&self) -> f64 {
+        self.parts.iter().map(|part| part.duration).sum::<u64>() as f64 / TIMESCALE as f64
+    }
+}
+
+#[derive(Default)]
+struct Playlist {
+    /// Init segments by their version
+    inits: VecDeque<(u64, Arc<Vec<u8>>)>,
+    segments: VecDeque<Segment>,
+}
+
+impl Playlist {
+    /// The msn and part index of the next part to be made
+    fn next_part(&self) -> (u64, usize) {
+        match self.segments.back() {
+            Some(segment) if !segment.complete => (segment.msn, segment.parts.len()),
+            Some(segment) => (segment.msn + 1, 0),
+            None => (0, 0),
+        }
+    }
+
+    /// True if the given part has been made
+    fn has_part(&self, msn: u64, part: usize) -> bool {
+        let (next_msn, next_part) = self.next_part();
+        msn < next_msn || (msn == next_msn && part < next_part)
+    }
+}
+
+/// A camera stream that is being segmented for HLS
+pub(super) struct HlsStream {
+    settings: HlsConfig,
+    playlist: Mutex<Playlist>,
+    /// Bumped whenever a part is added
+    updated: WatchSender<u64>,
+    last_access: Mutex<Instant>,
+    cancel: CancellationToken,
+}
+
+impl HlsStream {
+    /// Start segmenting the stream of the camera
+    pub(super) fn start(camera: NeoInstance, kind: StreamKind, settings: HlsConfig) -> Arc<Self> {
+        let (updated, _) = watch(0);
+        let stream = Arc::new(Self {
+            settings,
+            playlist: Default::default(),
+            updated,
+            last_access: Mutex::new(Instant::now()),
+            cancel: CancellationToken::new(),
+        });
+
+        let thread_stream = stream.clone();
+        tokio::task::spawn(async move {
+            let name = camera
+                .config()
+                .await
+                .map(|config| config.borrow().name.clone())
+                .unwrap_or_default();
+            let cancel = thread_stream.cancel.clone();
+            let r = tokio::select! {
+                _ = cancel.cancelled() => AnyResult::Ok(()),
+                v = thread_stream.idle() => v,
+                v = thread_stream.run(&camera, kind) => v,
+            };
+            if let Err(e) = &r {
+                log::warn!("{name}: HLS {kind} stopped: {e:?}");
+            } else {
+                log::debug!("{name}: HLS {kind} stopped");
+            }
+            cancel.cancel();
+        });
+        stream
+    }
+
+    /// Mark the stream as still being watched
+    pub(super) fn touch(&self) {
+        *self.last_access.lock().unwrap() = Instant::now();
+    }
+
+    /// True once the stream has stopped being segmented
+    pub(super) fn is_finished(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    async fn idle(&self) -> AnyResult<()> {
+        let mut check = interval(Duration::from_secs(5));
+        loop {
+            check.tick().await;
+            if self.last_access.lock().unwrap().elapsed() > IDLE_TIMEOUT {
+                return Ok(());
+            }
+        }
+    }
+
+    fn target_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.settings.segment_duration)
+    }
+
+    /// Wait until `f` returns `Some` or the timeout expires
+    async fn wait_for<T>(&self, wait: Duration, f: impl Fn(&Playlist) -> Option<T>) -> Option<T> {
+        let deadline = Instant::now() + wait;
+        let mut updated = self.updated.subscribe();
+        loop {
+            if let Some(result) = f(&self.playlist.lock().unwrap()) {
+                return Some(result);
+            }
+            if self.is_finished() {
+                return None;
+            }
+            match timeout_at(deadline, updated.changed()).await {
+                Ok(Ok(())) => {}
+                _ => return None,
+            }
+        }
+    }
+
+    /// The media playlist
+    ///
+    /// For a blocking playlist reload `msn` and `part` are the `_HLS_msn`
+    /// and `_HLS_part` of the request
+    pub(super) async fn playlist(&self, msn: Option<u64>, part: Option<usize>) -> Option<String> {
+        // Wait for the first segment before handing out a playlist
+        let wait = self.target_duration() * 3 + Duration::from_secs(10);
+        self.wait_for(wait, |playlist| {
+            playlist
+                .segments
+                .iter()
+                .any(|segment| segment.complete || self.settings.low_latency)
+                .then_some(())
+        })
+        .await?;
+
+        if let Some(msn) = msn.filter(|_| self.settings.low_latency) {
+            let part = part.unwrap_or(0);
+            // If it takes too long just give them what we have
+            let _ = self
+                .wait_for(self.target_duration() * 3, |playlist| {
+                    playlist.has_part(msn, part).then_some(())
+                })
+                .await;
+        }
+
+        Some(self.render(&self.playlist.lock().unwrap()))
+    }
+
+    fn render(&self, playlist: &Playlist) -> String {
+        let low_latency = self.settings.low_latency;
+        let segments = playlist
+            .segments
+            .iter()
+            .filter(|segment| segment.complete || low_latency)
+            .collect::<Vec<_>>();
+        let target = segments
+            .iter()
+            .map(|segment| segment.duration())
+            .fold(self.settings.segment_duration, f64::max)
+            .ceil();
+        let last_msn = segments.last().map(|segment| segment.msn).unwrap_or(0);
+
+        let mut lines = vec![
+            "#EXTM3U".to_string(),
+            format!("#EXT-X-VERSION:{}", if low_latency { 9 } else { 7 }),
+            format!("#EXT-X-TARGETDURATION:{target}"),
+        ];
+        if low_latency {
+            lines.push(format!(
+                "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}",
+                self.settings.part_duration * 3.0
+            ));
+            lines.push(format!(
+                "#EXT-X-PART-INF:PART-TARGET={:.3}",
+                self.settings.part_duration
+            ));
+        }
+        lines.push(format!(
+            "#EXT-X-MEDIA-SEQUENCE:{}",
+            segments.first().map(|segment| segment.msn).unwrap_or(0)
+        ));
+        lines.push("#EXT-X-INDEPENDENT-SEGMENTS".to_string());
+
+        let mut init = None;
+        for segment in segments.iter() {
+            if init != Some(segment.init) {
+                if init.is_some() {
+                    lines.push("#EXT-X-DISCONTINUITY".to_string());
+                }
+                lines.push(format!("#EXT-X-MAP:URI=\"init{}.mp4\"", segment.init));
+                init = Some(segment.init);
+            }
+            if low_latency && segment.msn + PART_SEGMENTS > last_msn {
+                for (i, part) in segment.parts.iter().enumerate() {
+                    lines.push(format!(
+                        "#EXT-X-PART:DURATION={:.5},URI=\"part{}.{}.m4s\"{}",
+                        part.duration as f64 / TIMESCALE as f64,
+                        segment.msn,
+                        i,
+                        if part.independent {
+                            ",INDEPENDENT=YES"
+                        } else {
+                            ""
+                        }
+                    ));
+                }
+            }
+            if segment.complete {
+                lines.push(format!("#EXTINF:{:.5},", segment.duration()));
+                lines.push(format!("segment{}.m4s", segment.msn));
+            }
+        }
+        if low_latency {
+            let (msn, part) = playlist.next_part();
+            lines.push(format!(
+                "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part{msn}.{part}.m4s\""
+            ));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// The init segment of the given version
+    pub(super) async fn init(&self, version: u64) -> Option<Arc<Vec<u8>>> {
+        self.wait_for(
+            self.target_duration() + Duration::from_secs(10),
+            |playlist| {
+                playlist
+                    .inits
+                    .iter()
+                    .find(|(v, _)| *v == version)
+                    .map(|(_, init)| init.clone())
+            },
+        )
+        .await
+    }
+
+    /// A complete segment
+    pub(super) fn segment(&self, msn: u64) -> Option<Vec<u8>> {
+        let playlist = self.playlist.lock().unwrap();
+        playlist
+            .segments
+            .iter()
+            .find(|segment| segment.msn == msn && segment.complete)
+            .map(|segment| {
+                segment
+                    .parts
+                    .iter()
+                    .flat_map(|part| part.data.iter().copied())
+                    .collect()
+            })
+    }
+
+    /// A part of a segment, this waits for the part if it is the next one
+    /// to be made so that preload hints can be answered
+    pub(super) async fn part(&self, msn: u64, index: usize) -> Option<Arc<Vec<u8>>> {
+        let next = self.playlist.lock().unwrap().next_part();
+        let wait = if (msn, index) == next {
+            self.target_duration() * 2
+        } else {
+            Duration::ZERO
+        };
+        self.wait_for(wait, |playlist| {
+            playlist
+                .segments
+                .iter()
+                .find(|segment| segment.msn == msn)
+                .and_then(|segment| segment.parts.get(index))
+                .map(|part| part.data.clone())
+        })
+        .await
+    }
+
+    /// Run the segmenter, restarting it whenever the stream format changes
+    async fn run(&self, camera: &NeoInstance, kind: StreamKind) -> AnyResult<()> {
+        let mut instance = camera.stream(kind).await?;
+        let mut init_version = 0;
+        loop {
+            instance
+                .config
+                .wait_for(|config| config.vid_ready())
+                .await?;
+            let stream_config = instance.config.borrow_and_update().clone();
+            let mut segmenter = Segmenter::new(
+                self,
+                stream_config.vid_format.clone(),
+                stream_config.resolution,
+                stream_config.fps,
+                init_version,
+            );
+            init_version += 1;
+
+            let initial = {
+                let history = instance.vid_history.borrow();
+                let start = history.iter().rposition(|frame| frame.keyframe);
+                start
+                    .map(|start| history.iter().skip(start).cloned().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            };
+            let mut frames = tokio_stream::iter(initial).chain(
+                BroadcastStream::new(instance.vid.resubscribe()).filter_map(|frame| frame.ok()), // Ignore lagged
+            );
+            loop {
+                tokio::select! {
+                    v = instance.config.changed() => {
+                        v?;
+                        log::debug!("HLS stream format changed");
+                        break;
+                    }
+                    frame = frames.next() => {
+                        let frame = frame.ok_or_else(|| anyhow!("Camera stream ended"))?;
+                        segmenter.push(frame)?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Playlist)) {
+        f(&mut self.playlist.lock().unwrap());
+        self.updated.send_modify(|count| *count += 1);
+    }
+}
+
+/// Turns frames into parts and segments of an [`HlsStream`]
+struct Segmenter<'a> {
+    stream: &'a HlsStream,
+    format: VidFormat,
+    resolution: [u32; 2],
+    init_version: u64,
+    started: bool,
+    params: ParameterSets,
+    /// Frame duration to use when the timestamps are unusable, this follows
+    /// the last usable one so it keeps up with changes to the fps
+    default_duration: u64,
+    /// The last frame which is held until its duration is known
+    pending: Option<(StampedData, Vec<u8>)>,
+    samples: Vec<Sample>,
+    part_ticks: u64,
+    segment_ticks: u64,
+    segment_open: bool,
+    decode_time: u64,
+    sequence: u32,
+}
+
+impl<'a> Segmenter<'a> {
+    fn new(
+        stream: &'a HlsStream,
+        format: VidFormat,
+        resolution: [u32; 2],
+        fps: u32,
+        init_version: u64,
+    ) -> Self {
+        Self {
+            stream,
+            format,
+            resolution,
+            init_version,
+            started: false,
+            params: Default::default(),
+            default_duration: TIMESCALE as u64 / fps.max(1) as u64,
+            pending: None,
+            samples: vec![],
+            part_ticks: 0,
+            segment_ticks: 0,
+            segment_open: false,
+            decode_time: 0,
+            sequence: 1,
+        }
+    }
+
+    fn push(&mut self, frame: StampedData) -> Result<()> {
+        let data = fmp4::frame_to_sample(&self.format, &frame.data, &mut self.params);
+        if !self.started {
+            if !frame.keyframe || !self.params.is_complete(&self.format) {
+                return Ok(());
+            }
+            let init = Arc::new(fmp4::init_segment(
+                &self.format,
+                &self.params,
+                self.resolution[0],
+                self.resolution[1],
+                None,
+            )?);
+            let version = self.init_version;
+            self.stream.update(|playlist| {
+                playlist.inits.push_back((version, init));
+                while playlist.inits.len() > 2 {
+                    playlist.inits.pop_front();
+                }
+            });
+            self.started = true;
+        }
+
+        if let Some((prev, prev_data)) = self.pending.take() {
+            let duration = match frame.ts.checked_sub(prev.ts) {
+                Some(delta) if !delta.is_zero() && delta < Duration::from_secs(5) => {
+                    self.default_duration =
+                        (delta.as_micros() as u64 * TIMESCALE as u64) / 1_000_000;
+                    self.default_duration
+                }
+                // Timestamps reset or jumped
+                _ => self.default_duration,
+            };
+            self.add_sample(Sample {
+                data: prev_data,
+                duration: duration as u32,
+                keyframe: prev.keyframe,
+            });
+        }
+        self.pending = Some((frame, data));
+        Ok(())
+    }
+
+    fn add_sample(&mut self, sample: Sample) {
+        let settings = &self.stream.settings;
+        let segment_target = (settings.segment_duration * TIMESCALE as f64) as u64;
+        let part_target = (settings.part_duration * TIMESCALE as f64) as u64;
+        let duration = sample.duration as u64;
+
+        if sample.keyframe && self.segment_open && self.segment_ticks >= segment_target {
+            self.flush_part();
+            self.close_segment();
+        } else if !self.samples.is_empty() && self.part_ticks + duration > part_target {
+            self.flush_part();
+        }
+        if !self.segment_open {
+            self.open_segment();
+        }
+        self.samples.push(sample);
+        self.part_ticks += duration;
+        self.segment_ticks += duration;
+    }
+
+    fn open_segment(&mut self) {
+        let init = self.init_version;
+        self.stream.update(|playlist| {
+            let (msn, _) = playlist.next_part();
+            playlist.segments.push_back(Segment {
+                msn,
+                init,
+                parts: vec![],
+                complete: false,
+            });
+        });
+        self.segment_open = true;
+        self.segment_ticks = 0;
+    }
+
+    fn flush_part(&mut self) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let data = fmp4::fragment(self.sequence, self.decode_time, &self.samples);
+        let part = Part {
+            data: Arc::new(data),
+            duration: self.part_ticks,
+            independent: self.samples[0].keyframe,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        self.decode_time += self.part_ticks;
+        self.part_ticks = 0;
+        self.samples.clear();
+        self.stream.update(|playlist| {
+            if let Some(segment) = playlist.segments.back_mut() {
+                segment.parts.push(part);
+            }
+        });
+    }
+
+    fn close_segment(&mut self) {
+        let keep = self.stream.settings.segments;
+        self.stream.update(|playlist| {
+            if let Some(segment) = playlist.segments.back_mut() {
+                segment.complete = true;
+            }
+            while playlist.segments.len() > keep {
+                playlist.segments.pop_front();
+            }
+        });
+        self.segment_open = false;
+    }
+}
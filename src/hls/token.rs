@@ -0,0 +1,76 @@
+//! Short lived signed urls
+//!
+//! When `[hls] secret` is set every request needs an `expires` unix time and
+//! a `sig` which is the HMAC-SHA256 of `{camera}:{expires}` with the secret.
+//! A signature gives access to all streams and the snapshot of one camera
+//! until it expires.
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &str, camera: &str, expires: u64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{camera}:{expires}").as_bytes());
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+/// The query string that grants access to `camera` for `ttl`
+pub(crate) fn sign(secret: &str, camera: &str, ttl: Duration) -> String {
+    let expires = now() + ttl.as_secs();
+    let sig = URL_SAFE_NO_PAD.encode(mac(secret, camera, expires).finalize().into_bytes());
+    format!("expires={expires}&sig={sig}")
+}
+
+/// Check the `expires` and `sig` query values for `camera`
+pub(crate) fn verify(secret: &str, camera: &str, expires: &str, sig: &str) -> bool {
+    let Ok(expires) = expires.parse::<u64>() else {
+        return false;
+    };
+    if expires < now() {
+        return false;
+    }
+    let Ok(sig) = URL_SAFE_NO_PAD.decode(sig) else {
+        return false;
+    };
+    mac(secret, camera, expires).verify_slice(&sig).is_ok()
+}
+
+/// Add the signature to every uri in the playlist
+///
+/// Players drop the query of the playlist when they resolve the relative
+/// segment uris so each one needs its own copy
+pub(crate) fn sign_playlist(playlist: &str, query: &str) -> String {
+    lazy_static! {
+        static ref RE_URI: Regex = Regex::new(r#"URI="([^"]*)""#).unwrap();
+    }
+    playlist
+        .split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                line.to_string()
+            } else if line.starts_with('#') {
+                RE_URI
+                    .replace_all(line, |caps: &regex::Captures| {
+                        format!("URI=\"{}?{query}\"", &caps[1])
+                    })
+                    .into_owned()
+            } else {
+                format!("{line}?{query}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
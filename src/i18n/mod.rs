@@ -0,0 +1,107 @@
+//!
+//! # Localization
+//!
+//! User facing CLI strings are looked up by key in a message catalog. The
+//! catalogs are the toml files in this directory and are compiled into the
+//! binary.
+//!
+//! The language is selected with (highest priority first):
+//!
+//! - `language = "de"` in the config
+//! - The `NEOLINK_LANG` environment variable
+//! - The `LC_ALL`, `LC_MESSAGES` or `LANG` environment variables
+//!
+//! Keys missing from a catalog fall back to English. Subcommands use the
+//! [`tr!`] macro so adding a new string only needs an entry in `en.toml`
+//! and optionally its translations.
+//!
+//! ```ignore
+//! let msg = tr!("talk.unsupported", camera = name);
+//! ```
+//!
+use lazy_static::lazy_static;
+use std::{collections::HashMap, fmt::Display, sync::RwLock};
+
+/// The languages that have a catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lang {
+    En,
+    De,
+}
+
+impl Lang {
+    /// Parse a language from a locale such as `de_DE.UTF-8`
+    pub(crate) fn from_locale(locale: &str) -> Option<Lang> {
+        let lang = locale
+            .split(|c| c == '_' || c == '-' || c == '.')
+            .next()?
+            .to_lowercase();
+        match lang.as_str() {
+            "en" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            _ => None,
+        }
+    }
+
+    fn from_env() -> Option<Lang> {
+        ["NEOLINK_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|val| !val.is_empty())
+            .and_then(|val| Lang::from_locale(&val))
+    }
+}
+
+fn parse_catalog(src: &str) -> HashMap<String, String> {
+    toml::from_str(src).expect("Message catalogs should be valid toml")
+}
+
+lazy_static! {
+    static ref EN: HashMap<String, String> = parse_catalog(include_str!("en.toml"));
+    static ref DE: HashMap<String, String> = parse_catalog(include_str!("de.toml"));
+    static ref CURRENT: RwLock<Lang> = RwLock::new(Lang::from_env().unwrap_or(Lang::En));
+}
+
+/// Set the language from a config value, falls back to the environment if `None`
+/// or unknown
+pub(crate) fn set_language(language: Option<&str>) {
+    let lang = match language.map(|l| (l, Lang::from_locale(l))) {
+        Some((_, Some(lang))) => lang,
+        Some((unknown, None)) => {
+            log::warn!("Unknown language {unknown:?}, using the environment's language");
+            Lang::from_env().unwrap_or(Lang::En)
+        }
+        None => Lang::from_env().unwrap_or(Lang::En),
+    };
+    *CURRENT.write().unwrap() = lang;
+}
+
+/// Look up a message and fill in its `{name}` placeholders
+pub(crate) fn translate(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let lang = *CURRENT.read().unwrap();
+    let catalog: &HashMap<String, String> = match lang {
+        Lang::En => &EN,
+        Lang::De => &DE,
+    };
+    let mut msg = match catalog.get(key).or_else(|| EN.get(key)) {
+        Some(msg) => msg.clone(),
+        None => {
+            log::debug!("Missing message catalog entry: {key}");
+            key.to_string()
+        }
+    };
+    for (name, value) in args {
+        msg = msg.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    msg
+}
+
+/// Translate a message from the catalog
+///
+/// Placeholders are given as `name = value` where value implements `Display`
+macro_rules! tr {
+    ($key:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::translate($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*])
+    };
+}
+pub(crate) use tr;
@@ -25,9 +25,10 @@ use tokio::{fs::File, io::AsyncWriteExt};
 use tokio_stream::wrappers::BroadcastStream;
 
 mod cmdline;
-mod gst;
 
 use crate::common::{NeoReactor, StampedData};
+use crate::i18n::tr;
+use crate::media::{Backend, ImageSink, MediaBackend};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the image subcommand
@@ -40,7 +41,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         let stream_data = camera
             .stream(StreamKind::Main)
             .await
-            .context("Failed to start video")?;
+            .context(tr!("image.video_failed"))?;
 
         // Get one iframe at the start while also getting the the video type
         let mut stream_config = stream_data.config.clone();
@@ -55,6 +56,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             if let Some(Ok(StampedData {
                 data,
                 ts: _,
+                time: _,
                 keyframe,
             })) = stream.next().await
             {
@@ -64,7 +66,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             }
         };
 
-        let mut sender = gst::from_input(vid_type, &opt.file_path).await?;
+        let mut sender = Backend::image_sink(vid_type, &opt.file_path).await?;
         sender.send(buf).await?; // Send first iframe
 
         // Keep sending both IFrame or PFrame until finished
@@ -76,7 +78,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                 }
             };
 
-            debug!("Sending frame data to gstreamer");
+            debug!("Sending frame data to the media backend");
             if sender.send(buf).await.is_err() {
                 // Assume that the sender is closed
                 // because the pipeline is finished
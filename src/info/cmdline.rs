@@ -0,0 +1,22 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum InfoFormat {
+    Table,
+    Json,
+}
+
+/// The info command prints the model and firmware of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// How to print the info
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: InfoFormat,
+    /// A toml file of the latest firmware of each model to check against
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub manifest: Option<PathBuf>,
+}
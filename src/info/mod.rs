@@ -0,0 +1,195 @@
+///
+/// # Neolink Info
+///
+/// This module prints the version info of the camera. That is its model,
+/// firmware, hardware and serial number.
+///
+/// A manifest of the latest firmware of each model can be given to check
+/// if the camera is outdated. Neolink does not ship one as reolink has no
+/// public list. It is a toml file of `[[firmware]]` entries
+///
+/// ```toml
+/// [[firmware]]
+/// model = "E1"
+/// # Optional, for models that have several hardware versions
+/// hardware = "IPC_566SD664M5MP"
+/// version = "v3.0.0.1388_22100600"
+/// # Optional, printed when the camera is outdated
+/// url = "https://reolink.com/download-center/"
+/// ```
+///
+/// # Usage
+///
+/// ```bash
+/// neolink info --config=config.toml CameraName
+/// neolink info --config=config.toml --format=json --manifest=firmware.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc::xml::VersionInfo;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, path::Path};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+use cmdline::InfoFormat;
+pub(crate) use cmdline::Opt;
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    firmware: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    model: String,
+    hardware: Option<String>,
+    version: String,
+    url: Option<String>,
+}
+
+impl Manifest {
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| tr!("config.read_failed", path = format!("{:?}", path)))?;
+        toml::from_str(&text)
+            .with_context(|| tr!("info.manifest_failed", path = format!("{:?}", path)))
+    }
+
+    /// The entry for this camera, one that names its hardware wins
+    fn find(&self, version: &VersionInfo) -> Option<&ManifestEntry> {
+        let model = version.model.as_deref()?;
+        let matching: Vec<&ManifestEntry> = self
+            .firmware
+            .iter()
+            .filter(|entry| entry.model.eq_ignore_ascii_case(model))
+            .filter(|entry| match entry.hardware.as_deref() {
+                Some(hardware) => hardware == version.hardwareVersion,
+                None => true,
+            })
+            .collect();
+        matching
+            .iter()
+            .find(|entry| entry.hardware.is_some())
+            .or_else(|| matching.first())
+            .copied()
+    }
+}
+
+/// Compare firmwares like `v3.0.0.1388_22100600` by their numbers in order
+fn compare_firmware(a: &str, b: &str) -> Ordering {
+    fn numbers(firmware: &str) -> Vec<u64> {
+        firmware
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    }
+    numbers(a).cmp(&numbers(b))
+}
+
+#[derive(Debug, Serialize)]
+struct Info {
+    name: String,
+    model: Option<String>,
+    firmware: String,
+    hardware: String,
+    serial: String,
+    build_day: String,
+    config_version: String,
+    detail: String,
+    /// Only when a manifest was given and has the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outdated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl Info {
+    fn new(version: VersionInfo, manifest: Option<&Manifest>) -> Self {
+        let entry = manifest.and_then(|manifest| manifest.find(&version));
+        Self {
+            latest: entry.map(|entry| entry.version.clone()),
+            outdated: entry.map(|entry| {
+                compare_firmware(&version.firmwareVersion, &entry.version) == Ordering::Less
+            }),
+            url: entry.and_then(|entry| entry.url.clone()),
+            name: version.name,
+            model: version.model,
+            firmware: version.firmwareVersion,
+            hardware: version.hardwareVersion,
+            serial: version.serialNumber,
+            build_day: version.buildDay,
+            config_version: version.cfgVersion,
+            detail: version.detail,
+        }
+    }
+
+    fn print_table(&self) {
+        let rows = [
+            ("Name", self.name.as_str()),
+            ("Model", self.model.as_deref().unwrap_or("Unknown")),
+            ("Firmware", self.firmware.as_str()),
+            ("Hardware", self.hardware.as_str()),
+            ("Serial", self.serial.as_str()),
+            ("Build Day", self.build_day.as_str()),
+            ("Config Version", self.config_version.as_str()),
+            ("Detail", self.detail.as_str()),
+        ];
+        for (key, value) in rows.iter() {
+            println!("{:<16}{}", key, value);
+        }
+        if let Some(latest) = self.latest.as_ref() {
+            println!("{:<16}{}", "Latest", latest);
+        }
+    }
+}
+
+/// Entry point for the info subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let manifest = opt
+        .manifest
+        .as_deref()
+        .map(Manifest::from_file)
+        .transpose()?;
+
+    let camera = reactor.get(&opt.camera).await?;
+    let version = camera
+        .run_task(|cam| {
+            Box::pin(async move { cam.version().await.context(tr!("info.get_failed")) })
+        })
+        .await?;
+    let info = Info::new(version, manifest.as_ref());
+
+    match opt.format {
+        InfoFormat::Table => info.print_table(),
+        InfoFormat::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+    }
+
+    match (manifest.is_some(), info.outdated) {
+        (_, Some(true)) => {
+            log::warn!(
+                "{}",
+                tr!(
+                    "info.outdated",
+                    camera = opt.camera,
+                    latest = info.latest.as_deref().unwrap_or_default()
+                )
+            );
+            if let Some(url) = info.url.as_ref() {
+                log::warn!("{url}");
+            }
+        }
+        (_, Some(false)) => log::info!("{}", tr!("info.up_to_date", camera = opt.camera)),
+        (true, None) => log::warn!("{}", tr!("info.not_in_manifest", camera = opt.camera)),
+        (false, None) => {}
+    }
+
+    Ok(())
+}
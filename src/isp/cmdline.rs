@@ -0,0 +1,43 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::DayNightMode;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+fn day_night_parse(src: &str) -> Result<DayNightMode> {
+    Ok(src.parse()?)
+}
+
+/// The isp command will control the picture settings of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The brightness from 0 to 255
+    #[arg(long)]
+    pub bright: Option<u8>,
+    /// The contrast from 0 to 255
+    #[arg(long)]
+    pub contrast: Option<u8>,
+    /// The saturation from 0 to 255
+    #[arg(long)]
+    pub saturation: Option<u8>,
+    /// Force the picture to colour or black and white or switch with the light
+    #[arg(long, value_parser = day_night_parse, value_name = "auto|day|night")]
+    pub day_night: Option<DayNightMode>,
+    /// Whether to flip the picture upside down
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub flip: Option<bool>,
+    /// Whether to mirror the picture left to right
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub mirror: Option<bool>,
+}
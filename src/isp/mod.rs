@@ -0,0 +1,70 @@
+///
+/// # Neolink ISP
+///
+/// This module handles the picture settings of the camera, the brightness,
+/// contrast and saturation, the day night mode and the orientation
+///
+/// Without any changes it prints the camera's VideoInput and InputAdvanceCfg
+/// xml
+///
+/// # Usage
+///
+/// ```bash
+/// # To force the camera into night mode
+/// neolink isp --config=config.toml CameraName --day-night night
+/// # To brighten the picture of a camera that is mounted upside down
+/// neolink isp --config=config.toml CameraName --bright 160 --flip on --mirror on
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::{Confirmation, IspChange};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the isp subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let change = IspChange {
+        bright: opt.bright,
+        contrast: opt.contrast,
+        saturation: opt.saturation,
+        day_night: opt.day_night,
+        flip: opt.flip,
+        mirror: opt.mirror,
+    };
+    if change == IspChange::default() {
+        let settings = camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.get_isp().await.context(tr!("isp.get_failed")) })
+            })
+            .await?;
+        for xml in [
+            yaserde::ser::serialize_with_writer(&settings.video_input, vec![], &Default::default()),
+            yaserde::ser::serialize_with_writer(&settings.advance, vec![], &Default::default()),
+        ] {
+            let isp_ser =
+                String::from_utf8(xml.expect("Should Ser the struct")).expect("Should be UTF8");
+            println!("{}", isp_ser);
+        }
+        return Ok(());
+    }
+
+    let confirmation = camera
+        .run_task(|cam| {
+            let change = change.clone();
+            Box::pin(async move { cam.isp_set(&change).await.context(tr!("isp.set_failed")) })
+        })
+        .await?;
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("isp.set_unconfirmed"));
+    }
+
+    Ok(())
+}
@@ -6,17 +6,23 @@ pub use neolink_core::{
 
 use neolink_core::bc_protocol::BcCamera;
 use neolink_core::bc_protocol::StreamKind;
+use neolink_core::bc_protocol::DEFAULT_STREAM_BUFFER;
+use neolink_core::bc_protocol::StreamData;
+use neolink_core::bcmedia::paramsets::ParamSetCache;
+use neolink_core::bcmedia::integrity::check_frame;
 use neolink_core::bc_protocol::BcCameraOpt;
 use neolink_core::bc_protocol::ConnectionProtocol;
 use neolink_core::bc_protocol::Credentials;
 use neolink_core::bc_protocol::DiscoveryMethods;
+use neolink_core::bc::xml::SupportItem;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ptr::null;
 //use neolink_core::bc_protocol::{self, Stream};
 use lazy_static::lazy_static;
-use std::convert::TryInto;
-use std::ffi::CStr;
+use std::convert::{TryFrom, TryInto};
+use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::thread;
 use std::{
@@ -25,9 +31,18 @@ use std::{
     str::FromStr,
 };
 use std::net::SocketAddr;
-use tokio::runtime::Runtime;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tokio::runtime::{Handle, Runtime};
+use tokio_util::sync::CancellationToken;
 //pub use neolink_core::bc_protocol::Error;
 
+/// Log target of the library's own records, the camera protocol logs under
+/// `neolink_core` and its raw messages under `neolink_core::bc::wire`
+const FFI_TARGET: &str = "neolink::ffi";
+
+/// Messages buffered for the live stream, the C callbacks can be slow so keep plenty
+const FFI_STREAM_BUFFER: usize = 9999;
+
 #[repr(C)]
 pub enum FrameType {
     /// H264 video data
@@ -49,10 +64,124 @@ pub struct ExtOutputs {
 }
 
 lazy_static! {
-    static ref RT: Runtime = Runtime::new().unwrap();
+    //taken by lib_shutdown to shut the runtime down
+    static ref RUNTIME: std::sync::Mutex<Option<Runtime>> = std::sync::Mutex::new(Some(Runtime::new().unwrap()));
+    static ref RT: Handle = RUNTIME.lock().unwrap().as_ref().expect("The library has been shut down").handle().clone();
+    //cancels the tasks spawned for the streams
+    static ref SHUTDOWN: CancellationToken = CancellationToken::new();
     static ref LOG_INIT: bool = false;
    
 }
+///called for each log record with the level (1=error,2=warn,3=info,4=debug,5=trace),
+///the target (module path) and the formatted message
+///the strings are only valid for the duration of the call
+pub type LogCallback = unsafe extern "C" fn(u32, *const c_char, *const c_char);
+
+lazy_static! {
+    static ref LOG_CALLBACK: std::sync::RwLock<Option<LogCallback>> = std::sync::RwLock::new(None);
+    //the level given to lib_set_log_callback
+    static ref LOG_LEVEL: std::sync::RwLock<log::LevelFilter> = std::sync::RwLock::new(log::LevelFilter::Trace);
+    //per target filter of lib_set_log_filter, replaces LOG_LEVEL when set
+    static ref LOG_FILTER: std::sync::RwLock<Option<env_logger::filter::Filter>> = std::sync::RwLock::new(None);
+}
+static LOGGER_INSTALLED: std::sync::Once = std::sync::Once::new();
+
+///forwards the logs to the host's callback or to env_logger if there is none
+struct HostLogger {
+    fallback: env_logger::Logger,
+}
+
+impl log::Log for HostLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        match *LOG_CALLBACK.read().unwrap() {
+            Some(_) => match LOG_FILTER.read().unwrap().as_ref() {
+                Some(filter) => filter.enabled(metadata),
+                None => metadata.level() <= log::max_level(),
+            },
+            None => self.fallback.enabled(metadata),
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match *LOG_CALLBACK.read().unwrap() {
+            Some(callback) => {
+                let target = CString::new(record.target()).unwrap_or_default();
+                let message = CString::new(record.args().to_string().replace('\0', "")).unwrap_or_default();
+                unsafe { callback(record.level() as u32, target.as_ptr(), message.as_ptr()) };
+            }
+            None => self.fallback.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.fallback.flush();
+    }
+}
+
+fn init_logger() {
+    LOGGER_INSTALLED.call_once(|| {
+        let fallback = env_logger::Builder::from_env(env_logger::Env::default()).build();
+        let max_level = fallback.filter();
+        if log::set_boxed_logger(Box::new(HostLogger { fallback })).is_ok() {
+            log::set_max_level(max_level);
+        }
+    });
+}
+
+///sends the log records to the callback instead of stderr
+///level is the most verbose level to forward (0=off,1=error,2=warn,3=info,4=debug,5=trace)
+///passing a null callback goes back to logging to stderr
+#[no_mangle]
+pub extern "C" fn lib_set_log_callback(callback: Option<LogCallback>, level: u32) {
+    init_logger();
+    let filter = match level {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Error,
+        2 => log::LevelFilter::Warn,
+        3 => log::LevelFilter::Info,
+        4 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    *LOG_CALLBACK.write().unwrap() = callback;
+    *LOG_LEVEL.write().unwrap() = filter;
+    update_max_level();
+}
+
+///limits the logs sent to the callback per target with a filter such as
+///"neolink::ffi=debug,neolink_core::bc::wire=trace,warn" (the RUST_LOG syntax)
+///the library logs under neolink::ffi, the camera protocol under neolink_core
+///and the raw camera messages under neolink_core::bc::wire
+///this replaces the level given to lib_set_log_callback, null removes the filter
+///returns 0 on success and -1 if the filter is invalid
+#[no_mangle]
+pub extern "C" fn lib_set_log_filter(c_filter: *const c_char) -> i32 {
+    init_logger();
+    let filter = if c_filter.is_null() {
+        None
+    } else {
+        let spec = unsafe { CStr::from_ptr(c_filter) };
+        match spec.to_str() {
+            Ok(spec) => Some(env_logger::filter::Builder::new().parse(spec).build()),
+            Err(_) => return -1,
+        }
+    };
+    *LOG_FILTER.write().unwrap() = filter;
+    update_max_level();
+    0
+}
+
+fn update_max_level() {
+    let level = match (*LOG_CALLBACK.read().unwrap(), LOG_FILTER.read().unwrap().as_ref()) {
+        (Some(_), Some(filter)) => filter.filter(),
+        (Some(_), None) => *LOG_LEVEL.read().unwrap(),
+        (None, _) => env_logger::Builder::from_env(env_logger::Env::default()).build().filter(),
+    };
+    log::set_max_level(level);
+}
+
 /*
 lazy_static! {
     static ref CAMS: HashMap<u64,BcCamera>=HashMap::new();
@@ -60,14 +189,9 @@ lazy_static! {
    
 }*/
 
-//
-fn print_type_of<T>(_: &T) {
-    println!("{}", std::any::type_name::<T>())
-}
-
 #[no_mangle]
 pub extern "C" fn lib_test() {
-    println!("Hello from the library!");
+    log::info!(target: FFI_TARGET, "Hello from the library!");
 }
 
 ///opens a camera connection
@@ -78,12 +202,12 @@ pub extern "C" fn lib_cam_open(
     c_password: *const c_char,
 ) -> *mut BcCamera {
 
-        env_logger::try_init();
+        init_logger();
     
     let ipaddress = string_from_c(c_ipaddress);
     let password = string_from_c(c_password);
     let username = string_from_c(c_username);
-    println!("Hello from the library, host:{}!", ipaddress);
+    log::debug!(target: FFI_TARGET, "Opening camera at {}", ipaddress);
 
     let socketaddr: SocketAddr = ipaddress.parse().unwrap();
     //let ipadr=IpAddr::from_str(&ipaddress).unwrap();
@@ -104,9 +228,11 @@ pub extern "C" fn lib_cam_open(
         },
         debug: false,
         max_discovery_retries: 0,
+        reply_timeout: None,
+        stream_handles: Default::default(),
+        capture_dir: None,
     };
 
-    neolink_core::bc_protocol::Error::AuthFailed
     //let mut rt = Runtime::new().unwrap();
     let cameraResult: std::result::Result<BcCamera,neolink_core::bc_protocol::Error> = RT.block_on(async { BcCamera::new(&options).await});
 
@@ -151,125 +277,351 @@ pub extern "C" fn lib_cam_start_stream(
 		
 		//let mut rt = Runtime::new().unwrap();
 		//let block_on = RT.block_on(
-            RT.spawn(
+            spawn_cancellable(
             async move{
-                println!("hello from the async block");
+                log::debug!(target: FFI_TARGET, "Logging in");
                 let loginResult=cam.login().await.expect("Bad Login data");
-                println!("IAMLOGGEDIN");
+                log::debug!(target: FFI_TARGET, "Logged in");
                 unsafe { (ext_output.info_func)(loginResult.resolution.width, loginResult.resolution.height, 0) };
-                let mut stream_data=cam.start_video(StreamKind::Main,09999,true).await.expect("JW:error1");
+                let mut stream_data=cam.start_video(StreamKind::Main,FFI_STREAM_BUFFER,true).await.expect("JW:error1");
                 //let mut stream_data = camera.start_video(name, 0, strict).await?;
 
                 
 
-                loop {
-                    log::debug!("Waiting for frame");
-                    
-                    let data = match stream_data.get_data().await{
-                        Ok(x)=>x.expect("JW:error2"),
-                        Err(e)=>break
-                    };
-                    
-
-                   
-                    let mut frame_type = FrameType::H264;
-                    let mut timestamp = 0;
-                    let mut payloaddata: Vec<u8> = Vec::new();
-                    let mut microseconds: u32 = 0;
-                    //let data1=data.unwrap();
-                    //let data2=data1.unwrap();
-                    log::debug!("Nice1:a1");
-                    match &data {
-                        BcMedia::InfoV1(payload) => {
-                            log::debug!("---Info1---");
-                            unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
-                        },
-                        BcMedia::InfoV2(payload) => {
-                            log::debug!("---Info2---");
-                            unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
-                        },
-
-                        _ => {
-                            //println!("{}", std::any::type_name::<T>())
-                           
-                            //print_type_of(&data);
-                            log::debug!("XXX:unk1:XXX");
-                        }
-                    }
-                    match data{
-                        BcMedia::Iframe(payload) => {
-                            frame_type = match payload.video_type {
-                                VideoType::H264 => FrameType::H264,
-                                VideoType::H265 => FrameType::H265,
-                            };
-                            microseconds = payload.microseconds;
-                            payloaddata = payload.data;
-                            timestamp = payload.time.unwrap_or(0);
-                        },
-                        BcMedia::Pframe(payload) => {
-                            frame_type = match payload.video_type {
-                                VideoType::H264 => FrameType::H264,
-                                VideoType::H265 => FrameType::H265,
-                            };
-                            microseconds = payload.microseconds;
-                            payloaddata = payload.data;
-                        },
-                        BcMedia::Aac(payload) => {
-                            payloaddata = payload.data;
-                            //microseconds = payload.microseconds;
-                            frame_type = FrameType::AAC;
-                        },
-                        BcMedia::Adpcm(payload) => {
-                            payloaddata = payload.data;
-                            //microseconds = payload.microseconds;
-                            frame_type = FrameType::AdPCM;
-                        },
-                        BcMedia::InfoV1(payload) => {
-                            log::debug!("---Info1---");
-                            unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
-                        },
-                        BcMedia::InfoV2(payload) => {
-                            log::debug!("---Info2---");
-                            unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
-                        },
-
-                        _ => {
-                            log::debug!("XXX:UNK2:XXX");
-                        }
-                    }
-                    log::debug!("Nice1:a2");
-                    if payloaddata.len() > 0 {
-                        let data_length = payloaddata.len().try_into().unwrap();
-                        let data_ptr = payloaddata.as_mut_ptr();
-                        unsafe {
-                            (ext_output.frame_func)(frame_type, timestamp, data_ptr, data_length, microseconds);
-                        }
-                    }
-                    log::debug!("Nice1:a3");
-                    
-                }
-
-
-            //bonus, you could spawn tasks too
-            //tokio::spawn(async { async_function("task1").await });
-            //tokio::spawn(async { async_function("task2").await });
+                forward_frames(&mut stream_data, &ext_output).await;
+
+
             
         });
-        /*cam.start_video(&mut ext_output, Stream::Main)
-            .map_err(|e| println!("error:{}!", e))
-            .ok();*/
-
-         log::debug!("Run finished.");
+         log::debug!(target: FFI_TARGET, "Run finished.");
     //});
 }
 
+///spawns a task on the runtime that is stopped by lib_shutdown
+fn spawn_cancellable<F>(fut: F)
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    RT.spawn(async move {
+        tokio::select! {
+            _ = SHUTDOWN.cancelled() => log::debug!(target: FFI_TARGET, "Task cancelled by shutdown"),
+            _ = fut => {},
+        }
+    });
+}
+
+///passes the frames of a stream to the external callbacks until the stream ends
+async fn forward_frames(stream_data: &mut StreamData, ext_output: &ExtOutputs) {
+    // The consumer may start decoding on any IFrame so each needs its parameter sets
+    let mut param_sets = ParamSetCache::new();
+    // After a corrupt frame the next frames refer to it so skip to the next IFrame
+    let mut skip_to_iframe = false;
+    loop {
+        log::trace!(target: FFI_TARGET, "Waiting for frame");
+        
+        let mut data = match stream_data.get_data().await{
+            Ok(x)=>x.expect("JW:error2"),
+            Err(e)=>break
+        };
+        let checked = match &data {
+            BcMedia::Iframe(BcMediaIframe { video_type, data, .. }) => {
+                Some((true, check_frame(*video_type, data)))
+            }
+            BcMedia::Pframe(BcMediaPframe { video_type, data, .. }) => {
+                Some((false, check_frame(*video_type, data)))
+            }
+            _ => None,
+        };
+        match checked {
+            Some((_, Err(defect))) => {
+                log::debug!(target: FFI_TARGET, "Dropping corrupt frame: {}", defect);
+                skip_to_iframe = true;
+                continue;
+            }
+            Some((true, Ok(()))) => skip_to_iframe = false,
+            _ => {}
+        }
+        if skip_to_iframe {
+            continue;
+        }
+        param_sets.inspect(&mut data);
+        
+
+       
+        let mut frame_type = FrameType::H264;
+        let mut timestamp = 0;
+        let mut payloaddata: Vec<u8> = Vec::new();
+        let mut microseconds: u32 = 0;
+        match data{
+            BcMedia::Iframe(payload) => {
+                frame_type = match payload.video_type {
+                    VideoType::H264 => FrameType::H264,
+                    VideoType::H265 => FrameType::H265,
+                };
+                microseconds = payload.microseconds;
+                payloaddata = payload.data.into();
+                timestamp = payload.time.unwrap_or(0);
+            },
+            BcMedia::Pframe(payload) => {
+                frame_type = match payload.video_type {
+                    VideoType::H264 => FrameType::H264,
+                    VideoType::H265 => FrameType::H265,
+                };
+                microseconds = payload.microseconds;
+                payloaddata = payload.data.into();
+            },
+            BcMedia::Aac(payload) => {
+                payloaddata = payload.data.into();
+                //microseconds = payload.microseconds;
+                frame_type = FrameType::AAC;
+            },
+            BcMedia::Adpcm(payload) => {
+                payloaddata = payload.data.into();
+                //microseconds = payload.microseconds;
+                frame_type = FrameType::AdPCM;
+            },
+            BcMedia::InfoV1(payload) => {
+                log::debug!(target: FFI_TARGET, "Stream info {}x{} at {} fps", payload.video_width, payload.video_height, payload.fps);
+                unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
+            },
+            BcMedia::InfoV2(payload) => {
+                log::debug!(target: FFI_TARGET, "Stream info {}x{} at {} fps", payload.video_width, payload.video_height, payload.fps);
+                unsafe { (ext_output.info_func)(payload.video_width, payload.video_height, payload.fps) };
+            },
+        }
+        if payloaddata.len() > 0 {
+            let data_length = payloaddata.len().try_into().unwrap();
+            let data_ptr = payloaddata.as_mut_ptr();
+            unsafe {
+                (ext_output.frame_func)(frame_type, timestamp, data_ptr, data_length, microseconds);
+            }
+        }
+        
+    }
+}
+
+fn datetime_from_epoch(seconds: u32) -> Option<PrimitiveDateTime> {
+    let dt = OffsetDateTime::from_unix_timestamp(seconds as i64).ok()?;
+    Some(PrimitiveDateTime::new(dt.date(), dt.time()))
+}
+
+fn datetime_to_epoch(dt: &PrimitiveDateTime) -> u32 {
+    dt.assume_utc().unix_timestamp().try_into().unwrap_or(0)
+}
+
+///lists the recordings on the sd card between start and end
+///the times are seconds since 1970 in the cameras local time
+///the callback gets name, start, end and size of each file, the name is only valid during the call
+///returns the number of recordings or -1 on error
+#[no_mangle]
+pub extern "C" fn lib_cam_list_recordings(
+    ptr: *const BcCamera,
+    start: u32,
+    end: u32,
+    recording: unsafe extern "C" fn(*const c_char, u32, u32, u64), //name,start,end,size
+) -> i32 {
+    let cam: &BcCamera = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    let (start, end) = match (datetime_from_epoch(start), datetime_from_epoch(end)) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return -1,
+    };
+
+    let recordings = match RT.block_on(async { cam.get_recordings(StreamKind::Main, start, end).await }) {
+        Ok(recordings) => recordings,
+        Err(e) => {
+            log::error!(target: FFI_TARGET, "Failed to list recordings: {:?}", e);
+            return -1;
+        }
+    };
+
+    for file in recordings.iter() {
+        let name = match file.name.as_ref().and_then(|name| CString::new(name.as_str()).ok()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let file_start = file.start_time.as_ref().and_then(|t| PrimitiveDateTime::try_from(t).ok()).map(|t| datetime_to_epoch(&t)).unwrap_or(0);
+        let file_end = file.end_time.as_ref().and_then(|t| PrimitiveDateTime::try_from(t).ok()).map(|t| datetime_to_epoch(&t)).unwrap_or(0);
+        unsafe { recording(name.as_ptr(), file_start, file_end, file.size.unwrap_or(0)) };
+    }
+    recordings.len().try_into().unwrap_or(i32::MAX)
+}
+
+///starts the playback of a recording from the sd card
+///the name is one given by lib_cam_list_recordings, frames are delivered like lib_cam_start_stream
+#[no_mangle]
+pub extern "C" fn lib_cam_playback_start(
+    ptr: *const BcCamera,
+    c_name: *const c_char,
+    newdata: unsafe extern "C" fn(FrameType, u32, *mut u8, i32, u32),
+    info: unsafe extern "C" fn(u32, u32, u8), //width,height,fps
+) -> i32 {
+    let ext_output: ExtOutputs = ExtOutputs {
+        frame_func: newdata,
+        info_func: info,
+    };
+
+    let cam: &BcCamera = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+    let name = string_from_c(c_name);
+
+    let mut stream_data = match RT.block_on(async { cam.start_playback(&name, StreamKind::Main, DEFAULT_STREAM_BUFFER, true).await }) {
+        Ok(stream_data) => stream_data,
+        Err(e) => {
+            log::error!(target: FFI_TARGET, "Failed to start playback of {}: {:?}", name, e);
+            return -1;
+        }
+    };
+    spawn_cancellable(async move {
+        forward_frames(&mut stream_data, &ext_output).await;
+        log::debug!(target: FFI_TARGET, "Playback finished.");
+    });
+    0
+}
+
+#[derive(Serialize)]
+struct SupportSummary {
+    ptz: bool,
+    talk: bool,
+    battery: bool,
+    email: bool,
+    floodlight: bool,
+    replay: bool,
+}
+
+fn support_summary(cam: &BcCamera) -> Result<SupportSummary, neolink_core::bc_protocol::Error> {
+    let is_set = |v: Option<u32>| v.unwrap_or(0) > 0;
+    let support = RT.block_on(async { cam.get_support().await })?;
+    //channel 0 as we only open single cameras
+    let item = support.items.iter().find(|item| item.chn_id == 0);
+    let item_set = |f: fn(&SupportItem) -> Option<u32>| item.map(|item| is_set(f(item))).unwrap_or(false);
+
+    //there is no support flag for the floodlight so check if its tasks can be read
+    let floodlight = RT.block_on(async { cam.get_flightlight_tasks().await }).is_ok();
+
+    Ok(SupportSummary {
+        ptz: support.ptz_mode.as_deref().map(|mode| mode != "none").unwrap_or(false) || item_set(|i| i.ptz_control),
+        talk: is_set(support.audio_talk) || item_set(|i| i.ipc_audio_talk),
+        battery: item_set(|i| i.battery),
+        email: is_set(support.email),
+        floodlight,
+        replay: is_set(support.replay_version),
+    })
+}
+
+///returns a json summary of what the camera supports, e.g. {"ptz":true,"talk":false,...}
+///returns null on error, the string must be freed with lib_free_string
+#[no_mangle]
+pub extern "C" fn lib_cam_get_support(ptr: *const BcCamera) -> *mut c_char {
+    let cam: &BcCamera = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+
+    let summary = match support_summary(cam) {
+        Ok(summary) => summary,
+        Err(e) => {
+            log::error!(target: FFI_TARGET, "Failed to get support: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    match serde_json::to_string(&summary).ok().and_then(|json| CString::new(json).ok()) {
+        Some(json) => json.into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[derive(Serialize)]
+struct VersionSummary {
+    version: &'static str,
+    crate_version: &'static str,
+    git_hash: &'static str,
+    features: Vec<&'static str>,
+}
+
+///returns a json description of this build, e.g. {"version":"v0.6.2","git_hash":"1a2b3c",...}
+///the string must be freed with lib_free_string
+#[no_mangle]
+pub extern "C" fn lib_get_version() -> *mut c_char {
+    let features = [
+        ("gstreamer", cfg!(feature = "gstreamer")),
+        ("tracing", cfg!(feature = "tracing")),
+        ("otel", cfg!(feature = "otel")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| *name)
+    .collect();
+    let summary = VersionSummary {
+        version: env!("NEOLINK_VERSION").trim(),
+        crate_version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("NEOLINK_GIT_HASH"),
+        features,
+    };
+    match serde_json::to_string(&summary).ok().and_then(|json| CString::new(json).ok()) {
+        Some(json) => json.into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+///frees a string returned by the library
+#[no_mangle]
+pub extern "C" fn lib_free_string(s: *mut c_char) {
+    if s.is_null() {
+        return;
+    }
+    unsafe {
+        drop(CString::from_raw(s));
+    }
+}
+
+///zooms the camera to the given position, returns 0 on success and -1 on error
+///the position is clamped to the cameras zoom range
+#[no_mangle]
+pub extern "C" fn lib_cam_zoom(ptr: *const BcCamera, position: u32) -> i32 {
+    let cam: &BcCamera = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+
+    match RT.block_on(async { cam.zoom_to(position).await }) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!(target: FFI_TARGET, "Failed to zoom: {:?}", e);
+            -1
+        }
+    }
+}
+
+///moves the focus of the camera to the given position, returns 0 on success and -1 on error
+///the position is clamped to the cameras focus range
+#[no_mangle]
+pub extern "C" fn lib_cam_focus(ptr: *const BcCamera, position: u32) -> i32 {
+    let cam: &BcCamera = unsafe {
+        assert!(!ptr.is_null());
+        &*ptr
+    };
+
+    match RT.block_on(async { cam.focus_to(position).await }) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!(target: FFI_TARGET, "Failed to focus: {:?}", e);
+            -1
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn lib_cam_stop(ptr: *mut BcCamera) {
     let cam = unsafe {
         assert!(!ptr.is_null());
         &mut *ptr
     };
-    log::debug!("Shutdown...");
+    log::debug!(target: FFI_TARGET, "Shutdown...");
 
     //let mut rt = Runtime::new().unwrap();
     RT.block_on(
@@ -280,8 +632,8 @@ pub extern "C" fn lib_cam_stop(ptr: *mut BcCamera) {
     );
 
 
-    log::debug!("Shutdown!");
-    log::debug!("Join..");
+    log::debug!(target: FFI_TARGET, "Shutdown!");
+    log::debug!(target: FFI_TARGET, "Join..");
     let cam:&BcCamera = unsafe {
         assert!(!ptr.is_null());
         &*ptr
@@ -292,7 +644,7 @@ pub extern "C" fn lib_cam_stop(ptr: *mut BcCamera) {
             cam.join().await;
         }
     );
-    log::debug!("Join!");
+    log::debug!(target: FFI_TARGET, "Join!");
 }
 
 pub fn string_from_c(s: *const c_char) -> String {
@@ -305,3 +657,16 @@ pub fn string_from_c(s: *const c_char) -> String {
     let r_str = c_str.to_str().unwrap();
     return r_str.to_string();
 }
+
+///stops all running streams and shuts down the runtime so the library can be unloaded
+///cameras should be stopped with lib_cam_stop before, no other function may be called afterwards
+#[no_mangle]
+pub extern "C" fn lib_shutdown() {
+    SHUTDOWN.cancel();
+    let runtime = RUNTIME.lock().unwrap().take();
+    if let Some(runtime) = runtime {
+        //let the cancelled tasks run their drop code
+        runtime.block_on(tokio::task::yield_now());
+        runtime.shutdown_timeout(std::time::Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,186 @@
+//!
+//! # Logging
+//!
+//! All logs go to stderr through `env_logger` as usual. When `[log_files]` is
+//! set in the config the messages about a camera are additionally written to
+//! `{dir}/{camera}.log`.
+//!
+//! A message is about a camera if it starts with `{camera}: `, which is how the
+//! camera threads and subcommands prefix their logs.
+//!
+//! The files are rotated once they reach `max_size` bytes, keeping `keep` old
+//! files as `{camera}.log.1` (newest) to `{camera}.log.{keep}` (oldest).
+//!
+//! With the `tracing` feature the messages are also forwarded to tracing as
+//! events of the span of the camera thread that logged them.
+//!
+use anyhow::{Context, Result};
+use env_logger::Env;
+use lazy_static::lazy_static;
+use log::{Log, Metadata, Record};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+
+use crate::config::{Config, LogFilesConfig};
+
+lazy_static! {
+    static ref CAMERA_LOGS: RwLock<Vec<CameraLog>> = RwLock::new(vec![]);
+}
+
+struct NeoLogger {
+    inner: env_logger::Logger,
+}
+
+/// Install the logger, this should be called before anything is logged
+pub(crate) fn init() {
+    let inner = env_logger::Builder::from_env(Env::default().default_filter_or("info")).build();
+    let max_level = inner.filter();
+    log::set_boxed_logger(Box::new(NeoLogger { inner })).expect("Logger should only be set once");
+    log::set_max_level(max_level);
+}
+
+/// Open the per camera log files from the config
+///
+/// This replaces any previously opened files so it can be called again when
+/// the config changes
+pub(crate) fn set_camera_logs(config: &Config) -> Result<()> {
+    let logs = match config.log_files.as_ref() {
+        Some(log_config) => {
+            fs::create_dir_all(&log_config.dir).with_context(|| {
+                format!("Failed to create the log directory {:?}", log_config.dir)
+            })?;
+            config
+                .cameras
+                .iter()
+                .filter(|camera| camera.enabled)
+                .map(|camera| CameraLog::open(&camera.name, log_config))
+                .collect::<Result<Vec<_>>>()?
+        }
+        None => vec![],
+    };
+    *CAMERA_LOGS.write().unwrap() = logs;
+    Ok(())
+}
+
+impl Log for NeoLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        crate::common::log_event(record);
+
+        let logs = CAMERA_LOGS.read().unwrap();
+        if logs.is_empty() {
+            return;
+        }
+        let msg = record.args().to_string();
+        for log in logs.iter().filter(|log| msg.starts_with(&log.prefix)) {
+            let line = format!(
+                "[{} {:<5} {}] {}\n",
+                humantime::format_rfc3339_seconds(SystemTime::now()),
+                record.level(),
+                record.target(),
+                msg
+            );
+            // Nowhere to report a failure to log so it is dropped
+            let _ = log.file.lock().unwrap().write_line(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        for log in CAMERA_LOGS.read().unwrap().iter() {
+            let _ = log.file.lock().unwrap().file.flush();
+        }
+    }
+}
+
+struct CameraLog {
+    /// Messages starting with this belong to the camera
+    prefix: String,
+    file: Mutex<RotatingFile>,
+}
+
+impl CameraLog {
+    fn open(name: &str, config: &LogFilesConfig) -> Result<Self> {
+        let file_name = format!("{}.log", name.replace(['/', '\\'], "_"));
+        Ok(Self {
+            prefix: format!("{name}: "),
+            file: Mutex::new(RotatingFile::open(
+                config.dir.join(file_name),
+                config.max_size,
+                config.keep,
+            )?),
+        })
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> Result<Self> {
+        let file = Self::append(&path)?;
+        let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn append(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open the log file {:?}", path))
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = Self::append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -31,29 +31,59 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use env_logger::Env;
 use log::*;
-use std::fs;
-use validator::Validate;
 
+mod audio;
 mod battery;
+mod bcsend;
 mod cmdline;
 mod common;
 mod config;
+mod configtool;
+mod crash;
+mod discover;
+mod encode;
+mod exitcode;
+mod floodlight;
+mod hls;
+mod i18n;
 mod image;
+mod info;
+mod isp;
+mod logging;
+mod mask;
+mod mdconfig;
+mod media;
+mod motion;
 mod mqtt;
+mod onvif;
+mod osd;
 mod pir;
 mod ptz;
+mod pushnoti;
+mod quirks;
 mod reboot;
+mod record;
+#[cfg(feature = "gstreamer")]
 mod rtsp;
+mod services;
+mod snapshot;
 mod statusled;
+mod storage;
 mod talk;
+mod testnotify;
+mod timelapse;
 mod utils;
+mod webhook;
+#[cfg(feature = "gstreamer")]
+mod webrtc;
 
 use cmdline::{Command, Opt};
-use common::NeoReactor;
+use common::{ConfigWatcher, NeoReactor};
 use config::Config;
 use console_subscriber as _;
+use exitcode::ErrorClass;
+use i18n::tr;
 
 pub(crate) type AnyResult<T> = Result<T, anyhow::Error>;
 
@@ -69,8 +99,9 @@ fn tokio_console_enable() {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+async fn main() -> std::process::ExitCode {
+    logging::init();
+    crash::install();
 
     info!(
         "Neolink {} {}",
@@ -79,54 +110,152 @@ async fn main() -> Result<()> {
     );
 
     let opt = Opt::parse();
+    let error_format = opt.error_format;
+    utils::set_pcap_dir(opt.pcap_dir.clone());
+    match run(opt).await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => exitcode::report(&e, error_format),
+    }
+}
+
+async fn run(opt: Opt) -> Result<()> {
+    // These commands do not need the config so they run before loading it
+    let cmd = match opt.cmd {
+        Some(Command::Config(opts)) => {
+            return configtool::main(opts, opt.config.as_deref()).await;
+        }
+        Some(Command::Discover(opts)) => {
+            return discover::main(opts).await;
+        }
+        cmd => cmd,
+    };
 
-    let conf_path = opt.config.context("Must supply --config file")?;
-    let config: Config = toml::from_str(
-        &fs::read_to_string(&conf_path)
-            .with_context(|| format!("Failed to read {:?}", conf_path))?,
-    )
-    .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    let conf_path = opt
+        .config
+        .context(tr!("config.missing"))
+        .context(ErrorClass::Config)?;
+    let config = Config::from_file(&conf_path).context(ErrorClass::Config)?;
 
-    config
-        .validate()
-        .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
+    i18n::set_language(config.language.as_deref());
+    logging::set_camera_logs(&config)?;
+    crash::set_config(&config);
+    quirks::set_config(&config);
+
+    // Only reads the saved registrations so the cameras are not started
+    let cmd = match cmd {
+        Some(Command::Pushnoti(opts)) => {
+            return pushnoti::main(opts, &config).await;
+        }
+        cmd => cmd,
+    };
 
     if config.tokio_console {
         tokio_console_enable();
     }
 
     let neo_reactor = NeoReactor::new(config.clone()).await;
+    let _config_watcher = config
+        .watch_config
+        .then(|| ConfigWatcher::new(conf_path.clone(), neo_reactor.clone()));
+
+    let result = tokio::select! {
+        v = run_command(cmd, &config, &neo_reactor) => v,
+        v = tokio::signal::ctrl_c() => {
+            info!("{}", tr!("shutdown.interrupted"));
+            v.context(tr!("shutdown.signal_failed"))
+        }
+    };
 
-    match opt.cmd {
+    // Dropping the reactor only starts the logout so wait for it here
+    // or the cameras can be left with our sessions still open
+    info!("{}", tr!("shutdown.logging_out"));
+    neo_reactor.shutdown().await;
+
+    result
+}
+
+#[cfg_attr(not(feature = "gstreamer"), allow(unused_variables))]
+async fn run_command(
+    cmd: Option<Command>,
+    config: &Config,
+    neo_reactor: &NeoReactor,
+) -> Result<()> {
+    match cmd {
+        #[cfg(feature = "gstreamer")]
         None => {
             warn!(
                 "Deprecated command line option. Please use: `neolink rtsp --config={:?}`",
                 config
             );
+            neo_reactor.connect_all().await?;
             rtsp::main(rtsp::Opt {}, neo_reactor.clone()).await?;
         }
+        #[cfg(not(feature = "gstreamer"))]
+        None => {
+            return Err(anyhow::anyhow!(
+                "Neolink was built without the `gstreamer` feature and cannot serve rtsp"
+            ));
+        }
+        #[cfg(feature = "gstreamer")]
         Some(Command::Rtsp(opts)) => {
+            neo_reactor.connect_all().await?;
             rtsp::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::StatusLight(opts)) => {
             statusled::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Floodlight(opts)) => {
+            floodlight::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Reboot(opts)) => {
             reboot::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Pir(opts)) => {
             pir::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Motion(opts)) => {
+            motion::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::MdConfig(opts)) => {
+            mdconfig::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Mask(opts)) => {
+            mask::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Osd(opts)) => {
+            osd::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Isp(opts)) => {
+            isp::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Encode(opts)) => {
+            encode::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Audio(opts)) => {
+            audio::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::TestNotify(opts)) => {
+            testnotify::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Storage(opts)) => {
+            storage::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Ptz(opts)) => {
             ptz::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Services(opts)) => {
+            services::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Talk(opts)) => {
             talk::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Mqtt(opts)) => {
+            neo_reactor.connect_all().await?;
             mqtt::main(opts, neo_reactor.clone()).await?;
         }
+        #[cfg(feature = "gstreamer")]
         Some(Command::MqttRtsp(opts)) => {
+            neo_reactor.connect_all().await?;
             tokio::select! {
                 v = mqtt::main(opts, neo_reactor.clone()) => v,
                 v = rtsp::main(rtsp::Opt {}, neo_reactor.clone()) => v,
@@ -135,9 +264,43 @@ async fn main() -> Result<()> {
         Some(Command::Image(opts)) => {
             image::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Info(opts)) => {
+            info::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Battery(opts)) => {
             battery::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::BcSend(opts)) => {
+            bcsend::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::OnvifEvents(opts)) => {
+            onvif::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Webrtc(opts)) => {
+            webrtc::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Hls(opts)) => {
+            hls::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Record(opts)) => {
+            record::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::SnapshotDaemon(opts)) => {
+            snapshot::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Timelapse(opts)) => {
+            timelapse::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Webhook(opts)) => {
+            webhook::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Config(_)) | Some(Command::Discover(_)) => {
+            unreachable!("Runs before the config is loaded")
+        }
+        Some(Command::Pushnoti(_)) => {
+            unreachable!("Runs before the cameras are started")
+        }
     }
 
     Ok(())
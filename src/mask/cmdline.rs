@@ -0,0 +1,30 @@
+use anyhow::Result;
+use clap::Parser;
+use neolink_core::bc_protocol::MaskRegion;
+
+fn region_parse(src: &str) -> Result<MaskRegion> {
+    Ok(src.parse()?)
+}
+
+/// The mask command will control the privacy mask of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+
+    #[command(subcommand)]
+    pub cmd: MaskCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum MaskCommand {
+    /// Print the privacy mask xml
+    Get,
+    /// Mask these regions, each is x,y,width,height in the coordinates of the xml
+    Set {
+        #[arg(value_parser = region_parse, required = true)]
+        regions: Vec<MaskRegion>,
+    },
+    /// Remove every region and turn the privacy mask off
+    Clear,
+}
@@ -0,0 +1,73 @@
+///
+/// # Neolink Mask
+///
+/// This module handles the privacy mask of the camera, the masked regions
+/// are blacked out of the video and the recordings
+///
+/// Each region is `x,y,width,height` in the coordinates of the `width` and
+/// `height` of the camera's Shelter xml
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the privacy mask
+/// neolink mask --config=config.toml CameraName get
+/// # To mask two regions
+/// neolink mask --config=config.toml CameraName set 0,0,200,100 400,300,100,60
+/// # To turn the privacy mask off
+/// neolink mask --config=config.toml CameraName clear
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+use cmdline::MaskCommand;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the mask subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let regions = match opt.cmd {
+        MaskCommand::Get => {
+            let shelter = camera
+                .run_task(|cam| {
+                    Box::pin(
+                        async move { cam.get_privacy_mask().await.context(tr!("mask.get_failed")) },
+                    )
+                })
+                .await?;
+            let shelter_ser = String::from_utf8(
+                yaserde::ser::serialize_with_writer(&shelter, vec![], &Default::default())
+                    .expect("Should Ser the struct"),
+            )
+            .expect("Should be UTF8");
+            println!("{}", shelter_ser);
+            return Ok(());
+        }
+        MaskCommand::Set { regions } => regions,
+        MaskCommand::Clear => vec![],
+    };
+
+    let confirmation = camera
+        .run_task(|cam| {
+            let regions = regions.clone();
+            Box::pin(async move {
+                cam.set_privacy_mask_regions(&regions)
+                    .await
+                    .context(tr!("mask.set_failed"))
+            })
+        })
+        .await?;
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("mask.set_unconfirmed"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use clap::Parser;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum CmdAiType {
+    People,
+    Vehicle,
+    DogCat,
+}
+
+impl CmdAiType {
+    /// The name of the kind of object in the camera's xml
+    pub fn xml_name(self) -> &'static str {
+        match self {
+            CmdAiType::People => "people",
+            CmdAiType::Vehicle => "vehicle",
+            CmdAiType::DogCat => "dog_cat",
+        }
+    }
+}
+
+/// The md-config command will tune the motion and ai detection of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+
+    #[command(subcommand)]
+    pub cmd: MdConfigCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum MdConfigCommand {
+    /// Print the motion detection xml, or the ai detection xml of one kind of object
+    Get {
+        #[arg(long, value_enum)]
+        ai: Option<CmdAiType>,
+    },
+    /// Set the motion sensitivity from 1 to 50, lower values trigger more easily
+    Sensitivity {
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=50))]
+        sensitivity: u8,
+    },
+    /// Set the ai sensitivity of one kind of object from 0 to 100, higher values trigger more easily
+    AiSensitivity {
+        #[arg(value_enum)]
+        ai: CmdAiType,
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        sensitivity: u8,
+    },
+    /// Print the detection zone as rows of # for watched cells and . for ignored ones
+    Zone {
+        #[arg(long, value_enum)]
+        ai: Option<CmdAiType>,
+    },
+    /// Set the detection zone from a file written like the output of zone, or `all`
+    SetZone {
+        #[arg(long, value_enum)]
+        ai: Option<CmdAiType>,
+        zone: String,
+    },
+}
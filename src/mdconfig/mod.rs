@@ -0,0 +1,154 @@
+///
+/// # Neolink MD Config
+///
+/// This module tunes the motion and ai detection of the camera so that false
+/// positives can be reduced without the Reolink app
+///
+/// The detection zone is printed and read as rows of `#` for the watched
+/// cells and `.` for the ignored ones
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the motion detection settings
+/// neolink md-config --config=config.toml CameraName get
+/// # Or those of the people detection
+/// neolink md-config --config=config.toml CameraName get --ai people
+/// # Make the motion detection trigger less easily
+/// neolink md-config --config=config.toml CameraName sensitivity 30
+/// # Make the vehicle detection trigger more easily
+/// neolink md-config --config=config.toml CameraName ai-sensitivity vehicle 70
+/// # Edit the detection zone
+/// neolink md-config --config=config.toml CameraName zone > zone.txt
+/// neolink md-config --config=config.toml CameraName set-zone zone.txt
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::{
+    bc::xml::{AiAlarm, MdAlarm},
+    bc_protocol::{Confirmation, DetectionZone},
+};
+
+mod cmdline;
+
+use crate::common::{NeoInstance, NeoReactor};
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+use cmdline::{CmdAiType, MdConfigCommand};
+
+/// Entry point for the md-config subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let confirmation = match opt.cmd {
+        MdConfigCommand::Get { ai } => {
+            let xml = match get_detection(&camera, ai).await? {
+                Detection::Md(md_alarm) => to_xml(&md_alarm),
+                Detection::Ai(ai_alarm) => to_xml(&ai_alarm),
+            };
+            println!("{}", xml);
+            return Ok(());
+        }
+        MdConfigCommand::Zone { ai } => {
+            let zone = get_detection(&camera, ai).await?.zone()?;
+            print!("{}", zone);
+            return Ok(());
+        }
+        MdConfigCommand::Sensitivity { sensitivity } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.md_set_sensitivity(sensitivity)
+                            .await
+                            .context(tr!("mdconfig.set_failed"))
+                    })
+                })
+                .await?
+        }
+        MdConfigCommand::AiSensitivity { ai, sensitivity } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.ai_set_sensitivity(ai.xml_name(), sensitivity)
+                            .await
+                            .context(tr!("mdconfig.set_failed"))
+                    })
+                })
+                .await?
+        }
+        MdConfigCommand::SetZone { ai, zone } => {
+            let mut detection = get_detection(&camera, ai).await?;
+            let zone = if zone == "all" {
+                detection.zone()?.all()
+            } else {
+                tokio::fs::read_to_string(&zone)
+                    .await
+                    .with_context(|| tr!("mdconfig.zone_read_failed", path = zone.clone()))?
+                    .parse::<DetectionZone>()
+                    .with_context(|| tr!("mdconfig.zone_read_failed", path = zone.clone()))?
+            };
+            match &mut detection {
+                Detection::Md(md_alarm) => md_alarm.set_zone(&zone)?,
+                Detection::Ai(ai_alarm) => ai_alarm.set_zone(&zone)?,
+            }
+            camera
+                .run_task(|cam| {
+                    let detection = detection.clone();
+                    Box::pin(async move {
+                        match detection {
+                            Detection::Md(md_alarm) => cam.set_md_alarm(md_alarm).await,
+                            Detection::Ai(ai_alarm) => cam.set_ai_alarm(ai_alarm).await,
+                        }
+                        .context(tr!("mdconfig.set_failed"))
+                    })
+                })
+                .await?
+        }
+    };
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("mdconfig.set_unconfirmed"));
+    }
+
+    Ok(())
+}
+
+/// The settings of either the motion or the ai detection
+#[derive(Clone)]
+enum Detection {
+    Md(MdAlarm),
+    Ai(AiAlarm),
+}
+
+impl Detection {
+    fn zone(&self) -> Result<DetectionZone> {
+        let zone = match self {
+            Detection::Md(md_alarm) => md_alarm.zone(),
+            Detection::Ai(ai_alarm) => ai_alarm.zone(),
+        };
+        Ok(zone.ok_or_else(|| anyhow!(tr!("mdconfig.no_zone")))??)
+    }
+}
+
+async fn get_detection(camera: &NeoInstance, ai: Option<CmdAiType>) -> Result<Detection> {
+    camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                match ai {
+                    Some(ai) => cam.get_ai_alarm(ai.xml_name()).await.map(Detection::Ai),
+                    None => cam.get_md_alarm().await.map(Detection::Md),
+                }
+                .context(tr!("mdconfig.get_failed"))
+            })
+        })
+        .await
+}
+
+fn to_xml<T: yaserde::YaSerialize>(xml: &T) -> String {
+    String::from_utf8(
+        yaserde::ser::serialize_with_writer(xml, vec![], &Default::default())
+            .expect("Should Ser the struct"),
+    )
+    .expect("Should be UTF8")
+}
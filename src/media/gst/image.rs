@@ -1,6 +1,7 @@
 use std::path::Path;
 
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use gstreamer::{parse_launch, prelude::*, ClockTime, MessageView, Pipeline, State};
 use gstreamer_app::AppSrc;
 use tokio::{
@@ -12,37 +13,37 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{common::VidFormat, AnyResult};
+use crate::{common::VidFormat, media::ImageSink, AnyResult};
 
 #[derive(Debug)]
 enum GstControl {
-    Data(std::sync::Arc<Vec<u8>>),
+    Data(Bytes),
     Eos,
 }
 
-pub(super) struct GstSender {
+pub(crate) struct GstSender {
     sender: Sender<GstControl>,
     set: JoinSet<Result<()>>,
     finished: sync::oneshot::Receiver<Result<()>>,
     cancel: CancellationToken,
 }
 
-impl GstSender {
-    pub(super) async fn send(&self, buf: std::sync::Arc<Vec<u8>>) -> Result<()> {
+impl ImageSink for GstSender {
+    async fn send(&self, buf: Bytes) -> Result<()> {
         self.sender
             .send(GstControl::Data(buf))
             .await
             .map_err(|e| anyhow!("Failed to send buffer: {:?}", e))
     }
 
-    pub(super) async fn eos(&self) -> Result<()> {
+    async fn eos(&self) -> Result<()> {
         self.sender
             .send(GstControl::Eos)
             .await
             .map_err(|e| anyhow!("Failed to send eos: {:?}", e))
     }
 
-    pub(super) async fn is_finished(&mut self) -> Option<Result<()>> {
+    async fn is_finished(&mut self) -> Option<Result<()>> {
         match self.finished.try_recv() {
             Ok(res) => Some(res),
             Err(sync::oneshot::error::TryRecvError::Empty) => None,
@@ -52,7 +53,7 @@ impl GstSender {
         }
     }
 
-    pub(super) async fn join(mut self) -> Result<()> {
+    async fn join(mut self) -> Result<()> {
         while self.set.join_next().await.is_some() {}
         Ok(())
     }
@@ -93,12 +94,8 @@ async fn output(pipeline: Pipeline) -> Result<GstSender> {
                     tokio::task::yield_now().await;
                     match control {
                         GstControl::Data(buf) => {
-                            let mut gst_buf = gstreamer::Buffer::with_size(buf.len()).unwrap();
-                            {
-                                let gst_buf_mut = gst_buf.get_mut().unwrap();
-                                let mut gst_buf_data = gst_buf_mut.map_writable().unwrap();
-                                gst_buf_data.copy_from_slice(&buf);
-                            }
+                            // Wraps the frame without a copy
+                            let gst_buf = gstreamer::Buffer::from_slice(buf);
                             source.push_buffer(gst_buf).map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
                         }
                         GstControl::Eos => {
@@ -0,0 +1,43 @@
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use std::path::Path;
+use tokio::task::JoinSet;
+
+mod image;
+mod talk;
+mod timelapse;
+
+use super::{MediaBackend, TalkInput};
+use crate::{common::VidFormat, AnyResult};
+
+/// Media backend that uses gstreamer pipelines
+pub(crate) struct GstBackend;
+
+impl MediaBackend for GstBackend {
+    type ImageSink = image::GstSender;
+
+    async fn image_sink(format: VidFormat, out_file: &Path) -> Result<Self::ImageSink> {
+        image::from_input(format, out_file).await
+    }
+
+    async fn assemble_timelapse(frames_dir: &Path, fps: u32, out_file: &Path) -> Result<()> {
+        timelapse::assemble(frames_dir, fps, out_file).await
+    }
+
+    fn talk_source(
+        input: TalkInput<'_>,
+        volume: f32,
+        block_align: u16,
+        sample_rate: u16,
+    ) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
+        let input_src = match input {
+            TalkInput::File(path) => format!(
+                "filesrc location={}",
+                path.to_str().expect("File path not UTF8 complient")
+            ),
+            TalkInput::Device(src) => src.to_string(),
+            TalkInput::Uri(uri) => format!("urisourcebin uri=\"{uri}\""),
+        };
+        talk::from_input(&input_src, volume, block_align, sample_rate)
+    }
+}
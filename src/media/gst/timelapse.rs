@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{parse_launch, prelude::*, ClockTime, MessageView, Pipeline, State};
+
+pub(super) async fn assemble(frames_dir: &Path, fps: u32, out_file: &Path) -> Result<()> {
+    let pipeline = create_pipeline(frames_dir, fps, out_file)?;
+    tokio::task::spawn_blocking(move || run_pipeline(pipeline))
+        .await
+        .context("The timelapse pipeline panicked")?
+}
+
+/// Run the pipeline until all the frames are written
+///
+/// Unlike the other pipelines an error ends it straight away as a partly
+/// written mp4 is of no use
+fn run_pipeline(pipeline: Pipeline) -> Result<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    let mut res = Ok(());
+    for msg in bus.iter_timed(ClockTime::NONE) {
+        match msg.view() {
+            MessageView::Eos(..) => break,
+            MessageView::Error(err) => {
+                res = Err(anyhow!(
+                    "Error from gstreamer while assembling the timelapse: {}",
+                    err.error()
+                ));
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+
+    res
+}
+
+fn create_pipeline(frames_dir: &Path, fps: u32, out_file: &Path) -> Result<Pipeline> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    // splitfilesrc reads the matching files one after the other sorted by
+    // name and jpegparse splits them back into frames
+    let launch_str = format!(
+        "splitfilesrc location=\"{}\" caps=\"image/jpeg,framerate={}/1\" \
+        ! jpegparse \
+        ! jpegdec \
+        ! videoconvert \
+        ! x264enc \
+        ! video/x-h264,profile=main \
+        ! mp4mux \
+        ! filesink location=\"{}\"",
+        frames_dir.join("*.jpeg").display(),
+        fps,
+        out_file.display()
+    );
+
+    log::debug!("{}", launch_str);
+
+    let pipeline = parse_launch(&launch_str)
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?;
+    pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+        anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+    })
+}
@@ -0,0 +1,90 @@
+//!
+//! # Media Backends
+//!
+//! Neolink needs to transcode media for a few of its subcommands. The
+//! image subcommand turns a video stream into a jpeg, the talk
+//! subcommand turns an audio file or microphone into adpcm and the
+//! timelapse subcommand turns a directory of jpegs into an mp4.
+//!
+//! This is done through a [`MediaBackend`] so that alternative backends
+//! can be slotted in per feature. The backend in use is exported as
+//! [`Backend`].
+//!
+//! The full backend is gstreamer with the `gstreamer` feature which is on
+//! by default. Without gstreamer the `openh264` feature provides a pure rust
+//! backend that can only decode h264 into jpegs, which is enough for the
+//! image subcommand and snapshots. When neolink is built without any backend
+//! the commands that need one will fail with an error at runtime.
+//!
+//! The rtsp server is built directly on gstreamer-rtsp-server and so is
+//! only available with the `gstreamer` feature.
+//!
+use anyhow::Result;
+use bytes::Bytes;
+use crossbeam_channel::Receiver;
+use std::path::Path;
+use tokio::task::JoinSet;
+
+use crate::{common::VidFormat, AnyResult};
+
+#[cfg(feature = "gstreamer")]
+mod gst;
+#[cfg(not(feature = "gstreamer"))]
+mod null;
+#[cfg(all(feature = "openh264", not(feature = "gstreamer")))]
+mod soft;
+
+/// The media backend selected at compile time
+#[cfg(feature = "gstreamer")]
+pub(crate) type Backend = gst::GstBackend;
+/// The media backend selected at compile time
+#[cfg(all(feature = "openh264", not(feature = "gstreamer")))]
+pub(crate) type Backend = soft::SoftBackend;
+/// The media backend selected at compile time
+#[cfg(not(any(feature = "gstreamer", feature = "openh264")))]
+pub(crate) type Backend = null::NullBackend;
+
+/// Where the audio for talk should come from
+pub(crate) enum TalkInput<'a> {
+    /// An audio file on disk
+    File(&'a Path),
+    /// A backend specific description of an input device
+    Device(&'a str),
+    /// An audio file at a url such as `https://` or `file://`
+    Uri(&'a str),
+}
+
+/// Accepts video frames and writes them out as a still image
+pub(crate) trait ImageSink {
+    /// Send a frame of video data
+    async fn send(&self, buf: Bytes) -> Result<()>;
+    /// Signal that no more frames will be sent
+    async fn eos(&self) -> Result<()>;
+    /// Returns `Some` once the image has been written
+    async fn is_finished(&mut self) -> Option<Result<()>>;
+    /// Wait for the sink to finish
+    async fn join(self) -> Result<()>;
+}
+
+/// A media backend used to transcode the camera's media
+pub(crate) trait MediaBackend {
+    /// The sink returned by [`MediaBackend::image_sink`]
+    type ImageSink: ImageSink;
+
+    /// Create a sink that will write a jpeg to `out_file` from
+    /// video frames in the given format
+    async fn image_sink(format: VidFormat, out_file: &Path) -> Result<Self::ImageSink>;
+
+    /// Encode the jpegs in `frames_dir`, in the order of their file names, into
+    /// an h264 mp4 at `out_file` that plays them at `fps`
+    async fn assemble_timelapse(frames_dir: &Path, fps: u32, out_file: &Path) -> Result<()>;
+
+    /// Create a source of DVI-4 adpcm blocks suitable for the camera to play
+    #[allow(clippy::type_complexity)]
+    fn talk_source(
+        input: TalkInput<'_>,
+        volume: f32,
+        block_align: u16,
+        sample_rate: u16,
+    ) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)>;
+}
@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use crossbeam_channel::Receiver;
+use std::path::Path;
+use tokio::task::JoinSet;
+
+use super::{ImageSink, MediaBackend, TalkInput};
+use crate::{common::VidFormat, exitcode::ErrorClass, AnyResult};
+
+const NO_BACKEND: &str =
+    "Neolink was built without a media backend that supports this, enable the `gstreamer` feature";
+
+/// Used when no media backend feature is enabled
+///
+/// All operations fail at runtime
+pub(crate) struct NullBackend;
+
+/// Image sink of the [`NullBackend`], this can never be constructed
+pub(crate) enum NullSink {}
+
+impl ImageSink for NullSink {
+    async fn send(&self, _buf: Bytes) -> Result<()> {
+        match *self {}
+    }
+
+    async fn eos(&self) -> Result<()> {
+        match *self {}
+    }
+
+    async fn is_finished(&mut self) -> Option<Result<()>> {
+        match *self {}
+    }
+
+    async fn join(self) -> Result<()> {
+        match self {}
+    }
+}
+
+impl MediaBackend for NullBackend {
+    type ImageSink = NullSink;
+
+    async fn image_sink(_format: VidFormat, _out_file: &Path) -> Result<Self::ImageSink> {
+        Err(anyhow!(NO_BACKEND).context(ErrorClass::Unsupported))
+    }
+
+    async fn assemble_timelapse(_frames_dir: &Path, _fps: u32, _out_file: &Path) -> Result<()> {
+        Err(anyhow!(NO_BACKEND).context(ErrorClass::Unsupported))
+    }
+
+    fn talk_source(
+        _input: TalkInput<'_>,
+        _volume: f32,
+        _block_align: u16,
+        _sample_rate: u16,
+    ) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
+        Err(anyhow!(NO_BACKEND).context(ErrorClass::Unsupported))
+    }
+}
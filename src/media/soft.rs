@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use crossbeam_channel::Receiver;
+use jpeg_encoder::{ColorType, Encoder};
+use openh264::{
+    decoder::{DecodedYUV, Decoder},
+    formats::YUVSource,
+    nal_units,
+};
+use std::path::Path;
+use tokio::{
+    sync::{
+        mpsc::{channel, Receiver as MpscReceiver, Sender},
+        oneshot,
+    },
+    task::{JoinHandle, JoinSet},
+};
+
+use super::{null::NullBackend, ImageSink, MediaBackend, TalkInput};
+use crate::{common::VidFormat, AnyResult};
+
+/// Quality of the written jpeg from 1 to 100
+const JPEG_QUALITY: u8 = 90;
+
+/// Used when neolink is built with `openh264` but without `gstreamer`
+///
+/// Only still images from h264 streams are supported, everything else fails
+/// at runtime like the [`NullBackend`]
+pub(crate) struct SoftBackend;
+
+#[derive(Debug)]
+enum SoftControl {
+    Data(Bytes),
+    Eos,
+}
+
+/// Image sink of the [`SoftBackend`]
+///
+/// The frames are decoded on a blocking thread until the first picture which
+/// is then written as the jpeg
+pub(crate) struct SoftSink {
+    sender: Sender<SoftControl>,
+    finished: oneshot::Receiver<Result<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl ImageSink for SoftSink {
+    async fn send(&self, buf: Bytes) -> Result<()> {
+        self.sender
+            .send(SoftControl::Data(buf))
+            .await
+            .map_err(|e| anyhow!("Failed to send buffer: {:?}", e))
+    }
+
+    async fn eos(&self) -> Result<()> {
+        self.sender
+            .send(SoftControl::Eos)
+            .await
+            .map_err(|e| anyhow!("Failed to send eos: {:?}", e))
+    }
+
+    async fn is_finished(&mut self) -> Option<Result<()>> {
+        match self.finished.try_recv() {
+            Ok(res) => Some(res),
+            Err(oneshot::error::TryRecvError::Empty) => None,
+            Err(oneshot::error::TryRecvError::Closed) => {
+                Some(Err(anyhow!("Decoder finished channel is closed")))
+            }
+        }
+    }
+
+    async fn join(self) -> Result<()> {
+        drop(self.sender);
+        self.handle.await.context("The decoder thread panicked")
+    }
+}
+
+impl MediaBackend for SoftBackend {
+    type ImageSink = SoftSink;
+
+    async fn image_sink(format: VidFormat, out_file: &Path) -> Result<Self::ImageSink> {
+        if !matches!(format, VidFormat::H264) {
+            return Err(anyhow!(
+                "Only h264 streams can be decoded without gstreamer, this camera sends {:?}",
+                format
+            ));
+        }
+        let out_file = out_file.with_extension("jpeg");
+        let (sender, receiver) = channel(100);
+        let (tx, finished) = oneshot::channel();
+        let handle = tokio::task::spawn_blocking(move || {
+            let res = decode(receiver, &out_file);
+            if let Err(e) = &res {
+                log::error!("Failed to decode the image: {:?}", e);
+            }
+            let _ = tx.send(res);
+        });
+        Ok(SoftSink {
+            sender,
+            finished,
+            handle,
+        })
+    }
+
+    async fn assemble_timelapse(frames_dir: &Path, fps: u32, out_file: &Path) -> Result<()> {
+        NullBackend::assemble_timelapse(frames_dir, fps, out_file).await
+    }
+
+    fn talk_source(
+        input: TalkInput<'_>,
+        volume: f32,
+        block_align: u16,
+        sample_rate: u16,
+    ) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
+        NullBackend::talk_source(input, volume, block_align, sample_rate)
+    }
+}
+
+/// Decode the frames until there is a picture and write it to `out_file`
+fn decode(mut receiver: MpscReceiver<SoftControl>, out_file: &Path) -> Result<()> {
+    let mut decoder = Decoder::new().map_err(|e| anyhow!("Failed to start openh264: {e}"))?;
+    while let Some(SoftControl::Data(buf)) = receiver.blocking_recv() {
+        for nal in nal_units(&buf) {
+            match decoder.decode(nal) {
+                Ok(Some(yuv)) => return write_jpeg(&yuv, out_file),
+                Ok(None) => {}
+                // The stream can start with frames that need an earlier keyframe
+                Err(e) => log::debug!("Skipping undecodable h264: {e}"),
+            }
+        }
+    }
+    let remaining = decoder
+        .flush_remaining()
+        .map_err(|e| anyhow!("Failed to flush openh264: {e}"))?;
+    match remaining.first() {
+        Some(yuv) => write_jpeg(yuv, out_file),
+        None => Err(anyhow!("The stream ended before a picture was decoded")),
+    }
+}
+
+fn write_jpeg(yuv: &DecodedYUV<'_>, out_file: &Path) -> Result<()> {
+    let (width, height) = yuv.dimensions();
+    let mut rgb = vec![0; width * height * 3];
+    yuv.write_rgb8(&mut rgb);
+    Encoder::new_file(out_file, JPEG_QUALITY)
+        .and_then(|encoder| encoder.encode(&rgb, width as u16, height as u16, ColorType::Rgb))
+        .with_context(|| format!("Failed to write {}", out_file.display()))
+}
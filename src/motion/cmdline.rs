@@ -0,0 +1,24 @@
+use clap::Parser;
+
+/// The motion command prints whether the camera currently detects motion
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Keep the camera connected and print each motion start and stop, the same as `watch`
+    #[arg(long)]
+    pub follow: bool,
+
+    #[command(subcommand)]
+    pub cmd: Option<MotionCommand>,
+}
+
+#[derive(Parser, Debug)]
+pub enum MotionCommand {
+    /// Print each motion start and stop as it happens until interrupted
+    Watch {
+        /// Print one json object per line instead of on and off
+        #[arg(long)]
+        json: bool,
+    },
+}
@@ -0,0 +1,118 @@
+///
+/// # Neolink Motion
+///
+/// This module prints the motion state of the camera as `on` or `off`. While
+/// there is motion the kinds of object that the AI detected follow the `on`
+/// such as `on people,vehicle`
+///
+/// The `watch` command keeps printing each start and stop so that scripts can
+/// react to motion without the MQTT stack. With `--json` each event is a line
+/// of json with the `neolink.motion_event.v1` schema
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the current motion state
+/// neolink motion --config=config.toml CameraName
+/// # Or print every change until interrupted
+/// neolink motion --config=config.toml CameraName watch
+/// # As json lines
+/// neolink motion --config=config.toml CameraName watch --json
+/// ```
+///
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::time::SystemTime;
+
+mod cmdline;
+
+use crate::common::{MdState, NeoReactor};
+use crate::i18n::tr;
+use cmdline::MotionCommand;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the motion subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let mut md = camera.motion().await?;
+
+    let (follow, json) = match opt.cmd {
+        Some(MotionCommand::Watch { json }) => (true, json),
+        None => (opt.follow, false),
+    };
+
+    let mut last = None;
+    loop {
+        let event = MotionEventV1::new(
+            &opt.camera,
+            &md.wait_for(|state| !matches!(state, MdState::Unknown))
+                .await
+                .context(tr!("motion.watch_failed"))?,
+        );
+        // The state is updated on every alarm so only print real changes
+        if last.as_ref() != Some(&event.line()) {
+            if json {
+                println!("{}", serde_json::to_string(&event)?);
+            } else {
+                println!("{}", event.line());
+            }
+            last = Some(event.line());
+        }
+
+        if !follow {
+            return Ok(());
+        }
+        md.changed().await.context(tr!("motion.watch_failed"))?;
+    }
+}
+
+/// A motion start or stop printed by `watch --json`
+#[derive(Serialize)]
+struct MotionEventV1 {
+    schema: &'static str,
+    camera: String,
+    /// When neolink saw the change in RFC 3339
+    time: String,
+    motion: bool,
+    /// Such as `["people", "vehicle"]`
+    ai_detections: Vec<String>,
+    motion_confidence: Option<u8>,
+    ai_confidence: Option<u8>,
+}
+
+impl MotionEventV1 {
+    fn new(camera: &str, state: &MdState) -> Self {
+        let detail = match state {
+            MdState::Start(_, detail) => Some(detail),
+            MdState::Stop(_) | MdState::Unknown => None,
+        };
+        MotionEventV1 {
+            schema: "neolink.motion_event.v1",
+            camera: camera.to_string(),
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            motion: detail.is_some(),
+            ai_detections: detail
+                .map(|detail| {
+                    detail
+                        .ai_detections()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            motion_confidence: detail.and_then(|detail| detail.motion_confidence),
+            ai_confidence: detail.and_then(|detail| detail.ai_confidence),
+        }
+    }
+
+    /// Such as `on people,vehicle` or `off`
+    fn line(&self) -> String {
+        match (self.motion, self.ai_detections.is_empty()) {
+            (false, _) => "off".to_string(),
+            (true, true) => "on".to_string(),
+            (true, false) => format!("on {}", self.ai_detections.join(",")),
+        }
+    }
+}
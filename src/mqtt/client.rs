@@ -0,0 +1,320 @@
+//! The connection to the broker over mqtt v3.1.1 or v5
+//!
+//! rumqttc has a separate client and event loop for each protocol version,
+//! these wrap them so that the rest of the mqtt code does not need to care
+//! which one is in use
+use anyhow::{anyhow, Context, Result};
+use rumqttc::{
+    v5::{
+        self,
+        mqttbytes::{
+            v5::{
+                ConnectProperties, ConnectReturnCode as ConnectReturnCodeV5,
+                LastWill as LastWillV5, Packet, PublishProperties,
+            },
+            QoS as QoSV5,
+        },
+    },
+    AsyncClient, ConnectReturnCode, Event, EventLoop, Incoming, Key, LastWill, MqttOptions, QoS,
+    TlsConfiguration, Transport,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
+use tokio::{sync::Mutex, time::Duration};
+
+use crate::config::{MqttProtocol, MqttServerConfig};
+
+const MAX_PACKET_SIZE: usize = 100 * (1024 * 1024);
+
+/// The event loop did not take the request, it can be sent again
+#[derive(Debug)]
+pub(super) struct ClientBusy;
+
+impl fmt::Display for ClientBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to send mqtt requests to eventloop")
+    }
+}
+
+impl std::error::Error for ClientBusy {}
+
+#[derive(Clone)]
+pub(super) enum MqttClient {
+    V3(Arc<AsyncClient>),
+    V5 {
+        client: Arc<v5::AsyncClient>,
+        aliases: Arc<TopicAliases>,
+    },
+}
+
+/// The topic aliases that we have sent to the broker
+///
+/// A topic is sent in full with its alias the first time and only the alias
+/// after that. The lock is held while the publish is queued so that no
+/// publish with only the alias can overtake the first one
+pub(super) struct TopicAliases {
+    /// The most that the config asks us to use
+    wanted: u16,
+    /// The most that the broker accepts, it is told to us in the ConnAck
+    broker_max: AtomicU16,
+    assigned: Mutex<HashMap<String, u16>>,
+}
+
+impl MqttClient {
+    pub(super) async fn publish<P: Into<Vec<u8>>>(
+        &self,
+        topic: String,
+        retain: bool,
+        payload: P,
+    ) -> Result<(), ClientBusy> {
+        match self {
+            Self::V3(client) => client
+                .publish(topic, QoS::AtLeastOnce, retain, payload)
+                .await
+                .map_err(|_| ClientBusy),
+            Self::V5 { client, aliases } => {
+                let mut assigned = aliases.assigned.lock().await;
+                let max = aliases
+                    .wanted
+                    .min(aliases.broker_max.load(Ordering::Relaxed));
+                let (alias, new_alias) = match assigned.get(&topic).copied() {
+                    Some(alias) => (Some(alias), false),
+                    None if (assigned.len() as u16) < max => {
+                        let alias = assigned.len() as u16 + 1;
+                        assigned.insert(topic.clone(), alias);
+                        (Some(alias), true)
+                    }
+                    None => (None, false),
+                };
+                let res = match alias {
+                    Some(alias) => {
+                        client
+                            .publish_with_properties(
+                                if new_alias {
+                                    topic.clone()
+                                } else {
+                                    String::new()
+                                },
+                                QoSV5::AtLeastOnce,
+                                retain,
+                                payload,
+                                PublishProperties {
+                                    topic_alias: Some(alias),
+                                    ..Default::default()
+                                },
+                            )
+                            .await
+                    }
+                    None => {
+                        client
+                            .publish(topic.clone(), QoSV5::AtLeastOnce, retain, payload)
+                            .await
+                    }
+                };
+                if res.is_err() && new_alias {
+                    // The broker never saw it so it must be sent in full again
+                    assigned.remove(&topic);
+                }
+                res.map_err(|_| ClientBusy)
+            }
+        }
+    }
+
+    pub(super) async fn subscribe(&self, topic: String) -> Result<(), ClientBusy> {
+        match self {
+            Self::V3(client) => client
+                .subscribe(topic, QoS::AtMostOnce)
+                .await
+                .map_err(|_| ClientBusy),
+            Self::V5 { client, .. } => client
+                .subscribe(topic, QoSV5::AtMostOnce)
+                .await
+                .map_err(|_| ClientBusy),
+        }
+    }
+}
+
+pub(super) enum MqttEventLoop {
+    V3(EventLoop),
+    V5 {
+        eventloop: v5::EventLoop,
+        aliases: Arc<TopicAliases>,
+    },
+}
+
+/// The events of the event loops that neolink acts on
+pub(super) enum Notification {
+    ConnAck { success: bool },
+    Publish { topic: String, message: String },
+    Other,
+}
+
+impl MqttEventLoop {
+    pub(super) async fn poll(&mut self) -> Result<Notification> {
+        match self {
+            Self::V3(eventloop) => Ok(match eventloop.poll().await? {
+                Event::Incoming(Incoming::ConnAck(connack)) => Notification::ConnAck {
+                    success: connack.code == ConnectReturnCode::Success,
+                },
+                Event::Incoming(Incoming::Publish(publish)) => Notification::Publish {
+                    topic: publish.topic,
+                    message: String::from_utf8_lossy(publish.payload.as_ref()).into_owned(),
+                },
+                _ => Notification::Other,
+            }),
+            Self::V5 { eventloop, aliases } => Ok(match eventloop.poll().await? {
+                v5::Event::Incoming(Packet::ConnAck(connack)) => {
+                    let broker_max = connack
+                        .properties
+                        .as_ref()
+                        .and_then(|properties| properties.topic_alias_max)
+                        .unwrap_or(0);
+                    aliases.broker_max.store(broker_max, Ordering::Relaxed);
+                    // Aliases do not last beyond the connection
+                    aliases.assigned.lock().await.clear();
+                    Notification::ConnAck {
+                        success: connack.code == ConnectReturnCodeV5::Success,
+                    }
+                }
+                v5::Event::Incoming(Packet::Publish(publish)) => Notification::Publish {
+                    topic: String::from_utf8_lossy(publish.topic.as_ref()).into_owned(),
+                    message: String::from_utf8_lossy(publish.payload.as_ref()).into_owned(),
+                },
+                _ => Notification::Other,
+            }),
+        }
+    }
+}
+
+/// Create a client for the broker of the config
+///
+/// `last_will` is the topic and message that the broker publishes retained
+/// when we disconnect uncleanly
+pub(super) fn connect(
+    config: &MqttServerConfig,
+    client_id: &str,
+    last_will: Option<(String, String)>,
+) -> Result<(MqttClient, MqttEventLoop)> {
+    let transport = transport(config)?;
+    let keep_alive = Duration::from_secs(5);
+    match config.protocol {
+        MqttProtocol::V3 => {
+            let mut options = MqttOptions::new(client_id, &config.broker_addr, config.port);
+            options.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
+            if let Some(transport) = transport {
+                options.set_transport(transport);
+            }
+            if let Some((username, password)) = &config.credentials {
+                options.set_credentials(username, password);
+            }
+            options.set_keep_alive(keep_alive);
+            if let Some((topic, message)) = last_will {
+                options.set_last_will(LastWill::new(topic, message, QoS::AtLeastOnce, true));
+            }
+
+            let (client, eventloop) = AsyncClient::new(options, 100);
+            Ok((
+                MqttClient::V3(Arc::new(client)),
+                MqttEventLoop::V3(eventloop),
+            ))
+        }
+        MqttProtocol::V5 => {
+            let mut options = v5::MqttOptions::new(client_id, &config.broker_addr, config.port);
+            let mut properties = ConnectProperties::new();
+            properties.max_packet_size = Some(MAX_PACKET_SIZE as u32);
+            properties.session_expiry_interval = config.session_expiry;
+            if config.topic_aliases > 0 {
+                // Let the broker alias the topics it sends us too
+                properties.topic_alias_max = Some(config.topic_aliases);
+            }
+            options.set_connect_properties(properties);
+            if config.session_expiry.is_some() {
+                // Pick up the session that the broker kept for us
+                options.set_clean_start(false);
+            }
+            if let Some(transport) = transport {
+                options.set_transport(transport);
+            }
+            if let Some((username, password)) = &config.credentials {
+                options.set_credentials(username, password);
+            }
+            options.set_keep_alive(keep_alive);
+            if let Some((topic, message)) = last_will {
+                options.set_last_will(LastWillV5::new(
+                    topic,
+                    message,
+                    QoSV5::AtLeastOnce,
+                    true,
+                    None,
+                ));
+            }
+
+            let (client, eventloop) = v5::AsyncClient::new(options, 100);
+            let aliases = Arc::new(TopicAliases {
+                wanted: config.topic_aliases,
+                broker_max: AtomicU16::new(0),
+                assigned: Default::default(),
+            });
+            Ok((
+                MqttClient::V5 {
+                    client: Arc::new(client),
+                    aliases: aliases.clone(),
+                },
+                MqttEventLoop::V5 { eventloop, aliases },
+            ))
+        }
+    }
+}
+
+/// TLS with the `ca` of the config or with the system's roots when only `tls`
+/// is set
+fn transport(config: &MqttServerConfig) -> Result<Option<Transport>> {
+    if let Some(ca_path) = &config.ca {
+        let ca = std::fs::read(ca_path)
+            .with_context(|| format!("Failed to read the mqtt ca {}", ca_path.display()))?;
+        let client_auth = match &config.client_auth {
+            Some((cert_path, key_path)) => {
+                let cert = std::fs::read(cert_path).with_context(|| {
+                    format!(
+                        "Failed to read the mqtt certificate {}",
+                        cert_path.display()
+                    )
+                })?;
+                let key = std::fs::read(key_path).with_context(|| {
+                    format!("Failed to read the mqtt key {}", key_path.display())
+                })?;
+                Some((cert, private_key(key)))
+            }
+            None => None,
+        };
+        Ok(Some(Transport::Tls(TlsConfiguration::Simple {
+            ca,
+            alpn: None,
+            client_auth,
+        })))
+    } else if config.client_auth.is_some() {
+        // The client certificate can only be sent with our own ca
+        Err(anyhow!(
+            "The mqtt client_auth needs the ca of the broker to be set"
+        ))
+    } else if config.tls {
+        Ok(Some(Transport::tls_with_default_config()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// rumqttc reads `RSA PRIVATE KEY` pems as rsa keys and any other as pkcs8
+fn private_key(pem: Vec<u8>) -> Key {
+    if String::from_utf8_lossy(&pem).contains("BEGIN RSA PRIVATE KEY") {
+        Key::RSA(pem)
+    } else {
+        Key::ECC(pem)
+    }
+}
@@ -5,9 +5,14 @@
 use anyhow::{Context, Result};
 use heck::ToTitleCase;
 use log::*;
-
-use super::mqttc::MqttInstance;
-use crate::{common::NeoInstance, config::MqttDiscoveryConfig};
+use neolink_core::bc::xml::SupportItem;
+use std::collections::HashSet;
+
+use super::{mqttc::MqttInstance, publish_zoom_focus};
+use crate::{
+    common::NeoInstance,
+    config::{MqttDiscoveryConfig, SnapFormat},
+};
 use serde::{Deserialize, Serialize, Serializer};
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Copy, Hash)]
@@ -30,6 +35,10 @@ pub(crate) enum Discoveries {
     Battery,
     #[serde(alias = "siren", alias = "alarm")]
     Siren,
+    #[serde(alias = "zoom", alias = "focus")]
+    Zoom,
+    #[serde(alias = "pir_switch")]
+    Pir,
 }
 
 #[derive(Debug, Clone)]
@@ -67,6 +76,8 @@ struct DiscoveryAvaliablity {
     payload_available: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     payload_not_available: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_template: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -89,7 +100,6 @@ struct DiscoveryLight {
 }
 
 #[derive(Serialize, Debug)]
-#[allow(dead_code)]
 enum Encoding {
     None,
     #[serde(rename = "b64")]
@@ -198,6 +208,22 @@ struct DiscoverySensor {
     unit_of_measurement: String,
 }
 
+#[derive(Serialize, Debug)]
+struct DiscoveryNumber {
+    name: String,
+    unique_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    device: DiscoveryDevice,
+    availability: DiscoveryAvaliablity,
+    // Number specific
+    command_topic: String,
+    state_topic: String,
+    min: u32,
+    max: u32,
+    mode: String,
+}
+
 /// Enables MQTT discovery for a camera. See docs at https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
 pub(crate) async fn enable_discovery(
     discovery_config: &MqttDiscoveryConfig,
@@ -229,23 +255,45 @@ pub(crate) async fn enable_discovery(
         return Ok(());
     }
 
+    // Include the namespace so that instances sharing a broker do not collide
+    let topic_root = format!("{}/{}", mqtt.root(), cam_config.name);
+    let id_root = format!(
+        "{}_{}",
+        mqtt.root().replace('/', "_"),
+        cam_config.name.replace('/', "_")
+    );
+
     let friendly_name = cam_config.name.replace('_', " ").to_title_case();
     let device = DiscoveryDevice {
         name: friendly_name.clone(),
         connections,
-        identifiers: vec![format!("neolink_{}", cam_config.name)],
+        identifiers: vec![id_root.clone()],
         manufacturer: Some("Reolink".to_string()),
         model: Some("Neolink".to_string()),
         sw_version: Some(env!("CARGO_PKG_VERSION").to_string()),
     };
 
     let availability = DiscoveryAvaliablity {
-        topic: format!("neolink/{}/status", cam_config.name),
-        payload_available: Some("connected".to_string()),
-        payload_not_available: None,
+        topic: format!("{topic_root}/status/availability"),
+        payload_available: Some("online".to_string()),
+        payload_not_available: Some("offline".to_string()),
+        // Home assistant ignores payloads that are neither so a disconnected
+        // camera has to be turned into offline
+        value_template: Some("{{ 'online' if value == 'online' else 'offline' }}".to_string()),
+    };
+
+    let features = if discovery_config.features.is_empty() {
+        let features = supported_discoveries(camera).await?;
+        info!(
+            "Discovered features {:?} for {} from its support matrix",
+            features, cam_config.name
+        );
+        features
+    } else {
+        discovery_config.features.clone()
     };
 
-    for feature in &discovery_config.features {
+    for feature in &features {
         match feature {
             Discoveries::Floodlight => {
                 let config_data = DiscoveryLight {
@@ -255,16 +303,16 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} Floodlight", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_floodlight", cam_config.name),
+                    unique_id: format!("{id_root}_floodlight"),
                     // Match native home assistant integration: https://github.com/home-assistant/core/blob/dev/homeassistant/components/reolink/light.py#L49
                     icon: Some("mdi:spotlight-beam".to_string()),
 
                     // State
-                    state_topic: Some(format!("neolink/{}/status/floodlight", cam_config.name)),
+                    state_topic: Some(format!("{topic_root}/status/floodlight")),
                     state_value_template: Some("{{ value_json.state }}".to_string()),
 
                     // Control
-                    command_topic: Some(format!("neolink/{}/control/floodlight", cam_config.name)),
+                    command_topic: Some(format!("{topic_root}/control/floodlight")),
                     // Lowercase payloads to match neolink convention
                     payload_on: "on".to_string(),
                     payload_off: "off".to_string(),
@@ -296,20 +344,17 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} FloodlightTasks", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_floodlight_tasks", cam_config.name),
+                    unique_id: format!("{id_root}_floodlight_tasks"),
                     // Match native home assistant integration: https://github.com/home-assistant/core/blob/dev/homeassistant/components/reolink/light.py#L49
                     icon: Some("mdi:spotlight-beam".to_string()),
 
                     // State
-                    state_topic: Some(format!(
-                        "neolink/{}/status/floodlight_tasks",
-                        cam_config.name
-                    )),
+                    state_topic: Some(format!("{topic_root}/status/floodlight_tasks")),
                     state_on: Some("on".to_string()),
                     state_off: Some("off".to_string()),
 
                     // Control
-                    command_topic: format!("neolink/{}/control/floodlight_tasks", cam_config.name),
+                    command_topic: format!("{topic_root}/control/floodlight_tasks"),
                     // Lowercase payloads to match neolink convention
                     payload_on: "on".to_string(),
                     payload_off: "off".to_string(),
@@ -335,6 +380,15 @@ pub(crate) async fn enable_discovery(
                 })?;
             }
             Discoveries::Camera => {
+                // The snaps are used when they are published as the preview
+                // is only on the interval
+                let (snap_topic, snap_encoding) = match cam_config.mqtt.publishes_snaps() {
+                    true => match cam_config.mqtt.snap_format {
+                        SnapFormat::Base64 => ("snap", Encoding::Base64),
+                        SnapFormat::Raw => ("snap", Encoding::None),
+                    },
+                    false => ("preview", Encoding::Base64),
+                };
                 let config_data = DiscoveryCamera {
                     // Common across all potential features
                     device: device.clone(),
@@ -342,12 +396,12 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} Camera", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_camera", cam_config.name),
+                    unique_id: format!("{id_root}_camera"),
                     icon: Some("mdi:camera-iris".to_string()),
 
                     // Camera specific
-                    topic: format!("neolink/{}/status/preview", cam_config.name),
-                    image_encoding: Encoding::Base64,
+                    topic: format!("{topic_root}/status/{snap_topic}"),
+                    image_encoding: snap_encoding,
                 };
 
                 // Each feature needs to be individually registered
@@ -377,11 +431,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} LED", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_led", cam_config.name),
+                    unique_id: format!("{id_root}_led"),
                     icon: Some("mdi:led-on".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/led", cam_config.name),
+                    command_topic: format!("{topic_root}/control/led"),
                     payload_off: "off".to_string(),
                     payload_on: "on".to_string(),
                     state_topic: None,
@@ -416,11 +470,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} IR", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_ir", cam_config.name),
+                    unique_id: format!("{id_root}_ir"),
                     icon: Some("mdi:lightbulb-night".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/ir", cam_config.name),
+                    command_topic: format!("{topic_root}/control/ir"),
                     options: vec!["on".to_string(), "off".to_string(), "auto".to_string()],
                     state_topic: None,
                 };
@@ -452,11 +506,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} MD", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_md", cam_config.name),
+                    unique_id: format!("{id_root}_md"),
                     icon: Some("mdi:motion-sensor".to_string()),
 
                     // Switch specific
-                    state_topic: format!("neolink/{}/status/motion", cam_config.name),
+                    state_topic: format!("{topic_root}/status/motion"),
                     payload_off: "off".to_string(),
                     payload_on: "on".to_string(),
                 };
@@ -488,11 +542,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} Reboot", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_reboot", cam_config.name),
+                    unique_id: format!("{id_root}_reboot"),
                     icon: Some("mdi:restart".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/reboot", cam_config.name),
+                    command_topic: format!("{topic_root}/control/reboot"),
                     payload_press: None,
                 };
 
@@ -524,11 +578,11 @@ pub(crate) async fn enable_discovery(
 
                         // Identifiers
                         name: format!("{} Pan {}", friendly_name.as_str(), dir),
-                        unique_id: format!("neolink_{}_pan_{}", cam_config.name, dir),
+                        unique_id: format!("{id_root}_pan_{}", dir),
                         icon: Some(format!("mdi:pan-{}", dir)),
 
                         // Switch specific
-                        command_topic: format!("neolink/{}/control/ptz", cam_config.name),
+                        command_topic: format!("{topic_root}/control/ptz"),
                         payload_press: Some(dir.to_string()),
                     };
 
@@ -560,11 +614,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} Battery", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_battery", cam_config.name),
+                    unique_id: format!("{id_root}_battery"),
                     icon: Some("mdi:battery".to_string()),
 
                     // Camera specific
-                    state_topic: format!("neolink/{}/status/battery_level", cam_config.name),
+                    state_topic: format!("{topic_root}/status/battery_level"),
                     state_class: "measurement".to_string(),
                     unit_of_measurement: "%".to_string(),
                 };
@@ -597,11 +651,11 @@ pub(crate) async fn enable_discovery(
 
                     // Identifiers
                     name: format!("{} Siren", friendly_name.as_str()),
-                    unique_id: format!("neolink_{}_siren", cam_config.name),
+                    unique_id: format!("{id_root}_siren"),
                     icon: Some("mdi:bell".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/siren", cam_config.name),
+                    command_topic: format!("{topic_root}/control/siren"),
                     payload_press: Some("on".to_string()),
                 };
 
@@ -624,6 +678,108 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Pir => {
+                let config_data = DiscoverySwitch {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} PIR", friendly_name.as_str()),
+                    unique_id: format!("{id_root}_pir"),
+                    icon: Some("mdi:motion-sensor".to_string()),
+
+                    // Switch specific
+                    command_topic: format!("{topic_root}/control/pir"),
+                    payload_off: "off".to_string(),
+                    payload_on: "on".to_string(),
+                    state_topic: None,
+                    state_off: None,
+                    state_on: None,
+                };
+
+                // Each feature needs to be individually registered
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/switch/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data)
+                        .with_context(|| "Cound not serialise discovery pir config into json")?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish pir auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
+            Discoveries::Zoom => {
+                // The sliders need the range of the camera
+                let zoom_focus = match camera
+                    .run_task(|cam| Box::pin(async move { Ok(cam.get_zoom().await?) }))
+                    .await
+                {
+                    Ok(zoom_focus) => zoom_focus,
+                    Err(e) => {
+                        warn!(
+                            "Skipping zoom discovery for {}, the camera did not report its zoom: {:?}",
+                            cam_config.name, e
+                        );
+                        continue;
+                    }
+                };
+                for (kind, position, icon) in [
+                    ("zoom", &zoom_focus.zoom, "mdi:magnify-plus-outline"),
+                    ("focus", &zoom_focus.focus, "mdi:image-filter-center-focus"),
+                ] {
+                    let config_data = DiscoveryNumber {
+                        // Common across all potential features
+                        device: device.clone(),
+                        availability: availability.clone(),
+
+                        // Identifiers
+                        name: format!("{} {}", friendly_name.as_str(), kind.to_title_case()),
+                        unique_id: format!("{id_root}_{kind}"),
+                        icon: Some(icon.to_string()),
+
+                        // Number specific
+                        command_topic: match kind {
+                            "zoom" => format!("{topic_root}/control/zoom/pos"),
+                            _ => format!("{topic_root}/control/focus"),
+                        },
+                        state_topic: format!("{topic_root}/status/{kind}"),
+                        min: position.min_pos,
+                        max: position.max_pos,
+                        mode: "slider".to_string(),
+                    };
+
+                    // Each feature needs to be individually registered
+                    mqtt.send_message_with_root_topic(
+                        &format!(
+                            "{}/number/{}",
+                            discovery_config.topic, &config_data.unique_id
+                        ),
+                        "config",
+                        &serde_json::to_string(&config_data).with_context(|| {
+                            "Cound not serialise discovery zoom config into json"
+                        })?,
+                        true,
+                    )
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to publish zoom auto-discover data on over MQTT for {}",
+                            cam_config.name
+                        )
+                    })?;
+                }
+                // So that the sliders start in the right place
+                publish_zoom_focus(mqtt, camera).await?;
+            }
         }
     }
 
@@ -635,3 +791,63 @@ pub(crate) async fn enable_discovery(
 
     Ok(())
 }
+
+/// The features of the camera's support matrix that have an entity
+///
+/// Used when no `features` are given in the discovery config
+async fn supported_discoveries(camera: &NeoInstance) -> Result<HashSet<Discoveries>> {
+    let cam_config = camera.config().await?.borrow().clone();
+    let support = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_support().await?) }))
+        .await
+        .with_context(|| format!("Failed to get the support matrix of {}", cam_config.name))?;
+    // There is no support flag for the floodlight so check if its tasks can be read
+    let floodlight = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_flightlight_tasks().await?) }))
+        .await
+        .is_ok();
+
+    let is_set = |v: Option<u32>| v.unwrap_or(0) > 0;
+    let item = support
+        .items
+        .iter()
+        .find(|item| item.chn_id == cam_config.channel_id as u32);
+    let item_set =
+        |f: fn(&SupportItem) -> Option<u32>| item.map(|item| is_set(f(item))).unwrap_or(false);
+
+    let mut features = HashSet::from([Discoveries::Motion, Discoveries::Ir]);
+    if cam_config.mqtt.enable_preview || cam_config.mqtt.publishes_snaps() {
+        features.insert(Discoveries::Camera);
+    }
+    if is_set(support.reboot) {
+        features.insert(Discoveries::Reboot);
+    }
+    if is_set(support.audio_alarm) {
+        features.insert(Discoveries::Siren);
+    }
+    if support
+        .ptz_mode
+        .as_deref()
+        .map(|mode| mode != "none")
+        .unwrap_or(false)
+        || item_set(|i| i.ptz_control)
+    {
+        features.insert(Discoveries::Pt);
+    }
+    if item_set(|i| i.auto_focus) {
+        features.insert(Discoveries::Zoom);
+    }
+    if item_set(|i| i.led_ctrl) {
+        features.insert(Discoveries::Led);
+    }
+    if item_set(|i| i.battery) {
+        features.insert(Discoveries::Battery);
+    }
+    if item_set(|i| i.rf_cfg) {
+        features.insert(Discoveries::Pir);
+    }
+    if floodlight {
+        features.insert(Discoveries::Floodlight);
+    }
+    Ok(features)
+}
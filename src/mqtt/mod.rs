@@ -5,16 +5,28 @@
 //!
 //! This acts as a bridge between cameras and MQTT servers
 //!
-//! Messages are prefixed with `neolink/{CAMERANAME}`
+//! Messages are prefixed with `neolink/{CAMERANAME}`, or with
+//! `{namespace}/neolink/{CAMERANAME}` when a `namespace` is set in the config
 //!
 //! Control messages:
 //!
 //! - `/control/floodlight [on|off]` Turns floodlight (if equipped) on/off
 //! - `/control/led [on|off]` Turns status LED on/off
 //! - `/control/pir [on|off]` Turns PIR on/off
+//! - `/control/pir/sensitivity [0-100]` Set how easily the PIR triggers
+//! - `/control/pir/schedule [always|20-6]` Only arm the PIR during these hours of each day
 //! - `/control/ir [on|off|auto]` Turn IR lights on/off or automatically via light detection
+//! - `/control/daynight [auto|day|night]` Force the picture to colour or black and white or switch with the light
+//! - `/control/isp/[bright|contrast|saturation] [0-255]` Adjust the picture
+//! - `/control/isp/[flip|mirror] [on|off]` Flip the picture upside down or mirror it left to right
+//! - `/control/audio/record [on|off]` Include the microphone in the streams
+//! - `/control/audio/volume [0-100]` Set the speaker volume
 //! - `/control/reboot` Reboot the camera
+//! - `/control/services/[http|https|rtsp|rtmp|onvif|all] [on|off]` Turn the camera's network
+//!   services on or off, `/status/services` is published after each change
 //! - `/control/ptz` [up|down|left|right|in|out] (amount) Control the PTZ movements, amount defaults to 32.0
+//! - `/control/zoom/pos [n]` Move the zoom to a position within the camera's range
+//! - `/control/focus [n]` Move the focus to a position within the camera's range
 //! - `/control/ptz/preset` [id] Move the camera to a known preset
 //! - `/control/ptz/assign` [id] [name] Assign the current ptz position to an ID and name
 //!
@@ -22,15 +34,44 @@
 //!
 //! `/status offline` Sent when the neolink goes offline this is a LastWill message
 //! `/status disconnected` Sent when the camera goes offline
+//! `/status/motion/event` Sent when the motion stops with `motion_events`, json with its
+//!    start time, duration and AI detections. It replaces the `on`/`off` of `/status/motion`
+//! `/status/availability [online|disconnected|offline]` Retained state of the camera,
+//!    `offline` is a LastWill message for when neolink stops or crashes
 //! `/status/battery` Sent in reply to a `/query/battery`
 //! `/status/pir` Sent in reply to a `/query/pir`
 //! `/status/ptz/preset` Sent in reply to a `/query/ptz/preset`
+//! `/status/storage` Sent in reply to a `/query/storage`
+//! `/status/services` The ports and state of the network services, sent after each change
+//!    and in reply to a `/query/services`
+//! `/status/zoom` and `/status/focus` The current positions, sent after each change
+//!    and in reply to a `/query/zoom`
+//! `/status/snap` A jpeg every `snap_interval` ms and when motion starts with
+//!    `snap_on_motion`, base64 encoded or raw with `snap_format = "raw"`
+//! `/status/frames_overflowed` Retained count of the frames dropped because neolink fell
+//!    behind the camera, sent when it changes
+//!
+//! `neolink/version` is a retained json message with the version, git hash and
+//! compiled in features of this neolink
+//!
+//! `neolink/update` is a retained json message describing a newer release or
+//! `none`, it is only checked for when `[update_check]` is in the config
+//!
+//! Each control message is acknowledged on its own topic with `OK` or `FAIL`
+//!
+//! The battery, pir, ptz, services and storage status payloads are json with a versioned `schema`
+//! field such as `"neolink.battery.v1"`. Set `payload_format = "xml"` to
+//! publish the camera's xml instead.
 //!
 //! Query Messages:
 //!
 //! `/query/battery` Request that the camera reports its battery level
 //! `/query/pir` Request that the camera reports its pir status
 //! `/query/ptz/preset` Request that the camera reports the PTZ presets
+//! `/query/zoom` Request that the camera reports its zoom and focus positions
+//! `/query/storage` Request that the camera reports the capacity, free space
+//!    and state of its SD cards
+//! `/query/services` Request that the camera reports its network services
 //! `/query/preview` Request that the camera post a base64 encoded jpeg
 //!    of the stream to `/status/preview`
 //!
@@ -61,36 +102,43 @@
 //!
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 use tokio::{
     sync::mpsc::channel as mpsc,
     task::JoinSet,
-    time::{interval, sleep, Duration, MissedTickBehavior},
+    time::{interval, sleep, sleep_until, timeout, Duration, Instant, MissedTickBehavior},
 };
 use tokio_stream::{wrappers::IntervalStream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use validator::Validate;
 
-use neolink_core::bc_protocol::{Direction as BcDirection, LightState};
+use neolink_core::bc_protocol::{
+    Confirmation, DayNightMode, Direction as BcDirection, IspChange, LightState, PirSchedule,
+    Service,
+};
 
+mod client;
 mod cmdline;
 mod discovery;
 mod mqttc;
+mod payload;
 
 use crate::{
-    common::{MdState, NeoInstance, NeoReactor},
-    config::Config,
+    common::{MdState, NeoInstance, NeoReactor, VersionInfo},
+    config::{Config, MqttConfig, PayloadFormat, SnapFormat},
+    media::TalkInput,
+    talk::talk,
     AnyResult,
 };
 use anyhow::{anyhow, Context, Result};
 pub(crate) use cmdline::Opt;
 pub(crate) use discovery::Discoveries;
 use log::*;
-use mqttc::{Mqtt, MqttReplyRef};
+use mqttc::MqttReplyRef;
+pub(crate) use mqttc::{Mqtt, MqttInstance};
 
-use self::{
-    discovery::enable_discovery,
-    mqttc::{MqttInstance, MqttReply},
-};
+use self::{discovery::enable_discovery, mqttc::MqttReply, payload::JsonPayload};
 
 /// Entry point for the mqtt subcommand
 ///
@@ -193,6 +241,41 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // This threads publishes when there is a newer release
+    let mut thread_update = reactor.update_available().await?;
+    let thread_instance = mqtt.subscribe("").await?;
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                loop {
+                    let update = thread_update.borrow_and_update().clone();
+                    let str = match update {
+                        Some(update) => serde_json::to_string(&update)?,
+                        None => "none".to_string(),
+                    };
+                    thread_instance.send_message("update", &str, true).await?;
+                    thread_update.changed().await?;
+                }
+            } => v,
+        }
+    });
+
+    // This threads publishes the running version
+    let thread_instance = mqtt.subscribe("").await?;
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                let str = serde_json::to_string(&VersionInfo::current())?;
+                thread_instance.send_message("version", &str, true).await?;
+                AnyResult::Ok(())
+            } => v,
+        }
+    });
+
     // This threads checks for config changes on the mqtt
     let thread_config = config.clone();
     let mut thread_instance = mqtt.subscribe("").await?;
@@ -272,6 +355,12 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         .await
                         .with_context(|| format!("Failed to publish status for {}", camera_name))?;
                 let _drop_message = mqtt_instance.last_will("status", "disconnected").await?;
+                mqtt_instance
+                        .send_message("status/availability", "disconnected", true)
+                        .await
+                        .with_context(|| format!("Failed to publish availability for {}", camera_name))?;
+                // So that it does not stay online when we crash
+                let _drop_message3 = mqtt_instance.last_will("status/availability", "offline").await?;
                 mqtt_instance
                     .send_message("status/motion", "unknown", true)
                     .await
@@ -303,15 +392,24 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let camera_pn = camera.clone();
                 let mqtt_pn = mqtt_instance.resubscribe().await?;
 
+                let camera_visitor = camera.clone();
+                let mqtt_visitor = mqtt_instance.resubscribe().await?;
+
                 let camera_snap = camera.clone();
                 let mqtt_snap = mqtt_instance.resubscribe().await?;
 
+                let camera_snaps = camera.clone();
+                let mqtt_snaps = mqtt_instance.resubscribe().await?;
+
                 let camera_battery = camera.clone();
                 let mqtt_battery = mqtt_instance.resubscribe().await?;
 
                 let camera_floodlight_tasks = camera.clone();
                 let mqtt_floodlight_tasks = mqtt_instance.resubscribe().await?;
 
+                let camera_overflow = camera.clone();
+                let mqtt_overflow = mqtt_instance.resubscribe().await?;
+
                 tokio::select! {
                     _ = cancel.cancelled() => AnyResult::Ok(()),
                     // Handles incomming requests
@@ -362,12 +460,18 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             mqtt_watch.send_message("status", "connected", true).await.with_context(|| {
                                 format!("{}: Failed to publish connected", camera_name)
                             })?;
+                            mqtt_watch.send_message("status/availability", "online", true).await.with_context(|| {
+                                format!("{}: Failed to publish online", camera_name)
+                            })?;
                             camera_watch.wait_for(|cam| cam.upgrade().is_none()).await.with_context(|| {
                                 format!("{}: Disconnect Watch Dropped", camera_name)
                             })?;
                             mqtt_watch.send_message("status", "disconnected", true).await.with_context(|| {
                                 format!("{}: Failed to publish disconnected", camera_name)
                             })?;
+                            mqtt_watch.send_message("status/availability", "disconnected", true).await.with_context(|| {
+                                format!("{}: Failed to publish availability", camera_name)
+                            })?;
                         }
                     } => {
                         log::debug!("CamConnection returned: {v:?}");
@@ -425,20 +529,90 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                     // Handle the motion messages
                     v = async {
                         let mut md = camera_motion.motion().await?;
+                        let debounce = Duration::from_millis(config.motion_debounce);
+                        let cooldown = Duration::from_millis(config.motion_cooldown);
+                        let toggles = !config.motion_events;
+                        let mut last_off: Option<Instant> = None;
                         loop {
                             let v = async {
-                                md.wait_for(|state| matches!(state, MdState::Start(_))).await.with_context(|| {
+                                md.wait_for(|state| matches!(state, MdState::Start(..))).await.with_context(|| {
                                     format!("{}: MdStart Watch Dropped", camera_name)
                                 })?;
-                                mqtt_motion.send_message("status/motion", "on", true).await.with_context(|| {
-                                    format!("{}: Failed to publish motion start", camera_name)
-                                })?;
-                                md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
-                                    format!("{}: MdStop Watch Dropped", camera_name)
-                                })?;
-                                mqtt_motion.send_message("status/motion", "off", true).await.with_context(|| {
-                                    format!("{}: Failed to publish motion stop", camera_name)
-                                })?;
+                                if let Some(last_off) = last_off {
+                                    // Hold the start back until the cooldown is over and
+                                    // drop it if the motion is gone by then
+                                    sleep_until(last_off + cooldown).await;
+                                    if !matches!(*md.borrow_and_update(), MdState::Start(..)) {
+                                        return AnyResult::Ok(());
+                                    }
+                                }
+                                let started = (SystemTime::now(), Instant::now());
+                                if toggles {
+                                    mqtt_motion.send_message("status/motion", "on", true).await.with_context(|| {
+                                        format!("{}: Failed to publish motion start", camera_name)
+                                    })?;
+                                }
+                                // Publish the detail of every alarm until the motion stops
+                                let mut detections = HashSet::new();
+                                let mut seen = HashSet::new();
+                                loop {
+                                    loop {
+                                        let detail = match &*md.borrow_and_update() {
+                                            MdState::Start(_, detail) => Some(detail.clone()),
+                                            _ => None,
+                                        };
+                                        let Some(detail) = detail else {
+                                            break;
+                                        };
+                                        mqtt_motion.send_message("status/motion/detail", &serde_json::to_string(&detail.to_json())?, false).await.with_context(|| {
+                                            format!("{}: Failed to publish motion detail", camera_name)
+                                        })?;
+                                        // Each kind of AI detection gets its own on/off topic
+                                        let current = detail.ai_detections().iter().map(ToString::to_string).collect::<HashSet<_>>();
+                                        if toggles {
+                                            for (kind, state) in current.difference(&detections).map(|kind| (kind, "on")).chain(detections.difference(&current).map(|kind| (kind, "off"))) {
+                                                mqtt_motion.send_message(&format!("status/motion/{kind}"), state, true).await.with_context(|| {
+                                                    format!("{}: Failed to publish motion {kind}", camera_name)
+                                                })?;
+                                            }
+                                        }
+                                        seen.extend(current.iter().cloned());
+                                        detections = current;
+                                        md.changed().await.with_context(|| {
+                                            format!("{}: MdStop Watch Dropped", camera_name)
+                                        })?;
+                                    }
+                                    // Motion that comes back within the debounce is part of this one
+                                    if debounce.is_zero() {
+                                        break;
+                                    }
+                                    match timeout(debounce, md.wait_for(|state| matches!(state, MdState::Start(..)))).await {
+                                        Ok(v) => {
+                                            v.with_context(|| {
+                                                format!("{}: MdStart Watch Dropped", camera_name)
+                                            })?;
+                                        }
+                                        Err(_) => break,
+                                    }
+                                }
+                                if toggles {
+                                    for kind in detections {
+                                        mqtt_motion.send_message(&format!("status/motion/{kind}"), "off", true).await.with_context(|| {
+                                            format!("{}: Failed to publish motion {kind}", camera_name)
+                                        })?;
+                                    }
+                                    mqtt_motion.send_message("status/motion", "off", true).await.with_context(|| {
+                                        format!("{}: Failed to publish motion stop", camera_name)
+                                    })?;
+                                } else {
+                                    let mut seen = seen.into_iter().collect::<Vec<_>>();
+                                    seen.sort();
+                                    let event = payload::MotionEventV1::new(started.0, started.1.elapsed(), seen);
+                                    mqtt_motion.send_message("status/motion/event", &serde_json::to_string(&event)?, false).await.with_context(|| {
+                                        format!("{}: Failed to publish motion event", camera_name)
+                                    })?;
+                                }
+                                last_off = Some(Instant::now());
                                 AnyResult::Ok(())
                             }.await;
                             log::debug!("Motion returned: {v:?}");
@@ -450,6 +624,37 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             }?;
                         }
                     }, if config.enable_motion => v,
+                    // Handle the doorbell presses
+                    v = async {
+                        let mut visitor = camera_visitor.visitor().await?;
+                        let mut pn = camera_visitor.push_notifications().await?;
+                        loop {
+                            // Presses come over bc while connected and as a push while asleep
+                            let press = tokio::select! {
+                                v = visitor.changed() => {
+                                    v.with_context(|| format!("{}: Visitor Watch Dropped", camera_name))?;
+                                    visitor.borrow_and_update().is_some()
+                                },
+                                v = pn.changed() => {
+                                    v.with_context(|| format!("{}: PushNoti Watch Dropped", camera_name))?;
+                                    pn.borrow_and_update().as_ref().is_some_and(|noti| noti.message.contains("visitor"))
+                                },
+                            };
+                            if !press {
+                                continue;
+                            }
+                            mqtt_visitor.send_message("status/visitor", "on", false).await.with_context(|| {
+                                format!("{}: Failed to publish visitor", camera_name)
+                            })?;
+                            // The bc press and its push arrive together so ignore any more for a while
+                            sleep(VISITOR_HOLD).await;
+                            let _ = visitor.borrow_and_update();
+                            let _ = pn.borrow_and_update();
+                            mqtt_visitor.send_message("status/visitor", "off", false).await.with_context(|| {
+                                format!("{}: Failed to publish visitor", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
                     // Handle the SNAP (image preview)
                     v = async {
                         let mut wait = IntervalStream::new({
@@ -494,6 +699,20 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         }?;
                         AnyResult::Ok(())
                     }, if config.enable_preview => v,
+                    // Handle the snaps on an interval and motion
+                    v = async {
+                        let v = publish_snaps(&camera_snaps, &mqtt_snaps, &config).await;
+                        log::debug!("Snaps returned: {v:?}");
+                        match v.map_err(|e| e.downcast::<neolink_core::Error>()) {
+                            Err(Ok(neolink_core::Error::UnintelligibleReply{..})) | Err(Ok(neolink_core::Error::CameraServiceUnavaliable(_))) => {
+                                log::debug!("Snaps not supported");
+                                futures::future::pending().await
+                            },
+                            Ok(()) => AnyResult::Ok(()),
+                            Err(Ok(e)) => Err(e.into()),
+                            Err(Err(e)) => Err(e),
+                        }
+                    }, if config.publishes_snaps() => v,
                     // Handle the battery publish
                     v = async {
                         let mut wait = IntervalStream::new({
@@ -602,6 +821,26 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         }
                         AnyResult::Ok(())
                     }, if config.enable_floodlight => v,
+                    // Publish the frames dropped from the stream buffers
+                    v = async {
+                        let metrics = camera_overflow.metrics();
+                        let mut wait = IntervalStream::new({
+                            let mut i = interval(OVERFLOW_UPDATE);
+                            i.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                            i
+                        });
+                        let mut published = None;
+                        while wait.next().await.is_some() {
+                            let overflowed = metrics.overflowed_frames();
+                            if published != Some(overflowed) {
+                                mqtt_overflow.send_message("status/frames_overflowed", &overflowed.to_string(), true).await.with_context(|| {
+                                    format!("{}: Failed to publish the overflowed frames", camera_name)
+                                })?;
+                                published = Some(overflowed);
+                            }
+                        }
+                        AnyResult::Ok(())
+                    } => v,
                 }?;
                 AnyResult::Ok(())
             } => v,
@@ -614,11 +853,175 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
     Ok(())
 }
 
+/// The reply to a command that the camera may not confirm
+fn confirmation_reply(res: AnyResult<Confirmation>, what: &str) -> String {
+    match res {
+        Ok(Confirmation::Confirmed) => "OK",
+        Ok(Confirmation::AssumedOk) => {
+            warn!("The camera did not confirm the request to {what}");
+            "OK: unconfirmed"
+        }
+        Err(e) => {
+            error!("Failed to {what}: {e:?}");
+            "FAIL"
+        }
+    }
+    .to_string()
+}
+
+/// Publish a jpeg to `status/snap` on the interval and each time motion starts
+async fn publish_snaps(
+    camera: &NeoInstance,
+    mqtt: &MqttInstance,
+    config: &MqttConfig,
+) -> Result<()> {
+    let mut md = camera.motion().await?;
+    let mut moving = matches!(*md.borrow_and_update(), MdState::Start(..));
+    let mut wait = config.snap_interval.map(|ms| {
+        let mut i = interval(Duration::from_millis(ms));
+        i.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        i
+    });
+    loop {
+        tokio::select! {
+            _ = async { wait.as_mut().unwrap().tick().await }, if wait.is_some() => {},
+            v = md.changed(), if config.snap_on_motion => {
+                v?;
+                let was_moving = moving;
+                moving = matches!(*md.borrow_and_update(), MdState::Start(..));
+                if was_moving || !moving {
+                    continue;
+                }
+            },
+        }
+        let image = camera
+            .run_passive_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+            .await?;
+        match config.snap_format {
+            SnapFormat::Base64 => {
+                mqtt.send_message("status/snap", BASE64.encode(image).as_str(), true)
+                    .await
+            }
+            SnapFormat::Raw => mqtt.send_bytes("status/snap", image, true).await,
+        }
+        .context("Failed to publish snap")?;
+    }
+}
+
+/// Publish the current zoom and focus positions to `status/zoom` and `status/focus`
+async fn publish_zoom_focus(mqtt: &MqttInstance, camera: &NeoInstance) -> Result<()> {
+    let zoom_focus = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_zoom().await?) }))
+        .await
+        .context("Failed to get the zoom and focus")?;
+    mqtt.send_message("status/zoom", &zoom_focus.zoom.cur_pos.to_string(), true)
+        .await
+        .with_context(|| "Failed to publish the zoom position")?;
+    mqtt.send_message("status/focus", &zoom_focus.focus.cur_pos.to_string(), true)
+        .await
+        .with_context(|| "Failed to publish the focus position")?;
+    Ok(())
+}
+
+/// Parse a `control/isp/{setting}` payload
+fn isp_change(setting: &str, message: &str) -> Result<IspChange> {
+    let level = || {
+        message
+            .parse::<u8>()
+            .with_context(|| format!("The {setting} should be a number from 0 to 255"))
+    };
+    let onoff = || match message {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(anyhow!("The {setting} should be on or off")),
+    };
+    let mut change = IspChange::default();
+    match setting {
+        "bright" => change.bright = Some(level()?),
+        "contrast" => change.contrast = Some(level()?),
+        "saturation" => change.saturation = Some(level()?),
+        "flip" => change.flip = Some(onoff()?),
+        "mirror" => change.mirror = Some(onoff()?),
+        _ => return Err(anyhow!("Unknown picture setting {setting}")),
+    }
+    Ok(change)
+}
+
+/// Parse a `control/services/{service}` payload
+fn service_change(service: &str, message: &str) -> Result<Vec<(Service, bool)>> {
+    let on = match message {
+        "on" => true,
+        "off" => false,
+        _ => return Err(anyhow!("The {service} service should be on or off")),
+    };
+    let services = match service {
+        "http" => vec![Service::Http],
+        "https" => vec![Service::Https],
+        "rtsp" => vec![Service::Rtsp],
+        "rtmp" => vec![Service::Rtmp],
+        "onvif" => vec![Service::Onvif],
+        "all" => Service::ALL.to_vec(),
+        _ => return Err(anyhow!("Unknown service {service}")),
+    };
+    Ok(services.into_iter().map(|service| (service, on)).collect())
+}
+
+/// Publish the ports and state of the network services to `status/services`
+async fn publish_services(
+    mqtt: &MqttInstance,
+    camera: &NeoInstance,
+    payload_format: PayloadFormat,
+) -> Result<()> {
+    let xml = camera
+        .run_task(|cam| Box::pin(async move { AnyResult::Ok(cam.get_services().await?) }))
+        .await
+        .context("Failed to get the services")?;
+    let str = payload::encode(&xml, payload_format)?;
+    mqtt.send_message("status/services", &str, true)
+        .await
+        .with_context(|| "Failed to publish services")?;
+    Ok(())
+}
+
+/// How often `status/frames_overflowed` is checked for a change
+const OVERFLOW_UPDATE: Duration = Duration::from_secs(10);
+
+/// How long `status/visitor` stays `on` after the doorbell is pressed
+const VISITOR_HOLD: Duration = Duration::from_secs(10);
+
+/// Play a `control/talk` payload through the camera
+///
+/// The payload is either the url of an audio file or a whole audio file in
+/// base64. Any format that the media backend can decode works
+async fn talk_message(camera: &NeoInstance, message: &str) -> Result<()> {
+    static TALK_FILES: AtomicU64 = AtomicU64::new(0);
+
+    let message = message.trim();
+    if message.contains("://") {
+        return talk(camera, TalkInput::Uri(message), 1.0).await;
+    }
+    let data = BASE64
+        .decode(message)
+        .context("The talk payload is neither a url nor base64 audio")?;
+    let path = std::env::temp_dir().join(format!(
+        "neolink-talk-{}-{}",
+        std::process::id(),
+        TALK_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    tokio::fs::write(&path, data)
+        .await
+        .context("Failed to save the talk audio")?;
+    let res = talk(camera, TalkInput::File(&path), 1.0).await;
+    let _ = tokio::fs::remove_file(&path).await;
+    res
+}
+
 async fn handle_mqtt_message(
     msg: MqttReply,
     mqtt: &MqttInstance,
     camera: &NeoInstance,
 ) -> Result<()> {
+    let payload_format = camera.config().await?.borrow().mqtt.payload_format;
     match msg.as_ref() {
         MqttReplyRef {
             topic: _,
@@ -641,19 +1044,12 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(true, 180).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(
+                        async move { AnyResult::Ok(cam.set_floodlight_manual(true, 180).await?) },
+                    )
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn on the floodlight light");
             mqtt.send_message("control/floodlight", &reply, false)
                 .await
                 .with_context(|| "Failed to publish camera status light on")?;
@@ -664,19 +1060,12 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(false, 180).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(
+                        async move { AnyResult::Ok(cam.set_floodlight_manual(false, 180).await?) },
+                    )
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn off the floodlight light");
             mqtt.send_message("control/floodlight", &reply, false)
                 .await
                 .with_context(|| "Failed to publish camera status light off")?;
@@ -687,19 +1076,10 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.led_light_set(true).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(async move { AnyResult::Ok(cam.led_light_set(true).await?) })
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn on the led");
             mqtt.send_message("control/led", &reply, false)
                 .await
                 .with_context(|| "Failed to publish led on")?;
@@ -710,19 +1090,10 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.led_light_set(false).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(async move { AnyResult::Ok(cam.led_light_set(false).await?) })
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn off the led");
             mqtt.send_message("control/led", &reply, false)
                 .await
                 .with_context(|| "Failed to publish led off")?;
@@ -733,19 +1104,12 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::On).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(
+                        async move { AnyResult::Ok(cam.irled_light_set(LightState::On).await?) },
+                    )
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the ir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn on the ir");
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir on")?;
@@ -756,19 +1120,12 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::Off).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(
+                        async move { AnyResult::Ok(cam.irled_light_set(LightState::Off).await?) },
+                    )
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the ir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn off the ir");
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir off")?;
@@ -779,19 +1136,12 @@ async fn handle_mqtt_message(
         } => {
             let res = camera
                 .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::Auto).await?;
-                        AnyResult::Ok(())
-                    })
+                    Box::pin(
+                        async move { AnyResult::Ok(cam.irled_light_set(LightState::Auto).await?) },
+                    )
                 })
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn set to auto on the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn set to auto on the led");
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir auto")?;
@@ -845,6 +1195,44 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/zoom", &reply, false)
                 .await
                 .with_context(|| "Failed to publish zoom on the camera")?;
+            if let Err(e) = publish_zoom_focus(mqtt, camera).await {
+                warn!("{:?}", e);
+            }
+        }
+        MqttReplyRef {
+            topic: topic @ ("control/zoom/pos" | "control/focus"),
+            message,
+        } => {
+            let reply = if let Ok(position) = message.parse::<u32>() {
+                let is_zoom = topic == "control/zoom/pos";
+                if let Err(e) = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            if is_zoom {
+                                cam.zoom_to(position).await?;
+                            } else {
+                                cam.focus_to(position).await?;
+                            }
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                {
+                    error!("Failed to move the zoom or focus: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            } else {
+                "FAIL: Could not convert message to number".to_string()
+            };
+
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish zoom on the camera")?;
+            if let Err(e) = publish_zoom_focus(mqtt, camera).await {
+                warn!("{:?}", e);
+            }
         }
         MqttReplyRef {
             topic: "control/ptz",
@@ -894,16 +1282,12 @@ async fn handle_mqtt_message(
                             },
                             Duration::from_millis(100),
                         );
-                        if let Err(e) = camera
-                            .run_task(|cam| {
-                                Box::pin(async move {
-                                    cam.send_ptz(bc_direction, speed).await?;
-                                    sleep(Duration::from_secs_f32(seconds)).await;
-                                    cam.send_ptz(BcDirection::Stop, speed).await?;
-                                    AnyResult::Ok(())
-                                })
-                            })
-                            .await
+                        if let Err(e) = async {
+                            camera.ptz(bc_direction, speed).await?;
+                            sleep(Duration::from_secs_f32(seconds)).await;
+                            camera.ptz(BcDirection::Stop, speed).await
+                        }
+                        .await
                         {
                             error!("Failed to send PTZ: {:?}", e);
                             "FAIL"
@@ -999,20 +1383,9 @@ async fn handle_mqtt_message(
             message: "on",
         } => {
             let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.pir_set(true).await?;
-                        AnyResult::Ok(())
-                    })
-                })
+                .run_task(|cam| Box::pin(async move { AnyResult::Ok(cam.pir_set(true).await?) }))
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the pir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn on the pir");
             mqtt.send_message("control/pir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish pir on")?;
@@ -1022,24 +1395,61 @@ async fn handle_mqtt_message(
             message: "off",
         } => {
             let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.pir_set(false).await?;
-                        AnyResult::Ok(())
-                    })
-                })
+                .run_task(|cam| Box::pin(async move { AnyResult::Ok(cam.pir_set(false).await?) }))
                 .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the pir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
+            let reply = confirmation_reply(res, "turn off the pir");
             mqtt.send_message("control/pir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish pir off")?;
         }
+        MqttReplyRef {
+            topic: "control/pir/sensitivity",
+            message,
+        } => {
+            let reply = match message.trim().parse::<u8>() {
+                Ok(sensitivity) => {
+                    let res = camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                AnyResult::Ok(cam.pir_set_sensitivity(sensitivity).await?)
+                            })
+                        })
+                        .await;
+                    confirmation_reply(res, "set the pir sensitivity")
+                }
+                Err(_) => {
+                    error!("PIR sensitivity should be a number from 0 to 100");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message("control/pir/sensitivity", &reply, false)
+                .await
+                .with_context(|| "Failed to publish pir sensitivity")?;
+        }
+        MqttReplyRef {
+            topic: "control/pir/schedule",
+            message,
+        } => {
+            let reply = match message.parse::<PirSchedule>() {
+                Ok(schedule) => {
+                    let res = camera
+                        .run_task(|cam| {
+                            Box::pin(
+                                async move { AnyResult::Ok(cam.pir_set_schedule(schedule).await?) },
+                            )
+                        })
+                        .await;
+                    confirmation_reply(res, "set the pir schedule")
+                }
+                Err(e) => {
+                    error!("{e}");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message("control/pir/schedule", &reply, false)
+                .await
+                .with_context(|| "Failed to publish pir schedule")?;
+        }
         MqttReplyRef {
             topic: "control/wakeup",
             message,
@@ -1115,6 +1525,159 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish floodlight_tasks")?;
         }
+        MqttReplyRef {
+            topic: "control/audio/record",
+            message,
+        } => {
+            let reply = match message.trim() {
+                "on" | "off" => {
+                    let record = message.trim() == "on";
+                    let res = camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                cam.set_audio_recording(record).await?;
+                                AnyResult::Ok(Confirmation::Confirmed)
+                            })
+                        })
+                        .await;
+                    confirmation_reply(res, "set the audio recording")
+                }
+                _ => {
+                    error!("Audio record should be on or off");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message("control/audio/record", &reply, false)
+                .await
+                .with_context(|| "Failed to publish audio record")?;
+        }
+        MqttReplyRef {
+            topic: "control/audio/volume",
+            message,
+        } => {
+            let reply = match message.trim().parse::<u8>() {
+                Ok(volume) => {
+                    let res = camera
+                        .run_task(|cam| {
+                            Box::pin(async move { AnyResult::Ok(cam.set_volume(volume).await?) })
+                        })
+                        .await;
+                    confirmation_reply(res, "set the volume")
+                }
+                Err(_) => {
+                    error!("Volume should be a number from 0 to 100");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message("control/audio/volume", &reply, false)
+                .await
+                .with_context(|| "Failed to publish volume")?;
+        }
+        MqttReplyRef {
+            topic: "control/daynight",
+            message,
+        } => {
+            let reply = match message.trim().parse::<DayNightMode>() {
+                Ok(mode) => {
+                    let change = IspChange {
+                        day_night: Some(mode),
+                        ..Default::default()
+                    };
+                    let res = camera
+                        .run_task(|cam| {
+                            let change = change.clone();
+                            Box::pin(async move { AnyResult::Ok(cam.isp_set(&change).await?) })
+                        })
+                        .await;
+                    confirmation_reply(res, "set the day night mode")
+                }
+                Err(e) => {
+                    error!("{e}");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message("control/daynight", &reply, false)
+                .await
+                .with_context(|| "Failed to publish day night mode")?;
+        }
+        MqttReplyRef { topic, message } if topic.starts_with("control/isp/") => {
+            let reply = match isp_change(&topic["control/isp/".len()..], message.trim()) {
+                Ok(change) => {
+                    let res = camera
+                        .run_task(|cam| {
+                            let change = change.clone();
+                            Box::pin(async move { AnyResult::Ok(cam.isp_set(&change).await?) })
+                        })
+                        .await;
+                    confirmation_reply(res, "change the picture settings")
+                }
+                Err(e) => {
+                    error!("{e}");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish picture settings")?;
+        }
+        MqttReplyRef { topic, message } if topic.starts_with("control/services/") => {
+            let reply = match service_change(&topic["control/services/".len()..], message.trim()) {
+                Ok(services) => {
+                    let res = camera
+                        .run_task(|cam| {
+                            let services = services.clone();
+                            Box::pin(async move {
+                                AnyResult::Ok(cam.set_services_bulk(&services).await?)
+                            })
+                        })
+                        .await;
+                    let reply = confirmation_reply(res, "change the services");
+                    if reply != "FAIL" {
+                        if let Err(e) = publish_services(mqtt, camera, payload_format).await {
+                            warn!("Failed to publish the services after the change: {e:?}");
+                        }
+                    }
+                    reply
+                }
+                Err(e) => {
+                    error!("{e}");
+                    "FAIL".to_string()
+                }
+            };
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish services change")?;
+        }
+        MqttReplyRef {
+            topic: "query/services",
+            ..
+        } => {
+            let reply = match publish_services(mqtt, camera, payload_format).await {
+                Ok(()) => "OK",
+                Err(e) => {
+                    error!("Failed to publish the services: {:?}", e);
+                    "FAIL"
+                }
+            };
+            mqtt.send_message("query/services", reply, false)
+                .await
+                .with_context(|| "Failed to publish services query")?;
+        }
+        MqttReplyRef {
+            topic: "control/talk",
+            message,
+        } => {
+            let reply = match talk_message(camera, message).await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to talk: {:?}", e);
+                    format!("FAIL: {e}")
+                }
+            };
+            mqtt.send_message("control/talk", &reply, false)
+                .await
+                .with_context(|| "Failed to publish talk")?;
+        }
         MqttReplyRef {
             topic: "control/siren",
             message: "on",
@@ -1155,28 +1718,18 @@ async fn handle_mqtt_message(
                     error!("Failed to get battery xml: {:?}", e);
                     "FAIL"
                 }
-                Ok(xml) => {
-                    let bytes_res =
-                        yaserde::ser::serialize_with_writer(&xml, vec![], &Default::default());
-                    match bytes_res {
-                        Ok(bytes) => match String::from_utf8(bytes) {
-                            Ok(str) => {
-                                mqtt.send_message("status/battery", &str, false)
-                                    .await
-                                    .with_context(|| "Failed to publish battery info")?;
-                                "OK"
-                            }
-                            Err(_) => {
-                                error!("Failed to encode battery status");
-                                "FAIL"
-                            }
-                        },
-                        Err(_) => {
-                            error!("Failed to serialise battery status");
-                            "FAIL"
-                        }
+                Ok(xml) => match payload::encode(&xml, payload_format) {
+                    Ok(str) => {
+                        mqtt.send_message("status/battery", &str, false)
+                            .await
+                            .with_context(|| "Failed to publish battery info")?;
+                        "OK"
                     }
-                }
+                    Err(e) => {
+                        error!("Failed to serialise battery status: {:?}", e);
+                        "FAIL"
+                    }
+                },
             }
             .to_string();
             mqtt.send_message("query/battery", &reply, false)
@@ -1199,28 +1752,18 @@ async fn handle_mqtt_message(
                     error!("Failed to get pir xml: {:?}", e);
                     "FAIL"
                 }
-                Ok(xml) => {
-                    let bytes_res =
-                        yaserde::ser::serialize_with_writer(&xml, vec![], &Default::default());
-                    match bytes_res {
-                        Ok(bytes) => match String::from_utf8(bytes) {
-                            Ok(str) => {
-                                mqtt.send_message("status/pir", &str, false)
-                                    .await
-                                    .with_context(|| "Failed to publish pir info")?;
-                                "OK"
-                            }
-                            Err(_) => {
-                                error!("Failed to encode pir status");
-                                "FAIL"
-                            }
-                        },
-                        Err(_) => {
-                            error!("Failed to serialise pir status");
-                            "FAIL"
-                        }
+                Ok(xml) => match payload::encode(&xml, payload_format) {
+                    Ok(str) => {
+                        mqtt.send_message("status/pir", &str, false)
+                            .await
+                            .with_context(|| "Failed to publish pir info")?;
+                        "OK"
                     }
-                }
+                    Err(e) => {
+                        error!("Failed to serialise pir status: {:?}", e);
+                        "FAIL"
+                    }
+                },
             }
             .to_string();
             mqtt.send_message("query/pir", &reply, false)
@@ -1244,34 +1787,74 @@ async fn handle_mqtt_message(
                     error!("Failed to get ptz xml: {:?}", e);
                     "FAIL"
                 }
-                Ok(xml) => {
-                    let bytes_res =
-                        yaserde::ser::serialize_with_writer(&xml, vec![], &Default::default());
-                    match bytes_res {
-                        Ok(bytes) => match String::from_utf8(bytes) {
-                            Ok(str) => {
-                                mqtt.send_message("status/ptz", &str, false)
-                                    .await
-                                    .with_context(|| "Failed to publish ptz info")?;
-                                "OK"
-                            }
-                            Err(_) => {
-                                error!("Failed to encode ptz status");
-                                "FAIL"
-                            }
-                        },
-                        Err(_) => {
-                            error!("Failed to serialise ptz status");
-                            "FAIL"
-                        }
+                Ok(xml) => match payload::encode(&xml, payload_format) {
+                    Ok(str) => {
+                        mqtt.send_message("status/ptz", &str, false)
+                            .await
+                            .with_context(|| "Failed to publish ptz info")?;
+                        "OK"
                     }
-                }
+                    Err(e) => {
+                        error!("Failed to serialise ptz status: {:?}", e);
+                        "FAIL"
+                    }
+                },
             }
             .to_string();
             mqtt.send_message("query/ptz", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ptz query")?;
         }
+        MqttReplyRef {
+            topic: "query/zoom",
+            ..
+        } => {
+            let reply = match publish_zoom_focus(mqtt, camera).await {
+                Ok(()) => "OK",
+                Err(e) => {
+                    error!("{:?}", e);
+                    "FAIL"
+                }
+            };
+            mqtt.send_message("query/zoom", reply, false)
+                .await
+                .with_context(|| "Failed to publish zoom query")?;
+        }
+        MqttReplyRef {
+            topic: "query/storage",
+            ..
+        } => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let xml = cam.get_storage_info().await?;
+                        AnyResult::Ok(xml)
+                    })
+                })
+                .await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to get storage xml: {:?}", e);
+                    "FAIL"
+                }
+                Ok(xml) => match payload::encode(&xml, payload_format) {
+                    Ok(str) => {
+                        mqtt.send_message("status/storage", &str, false)
+                            .await
+                            .with_context(|| "Failed to publish storage info")?;
+                        "OK"
+                    }
+                    Err(e) => {
+                        error!("Failed to serialise storage status: {:?}", e);
+                        "FAIL"
+                    }
+                },
+            }
+            .to_string();
+            mqtt.send_message("query/storage", &reply, false)
+                .await
+                .with_context(|| "Failed to publish storage query")?;
+        }
         MqttReplyRef {
             topic: "query/preview",
             ..
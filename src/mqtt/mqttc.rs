@@ -1,14 +1,10 @@
+use super::client::{connect, ClientBusy, Notification};
 use crate::{
     config::{Config, MqttServerConfig},
     AnyResult,
 };
 use anyhow::{anyhow, Context, Result};
 use futures::future::FutureExt;
-use log::*;
-use rumqttc::{
-    AsyncClient, ConnectReturnCode, Event, Incoming, Key, LastWill, MqttOptions, QoS,
-    TlsConfiguration, Transport,
-};
 use std::sync::Arc;
 use tokio::task::JoinSet;
 use tokio::{
@@ -31,6 +27,37 @@ pub(crate) struct Mqtt {
 
 impl Mqtt {
     pub(crate) async fn new(config: WatchReceiver<Config>) -> Self {
+        Self::start(config, "Neolink", true, vec![]).await
+    }
+
+    /// A client that only publishes such as for the scheduled reboots
+    ///
+    /// It connects with its own `client_id` so that it does not kick the mqtt
+    /// subcommand off the broker and it leaves `{root}/status` alone
+    pub(crate) async fn publisher(config: WatchReceiver<Config>, client_id: &str) -> Self {
+        Self::start(config, client_id, false, vec![]).await
+    }
+
+    /// A client that listens to topics outside of neolink's root such as
+    /// those of other devices
+    ///
+    /// The messages keep their full topic, wildcards can be used in `topics`
+    pub(crate) async fn listener(
+        config: WatchReceiver<Config>,
+        client_id: &str,
+        topics: Vec<String>,
+    ) -> Self {
+        Self::start(config, client_id, false, topics).await
+    }
+
+    async fn start(
+        config: WatchReceiver<Config>,
+        client_id: &str,
+        primary: bool,
+        topics: Vec<String>,
+    ) -> Self {
+        let client_id = client_id.to_string();
+        let topics = Arc::new(topics);
         let (incoming_tx, _) = broadcast::<MqttReply>(100);
         let (outgoing_tx, mut outgoing_rx) = mpsc::<MqttRequest>(100);
         let cancel = CancellationToken::new();
@@ -44,12 +71,15 @@ impl Mqtt {
         let thread_outgoing_tx = outgoing_tx.clone();
         set.spawn(async move {
             let mut mqtt_config = thread_config.borrow().mqtt.clone();
+            let mut root = thread_config.borrow().mqtt_root();
             let r = loop {
                 break tokio::select! {
                     _ = thread_cancel.cancelled() => AnyResult::Ok(()),
-                    v = thread_config.wait_for(|config| config.mqtt != mqtt_config).map(|res| res.map(|r| r.clone())) =>
+                    v = thread_config.wait_for(|config| config.mqtt != mqtt_config || config.mqtt_root() != root).map(|res| res.map(|r| r.clone())) =>
                     {
-                        mqtt_config = v?.mqtt.clone();
+                        let config = v?;
+                        mqtt_config = config.mqtt.clone();
+                        root = config.mqtt_root();
                         continue;
                     }
                     v = async {
@@ -58,6 +88,10 @@ impl Mqtt {
                             outgoing_rx: &mut outgoing_rx,
                             outgoing_tx: thread_outgoing_tx.clone(),
                             config: mqtt_config.as_ref().unwrap(),
+                            root: &root,
+                            client_id: &client_id,
+                            primary,
+                            topics: topics.clone(),
                             cancel: CancellationToken::new(),
                         };
                         backend.run().await
@@ -118,75 +152,36 @@ struct MqttBackend<'a> {
     outgoing_rx: &'a mut MpscReceiver<MqttRequest>,
     outgoing_tx: MpscSender<MqttRequest>,
     config: &'a MqttServerConfig,
+    /// Topic that all messages are sent under, see [`Config::mqtt_root`]
+    root: &'a str,
+    client_id: &'a str,
+    /// Announce on `{root}/status` and subscribe to the control messages
+    primary: bool,
+    /// Topic filters outside of the root to subscribe to
+    topics: Arc<Vec<String>>,
     cancel: CancellationToken,
 }
 
 impl<'a> MqttBackend<'a> {
     async fn run(&mut self) -> AnyResult<()> {
         log::trace!("Run MQTT Server");
-        let mut mqttoptions = MqttOptions::new(
-            "Neolink".to_string(),
-            &self.config.broker_addr,
-            self.config.port,
-        );
-        let max_size = 100 * (1024 * 1024);
-        mqttoptions.set_max_packet_size(max_size, max_size);
-
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &self.config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &self.config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, Key::RSA(key_buf)))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
-
-        if let Some((username, password)) = &self.config.credentials {
-            mqttoptions.set_credentials(username, password);
-        }
-
-        mqttoptions.set_keep_alive(Duration::from_secs(5));
-
         // On unclean disconnect send this
-        mqttoptions.set_last_will(LastWill::new(
-            "neolink/status".to_string(),
-            "offline",
-            QoS::AtLeastOnce,
-            true,
-        ));
+        let last_will = self
+            .primary
+            .then(|| (format!("{}/status", self.root), "offline".to_string()));
+        let (client, mut connection) = connect(self.config, self.client_id, last_will)?;
 
-        let (client, mut connection) = AsyncClient::new(mqttoptions, 100);
-
-        let client = Arc::new(client);
         let send_client = client.clone();
-        send_client
-            .publish(
-                "neolink/status".to_string(),
-                QoS::AtLeastOnce,
-                true,
-                "connected".to_string(),
-            )
-            .await?;
-        log::debug!("MQTT Published Startup");
+        if self.primary {
+            send_client
+                .publish(
+                    format!("{}/status", self.root),
+                    true,
+                    "connected".to_string(),
+                )
+                .await?;
+            log::debug!("MQTT Published Startup");
+        }
         let loop_cancel = CancellationToken::new();
         let _drop_guard = loop_cancel.clone().drop_guard();
         loop {
@@ -201,6 +196,8 @@ impl<'a> MqttBackend<'a> {
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
                     let server_config = self.config.clone();
+                    let root = self.root.to_string();
+                    let primary = self.primary;
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
@@ -210,7 +207,6 @@ impl<'a> MqttBackend<'a> {
                                     MqttRequest::Send(msg, tx) =>  {
                                         let v = send_client.publish(
                                             msg.topic.clone(),
-                                            QoS::AtLeastOnce,
                                             false,
                                             (*msg.message).clone(),
                                         ).await;
@@ -218,7 +214,7 @@ impl<'a> MqttBackend<'a> {
                                             Ok(()) => {
                                                 let _ = tx.send(Ok(()));
                                             },
-                                            Err(rumqttc::ClientError::Request(_)) | Err(rumqttc::ClientError::TryRequest(_)) => {
+                                            Err(ClientBusy) => {
                                                 // Requeue it
                                                 outgoing_tx.send(MqttRequest::Send(msg, tx)).await?;
                                             }
@@ -228,7 +224,6 @@ impl<'a> MqttBackend<'a> {
                                     MqttRequest::SendRetained(msg, tx) =>  {
                                         let v = send_client.publish(
                                             msg.topic.clone(),
-                                            QoS::AtLeastOnce,
                                             true,
                                             (*msg.message).clone(),
                                         ).await;
@@ -236,26 +231,45 @@ impl<'a> MqttBackend<'a> {
                                             Ok(()) => {
                                                 let _ = tx.send(Ok(()));
                                             },
-                                            Err(rumqttc::ClientError::Request(_)) | Err(rumqttc::ClientError::TryRequest(_)) => {
+                                            Err(ClientBusy) => {
                                                 // Requeue it
                                                 outgoing_tx.send(MqttRequest::Send(msg, tx)).await?;
                                             }
                                         };
                                         v?;
                                     }
+                                    MqttRequest::SendBytes{topic, payload, retain, reply} =>  {
+                                        let v = send_client.publish(
+                                            topic.clone(),
+                                            retain,
+                                            (*payload).clone(),
+                                        ).await;
+                                        match &v {
+                                            Ok(()) => {
+                                                let _ = reply.send(Ok(()));
+                                            },
+                                            Err(ClientBusy) => {
+                                                // Requeue it
+                                                outgoing_tx.send(MqttRequest::SendBytes{topic, payload, retain, reply}).await?;
+                                            }
+                                        };
+                                        v?;
+                                    }
                                     MqttRequest::HangUp(reply) => {
-                                        send_client.publish(
-                                            "neolink/status".to_string(),
-                                            QoS::AtLeastOnce,
-                                            true,
-                                            "disconnected".to_string(),
-                                        ).await?;
+                                        if primary {
+                                            send_client.publish(
+                                                format!("{root}/status"),
+                                                true,
+                                                "disconnected".to_string(),
+                                            ).await?;
+                                        }
                                         let _ = reply.send(());
                                         return Err(anyhow!("Disconneting"));
                                     }
                                     MqttRequest::Subscribe(name, reply) => {
                                         let instance = MqttInstance {
                                             name,
+                                            root,
                                             incomming_rx: BroadcastStream::new(incomming_tx.subscribe()),
                                             outgoing_tx: outgoing_tx.clone(),
                                         };
@@ -284,43 +298,54 @@ impl<'a> MqttBackend<'a> {
                     let incomming_tx = self.incomming_tx.clone();
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
+                    let root = self.root.to_string();
+                    let primary = self.primary;
+                    let topics = self.topics.clone();
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
                             _ = thread_cancel.cancelled() => AnyResult::Ok(()),
                             v = async {
                                 match notification {
-                                    Event::Incoming(Incoming::ConnAck(connected)) => {
-                                        if ConnectReturnCode::Success == connected.code {
+                                    Notification::ConnAck { success } => {
+                                        if primary && success {
                                             // Publish connected now that we are online
                                             client
                                             .publish(
-                                                "neolink/status".to_string(),
-                                                QoS::AtLeastOnce,
+                                                format!("{root}/status"),
                                                 true,
                                                 "connected",
                                             )
                                             .await?;
                                             // We succesfully logged in. Now ask for the cameras subscription.
                                             client
-                                            .subscribe("neolink/#".to_string(), QoS::AtMostOnce)
+                                            .subscribe(format!("{root}/#"))
                                             .await?;
                                         }
+                                        if success {
+                                            for topic in topics.iter() {
+                                                client.subscribe(topic.clone()).await?;
+                                            }
+                                        }
                                     }
-                                    Event::Incoming(Incoming::Publish(published_message)) => {
-                                        if let Some(sub_topic) = published_message
-                                            .topic
-                                            .strip_prefix("neolink/")
+                                    Notification::Publish { topic, message } => {
+                                        if topics.iter().any(|filter| rumqttc::matches(&topic, filter)) {
+                                            let _ = incomming_tx
+                                                .send(MqttReply {
+                                                    topic,
+                                                    message: Arc::new(message),
+                                                });
+                                        } else if let Some(sub_topic) = topic
+                                            .strip_prefix(&format!("{root}/"))
                                         {
                                             let _ = incomming_tx
                                                 .send(MqttReply {
                                                     topic: sub_topic.to_string(),
-                                                    message: Arc::new(String::from_utf8_lossy(published_message.payload.as_ref())
-                                                        .into_owned()),
+                                                    message: Arc::new(message),
                                                 });
                                         }
                                     }
-                                    _ => {}
+                                    Notification::Other => {}
                                 }
                                 AnyResult::Ok(())
                             } => v
@@ -348,6 +373,7 @@ pub(crate) struct MqttInstance {
     outgoing_tx: MpscSender<MqttRequest>,
     incomming_rx: BroadcastStream<MqttReply>,
     name: String,
+    root: String,
 }
 
 impl MqttInstance {
@@ -355,6 +381,11 @@ impl MqttInstance {
         &self.name
     }
 
+    /// The root topic of neolink's messages: `neolink` or `{namespace}/neolink`
+    pub(crate) fn root(&self) -> &str {
+        &self.root
+    }
+
     pub async fn subscribe<T: Into<String>>(&self, name: T) -> AnyResult<Self> {
         let (tx, rx) = oneshot();
         self.outgoing_tx
@@ -421,8 +452,34 @@ impl MqttInstance {
         message: &str,
         retain: bool,
     ) -> AnyResult<()> {
-        self.send_message_with_root_topic("neolink", sub_topic, message, retain)
+        self.send_message_with_root_topic(&self.root, sub_topic, message, retain)
+            .await?;
+        Ok(())
+    }
+
+    /// Send a binary payload such as a jpeg as it is
+    pub async fn send_bytes(
+        &self,
+        sub_topic: &str,
+        payload: Vec<u8>,
+        retain: bool,
+    ) -> AnyResult<()> {
+        let topic = [self.root.clone(), self.name.clone(), sub_topic.to_string()]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/");
+        let (tx, rx) = oneshot();
+        self.outgoing_tx
+            .send(MqttRequest::SendBytes {
+                topic,
+                payload: Arc::new(payload),
+                retain,
+                reply: tx,
+            })
             .await?;
+        rx.await??;
         Ok(())
     }
 
@@ -440,15 +497,16 @@ impl MqttInstance {
             if self.name.is_empty() {
                 break msg;
             } else {
-                let mut topics = msg.topic.split('/');
-                let sub_topic = topics.next();
+                // The name of an NVR channel such as `nvr/ch0` spans two levels
+                let sub_topic = match msg.topic.strip_prefix(self.name.as_str()) {
+                    Some("") => Some(""),
+                    Some(rest) => rest.strip_prefix('/'),
+                    None => None,
+                };
                 // log::debug!("topics: {:?}", msg.topic);
                 // log::debug!("sub_topic: {sub_topic:?}");
-                if sub_topic
-                    .map(|subtopic| *subtopic == self.name)
-                    .unwrap_or(false)
-                {
-                    msg.topic = topics.collect::<Vec<_>>().join("/");
+                if let Some(sub_topic) = sub_topic {
+                    msg.topic = sub_topic.to_string();
                     // log::debug!("new topics: {:?}", msg.topic);
                     break msg;
                 }
@@ -458,9 +516,9 @@ impl MqttInstance {
 
     pub(crate) async fn last_will(&self, topic: &str, message: &str) -> AnyResult<LastWillMqtt> {
         let topic = if self.name.is_empty() {
-            format!("neolink/{}", topic)
+            format!("{}/{}", self.root, topic)
         } else {
-            format!("neolink/{}/{}", self.name, topic)
+            format!("{}/{}/{}", self.root, self.name, topic)
         };
 
         let (tx, rx) = oneshot();
@@ -498,6 +556,12 @@ pub(crate) struct MqttReplyRef<'a> {
 enum MqttRequest {
     Send(MqttReply, OneshotSender<Result<()>>),
     SendRetained(MqttReply, OneshotSender<Result<()>>),
+    SendBytes {
+        topic: String,
+        payload: Arc<Vec<u8>>,
+        retain: bool,
+        reply: OneshotSender<Result<()>>,
+    },
     HangUp(OneshotSender<()>),
     Subscribe(String, OneshotSender<Result<MqttInstance>>),
     LastWill {
@@ -518,53 +582,11 @@ impl LastWillMqtt {
         message: String,
     ) -> AnyResult<Self> {
         log::trace!("Run MQTT Last Will");
-        let mut mqttoptions = MqttOptions::new(
-            format!("NeolinkLastWill_{}", topic),
-            &config.broker_addr,
-            config.port,
-        );
-        let max_size = 100 * (1024 * 1024);
-        mqttoptions.set_max_packet_size(max_size, max_size);
-
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, Key::RSA(key_buf)))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
-
-        if let Some((username, password)) = &config.credentials {
-            mqttoptions.set_credentials(username, password);
-        }
-
-        mqttoptions.set_keep_alive(Duration::from_secs(5));
-
-        // On unclean disconnect send this
-        mqttoptions.set_last_will(LastWill::new(topic, message, QoS::AtLeastOnce, true));
-
-        let (client, mut connection) = AsyncClient::new(mqttoptions, 100);
-        let client = Arc::new(client);
+        let (client, mut connection) = connect(
+            config,
+            &format!("NeolinkLastWill_{}", topic),
+            Some((topic, message)),
+        )?;
         let cancel = CancellationToken::new();
         let thread_cancel = cancel.clone();
 
@@ -0,0 +1,256 @@
+//! Versioned json payloads for the mqtt status topics
+//!
+//! Each payload carries a `schema` field of the form `neolink.<name>.v<version>`.
+//! The version is bumped whenever a field is removed or changes meaning, adding
+//! new fields does not change the version.
+//!
+//! Setting `payload_format = "xml"` in the `[cameras.mqtt]` config publishes the
+//! camera's xml instead, as was done before the json payloads were added.
+use anyhow::{anyhow, Context, Result};
+use neolink_core::{
+    bc::xml::{BatteryInfo, HddInfoList, PtzPreset, RfAlarmCfg, ServerPort},
+    bc_protocol::{MotionDetail, PirSchedule},
+};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use yaserde::YaSerialize;
+
+use crate::config::PayloadFormat;
+
+/// A camera xml that has a versioned json representation
+pub(crate) trait JsonPayload {
+    /// The json representation
+    type Json: Serialize;
+
+    /// Convert the xml into the json representation
+    fn to_json(&self) -> Self::Json;
+}
+
+/// Encode the xml in the requested payload format
+pub(crate) fn encode<T: JsonPayload + YaSerialize>(
+    xml: &T,
+    format: PayloadFormat,
+) -> Result<String> {
+    match format {
+        PayloadFormat::Json => {
+            serde_json::to_string(&xml.to_json()).context("Failed to serialise json payload")
+        }
+        PayloadFormat::Xml => {
+            let bytes = yaserde::ser::serialize_with_writer(xml, vec![], &Default::default())
+                .map_err(|e| anyhow!("Failed to serialise xml payload: {e}"))?;
+            String::from_utf8(bytes).context("Failed to encode xml payload")
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct BatteryV1 {
+    schema: &'static str,
+    charge_status: String,
+    adapter_status: String,
+    voltage: i32,
+    current: i32,
+    temperature: i32,
+    battery_percent: u32,
+    low_power: bool,
+    battery_version: u32,
+}
+
+impl JsonPayload for BatteryInfo {
+    type Json = BatteryV1;
+
+    fn to_json(&self) -> Self::Json {
+        BatteryV1 {
+            schema: "neolink.battery.v1",
+            charge_status: self.charge_status.clone(),
+            adapter_status: self.adapter_status.clone(),
+            voltage: self.voltage,
+            current: self.current,
+            temperature: self.temperature,
+            battery_percent: self.battery_percent,
+            low_power: self.low_power != 0,
+            battery_version: self.battery_version,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct PirV1 {
+    schema: &'static str,
+    enabled: bool,
+    sensitivity: u8,
+    reduce_false_alarm: bool,
+    /// Such as `20-6` or `always`, `null` if the time blocks were set up in
+    /// a way that this cannot describe
+    schedule: Option<String>,
+}
+
+impl JsonPayload for RfAlarmCfg {
+    type Json = PirV1;
+
+    fn to_json(&self) -> Self::Json {
+        PirV1 {
+            schema: "neolink.pir.v1",
+            enabled: self.enable != 0,
+            sensitivity: self.sensitivity,
+            reduce_false_alarm: self.reduceFalseAlarm != 0,
+            schedule: PirSchedule::from_time_blocks(&self.time_block_list.time_block)
+                .map(|schedule| schedule.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct PtzPresetV1 {
+    schema: &'static str,
+    presets: Vec<PresetV1>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PresetV1 {
+    id: u8,
+    name: Option<String>,
+}
+
+impl JsonPayload for PtzPreset {
+    type Json = PtzPresetV1;
+
+    fn to_json(&self) -> Self::Json {
+        PtzPresetV1 {
+            schema: "neolink.ptz_preset.v1",
+            presets: self
+                .preset_list
+                .preset
+                .iter()
+                .map(|preset| PresetV1 {
+                    id: preset.id,
+                    name: preset.name.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct StorageV1 {
+    schema: &'static str,
+    /// Empty when there is no SD card
+    cards: Vec<CardV1>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CardV1 {
+    id: u8,
+    capacity_mb: u64,
+    free_mb: Option<u64>,
+    healthy: bool,
+    storage_type: Option<String>,
+}
+
+impl JsonPayload for HddInfoList {
+    type Json = StorageV1;
+
+    fn to_json(&self) -> Self::Json {
+        StorageV1 {
+            schema: "neolink.storage.v1",
+            cards: self
+                .hdd_info
+                .iter()
+                .map(|hdd| CardV1 {
+                    id: hdd.number,
+                    capacity_mb: hdd.capacity,
+                    free_mb: hdd.remain_size,
+                    healthy: hdd.healthy(),
+                    storage_type: hdd.storage_type.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct ServicesV1 {
+    schema: &'static str,
+    http: ServiceV1,
+    https: ServiceV1,
+    rtsp: ServiceV1,
+    rtmp: ServiceV1,
+    onvif: ServiceV1,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ServiceV1 {
+    port: Option<u16>,
+    enabled: Option<bool>,
+}
+
+impl ServiceV1 {
+    fn new(port: Option<u16>, enabled: Option<u8>) -> Self {
+        Self {
+            port,
+            enabled: enabled.map(|enabled| enabled != 0),
+        }
+    }
+}
+
+impl JsonPayload for ServerPort {
+    type Json = ServicesV1;
+
+    fn to_json(&self) -> Self::Json {
+        ServicesV1 {
+            schema: "neolink.services.v1",
+            http: ServiceV1::new(self.http_port, self.http_enable),
+            https: ServiceV1::new(self.https_port, self.https_enable),
+            rtsp: ServiceV1::new(self.rtsp_port, self.rtsp_enable),
+            rtmp: ServiceV1::new(self.rtmp_port, self.rtmp_enable),
+            onvif: ServiceV1::new(self.onvif_port, self.onvif_enable),
+        }
+    }
+}
+
+/// One whole motion, sent when it stops
+#[derive(Serialize)]
+pub(crate) struct MotionEventV1 {
+    schema: &'static str,
+    /// Unix time in seconds
+    start: u64,
+    /// Seconds
+    duration: f64,
+    /// Every kind of AI detection seen during the motion
+    ai_detections: Vec<String>,
+}
+
+impl MotionEventV1 {
+    pub(crate) fn new(start: SystemTime, duration: Duration, ai_detections: Vec<String>) -> Self {
+        Self {
+            schema: "neolink.motion_event.v1",
+            start: start
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0),
+            duration: duration.as_secs_f64(),
+            ai_detections,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct MotionV1 {
+    schema: &'static str,
+    ai_type: Option<String>,
+    motion_confidence: Option<u8>,
+    ai_confidence: Option<u8>,
+}
+
+impl JsonPayload for MotionDetail {
+    type Json = MotionV1;
+
+    fn to_json(&self) -> Self::Json {
+        MotionV1 {
+            schema: "neolink.motion.v1",
+            ai_type: self.ai_type.clone(),
+            motion_confidence: self.motion_confidence,
+            ai_confidence: self.ai_confidence,
+        }
+    }
+}
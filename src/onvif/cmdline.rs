@@ -0,0 +1,5 @@
+use clap::Parser;
+
+/// Serve the camera's motion events as an ONVIF event service
+#[derive(Parser, Debug)]
+pub struct Opt {}
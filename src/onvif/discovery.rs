@@ -0,0 +1,140 @@
+//! WS-Discovery so that NVRs can find the cameras on their own
+//!
+//! NVRs send a multicast `Probe` to `239.255.255.250:3702` and each ONVIF
+//! device replies to them with a `ProbeMatch` holding the address of its
+//! device service. Every enabled camera is answered for as its own device.
+//!
+//! WS-Discovery uses an older addressing namespace than the other services
+//! so the envelope is built here rather than with [`super::soap::envelope`].
+use anyhow::{Context, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket as StdUdpSocket};
+use tokio::net::UdpSocket;
+
+use super::soap::{self, escape};
+use crate::common::NeoReactor;
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const DISCOVERY_PORT: u16 = 3702;
+
+/// Answer the probes until the server stops
+pub(super) async fn discovery(
+    reactor: NeoReactor,
+    bind_addr: IpAddr,
+    port: u16,
+    prefix: String,
+) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+        .await
+        .context("Failed to bind the ONVIF discovery port 3702")?;
+    let interface = match bind_addr {
+        IpAddr::V4(addr) => addr,
+        IpAddr::V6(_) => Ipv4Addr::UNSPECIFIED,
+    };
+    socket
+        .join_multicast_v4(MULTICAST_ADDR, interface)
+        .context("Failed to join the ONVIF discovery multicast group")?;
+    log::info!("ONVIF discovery listening on {MULTICAST_ADDR}:{DISCOVERY_PORT}");
+
+    let mut buf = vec![0; 65536];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..len]);
+        if soap::action(&request) != Some("Probe") || !wants_video(&request) {
+            continue;
+        }
+        let relates_to = soap::param(&request, "MessageID").unwrap_or_default();
+        let host = match reply_addr(bind_addr, from) {
+            Ok(host) => host,
+            Err(e) => {
+                log::debug!("No address to answer the ONVIF probe from {from}: {e:?}");
+                continue;
+            }
+        };
+
+        let cameras = reactor
+            .config()
+            .await?
+            .borrow()
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled)
+            .map(|cam| cam.name.clone())
+            .collect::<Vec<_>>();
+        for camera in cameras {
+            let xaddr = format!("http://{host}:{port}{prefix}/onvif/{camera}/device_service");
+            let reply = probe_match(&camera, &xaddr, relates_to);
+            if let Err(e) = socket.send_to(reply.as_bytes(), from).await {
+                log::debug!("Failed to answer the ONVIF probe from {from}: {e:?}");
+            }
+        }
+    }
+}
+
+/// A probe without types is for any device, otherwise it must ask for a
+/// video transmitter or a plain device
+fn wants_video(request: &str) -> bool {
+    match soap::param(request, "Types") {
+        None | Some("") => true,
+        Some(types) => types
+            .split_whitespace()
+            .filter_map(|kind| kind.rsplit(':').next())
+            .any(|kind| kind == "NetworkVideoTransmitter" || kind == "Device"),
+    }
+}
+
+/// The address of this machine that the prober can reach
+fn reply_addr(bind_addr: IpAddr, from: SocketAddr) -> Result<IpAddr> {
+    if !bind_addr.is_unspecified() {
+        return Ok(bind_addr);
+    }
+    // Connecting a udp socket sends nothing but picks the outgoing interface
+    let probe = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    probe.connect(from)?;
+    Ok(probe.local_addr()?.ip())
+}
+
+/// A uuid urn that stays the same for the same text
+fn urn(text: &str) -> String {
+    let hex = format!("{:x}", md5::compute(text));
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn probe_match(camera: &str, xaddr: &str, relates_to: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<env:Envelope xmlns:env="http://www.w3.org/2003/05/soap-envelope" "#,
+            r#"xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing" "#,
+            r#"xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery" "#,
+            r#"xmlns:dn="http://www.onvif.org/ver10/network/wsdl">"#,
+            "<env:Header>",
+            "<wsa:MessageID>{message_id}</wsa:MessageID>",
+            "<wsa:RelatesTo>{relates_to}</wsa:RelatesTo>",
+            "<wsa:To>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</wsa:To>",
+            "<wsa:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/ProbeMatches</wsa:Action>",
+            "</env:Header>",
+            "<env:Body><d:ProbeMatches><d:ProbeMatch>",
+            "<wsa:EndpointReference><wsa:Address>{address}</wsa:Address></wsa:EndpointReference>",
+            "<d:Types>dn:NetworkVideoTransmitter</d:Types>",
+            "<d:Scopes>onvif://www.onvif.org/type/video_encoder onvif://www.onvif.org/type/ptz ",
+            "onvif://www.onvif.org/Profile/Streaming onvif://www.onvif.org/hardware/Neolink ",
+            "onvif://www.onvif.org/name/{name}</d:Scopes>",
+            "<d:XAddrs>{xaddr}</d:XAddrs>",
+            "<d:MetadataVersion>1</d:MetadataVersion>",
+            "</d:ProbeMatch></d:ProbeMatches></env:Body></env:Envelope>"
+        ),
+        message_id = urn(&format!("{camera}{relates_to}")),
+        relates_to = escape(relates_to),
+        address = urn(camera),
+        // Scopes are separated by spaces so they cannot be in the name
+        name = escape(&camera.replace(' ', "%20")),
+        xaddr = escape(xaddr),
+    )
+}
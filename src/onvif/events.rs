@@ -0,0 +1,403 @@
+//! The ONVIF event service
+//!
+//! Only pull point subscriptions are supported, at most [`MAX_PULL_POINTS`]
+//! per camera. Each subscription holds its own watch on the camera's
+//! [`MdState`] and reports the changes as
+//!
+//! - Motion on both the `tns1:RuleEngine/CellMotionDetector/Motion` and
+//!   `tns1:VideoSource/MotionAlarm` topics since NVRs differ in which one they
+//!   listen to
+//! - PIR on `tns1:Device/Trigger/DigitalInput` with the `PIR` input token
+//! - The AI detections on the `tns1:RuleEngine/MyRuleDetector/...` topics that
+//!   the cameras use in their own ONVIF service, such as `PeopleDetect`
+use anyhow::Result;
+use neolink_core::bc_protocol::AiDetection;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use tokio::{
+    sync::{watch::Receiver as WatchReceiver, Mutex as AsyncMutex},
+    time::{timeout_at, Duration, Instant},
+};
+
+use super::soap::{self, escape};
+use crate::common::{MdState, NeoReactor};
+
+/// Longest time a pull may wait for an event
+const MAX_PULL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Subscription lifetime when the client does not ask for one
+const DEFAULT_TERMINATION: Duration = Duration::from_secs(60);
+/// Most subscriptions of one camera, as given in the service capabilities
+const MAX_PULL_POINTS: usize = 10;
+/// The AI detections that have a topic, the others are not reported
+const AI_TOPICS: [&str; 4] = [
+    "PeopleDetect",
+    "VehicleDetect",
+    "DogCatDetect",
+    "FaceDetect",
+];
+
+struct Subscription {
+    motion: WatchReceiver<MdState>,
+    /// The last state sent, `None` until the initial state has been sent
+    last_sent: Option<EventState>,
+    termination: SystemTime,
+}
+
+/// A subscription and the camera that it is on
+struct PullPoint {
+    camera: String,
+    subscription: Arc<AsyncMutex<Subscription>>,
+}
+
+#[derive(Default)]
+pub(super) struct Subscriptions {
+    next_id: AtomicU64,
+    subscriptions: Mutex<HashMap<u64, PullPoint>>,
+}
+
+/// The value of each topic
+#[derive(Clone, PartialEq, Eq)]
+struct EventState {
+    motion: bool,
+    pir: bool,
+    /// The [`AI_TOPICS`] that are on
+    ai: BTreeSet<&'static str>,
+}
+
+impl EventState {
+    fn new(state: &MdState) -> Option<Self> {
+        match state {
+            MdState::Start(_, detail) => Some(Self {
+                motion: true,
+                pir: detail.pir,
+                ai: detail.ai_detections().iter().filter_map(ai_topic).collect(),
+            }),
+            MdState::Stop(_) => Some(Self {
+                motion: false,
+                pir: false,
+                ai: Default::default(),
+            }),
+            MdState::Unknown => None,
+        }
+    }
+}
+
+fn ai_topic(detection: &AiDetection) -> Option<&'static str> {
+    match detection {
+        AiDetection::Person => Some(AI_TOPICS[0]),
+        AiDetection::Vehicle => Some(AI_TOPICS[1]),
+        AiDetection::Animal => Some(AI_TOPICS[2]),
+        AiDetection::Face => Some(AI_TOPICS[3]),
+        AiDetection::Other(_) => None,
+    }
+}
+
+fn utc(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// The messages of the topics that changed from `last`, all of them without it
+fn notification(state: &EventState, last: Option<&EventState>) -> String {
+    let operation = match last {
+        Some(_) => "Changed",
+        None => "Initialized",
+    };
+    let time = utc(SystemTime::now());
+    let message = |topic: &str, source: &str, data: &str| {
+        format!(
+            concat!(
+                "<wsnt:NotificationMessage>",
+                "<wsnt:Topic Dialect=\"http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet\">{}</wsnt:Topic>",
+                "<wsnt:Message><tt:Message UtcTime=\"{}\" PropertyOperation=\"{}\">",
+                "<tt:Source>{}</tt:Source><tt:Data>{}</tt:Data>",
+                "</tt:Message></wsnt:Message></wsnt:NotificationMessage>"
+            ),
+            topic, time, operation, source, data
+        )
+    };
+    let mut messages = vec![];
+    if last.map(|last| last.motion) != Some(state.motion) {
+        let motion = state.motion;
+        messages.push(message(
+            "tns1:RuleEngine/CellMotionDetector/Motion",
+            concat!(
+                "<tt:SimpleItem Name=\"VideoSourceConfigurationToken\" Value=\"VideoSource\"/>",
+                "<tt:SimpleItem Name=\"VideoAnalyticsConfigurationToken\" Value=\"VideoAnalytics\"/>",
+                "<tt:SimpleItem Name=\"Rule\" Value=\"MotionDetectorRule\"/>"
+            ),
+            &format!("<tt:SimpleItem Name=\"IsMotion\" Value=\"{motion}\"/>"),
+        ));
+        messages.push(message(
+            "tns1:VideoSource/MotionAlarm",
+            "<tt:SimpleItem Name=\"Source\" Value=\"VideoSource\"/>",
+            &format!("<tt:SimpleItem Name=\"State\" Value=\"{motion}\"/>"),
+        ));
+    }
+    if last.map(|last| last.pir) != Some(state.pir) {
+        messages.push(message(
+            "tns1:Device/Trigger/DigitalInput",
+            "<tt:SimpleItem Name=\"InputToken\" Value=\"PIR\"/>",
+            &format!(
+                "<tt:SimpleItem Name=\"LogicalState\" Value=\"{}\"/>",
+                state.pir
+            ),
+        ));
+    }
+    for topic in AI_TOPICS {
+        let on = state.ai.contains(topic);
+        if last.map(|last| last.ai.contains(topic)) != Some(on) {
+            messages.push(message(
+                &format!("tns1:RuleEngine/MyRuleDetector/{topic}"),
+                "<tt:SimpleItem Name=\"Source\" Value=\"VideoSource\"/>",
+                &format!("<tt:SimpleItem Name=\"State\" Value=\"{on}\"/>"),
+            ));
+        }
+    }
+    messages.concat()
+}
+
+/// The topic set entry of one of the [`AI_TOPICS`]
+macro_rules! ai_topic_description {
+    ($topic:literal) => {
+        concat!(
+            "<",
+            $topic,
+            " wstop:topic=\"true\"><tt:MessageDescription IsProperty=\"true\">",
+            "<tt:Source><tt:SimpleItemDescription Name=\"Source\" Type=\"tt:ReferenceToken\"/></tt:Source>",
+            "<tt:Data><tt:SimpleItemDescription Name=\"State\" Type=\"xs:boolean\"/></tt:Data>",
+            "</tt:MessageDescription></",
+            $topic,
+            ">"
+        )
+    };
+}
+
+const EVENT_PROPERTIES: &str = concat!(
+    "<tev:GetEventPropertiesResponse>",
+    "<tev:TopicNamespaceLocation>http://www.onvif.org/onvif/ver10/topics/topicns.xml</tev:TopicNamespaceLocation>",
+    "<wsnt:FixedTopicSet>true</wsnt:FixedTopicSet>",
+    "<wstop:TopicSet>",
+    "<tns1:RuleEngine><CellMotionDetector><Motion wstop:topic=\"true\"><tt:MessageDescription IsProperty=\"true\">",
+    "<tt:Source>",
+    "<tt:SimpleItemDescription Name=\"VideoSourceConfigurationToken\" Type=\"tt:ReferenceToken\"/>",
+    "<tt:SimpleItemDescription Name=\"VideoAnalyticsConfigurationToken\" Type=\"tt:ReferenceToken\"/>",
+    "<tt:SimpleItemDescription Name=\"Rule\" Type=\"xs:string\"/>",
+    "</tt:Source>",
+    "<tt:Data><tt:SimpleItemDescription Name=\"IsMotion\" Type=\"xs:boolean\"/></tt:Data>",
+    "</tt:MessageDescription></Motion></CellMotionDetector></tns1:RuleEngine>",
+    "<tns1:VideoSource><MotionAlarm wstop:topic=\"true\"><tt:MessageDescription IsProperty=\"true\">",
+    "<tt:Source><tt:SimpleItemDescription Name=\"Source\" Type=\"tt:ReferenceToken\"/></tt:Source>",
+    "<tt:Data><tt:SimpleItemDescription Name=\"State\" Type=\"xs:boolean\"/></tt:Data>",
+    "</tt:MessageDescription></MotionAlarm></tns1:VideoSource>",
+    "<tns1:Device><Trigger><DigitalInput wstop:topic=\"true\"><tt:MessageDescription IsProperty=\"true\">",
+    "<tt:Source><tt:SimpleItemDescription Name=\"InputToken\" Type=\"tt:ReferenceToken\"/></tt:Source>",
+    "<tt:Data><tt:SimpleItemDescription Name=\"LogicalState\" Type=\"xs:boolean\"/></tt:Data>",
+    "</tt:MessageDescription></DigitalInput></Trigger></tns1:Device>",
+    "<tns1:RuleEngine><MyRuleDetector>",
+    ai_topic_description!("PeopleDetect"),
+    ai_topic_description!("VehicleDetect"),
+    ai_topic_description!("DogCatDetect"),
+    ai_topic_description!("FaceDetect"),
+    "</MyRuleDetector></tns1:RuleEngine>",
+    "</wstop:TopicSet>",
+    "<wsnt:TopicExpressionDialect>http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet</wsnt:TopicExpressionDialect>",
+    "<wsnt:TopicExpressionDialect>http://docs.oasis-open.org/wsn/t-1/TopicExpression/Concrete</wsnt:TopicExpressionDialect>",
+    "<tev:MessageContentFilterDialect>http://www.onvif.org/ver10/tev/messageContentFilter/ItemFilter</tev:MessageContentFilterDialect>",
+    "<tev:MessageContentSchemaLocation>http://www.onvif.org/onvif/ver10/schema/onvif.xsd</tev:MessageContentSchemaLocation>",
+    "</tev:GetEventPropertiesResponse>"
+);
+
+const SERVICE_CAPABILITIES: &str = concat!(
+    "<tev:GetServiceCapabilitiesResponse>",
+    "<tev:Capabilities WSSubscriptionPolicySupport=\"false\" WSPullPointSupport=\"true\" ",
+    "WSPausableSubscriptionManagerInterfaceSupport=\"false\" MaxNotificationProducers=\"0\" MaxPullPoints=\"10\"/>",
+    "</tev:GetServiceCapabilitiesResponse>"
+);
+
+/// The termination time requested as either a duration or an absolute time
+fn termination_time(request: &str, name: &str) -> SystemTime {
+    let now = SystemTime::now();
+    let requested = soap::param(request, name).and_then(|value| {
+        soap::duration(value)
+            .map(|duration| now + duration)
+            .or_else(|| humantime::parse_rfc3339_weak(value.trim_end_matches('Z')).ok())
+    });
+    requested.unwrap_or(now + DEFAULT_TERMINATION)
+}
+
+impl Subscriptions {
+    fn get(&self, id: u64) -> Option<Arc<AsyncMutex<Subscription>>> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let now = SystemTime::now();
+        subscriptions.retain(|_, pull_point| {
+            pull_point
+                .subscription
+                .try_lock()
+                .map(|sub| sub.termination > now)
+                .unwrap_or(true)
+        });
+        subscriptions
+            .get(&id)
+            .map(|pull_point| pull_point.subscription.clone())
+    }
+
+    /// Handle a request to the event service of a camera
+    pub(super) async fn event_service(
+        &self,
+        reactor: &NeoReactor,
+        camera: &str,
+        base_url: &str,
+        action: &str,
+        request: &str,
+    ) -> Result<String> {
+        Ok(match action {
+            "GetServiceCapabilities" => soap::envelope(SERVICE_CAPABILITIES),
+            "GetEventProperties" => soap::envelope(EVENT_PROPERTIES),
+            "CreatePullPointSubscription" => {
+                let motion = reactor.get(camera).await?.motion().await?;
+                let termination = termination_time(request, "InitialTerminationTime");
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                self.get(id); // Clear out the expired subscriptions
+                {
+                    let mut subscriptions = self.subscriptions.lock().unwrap();
+                    let count = subscriptions
+                        .values()
+                        .filter(|pull_point| pull_point.camera == camera)
+                        .count();
+                    if count >= MAX_PULL_POINTS {
+                        log::debug!("{camera}: ONVIF subscription refused, {count} are open");
+                        return Ok(soap::fault(
+                            "ter:MaxPullPointsReached",
+                            "There are no more pull points available",
+                        ));
+                    }
+                    subscriptions.insert(
+                        id,
+                        PullPoint {
+                            camera: camera.to_string(),
+                            subscription: Arc::new(AsyncMutex::new(Subscription {
+                                motion,
+                                last_sent: None,
+                                termination,
+                            })),
+                        },
+                    );
+                }
+                log::debug!("{camera}: ONVIF subscription {id} created");
+                soap::envelope(&format!(
+                    concat!(
+                        "<tev:CreatePullPointSubscriptionResponse>",
+                        "<tev:SubscriptionReference><wsa:Address>{}/subscription/{}</wsa:Address></tev:SubscriptionReference>",
+                        "<wsnt:CurrentTime>{}</wsnt:CurrentTime>",
+                        "<wsnt:TerminationTime>{}</wsnt:TerminationTime>",
+                        "</tev:CreatePullPointSubscriptionResponse>"
+                    ),
+                    escape(base_url),
+                    id,
+                    utc(SystemTime::now()),
+                    utc(termination)
+                ))
+            }
+            action => soap::not_supported(action),
+        })
+    }
+
+    /// Handle a request to a pull point subscription
+    ///
+    /// Returns `None` if the subscription does not exist
+    pub(super) async fn subscription(
+        &self,
+        id: u64,
+        action: &str,
+        request: &str,
+    ) -> Option<String> {
+        let subscription = self.get(id)?;
+        let mut guard = subscription.lock().await;
+        let subscription = &mut *guard;
+        Some(match action {
+            "PullMessages" => {
+                let wait = soap::param(request, "Timeout")
+                    .and_then(soap::duration)
+                    .unwrap_or(MAX_PULL_TIMEOUT)
+                    .min(MAX_PULL_TIMEOUT);
+                let limit = soap::param(request, "MessageLimit")
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(1)
+                    .max(1);
+                let deadline = Instant::now() + wait;
+
+                let mut messages = vec![];
+                if subscription.last_sent.is_none() {
+                    if let Some(state) = EventState::new(&subscription.motion.borrow_and_update()) {
+                        messages.push(notification(&state, None));
+                        subscription.last_sent = Some(state);
+                    }
+                }
+                while messages.len() < limit {
+                    if !messages.is_empty() && !subscription.motion.has_changed().unwrap_or(false) {
+                        break;
+                    }
+                    match timeout_at(deadline, subscription.motion.changed()).await {
+                        Ok(Ok(())) => {
+                            let state = EventState::new(&subscription.motion.borrow_and_update());
+                            if let Some(state) = state {
+                                if subscription.last_sent.as_ref() != Some(&state) {
+                                    messages.push(notification(
+                                        &state,
+                                        subscription.last_sent.as_ref(),
+                                    ));
+                                    subscription.last_sent = Some(state);
+                                }
+                            }
+                        }
+                        // Camera went away or the timeout expired
+                        Ok(Err(_)) | Err(_) => break,
+                    }
+                }
+
+                soap::envelope(&format!(
+                    concat!(
+                        "<tev:PullMessagesResponse>",
+                        "<tev:CurrentTime>{}</tev:CurrentTime>",
+                        "<tev:TerminationTime>{}</tev:TerminationTime>",
+                        "{}",
+                        "</tev:PullMessagesResponse>"
+                    ),
+                    utc(SystemTime::now()),
+                    utc(subscription.termination),
+                    messages.concat()
+                ))
+            }
+            "Renew" => {
+                subscription.termination = termination_time(request, "TerminationTime");
+                soap::envelope(&format!(
+                    concat!(
+                        "<wsnt:RenewResponse>",
+                        "<wsnt:TerminationTime>{}</wsnt:TerminationTime>",
+                        "<wsnt:CurrentTime>{}</wsnt:CurrentTime>",
+                        "</wsnt:RenewResponse>"
+                    ),
+                    utc(subscription.termination),
+                    utc(SystemTime::now())
+                ))
+            }
+            "SetSynchronizationPoint" => {
+                subscription.last_sent = None;
+                soap::envelope("<tev:SetSynchronizationPointResponse/>")
+            }
+            "Unsubscribe" => {
+                drop(guard);
+                self.subscriptions.lock().unwrap().remove(&id);
+                log::debug!("ONVIF subscription {id} removed");
+                soap::envelope("<wsnt:UnsubscribeResponse/>")
+            }
+            action => soap::not_supported(action),
+        })
+    }
+}
@@ -0,0 +1,493 @@
+//! The ONVIF media service
+//!
+//! Each stream in the camera's encode tables becomes a media profile whose
+//! video encoder configuration is read from the camera's [`Compression`]
+//! settings. `SetVideoEncoderConfiguration` writes the frame and bit rate back
+//! to the camera, snapped to the nearest values in the encode table.
+//!
+//! The audio is only in the profiles when the camera's `audio_codec` is G.711
+//! or AAC as ONVIF has no encoding for the default L16 or for Opus.
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::{Compression, EncodeTable, StreamCompression};
+use neolink_core::bc_protocol::StreamKind;
+
+use super::{
+    ptz,
+    soap::{self, escape},
+};
+use crate::common::NeoReactor;
+use crate::config::AudioCodec;
+
+/// The settings of a single stream
+struct StreamEncoding {
+    kind: StreamKind,
+    table: EncodeTable,
+    current: Option<StreamCompression>,
+}
+
+fn stream_kind(name: &str) -> Option<StreamKind> {
+    match name {
+        "mainStream" => Some(StreamKind::Main),
+        "subStream" => Some(StreamKind::Sub),
+        "externStream" | "thirdStream" => Some(StreamKind::Extern),
+        _ => None,
+    }
+}
+
+/// Some cameras give the default as an index into the table
+fn table_default(table: &[u32], default: u32) -> u32 {
+    if table.contains(&default) {
+        default
+    } else {
+        table.get(default as usize).copied().unwrap_or(default)
+    }
+}
+
+/// The table value closest to the requested one
+fn nearest(table: &[u32], value: u32) -> Option<u32> {
+    table
+        .iter()
+        .copied()
+        .min_by_key(|entry| entry.abs_diff(value))
+}
+
+impl StreamEncoding {
+    fn token(&self) -> String {
+        self.kind.to_string()
+    }
+
+    fn width(&self) -> u32 {
+        self.current
+            .as_ref()
+            .map(|c| c.width)
+            .unwrap_or(self.table.resolution.width)
+    }
+
+    fn height(&self) -> u32 {
+        self.current
+            .as_ref()
+            .map(|c| c.height)
+            .unwrap_or(self.table.resolution.height)
+    }
+
+    fn frame_rate(&self) -> u32 {
+        self.current.as_ref().map(|c| c.frame).unwrap_or_else(|| {
+            table_default(&self.table.framerate_table, self.table.default_framerate)
+        })
+    }
+
+    fn bit_rate(&self) -> u32 {
+        self.current
+            .as_ref()
+            .map(|c| c.bit_rate)
+            .unwrap_or_else(|| table_default(&self.table.bitrate_table, self.table.default_bitrate))
+    }
+
+    fn encoding(&self) -> &'static str {
+        match self
+            .current
+            .as_ref()
+            .and_then(|c| c.encoder_type.as_deref())
+        {
+            Some("h265") => "H265",
+            _ => "H264",
+        }
+    }
+
+    fn h264_profile(&self) -> &'static str {
+        match self
+            .current
+            .as_ref()
+            .and_then(|c| c.encoder_profile.as_deref())
+        {
+            Some("base") => "Baseline",
+            Some("main") => "Main",
+            _ => "High",
+        }
+    }
+
+    fn encoder_configuration(&self, element: &str) -> String {
+        let gov = self
+            .current
+            .as_ref()
+            .and_then(|c| c.gop)
+            .unwrap_or(2)
+            .saturating_mul(self.frame_rate())
+            .max(1);
+        format!(
+            concat!(
+                "<{element} token=\"{token}\">",
+                "<tt:Name>{token}</tt:Name><tt:UseCount>1</tt:UseCount>",
+                "<tt:Encoding>{encoding}</tt:Encoding>",
+                "<tt:Resolution><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:Resolution>",
+                "<tt:Quality>5</tt:Quality>",
+                "<tt:RateControl><tt:FrameRateLimit>{fps}</tt:FrameRateLimit>",
+                "<tt:EncodingInterval>1</tt:EncodingInterval><tt:BitrateLimit>{bitrate}</tt:BitrateLimit></tt:RateControl>",
+                "<tt:H264><tt:GovLength>{gov}</tt:GovLength><tt:H264Profile>{profile}</tt:H264Profile></tt:H264>",
+                "<tt:Multicast><tt:Address><tt:Type>IPv4</tt:Type><tt:IPv4Address>0.0.0.0</tt:IPv4Address></tt:Address>",
+                "<tt:Port>0</tt:Port><tt:TTL>0</tt:TTL><tt:AutoStart>false</tt:AutoStart></tt:Multicast>",
+                "<tt:SessionTimeout>PT60S</tt:SessionTimeout>",
+                "</{element}>"
+            ),
+            element = element,
+            token = self.token(),
+            encoding = self.encoding(),
+            width = self.width(),
+            height = self.height(),
+            fps = self.frame_rate(),
+            bitrate = self.bit_rate(),
+            gov = gov,
+            profile = self.h264_profile(),
+        )
+    }
+
+    fn profile(&self, count: usize, audio: AudioCodec) -> String {
+        let encoding = encoding(audio);
+        format!(
+            concat!(
+                "<trt:Profiles token=\"{token}\" fixed=\"true\"><tt:Name>{token}</tt:Name>",
+                "<tt:VideoSourceConfiguration token=\"VideoSource\"><tt:Name>VideoSource</tt:Name>",
+                "<tt:UseCount>{count}</tt:UseCount><tt:SourceToken>VideoSource</tt:SourceToken>",
+                "<tt:Bounds x=\"0\" y=\"0\" width=\"{width}\" height=\"{height}\"/></tt:VideoSourceConfiguration>",
+                "{audio_source}",
+                "{encoder}",
+                "{audio_encoder}",
+                "{ptz}",
+                "</trt:Profiles>"
+            ),
+            token = self.token(),
+            count = count,
+            width = self.width(),
+            height = self.height(),
+            audio_source = match encoding {
+                Some(_) => audio_source_configuration(count),
+                None => String::new(),
+            },
+            encoder = self.encoder_configuration("tt:VideoEncoderConfiguration"),
+            audio_encoder = match encoding {
+                Some(encoding) => {
+                    audio_encoder_configuration("tt:AudioEncoderConfiguration", encoding, count)
+                }
+                None => String::new(),
+            },
+            ptz = ptz::configuration("tt:PTZConfiguration"),
+        )
+    }
+
+    fn options(&self) -> String {
+        let range = |table: &[u32]| {
+            format!(
+                "<tt:Min>{}</tt:Min><tt:Max>{}</tt:Max>",
+                table.iter().min().copied().unwrap_or(0),
+                table.iter().max().copied().unwrap_or(0)
+            )
+        };
+        format!(
+            concat!(
+                "<trt:GetVideoEncoderConfigurationOptionsResponse><trt:Options>",
+                "<tt:QualityRange><tt:Min>0</tt:Min><tt:Max>10</tt:Max></tt:QualityRange>",
+                "<tt:H264><tt:ResolutionsAvailable><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:ResolutionsAvailable>",
+                "<tt:GovLengthRange><tt:Min>1</tt:Min><tt:Max>400</tt:Max></tt:GovLengthRange>",
+                "<tt:FrameRateRange>{fps}</tt:FrameRateRange>",
+                "<tt:EncodingIntervalRange><tt:Min>1</tt:Min><tt:Max>1</tt:Max></tt:EncodingIntervalRange>",
+                "<tt:H264ProfilesSupported>{profile}</tt:H264ProfilesSupported></tt:H264>",
+                "<tt:Extension><tt:H264><tt:ResolutionsAvailable><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:ResolutionsAvailable>",
+                "<tt:GovLengthRange><tt:Min>1</tt:Min><tt:Max>400</tt:Max></tt:GovLengthRange>",
+                "<tt:FrameRateRange>{fps}</tt:FrameRateRange>",
+                "<tt:EncodingIntervalRange><tt:Min>1</tt:Min><tt:Max>1</tt:Max></tt:EncodingIntervalRange>",
+                "<tt:H264ProfilesSupported>{profile}</tt:H264ProfilesSupported>",
+                "<tt:BitrateRange>{bitrate}</tt:BitrateRange></tt:H264></tt:Extension>",
+                "</trt:Options></trt:GetVideoEncoderConfigurationOptionsResponse>"
+            ),
+            width = self.width(),
+            height = self.height(),
+            fps = range(&self.table.framerate_table),
+            bitrate = range(&self.table.bitrate_table),
+            profile = self.h264_profile(),
+        )
+    }
+}
+
+fn audio_source_configuration(count: usize) -> String {
+    format!(
+        concat!(
+            "<tt:AudioSourceConfiguration token=\"AudioSource\"><tt:Name>AudioSource</tt:Name>",
+            "<tt:UseCount>{count}</tt:UseCount><tt:SourceToken>AudioSource</tt:SourceToken>",
+            "</tt:AudioSourceConfiguration>"
+        ),
+        count = count,
+    )
+}
+
+/// The ONVIF encoding, kbps and kHz of the audio that the rtsp server sends
+fn encoding(audio: AudioCodec) -> Option<(&'static str, u32, u32)> {
+    match audio {
+        AudioCodec::Pcmu | AudioCodec::Pcma => Some(("G711", 64, 8)),
+        AudioCodec::Aac => Some(("AAC", 64, 16)),
+        AudioCodec::L16 | AudioCodec::Opus => None,
+    }
+}
+
+fn audio_encoder_configuration(
+    element: &str,
+    (encoding, bitrate, rate): (&str, u32, u32),
+    count: usize,
+) -> String {
+    format!(
+        concat!(
+            "<{element} token=\"Audio\">",
+            "<tt:Name>Audio</tt:Name><tt:UseCount>{count}</tt:UseCount>",
+            "<tt:Encoding>{encoding}</tt:Encoding><tt:Bitrate>{bitrate}</tt:Bitrate><tt:SampleRate>{rate}</tt:SampleRate>",
+            "<tt:Multicast><tt:Address><tt:Type>IPv4</tt:Type><tt:IPv4Address>0.0.0.0</tt:IPv4Address></tt:Address>",
+            "<tt:Port>0</tt:Port><tt:TTL>0</tt:TTL><tt:AutoStart>false</tt:AutoStart></tt:Multicast>",
+            "<tt:SessionTimeout>PT60S</tt:SessionTimeout>",
+            "</{element}>"
+        ),
+        element = element,
+        count = count,
+        encoding = encoding,
+        bitrate = bitrate,
+        rate = rate,
+    )
+}
+
+/// The `audio_codec` of the camera in the config
+async fn audio_codec(reactor: &NeoReactor, camera: &str) -> Result<AudioCodec> {
+    Ok(reactor
+        .config()
+        .await?
+        .borrow()
+        .cameras
+        .iter()
+        .find(|cam| cam.name == camera)
+        .map(|cam| cam.audio_codec)
+        .unwrap_or(AudioCodec::L16))
+}
+
+/// Read the encode tables and the current settings of the camera
+async fn streams(
+    reactor: &NeoReactor,
+    camera: &str,
+) -> Result<(Vec<StreamEncoding>, Option<Compression>)> {
+    let (stream_info, compression) = reactor
+        .get(camera)
+        .await?
+        .run_task(|cam| {
+            Box::pin(async move {
+                let stream_info = cam
+                    .get_stream_info()
+                    .await
+                    .context("Unable to get the camera's encode tables")?;
+                let compression = match cam.get_compression().await {
+                    Ok(compression) => Some(compression),
+                    Err(e) => {
+                        log::debug!("Could not get the camera's compression settings: {e:?}");
+                        None
+                    }
+                };
+                Ok((stream_info, compression))
+            })
+        })
+        .await?;
+
+    let streams = stream_info
+        .stream_infos
+        .iter()
+        .flat_map(|info| info.encode_tables.iter())
+        .filter_map(|table| {
+            let kind = stream_kind(&table.name)?;
+            Some(StreamEncoding {
+                kind,
+                table: table.clone(),
+                current: compression.as_ref().and_then(|c| c.stream(kind)).cloned(),
+            })
+        })
+        .collect();
+    Ok((streams, compression))
+}
+
+fn find<'a>(streams: &'a [StreamEncoding], token: Option<&str>) -> Result<&'a StreamEncoding> {
+    let token = token.ok_or_else(|| anyhow!("No token in request"))?;
+    streams
+        .iter()
+        .find(|stream| stream.token() == token)
+        .ok_or_else(|| anyhow!("Unknown token {token:?}"))
+}
+
+/// Handle a request to the media service of a camera
+///
+/// `rtsp_base` is the `rtsp://host:port` that `neolink rtsp` can be reached at
+pub(super) async fn media_service(
+    reactor: &NeoReactor,
+    camera: &str,
+    rtsp_base: &str,
+    action: &str,
+    request: &str,
+) -> Result<String> {
+    Ok(match action {
+        "GetServiceCapabilities" => soap::envelope(concat!(
+            "<trt:GetServiceCapabilitiesResponse><trt:Capabilities SnapshotUri=\"false\" Rotation=\"false\">",
+            "<trt:ProfileCapabilities MaximumNumberOfProfiles=\"3\"/>",
+            "<trt:StreamingCapabilities RTPMulticast=\"false\" RTP_TCP=\"true\" RTP_RTSP_TCP=\"true\"/>",
+            "</trt:Capabilities></trt:GetServiceCapabilitiesResponse>"
+        )),
+        "GetProfiles" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let audio = audio_codec(reactor, camera).await?;
+            soap::envelope(&format!(
+                "<trt:GetProfilesResponse>{}</trt:GetProfilesResponse>",
+                streams
+                    .iter()
+                    .map(|stream| stream.profile(streams.len(), audio))
+                    .collect::<String>()
+            ))
+        }
+        "GetProfile" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let stream = find(&streams, soap::param(request, "ProfileToken"))?;
+            let audio = audio_codec(reactor, camera).await?;
+            soap::envelope(&format!(
+                "<trt:GetProfileResponse>{}</trt:GetProfileResponse>",
+                stream
+                    .profile(streams.len(), audio)
+                    .replace("trt:Profiles", "trt:Profile")
+            ))
+        }
+        "GetVideoSources" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let main = streams
+                .iter()
+                .find(|s| s.kind == StreamKind::Main)
+                .or(streams.first())
+                .ok_or_else(|| anyhow!("Camera has no streams"))?;
+            soap::envelope(&format!(
+                concat!(
+                    "<trt:GetVideoSourcesResponse><trt:VideoSources token=\"VideoSource\">",
+                    "<tt:Framerate>{}</tt:Framerate>",
+                    "<tt:Resolution><tt:Width>{}</tt:Width><tt:Height>{}</tt:Height></tt:Resolution>",
+                    "</trt:VideoSources></trt:GetVideoSourcesResponse>"
+                ),
+                main.frame_rate(),
+                main.width(),
+                main.height()
+            ))
+        }
+        "GetAudioSources" => soap::envelope(
+            match encoding(audio_codec(reactor, camera).await?) {
+                None => "<trt:GetAudioSourcesResponse/>",
+                Some(_) => concat!(
+                    "<trt:GetAudioSourcesResponse><trt:AudioSources token=\"AudioSource\">",
+                    "<tt:Channels>1</tt:Channels></trt:AudioSources></trt:GetAudioSourcesResponse>"
+                ),
+            },
+        ),
+        "GetAudioEncoderConfigurations" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            soap::envelope(&format!(
+                "<trt:GetAudioEncoderConfigurationsResponse>{}</trt:GetAudioEncoderConfigurationsResponse>",
+                match encoding(audio_codec(reactor, camera).await?) {
+                    None => String::new(),
+                    Some(encoding) => {
+                        audio_encoder_configuration("trt:Configurations", encoding, streams.len())
+                    }
+                }
+            ))
+        }
+        "GetAudioEncoderConfiguration" => match encoding(audio_codec(reactor, camera).await?) {
+            None => return Err(anyhow!("The audio has no ONVIF encoding")),
+            Some(encoding) => {
+                let (streams, _) = streams(reactor, camera).await?;
+                soap::envelope(&format!(
+                    "<trt:GetAudioEncoderConfigurationResponse>{}</trt:GetAudioEncoderConfigurationResponse>",
+                    audio_encoder_configuration("trt:Configuration", encoding, streams.len())
+                ))
+            }
+        },
+        "GetVideoEncoderConfigurations" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            soap::envelope(&format!(
+                "<trt:GetVideoEncoderConfigurationsResponse>{}</trt:GetVideoEncoderConfigurationsResponse>",
+                streams
+                    .iter()
+                    .map(|stream| stream.encoder_configuration("trt:Configurations"))
+                    .collect::<String>()
+            ))
+        }
+        "GetVideoEncoderConfiguration" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let stream = find(&streams, soap::param(request, "ConfigurationToken"))?;
+            soap::envelope(&format!(
+                "<trt:GetVideoEncoderConfigurationResponse>{}</trt:GetVideoEncoderConfigurationResponse>",
+                stream.encoder_configuration("trt:Configuration")
+            ))
+        }
+        "GetVideoEncoderConfigurationOptions" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let token = soap::param(request, "ConfigurationToken")
+                .or_else(|| soap::param(request, "ProfileToken"));
+            let stream = match token {
+                Some(_) => find(&streams, token)?,
+                None => streams
+                    .first()
+                    .ok_or_else(|| anyhow!("Camera has no streams"))?,
+            };
+            soap::envelope(&stream.options())
+        }
+        "SetVideoEncoderConfiguration" => {
+            let (streams, compression) = streams(reactor, camera).await?;
+            let stream = find(&streams, soap::attribute(request, "Configuration", "token"))?;
+            let mut compression =
+                compression.ok_or_else(|| anyhow!("Camera did not report its compression"))?;
+            let settings = compression
+                .stream_mut(stream.kind)
+                .ok_or_else(|| anyhow!("Camera has no compression for {}", stream.kind))?;
+
+            if let Some(fps) = soap::param(request, "FrameRateLimit").and_then(|v| v.parse().ok()) {
+                settings.frame = nearest(&stream.table.framerate_table, fps).unwrap_or(settings.frame);
+            }
+            if let Some(bitrate) = soap::param(request, "BitrateLimit").and_then(|v| v.parse().ok()) {
+                settings.bit_rate =
+                    nearest(&stream.table.bitrate_table, bitrate).unwrap_or(settings.bit_rate);
+            }
+            log::info!(
+                "{camera}: Setting {} to {}fps {}kbps",
+                stream.kind,
+                settings.frame,
+                settings.bit_rate
+            );
+
+            reactor
+                .get(camera)
+                .await?
+                .run_task(|cam| {
+                    let compression = compression.clone();
+                    Box::pin(async move {
+                        cam.set_compression(compression)
+                            .await
+                            .context("Unable to set the camera's compression")
+                    })
+                })
+                .await?;
+            soap::envelope("<trt:SetVideoEncoderConfigurationResponse/>")
+        }
+        "GetStreamUri" => {
+            let (streams, _) = streams(reactor, camera).await?;
+            let stream = find(&streams, soap::param(request, "ProfileToken"))?;
+            let path = match stream.kind {
+                StreamKind::Main => "main",
+                StreamKind::Sub => "sub",
+                StreamKind::Extern => "extern",
+            };
+            soap::envelope(&format!(
+                concat!(
+                    "<trt:GetStreamUriResponse><trt:MediaUri>",
+                    "<tt:Uri>{}</tt:Uri><tt:InvalidAfterConnect>false</tt:InvalidAfterConnect>",
+                    "<tt:InvalidAfterReboot>false</tt:InvalidAfterReboot><tt:Timeout>PT0S</tt:Timeout>",
+                    "</trt:MediaUri></trt:GetStreamUriResponse>"
+                ),
+                escape(&format!("{rtsp_base}/{camera}/{path}"))
+            ))
+        }
+        action => soap::not_supported(action),
+    })
+}
@@ -0,0 +1,298 @@
+///
+/// # Neolink ONVIF Events
+///
+/// This module serves the motion events of the cameras as an ONVIF event
+/// service so that NVRs that only understand ONVIF events (e.g. Synology)
+/// can record on motion
+///
+/// Each camera is its own ONVIF device at
+/// `http://{bind}:{port}/onvif/{CameraName}/device_service`. Add this address
+/// to the NVR along with the rtsp stream from `neolink rtsp`.
+///
+/// Only the pull point subscriptions of the event service are supported
+/// along with enough of the device service for an NVR to find them.
+///
+/// The media service lists a profile for each of the camera's streams with
+/// the rtsp address of `neolink rtsp`. The frame and bit rates of the streams
+/// can be changed with `SetVideoEncoderConfiguration`.
+///
+/// The PTZ service can move the camera continuously and go to its presets.
+///
+/// The cameras answer WS-Discovery probes so that NVRs can find them without
+/// adding each address by hand, unless `discovery` is turned off.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink onvif-events --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [onvif]
+///   bind = "0.0.0.0"
+///   port = 8000
+///   discovery = true
+/// ```
+///
+use anyhow::{Context, Result};
+use hyper::{
+    body::to_bytes,
+    header::{CONTENT_TYPE, HOST},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+mod cmdline;
+mod discovery;
+mod events;
+mod media;
+mod ptz;
+mod soap;
+
+use crate::common::NeoReactor;
+use events::Subscriptions;
+use soap::escape;
+
+pub(crate) use cmdline::Opt;
+
+struct OnvifState {
+    reactor: NeoReactor,
+    subscriptions: Subscriptions,
+    /// Port of the rtsp server for the stream uris
+    rtsp_port: u16,
+    /// Namespace prefix of all paths, see [`crate::config::Config::path_prefix`]
+    prefix: String,
+}
+
+/// Entry point for the onvif-events subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    let addr: SocketAddr = format!("{}:{}", config.onvif.bind_addr, config.onvif.bind_port)
+        .parse()
+        .context("Invalid ONVIF bind address")?;
+
+    let discovery_reactor = reactor.clone();
+    let state = Arc::new(OnvifState {
+        reactor,
+        subscriptions: Default::default(),
+        rtsp_port: config.bind_port,
+        prefix: config.path_prefix(),
+    });
+
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(req, state).await) }
+            }))
+        }
+    });
+
+    log::info!(
+        "ONVIF events listening on http://{addr}{}/onvif/{{CameraName}}/device_service",
+        config.path_prefix()
+    );
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind ONVIF server to {addr}"))?
+        .serve(make_svc);
+    tokio::select! {
+        v = server => v?,
+        _ = async {
+            let res = discovery::discovery(
+                discovery_reactor,
+                addr.ip(),
+                addr.port(),
+                config.path_prefix(),
+            )
+            .await;
+            // The cameras can still be added by hand
+            log::warn!("ONVIF discovery stopped: {res:?}");
+            futures::future::pending::<()>().await
+        }, if config.onvif.discovery => {}
+    }
+    Ok(())
+}
+
+fn soap_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/soap+xml; charset=utf-8")
+        .body(Body::from(body))
+        .expect("Response should be valid")
+}
+
+async fn handle(req: Request<Body>, state: Arc<OnvifState>) -> Response<Body> {
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost")
+        .to_string();
+    let path = match req.uri().path().strip_prefix(&state.prefix) {
+        Some(path) => path.to_string(),
+        None => return soap_response(StatusCode::NOT_FOUND, String::new()),
+    };
+    let path = path
+        .trim_matches('/')
+        .split('/')
+        .map(|part| part.to_string())
+        .collect::<Vec<_>>();
+    let request = match to_bytes(req.into_body()).await {
+        Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+        Err(e) => {
+            log::debug!("Failed to read ONVIF request: {e:?}");
+            return soap_response(StatusCode::BAD_REQUEST, String::new());
+        }
+    };
+    let action = match soap::action(&request) {
+        Some(action) => action,
+        None => return soap_response(StatusCode::BAD_REQUEST, String::new()),
+    };
+
+    let path = path.iter().map(|part| part.as_str()).collect::<Vec<_>>();
+    let (camera, service) = match path[..] {
+        ["onvif", camera, ref service @ ..] => (camera, service),
+        _ => return soap_response(StatusCode::NOT_FOUND, String::new()),
+    };
+    let known = state
+        .reactor
+        .config()
+        .await
+        .map(|config| {
+            config
+                .borrow()
+                .cameras
+                .iter()
+                .any(|cam| cam.enabled && cam.name == camera)
+        })
+        .unwrap_or(false);
+    if !known {
+        return soap_response(StatusCode::NOT_FOUND, String::new());
+    }
+
+    let base_url = format!("http://{host}{}/onvif/{camera}", state.prefix);
+    let rtsp_base = format!(
+        "rtsp://{}:{}{}",
+        host.rsplit_once(':').map(|(host, _)| host).unwrap_or(&host),
+        state.rtsp_port,
+        state.prefix
+    );
+    log::trace!("{camera}: ONVIF {action} on {service:?}");
+    let reply = match service {
+        ["device_service"] => Ok(device_service(camera, &base_url, action)),
+        ["media_service"] => {
+            media::media_service(&state.reactor, camera, &rtsp_base, action, &request).await
+        }
+        ["ptz_service"] => ptz::ptz_service(&state.reactor, camera, action, &request).await,
+        ["event_service"] => {
+            state
+                .subscriptions
+                .event_service(&state.reactor, camera, &base_url, action, &request)
+                .await
+        }
+        ["subscription", id] => match id.parse::<u64>() {
+            Ok(id) => match state.subscriptions.subscription(id, action, &request).await {
+                Some(reply) => Ok(reply),
+                None => return soap_response(StatusCode::NOT_FOUND, String::new()),
+            },
+            Err(_) => return soap_response(StatusCode::NOT_FOUND, String::new()),
+        },
+        _ => return soap_response(StatusCode::NOT_FOUND, String::new()),
+    };
+
+    match reply {
+        Ok(body) if body.contains("<env:Fault>") => {
+            soap_response(StatusCode::INTERNAL_SERVER_ERROR, body)
+        }
+        Ok(body) => soap_response(StatusCode::OK, body),
+        Err(e) => {
+            log::warn!("{camera}: ONVIF {action} failed: {e:?}");
+            soap_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                soap::not_supported(action),
+            )
+        }
+    }
+}
+
+/// The parts of the device service needed to find the other services
+fn device_service(camera: &str, base_url: &str, action: &str) -> String {
+    let device_url = escape(&format!("{base_url}/device_service"));
+    let events_url = escape(&format!("{base_url}/event_service"));
+    let media_url = escape(&format!("{base_url}/media_service"));
+    let ptz_url = escape(&format!("{base_url}/ptz_service"));
+    match action {
+        "GetCapabilities" => soap::envelope(&format!(
+            concat!(
+                "<tds:GetCapabilitiesResponse><tds:Capabilities>",
+                "<tt:Device><tt:XAddr>{}</tt:XAddr></tt:Device>",
+                "<tt:Events><tt:XAddr>{}</tt:XAddr>",
+                "<tt:WSSubscriptionPolicySupport>false</tt:WSSubscriptionPolicySupport>",
+                "<tt:WSPullPointSupport>true</tt:WSPullPointSupport>",
+                "<tt:WSPausableSubscriptionManagerInterfaceSupport>false</tt:WSPausableSubscriptionManagerInterfaceSupport>",
+                "</tt:Events>",
+                "<tt:Media><tt:XAddr>{}</tt:XAddr><tt:StreamingCapabilities>",
+                "<tt:RTPMulticast>false</tt:RTPMulticast><tt:RTP_TCP>true</tt:RTP_TCP><tt:RTP_RTSP_TCP>true</tt:RTP_RTSP_TCP>",
+                "</tt:StreamingCapabilities></tt:Media>",
+                "<tt:PTZ><tt:XAddr>{}</tt:XAddr></tt:PTZ>",
+                "</tds:Capabilities></tds:GetCapabilitiesResponse>"
+            ),
+            device_url, events_url, media_url, ptz_url
+        )),
+        "GetServices" => soap::envelope(&format!(
+            concat!(
+                "<tds:GetServicesResponse>",
+                "<tds:Service><tds:Namespace>http://www.onvif.org/ver10/device/wsdl</tds:Namespace>",
+                "<tds:XAddr>{}</tds:XAddr><tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version></tds:Service>",
+                "<tds:Service><tds:Namespace>http://www.onvif.org/ver10/events/wsdl</tds:Namespace>",
+                "<tds:XAddr>{}</tds:XAddr><tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version></tds:Service>",
+                "<tds:Service><tds:Namespace>http://www.onvif.org/ver10/media/wsdl</tds:Namespace>",
+                "<tds:XAddr>{}</tds:XAddr><tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version></tds:Service>",
+                "<tds:Service><tds:Namespace>http://www.onvif.org/ver20/ptz/wsdl</tds:Namespace>",
+                "<tds:XAddr>{}</tds:XAddr><tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version></tds:Service>",
+                "</tds:GetServicesResponse>"
+            ),
+            device_url, events_url, media_url, ptz_url
+        )),
+        "GetDeviceInformation" => soap::envelope(&format!(
+            concat!(
+                "<tds:GetDeviceInformationResponse>",
+                "<tds:Manufacturer>Neolink</tds:Manufacturer>",
+                "<tds:Model>{}</tds:Model>",
+                "<tds:FirmwareVersion>{}</tds:FirmwareVersion>",
+                "<tds:SerialNumber>{}</tds:SerialNumber>",
+                "<tds:HardwareId>neolink</tds:HardwareId>",
+                "</tds:GetDeviceInformationResponse>"
+            ),
+            escape(camera),
+            escape(env!("NEOLINK_VERSION").trim()),
+            escape(camera)
+        )),
+        "GetSystemDateAndTime" => {
+            let now = time::OffsetDateTime::now_utc();
+            soap::envelope(&format!(
+                concat!(
+                    "<tds:GetSystemDateAndTimeResponse><tds:SystemDateAndTime>",
+                    "<tt:DateTimeType>NTP</tt:DateTimeType><tt:DaylightSavings>false</tt:DaylightSavings>",
+                    "<tt:TimeZone><tt:TZ>UTC0</tt:TZ></tt:TimeZone>",
+                    "<tt:UTCDateTime><tt:Time><tt:Hour>{}</tt:Hour><tt:Minute>{}</tt:Minute><tt:Second>{}</tt:Second></tt:Time>",
+                    "<tt:Date><tt:Year>{}</tt:Year><tt:Month>{}</tt:Month><tt:Day>{}</tt:Day></tt:Date></tt:UTCDateTime>",
+                    "</tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>"
+                ),
+                now.hour(),
+                now.minute(),
+                now.second(),
+                now.year(),
+                u8::from(now.month()),
+                now.day()
+            ))
+        }
+        action => soap::not_supported(action),
+    }
+}
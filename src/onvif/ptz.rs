@@ -0,0 +1,172 @@
+//! The ONVIF PTZ service
+//!
+//! Only continuous pan and tilt and the camera's presets are supported. The
+//! moves go through the camera's ptz queue like those from mqtt and keep going
+//! until `Stop`, the `Timeout` of `ContinuousMove` is ignored.
+//!
+//! Every profile has the PTZ configuration as there is no cheap way to ask
+//! the camera if it can move, fixed cameras reply with a fault.
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc_protocol::Direction;
+
+use super::soap::{self, escape};
+use crate::common::NeoReactor;
+
+/// The speed sent to the camera for a velocity of 1
+const MAX_SPEED: f32 = 64.0;
+
+/// Token of the only PTZ node and configuration
+const PTZ_TOKEN: &str = "PTZ";
+
+const PAN_TILT_SPACE: &str = "http://www.onvif.org/ver10/tptz/PanTiltSpaces/VelocityGenericSpace";
+
+/// The PTZ configuration as found in the profiles
+pub(super) fn configuration(element: &str) -> String {
+    format!(
+        concat!(
+            "<{element} token=\"{token}\">",
+            "<tt:Name>{token}</tt:Name><tt:UseCount>1</tt:UseCount>",
+            "<tt:NodeToken>{token}</tt:NodeToken>",
+            "<tt:DefaultContinuousPanTiltVelocitySpace>{space}</tt:DefaultContinuousPanTiltVelocitySpace>",
+            "<tt:DefaultPTZTimeout>PT5S</tt:DefaultPTZTimeout>",
+            "</{element}>"
+        ),
+        element = element,
+        token = PTZ_TOKEN,
+        space = PAN_TILT_SPACE,
+    )
+}
+
+fn node(element: &str) -> String {
+    format!(
+        concat!(
+            "<{element} token=\"{token}\" FixedHomePosition=\"false\">",
+            "<tt:Name>{token}</tt:Name>",
+            "<tt:SupportedPTZSpaces><tt:ContinuousPanTiltVelocitySpace>",
+            "<tt:URI>{space}</tt:URI>",
+            "<tt:XRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:XRange>",
+            "<tt:YRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:YRange>",
+            "</tt:ContinuousPanTiltVelocitySpace></tt:SupportedPTZSpaces>",
+            "<tt:MaximumNumberOfPresets>64</tt:MaximumNumberOfPresets>",
+            "<tt:HomeSupported>false</tt:HomeSupported>",
+            "</{element}>"
+        ),
+        element = element,
+        token = PTZ_TOKEN,
+        space = PAN_TILT_SPACE,
+    )
+}
+
+/// The direction and speed for a velocity, the axis with the larger velocity
+/// wins as the camera only moves along one at a time
+fn velocity_to_move(x: f32, y: f32) -> (Direction, f32) {
+    let (value, direction) = match (x.abs() >= y.abs(), x < 0.0, y < 0.0) {
+        (true, true, _) => (x, Direction::Left),
+        (true, false, _) => (x, Direction::Right),
+        (false, _, true) => (y, Direction::Down),
+        (false, _, false) => (y, Direction::Up),
+    };
+    match value.abs().min(1.0) {
+        value if value > 0.0 => (direction, (value * MAX_SPEED).round().max(1.0)),
+        _ => (Direction::Stop, 0.0),
+    }
+}
+
+pub(super) async fn ptz_service(
+    reactor: &NeoReactor,
+    camera: &str,
+    action: &str,
+    request: &str,
+) -> Result<String> {
+    Ok(match action {
+        "GetServiceCapabilities" => soap::envelope(concat!(
+            "<tptz:GetServiceCapabilitiesResponse>",
+            "<tptz:Capabilities EFlip=\"false\" Reverse=\"false\" MoveStatus=\"false\" StatusPosition=\"false\"/>",
+            "</tptz:GetServiceCapabilitiesResponse>"
+        )),
+        "GetNodes" => soap::envelope(&format!(
+            "<tptz:GetNodesResponse>{}</tptz:GetNodesResponse>",
+            node("tptz:PTZNode")
+        )),
+        "GetNode" => soap::envelope(&format!(
+            "<tptz:GetNodeResponse>{}</tptz:GetNodeResponse>",
+            node("tptz:PTZNode")
+        )),
+        "GetConfigurations" => soap::envelope(&format!(
+            "<tptz:GetConfigurationsResponse>{}</tptz:GetConfigurationsResponse>",
+            configuration("tptz:PTZConfiguration")
+        )),
+        "GetConfiguration" => soap::envelope(&format!(
+            "<tptz:GetConfigurationResponse>{}</tptz:GetConfigurationResponse>",
+            configuration("tptz:PTZConfiguration")
+        )),
+        "GetConfigurationOptions" => soap::envelope(&format!(
+            concat!(
+                "<tptz:GetConfigurationOptionsResponse><tptz:PTZConfigurationOptions>",
+                "<tt:Spaces><tt:ContinuousPanTiltVelocitySpace>",
+                "<tt:URI>{}</tt:URI>",
+                "<tt:XRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:XRange>",
+                "<tt:YRange><tt:Min>-1</tt:Min><tt:Max>1</tt:Max></tt:YRange>",
+                "</tt:ContinuousPanTiltVelocitySpace></tt:Spaces>",
+                "<tt:PTZTimeout><tt:Min>PT1S</tt:Min><tt:Max>PT60S</tt:Max></tt:PTZTimeout>",
+                "</tptz:PTZConfigurationOptions></tptz:GetConfigurationOptionsResponse>"
+            ),
+            PAN_TILT_SPACE
+        )),
+        "ContinuousMove" => {
+            let velocity = |axis: &str| {
+                soap::attribute(request, "PanTilt", axis)
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .unwrap_or(0.0)
+            };
+            let (direction, speed) = velocity_to_move(velocity("x"), velocity("y"));
+            reactor.get(camera).await?.ptz(direction, speed).await?;
+            soap::envelope("<tptz:ContinuousMoveResponse/>")
+        }
+        "Stop" => {
+            reactor.get(camera).await?.ptz(Direction::Stop, 0.0).await?;
+            soap::envelope("<tptz:StopResponse/>")
+        }
+        "GetPresets" => {
+            let presets = reactor
+                .get(camera)
+                .await?
+                .run_task(|cam| Box::pin(async move { Ok(cam.get_ptz_preset().await?) }))
+                .await?;
+            let presets = presets
+                .preset_list
+                .preset
+                .iter()
+                .map(|preset| {
+                    let name = preset
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("Preset {}", preset.id));
+                    format!(
+                        "<tptz:Preset token=\"{}\"><tt:Name>{}</tt:Name></tptz:Preset>",
+                        preset.id,
+                        escape(&name)
+                    )
+                })
+                .collect::<String>();
+            soap::envelope(&format!(
+                "<tptz:GetPresetsResponse>{presets}</tptz:GetPresetsResponse>"
+            ))
+        }
+        "GotoPreset" => {
+            let preset_id = soap::param(request, "PresetToken")
+                .ok_or_else(|| anyhow!("No PresetToken"))?
+                .parse::<u8>()
+                .context("Invalid PresetToken")?;
+            reactor
+                .get(camera)
+                .await?
+                .run_task(|cam| {
+                    Box::pin(async move { Ok(cam.moveto_ptz_preset(preset_id).await?) })
+                })
+                .await?;
+            soap::envelope("<tptz:GotoPresetResponse/>")
+        }
+        action => soap::not_supported(action),
+    })
+}
@@ -0,0 +1,106 @@
+//! Just enough SOAP to answer ONVIF requests
+//!
+//! The requests are not fully parsed, the action is taken from the first
+//! element in the body and the few parameters we need are found by name.
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::time::Duration;
+
+lazy_static! {
+    static ref RE_ACTION: Regex =
+        Regex::new(r"<(?:[\w-]+:)?Body[^>]*>\s*<(?:[\w-]+:)?(\w+)").unwrap();
+    static ref RE_DURATION: Regex =
+        Regex::new(r"^P(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+(?:\.\d+)?)S)?)?$").unwrap();
+}
+
+const NAMESPACES: &str = concat!(
+    r#"xmlns:env="http://www.w3.org/2003/05/soap-envelope" "#,
+    r#"xmlns:wsa="http://www.w3.org/2005/08/addressing" "#,
+    r#"xmlns:tt="http://www.onvif.org/ver10/schema" "#,
+    r#"xmlns:tds="http://www.onvif.org/ver10/device/wsdl" "#,
+    r#"xmlns:tev="http://www.onvif.org/ver10/events/wsdl" "#,
+    r#"xmlns:trt="http://www.onvif.org/ver10/media/wsdl" "#,
+    r#"xmlns:tptz="http://www.onvif.org/ver20/ptz/wsdl" "#,
+    r#"xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2" "#,
+    r#"xmlns:wstop="http://docs.oasis-open.org/wsn/t-1" "#,
+    r#"xmlns:tns1="http://www.onvif.org/ver10/topics" "#,
+    r#"xmlns:ter="http://www.onvif.org/ver10/error" "#,
+    r#"xmlns:xs="http://www.w3.org/2001/XMLSchema""#,
+);
+
+/// The name of the requested action e.g. `PullMessages`
+pub(super) fn action(request: &str) -> Option<&str> {
+    RE_ACTION
+        .captures(request)
+        .and_then(|caps| caps.get(1))
+        .map(|action| action.as_str())
+}
+
+/// The text of the first element with the given local name
+pub(super) fn param<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    let re = Regex::new(&format!(r"<(?:[\w-]+:)?{name}(?:\s[^>]*)?>([^<]*)<")).ok()?;
+    re.captures(request)
+        .and_then(|caps| caps.get(1))
+        .map(|value| value.as_str().trim())
+}
+
+/// The value of an attribute of the first element with the given local name
+pub(super) fn attribute<'a>(request: &'a str, name: &str, attribute: &str) -> Option<&'a str> {
+    let re = Regex::new(&format!(
+        r#"<(?:[\w-]+:)?{name}\s[^>]*\b{attribute}="([^"]*)""#
+    ))
+    .ok()?;
+    re.captures(request)
+        .and_then(|caps| caps.get(1))
+        .map(|value| value.as_str())
+}
+
+/// Parse an xml duration such as `PT10S`
+pub(super) fn duration(value: &str) -> Option<Duration> {
+    let caps = RE_DURATION.captures(value.trim())?;
+    let num = |i: usize| {
+        caps.get(i)
+            .map(|v| v.as_str().parse::<f64>().unwrap_or(0.0))
+            .unwrap_or(0.0)
+    };
+    Some(Duration::from_secs_f64(
+        num(1) * 86400.0 + num(2) * 3600.0 + num(3) * 60.0 + num(4),
+    ))
+}
+
+/// Wrap the body in a SOAP envelope
+pub(super) fn envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><env:Envelope {NAMESPACES}><env:Body>{body}</env:Body></env:Envelope>"#
+    )
+}
+
+/// A SOAP fault for an action we do not support
+pub(super) fn not_supported(action: &str) -> String {
+    fault(
+        "ter:ActionNotSupported",
+        &format!("{} is not supported", action),
+    )
+}
+
+/// A SOAP fault of the receiver with the ONVIF `subcode`
+pub(super) fn fault(subcode: &str, reason: &str) -> String {
+    envelope(&format!(
+        concat!(
+            "<env:Fault><env:Code><env:Value>env:Receiver</env:Value>",
+            "<env:Subcode><env:Value>{}</env:Value></env:Subcode></env:Code>",
+            "<env:Reason><env:Text xml:lang=\"en\">{}</env:Text></env:Reason>",
+            "</env:Fault>"
+        ),
+        subcode,
+        escape(reason)
+    ))
+}
+
+/// Escape text for use in xml
+pub(super) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,29 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The osd command will control the name and time overlays of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Whether to show the camera name on the video
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub name: Option<bool>,
+    /// Whether to show the date and time on the video
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub time: Option<bool>,
+    /// Change the name that the camera shows
+    #[arg(long)]
+    pub display_name: Option<String>,
+}
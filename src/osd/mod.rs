@@ -0,0 +1,67 @@
+///
+/// # Neolink OSD
+///
+/// This module handles the overlays that the camera draws on its video, the
+/// camera name and the date and time
+///
+/// Without any changes it prints the camera's OsdChannelName and OsdDatetime
+/// xml
+///
+/// # Usage
+///
+/// ```bash
+/// # To hide the date and time
+/// neolink osd --config=config.toml CameraName --time off
+/// # To show and rename the camera name
+/// neolink osd --config=config.toml CameraName --name on --display-name "Front Door"
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the osd subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if opt.name.is_none() && opt.time.is_none() && opt.display_name.is_none() {
+        let osd = camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.get_osd().await.context(tr!("osd.get_failed")) })
+            })
+            .await?;
+        for xml in [
+            yaserde::ser::serialize_with_writer(&osd.channel_name, vec![], &Default::default()),
+            yaserde::ser::serialize_with_writer(&osd.datetime, vec![], &Default::default()),
+        ] {
+            let osd_ser =
+                String::from_utf8(xml.expect("Should Ser the struct")).expect("Should be UTF8");
+            println!("{}", osd_ser);
+        }
+        return Ok(());
+    }
+
+    let (show_name, show_time) = (opt.name, opt.time);
+    let confirmation = camera
+        .run_task(|cam| {
+            let display_name = opt.display_name.clone();
+            Box::pin(async move {
+                cam.osd_set(show_name, show_time, display_name.as_deref())
+                    .await
+                    .context(tr!("osd.set_failed"))
+            })
+        })
+        .await?;
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("osd.set_unconfirmed"));
+    }
+
+    Ok(())
+}
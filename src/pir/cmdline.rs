@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use neolink_core::bc_protocol::PirSchedule;
 
 fn onoff_parse(src: &str) -> Result<bool> {
     match src {
@@ -12,6 +13,10 @@ fn onoff_parse(src: &str) -> Result<bool> {
     }
 }
 
+fn schedule_parse(src: &str) -> Result<PirSchedule> {
+    Ok(src.parse()?)
+}
+
 /// The pir command will control the PIR status of the camera
 #[derive(Parser, Debug)]
 pub struct Opt {
@@ -20,4 +25,16 @@ pub struct Opt {
     /// Whether to turn the PIR ON or OFF
     #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
     pub on: Option<bool>,
+    /// Set how easily the PIR triggers from 0 to 100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub sensitivity: Option<u8>,
+    /// Only arm the PIR during these hours of each day such as 20-6, or always
+    #[arg(long, value_parser = schedule_parse)]
+    pub schedule: Option<PirSchedule>,
+    /// Keep the camera connected and print the PIR settings whenever they change
+    #[arg(long)]
+    pub follow: bool,
+    /// Seconds between each check when following
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
 }
@@ -3,6 +3,8 @@
 ///
 /// This module handles the controls of the pir sensor alarm
 ///
+/// Without any changes it prints the camera's rfAlarmCfg xml which has the
+/// current state, sensitivity and time blocks
 ///
 /// # Usage
 ///
@@ -11,13 +13,22 @@
 /// neolink pir --config=config.toml CameraName on
 /// # Or off
 /// neolink pir --config=config.toml CameraName off
+/// # Make it trigger more easily
+/// neolink pir --config=config.toml CameraName --sensitivity 80
+/// # Only arm it from 20:00 until 06:00
+/// neolink pir --config=config.toml CameraName on --schedule 20-6
+/// # Print the settings again whenever they are changed, such as from the app
+/// neolink pir --config=config.toml CameraName --follow
 /// ```
 ///
 use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+use tokio::time::{interval, Duration};
 
 mod cmdline;
 
 use crate::common::NeoReactor;
+use crate::i18n::tr;
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the pir subcommand
@@ -26,33 +37,86 @@ pub(crate) use cmdline::Opt;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    if let Some(on) = opt.on {
-        camera
+    if let Some(schedule) = opt.schedule {
+        let confirmation = camera
             .run_task(|cam| {
                 Box::pin(async move {
-                    cam.pir_set(on)
+                    cam.pir_set_schedule(schedule)
                         .await
-                        .context("Unable to set camera PIR state")
+                        .context(tr!("pir.set_failed"))
                 })
             })
             .await?;
-    } else {
-        let pir_state = camera
+        warn_unconfirmed(confirmation);
+    }
+    if let Some(sensitivity) = opt.sensitivity {
+        let confirmation = camera
             .run_task(|cam| {
                 Box::pin(async move {
-                    cam.get_pirstate()
+                    cam.pir_set_sensitivity(sensitivity)
                         .await
-                        .context("Unable to get camera PIR state")
+                        .context(tr!("pir.set_failed"))
                 })
             })
             .await?;
+        warn_unconfirmed(confirmation);
+    }
+    if let Some(on) = opt.on {
+        let confirmation = camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.pir_set(on).await.context(tr!("pir.set_failed")) })
+            })
+            .await?;
+        warn_unconfirmed(confirmation);
+    }
+
+    let changed = opt.on.is_some() || opt.sensitivity.is_some() || opt.schedule.is_some();
+    if changed && !opt.follow {
+        return Ok(());
+    }
+
+    // Holding a permit stops an idle disconnect between the checks
+    let _permit = if opt.follow {
+        Some(camera.permit().await?)
+    } else {
+        None
+    };
+    let mut poll = interval(Duration::from_secs(opt.interval));
+    let mut last = None;
+    loop {
+        poll.tick().await;
+        let pir_state = match camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.get_pirstate().await.context(tr!("pir.get_failed")) })
+            })
+            .await
+        {
+            Ok(pir_state) => pir_state,
+            // A missed check should not end the monitor
+            Err(e) if opt.follow => {
+                log::warn!("{e:?}");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
         let pir_ser = String::from_utf8(
             yaserde::ser::serialize_with_writer(&pir_state, vec![], &Default::default())
                 .expect("Should Ser the struct"),
         )
         .expect("Should be UTF8");
-        println!("{}", pir_ser);
+        if last.as_ref() != Some(&pir_ser) {
+            println!("{}", pir_ser);
+            last = Some(pir_ser);
+        }
+
+        if !opt.follow {
+            return Ok(());
+        }
     }
+}
 
-    Ok(())
+fn warn_unconfirmed(confirmation: Confirmation) {
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("pir.set_unconfirmed"));
+    }
 }
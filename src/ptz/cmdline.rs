@@ -39,4 +39,14 @@ pub enum PtzCommand {
         /// The amount to zoom to
         amount: f32,
     },
+    /// Move the camera with the left stick of a local gamepad until Start is pressed
+    #[cfg(feature = "joystick")]
+    Joystick {
+        /// How far the stick must be pushed, from 0 to 1, before the camera moves
+        #[arg(long, default_value_t = 0.2)]
+        dead_zone: f32,
+        /// The speed when the stick is pushed all the way
+        #[arg(long, default_value_t = 32)]
+        max_speed: u32,
+    },
 }
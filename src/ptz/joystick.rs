@@ -0,0 +1,113 @@
+//! Continuous PTZ from a local gamepad
+//!
+//! The left stick moves the camera and the further it is pushed the faster
+//! it moves. Letting go stops the camera and Start ends the control.
+//!
+//! The gamepad is read on its own thread because gilrs cannot be moved
+//! between threads, the moves are then sent through the camera's ptz queue
+//! so that it is not flooded with every small change of the stick.
+use anyhow::{anyhow, Context, Result};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use tokio::sync::mpsc::{channel as mpsc, Sender as MpscSender};
+use tokio::time::Duration;
+
+use crate::common::NeoInstance;
+use crate::i18n::tr;
+use neolink_core::bc_protocol::Direction;
+
+/// How long the gamepad thread waits for an event before checking if the
+/// control has ended
+const POLL_TIMEOUT: Duration = Duration::from_millis(200);
+
+enum StickEvent {
+    Moved { x: f32, y: f32 },
+    Quit,
+}
+
+/// The direction and speed for a stick position
+///
+/// The camera only moves along one axis at a time so the axis that is pushed
+/// furthest wins. Inside the dead zone the camera stops.
+fn stick_to_move(x: f32, y: f32, dead_zone: f32, max_speed: f32) -> (Direction, f32) {
+    let (value, direction) = match (x.abs() >= y.abs(), x < 0.0, y < 0.0) {
+        (true, true, _) => (x, Direction::Left),
+        (true, false, _) => (x, Direction::Right),
+        (false, _, true) => (y, Direction::Down),
+        (false, _, false) => (y, Direction::Up),
+    };
+    let value = value.abs().min(1.0);
+    if value <= dead_zone {
+        return (Direction::Stop, 0.0);
+    }
+    // Scale what is left after the dead zone from 1 to the max speed
+    let scaled = (value - dead_zone) / (1.0 - dead_zone);
+    let speed = (1.0 + scaled * (max_speed - 1.0)).round();
+    (direction, speed)
+}
+
+fn read_gamepad(sender: MpscSender<StickEvent>) -> Result<()> {
+    let mut gilrs = Gilrs::new().map_err(|e| anyhow!("{}", e))?;
+    if let Some((_, gamepad)) = gilrs.gamepads().next() {
+        log::info!("Using gamepad {}", gamepad.name());
+    } else {
+        log::warn!("{}", tr!("ptz.joystick_waiting"));
+    }
+
+    let (mut x, mut y) = (0.0, 0.0);
+    while !sender.is_closed() {
+        let event = match gilrs.next_event_blocking(Some(POLL_TIMEOUT)) {
+            Some(event) => event,
+            None => continue,
+        };
+        match event.event {
+            EventType::AxisChanged(Axis::LeftStickX, value, _) => x = value,
+            EventType::AxisChanged(Axis::LeftStickY, value, _) => y = value,
+            EventType::ButtonPressed(Button::Start, _) => {
+                let _ = sender.blocking_send(StickEvent::Quit);
+                break;
+            }
+            // Stop rather than keep moving with the last position
+            EventType::Disconnected => {
+                x = 0.0;
+                y = 0.0;
+            }
+            _ => continue,
+        }
+        if sender.blocking_send(StickEvent::Moved { x, y }).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Move the camera with the gamepad until Start is pressed
+pub(crate) async fn control(camera: &NeoInstance, dead_zone: f32, max_speed: u32) -> Result<()> {
+    let max_speed = max_speed.max(1) as f32;
+    let (sender, mut receiver) = mpsc(100);
+    let reader = tokio::task::spawn_blocking(move || read_gamepad(sender));
+
+    let mut last = (Direction::Stop, 0.0);
+    while let Some(event) = receiver.recv().await {
+        let (direction, speed) = match event {
+            StickEvent::Moved { x, y } => stick_to_move(x, y, dead_zone, max_speed),
+            StickEvent::Quit => break,
+        };
+        if (direction, speed) == last {
+            continue;
+        }
+        last = (direction, speed);
+        if let Err(e) = camera.ptz(direction, speed).await {
+            log::warn!("{}: {:?}", tr!("ptz.move_failed"), e);
+        }
+    }
+    drop(receiver);
+
+    camera
+        .ptz(Direction::Stop, 0.0)
+        .await
+        .context(tr!("ptz.move_failed"))?;
+    reader
+        .await
+        .context("The gamepad thread panicked")?
+        .context(tr!("ptz.joystick_failed"))
+}
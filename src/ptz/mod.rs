@@ -16,14 +16,19 @@
 /// neolink ptz --config=config.toml CameraName preset 0
 /// # Save the current position as preset ID 0 with name PresetName
 /// neolink ptz --config=config.toml CameraName assign 0 PresetName
+/// # Move the camera with a gamepad (needs the joystick feature)
+/// neolink ptz --config=config.toml CameraName joystick
 /// ```
 ///
 use anyhow::{Context, Result};
 use tokio::time::{sleep, Duration};
 
 mod cmdline;
+#[cfg(feature = "joystick")]
+mod joystick;
 
 use crate::common::NeoReactor;
+use crate::i18n::tr;
 use crate::ptz::cmdline::CmdDirection;
 use crate::ptz::cmdline::PtzCommand;
 pub(crate) use cmdline::Opt;
@@ -43,7 +48,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         Box::pin(async move {
                             cam.moveto_ptz_preset(preset_id)
                                 .await
-                                .context("Unable to move to PTZ preset")?;
+                                .context(tr!("ptz.preset_move_failed"))?;
                             Ok(())
                         })
                     })
@@ -55,13 +60,13 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                             let preset_list = cam
                                 .get_ptz_preset()
                                 .await
-                                .context("Unable to get PTZ presets")?;
+                                .context(tr!("ptz.preset_get_failed"))?;
                             Ok(preset_list)
                         })
                     })
                     .await?;
 
-                println!("Available presets:\nID Name");
+                println!("{}", tr!("ptz.preset_header"));
                 for preset in preset_list.preset_list.preset {
                     println!("{:<2} {:?}", preset.id, preset.name);
                 }
@@ -74,7 +79,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                     Box::pin(async move {
                         cam.set_ptz_preset(preset_id, name)
                             .await
-                            .context("Unable to set PTZ preset")?;
+                            .context(tr!("ptz.preset_set_failed"))?;
                         Ok(())
                     })
                 })
@@ -96,27 +101,15 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             let seconds = amount as f32 / speed;
             let duration = Duration::from_secs_f32(seconds);
             camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.send_ptz(direction, speed)
-                            .await
-                            .context("Unable to execute PTZ move command")?;
-                        Ok(())
-                    })
-                })
-                .await?;
+                .ptz(direction, speed)
+                .await
+                .context(tr!("ptz.move_failed"))?;
 
             sleep(duration).await;
             camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.send_ptz(Direction::Stop, 0_f32)
-                            .await
-                            .context("Unable to execute PTZ move command")?;
-                        Ok(())
-                    })
-                })
-                .await?;
+                .ptz(Direction::Stop, 0_f32)
+                .await
+                .context(tr!("ptz.move_failed"))?;
         }
         PtzCommand::Zoom { amount } => {
             camera
@@ -124,13 +117,20 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                     Box::pin(async move {
                         cam.zoom_to((amount * 1000.0) as u32)
                             .await
-                            .context("Unable to execute PTZ move command")?;
+                            .context(tr!("ptz.move_failed"))?;
                         Ok(())
                     })
                 })
                 .await?;
             sleep(Duration::from_secs(1)).await;
         }
+        #[cfg(feature = "joystick")]
+        PtzCommand::Joystick {
+            dead_zone,
+            max_speed,
+        } => {
+            joystick::control(&camera, dead_zone, max_speed).await?;
+        }
     };
 
     Ok(())
@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// The pushnoti command inspects the saved push notification registrations
+#[derive(Parser, Debug)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub cmd: PushNotiCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum PushNotiCommand {
+    /// Print the registration of each push account and the cameras using it
+    Status,
+}
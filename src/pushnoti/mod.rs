@@ -0,0 +1,100 @@
+///
+/// # Neolink Push Notifications
+///
+/// This module inspects the push notification registrations that neolink
+/// keeps between runs
+///
+/// Each `push_account` of the cameras is a separate registration. A camera
+/// is told the token of its account when it connects and sends its
+/// notifications there until it is told another one, so as long as the
+/// store is kept the notifications keep working across restarts.
+///
+/// This command does not connect to the cameras.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink pushnoti --config=config.toml status
+/// ```
+///
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+mod cmdline;
+
+use crate::common::{push_uid, PushStore};
+use crate::config::Config;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+use cmdline::PushNotiCommand;
+
+/// Entry point for the pushnoti subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config: &Config) -> Result<()> {
+    match opt.cmd {
+        PushNotiCommand::Status => status(config),
+    }
+}
+
+fn status(config: &Config) -> Result<()> {
+    let path = PushStore::path(config);
+    match path.as_ref() {
+        Some(path) => println!("{}", tr!("pushnoti.store", path = path.display())),
+        None => println!("{}", tr!("pushnoti.no_store")),
+    }
+    let data = PushStore::load(path).data();
+
+    // The accounts that are saved and the accounts that the cameras ask for
+    let mut accounts: BTreeMap<String, Vec<&str>> = data
+        .accounts
+        .keys()
+        .map(|account| (account.clone(), vec![]))
+        .collect();
+    for camera in config.cameras.iter().filter(|camera| camera.enabled) {
+        if camera.push_notifications {
+            accounts
+                .entry(camera.push_account.clone())
+                .or_default()
+                .push(camera.name.as_str());
+        }
+    }
+
+    println!(
+        "{:<16} {:<32} {:<20} {:<8} CAMERAS",
+        "ACCOUNT", "UID", "TOKEN", "RECEIVED"
+    );
+    for (account, cameras) in accounts.iter() {
+        let saved = data.accounts.get(account);
+        let cameras = if cameras.is_empty() {
+            "-".to_string()
+        } else {
+            cameras.join(", ")
+        };
+        match saved {
+            Some(saved) => {
+                let token = &saved.registration.fcm_token;
+                println!(
+                    "{:<16} {:<32} {:<20} {:<8} {}",
+                    account,
+                    push_uid(token),
+                    format!("{}...", token.chars().take(16).collect::<String>()),
+                    saved.received_ids.len(),
+                    cameras,
+                );
+            }
+            None => {
+                println!(
+                    "{:<16} {:<32} {:<20} {:<8} {}",
+                    account,
+                    tr!("pushnoti.unregistered"),
+                    "-",
+                    "-",
+                    cameras,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//!
+//! # Camera Quirks
+//!
+//! Some models or firmwares need neolink to behave differently. The quirks of
+//! a camera are looked up from the model and firmware in its version info each
+//! time it connects, first from the built in table and then from the
+//! `[[quirks]]` entries of the config.
+//!
+//! ```toml
+//! [[quirks]]
+//!   model = "E1"
+//!   firmware = "v3.0.0.1"
+//!   no_ping = true
+//!   broken_extern_stream = true
+//!   max_encryption = "BcEncrypt"
+//! ```
+//!
+//! The login happens before the version can be read so `max_encryption` is
+//! used from the connection after the model was first seen. It can only
+//! lower the `max_encryption` of the camera, never raise it.
+//!
+//! Cameras whose media cannot be parsed under `Aes` are also remembered
+//! here so that they login with `HeaderAes` until neolink restarts.
+//!
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use neolink_core::{
+    bc::xml::VersionInfo,
+    bc_protocol::{MaxEncryption, StreamKind},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use crate::config::{CameraConfig, Config, QuirkConfig, StreamConfig};
+
+/// The behaviours that are changed for a camera
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Quirks {
+    /// Do not send pings to keep the connection alive
+    pub(crate) no_ping: bool,
+    /// Never use the externStream
+    pub(crate) broken_extern_stream: bool,
+    /// Overrides the `max_encryption` of the camera config
+    pub(crate) max_encryption: Option<String>,
+}
+
+struct BuiltIn {
+    model: &'static str,
+    firmware: Option<&'static str>,
+    no_ping: Option<bool>,
+    broken_extern_stream: Option<bool>,
+    max_encryption: Option<&'static str>,
+}
+
+/// The known quirks, entries are checked in order and later ones win
+const BUILT_IN: &[BuiltIn] = &[
+    // The E1 has no externStream
+    BuiltIn {
+        model: "E1",
+        firmware: None,
+        no_ping: None,
+        broken_extern_stream: Some(true),
+        max_encryption: None,
+    },
+];
+
+impl From<&BuiltIn> for QuirkConfig {
+    fn from(built_in: &BuiltIn) -> Self {
+        QuirkConfig {
+            model: built_in.model.to_string(),
+            firmware: built_in.firmware.map(|firmware| firmware.to_string()),
+            no_ping: built_in.no_ping,
+            broken_extern_stream: built_in.broken_extern_stream,
+            max_encryption: built_in.max_encryption.map(|enc| enc.to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct QuirkState {
+    /// The entries from the config
+    config: Vec<QuirkConfig>,
+    /// Camera name to its quirks from the last connection
+    cameras: HashMap<String, Quirks>,
+    /// Cameras that login with HeaderAes because Aes media failed to parse
+    aes_fallback: HashSet<String>,
+}
+
+lazy_static! {
+    static ref STATE: RwLock<QuirkState> = Default::default();
+}
+
+/// Set the quirk entries from the config
+pub(crate) fn set_config(config: &Config) {
+    if let Ok(mut state) = STATE.write() {
+        state.config = config.quirks.clone();
+    }
+}
+
+fn matches(entry: &QuirkConfig, version: &VersionInfo) -> bool {
+    let firmware = match entry.firmware.as_deref() {
+        Some(firmware) => version.firmwareVersion.starts_with(firmware),
+        None => true,
+    };
+    firmware
+        && version
+            .model
+            .as_deref()
+            .is_some_and(|model| model.eq_ignore_ascii_case(&entry.model))
+}
+
+/// Look up and remember the quirks of a camera from its version info
+pub(crate) fn set_camera_version(name: &str, version: &VersionInfo) -> Quirks {
+    let mut state = match STATE.write() {
+        Ok(state) => state,
+        Err(_) => return Default::default(),
+    };
+    let mut quirks = Quirks::default();
+    let entries = BUILT_IN
+        .iter()
+        .map(QuirkConfig::from)
+        .chain(state.config.iter().cloned());
+    for entry in entries.filter(|entry| matches(entry, version)) {
+        if let Some(no_ping) = entry.no_ping {
+            quirks.no_ping = no_ping;
+        }
+        if let Some(broken_extern_stream) = entry.broken_extern_stream {
+            quirks.broken_extern_stream = broken_extern_stream;
+        }
+        if let Some(max_encryption) = entry.max_encryption {
+            quirks.max_encryption = Some(max_encryption);
+        }
+    }
+    if quirks != Quirks::default() {
+        log::info!("{name}: Applying quirks {quirks:?}");
+    }
+    state.cameras.insert(name.to_string(), quirks.clone());
+    quirks
+}
+
+/// The quirks of a camera from its last connection
+pub(crate) fn get(name: &str) -> Quirks {
+    STATE
+        .read()
+        .ok()
+        .and_then(|state| state.cameras.get(name).cloned())
+        .unwrap_or_default()
+}
+
+/// The configured streams of a camera without those that its quirks rule out
+pub(crate) fn stream_kinds(name: &str, stream: &StreamConfig) -> Vec<StreamKind> {
+    let quirks = get(name);
+    stream
+        .as_stream_kinds()
+        .into_iter()
+        .filter(|kind| !(quirks.broken_extern_stream && *kind == StreamKind::Extern))
+        .collect()
+}
+
+/// The encryption to login with
+///
+/// This is the `max_encryption` of the config, lowered to that of the quirks
+/// and to `HeaderAes` if the camera fell back from `Aes`
+pub(crate) fn max_encryption(camera: &CameraConfig) -> Result<MaxEncryption> {
+    let (quirk, fell_back) = match STATE.read() {
+        Ok(state) => (
+            state
+                .cameras
+                .get(&camera.name)
+                .and_then(|quirks| quirks.max_encryption.clone()),
+            state.aes_fallback.contains(&camera.name),
+        ),
+        Err(_) => (None, false),
+    };
+    let mut max_encryption: MaxEncryption = camera
+        .max_encryption
+        .parse()
+        .with_context(|| format!("Invalid max_encryption for {}", camera.name))?;
+    if let Some(quirk) = quirk {
+        let quirk: MaxEncryption = quirk
+            .parse()
+            .with_context(|| format!("Invalid quirk max_encryption for {}", camera.name))?;
+        max_encryption = max_encryption.min(quirk);
+    }
+    match max_encryption {
+        MaxEncryption::Aes if fell_back => Ok(MaxEncryption::HeaderAes),
+        max_encryption => Ok(max_encryption),
+    }
+}
+
+/// Login with `HeaderAes` instead of `Aes` from the next connection
+///
+/// Returns false if the camera does not login with `Aes`
+pub(crate) fn fall_back_encryption(camera: &CameraConfig) -> bool {
+    if !matches!(max_encryption(camera), Ok(MaxEncryption::Aes)) {
+        return false;
+    }
+    match STATE.write() {
+        Ok(mut state) => state.aes_fallback.insert(camera.name.clone()),
+        Err(_) => false,
+    }
+}
@@ -3,6 +3,13 @@ use clap::Parser;
 /// The reboot command will reboot the camera
 #[derive(Parser, Debug)]
 pub struct Opt {
-    /// The name of the camera to change the lights of. Must be a name in the config
-    pub camera: String,
+    /// The names of the cameras to reboot. Must be names in the config
+    #[arg(required = true)]
+    pub cameras: Vec<String>,
+    /// Keep running and reboot on this cron schedule in local time such as "0 4 * * *"
+    #[arg(long)]
+    pub schedule: Option<String>,
+    /// Wait up to this many seconds at random after each scheduled time
+    #[arg(long, default_value_t = 300)]
+    pub jitter: u64,
 }
@@ -5,35 +5,140 @@
 ///
 /// The subcommand attepts to reboot the camera.
 ///
+/// With `--schedule` it keeps running and reboots the cameras on a cron
+/// schedule in local time. This works around firmware that slowly leaks
+/// memory until the camera stops responding. Each camera waits a random
+/// `--jitter` of seconds after the scheduled time so that they do not all
+/// go down together.
+///
+/// After each scheduled reboot a message is published to
+/// `neolink/{CAMERANAME}/status/reboot` when `[mqtt]` is in the config and a
+/// `reboot` event is POSTed when `[webhook]` is in the config
+///
 /// # Usage
 ///
 /// ```bash
 /// neolink reboot --config=config.toml CameraName
+/// neolink reboot --config=config.toml --schedule "0 4 * * *" --jitter 600 CameraName OtherCamera
 /// ```
 ///
 use anyhow::{Context, Result};
+use chrono::Local;
+use croner::Cron;
+use log::*;
+use rand::Rng;
+use std::time::SystemTime;
+use tokio::{
+    sync::mpsc::{channel as mpsc, Sender as MpscSender},
+    task::JoinSet,
+    time::{sleep, timeout, Duration},
+};
 
 mod cmdline;
 
 use crate::common::NeoReactor;
+use crate::i18n::tr;
+use crate::mqtt::{Mqtt, MqttInstance};
+use crate::webhook::{deliver, Event, Notifier};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the reboot subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
+    let Some(schedule) = opt.schedule else {
+        for name in opt.cameras.iter() {
+            reboot(&reactor, name).await?;
+        }
+        return Ok(());
+    };
+    let cron = Cron::new(&schedule)
+        .parse()
+        .with_context(|| tr!("reboot.invalid_schedule", schedule = &schedule))?;
+
+    let config = reactor.config().await?;
+    let has_mqtt = config.borrow().mqtt.is_some();
+    let mqtt = match has_mqtt {
+        true => Some(Mqtt::publisher(config.clone(), "Neolink-reboot").await),
+        false => None,
+    };
+    let mut set = JoinSet::<Result<()>>::new();
+    let webhook = config.borrow().webhook.clone().map(|settings| {
+        let (tx, rx) = mpsc(100);
+        set.spawn(async move { deliver(&settings, rx).await });
+        tx
+    });
+
+    let max_jitter = opt.jitter;
+    for name in opt.cameras {
+        let reactor = reactor.clone();
+        let cron = cron.clone();
+        let schedule = schedule.clone();
+        let camera_mqtt = match mqtt.as_ref() {
+            Some(mqtt) => Some(mqtt.subscribe(name.clone()).await?),
+            None => None,
+        };
+        let webhook = webhook.clone();
+        set.spawn(async move {
+            loop {
+                let now = Local::now();
+                let next = cron
+                    .find_next_occurrence(&now, false)
+                    .with_context(|| tr!("reboot.invalid_schedule", schedule = &schedule))?;
+                let jitter = Duration::from_secs(rand::thread_rng().gen_range(0..=max_jitter));
+                let wait = (next - now).to_std().unwrap_or_default() + jitter;
+                info!("{name}: Next reboot at {next} after {jitter:?} of jitter");
+                sleep(wait).await;
+
+                if let Err(e) = reboot(&reactor, &name).await {
+                    warn!("{name}: Scheduled reboot failed: {e:?}");
+                    continue;
+                }
+                info!("{name}: Rebooted on schedule");
+                notify(&name, camera_mqtt.as_ref(), webhook.as_ref()).await;
+            }
+        });
+    }
+
+    // The camera tasks only end on error
+    while let Some(res) = set.join_next().await {
+        res??;
+    }
+    Ok(())
+}
+
+async fn reboot(reactor: &NeoReactor, name: &str) -> Result<()> {
+    let camera = reactor.get(name).await?;
 
     camera
         .run_task(|camera| {
-            Box::pin(async move {
-                camera
-                    .reboot()
-                    .await
-                    .context("Could not send reboot command to the camera")
-            })
+            Box::pin(async move { camera.reboot().await.context(tr!("reboot.failed")) })
         })
-        .await?;
+        .await
+}
 
-    Ok(())
+/// Tell mqtt and the webhook about a scheduled reboot
+///
+/// These are best effort and only logged on failure
+async fn notify(name: &str, mqtt: Option<&MqttInstance>, webhook: Option<&MpscSender<String>>) {
+    if let Some(mqtt) = mqtt {
+        let time = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        // The message is queued until the broker is reachable so do not wait forever
+        match timeout(
+            Duration::from_secs(30),
+            mqtt.send_message("status/reboot", &time, false),
+        )
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("{name}: Failed to publish the reboot: {e:?}"),
+            Err(_) => warn!("{name}: Timed out publishing the reboot"),
+        }
+    }
+    if let Some(webhook) = webhook {
+        let notifier = Notifier::new(name.to_string(), webhook.clone());
+        if let Err(e) = notifier.send(Event::Reboot { scheduled: true }).await {
+            warn!("{name}: Failed to queue the reboot webhook: {e:?}");
+        }
+    }
 }
@@ -0,0 +1,35 @@
+use clap::Parser;
+use neolink_core::bc_protocol::StreamKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn stream_kind(name: &str) -> Result<StreamKind, String> {
+    match name {
+        "main" | "mainStream" => Ok(StreamKind::Main),
+        "sub" | "subStream" => Ok(StreamKind::Sub),
+        "extern" | "externStream" => Ok(StreamKind::Extern),
+        _ => Err(format!("Unknown stream {name}, use main, sub or extern")),
+    }
+}
+
+/// Record a camera stream to disk as mp4 files
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to record. Must be a name in the config
+    pub camera: String,
+    /// The directory to write the recordings to
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub dir: PathBuf,
+    /// The stream to record: main, sub or extern
+    #[arg(short, long, default_value = "main", value_parser = stream_kind)]
+    pub stream: StreamKind,
+    /// Target length of each file in seconds, overrides the config
+    #[arg(long)]
+    pub segment_duration: Option<u64>,
+    /// Delete recordings older than this many days, overrides the config
+    #[arg(long)]
+    pub retention_days: Option<u64>,
+    /// Only record clips while there is motion, overrides the config
+    #[arg(long)]
+    pub on_motion: bool,
+}
@@ -0,0 +1,613 @@
+///
+/// # Neolink Record
+///
+/// This module records a camera stream to disk as a series of mp4 files
+///
+/// The video is written as is into fragmented mp4 files, there is no
+/// transcoding and no audio. A new file is started on the first keyframe
+/// after the segment duration so each file can be played on its own. Files
+/// are named after the camera's clock at their first frame
+///
+/// With `on_motion` set only clips of motion events are recorded, one file
+/// per event. The clip starts up to `pre_roll` seconds before the motion
+/// and ends `post_roll` seconds after it stops. The pre-roll comes from the
+/// camera's `[cameras.prebuffer]` when it has one
+///
+/// # Usage
+///
+/// ```bash
+/// neolink record --config=config.toml --dir=/recordings CameraName
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [record]
+///   segment_duration = 600
+///   retention_days = 7
+///   on_motion = true
+///   pre_roll = 5
+///   post_roll = 10
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::watch::Receiver as WatchReceiver,
+    time::{interval, sleep_until, Duration, Instant},
+};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    StreamExt,
+};
+
+mod cmdline;
+
+use crate::{
+    common::{
+        MdState, NeoInstance, NeoReactor, Prebuffer, StampedData, StreamConfig, StreamInstance,
+        VidFormat,
+    },
+    config::RecordConfig,
+    hls::fmp4::{self, ParameterSets, Sample, TIMESCALE},
+};
+
+pub(crate) use cmdline::Opt;
+
+/// Start a new file if the camera's clock is this far from where we expect
+/// it to be, which happens when frames are lost or the clock is changed
+const MAX_DRIFT: i64 = 10;
+
+/// Entry point for the record subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let mut settings = reactor.config().await?.borrow().record.clone();
+    if let Some(segment_duration) = opt.segment_duration {
+        settings.segment_duration = segment_duration;
+    }
+    if let Some(retention_days) = opt.retention_days {
+        settings.retention_days = Some(retention_days);
+    }
+    if opt.on_motion {
+        settings.on_motion = true;
+    }
+
+    let camera = reactor.get(&opt.camera).await?;
+    let dir = opt.dir.join(&opt.camera);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    info!(
+        "{}: Recording {} to {}",
+        opt.camera,
+        opt.stream,
+        dir.display()
+    );
+    tokio::select! {
+        v = record(&camera, opt.stream, &dir, &settings) => v,
+        v = retention(&dir, settings.retention_days) => v,
+    }
+}
+
+/// Record the stream, starting a new file whenever the stream format changes
+async fn record(
+    camera: &NeoInstance,
+    kind: StreamKind,
+    dir: &Path,
+    settings: &RecordConfig,
+) -> Result<()> {
+    let mut instance = camera.stream(kind).await?;
+    let mut gate = if settings.on_motion {
+        Some(MotionGate::new(
+            camera.motion().await?,
+            camera.trigger().await?,
+            settings,
+            instance.prebuffer.clone(),
+        ))
+    } else {
+        None
+    };
+    loop {
+        instance
+            .config
+            .wait_for(|config| config.vid_ready())
+            .await?;
+        let stream_config = instance.config.borrow_and_update().clone();
+        let mut recorder = Recorder::new(dir, kind, &stream_config, settings);
+
+        let result = record_frames(&mut instance, &mut recorder, gate.as_mut()).await;
+        // Keep what we have even if the stream failed
+        recorder.finish().await?;
+        if let Some(gate) = gate.as_mut() {
+            gate.buffer.clear();
+        }
+        result?;
+    }
+}
+
+/// Feed the frames to the recorder until the stream format changes
+async fn record_frames(
+    instance: &mut StreamInstance,
+    recorder: &mut Recorder<'_>,
+    mut gate: Option<&mut MotionGate>,
+) -> Result<()> {
+    if let Some(gate) = gate.as_deref_mut() {
+        // Motion may have started before we were ready
+        gate.update().await;
+    }
+    let mut frames = BroadcastStream::new(instance.vid.resubscribe());
+    loop {
+        let stop_at = gate.as_ref().and_then(|gate| gate.stop_at);
+        tokio::select! {
+            v = instance.config.changed() => {
+                v?;
+                debug!("Recording stream format changed");
+                return Ok(());
+            }
+            v = MotionGate::changed(gate.as_deref_mut()) => {
+                v?;
+                if let Some(gate) = gate.as_deref_mut() {
+                    for frame in gate.update().await {
+                        recorder.push(frame).await?;
+                    }
+                }
+            }
+            _ = sleep_until(stop_at.unwrap_or_else(Instant::now)), if stop_at.is_some() => {
+                recorder.finish().await?;
+                if let Some(gate) = gate.as_deref_mut() {
+                    gate.end_clip();
+                }
+            }
+            frame = frames.next() => {
+                match frame {
+                    Some(Ok(frame)) => {
+                        let frame = match gate.as_deref_mut() {
+                            Some(gate) => gate.hold(frame),
+                            None => Some(frame),
+                        };
+                        if let Some(frame) = frame {
+                            recorder.push(frame).await?;
+                        }
+                    }
+                    Some(Err(BroadcastStreamRecvError::Lagged(count))) => {
+                        warn!("Recording fell behind and lost {count} frames");
+                        match gate.as_deref_mut() {
+                            Some(gate) if !gate.active => gate.buffer.clear(),
+                            _ => recorder.skip().await?,
+                        }
+                    }
+                    None => return Err(anyhow!("Camera stream ended")),
+                }
+            }
+        }
+    }
+}
+
+/// Holds back the frames while there is no motion
+///
+/// The last few seconds are kept so that a clip can start a little before
+/// the motion did. When the camera has a prebuffer they are taken from it
+/// rather than held here. An external trigger starts a clip in the same way
+struct MotionGate {
+    motion: WatchReceiver<MdState>,
+    /// Until when an external trigger is active
+    trigger: WatchReceiver<Option<Instant>>,
+    pre_roll: Duration,
+    post_roll: Duration,
+    /// Frames from before the motion, this always starts on a keyframe
+    buffer: VecDeque<StampedData>,
+    prebuffer: Option<Arc<Mutex<Prebuffer>>>,
+    /// The last frame of the clip that came from the prebuffer, the live
+    /// frames up to it are already recorded
+    resume_after: Option<Duration>,
+    /// True while a clip is being recorded
+    active: bool,
+    /// When the clip should end, set once the motion stops
+    stop_at: Option<Instant>,
+}
+
+impl MotionGate {
+    fn new(
+        motion: WatchReceiver<MdState>,
+        trigger: WatchReceiver<Option<Instant>>,
+        settings: &RecordConfig,
+        prebuffer: Option<Arc<Mutex<Prebuffer>>>,
+    ) -> Self {
+        Self {
+            motion,
+            trigger,
+            pre_roll: Duration::from_secs(settings.pre_roll),
+            post_roll: Duration::from_secs(settings.post_roll),
+            buffer: Default::default(),
+            prebuffer,
+            resume_after: None,
+            active: false,
+            stop_at: None,
+        }
+    }
+
+    async fn changed(gate: Option<&mut Self>) -> Result<()> {
+        match gate {
+            Some(gate) => tokio::select! {
+                v = gate.motion.changed() => Ok(v?),
+                v = gate.trigger.changed() => Ok(v?),
+            },
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Check the motion and trigger state, returns the pre-roll frames when a
+    /// clip starts
+    async fn update(&mut self) -> Vec<StampedData> {
+        let (moving, motion_end) = match &*self.motion.borrow_and_update() {
+            MdState::Start(..) => (true, None),
+            MdState::Stop(at) => (false, Some(*at)),
+            MdState::Unknown => (false, None),
+        };
+        let trigger_end = *self.trigger.borrow_and_update();
+        let triggered = trigger_end.is_some_and(|until| until > Instant::now());
+
+        let mut pre_roll = vec![];
+        if (moving || triggered) && !self.active {
+            if moving {
+                info!("Motion started, recording a clip");
+            } else {
+                info!("Triggered, recording a clip");
+            }
+            self.active = true;
+            pre_roll = match self.prebuffer.as_ref() {
+                Some(prebuffer) => {
+                    let frames = prebuffer.lock().unwrap().frames();
+                    match frames.await {
+                        Ok(frames) => {
+                            let mut frames = VecDeque::from(frames);
+                            trim(&mut frames, self.pre_roll);
+                            self.resume_after = frames.back().map(|frame| frame.ts);
+                            frames.into()
+                        }
+                        Err(e) => {
+                            warn!("Failed to read the prebuffer: {e:?}");
+                            vec![]
+                        }
+                    }
+                }
+                None => self.buffer.drain(..).collect(),
+            };
+        }
+        if self.active {
+            // The clip goes on until both the motion and the trigger are over
+            self.stop_at = match moving {
+                true => None,
+                false => motion_end.max(trigger_end).map(|end| end + self.post_roll),
+            };
+        }
+        pre_roll
+    }
+
+    /// Returns the frame if it should be recorded now or holds on to it
+    fn hold(&mut self, frame: StampedData) -> Option<StampedData> {
+        if self.active {
+            return match self.resume_after {
+                Some(last) if frame.ts <= last => None,
+                _ => Some(frame),
+            };
+        }
+        if self.prebuffer.is_none() {
+            self.buffer.push_back(frame);
+            trim(&mut self.buffer, self.pre_roll);
+        }
+        None
+    }
+
+    /// Called once the post-roll is over
+    fn end_clip(&mut self) {
+        info!("Motion stopped, clip finished");
+        self.active = false;
+        self.stop_at = None;
+        self.resume_after = None;
+    }
+}
+
+/// Start from the newest keyframe that still covers the pre-roll. If the
+/// timestamps went backwards the older frames are dropped too
+fn trim(buffer: &mut VecDeque<StampedData>, pre_roll: Duration) {
+    let Some(newest) = buffer.back().map(|frame| frame.ts) else {
+        return;
+    };
+    let start = buffer.iter().rposition(|frame| {
+        frame.keyframe
+            && newest
+                .checked_sub(frame.ts)
+                .map(|age| age >= pre_roll)
+                .unwrap_or(true)
+    });
+    match start {
+        Some(start) => {
+            buffer.drain(..start);
+        }
+        None => {
+            while buffer.front().is_some_and(|frame| !frame.keyframe) {
+                buffer.pop_front();
+            }
+        }
+    }
+}
+
+/// Delete recordings that are older than the retention period
+async fn retention(dir: &Path, days: Option<u64>) -> Result<()> {
+    let Some(days) = days else {
+        return futures::future::pending().await;
+    };
+    let max_age = Duration::from_secs(days * 24 * 60 * 60);
+    let mut check = interval(Duration::from_secs(60 * 60));
+    loop {
+        check.tick().await;
+        if let Err(e) = remove_old(dir, max_age).await {
+            warn!("Failed to remove old recordings: {e:?}");
+        }
+    }
+}
+
+async fn remove_old(dir: &Path, max_age: Duration) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("mp4") {
+            continue;
+        }
+        let modified = entry.metadata().await?.modified()?;
+        if modified.elapsed().unwrap_or_default() > max_age {
+            info!("Removing old recording {}", path.display());
+            tokio::fs::remove_file(&path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The current POSIX time of this machine
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+/// File name of a recording such as `mainStream_2023-10-17T10-15-00Z.mp4`
+fn file_name(kind: StreamKind, time: u32, attempt: usize) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(time as u64);
+    // `:` is not allowed in windows file names
+    let stamp = humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .replace(':', "-");
+    if attempt == 0 {
+        format!("{kind}_{stamp}.mp4")
+    } else {
+        format!("{kind}_{stamp}_{attempt}.mp4")
+    }
+}
+
+/// An mp4 file that is being written
+struct Recording {
+    file: File,
+    path: PathBuf,
+    /// POSIX time of the first frame
+    start: u32,
+    /// Duration written so far in [`TIMESCALE`] units
+    ticks: u64,
+    sequence: u32,
+}
+
+/// Turns frames into fragments and writes them into rotating files
+struct Recorder<'a> {
+    dir: &'a Path,
+    kind: StreamKind,
+    settings: &'a RecordConfig,
+    format: VidFormat,
+    resolution: [u32; 2],
+    params: ParameterSets,
+    /// Frame duration to use when the timestamps are unusable, this follows
+    /// the last usable one so it keeps up with changes to the fps
+    default_duration: u64,
+    /// Set when frames were lost, no more frames are written until the next keyframe
+    skipping: bool,
+    /// The last frame which is held until its duration is known
+    pending: Option<(StampedData, Vec<u8>)>,
+    /// The frames since the last keyframe, they are written as one fragment
+    samples: Vec<Sample>,
+    recording: Option<Recording>,
+}
+
+impl<'a> Recorder<'a> {
+    fn new(
+        dir: &'a Path,
+        kind: StreamKind,
+        stream_config: &StreamConfig,
+        settings: &'a RecordConfig,
+    ) -> Self {
+        Self {
+            dir,
+            kind,
+            settings,
+            format: stream_config.vid_format.clone(),
+            resolution: stream_config.resolution,
+            params: Default::default(),
+            default_duration: TIMESCALE as u64 / stream_config.fps.max(1) as u64,
+            skipping: false,
+            pending: None,
+            samples: vec![],
+            recording: None,
+        }
+    }
+
+    async fn push(&mut self, frame: StampedData) -> Result<()> {
+        let data = fmp4::frame_to_sample(&self.format, &frame.data, &mut self.params);
+        if self.recording.is_none() || self.skipping {
+            if !frame.keyframe || !self.params.is_complete(&self.format) {
+                return Ok(());
+            }
+            self.skipping = false;
+        }
+
+        if let Some((prev, prev_data)) = self.pending.take() {
+            let duration = match frame.ts.checked_sub(prev.ts) {
+                Some(delta) if !delta.is_zero() && delta < Duration::from_secs(5) => {
+                    self.default_duration =
+                        (delta.as_micros() as u64 * TIMESCALE as u64) / 1_000_000;
+                    self.default_duration
+                }
+                // Timestamps reset or jumped
+                _ => self.default_duration,
+            };
+            self.samples.push(Sample {
+                data: prev_data,
+                duration: duration as u32,
+                keyframe: prev.keyframe,
+            });
+        }
+
+        if frame.keyframe {
+            self.write_fragment().await?;
+            if self.should_rotate(frame.time) {
+                self.close().await?;
+            }
+            if self.recording.is_none() {
+                self.open(frame.time.unwrap_or_else(now)).await?;
+            }
+        }
+        self.pending = Some((frame, data));
+        Ok(())
+    }
+
+    /// Write what we have and wait for the next keyframe
+    async fn skip(&mut self) -> Result<()> {
+        self.take_pending();
+        self.write_fragment().await?;
+        self.skipping = true;
+        Ok(())
+    }
+
+    /// Write everything that is left and close the file
+    async fn finish(&mut self) -> Result<()> {
+        self.take_pending();
+        self.write_fragment().await?;
+        self.close().await
+    }
+
+    /// Move the pending frame into the samples using the default duration
+    fn take_pending(&mut self) {
+        if let Some((prev, prev_data)) = self.pending.take() {
+            self.samples.push(Sample {
+                data: prev_data,
+                duration: self.default_duration as u32,
+                keyframe: prev.keyframe,
+            });
+        }
+    }
+
+    /// True if a new file should be started at a keyframe with this time
+    fn should_rotate(&self, time: Option<u32>) -> bool {
+        let Some(recording) = self.recording.as_ref() else {
+            return false;
+        };
+        // Clips cover one motion event however long it is
+        if self.settings.on_motion {
+            return false;
+        }
+        let elapsed = recording.ticks / TIMESCALE as u64;
+        if elapsed >= self.settings.segment_duration {
+            return true;
+        }
+        match time {
+            Some(time) => {
+                let expected = recording.start as i64 + elapsed as i64;
+                (time as i64 - expected).abs() > MAX_DRIFT
+            }
+            None => false,
+        }
+    }
+
+    async fn open(&mut self, start: u32) -> Result<()> {
+        let init = fmp4::init_segment(
+            &self.format,
+            &self.params,
+            self.resolution[0],
+            self.resolution[1],
+            Some(start),
+        )?;
+
+        // Never overwrite an earlier recording, the camera's clock can go backwards
+        let mut attempt = 0;
+        let (mut file, path) = loop {
+            let path = self.dir.join(file_name(self.kind, start, attempt));
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => break (file, path),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+                }
+            }
+        };
+        file.write_all(&init).await?;
+        debug!("Recording to {}", path.display());
+
+        self.recording = Some(Recording {
+            file,
+            path,
+            start,
+            ticks: 0,
+            sequence: 1,
+        });
+        Ok(())
+    }
+
+    async fn write_fragment(&mut self) -> Result<()> {
+        if self.samples.is_empty() {
+            return Ok(());
+        }
+        let samples = std::mem::take(&mut self.samples);
+        let Some(recording) = self.recording.as_mut() else {
+            return Ok(());
+        };
+        let data = fmp4::fragment(recording.sequence, recording.ticks, &samples);
+        recording
+            .file
+            .write_all(&data)
+            .await
+            .with_context(|| format!("Failed to write {}", recording.path.display()))?;
+        recording.sequence = recording.sequence.wrapping_add(1);
+        recording.ticks += samples
+            .iter()
+            .map(|sample| sample.duration as u64)
+            .sum::<u64>();
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if let Some(mut recording) = self.recording.take() {
+            recording.file.flush().await?;
+            info!(
+                "Finished recording {} ({}s)",
+                recording.path.display(),
+                recording.ticks / TIMESCALE as u64
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! The rtsp admin server
+//!
+//! When `[rtsp_admin]` is in the config the sessions of the rtsp clients can
+//! be listed with `GET /sessions` and a client can be disconnected with
+//! `DELETE /sessions/{id}`.
+use anyhow::Result;
+use hyper::{Method, StatusCode};
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::watch::Receiver as WatchReceiver;
+
+use super::gst::{NeoRtspServer, RtspSession};
+use crate::{
+    common::{http_server, response},
+    config::Config,
+};
+
+#[derive(Serialize)]
+struct SessionsV1 {
+    schema: &'static str,
+    mounts: BTreeMap<String, Vec<RtspSession>>,
+}
+
+pub(super) async fn admin_server(
+    config: WatchReceiver<Config>,
+    rtsp: Arc<NeoRtspServer>,
+) -> Result<()> {
+    http_server(
+        config,
+        "RTSP admin",
+        |config| {
+            config
+                .rtsp_admin
+                .as_ref()
+                .map(|admin| format!("{}:{}", admin.bind_addr, admin.bind_port))
+        },
+        move |req| {
+            let path = req.uri().path();
+            let session_id = path.strip_prefix("/sessions/").filter(|id| !id.is_empty());
+            match (req.method(), path, session_id) {
+                (&Method::GET, "/sessions" | "/sessions/", _) => {
+                    let sessions = SessionsV1 {
+                        schema: "neolink.rtsp_sessions.v1",
+                        mounts: rtsp.sessions(),
+                    };
+                    match serde_json::to_string(&sessions) {
+                        Ok(json) => response(StatusCode::OK, "application/json", json),
+                        Err(e) => response(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "text/plain",
+                            e.to_string(),
+                        ),
+                    }
+                }
+                (&Method::DELETE, _, Some(id)) => match rtsp.kick(id) {
+                    true => response(StatusCode::NO_CONTENT, "text/plain", String::new()),
+                    false => response(StatusCode::NOT_FOUND, "text/plain", String::new()),
+                },
+                (_, "/sessions" | "/sessions/", _) | (_, _, Some(_)) => {
+                    response(StatusCode::METHOD_NOT_ALLOWED, "text/plain", String::new())
+                }
+                _ => response(StatusCode::NOT_FOUND, "text/plain", String::new()),
+            }
+        },
+    )
+    .await
+}
@@ -1,11 +1,14 @@
 use anyhow::{anyhow, Context, Result};
 use gstreamer::{prelude::*, Bin, Caps, Element, ElementFactory, GhostPad};
-use gstreamer_app::{AppSrc, AppSrcCallbacks, AppStreamType};
+use gstreamer_app::{AppSink, AppSrc, AppSrcCallbacks, AppStreamType};
+use gstreamer_rtsp::RTSPLowerTrans;
+use gstreamer_rtsp_server::{prelude::*, RTSPAddressPool};
 use log::*;
 use tokio::sync::mpsc::{channel as mpsc, Receiver as MpscReceiver};
 
 use crate::{
     common::{AudFormat, StreamConfig, VidFormat},
+    config::{AudioCodec, Latency, MulticastGroup, RtpConfig},
     rtsp::gst::NeoMediaFactory,
     AnyResult,
 };
@@ -17,6 +20,15 @@ pub(super) struct ClientSourceData {
 pub(super) struct ClientData {
     pub(super) vid: Option<ClientSourceData>,
     pub(super) aud: Option<ClientSourceData>,
+    /// The adpcm of the client's backchannel, only set when it asked for one
+    pub(super) talk: Option<AppSink>,
+}
+
+/// The adpcm that the camera plays, from its talk config
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Backchannel {
+    pub(super) block_align: u16,
+    pub(super) sample_rate: u16,
 }
 
 pub(super) async fn make_dummy_factory(
@@ -37,13 +49,37 @@ pub(super) async fn make_dummy_factory(
 
 pub(super) async fn make_factory(
     stream_config: &StreamConfig,
+    audio_codec: AudioCodec,
+    latency: Latency,
+    use_mpegts: bool,
+    rtp: &RtpConfig,
+    backchannel: Option<Backchannel>,
 ) -> AnyResult<(NeoMediaFactory, MpscReceiver<ClientData>)> {
     let (client_tx, client_rx) = mpsc(100);
     let factory = {
         let stream_config = stream_config.clone();
+        let rtp = rtp.clone();
 
         NeoMediaFactory::new_with_callback(move |element| {
             clear_bin(&element)?;
+            if use_mpegts && !matches!(stream_config.vid_format, VidFormat::None) {
+                let (vid, aud) = build_mpegts(&element, &stream_config, latency)?;
+                for app in std::iter::once(&vid).chain(aud.iter()) {
+                    app.set_callbacks(
+                        AppSrcCallbacks::builder()
+                            .seek_data(move |_, _seek_pos| true)
+                            .build(),
+                    );
+                }
+                apply_rtp(&element, &rtp)?;
+
+                client_tx.blocking_send(ClientData {
+                    vid: Some(ClientSourceData { app: vid }),
+                    aud: aud.map(|app| ClientSourceData { app }),
+                    talk: backchannel_sink(&element),
+                })?;
+                return Ok(Some(element));
+            }
             let vid = match stream_config.vid_format {
                 VidFormat::None => {
                     // This should not be reachable
@@ -52,7 +88,7 @@ pub(super) async fn make_factory(
                     AnyResult::Ok(None)
                 }
                 VidFormat::H264 => {
-                    let app = build_h264(&element, &stream_config)?;
+                    let app = build_h264(&element, &stream_config, latency)?;
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
                             .seek_data(move |_, _seek_pos| true)
@@ -61,7 +97,7 @@ pub(super) async fn make_factory(
                     AnyResult::Ok(Some(app))
                 }
                 VidFormat::H265 => {
-                    let app = build_h265(&element, &stream_config)?;
+                    let app = build_h265(&element, &stream_config, latency)?;
 
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
@@ -77,7 +113,7 @@ pub(super) async fn make_factory(
                 match stream_config.aud_format {
                     AudFormat::None => AnyResult::Ok(None),
                     AudFormat::Aac => {
-                        let app = build_aac(&element, &stream_config)?;
+                        let app = build_aac(&element, &stream_config, audio_codec, latency)?;
                         app.set_callbacks(
                             AppSrcCallbacks::builder()
                                 .seek_data(move |_, _seek_pos| true)
@@ -86,7 +122,13 @@ pub(super) async fn make_factory(
                         AnyResult::Ok(Some(app))
                     }
                     AudFormat::Adpcm(block_size) => {
-                        let app = build_adpcm(&element, block_size, &stream_config)?;
+                        let app = build_adpcm(
+                            &element,
+                            block_size,
+                            &stream_config,
+                            audio_codec,
+                            latency,
+                        )?;
                         app.set_callbacks(
                             AppSrcCallbacks::builder()
                                 .seek_data(move |_, _seek_pos| true)
@@ -96,19 +138,101 @@ pub(super) async fn make_factory(
                     }
                 }?
             };
+            apply_rtp(&element, &rtp)?;
 
             client_tx.blocking_send(ClientData {
                 vid: vid.map(|app| ClientSourceData { app }),
                 aud: aud.map(|app| ClientSourceData { app }),
+                talk: backchannel_sink(&element),
             })?;
             Ok(Some(element))
         })
         .await
     }?;
+    if let Some(backchannel) = backchannel {
+        factory.set_backchannel_launch(Some(&backchannel_launch(backchannel)));
+    }
 
     Ok((factory, client_rx))
 }
 
+/// Serve the factory to the multicast group as well as unicast
+///
+/// All the clients share one media so the camera stream is sent to the group
+/// once however many clients join it
+pub(super) fn set_multicast(factory: &NeoMediaFactory, group: &MulticastGroup) -> Result<()> {
+    let pool = RTSPAddressPool::new();
+    // Video and audio each take a port for RTP and one for RTCP
+    pool.add_range(
+        &group.address,
+        &group.address,
+        group.port,
+        group.port + 3,
+        group.ttl,
+    )
+    .with_context(|| format!("Invalid multicast group {}:{}", group.address, group.port))?;
+    factory.set_address_pool(Some(&pool));
+    factory.set_protocols(RTSPLowerTrans::UDP | RTSPLowerTrans::UDP_MCAST | RTSPLowerTrans::TCP);
+    factory.set_shared(true);
+    Ok(())
+}
+
+/// The pipeline that the ONVIF factory adds for clients that want to talk
+///
+/// ONVIF clients send G.711 mu-law at 8kHz, it is encoded to the camera's adpcm
+fn backchannel_launch(backchannel: Backchannel) -> String {
+    format!(
+        "( capsfilter name=depay_backchannel \
+        caps=\"application/x-rtp,media=audio,payload=0,clock-rate=8000,encoding-name=PCMU\" \
+        ! rtppcmudepay \
+        ! mulawdec \
+        ! audioconvert \
+        ! audioresample \
+        ! audio/x-raw,rate={},channels=1 \
+        ! adpcmenc blockalign={} layout=dvi \
+        ! appsink name=backchannel_sink sync=false async=false )",
+        backchannel.sample_rate, backchannel.block_align
+    )
+}
+
+/// The sink of the backchannel if the ONVIF factory added one for the client
+fn backchannel_sink(bin: &Element) -> Option<AppSink> {
+    bin.clone()
+        .dynamic_cast::<Bin>()
+        .ok()?
+        .by_name("backchannel_sink")?
+        .dynamic_cast::<AppSink>()
+        .ok()
+}
+
+/// Set the `[cameras.rtp]` settings on the payloaders
+///
+/// Unset values keep the defaults of the gstreamer payloaders
+fn apply_rtp(bin: &Element, rtp: &RtpConfig) -> Result<()> {
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    if let Some(payload) = bin.by_name("pay0") {
+        if let Some(pt) = rtp.video_pt {
+            payload.set_property("pt", pt);
+        }
+        // The splash screen's jpeg payloader has no config-interval
+        match rtp.config_interval {
+            Some(interval) if payload.has_property("config-interval", None) => {
+                payload.set_property("config-interval", interval);
+            }
+            _ => {}
+        }
+    }
+    if let Some(payload) = bin.by_name("pay1") {
+        if let Some(pt) = rtp.audio_pt {
+            payload.set_property("pt", pt);
+        }
+    }
+    Ok(())
+}
+
 fn clear_bin(bin: &Element) -> Result<()> {
     let bin = bin
         .clone()
@@ -117,6 +241,10 @@ fn clear_bin(bin: &Element) -> Result<()> {
     // Clear the autogenerated ones
     log::debug!("Clearing old elements");
     for element in bin.iterate_elements().into_iter().flatten() {
+        // Added by the ONVIF factory for clients that asked for a backchannel
+        if element.name() == "onvif-backchannel" {
+            continue;
+        }
         bin.remove(&element)?;
     }
 
@@ -132,7 +260,7 @@ fn build_unknown(bin: &Element, pattern: &str) -> Result<()> {
     let source = make_element("videotestsrc", "testvidsrc")?;
     source.set_property_from_str("pattern", pattern);
     source.set_property("num-buffers", 500i32); // Send buffers then EOS
-    let queue = make_queue("queue0", 1024 * 1024 * 4)?;
+    let queue = make_queue("queue0", 1024 * 1024 * 4, Latency::Normal)?;
 
     let overlay = make_element("textoverlay", "overlay")?;
     overlay.set_property("text", "Stream not Ready");
@@ -157,8 +285,8 @@ fn build_unknown(bin: &Element, pattern: &str) -> Result<()> {
     Ok(())
 }
 
-fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_h264(bin: &Element, stream_config: &StreamConfig, latency: Latency) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate, latency);
     log::debug!("buffer_size: {buffer_size}");
     let bin = bin
         .clone()
@@ -169,7 +297,7 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
-    source.set_is_live(true);
+    source.set_is_live(is_live(latency));
     source.set_block(false);
     source.set_min_latency(0);
     source.set_property("emit-signals", false);
@@ -180,7 +308,7 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     let source = source
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
-    let queue = make_queue("source_queue", buffer_size)?;
+    let queue = make_queue("source_queue", buffer_size, latency)?;
     let parser = make_element("h264parse", "parser")?;
     let stamper = make_element("h264timestamper", "stamper")?;
     let payload = make_element("rtph264pay", "pay0")?;
@@ -193,8 +321,8 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_h265(bin: &Element, stream_config: &StreamConfig, latency: Latency) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate, latency);
     log::debug!("buffer_size: {buffer_size}");
     let bin = bin
         .clone()
@@ -204,7 +332,7 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     let source = make_element("appsrc", "vidsrc")?
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
-    source.set_is_live(true);
+    source.set_is_live(is_live(latency));
     source.set_block(false);
     source.set_min_latency(0);
     source.set_property("emit-signals", false);
@@ -215,7 +343,7 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     let source = source
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
-    let queue = make_queue("source_queue", buffer_size)?;
+    let queue = make_queue("source_queue", buffer_size, latency)?;
     let parser = make_element("h265parse", "parser")?;
     let stamper = make_element("h265timestamper", "stamper")?;
     let payload = make_element("rtph265pay", "pay0")?;
@@ -228,8 +356,13 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_aac(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    audio_codec: AudioCodec,
+    latency: Latency,
+) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate, latency);
     log::debug!("buffer_size: {buffer_size}");
     let bin = bin
         .clone()
@@ -240,7 +373,7 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
-    source.set_is_live(true);
+    source.set_is_live(is_live(latency));
     source.set_block(false);
     source.set_min_latency(0);
     source.set_property("emit-signals", false);
@@ -252,7 +385,7 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
-    let queue = make_queue("audqueue", buffer_size)?;
+    let queue = make_queue("audqueue", buffer_size, latency)?;
     let parser = make_element("aacparse", "audparser")?;
     let decoder = match make_element("faad", "auddecoder_faad") {
         Ok(ele) => Ok(ele),
@@ -268,8 +401,7 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
         fallback_switch.set_property("immediate-fallback", true);
     }
 
-    let encoder = make_element("audioconvert", "audencoder")?;
-    let payload = make_element("rtpL16pay", "pay1")?;
+    let (encoder, payload) = make_audio_payload(audio_codec)?;
 
     bin.add_many([&source, &queue, &parser, &decoder, &encoder, &payload])?;
     if let Ok(fallback_switch) = fallback_switch.as_ref() {
@@ -294,8 +426,14 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_adpcm(
+    bin: &Element,
+    block_size: u32,
+    stream_config: &StreamConfig,
+    audio_codec: AudioCodec,
+    latency: Latency,
+) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate, latency);
     log::debug!("buffer_size: {buffer_size}");
     let bin = bin
         .clone()
@@ -312,7 +450,7 @@ fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) ->
     let source = make_element("appsrc", "audsrc")?
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
-    source.set_is_live(true);
+    source.set_is_live(is_live(latency));
     source.set_block(false);
     source.set_min_latency(0);
     source.set_property("emit-signals", false);
@@ -333,10 +471,9 @@ fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) ->
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
-    let queue = make_queue("audqueue", buffer_size)?;
+    let queue = make_queue("audqueue", buffer_size, latency)?;
     let decoder = make_element("decodebin", "auddecoder")?;
-    let encoder = make_element("audioconvert", "audencoder")?;
-    let payload = make_element("rtpL16pay", "pay1")?;
+    let (encoder, payload) = make_audio_payload(audio_codec)?;
 
     bin.add_many([&source, &queue, &decoder, &encoder, &payload])?;
     Element::link_many([&source, &queue, &decoder])?;
@@ -356,6 +493,110 @@ fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) ->
     Ok(source)
 }
 
+/// Mux the video and audio into one MPEG-TS on `pay0`
+///
+/// The muxer writes the PCR from the timestamps of both so clients that
+/// drift with separate video and audio payloads stay in sync. AAC is muxed
+/// as it is, ADPCM is encoded to AAC when an encoder is installed and left
+/// out otherwise.
+fn build_mpegts(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    latency: Latency,
+) -> Result<(AppSrc, Option<AppSrc>)> {
+    let buffer_size = buffer_size(stream_config.bitrate, latency);
+    log::debug!("buffer_size: {buffer_size}");
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building MPEG-TS pipeline");
+    let (parser, stamper) = match stream_config.vid_format {
+        VidFormat::H264 => ("h264parse", "h264timestamper"),
+        VidFormat::H265 => ("h265parse", "h265timestamper"),
+        VidFormat::None => return Err(anyhow!("MPEG-TS needs a known video format")),
+    };
+
+    let vid_source = make_source("vidsrc", buffer_size, latency)?;
+    let queue = make_queue("source_queue", buffer_size, latency)?;
+    let parser = make_element(parser, "parser")?;
+    // Repeat the parameter sets so that clients can join at any keyframe
+    parser.set_property("config-interval", -1i32);
+    let stamper = make_element(stamper, "stamper")?;
+    let mux = make_element("mpegtsmux", "mux")?;
+    // Whole RTP packets of seven TS packets
+    mux.set_property("alignment", 7i32);
+    let payload = make_element("rtpmp2tpay", "pay0")?;
+    let vid_element = vid_source.clone().upcast::<Element>();
+    bin.add_many([&vid_element, &queue, &parser, &stamper, &mux, &payload])?;
+    Element::link_many([&vid_element, &queue, &parser, &stamper, &mux, &payload])?;
+
+    let aud_source = match stream_config.aud_format {
+        AudFormat::None => None,
+        AudFormat::Aac => {
+            let source = make_source("audsrc", buffer_size, latency)?;
+            let queue = make_queue("audqueue", buffer_size, latency)?;
+            let parser = make_element("aacparse", "audparser")?;
+            let element = source.clone().upcast::<Element>();
+            bin.add_many([&element, &queue, &parser])?;
+            Element::link_many([&element, &queue, &parser, &mux])?;
+            Some(source)
+        }
+        AudFormat::Adpcm(block_size) => {
+            let encoder = make_element("avenc_aac", "audencoder")
+                .or_else(|_| make_element("fdkaacenc", "audencoder"))
+                .or_else(|_| make_element("voaacenc", "audencoder"));
+            match encoder {
+                Ok(encoder) => {
+                    let source = make_source("audsrc", buffer_size, latency)?;
+                    source.set_caps(Some(
+                        &Caps::builder("audio/x-adpcm")
+                            .field("layout", "dvi")
+                            .field("block_align", block_size as i32)
+                            .field("channels", 1i32)
+                            .field("rate", 8000i32)
+                            .build(),
+                    ));
+                    let queue = make_queue("audqueue", buffer_size, latency)?;
+                    let decoder = make_element("adpcmdec", "auddecoder")?;
+                    let convert = make_element("audioconvert", "audconvert")?;
+                    let resample = make_element("audioresample", "audresample")?;
+                    let parser = make_element("aacparse", "audparser")?;
+                    let element = source.clone().upcast::<Element>();
+                    bin.add_many([
+                        &element, &queue, &decoder, &convert, &resample, &encoder, &parser,
+                    ])?;
+                    Element::link_many([
+                        &element, &queue, &decoder, &convert, &resample, &encoder, &parser, &mux,
+                    ])?;
+                    Some(source)
+                }
+                Err(e) => {
+                    log::warn!("MPEG-TS will have no audio: {e:?}");
+                    None
+                }
+            }
+        }
+    };
+
+    Ok((vid_source, aud_source))
+}
+
+/// An appsrc set up like the ones of the separate payloads
+fn make_source(name: &str, buffer_size: u32, latency: Latency) -> Result<AppSrc> {
+    let source = make_element("appsrc", name)?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    source.set_is_live(is_live(latency));
+    source.set_block(false);
+    source.set_min_latency(0);
+    source.set_property("emit-signals", false);
+    source.set_max_bytes(buffer_size as u64);
+    source.set_do_timestamp(true);
+    source.set_stream_type(AppStreamType::Seekable);
+    Ok(source)
+}
+
 // Convenice funcion to make an element or provide a message
 // about what plugin is missing
 fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
@@ -371,6 +612,18 @@ fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
             "rtpjitterbuffer" => "rtp (gst-plugins-good)",
             "aacparse" => "audioparsers (gst-plugins-good)",
             "rtpL16pay" => "rtp (gst-plugins-good)",
+            "rtppcmupay" => "rtp (gst-plugins-good)",
+            "rtppcmapay" => "rtp (gst-plugins-good)",
+            "mulawenc" => "mulaw (gst-plugins-good)",
+            "alawenc" => "alaw (gst-plugins-good)",
+            "avenc_aac" => "libav (gst-libav)",
+            "voaacenc" => "voaacenc (gst-plugins-bad)",
+            "fdkaacenc" => "fdkaac (gst-plugins-bad)",
+            "rtpmp4gpay" => "rtp (gst-plugins-good)",
+            "opusenc" => "opus (gst-plugins-base)",
+            "rtpopuspay" => "rtp (gst-plugins-good)",
+            "audioresample" => "audioresample (gst-plugins-base)",
+            "capsfilter" => "coreelements (gstreamer)",
             "x264enc" => "x264 (gst-plugins-ugly)",
             "x265enc" => "x265 (gst-plugins-bad)",
             "avdec_h264" => "libav (gst-libav)",
@@ -387,11 +640,80 @@ fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
         )
     })
 }
-fn make_queue(name: &str, buffer_size: u32) -> AnyResult<Element> {
+/// The encoder and payloader of the raw audio for the rtsp clients
+///
+/// L16 is sent at the camera's rate. Many NVRs only take G.711 so for that
+/// the audio is resampled to 8kHz mono first. AAC is encoded at 16kHz and
+/// Opus at a rate the encoder negotiates with the resampler
+fn make_audio_payload(audio_codec: AudioCodec) -> AnyResult<(Element, Element)> {
+    let (encoder, payload, rate) = match audio_codec {
+        AudioCodec::L16 => {
+            return Ok((
+                make_element("audioconvert", "audencoder")?,
+                make_element("rtpL16pay", "pay1")?,
+            ))
+        }
+        AudioCodec::Pcmu => (
+            make_element("mulawenc", "audcodec")?,
+            "rtppcmupay",
+            Some(8000i32),
+        ),
+        AudioCodec::Pcma => (
+            make_element("alawenc", "audcodec")?,
+            "rtppcmapay",
+            Some(8000i32),
+        ),
+        AudioCodec::Aac => (
+            make_element("avenc_aac", "audcodec")
+                .or_else(|_| make_element("voaacenc", "audcodec"))
+                .or_else(|_| make_element("fdkaacenc", "audcodec"))?,
+            "rtpmp4gpay",
+            Some(16000i32),
+        ),
+        AudioCodec::Opus => (make_element("opusenc", "audcodec")?, "rtpopuspay", None),
+    };
+    let convert = make_element("audioconvert", "audconvert")?;
+    let resample = make_element("audioresample", "audresample")?;
+    let filter = make_element("capsfilter", "audfilter")?;
+    let mut caps = Caps::builder("audio/x-raw").field("channels", 1i32);
+    if let Some(rate) = rate {
+        caps = caps.field("rate", rate);
+    }
+    filter.set_property("caps", caps.build());
+
+    let bin = gstreamer::Bin::builder().name("audencoder").build();
+    bin.add_many([&convert, &resample, &filter, &encoder])?;
+    Element::link_many([&convert, &resample, &filter, &encoder])?;
+
+    let pad = convert
+        .static_pad("sink")
+        .expect("Failed to get a static pad from audioconvert.");
+    let ghost_pad = GhostPad::builder_with_target(&pad).unwrap().build();
+    ghost_pad.set_active(true)?;
+    bin.add_pad(&ghost_pad)?;
+
+    let pad = encoder
+        .static_pad("src")
+        .expect("Failed to get a static pad from the audio encoder.");
+    let ghost_pad = GhostPad::builder_with_target(&pad).unwrap().build();
+    ghost_pad.set_active(true)?;
+    bin.add_pad(&ghost_pad)?;
+
+    let bin = bin
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot convert bin"))?;
+    Ok((bin, make_element(payload, "pay1")?))
+}
+
+fn make_queue(name: &str, buffer_size: u32, latency: Latency) -> AnyResult<Element> {
     let queue = make_element("queue", &format!("queue1_{}", name))?;
     queue.set_property("max-size-bytes", buffer_size);
     queue.set_property("max-size-buffers", 0u32);
     queue.set_property("max-size-time", 0u64);
+    if latency == Latency::Low {
+        // Drop the oldest data rather than fall behind live
+        queue.set_property_from_str("leaky", "downstream");
+    }
     // queue.set_property(
     //     "max-size-time",
     //     std::convert::TryInto::<u64>::try_into(tokio::time::Duration::from_secs(5).as_nanos())
@@ -433,6 +755,18 @@ fn make_queue(name: &str, buffer_size: u32) -> AnyResult<Element> {
     Ok(bin)
 }
 
-fn buffer_size(bitrate: u32) -> u32 {
-    std::cmp::max(bitrate * 15u32 / 8u32, 4u32 * 1024u32 * 1024u32)
+/// Bytes to buffer for the bitrate, this is some seconds of the stream with a
+/// floor so that low bitrates still have room for a keyframe
+fn buffer_size(bitrate: u32, latency: Latency) -> u32 {
+    let (seconds, floor) = match latency {
+        Latency::Low => (2u32, 1024u32 * 1024u32),
+        Latency::Normal => (15u32, 4u32 * 1024u32 * 1024u32),
+        Latency::Smooth => (30u32, 8u32 * 1024u32 * 1024u32),
+    };
+    std::cmp::max(bitrate.saturating_mul(seconds) / 8u32, floor)
+}
+
+/// With `smooth` the sources are not live so that gstreamer buffers them
+fn is_live(latency: Latency) -> bool {
+    latency != Latency::Smooth
 }
@@ -14,8 +14,8 @@ use gstreamer::{
 use gstreamer_rtsp::RTSPUrl;
 use gstreamer_rtsp_server::prelude::*;
 use gstreamer_rtsp_server::subclass::prelude::*;
-use gstreamer_rtsp_server::RTSPMediaFactory;
 use gstreamer_rtsp_server::RTSPTransportMode;
+use gstreamer_rtsp_server::{RTSPMediaFactory, RTSPOnvifMediaFactory};
 use gstreamer_rtsp_server::{RTSP_PERM_MEDIA_FACTORY_ACCESS, RTSP_PERM_MEDIA_FACTORY_CONSTRUCT};
 use log::*;
 use std::collections::HashSet;
@@ -23,8 +23,10 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 glib::wrapper! {
-    /// The wrapped RTSPMediaFactory
-    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPMediaFactory;
+    /// The wrapped RTSPOnvifMediaFactory
+    ///
+    /// The ONVIF factory is the parent so that clients can ask for a backchannel
+    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPOnvifMediaFactory, RTSPMediaFactory;
 }
 
 impl Default for NeoMediaFactory {
@@ -138,6 +140,7 @@ impl NeoMediaFactoryImpl {
 }
 
 impl ObjectImpl for NeoMediaFactoryImpl {}
+impl RTSPOnvifMediaFactoryImpl for NeoMediaFactoryImpl {}
 impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
     fn create_element(&self, url: &RTSPUrl) -> Option<Element> {
         self.parent_create_element(url)
@@ -149,5 +152,5 @@ impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
 impl ObjectSubclass for NeoMediaFactoryImpl {
     const NAME: &'static str = "NeoMediaFactory";
     type Type = super::NeoMediaFactory;
-    type ParentType = RTSPMediaFactory;
+    type ParentType = RTSPOnvifMediaFactory;
 }
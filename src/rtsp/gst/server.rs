@@ -7,19 +7,28 @@ use super::AnyResult;
 use crate::config::*;
 
 use anyhow::Context;
-use gstreamer::glib::{self, object_subclass, subclass::types::ObjectSubclass, MainLoop, Object};
+use gstreamer::glib::{
+    self, object_subclass,
+    subclass::types::ObjectSubclass,
+    translate::{from_glib_none, ToGlibPtr},
+    MainLoop, Object,
+};
 use gstreamer_rtsp::RTSPAuthMethod;
 use gstreamer_rtsp_server::{
-    gio::{TlsAuthenticationMode, TlsCertificate},
+    gio::{TlsAuthenticationMode, TlsCertificate, TlsConnection},
     prelude::*,
     subclass::prelude::*,
-    RTSPAuth, RTSPFilterResult, RTSPServer, RTSPToken, RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+    RTSPAuth, RTSPClient, RTSPFilterResult, RTSPOnvifServer, RTSPServer, RTSPToken,
+    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
 };
 use log::*;
+use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::CStr,
     fs,
-    sync::Arc,
+    sync::{Arc, RwLock as StdRwLock},
+    time::SystemTime,
 };
 use tokio::{
     sync::RwLock,
@@ -29,8 +38,10 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 
 glib::wrapper! {
-    /// The wrapped RTSPServer
-    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPServer;
+    /// The wrapped RTSPOnvifServer
+    ///
+    /// The ONVIF server makes the clients that understand the backchannel requirement
+    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPOnvifServer, RTSPServer;
 }
 
 impl Default for NeoRtspServer {
@@ -52,13 +63,36 @@ impl NeoRtspServer {
             (RTSP_TOKEN_MEDIA_FACTORY_ROLE, &"anonymous"),
         ]);
         auth.set_default_token(Some(&mut un_authtoken));
+        // Accept the client certificates of the users, the others are left to
+        // the default check against the tls database
+        let server = factory.downgrade();
+        auth.connect_accept_certificate(move |_, _, peer_cert, _| {
+            server
+                .upgrade()
+                .is_some_and(|server| server.imp().certificate_user(peer_cert).is_some())
+        });
         factory.set_auth(Some(&auth));
 
-        factory.connect_client_connected(|_, client| {
+        factory.connect_client_connected(|server, client| {
             client.connect_new_session(|_, session| {
                 log::debug!("New Session");
                 session.set_timeout(5);
             });
+            server.imp().watch_client_certificate(client);
+
+            let key = client.as_ptr() as usize;
+            server
+                .imp()
+                .connected
+                .write()
+                .unwrap()
+                .insert(key, SystemTime::now());
+            let server = server.downgrade();
+            client.connect_closed(move |_| {
+                if let Some(server) = server.upgrade() {
+                    server.imp().connected.write().unwrap().remove(&key);
+                }
+            });
         });
 
         Ok(factory)
@@ -152,6 +186,77 @@ impl NeoRtspServer {
     pub(crate) async fn get_users(&self) -> AnyResult<HashSet<String>> {
         self.imp().get_users().await
     }
+
+    /// The sessions of the connected clients by their mount point
+    pub(crate) fn sessions(&self) -> BTreeMap<String, Vec<RtspSession>> {
+        let connected = self.imp().connected.read().unwrap().clone();
+        let mut mounts = BTreeMap::<String, Vec<RtspSession>>::new();
+        self.client_filter(Some(&mut |_, client| {
+            let ip = client_ip(client);
+            let since = connected.get(&(client.as_ptr() as usize));
+            client.session_filter(Some(&mut |_, session| {
+                let id = session.sessionid().map(|id| id.to_string());
+                for media in session.filter(None) {
+                    let transports = media.transports();
+                    let mount = transports
+                        .iter()
+                        .find_map(|transport| transport.url())
+                        .map(|url| mount_path(&url.decode_path_components()))
+                        .unwrap_or_default();
+                    let bytes_sent = transports
+                        .iter()
+                        .filter_map(|transport| transport.stream()?.rtpsession())
+                        .map(|rtp_session| octets_sent(&rtp_session))
+                        .sum();
+                    mounts.entry(mount).or_default().push(RtspSession {
+                        id: id.clone().unwrap_or_default(),
+                        client: ip.clone(),
+                        connected_secs: since
+                            .and_then(|since| since.elapsed().ok())
+                            .map(|elapsed| elapsed.as_secs()),
+                        bytes_sent,
+                    });
+                }
+                RTSPFilterResult::Keep
+            }));
+            RTSPFilterResult::Keep
+        }));
+        mounts
+    }
+
+    /// Disconnect the client that holds a session, false if none does
+    pub(crate) fn kick(&self, session_id: &str) -> bool {
+        let mut kicked = false;
+        self.client_filter(Some(&mut |_, client| {
+            let owned = client.session_filter(Some(&mut |_, session| {
+                if session.sessionid().as_deref() == Some(session_id) {
+                    RTSPFilterResult::Ref
+                } else {
+                    RTSPFilterResult::Keep
+                }
+            }));
+            if owned.is_empty() {
+                RTSPFilterResult::Keep
+            } else {
+                info!("Disconnecting the rtsp client of session {session_id}");
+                kicked = true;
+                RTSPFilterResult::Remove
+            }
+        }));
+        kicked
+    }
+}
+
+/// A session of an rtsp client on one mount point
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RtspSession {
+    pub(crate) id: String,
+    /// The ip of the client
+    pub(crate) client: Option<String>,
+    /// Seconds since the client connected
+    pub(crate) connected_secs: Option<u64>,
+    /// RTP bytes sent, these are shared with other clients if the media is
+    pub(crate) bytes_sent: u64,
 }
 
 unsafe impl Send for NeoRtspServer {}
@@ -161,17 +266,24 @@ unsafe impl Sync for NeoRtspServer {}
 pub(crate) struct NeoRtspServerImpl {
     threads: RwLock<JoinSet<AnyResult<()>>>,
     users: RwLock<HashMap<String, String>>,
+    /// The users of the client certificates keyed by the DER of the certificate
+    ///
+    /// These are read from the glib callbacks so this is not an async lock
+    client_certs: StdRwLock<HashMap<Vec<u8>, String>>,
+    /// When each client connected keyed by the address of the client
+    connected: StdRwLock<HashMap<usize, SystemTime>>,
     main_loop: RwLock<Option<Arc<MainLoop>>>,
 }
 
 impl ObjectImpl for NeoRtspServerImpl {}
 impl RTSPServerImpl for NeoRtspServerImpl {}
+impl RTSPOnvifServerImpl for NeoRtspServerImpl {}
 
 #[object_subclass]
 impl ObjectSubclass for NeoRtspServerImpl {
     const NAME: &'static str = "NeoRtspServer";
     type Type = NeoRtspServer;
-    type ParentType = RTSPServer;
+    type ParentType = RTSPOnvifServer;
 }
 
 impl NeoRtspServerImpl {
@@ -205,9 +317,73 @@ impl NeoRtspServerImpl {
             self.set_tls(cert_path, tls_client_auth)
                 .with_context(|| "Failed to set up TLS")?;
         }
+        self.set_client_certificates(config)
+    }
+
+    /// Load the client certificates of the users
+    fn set_client_certificates(&self, config: &Config) -> AnyResult<()> {
+        let mut client_certs = HashMap::new();
+        for user in config.users.iter() {
+            if let Some(cert_path) = &user.certificate {
+                let cert = TlsCertificate::from_file(cert_path)
+                    .with_context(|| format!("Invalid client certificate of user {}", user.name))?;
+                let der = cert
+                    .certificate()
+                    .with_context(|| format!("Empty client certificate of user {}", user.name))?;
+                client_certs.insert(der.to_vec(), user.name.clone());
+            }
+        }
+        if !client_certs.is_empty()
+            && (config.certificate.is_none() || config.tls_client_auth == "none")
+        {
+            warn!("The client certificates of the users need a server certificate and tls_client_auth");
+        }
+        *self.client_certs.write().unwrap() = client_certs;
         Ok(())
     }
 
+    /// The user of a client certificate
+    pub(crate) fn certificate_user(&self, cert: &TlsCertificate) -> Option<String> {
+        let der = cert.certificate()?;
+        self.client_certs.read().unwrap().get(&der[..]).cloned()
+    }
+
+    /// Once the client has shown its certificate it is given the role of that
+    /// certificate's user so that it does not need the password
+    pub(crate) fn watch_client_certificate(&self, client: &RTSPClient) {
+        let Some(auth) = self.obj().auth() else {
+            return;
+        };
+        if self.client_certs.read().unwrap().is_empty() || auth.tls_certificate().is_none() {
+            return;
+        }
+        let Some(tls) = client_tls(client) else {
+            return;
+        };
+        let server = self.obj().downgrade();
+        let client = client.downgrade();
+        tls.connect_peer_certificate_notify(move |tls| {
+            let (Some(server), Some(client), Some(peer_cert)) =
+                (server.upgrade(), client.upgrade(), tls.peer_certificate())
+            else {
+                return;
+            };
+            let Some(user) = server.imp().certificate_user(&peer_cert) else {
+                return;
+            };
+            debug!("Client certificate of user {user} accepted");
+            let user_auth = RTSPAuth::new();
+            if let Some(auth) = server.auth() {
+                user_auth.set_tls_certificate(auth.tls_certificate().as_ref());
+                user_auth.set_tls_authentication_mode(auth.tls_authentication_mode());
+            }
+            user_auth.set_supported_methods(RTSPAuthMethod::Basic);
+            let mut token = RTSPToken::new(&[(RTSP_TOKEN_MEDIA_FACTORY_ROLE, &user)]);
+            user_auth.set_default_token(Some(&mut token));
+            client.set_auth(Some(&user_auth));
+        });
+    }
+
     pub(crate) async fn add_user(&self, username: &str, password: &str) -> AnyResult<()> {
         let mut locked_users = self.users.write().await;
         let auth = self.obj().auth().unwrap();
@@ -248,3 +424,84 @@ impl NeoRtspServerImpl {
         Ok(locked_users.keys().cloned().collect())
     }
 }
+
+/// The mount point from the path of a setup url which ends in the stream control
+fn mount_path(components: &[glib::GString]) -> String {
+    let mut components = components
+        .iter()
+        .map(|component| component.as_str())
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>();
+    if components
+        .last()
+        .is_some_and(|last| last.starts_with("stream="))
+    {
+        components.pop();
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// The RTP bytes sent by the internal sources of an rtp session
+fn octets_sent(rtp_session: &Object) -> u64 {
+    if !rtp_session.has_property("stats", None) {
+        return 0;
+    }
+    let Ok(stats) = rtp_session
+        .property_value("stats")
+        .get::<gstreamer::Structure>()
+    else {
+        return 0;
+    };
+    let Ok(sources) = stats.get::<glib::ValueArray>("source-stats") else {
+        return 0;
+    };
+    sources
+        .iter()
+        .filter_map(|source| source.get::<gstreamer::Structure>().ok())
+        .filter(|source| source.get::<bool>("internal").unwrap_or(false))
+        .filter_map(|source| source.get::<u64>("octets-sent").ok())
+        .sum()
+}
+
+/// The ip of a client, the bindings do not have the client's connection
+fn client_ip(client: &RTSPClient) -> Option<String> {
+    // SAFETY: The connection and its ip are owned by the client which
+    // outlives this call, the ip is copied before returning
+    unsafe {
+        let conn =
+            gstreamer_rtsp_server::ffi::gst_rtsp_client_get_connection(client.to_glib_none().0);
+        if conn.is_null() {
+            return None;
+        }
+        let ip = gstreamer_rtsp::ffi::gst_rtsp_connection_get_ip(conn);
+        if ip.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(ip).to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// The TLS connection of a client, the bindings do not have the client's connection
+fn client_tls(client: &RTSPClient) -> Option<TlsConnection> {
+    // SAFETY: The connection is owned by the client which outlives this call
+    // and the returned TLS connection is a borrowed reference that we ref
+    unsafe {
+        let conn =
+            gstreamer_rtsp_server::ffi::gst_rtsp_client_get_connection(client.to_glib_none().0);
+        if conn.is_null() {
+            return None;
+        }
+        let mut error = std::ptr::null_mut();
+        let tls = gstreamer_rtsp::ffi::gst_rtsp_connection_get_tls(conn, &mut error);
+        if !error.is_null() {
+            glib::ffi::g_error_free(error);
+            return None;
+        }
+        if tls.is_null() {
+            None
+        } else {
+            Some(from_glib_none(tls))
+        }
+    }
+}
@@ -20,6 +20,9 @@
 ///
 /// `rtsp://my.ip.address:8554/Garage/subStream`
 ///
+/// When a `namespace` is set in the config the streams are under it
+/// e.g. `rtsp://my.ip.address:8554/site1/Garage`
+///
 /// # Usage
 ///
 /// To start the subcommand use the following in a shell.
@@ -41,11 +44,14 @@
 //   on_client = false
 //   mode = "none"
 //   timeout = 1.0
+//   wake_on_push = false
+//   wake_window = 30
 // ```
 //
 // - When `on_motion` is true the camera will pause streaming when motion is stopped and resume it when motion is started
 // - When `on_client` is true the camera will pause while there is no client connected.
 // - `timeout` handels how long to wait after motion stops before pausing the stream
+// - When `wake_on_push` and `on_motion` are true a push notification or doorbell press wakes the camera and streams it for `wake_window` seconds even without a client
 // - `mode` has the following values:
 //   - `"black"`: Switches to a black screen. Requires more cpu as the stream is fully reencoded
 //   - `"still"`: Switches to a still image. Requires more cpu as the stream is fully reencoded
@@ -58,8 +64,9 @@ use log::*;
 use neolink_core::bc_protocol::StreamKind;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::{fs, time::SystemTime};
 use tokio::{
-    sync::watch::channel as watch,
+    sync::watch::{channel as watch, Receiver as WatchReceiver},
     task::JoinSet,
     time::{interval, Duration},
 };
@@ -67,6 +74,7 @@ use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
 
+mod admin;
 mod cmdline;
 mod factory;
 mod gst;
@@ -76,7 +84,8 @@ use crate::common::{NeoInstance, NeoReactor};
 use factory::*;
 use stream::*;
 
-use super::config::UserConfig;
+use super::config::{Config, UserConfig};
+use crate::quirks;
 pub(crate) use cmdline::Opt;
 use gst::NeoRtspServer;
 
@@ -101,9 +110,24 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
         tokio::select! {
             _ = thread_cancel.cancelled() => AnyResult::Ok(()),
             v = async {
+                // The certificates are also reloaded when their files change
+                // so that renewed certificates are used without a restart
+                let mut modified = certificates_modified(&thread_config.borrow());
+                let mut check = interval(CERTIFICATE_CHECK);
                 loop {
-                    thread_config.changed().await?;
-                    if let Err(e) = thread_rtsp.set_up_tls(&thread_config.borrow().clone()) {
+                    tokio::select! {
+                        v = thread_config.changed() => v?,
+                        _ = check.tick() => {
+                            let now = certificates_modified(&thread_config.borrow());
+                            if now == modified {
+                                continue;
+                            }
+                            info!("TLS certificate changed, reloading");
+                        }
+                    }
+                    let config = thread_config.borrow().clone();
+                    modified = certificates_modified(&config);
+                    if let Err(e) = thread_rtsp.set_up_tls(&config) {
                         log::error!("Could not seup TLS: {e}");
                     }
                 }
@@ -141,6 +165,17 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // Thread for the admin server that lists and disconnects the clients
+    let thread_config = reactor.config().await?;
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = admin::admin_server(thread_config, thread_rtsp) => v,
+        }
+    });
+
     // Startup and stop cameras as they are added/removed to the config
     let mut thread_config = reactor.config().await?;
     let thread_cancel = global_cancel.clone();
@@ -169,8 +204,10 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                             let thread_rtsp2 = thread_rtsp.clone();
                             let thread_reactor2 = thread_reactor.clone();
                             let name = name.clone();
+                            let prefix = thread_config.borrow().path_prefix();
                             set.spawn(async move {
                                 let camera = thread_reactor2.get(&name).await?;
+                                let config = thread_reactor2.config().await?;
                                 tokio::select!(
                                     _ = thread_global_cancel.cancelled() => {
                                         AnyResult::Ok(())
@@ -178,7 +215,7 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                                     _ = local_cancel.cancelled() => {
                                         AnyResult::Ok(())
                                     },
-                                    v = camera_main(camera, &thread_rtsp2) => v,
+                                    v = camera_main(camera, config, &thread_rtsp2, &prefix) => v,
                                 )
                             }) ;
                         }
@@ -230,6 +267,24 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
     Ok(())
 }
 
+/// How often the certificate files are checked for changes
+const CERTIFICATE_CHECK: Duration = Duration::from_secs(60);
+
+/// The modification times of the server and client certificates
+fn certificates_modified(config: &Config) -> Vec<Option<SystemTime>> {
+    config
+        .certificate
+        .iter()
+        .chain(
+            config
+                .users
+                .iter()
+                .filter_map(|user| user.certificate.as_ref()),
+        )
+        .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
 /// This keeps the users in rtsp and the config in sync
 async fn apply_users(rtsp: &NeoRtspServer, curr_users: &HashSet<UserConfig>) -> AnyResult<()> {
     // Add those missing
@@ -248,10 +303,55 @@ async fn apply_users(rtsp: &NeoRtspServer, curr_users: &HashSet<UserConfig>) ->
     Ok(())
 }
 
+/// The users allowed to watch one stream of a camera
+///
+/// `permitted_users` of the camera picks the users and the `cameras` of each
+/// user can then limit that to some cameras or streams
+fn permitted_stream_users(
+    users: &[UserConfig],
+    permitted_users: &Option<Vec<String>>,
+    camera: &str,
+    stream: StreamKind,
+) -> HashSet<String> {
+    let named_users = users
+        .iter()
+        .filter(|user| user.name != "anyone" && user.name != "anonymous")
+        .collect::<Vec<_>>();
+    let can_watch = |name: &str| {
+        named_users
+            .iter()
+            .find(|user| user.name == name)
+            .map_or(true, |user| user.can_watch(camera, stream))
+    };
+    let all_users = named_users
+        .iter()
+        .filter(|user| user.can_watch(camera, stream))
+        .map(|user| user.name.clone());
+    match permitted_users {
+        // If in the camera config there is the user "anyone", or if none is specified but users
+        // are defined at all, then we add all users to the camera's allowed list.
+        Some(p) if p.iter().any(|u| u == "anyone") => all_users.collect(),
+        None if !named_users.is_empty() => all_users.collect(),
+
+        // The user specified permitted_users
+        Some(p) => p.iter().filter(|u| can_watch(u)).cloned().collect(),
+
+        // The user didn't specify permitted_users, and there are none defined anyway
+        None => ["anonymous".to_string()].iter().cloned().collect(),
+    }
+}
+
 /// Top level camera entry point
 ///
 /// It checks which streams are supported and then starts them
-async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
+///
+/// The streams are mounted under `prefix` which is empty unless a namespace is set
+async fn camera_main(
+    camera: NeoInstance,
+    mut config: WatchReceiver<Config>,
+    rtsp: &NeoRtspServer,
+    prefix: &str,
+) -> Result<()> {
     let name = camera.config().await?.borrow().name.clone();
     log::debug!("{name}: Camera Main");
     let later_camera = camera.clone();
@@ -297,8 +397,8 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
     loop {
         let prev_stream_config = camera_config.borrow_and_update().stream;
         let prev_stream_users = camera_config.borrow().permitted_users.clone();
-        let active_streams = prev_stream_config
-            .as_stream_kinds()
+        let prev_users = config.borrow_and_update().users.clone();
+        let active_streams = quirks::stream_kinds(&name, &prev_stream_config)
             .drain(..)
             .collect::<HashSet<_>>();
         let use_splash = camera_config.borrow().use_splash;
@@ -314,27 +414,26 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                     continue;
                 }
             },
+            v = config.wait_for(|config| config.users != prev_users) => {
+                if let Err(e) = v {
+                    AnyResult::Err(e.into())
+                } else {
+                    // The users or what they may watch changed restart
+                    continue;
+                }
+            },
             v = async {
                 // This select handles enabling the right stream
                 log::debug!("{name}: Camera Main::Select Stream");
-                // and setting up the users
-                let all_users = rtsp.get_users().await?.iter().filter(|a| *a != "anyone" && *a != "anonymous").cloned().collect::<HashSet<_>>();
-                let permitted_users: HashSet<String> = match &prev_stream_users {
-                    // If in the camera config there is the user "anyone", or if none is specified but users
-                    // are defined at all, then we add all users to the camera's allowed list.
-                    Some(p) if p.iter().any(|u| u == "anyone") => all_users,
-                    None if !all_users.is_empty() => all_users,
-
-                    // The user specified permitted_users
-                    Some(p) => p.iter().cloned().collect(),
-
-                    // The user didn't specify permitted_users, and there are none defined anyway
-                    None => ["anonymous".to_string()].iter().cloned().collect(),
-                };
-
-                // Create the dummy factory
+                // and setting up the users of each stream
+                let stream_users = |stream| permitted_stream_users(&prev_users, &prev_stream_users, &name, stream);
+                let main_users = stream_users(StreamKind::Main);
+                let sub_users = stream_users(StreamKind::Sub);
+                let extern_users = stream_users(StreamKind::Extern);
+
+                // Create the dummy factory, anyone who may watch any stream may wait on it
                 let dummy_factory = make_dummy_factory(use_splash, splash_pattern).await?;
-                dummy_factory.add_permitted_roles(&permitted_users);
+                dummy_factory.add_permitted_roles(&main_users.iter().chain(sub_users.iter()).chain(extern_users.iter()).collect::<HashSet<_>>());
                 let mut supported_streams_1 = supported_streams.clone();
                 let mut supported_streams_2 = supported_streams.clone();
                 let mut supported_streams_3 = supported_streams.clone();
@@ -343,15 +442,15 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{name}: Camera Main::Select Main");
                         let name = camera.config().await?.borrow().name.clone();
                         let mut paths = vec![
-                            format!("/{name}/main"),
-                            format!("/{name}/Main"),
-                            format!("/{name}/mainStream"),
-                            format!("/{name}/MainStream"),
-                            format!("/{name}/Mainstream"),
-                            format!("/{name}/mainstream"),
+                            format!("{prefix}/{name}/main"),
+                            format!("{prefix}/{name}/Main"),
+                            format!("{prefix}/{name}/mainStream"),
+                            format!("{prefix}/{name}/MainStream"),
+                            format!("{prefix}/{name}/Mainstream"),
+                            format!("{prefix}/{name}/mainstream"),
                         ];
                         paths.push(
-                            format!("/{name}")
+                            format!("{prefix}/{name}")
                         );
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
@@ -368,22 +467,22 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_1.wait_for(|ss| ss.contains(&StreamKind::Main)).await?;
-                        stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &permitted_users, &paths).await
+                        stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &main_users, &paths).await
                     }, if active_streams.contains(&StreamKind::Main) => v,
                     v = async {
                         log::debug!("{name}: Camera Main::Select Sub");
                         let name = camera.config().await?.borrow().name.clone();
                         let mut paths = vec![
-                            format!("/{name}/sub"),
-                            format!("/{name}/Sub"),
-                            format!("/{name}/subStream"),
-                            format!("/{name}/SubStream"),
-                            format!("/{name}/Substream"),
-                            format!("/{name}/substream"),
+                            format!("{prefix}/{name}/sub"),
+                            format!("{prefix}/{name}/Sub"),
+                            format!("{prefix}/{name}/subStream"),
+                            format!("{prefix}/{name}/SubStream"),
+                            format!("{prefix}/{name}/Substream"),
+                            format!("{prefix}/{name}/substream"),
                         ];
                         if ! active_streams.contains(&StreamKind::Main) {
                             paths.push(
-                                format!("/{name}")
+                                format!("{prefix}/{name}")
                             );
                         }
 
@@ -403,22 +502,22 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_2.wait_for(|ss| ss.contains(&StreamKind::Sub)).await?;
-                        stream_main(camera.stream(StreamKind::Sub).await?,camera.clone(), rtsp, &permitted_users, &paths).await
+                        stream_main(camera.stream(StreamKind::Sub).await?,camera.clone(), rtsp, &sub_users, &paths).await
                     }, if active_streams.contains(&StreamKind::Sub) => v,
                     v = async {
                         log::debug!("{name}: Camera Main::Select Extern");
                         let name = camera.config().await?.borrow().name.clone();
                         let mut paths = vec![
-                            format!("/{name}/extern"),
-                            format!("/{name}/Extern"),
-                            format!("/{name}/externStream"),
-                            format!("/{name}/ExternStream"),
-                            format!("/{name}/Externstream"),
-                            format!("/{name}/externstream"),
+                            format!("{prefix}/{name}/extern"),
+                            format!("{prefix}/{name}/Extern"),
+                            format!("{prefix}/{name}/externStream"),
+                            format!("{prefix}/{name}/ExternStream"),
+                            format!("{prefix}/{name}/Externstream"),
+                            format!("{prefix}/{name}/externstream"),
                         ];
                         if ! active_streams.contains(&StreamKind::Main) && ! active_streams.contains(&StreamKind::Sub) {
                             paths.push(
-                                format!("/{name}")
+                                format!("{prefix}/{name}")
                             );
                         }
 
@@ -437,7 +536,7 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_3.wait_for(|ss| ss.contains(&StreamKind::Extern)).await?;
-                        stream_main(camera.stream(StreamKind::Extern).await?,camera.clone(), rtsp, &permitted_users, &paths).await
+                        stream_main(camera.stream(StreamKind::Extern).await?,camera.clone(), rtsp, &extern_users, &paths).await
                     }, if active_streams.contains(&StreamKind::Extern) => v,
                     else => {
                         // all disabled just wait here until config is changed
@@ -1,9 +1,9 @@
 use anyhow::{anyhow, Result};
 use gstreamer::{prelude::*, ClockTime, FlowError};
-use gstreamer_app::AppSrc;
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
 use gstreamer_rtsp_server::prelude::*;
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::{
     sync::{broadcast::channel as broadcast, watch::channel as watch},
     task::JoinSet,
@@ -12,11 +12,13 @@ use tokio::{
 use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 use tokio_util::sync::CancellationToken;
 
-use crate::common::{Permit, StampedData, UseCounter};
+use crate::common::{CameraMetrics, Permit, StampedData, UseCounter};
 use crate::{
     common::{NeoInstance, StreamConfig, StreamInstance},
+    config::{AudioCodec, Latency, MulticastGroup, RtpConfig},
     AnyResult,
 };
+use neolink_core::{bc::xml::TalkConfig, bc_protocol::StreamKind};
 
 use super::{factory::*, gst::NeoRtspServer};
 
@@ -25,6 +27,8 @@ struct PauseAffectors {
     motion: bool,
     push: bool,
     client: bool,
+    /// Woken by `wake_on_push`, streams without a client
+    wake: bool,
 }
 
 /// This handles the stream by activating and deacivating it as required
@@ -39,6 +43,14 @@ pub(super) async fn stream_main(
     let name = camera_config.borrow().name.clone();
 
     let mut curr_pause;
+    let mut curr_max_bitrate;
+    let mut curr_max_main_clients;
+    let mut curr_audio_codec;
+    let mut curr_latency;
+    let mut curr_use_mpegts;
+    let mut curr_backchannel;
+    let mut curr_multicast;
+    let mut curr_rtp;
     loop {
         let this_loop_cancel = CancellationToken::new();
         let _drop_guard = this_loop_cancel.clone().drop_guard();
@@ -71,6 +83,18 @@ pub(super) async fn stream_main(
         }
 
         curr_pause = camera_config.borrow().pause.clone();
+        curr_max_bitrate = camera_config.borrow().max_bitrate;
+        curr_max_main_clients = camera_config.borrow().max_main_clients;
+        curr_audio_codec = camera_config.borrow().audio_codec;
+        curr_latency = camera_config.borrow().latency;
+        curr_use_mpegts = camera_config.borrow().use_mpegts;
+        curr_backchannel = camera_config.borrow().backchannel;
+        curr_multicast = camera_config
+            .borrow()
+            .multicast
+            .group(stream_instance.name)
+            .cloned();
+        curr_rtp = camera_config.borrow().rtp.clone();
 
         let last_stream_config = stream_instance.config.borrow().clone();
         let mut thread_stream_config = stream_instance.config.clone();
@@ -79,6 +103,7 @@ pub(super) async fn stream_main(
             motion: false,
             push: false,
             client: false,
+            wake: false,
         });
         let pause_affector_tx = Arc::new(pause_affector_tx);
 
@@ -89,8 +114,11 @@ pub(super) async fn stream_main(
         let client_count = client_counter.create_deactivated().await?;
 
         // Client count affector
-        if curr_pause.on_motion {
-            let thread_name = name.clone();
+        //
+        // Each stream has its own counter so that the main and sub streams
+        // are paused on their own clients
+        if curr_pause.on_disconnect {
+            let thread_name = format!("{name}::{}", stream_instance.name);
             let client_count = client_counter.create_deactivated().await?;
             let thread_pause_affector_tx = pause_affector_tx.clone();
             let cancel = this_loop_cancel.clone();
@@ -133,7 +161,7 @@ pub(super) async fn stream_main(
                         log::debug!("{}: Activating Motion Pause", &thread_name);
                         loop {
                             motion
-                                .wait_for(|md| matches!(md, crate::common::MdState::Start(_)))
+                                .wait_for(|md| matches!(md, crate::common::MdState::Start(..)))
                                 .await?;
                             log::info!("{}: Enabling Motion", thread_name);
                             thread_pause_affector_tx.send_modify(|current| {
@@ -190,6 +218,50 @@ pub(super) async fn stream_main(
                     } => v,
                 }
             });
+
+            // Wake on push, the push or doorbell press fires the trigger for the window
+            if curr_pause.wake_on_push {
+                log::debug!("{}: Activating Wake On Push", &name);
+                let mut trigger = camera.trigger().await?;
+                let thread_name = name.clone();
+                let thread_pause_affector_tx = pause_affector_tx.clone();
+                let cancel = this_loop_cancel.clone();
+                set.spawn(async move {
+                    tokio::select! {
+                        _ = cancel.cancelled() => AnyResult::Ok(()),
+                        v = async {
+                            loop {
+                                let Some(mut until) = *trigger
+                                    .wait_for(|until| until.is_some_and(|until| until > Instant::now()))
+                                    .await?
+                                else {
+                                    continue;
+                                };
+                                log::info!("{}: Enabling Wake", thread_name);
+                                thread_pause_affector_tx.send_modify(|current| {
+                                    current.wake = true;
+                                });
+                                loop {
+                                    tokio::select! {
+                                        _ = sleep_until(until) => break,
+                                        v = trigger.changed() => {
+                                            v?;
+                                            // Overlapping wakes keep the latest end
+                                            if let Some(new_until) = *trigger.borrow_and_update() {
+                                                until = until.max(new_until);
+                                            }
+                                        }
+                                    }
+                                }
+                                log::info!("{}: Pausing Wake", thread_name);
+                                thread_pause_affector_tx.send_modify(|current| {
+                                    current.wake = false;
+                                });
+                            }
+                        } => v,
+                    }
+                });
+            }
         }
 
         if curr_pause.on_motion || curr_pause.on_disconnect {
@@ -206,13 +278,13 @@ pub(super) async fn stream_main(
                     v = async {
                         while let Some(state) = pause_affector.next().await {
                             if thread_curr_pause.on_motion && thread_curr_pause.on_disconnect {
-                                if state.client && (state.motion || state.push) {
+                                if (state.client && (state.motion || state.push)) || state.wake {
                                     client_activator.activate().await?;
                                 } else {
                                     client_activator.deactivate().await?;
                                 }
                             } else if thread_curr_pause.on_motion {
-                                if state.motion || state.push {
+                                if state.motion || state.push || state.wake {
                                     client_activator.activate().await?;
                                 } else {
                                     client_activator.deactivate().await?;
@@ -290,34 +362,110 @@ pub(super) async fn stream_main(
                 log::info!("{}: Pause Configuration Changed. Reloading Streams", &name);
                 continue;
             },
-            v = stream_run(&name, &stream_instance, rtsp, &last_stream_config, users, paths, client_count) => v,
+            v = camera_config.wait_for(|new_conf| new_conf.max_bitrate != curr_max_bitrate ) => {
+                v?;
+                log::info!("{}: Max Bitrate Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.max_main_clients != curr_max_main_clients ) => {
+                v?;
+                log::info!("{}: Max Main Clients Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.audio_codec != curr_audio_codec ) => {
+                v?;
+                log::info!("{}: Audio Codec Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.latency != curr_latency ) => {
+                v?;
+                log::info!("{}: Latency Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.use_mpegts != curr_use_mpegts ) => {
+                v?;
+                log::info!("{}: MPEG-TS Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.backchannel != curr_backchannel ) => {
+                v?;
+                log::info!("{}: Backchannel Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.multicast.group(stream_instance.name) != curr_multicast.as_ref() ) => {
+                v?;
+                log::info!("{}: Multicast Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = camera_config.wait_for(|new_conf| new_conf.rtp != curr_rtp ) => {
+                v?;
+                log::info!("{}: RTP Configuration Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = stream_run(&name, &stream_instance, &camera, rtsp, &last_stream_config, users, paths, client_count, curr_max_bitrate, curr_max_main_clients, curr_audio_codec, curr_latency, curr_use_mpegts, curr_backchannel, curr_multicast.as_ref(), &curr_rtp) => v,
         };
     }
 }
 
 /// This handles the stream itself by creating the factory and pushing messages into it
+#[allow(clippy::too_many_arguments)]
 async fn stream_run(
     name: &str,
     stream_instance: &StreamInstance,
+    camera: &NeoInstance,
     rtsp: &NeoRtspServer,
     stream_config: &StreamConfig,
     users: &HashSet<String>,
     paths: &[String],
     client_count: Permit,
+    max_bitrate: Option<u32>,
+    max_main_clients: Option<u32>,
+    audio_codec: AudioCodec,
+    latency: Latency,
+    use_mpegts: bool,
+    backchannel: bool,
+    multicast: Option<&MulticastGroup>,
+    rtp: &RtpConfig,
 ) -> AnyResult<()> {
-    let vidstream = stream_instance.vid.resubscribe();
-    let audstream = stream_instance.aud.resubscribe();
-    let vid_history = stream_instance.vid_history.clone();
-    let aud_history = stream_instance.aud_history.clone();
+    let metrics = camera.metrics();
+    // The backchannel audio is encoded for the camera's talk config
+    let talk_config = match backchannel {
+        true => match crate::talk::talk_config(camera).await {
+            Ok(talk_config) => Some(talk_config),
+            Err(e) => {
+                log::warn!("{name}: No backchannel as the camera cannot talk: {e:?}");
+                None
+            }
+        },
+        false => None,
+    };
+    let backchannel = talk_config.as_ref().map(|talk_config| Backchannel {
+        block_align: (talk_config.audio_config.length_per_encoder / 2) + 4,
+        sample_rate: talk_config.audio_config.sample_rate,
+    });
+    // One clone per client that is served the mainStream
+    let main_clients = Arc::new(());
+    let power = camera.power().await?;
 
     // Finally ready to create the factory and connect the stream
     let mounts = rtsp
         .mount_points()
         .ok_or(anyhow!("RTSP server lacks mount point"))?;
     // Create the factory
-    let (factory, mut client_rx) = make_factory(stream_config).await?;
+    let (factory, mut client_rx) = make_factory(
+        stream_config,
+        audio_codec,
+        latency,
+        use_mpegts,
+        rtp,
+        backchannel,
+    )
+    .await?;
 
     factory.add_permitted_roles(users);
+    if let Some(group) = multicast {
+        set_multicast(&factory, group)?;
+    }
 
     for path in paths.iter() {
         log::debug!("Path: {}", path);
@@ -334,6 +482,44 @@ async fn stream_run(
         // New media created
         let vid = client_data.vid.take().map(|data| data.app);
         let aud = client_data.aud.take().map(|data| data.app);
+        if let (Some(sink), Some(talk_config)) = (client_data.talk.take(), talk_config.as_ref()) {
+            let thread_name = name.to_string();
+            let thread_camera = camera.clone();
+            let thread_talk_config = talk_config.clone();
+            let thread_stream_cancel = stream_cancel.clone();
+            set.spawn(async move {
+                let r = tokio::select! {
+                    _ = thread_stream_cancel.cancelled() => AnyResult::Ok(()),
+                    v = send_to_camera(sink, &thread_camera, thread_talk_config) => v,
+                };
+                log::debug!("{thread_name}: Backchannel End: {r:?}");
+                AnyResult::Ok(())
+            });
+        }
+
+        let overflow = match max_main_clients {
+            Some(max) if stream_instance.name == StreamKind::Main => {
+                Arc::strong_count(&main_clients) > max as usize
+            }
+            _ => false,
+        };
+        // New clients get the subStream while the battery is low
+        let overflow =
+            overflow || (stream_instance.name == StreamKind::Main && power.borrow().sub_stream);
+        let sub_instance = match overflow {
+            true => overflow_stream(name, camera, stream_config).await,
+            false => None,
+        };
+        // Held by the video task so the count drops when the client leaves
+        let main_client = sub_instance.is_none().then(|| main_clients.clone());
+        let source = sub_instance.as_ref().unwrap_or(stream_instance);
+        let vidstream = source.vid.resubscribe();
+        let audstream = source.aud.resubscribe();
+        let vid_history = source.vid_history.clone();
+        let aud_history = source.aud_history.clone();
+        let thread_name = format!("{name}::{}", source.name);
+        // The video and audio of a client share one timeline to stay in sync
+        let timeline = Arc::new(Timeline::default());
 
         // This is the data that gets sent to gstreamer thread
         // It represents the combination of the camera stream and the appsrc seek messages
@@ -428,13 +614,24 @@ async fn stream_run(
         let vid_data_rx = BroadcastStream::new(vid_data_rx).filter(|f| f.is_ok()); // Filter to ignore lagged
         let thread_vid = vid.clone();
         let mut thread_client_count = client_count.subscribe();
+        let thread_metrics = metrics.clone();
+        let thread_timeline = timeline.clone();
+        // The main stream keeps its keyframes, the others are paused
+        let keyframes_only = stream_instance.name == StreamKind::Main;
         log::debug!("stream_config.fps: {}", stream_config.fps);
         // let fallback_time = Duration::from_secs(3);
         // let fallback_framerate =
         //     Duration::from_millis(1000u64 / std::cmp::max(stream_config.fps as u64, 5u64));
+        // A client moved to the subStream keeps that running rather than this stream
+        let counts_client = sub_instance.is_none();
         if let Some(thread_vid) = thread_vid {
             set.spawn(async move {
-                thread_client_count.activate().await?;
+                // Keeps the subStream running for an overflow client
+                let _source = (sub_instance, main_client);
+                if counts_client {
+                    thread_client_count.activate().await?;
+                }
+                let _client = thread_metrics.rtsp_client();
                 let r = tokio::select! {
                     _ = thread_stream_cancel.cancelled() => {
                         AnyResult::Ok(())
@@ -443,15 +640,22 @@ async fn stream_run(
                         // repeat_keyframe(
                             frametime_stream(
                                 hold_stream(
-                                    wait_for_keyframe(
-                                        vid_data_rx,
+                                    bandwidth_cap(
+                                        wait_for_keyframe(
+                                            vid_data_rx,
+                                        ),
+                                        thread_name,
+                                        thread_metrics.clone(),
+                                        max_bitrate,
+                                        keyframes_only,
                                     )
                                 )
                             ),
                         //     fallback_time,
                         //     fallback_framerate,
                         // ),
-                        &thread_vid) => {
+                        &thread_vid,
+                        &thread_timeline) => {
                         v
                     },
                 };
@@ -466,6 +670,7 @@ async fn stream_run(
         let thread_stream_cancel = stream_cancel.clone();
         let aud_data_rx = BroadcastStream::new(aud_data_rx).filter(|f| f.is_ok()); // Filter to ignore lagged
         let thread_aud = aud.clone();
+        let thread_timeline = timeline.clone();
         if let Some(thread_aud) = thread_aud {
             set.spawn(async move {
                 let r = tokio::select! {
@@ -479,7 +684,7 @@ async fn stream_run(
                                     aud_data_rx
                                 )
                             )
-                        ), &thread_aud) => {
+                        ), &thread_aud, &thread_timeline) => {
                         v
                     },
                 };
@@ -525,6 +730,50 @@ fn get_runtime(app: &AppSrc) -> Option<Duration> {
     None
 }
 
+// The subStream for a client over the max_main_clients of the mainStream or
+// while the battery policy asks for it
+//
+// The factory of the mainStream path was built for its formats so the
+// subStream is only used if its formats are the same. Otherwise the client
+// gets the mainStream anyway
+async fn overflow_stream(
+    name: &str,
+    camera: &NeoInstance,
+    stream_config: &StreamConfig,
+) -> Option<StreamInstance> {
+    let sub = match camera.stream(StreamKind::Sub).await {
+        Ok(sub) => sub,
+        Err(e) => {
+            log::warn!("{name}: Could not get the subStream for an extra client: {e:?}");
+            return None;
+        }
+    };
+    let mut sub_config = sub.config.clone();
+    let ready = matches!(
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            sub_config.wait_for(|config| config.vid_ready()),
+        )
+        .await,
+        Ok(Ok(_))
+    );
+    if !ready {
+        log::warn!("{name}: The subStream is not ready, serving the mainStream");
+        return None;
+    }
+    let sub_config = sub_config.borrow().clone();
+    if sub_config.vid_format != stream_config.vid_format
+        || sub_config.aud_format != stream_config.aud_format
+    {
+        log::warn!(
+            "{name}: The subStream formats differ from the mainStream, serving the mainStream"
+        );
+        return None;
+    }
+    log::info!("{name}: Too many mainStream clients, serving the subStream");
+    Some(sub)
+}
+
 // This ensures we start at a keyframe
 fn wait_for_keyframe<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     mut stream: T,
@@ -542,6 +791,63 @@ fn wait_for_keyframe<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     })
 }
 
+// Keep the video sent to the rtsp clients of a camera under its max_bitrate
+//
+// Over the cap the main stream only sends keyframes and the other streams
+// are paused. After a frame has been dropped the stream only resumes on a
+// keyframe so that a frame is never sent without the ones it references
+fn bandwidth_cap<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
+    mut stream: T,
+    name: String,
+    metrics: Arc<CameraMetrics>,
+    max_bitrate: Option<u32>,
+    keyframes_only: bool,
+) -> impl Stream<Item = AnyResult<StampedData>> + Unpin {
+    Box::pin(async_stream::stream! {
+        const WINDOW: Duration = Duration::from_secs(1);
+        let mut window_start = (Instant::now(), metrics.rtsp_bytes());
+        let mut kbps = 0;
+        let mut capped = false;
+        let mut need_key = false;
+        while let Some(frame) = stream.next().await {
+            if let Ok(frame) = frame {
+                // The bytes are shared by all streams and clients of the camera
+                let elapsed = window_start.0.elapsed();
+                if elapsed >= WINDOW {
+                    let bytes = metrics.rtsp_bytes();
+                    kbps = (bytes - window_start.1) * 8 / elapsed.as_millis() as u64;
+                    window_start = (Instant::now(), bytes);
+                }
+                let over = max_bitrate.map(|max| kbps > max as u64).unwrap_or(false);
+                if over != capped {
+                    capped = over;
+                    match (capped, keyframes_only) {
+                        (true, true) => log::info!(
+                            "{name}: Over the max bitrate at {kbps} kbit/s, only sending keyframes"
+                        ),
+                        (true, false) => {
+                            log::info!("{name}: Over the max bitrate at {kbps} kbit/s, pausing")
+                        }
+                        (false, _) => log::info!("{name}: Back under the max bitrate"),
+                    }
+                }
+
+                let send = match frame.keyframe {
+                    true => !over || keyframes_only,
+                    false => !over && !need_key,
+                };
+                if send {
+                    need_key &= !frame.keyframe;
+                    metrics.rtsp_sent(frame.data.len());
+                    yield Ok(frame);
+                } else {
+                    need_key = true;
+                }
+            }
+        }
+    })
+}
+
 // Take a stream of stamped data and release them
 // in waves when a new key frame is found
 // this ensure that the last frame sent is always an IFrame
@@ -659,27 +965,62 @@ fn repeat_keyframe<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     })
 }
 
+/// Places the camera timestamps of the frames on the running time of the appsrcs
+///
+/// The first frame is put at the running time and the ones after it keep
+/// their distance from it. If that gets too far from the running time, such
+/// as after the stream was held, the frames are put on the running time again
+#[derive(Default)]
+struct Timeline {
+    /// The camera timestamp and running time of the same instant
+    anchor: Mutex<Option<(Duration, Duration)>>,
+}
+
+impl Timeline {
+    /// How far the frames may be from the running time before moving them onto it
+    const MAX_SKEW: Duration = Duration::from_secs(2);
+
+    fn pts(&self, ts: Duration, runtime: Duration) -> Duration {
+        let mut anchor = self.anchor.lock().unwrap();
+        let pts = anchor
+            .and_then(|(anchor_ts, anchor_rt)| Some(anchor_rt + ts.checked_sub(anchor_ts)?))
+            .filter(|pts| pts.abs_diff(runtime) <= Self::MAX_SKEW);
+        match pts {
+            Some(pts) => pts,
+            None => {
+                *anchor = Some((ts, runtime));
+                runtime
+            }
+        }
+    }
+}
+
 /// Takes a stream and sends it to an appsrc
 async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     mut stream: T,
     appsrc: &AppSrc,
+    timeline: &Timeline,
 ) -> AnyResult<()> {
     let mut rt = Duration::ZERO;
+    let mut prev_pts = None;
     while let Some(Ok(data)) = stream.next().await {
         check_live(appsrc)?; // Stop if appsrc is dropped
         if let Some(rt_i) = get_runtime(appsrc) {
             rt = rt_i;
         }
+        // Buffers of an appsrc must not go backwards
+        let pts = timeline.pts(data.ts, rt).max(prev_pts.unwrap_or_default());
+        prev_pts = Some(pts);
         let buf = {
-            let mut gst_buf = gstreamer::Buffer::with_size(data.data.len()).unwrap();
+            // Wraps the frame without a copy, the memory is read only which is
+            // all that the payloaders need
+            let mut gst_buf = gstreamer::Buffer::from_slice(data.data);
             {
                 let gst_buf_mut = gst_buf.get_mut().unwrap();
-                // log::debug!("Setting PTS: {ts:?}, Runtime: {ts:?}");
-                let time = ClockTime::from_useconds(rt.as_micros() as u64);
+                // log::debug!("Setting PTS: {pts:?}, Runtime: {rt:?}");
+                let time = ClockTime::from_useconds(pts.as_micros() as u64);
                 gst_buf_mut.set_dts(time);
                 gst_buf_mut.set_pts(time);
-                let mut gst_buf_data = gst_buf_mut.map_writable().unwrap();
-                gst_buf_data.copy_from_slice(data.data.as_slice());
             }
             gst_buf
         };
@@ -695,3 +1036,39 @@ async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     }
     Ok(())
 }
+
+/// Plays the backchannel audio of a client on the camera's speaker
+///
+/// The camera talks until the client's media is destroyed which drops the
+/// sender in the callbacks of the appsink
+async fn send_to_camera(
+    sink: AppSink,
+    camera: &NeoInstance,
+    talk_config: TalkConfig,
+) -> AnyResult<()> {
+    let (tx, rx) = crossbeam_channel::bounded(30);
+    sink.set_callbacks(
+        AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                // Drop the audio rather than hold up the client when the camera is behind
+                let _ = tx.try_send(map.as_slice().to_vec());
+                Ok(gstreamer::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+    drop(sink);
+
+    camera
+        .run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        })
+        .await
+}
@@ -0,0 +1,50 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum CmdService {
+    Http,
+    Https,
+    Rtsp,
+    Rtmp,
+    Onvif,
+}
+
+/// The services command will control the network services of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+
+    #[command(subcommand)]
+    pub cmd: ServicesCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum ServicesCommand {
+    /// Print the ports and state of the services
+    Get,
+    /// Turn one service on or off
+    Set {
+        #[clap(value_enum)]
+        service: CmdService,
+        #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+        on: bool,
+    },
+    /// Turn all of the services on or off in one message
+    SetAll {
+        #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+        on: bool,
+    },
+}
@@ -0,0 +1,86 @@
+///
+/// # Neolink Services
+///
+/// This module handles the network services of the camera such as its
+/// http, rtsp and onvif servers
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the ports and state of the services
+/// neolink services --config=config.toml CameraName get
+/// # To turn the rtsp server on
+/// neolink services --config=config.toml CameraName set rtsp on
+/// # To turn all of the services off in one message
+/// neolink services --config=config.toml CameraName set-all off
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::{Confirmation, Service};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+use cmdline::{CmdService, ServicesCommand};
+
+impl From<CmdService> for Service {
+    fn from(service: CmdService) -> Self {
+        match service {
+            CmdService::Http => Service::Http,
+            CmdService::Https => Service::Https,
+            CmdService::Rtsp => Service::Rtsp,
+            CmdService::Rtmp => Service::Rtmp,
+            CmdService::Onvif => Service::Onvif,
+        }
+    }
+}
+
+/// Entry point for the services subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let services = match opt.cmd {
+        ServicesCommand::Get => {
+            let server_port = camera
+                .run_task(|cam| {
+                    Box::pin(
+                        async move { cam.get_services().await.context(tr!("services.get_failed")) },
+                    )
+                })
+                .await?;
+            let server_port_ser = String::from_utf8(
+                yaserde::ser::serialize_with_writer(&server_port, vec![], &Default::default())
+                    .expect("Should Ser the struct"),
+            )
+            .expect("Should be UTF8");
+            println!("{}", server_port_ser);
+            return Ok(());
+        }
+        ServicesCommand::Set { service, on } => vec![(service.into(), on)],
+        ServicesCommand::SetAll { on } => Service::ALL
+            .iter()
+            .map(|service| (*service, on))
+            .collect::<Vec<_>>(),
+    };
+
+    let confirmation = camera
+        .run_task(|cam| {
+            let services = services.clone();
+            Box::pin(async move {
+                cam.set_services_bulk(&services)
+                    .await
+                    .context(tr!("services.set_failed"))
+            })
+        })
+        .await?;
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("services.set_unconfirmed"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Take a snapshot whenever the camera detects motion
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to watch. Must be a name in the config
+    pub camera: String,
+    /// The directory to write the snapshots to, overrides the config
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub dir: Option<PathBuf>,
+    /// Url to POST the snapshots to, overrides the config
+    #[arg(short, long)]
+    pub webhook: Option<String>,
+}
@@ -0,0 +1,155 @@
+///
+/// # Neolink Snapshot Daemon
+///
+/// This module takes a jpeg snapshot each time the camera detects motion
+///
+/// The snapshots are written into a directory, POSTed to a webhook or both.
+/// One snapshot is taken at the start of each motion event using the
+/// camera's SNAP command
+///
+/// # Usage
+///
+/// ```bash
+/// neolink snapshot-daemon --config=config.toml --dir=/snapshots CameraName
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [snapshot]
+///   dir = "/snapshots"
+///   webhook = "http://homeassistant.local:8123/api/webhook/neolink"
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use std::{io::ErrorKind, path::Path, time::SystemTime};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, time::Duration};
+
+mod cmdline;
+
+use crate::common::{MdState, NeoInstance, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the snapshot-daemon subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let mut settings = reactor.config().await?.borrow().snapshot.clone();
+    if let Some(dir) = opt.dir {
+        settings.dir = Some(dir);
+    }
+    if let Some(webhook) = opt.webhook {
+        settings.webhook = Some(webhook);
+    }
+    if settings.dir.is_none() && settings.webhook.is_none() {
+        return Err(anyhow!(
+            "Snapshots need somewhere to go, set a dir or webhook in [snapshot] or use --dir or --webhook"
+        ));
+    }
+
+    let camera = reactor.get(&opt.camera).await?;
+    let dir = match settings.dir {
+        Some(dir) => {
+            let dir = dir.join(&opt.camera);
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            Some(dir)
+        }
+        None => None,
+    };
+    let client = reqwest::Client::new();
+
+    info!("{}: Taking snapshots on motion", opt.camera);
+    let mut motion = camera.motion().await?;
+    let mut active = false;
+    loop {
+        // The state is updated on every alarm so only act on the start of an event
+        let started = matches!(*motion.borrow_and_update(), MdState::Start(..));
+        if started && !active {
+            let sent = snapshot(
+                &camera,
+                &opt.camera,
+                dir.as_deref(),
+                settings.webhook.as_deref(),
+                &client,
+            )
+            .await;
+            if let Err(e) = sent {
+                warn!(
+                    "{}: Failed to handle the motion snapshot: {e:?}",
+                    opt.camera
+                );
+            }
+        }
+        active = started;
+        motion.changed().await?;
+    }
+}
+
+/// Take a snapshot and send it everywhere that was configured
+async fn snapshot(
+    camera: &NeoInstance,
+    name: &str,
+    dir: Option<&Path>,
+    webhook: Option<&str>,
+    client: &reqwest::Client,
+) -> Result<()> {
+    let time = SystemTime::now();
+    let jpeg = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+        .await
+        .context("Failed to take a snapshot")?;
+    let stamp = humantime::format_rfc3339_seconds(time).to_string();
+
+    if let Some(dir) = dir {
+        let path = write(dir, &stamp, &jpeg).await?;
+        info!("{name}: Saved motion snapshot {}", path.display());
+    }
+    if let Some(url) = webhook {
+        client
+            .post(url)
+            .timeout(Duration::from_secs(10))
+            .header(reqwest::header::CONTENT_TYPE, "image/jpeg")
+            .header("X-Neolink-Camera", name)
+            .header("X-Neolink-Time", &stamp)
+            .body(jpeg)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to POST the snapshot to {url}"))?;
+        info!("{name}: Sent motion snapshot to the webhook");
+    }
+    Ok(())
+}
+
+/// Write the jpeg to a new file named after the time such as
+/// `2023-10-17T10-15-00Z.jpeg`
+async fn write(dir: &Path, stamp: &str, jpeg: &[u8]) -> Result<std::path::PathBuf> {
+    // `:` is not allowed in windows file names
+    let stamp = stamp.replace(':', "-");
+    let mut attempt = 0;
+    loop {
+        let path = if attempt == 0 {
+            dir.join(format!("{stamp}.jpeg"))
+        } else {
+            dir.join(format!("{stamp}_{attempt}.jpeg"))
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(mut file) => {
+                file.write_all(jpeg).await?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+            }
+        }
+    }
+}
@@ -16,10 +16,12 @@
 /// ```
 ///
 use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
 
 mod cmdline;
 
 use crate::common::NeoReactor;
+use crate::i18n::tr;
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the ledstatus subcommand
@@ -29,16 +31,19 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
     let on = opt.on;
-    camera
+    let confirmation = camera
         .run_task(|camera| {
             Box::pin(async move {
                 camera
                     .led_light_set(on)
                     .await
-                    .context("Unable to set camera light state")
+                    .context(tr!("statusled.set_failed"))
             })
         })
         .await?;
+    if confirmation == Confirmation::AssumedOk {
+        log::warn!("{}", tr!("statusled.set_unconfirmed"));
+    }
 
     Ok(())
 }
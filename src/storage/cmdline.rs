@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// The storage command reports the SD cards of the camera and can format them
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Format the card with this id, this erases all recordings on it
+    #[arg(long, value_name = "ID", requires = "yes")]
+    pub format: Option<u8>,
+    /// Confirm that the card should be formatted
+    #[arg(long)]
+    pub yes: bool,
+}
@@ -0,0 +1,82 @@
+///
+/// # Neolink Storage
+///
+/// This module reports the capacity, free space and state of the SD cards
+/// of the camera and can format them
+///
+/// Formatting erases all recordings so it also needs `--yes`
+///
+/// # Usage
+///
+/// ```bash
+/// # Print the SD cards
+/// neolink storage --config=config.toml CameraName
+/// # Format the SD card with id 0
+/// neolink storage --config=config.toml CameraName --format 0 --yes
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::Confirmation;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the storage subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if let Some(number) = opt.format {
+        let confirmation = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.format_storage(number)
+                        .await
+                        .context(tr!("storage.format_failed"))
+                })
+            })
+            .await?;
+        if confirmation == Confirmation::AssumedOk {
+            log::warn!("{}", tr!("storage.format_unconfirmed"));
+        }
+        return Ok(());
+    }
+
+    let hdd_info_list = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.get_storage_info()
+                    .await
+                    .context(tr!("storage.get_failed"))
+            })
+        })
+        .await?;
+
+    if hdd_info_list.hdd_info.is_empty() {
+        println!("{}", tr!("storage.none"));
+    }
+    for hdd in hdd_info_list.hdd_info.iter() {
+        let free = hdd
+            .remain_size
+            .map(|free| free.to_string())
+            .unwrap_or_else(|| "?".to_string());
+        println!(
+            "{}: {} {}/{} MB free, {}",
+            hdd.number,
+            hdd.storage_type.as_deref().unwrap_or("sd"),
+            free,
+            hdd.capacity,
+            if hdd.healthy() {
+                "ok"
+            } else {
+                "not mounted or formatted"
+            }
+        );
+    }
+
+    Ok(())
+}
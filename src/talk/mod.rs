@@ -11,13 +11,17 @@
 /// neolink talk --config=config.toml --adpcm-file=data.adpcm --sample-rate=16000 --block-size=512 CameraName
 /// ```
 ///
+/// The same can be done over mqtt with the `control/talk` topic, see
+/// [`talk`]
+///
 use anyhow::{anyhow, Context, Result};
 use neolink_core::bc::xml::TalkConfig;
 
 mod cmdline;
-mod gst;
 
-use crate::common::NeoReactor;
+use crate::common::{NeoInstance, NeoReactor};
+use crate::i18n::tr;
+use crate::media::{Backend, MediaBackend, TalkInput};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the talk subcommand
@@ -25,6 +29,50 @@ pub(crate) use cmdline::Opt;
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
+    let input = match (&opt.file_path, &opt.microphone) {
+        (Some(path), false) => TalkInput::File(path),
+        (None, true) => TalkInput::Device(&opt.input_src),
+        _ => unreachable!(),
+    };
+    talk(&camera, input, opt.volume).await
+}
+
+/// Play the audio of `input` through the camera's speaker until it ends
+pub(crate) async fn talk(camera: &NeoInstance, input: TalkInput<'_>, volume: f32) -> Result<()> {
+    let talk_config = talk_config(camera).await?;
+    let block_size = (talk_config.audio_config.length_per_encoder / 2) + 4;
+    let sample_rate = talk_config.audio_config.sample_rate;
+
+    let (mut set, rx) = match input {
+        TalkInput::File(path) => Backend::talk_source(input, volume, block_size, sample_rate)
+            .with_context(|| tr!("talk.file_failed", path = path.display()))?,
+        TalkInput::Device(_) => Backend::talk_source(input, volume, block_size, sample_rate)
+            .context(tr!("talk.microphone_failed"))?,
+        TalkInput::Uri(uri) => Backend::talk_source(input, volume, block_size, sample_rate)
+            .with_context(|| tr!("talk.uri_failed", uri = uri))?,
+    };
+
+    camera
+        .run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        })
+        .await
+        .context(tr!("talk.ended_early"))?;
+
+    drop(rx);
+    while set.join_next().await.is_some() {}
+
+    Ok(())
+}
+
+/// The config of the first talk ability of the camera, the adpcm sent to it
+/// must use its block size and sample rate
+pub(crate) async fn talk_config(camera: &NeoInstance) -> Result<TalkConfig> {
     let config = camera.config().await?.borrow().clone();
     let name = config.name.clone();
 
@@ -36,13 +84,13 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             })
         })
         .await
-        .with_context(|| format!("Camera {} does not support talk", name))?;
+        .with_context(|| tr!("talk.unsupported", camera = name))?;
 
     if talk_ability.duplex_list.is_empty()
         || talk_ability.audio_stream_mode_list.is_empty()
         || talk_ability.audio_config_list.is_empty()
     {
-        return Err(anyhow!("Camera {} does not support talk", name));
+        return Err(anyhow!(tr!("talk.unsupported", camera = name)));
     }
 
     // Just copy that data from the first talk ability in the config have never seen more
@@ -61,45 +109,9 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         ..Default::default()
     };
 
-    let block_size = (talk_config.audio_config.length_per_encoder / 2) + 4;
-    let sample_rate = talk_config.audio_config.sample_rate;
-    if block_size == 0 || sample_rate == 0 {
-        return Err(anyhow!(
-            "The camera {} does not support talk with adpcm",
-            name
-        ));
+    if talk_config.audio_config.length_per_encoder == 0 || talk_config.audio_config.sample_rate == 0
+    {
+        return Err(anyhow!(tr!("talk.unsupported_adpcm", camera = name)));
     }
-
-    let (mut set, rx) = match (&opt.file_path, &opt.microphone) {
-        (Some(path), false) => gst::from_input(
-            &format!(
-                "filesrc location={}",
-                path.to_str().expect("File path not UTF8 complient")
-            ),
-            opt.volume,
-            block_size,
-            sample_rate,
-        )
-        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
-        (None, true) => gst::from_input(&opt.input_src, opt.volume, block_size, sample_rate)
-            .context("Failed to setup gst using the microphone")?,
-        _ => unreachable!(),
-    };
-
-    camera
-        .run_task(|cam| {
-            let rx = rx.clone();
-            let talk_config = talk_config.clone();
-            Box::pin(async move {
-                cam.talk_stream(rx, talk_config).await?;
-                Ok(())
-            })
-        })
-        .await
-        .context("Talk stream ended early")?;
-
-    drop(rx);
-    while set.join_next().await.is_some() {}
-
-    Ok(())
+    Ok(talk_config)
 }
@@ -0,0 +1,17 @@
+use clap::Parser;
+
+/// The test-notify command asks the camera to deliver test notifications
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Send a test push notification
+    #[arg(long)]
+    pub push: bool,
+    /// Send a test email
+    #[arg(long)]
+    pub email: bool,
+    /// Upload a test file to the FTP server
+    #[arg(long)]
+    pub ftp: bool,
+}
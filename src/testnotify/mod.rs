@@ -0,0 +1,62 @@
+///
+/// # Neolink Test Notify
+///
+/// This module asks the camera to deliver test notifications so that its
+/// email, FTP and push settings can be checked without the Reolink app
+///
+/// Each test prints whether the camera attempted the delivery. Without any
+/// options every kind is tested
+///
+/// # Usage
+///
+/// ```bash
+/// neolink test-notify --config=config.toml CameraName --email --push
+/// ```
+///
+use anyhow::{anyhow, Result};
+use neolink_core::bc_protocol::{Confirmation, NotifyTest};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+use crate::i18n::tr;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the test-notify subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let all = !opt.push && !opt.email && !opt.ftp;
+    let tests = [
+        (NotifyTest::Push, opt.push),
+        (NotifyTest::Email, opt.email),
+        (NotifyTest::Ftp, opt.ftp),
+    ]
+    .iter()
+    .filter(|(_, selected)| all || *selected)
+    .map(|(test, _)| *test)
+    .collect::<Vec<_>>();
+
+    let mut failed = 0;
+    for test in tests {
+        let res = camera
+            .run_task(|cam| Box::pin(async move { Ok(cam.test_notify(test).await?) }))
+            .await;
+        match res {
+            Ok(Confirmation::Confirmed) => println!("{}: {}", test, tr!("testnotify.attempted")),
+            Ok(Confirmation::AssumedOk) => println!("{}: {}", test, tr!("testnotify.no_reply")),
+            Err(e) => {
+                failed += 1;
+                log::debug!("{test}: {e:?}");
+                println!("{}: {}", test, tr!("testnotify.failed"));
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!(tr!("testnotify.some_failed", count = failed)));
+    }
+    Ok(())
+}
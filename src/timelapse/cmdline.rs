@@ -0,0 +1,20 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Take a snapshot at a fixed interval for a timelapse
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The names of the cameras. Must be names in the config
+    #[arg(required = true)]
+    pub cameras: Vec<String>,
+    /// The directory to write the frames and videos to, overrides the config
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub dir: Option<PathBuf>,
+    /// Seconds between two frames, overrides the config
+    #[arg(long)]
+    pub interval: Option<u64>,
+    /// Turn the frames of each day into an mp4, overrides the config
+    #[arg(long)]
+    pub assemble: bool,
+}
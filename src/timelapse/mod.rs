@@ -0,0 +1,195 @@
+///
+/// # Neolink Timelapse
+///
+/// This module takes a jpeg snapshot of each camera at a fixed interval
+///
+/// The frames of a day are written into a directory named after the date
+/// such as `/timelapse/CameraName/2023-10-17/10-15-00.jpeg`. With
+/// `assemble` the frames of each day are turned into
+/// `/timelapse/CameraName/2023-10-17.mp4` once the day is over, which needs
+/// the `gstreamer` feature. Days that were missed while neolink was not
+/// running are assembled when it starts.
+///
+/// The cameras are used through the reactor so a lost connection only
+/// costs the frames until the camera is back.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink timelapse --config=config.toml --dir=/timelapse --interval=60 CameraName
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [timelapse]
+///   dir = "/timelapse"
+///   interval = 60
+///   assemble = true
+///   fps = 25
+///   delete_frames = true
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::*;
+use std::path::{Path, PathBuf};
+use tokio::{
+    task::JoinSet,
+    time::{interval, Duration, MissedTickBehavior},
+};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::TimelapseConfig,
+    media::{Backend, MediaBackend},
+};
+pub(crate) use cmdline::Opt;
+
+/// Format of the directory that holds the frames of one day
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// Entry point for the timelapse subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let mut settings = reactor.config().await?.borrow().timelapse.clone();
+    if let Some(dir) = opt.dir {
+        settings.dir = Some(dir);
+    }
+    if let Some(interval) = opt.interval {
+        settings.interval = interval.max(1);
+    }
+    if opt.assemble {
+        settings.assemble = true;
+    }
+    let dir = settings.dir.clone().ok_or_else(|| {
+        anyhow!("The timelapse needs somewhere to go, set a dir in [timelapse] or use --dir")
+    })?;
+
+    let mut set = JoinSet::<Result<()>>::new();
+    for name in opt.cameras {
+        let camera = reactor.get(&name).await?;
+        let camera_dir = dir.join(&name);
+        tokio::fs::create_dir_all(&camera_dir)
+            .await
+            .with_context(|| format!("Failed to create {}", camera_dir.display()))?;
+        let settings = settings.clone();
+        set.spawn(async move { capture(camera, name, camera_dir, settings).await });
+    }
+
+    // The camera tasks only end on error
+    while let Some(res) = set.join_next().await {
+        res??;
+    }
+    Ok(())
+}
+
+/// Take the frames of one camera forever
+async fn capture(
+    camera: NeoInstance,
+    name: String,
+    camera_dir: PathBuf,
+    settings: TimelapseConfig,
+) -> Result<()> {
+    info!(
+        "{name}: Taking a timelapse frame every {}s",
+        settings.interval
+    );
+    let mut today = Local::now().format(DAY_FORMAT).to_string();
+    if settings.assemble {
+        for day in unassembled_days(&camera_dir, &today).await? {
+            spawn_assemble(&name, &camera_dir, day, &settings);
+        }
+    }
+
+    let mut ticker = interval(Duration::from_secs(settings.interval));
+    // A frame late is better than a burst of them after a reconnect
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let now = Local::now();
+        let day = now.format(DAY_FORMAT).to_string();
+        if day != today {
+            let yesterday = std::mem::replace(&mut today, day);
+            if settings.assemble {
+                spawn_assemble(&name, &camera_dir, yesterday, &settings);
+            }
+        }
+
+        let path = camera_dir
+            .join(&today)
+            .join(format!("{}.jpeg", now.format("%H-%M-%S")));
+        if let Err(e) = frame(&camera, &path).await {
+            warn!("{name}: Failed to take a timelapse frame: {e:?}");
+        }
+    }
+}
+
+/// Take a snapshot and write it to `path`
+async fn frame(camera: &NeoInstance, path: &Path) -> Result<()> {
+    let jpeg = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+        .await
+        .context("Failed to take a snapshot")?;
+    if let Some(day_dir) = path.parent() {
+        tokio::fs::create_dir_all(day_dir)
+            .await
+            .with_context(|| format!("Failed to create {}", day_dir.display()))?;
+    }
+    tokio::fs::write(path, jpeg)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    debug!("Saved timelapse frame {}", path.display());
+    Ok(())
+}
+
+/// Days before today that have frames but no mp4 yet
+async fn unassembled_days(camera_dir: &Path, today: &str) -> Result<Vec<String>> {
+    let mut days = vec![];
+    let mut entries = tokio::fs::read_dir(camera_dir)
+        .await
+        .with_context(|| format!("Failed to read {}", camera_dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let day = entry.file_name().to_string_lossy().to_string();
+        let is_day = chrono::NaiveDate::parse_from_str(&day, DAY_FORMAT).is_ok();
+        if is_day
+            && day.as_str() < today
+            && entry.file_type().await?.is_dir()
+            && !camera_dir.join(format!("{day}.mp4")).exists()
+        {
+            days.push(day);
+        }
+    }
+    days.sort();
+    Ok(days)
+}
+
+/// Turn the frames of a day into an mp4 without holding up the next frame
+fn spawn_assemble(name: &str, camera_dir: &Path, day: String, settings: &TimelapseConfig) {
+    let name = name.to_string();
+    let frames_dir = camera_dir.join(&day);
+    let out_file = camera_dir.join(format!("{day}.mp4"));
+    let fps = settings.fps;
+    let delete_frames = settings.delete_frames;
+    tokio::task::spawn(async move {
+        info!("{name}: Assembling the timelapse of {day}");
+        if let Err(e) = Backend::assemble_timelapse(&frames_dir, fps, &out_file).await {
+            warn!("{name}: Failed to assemble the timelapse of {day}: {e:?}");
+            // Leave no half written video so that it is tried again next start
+            let _ = tokio::fs::remove_file(&out_file).await;
+            return;
+        }
+        info!("{name}: Saved timelapse {}", out_file.display());
+        if delete_frames {
+            if let Err(e) = tokio::fs::remove_dir_all(&frames_dir).await {
+                warn!(
+                    "{name}: Failed to delete the frames in {}: {e:?}",
+                    frames_dir.display()
+                );
+            }
+        }
+    });
+}
@@ -1,18 +1,38 @@
 //! Contains code that is not specific to any of the subcommands
 //!
+use lazy_static::lazy_static;
 use log::*;
 
 use super::config::CameraConfig;
+use super::quirks;
 use anyhow::{anyhow, Context, Error, Result};
 use neolink_core::bc_protocol::{
-    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, MaxEncryption,
+    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods,
 };
 use std::{
     fmt::{Display, Error as FmtError, Formatter},
     net::{IpAddr, ToSocketAddrs},
+    path::PathBuf,
     str::FromStr,
+    sync::RwLock,
+    time::Duration,
 };
 
+lazy_static! {
+    static ref PCAP_DIR: RwLock<Option<PathBuf>> = Default::default();
+}
+
+/// Record the messages of every camera connection into this directory
+pub(crate) fn set_pcap_dir(dir: Option<PathBuf>) {
+    if let Ok(mut pcap_dir) = PCAP_DIR.write() {
+        *pcap_dir = dir;
+    }
+}
+
+fn pcap_dir() -> Option<PathBuf> {
+    PCAP_DIR.read().ok().and_then(|dir| dir.clone())
+}
+
 pub(crate) fn timeout<F>(future: F) -> tokio::time::Timeout<F>
 where
     F: std::future::Future,
@@ -99,6 +119,9 @@ impl AddressOrUid {
             },
             debug: camera_config.debug,
             max_discovery_retries: camera_config.max_discovery_retries,
+            reply_timeout: camera_config.reply_timeout.map(Duration::from_millis),
+            stream_handles: camera_config.stream_handles.handles(),
+            capture_dir: pcap_dir(),
         };
 
         trace!("Camera Info: {:?}", options);
@@ -129,12 +152,7 @@ pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<Bc
             )
         })?;
 
-    let max_encryption = match camera_config.max_encryption.to_lowercase().as_str() {
-        "none" => MaxEncryption::None,
-        "bcencrypt" => MaxEncryption::BcEncrypt,
-        "aes" => MaxEncryption::Aes,
-        _ => MaxEncryption::Aes,
-    };
+    let max_encryption = quirks::max_encryption(camera_config)?;
     info!("{}: Logging in", camera_config.name);
     timeout(camera.login_with_maxenc(max_encryption))
         .await
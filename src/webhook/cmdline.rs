@@ -0,0 +1,9 @@
+use clap::Parser;
+
+/// POST the events of all cameras to a webhook as json
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Url to POST the events to, overrides the config
+    #[arg(short, long)]
+    pub url: Option<String>,
+}
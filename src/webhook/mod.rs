@@ -0,0 +1,365 @@
+///
+/// # Neolink Webhook
+///
+/// This module POSTs the events of all cameras to a webhook as json so that
+/// they can be used without an mqtt broker
+///
+/// The events are the start and end of motion, the camera connecting and
+/// disconnecting and changes to the battery and PIR state. The battery and
+/// PIR are polled every `poll_interval` seconds while the camera is
+/// connected.
+///
+/// The `reboot` events of `neolink reboot --schedule` go to the same url.
+///
+/// Events are delivered in order. A failed delivery is retried with an
+/// increasing delay and dropped after `retries` attempts.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink webhook --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [webhook]
+///   url = "http://homeassistant.local:8123/api/webhook/neolink"
+///   retries = 5
+///   poll_interval = 60
+/// ```
+///
+/// # Example Event
+///
+/// ```json
+/// {
+///   "schema": "neolink.event.v1",
+///   "camera": "Camera01",
+///   "time": "2023-10-17T10:15:00Z",
+///   "event": "motion",
+///   "active": true,
+///   "ai_type": "people",
+///   "motion_confidence": 80,
+///   "ai_confidence": 93
+/// }
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+use tokio::{
+    sync::mpsc::{channel as mpsc, Receiver as MpscReceiver, Sender as MpscSender},
+    task::JoinSet,
+    time::{interval, sleep, Duration, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+mod cmdline;
+
+use crate::{
+    common::{MdState, NeoInstance, NeoReactor},
+    config::WebhookConfig,
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// Longest wait between two delivery attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Entry point for the webhook subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let mut config = reactor.config().await?;
+    let settings = match (config.borrow().webhook.clone(), opt.url) {
+        (Some(mut settings), Some(url)) => {
+            settings.url = url;
+            settings
+        }
+        (Some(settings), None) => settings,
+        (None, Some(url)) => WebhookConfig::new(url),
+        (None, None) => return Err(anyhow!("No webhook url, set one in [webhook] or use --url")),
+    };
+
+    let (tx, rx) = mpsc(100);
+    let mut set = JoinSet::new();
+    let mut cameras: HashMap<String, CancellationToken> = Default::default();
+    let mut names = HashSet::new();
+
+    info!("Sending events to {}", settings.url);
+    tokio::select! {
+        v = deliver(&settings, rx) => v,
+        // Start and stop cameras as they are added/removed to the config
+        v = async {
+            loop {
+                names = config
+                    .wait_for(|config| enabled_cameras(config) != names)
+                    .await
+                    .map(|config| enabled_cameras(&config))?;
+                for name in names.iter() {
+                    if cameras.contains_key(name) {
+                        continue;
+                    }
+                    let cancel = CancellationToken::new();
+                    cameras.insert(name.clone(), cancel.clone());
+                    let notifier = Notifier::new(name.clone(), tx.clone());
+                    let reactor = reactor.clone();
+                    let poll_interval = Duration::from_secs(settings.poll_interval);
+                    set.spawn(async move {
+                        loop {
+                            let watch = async {
+                                let camera = reactor.get(&notifier.name).await?;
+                                watch_camera(&camera, &notifier, poll_interval).await
+                            };
+                            tokio::select! {
+                                _ = cancel.cancelled() => break,
+                                v = watch => {
+                                    warn!("{}: Webhook events stopped: {:?}", notifier.name, v);
+                                    sleep(Duration::from_secs(5)).await;
+                                }
+                            }
+                        }
+                    });
+                }
+                cameras.retain(|name, cancel| {
+                    let keep = names.contains(name);
+                    if !keep {
+                        cancel.cancel();
+                    }
+                    keep
+                });
+            }
+        } => v,
+    }
+}
+
+fn enabled_cameras(config: &crate::config::Config) -> HashSet<String> {
+    config
+        .cameras
+        .iter()
+        .filter(|camera| camera.enabled)
+        .map(|camera| camera.name.clone())
+        .collect()
+}
+
+/// The json body of each POST
+#[derive(Serialize)]
+struct EventV1<'a> {
+    schema: &'static str,
+    camera: &'a str,
+    /// When the event was seen by neolink in rfc3339
+    time: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    Motion {
+        active: bool,
+        ai_type: Option<String>,
+        motion_confidence: Option<u8>,
+        ai_confidence: Option<u8>,
+    },
+    Connection {
+        connected: bool,
+    },
+    Pir {
+        enabled: bool,
+    },
+    Battery {
+        battery_percent: u32,
+        charge_status: String,
+    },
+    /// Sent by `neolink reboot --schedule` after it rebooted the camera
+    Reboot {
+        scheduled: bool,
+    },
+}
+
+/// Queues the events of one camera for delivery
+pub(crate) struct Notifier {
+    name: String,
+    events: MpscSender<String>,
+}
+
+impl Notifier {
+    /// Queue the events of the camera `name` on `events` for [`deliver`]
+    pub(crate) fn new(name: String, events: MpscSender<String>) -> Self {
+        Self { name, events }
+    }
+
+    pub(crate) async fn send(&self, event: Event) -> Result<()> {
+        debug!("{}: Webhook event {:?}", self.name, event);
+        let body = serde_json::to_string(&EventV1 {
+            schema: "neolink.event.v1",
+            camera: &self.name,
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            event,
+        })?;
+        self.events
+            .send(body)
+            .await
+            .map_err(|_| anyhow!("Webhook delivery stopped"))
+    }
+}
+
+/// POST the events one at a time retrying each with a growing delay
+pub(crate) async fn deliver(
+    settings: &WebhookConfig,
+    mut events: MpscReceiver<String>,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    while let Some(body) = events.recv().await {
+        let mut backoff = Duration::from_secs(1);
+        for attempt in 1..=settings.retries {
+            let sent = client
+                .post(&settings.url)
+                .timeout(Duration::from_secs(10))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match sent {
+                Ok(_) => break,
+                Err(e) if attempt < settings.retries => {
+                    debug!("Webhook attempt {attempt} failed, retrying in {backoff:?}: {e:?}");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => warn!("Dropping webhook event after {attempt} attempts: {e:?}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn watch_camera(
+    camera: &NeoInstance,
+    notifier: &Notifier,
+    poll_interval: Duration,
+) -> Result<()> {
+    tokio::select! {
+        v = connection(camera, notifier) => v,
+        v = motion(camera, notifier) => v,
+        v = pir(camera, notifier, poll_interval) => v,
+        v = battery(camera, notifier, poll_interval) => v,
+    }
+}
+
+async fn connection(camera: &NeoInstance, notifier: &Notifier) -> Result<()> {
+    let mut camera_watch = camera.camera();
+    loop {
+        camera_watch
+            .wait_for(|cam| cam.upgrade().is_some())
+            .await
+            .context("Online Watch Dropped")?;
+        notifier.send(Event::Connection { connected: true }).await?;
+        camera_watch
+            .wait_for(|cam| cam.upgrade().is_none())
+            .await
+            .context("Disconnect Watch Dropped")?;
+        notifier
+            .send(Event::Connection { connected: false })
+            .await?;
+    }
+}
+
+async fn motion(camera: &NeoInstance, notifier: &Notifier) -> Result<()> {
+    let mut md = camera.motion().await?;
+    let mut active = None;
+    loop {
+        // The state is updated on every alarm so only send when it flips
+        let event = match &*md.borrow_and_update() {
+            MdState::Start(_, detail) => Some(Event::Motion {
+                active: true,
+                ai_type: detail.ai_type.clone(),
+                motion_confidence: detail.motion_confidence,
+                ai_confidence: detail.ai_confidence,
+            }),
+            MdState::Stop(_) => Some(Event::Motion {
+                active: false,
+                ai_type: None,
+                motion_confidence: None,
+                ai_confidence: None,
+            }),
+            MdState::Unknown => None,
+        };
+        if let Some(event @ Event::Motion { active: now, .. }) = event {
+            if active != Some(now) {
+                active = Some(now);
+                notifier.send(event).await?;
+            }
+        }
+        md.changed().await?;
+    }
+}
+
+async fn pir(camera: &NeoInstance, notifier: &Notifier, poll_interval: Duration) -> Result<()> {
+    let mut wait = poll(poll_interval);
+    let mut last = None;
+    loop {
+        wait.tick().await;
+        let state = camera
+            .run_passive_task(|cam| Box::pin(async move { Ok(cam.get_pirstate().await?) }))
+            .await;
+        let event = Event::Pir {
+            enabled: supported(state, "PIR").await?.enable != 0,
+        };
+        if last.as_ref() != Some(&event) {
+            last = Some(event.clone());
+            notifier.send(event).await?;
+        }
+    }
+}
+
+async fn battery(camera: &NeoInstance, notifier: &Notifier, poll_interval: Duration) -> Result<()> {
+    let mut wait = poll(poll_interval);
+    let mut last = None;
+    loop {
+        wait.tick().await;
+        let info = camera
+            .run_passive_task(|cam| Box::pin(async move { Ok(cam.battery_info().await?) }))
+            .await;
+        let info = supported(info, "Battery").await?;
+        let event = Event::Battery {
+            battery_percent: info.battery_percent,
+            charge_status: info.charge_status,
+        };
+        if last.as_ref() != Some(&event) {
+            last = Some(event.clone());
+            notifier.send(event).await?;
+        }
+    }
+}
+
+fn poll(period: Duration) -> tokio::time::Interval {
+    let mut wait = interval(period);
+    wait.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    wait
+}
+
+/// Wait forever if the camera does not support the request
+async fn supported<T>(result: AnyResult<T>, what: &str) -> AnyResult<T> {
+    match result {
+        Err(e) => match e.downcast::<neolink_core::Error>() {
+            Ok(
+                neolink_core::Error::CameraServiceUnavaliable(_)
+                | neolink_core::Error::MissingAbility { .. }
+                | neolink_core::Error::UnintelligibleReply { .. },
+            ) => {
+                debug!("{what} not supported");
+                futures::future::pending().await
+            }
+            Ok(e) => Err(e.into()),
+            Err(e) => Err(e),
+        },
+        v => v,
+    }
+}
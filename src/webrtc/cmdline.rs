@@ -0,0 +1,5 @@
+use clap::Parser;
+
+/// Serve the camera streams to browsers over WebRTC
+#[derive(Parser, Debug)]
+pub struct Opt {}
@@ -0,0 +1,238 @@
+///
+/// # Neolink WebRTC
+///
+/// This module serves the camera streams over WebRTC so that they can be
+/// watched in a browser with sub second latency
+///
+/// Each stream has a player page at `http://{bind}:{port}/{CameraName}/main`
+/// (or `sub`/`extern`). Other WHEP players can use the endpoint at
+/// `http://{bind}:{port}/{CameraName}/main/whep`.
+///
+/// The streams come from the same camera streams as `neolink rtsp`. Video
+/// is sent as is while audio is transcoded to opus
+///
+/// # Usage
+///
+/// ```bash
+/// neolink webrtc --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [webrtc]
+///   bind = "0.0.0.0"
+///   port = 8889
+///   # Only needed to view from outside the local network
+///   stun_server = "stun://stun.l.google.com:19302"
+/// ```
+///
+use anyhow::{Context, Result};
+use hyper::{
+    body::to_bytes,
+    header::{
+        ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_EXPOSE_HEADERS, CONTENT_TYPE, LOCATION,
+    },
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+mod cmdline;
+mod session;
+
+use crate::{common::NeoReactor, config::WebRtcConfig};
+use session::Session;
+
+pub(crate) use cmdline::Opt;
+
+const PLAYER: &str = include_str!("player.html");
+
+struct WebRtcState {
+    reactor: NeoReactor,
+    config: WebRtcConfig,
+    /// Namespace prefix of all paths, see [`crate::config::Config::path_prefix`]
+    prefix: String,
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Session>>,
+}
+
+/// Entry point for the webrtc subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (config, prefix) = {
+        let config = reactor.config().await?;
+        let config = config.borrow();
+        (config.webrtc.clone(), config.path_prefix())
+    };
+    let addr: SocketAddr = format!("{}:{}", config.bind_addr, config.bind_port)
+        .parse()
+        .context("Invalid WebRTC bind address")?;
+
+    log::info!("WebRTC players avaliable at http://{addr}{prefix}/{{CameraName}}/main");
+    let state = Arc::new(WebRtcState {
+        reactor,
+        config,
+        prefix,
+        next_id: AtomicU64::new(0),
+        sessions: Default::default(),
+    });
+
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(req, state).await) }
+            }))
+        }
+    });
+
+    Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind WebRTC server to {addr}"))?
+        .serve(make_svc)
+        .await?;
+    Ok(())
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, DELETE, OPTIONS")
+        .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type")
+        .header(ACCESS_CONTROL_EXPOSE_HEADERS, "Location")
+        .body(Body::from(body))
+        .expect("Response should be valid")
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    response(status, "text/plain", message.to_string())
+}
+
+fn stream_kind(name: &str) -> Option<StreamKind> {
+    match name {
+        "main" | "mainStream" => Some(StreamKind::Main),
+        "sub" | "subStream" => Some(StreamKind::Sub),
+        "extern" | "externStream" => Some(StreamKind::Extern),
+        _ => None,
+    }
+}
+
+async fn handle(req: Request<Body>, state: Arc<WebRtcState>) -> Response<Body> {
+    // Forget about viewers that have gone away
+    state
+        .sessions
+        .lock()
+        .unwrap()
+        .retain(|_, session| !session.is_finished());
+
+    let method = req.method().clone();
+    let path = match req.uri().path().strip_prefix(&state.prefix) {
+        Some(path) => path.to_string(),
+        None => return error(StatusCode::NOT_FOUND, "Not found"),
+    };
+    let path = path
+        .trim_matches('/')
+        .split('/')
+        .map(|part| part.to_string())
+        .collect::<Vec<_>>();
+    let path = path.iter().map(|part| part.as_str()).collect::<Vec<_>>();
+
+    let (camera, kind, rest) = match path[..] {
+        [camera, stream, ref rest @ ..] => match stream_kind(stream) {
+            Some(kind) => (camera, kind, rest),
+            None => return error(StatusCode::NOT_FOUND, "Unknown stream"),
+        },
+        _ => return error(StatusCode::NOT_FOUND, "Not found"),
+    };
+    let known = state
+        .reactor
+        .config()
+        .await
+        .map(|config| {
+            config
+                .borrow()
+                .cameras
+                .iter()
+                .any(|cam| cam.enabled && cam.name == camera)
+        })
+        .unwrap_or(false);
+    if !known {
+        return error(StatusCode::NOT_FOUND, "Unknown camera");
+    }
+
+    match (&method, rest) {
+        (&Method::OPTIONS, _) => response(StatusCode::NO_CONTENT, "text/plain", String::new()),
+        (&Method::GET, []) => response(
+            StatusCode::OK,
+            "text/html; charset=utf-8",
+            PLAYER.to_string(),
+        ),
+        (&Method::POST, ["whep"]) => {
+            let offer = match to_bytes(req.into_body()).await {
+                Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+                Err(e) => {
+                    log::debug!("Failed to read WHEP offer: {e:?}");
+                    return error(StatusCode::BAD_REQUEST, "Failed to read offer");
+                }
+            };
+            match start_session(&state, camera, kind, &offer).await {
+                Ok((id, answer)) => {
+                    log::info!("{camera}: WebRTC viewer {id} connected to {kind}");
+                    let mut reply = response(StatusCode::CREATED, "application/sdp", answer);
+                    if let Ok(location) =
+                        format!("{}/{}/{}/whep/{id}", state.prefix, path[0], path[1]).parse()
+                    {
+                        reply.headers_mut().insert(LOCATION, location);
+                    }
+                    reply
+                }
+                Err(e) => {
+                    log::warn!("{camera}: Failed to start WebRTC stream: {e:?}");
+                    error(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e}"))
+                }
+            }
+        }
+        (&Method::DELETE, ["whep", id]) => {
+            let removed = id
+                .parse::<u64>()
+                .ok()
+                .and_then(|id| state.sessions.lock().unwrap().remove(&id));
+            match removed {
+                Some(_) => {
+                    log::info!("{camera}: WebRTC viewer {id} disconnected");
+                    response(StatusCode::OK, "text/plain", String::new())
+                }
+                None => error(StatusCode::NOT_FOUND, "Unknown session"),
+            }
+        }
+        _ => error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+    }
+}
+
+async fn start_session(
+    state: &WebRtcState,
+    camera: &str,
+    kind: StreamKind,
+    offer: &str,
+) -> Result<(u64, String)> {
+    let camera = state.reactor.get(camera).await?;
+    let (session, answer) =
+        Session::new(&camera, kind, offer, state.config.stun_server.as_deref()).await?;
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+    state.sessions.lock().unwrap().insert(id, session);
+    Ok((id, answer))
+}
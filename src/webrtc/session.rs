@@ -0,0 +1,314 @@
+//! A single WebRTC viewer
+//!
+//! Each viewer gets its own gstreamer pipeline that ends in a `webrtcbin`.
+//! The offer from the browser is only answered once ICE gathering has
+//! completed so that neither side needs to trickle candidates
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{parse_launch, prelude::*, Element, MessageView, Pipeline, Promise, State};
+use gstreamer_app::AppSrc;
+use gstreamer_sdp::SDPMessage;
+use gstreamer_webrtc::{
+    WebRTCICEGatheringState, WebRTCPeerConnectionState, WebRTCSDPType, WebRTCSessionDescription,
+};
+use neolink_core::bc_protocol::StreamKind;
+use std::collections::VecDeque;
+use tokio::{
+    sync::{
+        broadcast::Receiver as BroadcastReceiver, mpsc::unbounded_channel,
+        watch::Receiver as WatchReceiver,
+    },
+    task::JoinSet,
+    time::{interval, sleep, timeout, Duration, Instant},
+};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::{AudFormat, NeoInstance, StampedData, StreamConfig, VidFormat},
+    AnyResult,
+};
+
+/// How long to wait for the camera to start sending video
+const STREAM_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait for our ICE candidates before answering with what we have
+const ICE_GATHERING_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) struct Session {
+    pipeline: Pipeline,
+    cancel: CancellationToken,
+    set: JoinSet<AnyResult<()>>,
+}
+
+impl Session {
+    /// Start streaming to a new viewer
+    ///
+    /// Returns the session along with the SDP answer for the viewer's offer
+    pub(super) async fn new(
+        camera: &NeoInstance,
+        kind: StreamKind,
+        offer: &str,
+        stun_server: Option<&str>,
+    ) -> Result<(Self, String)> {
+        let offer = SDPMessage::parse_buffer(offer.as_bytes()).context("Invalid SDP offer")?;
+
+        let mut stream = camera.stream(kind).await?;
+        timeout(
+            STREAM_TIMEOUT,
+            stream.config.wait_for(|config| config.vid_ready()),
+        )
+        .await
+        .context("Timed out waiting for the camera to send video")??;
+        // After vid give it 1s to look for audio
+        if let Ok(v) = timeout(
+            Duration::from_secs(1),
+            stream.config.wait_for(|config| config.aud_ready()),
+        )
+        .await
+        {
+            v?;
+        }
+        let stream_config = stream.config.borrow().clone();
+
+        let pipeline = create_pipeline(&stream_config)?;
+        let webrtc = pipeline
+            .by_name("webrtc")
+            .ok_or_else(|| anyhow!("Pipeline is missing webrtcbin"))?;
+        if let Some(stun_server) = stun_server {
+            webrtc.set_property("stun-server", stun_server);
+        }
+        pipeline
+            .set_state(State::Playing)
+            .context("Failed to start the webrtc pipeline")?;
+
+        let mut session = Session {
+            pipeline,
+            cancel: CancellationToken::new(),
+            set: JoinSet::new(),
+        };
+
+        let answer = negotiate(&webrtc, offer).await?;
+
+        if let Some(vid) = session.appsrc("vidsrc") {
+            let history = stream.vid_history.clone();
+            let live = stream.vid.resubscribe();
+            let cancel = session.cancel.clone();
+            session.set.spawn(async move {
+                tokio::select! {
+                    _ = cancel.cancelled() => AnyResult::Ok(()),
+                    v = send_to_appsrc(Some(history), live, &vid) => v,
+                }
+            });
+        }
+        if let Some(aud) = session.appsrc("audsrc") {
+            let live = stream.aud.resubscribe();
+            let cancel = session.cancel.clone();
+            session.set.spawn(async move {
+                tokio::select! {
+                    _ = cancel.cancelled() => AnyResult::Ok(()),
+                    v = send_to_appsrc(None, live, &aud) => v,
+                }
+            });
+        }
+
+        // Watch for the viewer going away and keep the stream in use until then
+        let cancel = session.cancel.clone();
+        let bus = session
+            .pipeline
+            .bus()
+            .ok_or_else(|| anyhow!("Pipeline without bus"))?;
+        session.set.spawn(async move {
+            let _stream = stream;
+            let mut check = interval(Duration::from_secs(1));
+            let connect_deadline = Instant::now() + STREAM_TIMEOUT;
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = check.tick() => {},
+                }
+                while let Some(msg) = bus.pop() {
+                    match msg.view() {
+                        MessageView::Eos(..) => cancel.cancel(),
+                        MessageView::Error(err) => {
+                            log::warn!("WebRTC pipeline error: {:?}", err);
+                            cancel.cancel();
+                        }
+                        _ => {}
+                    }
+                }
+                match webrtc.property::<WebRTCPeerConnectionState>("connection-state") {
+                    WebRTCPeerConnectionState::Failed | WebRTCPeerConnectionState::Closed => {
+                        cancel.cancel()
+                    }
+                    WebRTCPeerConnectionState::New | WebRTCPeerConnectionState::Connecting
+                        if Instant::now() > connect_deadline =>
+                    {
+                        log::debug!("WebRTC viewer never connected");
+                        cancel.cancel();
+                    }
+                    _ => {}
+                }
+            }
+            AnyResult::Ok(())
+        });
+
+        Ok((session, answer))
+    }
+
+    fn appsrc(&self, name: &str) -> Option<AppSrc> {
+        self.pipeline
+            .by_name(name)
+            .and_then(|element| element.dynamic_cast::<AppSrc>().ok())
+    }
+
+    /// True once the viewer has gone away
+    pub(super) fn is_finished(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        log::trace!("Drop WebRTC Session");
+        self.cancel.cancel();
+        let _ = self.pipeline.set_state(State::Null);
+        let mut set = std::mem::take(&mut self.set);
+        tokio::task::spawn(async move {
+            while set.join_next().await.is_some() {}
+            log::trace!("Dropped WebRTC Session");
+        });
+    }
+}
+
+/// Answer the offer once the ICE candidates are gathered
+async fn negotiate(webrtc: &Element, offer: SDPMessage) -> Result<String> {
+    let offer = WebRTCSessionDescription::new(WebRTCSDPType::Offer, offer);
+    webrtc.emit_by_name::<()>("set-remote-description", &[&offer, &None::<Promise>]);
+
+    let (promise, reply) = Promise::new_future();
+    webrtc.emit_by_name::<()>("create-answer", &[&None::<gstreamer::Structure>, &promise]);
+    let answer = reply
+        .await
+        .ok()
+        .flatten()
+        .and_then(|reply| reply.get::<WebRTCSessionDescription>("answer").ok())
+        .ok_or_else(|| anyhow!("Could not create an answer for the offer"))?;
+
+    // Gathering starts with the local description so listen before setting it
+    let (gathering_tx, mut gathering_rx) = unbounded_channel();
+    let handler = webrtc.connect_notify(Some("ice-gathering-state"), move |_, _| {
+        let _ = gathering_tx.send(());
+    });
+    webrtc.emit_by_name::<()>("set-local-description", &[&answer, &None::<Promise>]);
+    let gathered = timeout(ICE_GATHERING_TIMEOUT, async {
+        while webrtc.property::<WebRTCICEGatheringState>("ice-gathering-state")
+            != WebRTCICEGatheringState::Complete
+        {
+            if gathering_rx.recv().await.is_none() {
+                break;
+            }
+        }
+    })
+    .await;
+    webrtc.disconnect(handler);
+    if gathered.is_err() {
+        log::debug!("ICE gathering timed out, answering with the candidates so far");
+    }
+
+    webrtc
+        .property::<Option<WebRTCSessionDescription>>("local-description")
+        .and_then(|description| description.sdp().as_text().ok())
+        .ok_or_else(|| anyhow!("Could not read the SDP answer"))
+}
+
+/// Push the frames into the appsrc
+///
+/// If a history is given the viewer starts on the last keyframe in it
+/// otherwise they wait for the next live keyframe
+async fn send_to_appsrc(
+    history: Option<WatchReceiver<VecDeque<StampedData>>>,
+    live: BroadcastReceiver<StampedData>,
+    appsrc: &AppSrc,
+) -> AnyResult<()> {
+    let video = history.is_some();
+    let initial = history
+        .map(|history| {
+            let history = history.borrow();
+            let start = history.iter().rposition(|frame| frame.keyframe);
+            start
+                .map(|start| history.iter().skip(start).cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+    let mut found_key = !video || !initial.is_empty();
+    let mut frames = tokio_stream::iter(initial).chain(
+        BroadcastStream::new(live).filter_map(|frame| frame.ok()), // Ignore lagged
+    );
+
+    while let Some(frame) = frames.next().await {
+        found_key = found_key || frame.keyframe;
+        if !found_key {
+            continue;
+        }
+        // Wraps the frame without a copy
+        let gst_buf = gstreamer::Buffer::from_slice(frame.data);
+        if appsrc.push_buffer(gst_buf).is_err() {
+            // Pipeline has stopped
+            break;
+        }
+    }
+    // Give gstreamer a moment before the pipeline is torn down
+    sleep(Duration::from_millis(10)).await;
+    Ok(())
+}
+
+fn create_pipeline(stream_config: &StreamConfig) -> Result<Pipeline> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let source = "is-live=true do-timestamp=true format=time min-latency=0";
+    let video = match stream_config.vid_format {
+        VidFormat::H264 => format!(
+            "appsrc name=vidsrc {source} \
+            ! queue ! h264parse \
+            ! rtph264pay config-interval=-1 aggregate-mode=zero-latency \
+            ! application/x-rtp,media=video,encoding-name=H264,payload=96 \
+            ! webrtc."
+        ),
+        VidFormat::H265 => {
+            log::warn!("Most browsers cannot play H265 over WebRTC, try the sub stream");
+            format!(
+                "appsrc name=vidsrc {source} \
+                ! queue ! h265parse \
+                ! rtph265pay config-interval=-1 aggregate-mode=zero-latency \
+                ! application/x-rtp,media=video,encoding-name=H265,payload=96 \
+                ! webrtc."
+            )
+        }
+        VidFormat::None => return Err(anyhow!("Stream has no video")),
+    };
+    // Browsers only take opus so the audio is always transcoded
+    let opus = "audioconvert ! audioresample ! opusenc ! rtpopuspay \
+        ! application/x-rtp,media=audio,encoding-name=OPUS,payload=97 \
+        ! webrtc.";
+    let audio = match stream_config.aud_format {
+        AudFormat::None => String::new(),
+        AudFormat::Aac => format!(
+            "appsrc name=audsrc {source} \
+            ! queue ! aacparse ! decodebin ! {opus}"
+        ),
+        AudFormat::Adpcm(block_size) => format!(
+            "appsrc name=audsrc {source} \
+            caps=audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000 \
+            ! queue ! adpcmdec ! {opus}"
+        ),
+    };
+    let launch_str = format!("webrtcbin name=webrtc bundle-policy=max-bundle {video} {audio}");
+    log::debug!("{}", launch_str);
+
+    let pipeline = parse_launch(&launch_str).context(
+        "Unable to load gstreamer pipeline ensure the webrtc gstreamer plugins are installed",
+    )?;
+    pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+        anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+    })
+}